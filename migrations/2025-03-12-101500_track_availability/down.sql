@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_music_availability;
+ALTER TABLE music DROP COLUMN availability_reason;
+ALTER TABLE music DROP COLUMN availability;
@@ -0,0 +1,5 @@
+-- available | missing_file | taken_down | private
+ALTER TABLE music ADD COLUMN availability TEXT NOT NULL DEFAULT 'available';
+ALTER TABLE music ADD COLUMN availability_reason TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_music_availability ON music(availability);
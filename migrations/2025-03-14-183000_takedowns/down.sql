@@ -0,0 +1,8 @@
+DROP INDEX IF EXISTS idx_takedown_events_takedown;
+DROP INDEX IF EXISTS idx_takedowns_target;
+DROP TABLE takedown_events;
+DROP TABLE takedowns;
+ALTER TABLE playlists DROP COLUMN availability_reason;
+ALTER TABLE playlists DROP COLUMN availability;
+ALTER TABLE music DROP COLUMN uploader_id;
+ALTER TABLE users DROP COLUMN is_admin;
@@ -0,0 +1,31 @@
+ALTER TABLE users ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT 0;
+
+-- The user who loaded the music into the library (if known)
+ALTER TABLE music ADD COLUMN uploader_id TEXT REFERENCES users(user_id);
+
+-- Playlists go through the same availability layer as music
+ALTER TABLE playlists ADD COLUMN availability TEXT NOT NULL DEFAULT 'available';
+ALTER TABLE playlists ADD COLUMN availability_reason TEXT;
+
+CREATE TABLE takedowns (
+	takedown_id TEXT PRIMARY KEY NOT NULL,
+	target_type TEXT NOT NULL, -- track | playlist
+	target_id TEXT NOT NULL,
+	reason TEXT NOT NULL,
+	admin_id TEXT NOT NULL REFERENCES users(user_id),
+	status TEXT NOT NULL, -- active | appealed | reinstated | upheld
+	created_date_time TEXT NOT NULL
+);
+
+-- Appeal trail, every action taken on a takedown is kept here
+CREATE TABLE takedown_events (
+	event_id TEXT PRIMARY KEY NOT NULL,
+	takedown_id TEXT NOT NULL REFERENCES takedowns(takedown_id),
+	actor_id TEXT NOT NULL REFERENCES users(user_id),
+	action TEXT NOT NULL, -- takedown | appeal | reinstate | uphold
+	message TEXT NOT NULL,
+	created_date_time TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_takedowns_target ON takedowns(target_type, target_id);
+CREATE INDEX IF NOT EXISTS idx_takedown_events_takedown ON takedown_events(takedown_id);
@@ -0,0 +1,10 @@
+-- Other Lobic instances this server is peered with
+CREATE TABLE federation_peers (
+	peer_id TEXT PRIMARY KEY NOT NULL,
+	name TEXT NOT NULL,
+	base_url TEXT, -- unknown until the peer completes the handshake
+	local_token TEXT NOT NULL UNIQUE, -- issued by us, the peer presents it when calling us
+	remote_token TEXT, -- issued by the peer, we present it when calling them
+	status TEXT NOT NULL, -- invited | active | revoked
+	created_date_time TEXT NOT NULL
+);
@@ -0,0 +1,5 @@
+-- Per user preferences, users without a row get the defaults
+CREATE TABLE user_settings (
+	user_id TEXT PRIMARY KEY NOT NULL REFERENCES users(user_id),
+	stats_visibility TEXT NOT NULL DEFAULT 'friends' -- public | friends | private
+);
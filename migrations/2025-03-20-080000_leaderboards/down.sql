@@ -0,0 +1,3 @@
+ALTER TABLE user_settings DROP COLUMN leaderboard_opt_out;
+DROP TABLE leaderboard_entries;
+DROP TABLE play_events;
@@ -0,0 +1,21 @@
+-- Every single play, play_log only keeps the latest one per track
+CREATE TABLE play_events (
+	event_id TEXT PRIMARY KEY NOT NULL,
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	music_id TEXT NOT NULL REFERENCES music(music_id),
+	played_date_time TEXT NOT NULL,
+	listened_secs BIGINT NOT NULL
+);
+CREATE INDEX play_events_user_time ON play_events(user_id, played_date_time);
+
+-- Weekly standings, rebuilt by the scheduler
+CREATE TABLE leaderboard_entries (
+	week_start TEXT NOT NULL,
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	minutes_listened BIGINT NOT NULL,
+	distinct_artists INTEGER NOT NULL,
+	refreshed_date_time TEXT NOT NULL,
+	PRIMARY KEY (week_start, user_id)
+);
+
+ALTER TABLE user_settings ADD COLUMN leaderboard_opt_out BOOLEAN NOT NULL DEFAULT FALSE;
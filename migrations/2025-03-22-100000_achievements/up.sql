@@ -0,0 +1,8 @@
+-- Progress of every user towards each achievement, unlocked ones keep their date
+CREATE TABLE user_achievements (
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	achievement_id TEXT NOT NULL,
+	progress INTEGER NOT NULL,
+	unlocked_date_time TEXT,
+	PRIMARY KEY (user_id, achievement_id)
+);
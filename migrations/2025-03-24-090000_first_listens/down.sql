@@ -0,0 +1 @@
+DROP TABLE first_listens;
@@ -0,0 +1,20 @@
+-- When the user played each track and artist for the very first time
+CREATE TABLE first_listens (
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	target_type TEXT NOT NULL, -- track | artist
+	target_id TEXT NOT NULL, -- music id or artist name
+	first_played_date_time TEXT NOT NULL,
+	notified_year INTEGER, -- the last year the anniversary was notified
+	PRIMARY KEY (user_id, target_type, target_id)
+);
+
+-- Filling in from the history we have, play_log only knows the latest play so it is a best effort
+INSERT OR IGNORE INTO first_listens
+SELECT user_id, 'track', music_id, MIN(played_date_time), NULL FROM play_events GROUP BY user_id, music_id;
+INSERT OR IGNORE INTO first_listens
+SELECT user_id, 'track', music_id, music_played_date_time, NULL FROM play_log;
+INSERT OR IGNORE INTO first_listens
+SELECT f.user_id, 'artist', m.artist, MIN(f.first_played_date_time), NULL
+FROM first_listens f JOIN music m ON m.music_id = f.target_id
+WHERE f.target_type = 'track'
+GROUP BY f.user_id, m.artist;
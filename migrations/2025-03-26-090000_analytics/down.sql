@@ -0,0 +1,3 @@
+DROP TABLE analytics_top_content;
+DROP TABLE analytics_retention;
+DROP TABLE analytics_daily;
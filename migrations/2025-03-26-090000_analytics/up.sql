@@ -0,0 +1,27 @@
+-- Instance analytics, rebuilt from the play events by the scheduler
+CREATE TABLE analytics_daily (
+	day TEXT PRIMARY KEY NOT NULL, -- YYYY-MM-DD, UTC
+	active_users INTEGER NOT NULL,
+	weekly_active_users INTEGER NOT NULL, -- trailing 7 days
+	monthly_active_users INTEGER NOT NULL, -- trailing 30 days
+	plays INTEGER NOT NULL,
+	minutes_listened BIGINT NOT NULL,
+	refreshed_date_time TEXT NOT NULL
+);
+
+-- Users grouped by the week of their first play, and how many came back in the following weeks
+CREATE TABLE analytics_retention (
+	cohort_week TEXT NOT NULL,
+	week_offset INTEGER NOT NULL,
+	cohort_size INTEGER NOT NULL,
+	retained_users INTEGER NOT NULL,
+	PRIMARY KEY (cohort_week, week_offset)
+);
+
+CREATE TABLE analytics_top_content (
+	kind TEXT NOT NULL, -- track | artist
+	rank INTEGER NOT NULL,
+	target_id TEXT NOT NULL, -- music id or artist name
+	plays INTEGER NOT NULL,
+	PRIMARY KEY (kind, rank)
+);
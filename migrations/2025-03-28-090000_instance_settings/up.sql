@@ -0,0 +1,5 @@
+-- Settings of the instance itself which the admins change at runtime
+CREATE TABLE instance_settings (
+	key TEXT PRIMARY KEY NOT NULL,
+	value TEXT NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Preferred language for user-facing texts, NULL follows Accept-Language
+ALTER TABLE user_settings ADD COLUMN language TEXT;
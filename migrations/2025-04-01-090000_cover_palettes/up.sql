@@ -0,0 +1,6 @@
+-- Colors extracted from the cover images, covers never change so this is never invalidated
+CREATE TABLE cover_palettes (
+	img_uuid TEXT PRIMARY KEY NOT NULL,
+	colors TEXT NOT NULL, -- json, most common first
+	extracted_date_time TEXT NOT NULL
+);
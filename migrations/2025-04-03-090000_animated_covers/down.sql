@@ -0,0 +1 @@
+DROP TABLE animated_covers;
@@ -0,0 +1,9 @@
+-- Looping videos shown instead of the static cover, the files live in storage/animated_covers
+CREATE TABLE animated_covers (
+	target_type TEXT NOT NULL, -- album | playlist
+	target_id TEXT NOT NULL, -- cover image uuid of the album or the playlist id
+	uploader_id TEXT NOT NULL REFERENCES users(user_id),
+	duration_secs DOUBLE NOT NULL,
+	uploaded_date_time TEXT NOT NULL,
+	PRIMARY KEY (target_type, target_id)
+);
@@ -0,0 +1,9 @@
+-- Chapters read from the ID3 CHAP frames at ingest, for audiobooks and long mixes
+CREATE TABLE music_chapters (
+	music_id TEXT NOT NULL REFERENCES music(music_id),
+	chapter_index INTEGER NOT NULL, -- in playback order, from 0
+	title TEXT NOT NULL,
+	start_ms BIGINT NOT NULL,
+	end_ms BIGINT NOT NULL,
+	PRIMARY KEY (music_id, chapter_index)
+);
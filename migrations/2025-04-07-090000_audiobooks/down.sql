@@ -0,0 +1,4 @@
+DROP TABLE audiobook_progress;
+ALTER TABLE music DROP COLUMN series_index;
+ALTER TABLE music DROP COLUMN series;
+ALTER TABLE music DROP COLUMN content_type;
@@ -0,0 +1,13 @@
+-- music, audiobook or podcast, only music takes part in shuffle and recommendations
+ALTER TABLE music ADD COLUMN content_type TEXT NOT NULL DEFAULT 'music';
+ALTER TABLE music ADD COLUMN series TEXT;
+ALTER TABLE music ADD COLUMN series_index INTEGER;
+
+CREATE TABLE audiobook_progress (
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	music_id TEXT NOT NULL REFERENCES music(music_id),
+	position DOUBLE NOT NULL, -- seconds into the book
+	finished BOOLEAN NOT NULL DEFAULT FALSE,
+	updated_date_time TEXT NOT NULL,
+	PRIMARY KEY (user_id, music_id)
+);
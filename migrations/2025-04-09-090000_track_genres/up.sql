@@ -0,0 +1,31 @@
+-- Every genre of a track, music.genre stays as the primary (first) one
+CREATE TABLE track_genres (
+	music_id TEXT NOT NULL REFERENCES music(music_id),
+	genre TEXT NOT NULL COLLATE NOCASE, -- Rock and rock are the same genre
+	position INTEGER NOT NULL, -- in the order of the tag, 0 is the primary genre
+	PRIMARY KEY (music_id, genre)
+);
+CREATE INDEX track_genres_genre ON track_genres (genre);
+
+-- Splitting the existing genre strings on semicolons and commas
+INSERT OR IGNORE INTO track_genres (music_id, genre, position)
+WITH RECURSIVE split (music_id, genre, rest, position) AS (
+	SELECT music_id, NULL, REPLACE(genre, ',', ';') || ';', -1 FROM music
+	UNION ALL
+	SELECT
+		music_id,
+		TRIM(SUBSTR(rest, 1, INSTR(rest, ';') - 1)),
+		SUBSTR(rest, INSTR(rest, ';') + 1),
+		position + 1
+	FROM split
+	WHERE rest <> ''
+)
+SELECT music_id, genre, position FROM split WHERE genre <> '';
+
+UPDATE music SET genre = (
+	SELECT track_genres.genre FROM track_genres
+	WHERE track_genres.music_id = music.music_id
+	ORDER BY track_genres.position
+	LIMIT 1
+)
+WHERE music_id IN (SELECT music_id FROM track_genres);
@@ -0,0 +1,2 @@
+DROP INDEX music_release_year;
+ALTER TABLE music DROP COLUMN release_year;
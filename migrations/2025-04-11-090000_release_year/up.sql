@@ -0,0 +1,3 @@
+-- From the TDRC/TYER tags, NULL when the tags don't tell
+ALTER TABLE music ADD COLUMN release_year INTEGER;
+CREATE INDEX music_release_year ON music (release_year);
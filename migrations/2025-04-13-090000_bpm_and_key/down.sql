@@ -0,0 +1,3 @@
+DROP INDEX music_bpm;
+ALTER TABLE music DROP COLUMN musical_key;
+ALTER TABLE music DROP COLUMN bpm;
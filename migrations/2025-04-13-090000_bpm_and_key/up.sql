@@ -0,0 +1,4 @@
+-- From the TBPM/TKEY tags, or estimated from the audio when AUDIO_ANALYSIS is on
+ALTER TABLE music ADD COLUMN bpm DOUBLE;
+ALTER TABLE music ADD COLUMN musical_key TEXT; -- C, C#m, ... sharps only
+CREATE INDEX music_bpm ON music (bpm);
@@ -0,0 +1,10 @@
+-- One mood per track, tagged by an admin or worked out from the tempo and key
+CREATE TABLE track_moods (
+	music_id TEXT PRIMARY KEY NOT NULL REFERENCES music(music_id),
+	mood TEXT NOT NULL, -- chill, melancholic, upbeat, dark, energetic or intense
+	energy DOUBLE NOT NULL, -- 0 is the calmest, 1 the most energetic
+	source TEXT NOT NULL, -- manual or analysis, analysis never replaces a manual tag
+	tagged_by TEXT REFERENCES users(user_id),
+	updated_date_time TEXT NOT NULL
+);
+CREATE INDEX track_moods_mood ON track_moods (mood);
@@ -0,0 +1,2 @@
+DROP TABLE blocked_tags;
+DROP TABLE user_tags;
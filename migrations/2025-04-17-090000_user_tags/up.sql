@@ -0,0 +1,18 @@
+-- Free-form tags the listeners put on tracks and albums, albums are known by their name
+CREATE TABLE user_tags (
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	target_type TEXT NOT NULL, -- track or album
+	target_id TEXT NOT NULL,
+	tag TEXT NOT NULL COLLATE NOCASE,
+	created_date_time TEXT NOT NULL,
+	PRIMARY KEY (user_id, target_type, target_id, tag)
+);
+CREATE INDEX user_tags_target ON user_tags (target_type, target_id);
+CREATE INDEX user_tags_tag ON user_tags (tag);
+
+-- Tags the admins took out, they can't be used again until unblocked
+CREATE TABLE blocked_tags (
+	tag TEXT PRIMARY KEY NOT NULL COLLATE NOCASE,
+	blocked_by TEXT NOT NULL REFERENCES users(user_id),
+	blocked_date_time TEXT NOT NULL
+);
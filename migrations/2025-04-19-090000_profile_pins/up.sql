@@ -0,0 +1,9 @@
+-- Playlists, albums and tracks shown at the top of a profile, albums are known by their name
+CREATE TABLE profile_pins (
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	target_type TEXT NOT NULL, -- playlist, album or track
+	target_id TEXT NOT NULL,
+	position INTEGER NOT NULL, -- 0 is shown first
+	pinned_date_time TEXT NOT NULL,
+	PRIMARY KEY (user_id, target_type, target_id)
+);
@@ -0,0 +1,7 @@
+-- The track a user features on their profile, played from the offset they picked
+CREATE TABLE profile_anthems (
+	user_id TEXT PRIMARY KEY NOT NULL REFERENCES users(user_id),
+	music_id TEXT NOT NULL REFERENCES music(music_id),
+	start_secs DOUBLE NOT NULL DEFAULT 0,
+	updated_date_time TEXT NOT NULL
+);
@@ -0,0 +1,2 @@
+-- While in the future, plays stay in the user's own history but aren't shown to friends or scrobbled
+ALTER TABLE user_settings ADD COLUMN private_session_until TEXT;
@@ -0,0 +1,3 @@
+ALTER TABLE user_settings DROP COLUMN quiet_hours_end;
+ALTER TABLE user_settings DROP COLUMN quiet_hours_start;
+ALTER TABLE user_settings DROP COLUMN do_not_disturb;
@@ -0,0 +1,4 @@
+-- Quiet hours are "HH:MM" in server local time, both ends have to be set for them to apply
+ALTER TABLE user_settings ADD COLUMN do_not_disturb BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE user_settings ADD COLUMN quiet_hours_start TEXT;
+ALTER TABLE user_settings ADD COLUMN quiet_hours_end TEXT;
@@ -0,0 +1 @@
+ALTER TABLE user_settings DROP COLUMN allow_listen_along;
@@ -0,0 +1 @@
+ALTER TABLE user_settings ADD COLUMN allow_listen_along BOOLEAN NOT NULL DEFAULT 1;
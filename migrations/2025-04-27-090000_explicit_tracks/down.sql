@@ -0,0 +1 @@
+ALTER TABLE music DROP COLUMN explicit;
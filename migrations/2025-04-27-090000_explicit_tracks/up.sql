@@ -0,0 +1,2 @@
+-- From the iTunes advisory tag, lobbies with the family filter on keep these out of the queue
+ALTER TABLE music ADD COLUMN explicit BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+DROP TABLE play_rollups;
@@ -0,0 +1,9 @@
+-- Play events older than the retention window end up here, one row per track a user played in a month
+CREATE TABLE play_rollups (
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	month TEXT NOT NULL, -- YYYY-MM in the instance's local time, like the stats
+	music_id TEXT NOT NULL REFERENCES music(music_id),
+	play_count INTEGER NOT NULL,
+	listened_secs BIGINT NOT NULL,
+	PRIMARY KEY (user_id, month, music_id)
+);
@@ -0,0 +1,2 @@
+DROP TABLE play_rollups_daily;
+ALTER TABLE play_rollups_monthly RENAME TO play_rollups;
@@ -0,0 +1,27 @@
+ALTER TABLE play_rollups RENAME TO play_rollups_monthly;
+
+-- Kept up to date with every play, the stats read these for everything but the last few days
+CREATE TABLE play_rollups_daily (
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	day TEXT NOT NULL, -- YYYY-MM-DD in the instance's local time, like the stats
+	music_id TEXT NOT NULL REFERENCES music(music_id),
+	play_count INTEGER NOT NULL,
+	listened_secs BIGINT NOT NULL,
+	PRIMARY KEY (user_id, day, music_id)
+);
+
+-- Only pruned plays were rolled up so far, the events still around are added on top
+INSERT INTO play_rollups_daily (user_id, day, music_id, play_count, listened_secs)
+SELECT user_id, date(played_date_time, 'localtime'), music_id, COUNT(*), SUM(listened_secs)
+FROM play_events
+WHERE date(played_date_time, 'localtime') IS NOT NULL
+GROUP BY 1, 2, 3;
+
+INSERT INTO play_rollups_monthly (user_id, month, music_id, play_count, listened_secs)
+SELECT user_id, strftime('%Y-%m', played_date_time, 'localtime'), music_id, COUNT(*), SUM(listened_secs)
+FROM play_events
+WHERE strftime('%Y-%m', played_date_time, 'localtime') IS NOT NULL
+GROUP BY 1, 2, 3
+ON CONFLICT (user_id, month, music_id) DO UPDATE SET
+	play_count = play_count + excluded.play_count,
+	listened_secs = listened_secs + excluded.listened_secs;
@@ -0,0 +1,2 @@
+-- Bumped on every edit, edits made against an older version are turned down
+ALTER TABLE playlists ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,7 @@
+-- What a removal, clear or delete took away, until the undo token expires or is used
+CREATE TABLE playlist_undo (
+	token TEXT PRIMARY KEY NOT NULL,
+	playlist_id TEXT NOT NULL, -- no foreign key, the playlist may be the thing deleted
+	snapshot TEXT NOT NULL, -- json
+	expires_date_time TEXT NOT NULL
+);
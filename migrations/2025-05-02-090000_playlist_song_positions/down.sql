@@ -0,0 +1,16 @@
+CREATE TABLE playlist_songs_unique (
+	playlist_id TEXT NOT NULL REFERENCES playlists(playlist_id),
+	music_id TEXT NOT NULL REFERENCES music(music_id),
+	song_adder_id TEXT NOT NULL REFERENCES users(user_id), --for combined playlist added by
+	song_added_date_time TEXT NOT NULL,
+	PRIMARY KEY (playlist_id, music_id)
+);
+
+-- Only the first copy of a duplicated track is kept
+INSERT OR IGNORE INTO playlist_songs_unique (playlist_id, music_id, song_adder_id, song_added_date_time)
+SELECT playlist_id, music_id, song_adder_id, song_added_date_time
+FROM playlist_songs
+ORDER BY position;
+
+DROP TABLE playlist_songs;
+ALTER TABLE playlist_songs_unique RENAME TO playlist_songs;
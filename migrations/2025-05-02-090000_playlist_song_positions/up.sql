@@ -0,0 +1,17 @@
+-- Songs are keyed by their place in the playlist, so a track can be added more than once
+CREATE TABLE playlist_songs_positioned (
+	playlist_id TEXT NOT NULL REFERENCES playlists(playlist_id),
+	music_id TEXT NOT NULL REFERENCES music(music_id),
+	song_adder_id TEXT NOT NULL REFERENCES users(user_id), --for combined playlist added by
+	song_added_date_time TEXT NOT NULL,
+	position INTEGER NOT NULL, -- order in the playlist, gaps are left by removals
+	PRIMARY KEY (playlist_id, position)
+);
+
+INSERT INTO playlist_songs_positioned (playlist_id, music_id, song_adder_id, song_added_date_time, position)
+SELECT playlist_id, music_id, song_adder_id, song_added_date_time,
+	ROW_NUMBER() OVER (PARTITION BY playlist_id ORDER BY song_added_date_time, music_id) - 1
+FROM playlist_songs;
+
+DROP TABLE playlist_songs;
+ALTER TABLE playlist_songs_positioned RENAME TO playlist_songs;
@@ -0,0 +1,2 @@
+DROP TABLE library_changes;
+DROP TABLE webhooks;
@@ -0,0 +1,17 @@
+-- Integrations that mirror the library, they get the changes posted to them in order
+CREATE TABLE webhooks (
+	webhook_id TEXT PRIMARY KEY NOT NULL,
+	url TEXT NOT NULL,
+	secret TEXT NOT NULL, -- sent as a bearer token so the receiver knows it's us
+	delivered_change_id INTEGER NOT NULL, -- the last library change it got
+	last_error TEXT, -- of the last delivery, NULL once one goes through
+	created_date_time TEXT NOT NULL
+);
+
+-- Tracks added, removed or retagged, kept until every webhook got them
+CREATE TABLE library_changes (
+	change_id INTEGER PRIMARY KEY NOT NULL,
+	music_id TEXT NOT NULL, -- no foreign key, removed tracks may be gone
+	change TEXT NOT NULL, -- added, removed or retagged
+	changed_date_time TEXT NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Device tokens that can only report plays, for players without a browser session
+CREATE TABLE scrobble_tokens (
+	token_id TEXT PRIMARY KEY NOT NULL,
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	name TEXT NOT NULL, -- what the user calls the device
+	token TEXT NOT NULL UNIQUE,
+	created_date_time TEXT NOT NULL,
+	last_used_date_time TEXT
+);
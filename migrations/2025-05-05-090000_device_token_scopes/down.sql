@@ -0,0 +1 @@
+ALTER TABLE scrobble_tokens DROP COLUMN scope;
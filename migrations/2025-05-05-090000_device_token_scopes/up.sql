@@ -0,0 +1,2 @@
+-- scrobble tokens only report plays, mpd tokens log in to the MPD bridge
+ALTER TABLE scrobble_tokens ADD COLUMN scope TEXT NOT NULL DEFAULT 'scrobble';
@@ -0,0 +1,8 @@
+-- Addresses or ranges the admins turned away, allow rules punch holes into the deny ones
+CREATE TABLE ip_rules (
+	rule_id TEXT PRIMARY KEY NOT NULL,
+	cidr TEXT NOT NULL UNIQUE, -- a single address or a range, like 203.0.113.0/24
+	action TEXT NOT NULL, -- allow or deny
+	note TEXT, -- why, for the other admins
+	created_date_time TEXT NOT NULL
+);
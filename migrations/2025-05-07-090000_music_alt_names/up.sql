@@ -0,0 +1,9 @@
+-- Other forms of a track's title and artist, so 東京 is found by Tokyo and the other way around
+CREATE TABLE music_alt_names (
+	music_id TEXT NOT NULL REFERENCES music(music_id),
+	field TEXT NOT NULL, -- title or artist
+	name TEXT NOT NULL,
+	kind TEXT NOT NULL, -- original (the native script), romanized or translated
+	PRIMARY KEY (music_id, field, name)
+);
+CREATE INDEX music_alt_names_name ON music_alt_names (name);
@@ -0,0 +1,14 @@
+-- A search kept under a name, listed with the user's playlists and run again every time it's opened
+CREATE TABLE saved_searches (
+	search_id TEXT PRIMARY KEY NOT NULL,
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	name TEXT NOT NULL,
+	search_string TEXT NOT NULL, -- operators included
+	genre TEXT,
+	decade TEXT,
+	bpm_min DOUBLE,
+	bpm_max DOUBLE,
+	musical_key TEXT,
+	created_date_time TEXT NOT NULL
+);
+CREATE INDEX saved_searches_user_id ON saved_searches (user_id);
@@ -0,0 +1,2 @@
+DROP TABLE new_releases;
+DROP TABLE artist_follows;
@@ -0,0 +1,20 @@
+-- Artists a user wants to hear about when the scanner adds tracks of theirs
+CREATE TABLE artist_follows (
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	artist TEXT NOT NULL,
+	followed_date_time TEXT NOT NULL,
+	PRIMARY KEY (user_id, artist)
+);
+CREATE INDEX artist_follows_artist ON artist_follows (artist);
+
+-- A feed item per follower for the tracks of an album one scan added
+CREATE TABLE new_releases (
+	release_id TEXT PRIMARY KEY NOT NULL,
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	artist TEXT NOT NULL,
+	album TEXT NOT NULL,
+	music_ids TEXT NOT NULL, -- json array, in the order they were scanned
+	released_date_time TEXT NOT NULL,
+	notified BOOLEAN NOT NULL DEFAULT 0 -- the follower got the notification
+);
+CREATE INDEX new_releases_user_id ON new_releases (user_id, released_date_time);
@@ -0,0 +1,8 @@
+-- Tracks and artists a user never wants played, shuffles, radios and lobby queues skip them
+CREATE TABLE blocked_content (
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	target_type TEXT NOT NULL, -- track or artist
+	target_id TEXT NOT NULL, -- the music_id or the artist name
+	blocked_date_time TEXT NOT NULL,
+	PRIMARY KEY (user_id, target_type, target_id)
+);
@@ -0,0 +1,11 @@
+-- Play queues kept under a name to go back to, logging out keeps one on its own
+CREATE TABLE queue_snapshots (
+	snapshot_id TEXT PRIMARY KEY NOT NULL,
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	name TEXT NOT NULL,
+	music_ids TEXT NOT NULL, -- json array, in queue order
+	position DOUBLE NOT NULL, -- seconds into the first track
+	automatic BOOLEAN NOT NULL, -- taken on logout, only the latest few are kept
+	created_date_time TEXT NOT NULL
+);
+CREATE INDEX queue_snapshots_user_id ON queue_snapshots (user_id, created_date_time);
@@ -0,0 +1 @@
+DROP TABLE listening_goals;
@@ -0,0 +1,14 @@
+-- Goals the users set for themselves, the progress is kept up to date from their plays
+CREATE TABLE listening_goals (
+	goal_id TEXT PRIMARY KEY NOT NULL,
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	kind TEXT NOT NULL, -- plays, minutes, new_artists or new_tracks
+	target INTEGER NOT NULL,
+	period_start TEXT NOT NULL, -- local days, both included
+	period_end TEXT NOT NULL,
+	progress INTEGER NOT NULL DEFAULT 0,
+	completed_date_time TEXT,
+	created_date_time TEXT NOT NULL
+);
+
+CREATE INDEX listening_goals_user_id ON listening_goals(user_id);
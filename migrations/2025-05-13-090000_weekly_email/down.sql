@@ -0,0 +1,2 @@
+DROP TABLE weekly_emails;
+ALTER TABLE user_settings DROP COLUMN weekly_email;
@@ -0,0 +1,9 @@
+ALTER TABLE user_settings ADD COLUMN weekly_email BOOLEAN NOT NULL DEFAULT 1;
+
+-- The weeks a summary went out for, so a restart doesn't send one twice
+CREATE TABLE weekly_emails (
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	week_start TEXT NOT NULL, -- the monday of the summed up week, local day
+	sent_date_time TEXT NOT NULL,
+	PRIMARY KEY (user_id, week_start)
+);
@@ -0,0 +1,38 @@
+-- Third party apps acting for the users that allowed them to
+CREATE TABLE oauth_clients (
+	client_id TEXT PRIMARY KEY NOT NULL,
+	client_secret TEXT NOT NULL,
+	name TEXT NOT NULL,
+	redirect_uris TEXT NOT NULL, -- json array, the redirect_uri asked for has to be one of them exactly
+	owner_id TEXT NOT NULL REFERENCES users(user_id),
+	created_date_time TEXT NOT NULL
+);
+
+-- What the user agreed to let the app do
+CREATE TABLE oauth_grants (
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	client_id TEXT NOT NULL REFERENCES oauth_clients(client_id),
+	scope TEXT NOT NULL, -- space separated
+	granted_date_time TEXT NOT NULL,
+	PRIMARY KEY (user_id, client_id)
+);
+
+-- Authorization codes, traded for tokens once
+CREATE TABLE oauth_codes (
+	code TEXT PRIMARY KEY NOT NULL,
+	client_id TEXT NOT NULL REFERENCES oauth_clients(client_id),
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	redirect_uri TEXT NOT NULL,
+	scope TEXT NOT NULL,
+	expires_date_time TEXT NOT NULL
+);
+
+CREATE TABLE oauth_tokens (
+	access_token TEXT PRIMARY KEY NOT NULL,
+	refresh_token TEXT NOT NULL UNIQUE,
+	client_id TEXT NOT NULL REFERENCES oauth_clients(client_id),
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	scope TEXT NOT NULL,
+	expires_date_time TEXT NOT NULL, -- of the access token, the refresh token lasts until used or revoked
+	created_date_time TEXT NOT NULL
+);
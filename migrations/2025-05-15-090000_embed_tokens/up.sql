@@ -0,0 +1,10 @@
+-- Read only tokens for embedding a single public playlist or profile on another site
+CREATE TABLE embed_tokens (
+	token_id TEXT PRIMARY KEY NOT NULL,
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	target_type TEXT NOT NULL, -- playlist | profile
+	target_id TEXT NOT NULL, -- the playlist_id, or the user_id for the profile
+	token TEXT NOT NULL UNIQUE,
+	created_date_time TEXT NOT NULL,
+	last_used_date_time TEXT
+);
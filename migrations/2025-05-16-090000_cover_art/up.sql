@@ -0,0 +1,10 @@
+-- Artwork uploaded for an album, the cover it had from its tags is kept as the original
+CREATE TABLE cover_art (
+	art_id TEXT PRIMARY KEY NOT NULL,
+	img_uuid TEXT NOT NULL, -- the album's cover uuid
+	uploader_id TEXT REFERENCES users(user_id), -- none for the original
+	is_original BOOLEAN NOT NULL DEFAULT FALSE,
+	is_canonical BOOLEAN NOT NULL DEFAULT FALSE, -- the one served at /image/:img_uuid
+	uploaded_date_time TEXT NOT NULL
+);
+CREATE INDEX cover_art_img_uuid ON cover_art(img_uuid);
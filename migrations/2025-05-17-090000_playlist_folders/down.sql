@@ -0,0 +1,2 @@
+DROP TABLE playlist_folder_items;
+DROP TABLE playlist_folders;
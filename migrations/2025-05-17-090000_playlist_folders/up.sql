@@ -0,0 +1,19 @@
+-- Folders a user keeps their playlists in, they can hold other folders
+CREATE TABLE playlist_folders (
+	folder_id TEXT PRIMARY KEY NOT NULL,
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	name TEXT NOT NULL,
+	parent_id TEXT REFERENCES playlist_folders(folder_id), -- none at the top
+	position INTEGER NOT NULL, -- among the folders of the parent, 0 is first
+	created_date_time TEXT NOT NULL
+);
+
+-- Where a user put a playlist, their own or one shared with them. Playlists without a row are at the top,
+-- after the ones with a position there.
+CREATE TABLE playlist_folder_items (
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	playlist_id TEXT NOT NULL,
+	folder_id TEXT REFERENCES playlist_folders(folder_id), -- none at the top
+	position INTEGER NOT NULL, -- among the playlists of the folder, 0 is first
+	PRIMARY KEY (user_id, playlist_id)
+);
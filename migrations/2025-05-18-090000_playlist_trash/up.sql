@@ -0,0 +1,11 @@
+-- Deleted playlists, kept for their owner to restore until purged
+CREATE TABLE playlist_trash (
+	playlist_id TEXT PRIMARY KEY NOT NULL,
+	user_id TEXT NOT NULL REFERENCES users(user_id), -- the owner
+	playlist_name TEXT NOT NULL,
+	song_count INTEGER NOT NULL,
+	snapshot TEXT NOT NULL, -- PlaylistState as json
+	deleted_date_time TEXT NOT NULL,
+	purge_date_time TEXT NOT NULL
+);
+CREATE INDEX playlist_trash_user_id ON playlist_trash(user_id);
@@ -0,0 +1,9 @@
+-- Playlists kept private until they get released, one release per playlist
+CREATE TABLE playlist_releases (
+	playlist_id TEXT PRIMARY KEY NOT NULL REFERENCES playlists(playlist_id),
+	user_id TEXT NOT NULL REFERENCES users(user_id), -- the owner
+	release_date_time TEXT NOT NULL,
+	previous_availability TEXT NOT NULL, -- put back when the release is called off
+	scheduled_date_time TEXT NOT NULL
+);
+CREATE INDEX playlist_releases_release_date_time ON playlist_releases(release_date_time);
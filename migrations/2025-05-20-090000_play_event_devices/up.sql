@@ -0,0 +1,2 @@
+-- What the play came from, the client's name for itself or the scrobble token's name
+ALTER TABLE play_events ADD COLUMN device TEXT;
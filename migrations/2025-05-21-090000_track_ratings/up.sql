@@ -0,0 +1,8 @@
+-- Star ratings of the tracks, one per user and track
+CREATE TABLE track_ratings (
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	music_id TEXT NOT NULL REFERENCES music(music_id),
+	rating INTEGER NOT NULL CHECK (rating BETWEEN 1 AND 5),
+	rated_date_time TEXT NOT NULL,
+	PRIMARY KEY (user_id, music_id)
+);
@@ -0,0 +1,8 @@
+-- Pictures of the artists fetched from an outside provider, the covers fall back to them
+CREATE TABLE artist_images (
+	artist TEXT PRIMARY KEY NOT NULL, -- the main artist of the tracks, lowercased
+	img_uuid TEXT NOT NULL, -- the file in the artist image storage
+	source TEXT NOT NULL, -- the provider
+	source_url TEXT,
+	fetched_date_time TEXT NOT NULL
+);
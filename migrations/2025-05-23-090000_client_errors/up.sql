@@ -0,0 +1,14 @@
+-- Playback failures as the players report them, kept for the admins
+CREATE TABLE client_errors (
+	error_id TEXT PRIMARY KEY NOT NULL,
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	music_id TEXT NOT NULL REFERENCES music(music_id),
+	error_code TEXT NOT NULL,
+	device TEXT,
+	network_type TEXT,
+	message TEXT,
+	reported_date_time TEXT NOT NULL
+);
+
+CREATE INDEX client_errors_music_id ON client_errors(music_id);
+CREATE INDEX client_errors_reported ON client_errors(reported_date_time);
@@ -0,0 +1,8 @@
+-- How much of the expensive features each user used per UTC day, the limits are in instance_settings
+CREATE TABLE quotas (
+	user_id TEXT NOT NULL REFERENCES users(user_id),
+	feature TEXT NOT NULL,
+	day TEXT NOT NULL,
+	used INTEGER NOT NULL DEFAULT 0,
+	PRIMARY KEY (user_id, feature, day)
+);
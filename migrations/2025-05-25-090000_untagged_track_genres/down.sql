@@ -0,0 +1,2 @@
+-- The rows can't be told apart from the tagged ones, they are left in place
+SELECT 1;
@@ -0,0 +1,4 @@
+-- Tracks scanned without a genre tag had no track_genres row, they get one for music.genre
+INSERT OR IGNORE INTO track_genres (music_id, genre, position)
+SELECT music_id, genre, 0 FROM music
+WHERE music_id NOT IN (SELECT music_id FROM track_genres);
@@ -0,0 +1,2 @@
+ALTER TABLE oauth_codes DROP COLUMN code_challenge;
+ALTER TABLE oauth_clients DROP COLUMN is_public;
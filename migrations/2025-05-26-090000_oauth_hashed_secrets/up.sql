@@ -0,0 +1,9 @@
+-- Secrets, codes and tokens are only kept as sha256 hashes from now on. The plain ones can't be hashed
+-- here: the codes and tokens are dropped, the apps ask their users again, and the secrets have to be reset.
+DELETE FROM oauth_tokens;
+DELETE FROM oauth_codes;
+UPDATE oauth_clients SET client_secret = '';
+
+-- Apps that can't keep a secret, like the ones on phones, prove the code is theirs with PKCE (RFC 7636)
+ALTER TABLE oauth_clients ADD COLUMN is_public BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE oauth_codes ADD COLUMN code_challenge TEXT; -- S256 of the code_verifier
@@ -0,0 +1,17 @@
+CREATE TABLE playlist_releases_referenced (
+	playlist_id TEXT PRIMARY KEY NOT NULL REFERENCES playlists(playlist_id),
+	user_id TEXT NOT NULL REFERENCES users(user_id), -- the owner
+	release_date_time TEXT NOT NULL,
+	previous_availability TEXT NOT NULL, -- put back when the release is called off
+	scheduled_date_time TEXT NOT NULL
+);
+
+-- The releases of the playlists in the trash have nothing to point at
+INSERT INTO playlist_releases_referenced (playlist_id, user_id, release_date_time, previous_availability, scheduled_date_time)
+SELECT playlist_id, user_id, release_date_time, previous_availability, scheduled_date_time
+FROM playlist_releases
+WHERE playlist_id IN (SELECT playlist_id FROM playlists);
+
+DROP TABLE playlist_releases;
+ALTER TABLE playlist_releases_referenced RENAME TO playlist_releases;
+CREATE INDEX playlist_releases_release_date_time ON playlist_releases(release_date_time);
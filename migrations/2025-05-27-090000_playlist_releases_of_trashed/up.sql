@@ -0,0 +1,16 @@
+-- A deleted playlist keeps its release while it sits in the trash, so the release can't point at playlists
+CREATE TABLE playlist_releases_trashable (
+	playlist_id TEXT PRIMARY KEY NOT NULL,
+	user_id TEXT NOT NULL REFERENCES users(user_id), -- the owner
+	release_date_time TEXT NOT NULL,
+	previous_availability TEXT NOT NULL, -- put back when the release is called off
+	scheduled_date_time TEXT NOT NULL
+);
+
+INSERT INTO playlist_releases_trashable (playlist_id, user_id, release_date_time, previous_availability, scheduled_date_time)
+SELECT playlist_id, user_id, release_date_time, previous_availability, scheduled_date_time
+FROM playlist_releases;
+
+DROP TABLE playlist_releases;
+ALTER TABLE playlist_releases_trashable RENAME TO playlist_releases;
+CREATE INDEX playlist_releases_release_date_time ON playlist_releases(release_date_time);
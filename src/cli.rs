@@ -0,0 +1,217 @@
+use crate::core::{doctor, tokens};
+use crate::lobic_db::db::{generate_db_pool, DatabasePool};
+use crate::lobic_db::models::User;
+use crate::lobic_db::seed;
+use crate::schema::users;
+use crate::services::MusicService;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use pwhash::bcrypt;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+const BACKUP_DIR: &str = "./backups";
+
+const USAGE: &str = "Usage:
+	Lobic                                       start the server
+	Lobic user create-admin --username U --email E --password P
+	Lobic library scan <path> [--uploader USER_ID]
+	Lobic library backfill-years
+	Lobic library analyze                      tempo and key of the music missing them, needs AUDIO_ANALYSIS=true
+	Lobic library moods                        mood and energy of the music with a tempo but no mood
+	Lobic token revoke-all
+	Lobic db backup [path]
+	Lobic doctor                               checks config, media roots, ffmpeg, smtp and the database
+	Lobic seed [--tracks N] [--users N] [--plays N] [--playlists N] [--friends N] [--seed N]";
+
+// Runs the subcommand instead of starting the server, `args` excludes the binary name
+pub fn run(args: &[String]) -> Result<(), String> {
+	let db_pool = generate_db_pool();
+	let mut db_conn = db_pool
+		.get()
+		.map_err(|err| format!("Failed to connect to the database: {err}"))?;
+
+	let command: Vec<&str> = args.iter().take(2).map(String::as_str).collect();
+	match command.as_slice() {
+		["user", "create-admin"] => create_admin(&parse_flags(&args[2..])?, &mut db_conn),
+		["library", "scan"] => scan_library(&args[2..], &MusicService::new(&db_pool)),
+		["library", "backfill-years"] => backfill_years(&MusicService::new(&db_pool)),
+		["library", "analyze"] => analyze_library(&MusicService::new(&db_pool)),
+		["library", "moods"] => tag_moods(&MusicService::new(&db_pool)),
+		["token", "revoke-all"] => revoke_tokens(&mut db_conn),
+		["db", "backup"] => backup_db(args.get(2).map(String::as_str), &mut db_conn),
+		["seed", ..] => seed::run(&args[1..], &mut db_conn),
+		["doctor", ..] | ["--doctor", ..] => run_doctor(&db_pool),
+		["help", ..] | ["--help", ..] => {
+			println!("{USAGE}");
+			Ok(())
+		}
+		_ => Err(format!("Unknown command: {}\n\n{USAGE}", args.join(" "))),
+	}
+}
+
+// Prints every finding, fails when any of them is an error
+fn run_doctor(db_pool: &DatabasePool) -> Result<(), String> {
+	let report = doctor::diagnose(db_pool);
+	for finding in &report.findings {
+		println!("{:<22} {:<8?} {}", finding.check, finding.severity, finding.message);
+		if let Some(fix) = &finding.fix {
+			println!("{:<31} {fix}", "");
+		}
+	}
+	match report.healthy {
+		true => Ok(()),
+		false => Err("The doctor found errors, see above".to_string()),
+	}
+}
+
+// Parses `--name value` and `--name=value` pairs
+fn parse_flags(args: &[String]) -> Result<HashMap<String, String>, String> {
+	let mut flags = HashMap::new();
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		let Some(flag) = arg.strip_prefix("--") else {
+			return Err(format!("Unexpected argument {arg}"));
+		};
+		let (name, value) = match flag.split_once('=') {
+			Some((name, value)) => (name.to_string(), value.to_string()),
+			None => (
+				flag.to_string(),
+				args.next().cloned().ok_or(format!("Missing value for {arg}"))?,
+			),
+		};
+		flags.insert(name, value);
+	}
+	Ok(flags)
+}
+
+fn required<'a>(flags: &'a HashMap<String, String>, name: &str) -> Result<&'a str, String> {
+	flags
+		.get(name)
+		.map(String::as_str)
+		.ok_or(format!("Missing --{name}\n\n{USAGE}"))
+}
+
+// Creates a verified admin account, or promotes the account already using the email
+fn create_admin(flags: &HashMap<String, String>, db_conn: &mut SqliteConnection) -> Result<(), String> {
+	let email = required(flags, "email")?;
+
+	let existing = users::table
+		.filter(users::email.eq(email))
+		.first::<User>(db_conn)
+		.optional()
+		.map_err(|err| err.to_string())?;
+	if let Some(user) = existing {
+		diesel::update(users::table.filter(users::user_id.eq(&user.user_id)))
+			.set(users::is_admin.eq(true))
+			.execute(db_conn)
+			.map_err(|err| err.to_string())?;
+		println!("Promoted {} ({}) to admin", user.username, user.user_id);
+		return Ok(());
+	}
+
+	let username = required(flags, "username")?;
+	let password = required(flags, "password")?;
+	let taken = users::table
+		.filter(users::username.eq(username))
+		.count()
+		.get_result::<i64>(db_conn)
+		.map_err(|err| err.to_string())?;
+	if taken > 0 {
+		return Err(format!("Account with username {username} has already been registered"));
+	}
+
+	let user = User {
+		user_id: Uuid::new_v4().to_string(),
+		username: username.to_string(),
+		email: email.to_string(),
+		pwd_hash: bcrypt::hash(password).map_err(|err| err.to_string())?,
+		email_verified: true,
+		otp: String::new(),
+		otp_expires_at: Utc::now().to_string(),
+		otp_verified: None,
+		is_admin: true,
+	};
+	diesel::insert_into(users::table)
+		.values(&user)
+		.execute(db_conn)
+		.map_err(|err| err.to_string())?;
+	println!("Created admin {} ({})", user.username, user.user_id);
+	Ok(())
+}
+
+fn scan_library(args: &[String], music_service: &MusicService) -> Result<(), String> {
+	let (path, flags) = match args.split_first() {
+		Some((path, rest)) if !path.starts_with("--") => (path, parse_flags(rest)?),
+		_ => return Err(format!("Missing the path to scan\n\n{USAGE}")),
+	};
+
+	let report = music_service
+		.scan(path, flags.get("uploader").map(String::as_str))
+		.map_err(|err| err.to_string())?;
+	for error in &report.errors {
+		eprintln!("{error}");
+	}
+	println!(
+		"Processed {} files, {} failed, in {:.1}s ({:.1} files/s on {} workers)",
+		report.saved,
+		report.errors.len(),
+		report.elapsed_secs,
+		report.files_per_sec,
+		report.workers
+	);
+	Ok(())
+}
+
+// Release years of the music saved before they were read at ingest
+fn backfill_years(music_service: &MusicService) -> Result<(), String> {
+	let found = music_service.backfill_release_years().map_err(|err| err.to_string())?;
+	println!("Found the release year of {found} songs");
+	Ok(())
+}
+
+fn analyze_library(music_service: &MusicService) -> Result<(), String> {
+	let analyzed = music_service.analyze_missing().map_err(|err| err.to_string())?;
+	println!("Analyzed {analyzed} songs");
+	Ok(())
+}
+
+fn tag_moods(music_service: &MusicService) -> Result<(), String> {
+	let tagged = music_service.tag_missing_moods().map_err(|err| err.to_string())?;
+	println!("Tagged {tagged} songs with a mood");
+	Ok(())
+}
+
+fn revoke_tokens(db_conn: &mut SqliteConnection) -> Result<(), String> {
+	tokens::revoke_all(db_conn).map_err(|err| err.to_string())?;
+	println!(
+		"Revoked every session, a running server picks it up within {} seconds",
+		tokens::RELOAD_INTERVAL.as_secs()
+	);
+	Ok(())
+}
+
+// Consistent copy of the database, safe to take while the server is running
+fn backup_db(path: Option<&str>, db_conn: &mut SqliteConnection) -> Result<(), String> {
+	let path = match path {
+		Some(path) => path.to_string(),
+		None => {
+			fs::create_dir_all(BACKUP_DIR).map_err(|err| err.to_string())?;
+			format!("{BACKUP_DIR}/lobic-{}.db", Utc::now().format("%Y%m%d-%H%M%S"))
+		}
+	};
+	if Path::new(&path).exists() {
+		return Err(format!("{path} already exists"));
+	}
+
+	diesel::sql_query("VACUUM INTO ?")
+		.bind::<Text, _>(&path)
+		.execute(db_conn)
+		.map_err(|err| format!("Failed to back up the database: {err}"))?;
+	println!("Backed up the database to {path}");
+	Ok(())
+}
@@ -1,4 +1,3 @@
-use axum::http::{request::Parts, HeaderValue};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use local_ip_address::local_ip;
@@ -12,7 +11,71 @@ pub const COVER_IMG_STORAGE: &str = "./storage/cover_images";
 pub const MUSIC_STORAGE: &str = "./storage/music_db";
 pub const USER_PFP_STORAGE: &str = "./storage/users_pfps";
 pub const PLAYLIST_COVER_IMG_STORAGE: &str = "./storage/playlists_cover_img";
+pub const ANIMATED_COVER_STORAGE: &str = "./storage/animated_covers";
+pub const COVER_ART_STORAGE: &str = "./storage/cover_art"; // every version of the album covers
+pub const ARTIST_IMG_STORAGE: &str = "./storage/artist_images"; // what the covers fall back to
+pub const TRANSCODE_CACHE_STORAGE: &str = "./storage/transcode_cache"; // the streams at a lower bitrate
 pub const DEV: bool = true;
+pub const API_VERSION: &str = "1";
+pub const MAX_JSON_BODY_BYTES: usize = 64 * 1024; // every request body but the uploads
+pub const MAX_UPLOAD_BYTES: usize = 2 * 1024 * 1024; // profile pictures and playlist covers
+pub const STREAM_FORMATS: [&str; 1] = ["mp3"];
+pub const TRANSCODE_BITRATES: [u32; 3] = [64, 128, 192]; // kbps, what a stream can ask for with ?bitrate=
+pub const DEFAULT_TRANSCODE_CACHE_MAX_MB: i64 = 2048; // until the admins set transcode_cache_max_mb
+pub const DEFAULT_TRANSCODE_CACHE_LOW_WATER_PERCENT: i64 = 80; // of the max, where the eviction stops
+pub const MAX_TRANSCODE_CACHE_MB: i64 = 1024 * 1024;
+pub const IMAGE_FORMATS: [&str; 1] = ["png"];
+pub const ANIMATED_COVER_FORMATS: [&str; 4] = ["mp4", "mov", "webm", "gif"]; // always served as mp4
+pub const MAX_ANIMATED_COVER_BYTES: usize = 16 * 1024 * 1024;
+pub const MAX_ANIMATED_COVER_SECS: f64 = 15.0;
+pub const MAX_COVER_ART_BYTES: usize = 10 * 1024 * 1024;
+pub const MAX_ARTIST_IMAGE_BYTES: usize = 4 * 1024 * 1024; // fetched from the provider
+pub const MAX_CURATION_IMPORT_BYTES: usize = 32 * 1024 * 1024; // an itunes library of a few ten thousand tracks
+pub const MAX_PROFILE_PINS: usize = 6;
+pub const MAX_PLAYLIST_FOLDERS: usize = 200; // per user
+pub const MAX_FOLDER_DEPTH: usize = 4; // folders in folders, the top ones are at depth 1
+pub const MAX_AUTOMATIC_QUEUE_SNAPSHOTS: i64 = 5; // the older ones taken on logout are dropped
+pub const MAX_SCROBBLE_TOKENS: i64 = 10; // per user
+pub const MAX_EMBED_TOKENS: i64 = 20; // per user
+pub const EMBED_CACHE_SECS: u64 = 60; // the widgets pick up edits after at most this long
+pub const OVERLAY_REFRESH_SECS: u64 = 5; // how often the html overlay reloads itself
+pub const MAX_LOOKUP_IDS: usize = 200; // per /music/lookup request
+pub const SAVED_SEARCH_PAGE_LENGTH: i64 = 50; // songs of a saved search per page, unless asked for fewer
+pub const MAX_SCAN_WORKERS: usize = 16; // files read at the same time by a scan, SCAN_WORKERS can lower it
+pub const SCAN_BATCH_SIZE: usize = 50; // scanned files saved per transaction
+pub const ANTHEM_PREVIEW_SECS: f64 = 30.0;
+pub const PREFETCH_URL_SECS: u64 = 60 * 60; // how long the signed stream urls in prefetch hints stay good
+pub const SIGNED_IMAGE_URL_SECS: u64 = 6 * 60 * 60; // the signed image urls change once per this, and stay good for one more
+pub const MAX_SIGNED_IMAGES: usize = 500; // per /images/sign request
+pub const MAX_ARTIST_IMAGE_FETCHES: usize = 50; // per request, the rest are left for the next one
+pub const IMAGE_SIZES: [usize; 4] = [64, 128, 300, 640]; // the size variants handed out, in pixels
+pub const OAUTH_CODE_SECS: i64 = 10 * 60; // authorization codes have to be traded for tokens within it
+pub const OAUTH_ACCESS_TOKEN_SECS: i64 = 60 * 60;
+pub const OAUTH_REFRESH_TOKEN_DAYS: i64 = 90; // unused refresh tokens are dropped after it
+pub const PLAYLIST_UNDO_SECS: i64 = 10 * 60; // how long a removed song or deleted playlist can be brought back
+pub const PLAYLIST_TRASH_DAYS: i64 = 30; // how long a deleted playlist stays in the trash of its owner
+pub const MAX_RELEASE_SCHEDULE_DAYS: i64 = 365; // how far ahead a playlist release can be scheduled
+pub const MAX_SLEEP_TIMER_MINUTES: u64 = 24 * 60;
+pub const MAX_DJ_TURN_MINUTES: u64 = 24 * 60;
+pub const PRIVATE_SESSION_HOURS: i64 = 6; // unless the user asks for another length
+pub const MAX_PRIVATE_SESSION_HOURS: i64 = 24;
+pub const DEFAULT_CHAT_RETENTION_HOURS: i64 = 24; // until the admins set chat_retention_hours
+pub const MAX_CHAT_RETENTION_HOURS: i64 = 30 * 24;
+pub const DEFAULT_PLAY_EVENT_RETENTION_DAYS: i64 = 400; // older plays are only kept as monthly rollups
+pub const MIN_PLAY_EVENT_RETENTION_DAYS: i64 = 100; // the analytics and streaks look back this far
+pub const MAX_RETENTION_DAYS: i64 = 36500; // longer windows would overflow the date math in the pruning job
+pub const DEFAULT_PLAY_MIN_PERCENT: i64 = 0; // of the track, until the admins set play_min_percent
+pub const DEFAULT_PLAY_MIN_SECS: i64 = 0; // every play counts until the admins set play_min_secs
+pub const SESSION_GAP_MINUTES: i64 = 30; // plays further apart are in different listening sessions
+pub const MAX_HISTORY_DAYS: i64 = 365; // the listening sessions go back at most this far
+pub const DEFAULT_AUDIT_LOG_RETENTION_DAYS: i64 = 365;
+pub const CLIENT_ERROR_RETENTION_DAYS: i64 = 90; // playback error reports are dropped after it
+pub const MAX_CLIENT_ERRORS_PER_HOUR: i64 = 120; // per user, a player stuck in a retry loop stops there
+// Per user and UTC day until the admins set otherwise, 0 turns the feature off
+pub const DEFAULT_TRANSCODE_QUOTA: i64 = 10; // animated covers run through ffmpeg
+pub const DEFAULT_EXPORT_QUOTA: i64 = 20;
+pub const DEFAULT_METADATA_LOOKUP_QUOTA: i64 = 200; // requests to the outside providers, one per artist
+pub const MAX_QUOTA: i64 = 10_000;
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum OpCode {
@@ -49,6 +112,66 @@ pub enum OpCode {
 	NOTIFICATION,
 	#[allow(non_camel_case_types)]
 	REQUEST_MUSIC_PLAY,
+	#[allow(non_camel_case_types)]
+	SET_SLEEP_TIMER,
+	#[allow(non_camel_case_types)]
+	CANCEL_SLEEP_TIMER,
+	#[allow(non_camel_case_types)]
+	FADE_OUT,
+	#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+	TAKEDOWN,
+	#[allow(non_camel_case_types)]
+	TAKEDOWN_APPEAL,
+	#[allow(non_camel_case_types)]
+	TAKEDOWN_RESOLVED,
+	#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+	SUBSCRIBE,
+	#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+	UNSUBSCRIBE,
+	#[allow(non_camel_case_types)]
+	LIBRARY_UPDATE,
+	#[allow(non_camel_case_types)]
+	PLAYER_HEARTBEAT,
+	#[allow(non_camel_case_types)]
+	PLAYER_COMMAND,
+	#[allow(non_camel_case_types)]
+	FRIEND_ACTIVITY,
+	#[allow(non_camel_case_types)]
+	ACHIEVEMENT_UNLOCKED,
+	#[allow(non_camel_case_types)]
+	ON_THIS_DAY,
+	#[allow(non_camel_case_types)]
+	LISTEN_ALONG,
+	#[allow(non_camel_case_types)]
+	SET_DJ_ROTATION,
+	#[allow(non_camel_case_types)]
+	DJ_OPT_IN,
+	#[allow(non_camel_case_types)]
+	DJ_TURN,
+	#[allow(non_camel_case_types)]
+	CREATE_POLL,
+	#[allow(non_camel_case_types)]
+	VOTE_POLL,
+	#[allow(non_camel_case_types)]
+	POLL_UPDATE,
+	#[allow(non_camel_case_types)]
+	POLL_RESULT,
+	#[allow(non_camel_case_types)]
+	SEND_REACTION,
+	#[allow(non_camel_case_types)]
+	SET_REACTIONS,
+	#[allow(non_camel_case_types)]
+	SET_FAMILY_FILTER,
+	#[allow(clippy::upper_case_acronyms)]
+	MAINTENANCE,
+	#[allow(non_camel_case_types)]
+	NEW_RELEASE,
+	#[allow(non_camel_case_types)]
+	GOAL_COMPLETED,
+	#[allow(non_camel_case_types)]
+	IMPORT_PROGRESS,
+	#[allow(non_camel_case_types)]
+	PLAYLIST_RELEASED,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
@@ -64,23 +187,6 @@ pub enum MusicState {
 	EMPTY,
 }
 
-pub fn allowed_origins(origin: &HeaderValue, _request: &Parts) -> bool {
-	let mut origins = Vec::new();
-	let ips = [
-		"localhost",
-		"127.0.0.1",
-		&server_ip(),
-	];
-
-	for port in 5173..5175 {
-		for ip in ips {
-			let origin = format!("http://{}:{}", ip, port);
-			origins.push(origin);
-		}
-	}
-	origins.iter().any(|allowed| *origin == *allowed)
-}
-
 // Structure for WebSocket
 
 // Request structure
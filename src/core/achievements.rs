@@ -0,0 +1,206 @@
+use crate::config::OpCode;
+use crate::core::event_bus::{Event, EventBus};
+use crate::core::user_pool::UserPool;
+use crate::i18n::Locale;
+use crate::lobic_db::db::DatabasePool;
+use crate::lobic_db::models::{Notification, UserAchievement};
+use crate::routes::notify::notify;
+use crate::schema::{play_rollups_daily, play_rollups_monthly, track_genres, user_achievements};
+
+use chrono::{DateTime, Duration, Local, NaiveDate, Timelike, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashSet;
+use tokio::sync::broadcast::error::RecvError;
+
+#[derive(Debug, Serialize)]
+pub struct Achievement {
+	pub id: &'static str,
+	pub name: &'static str,
+	pub description: &'static str,
+	pub target: i32,
+}
+
+pub const NIGHT_OWL: &str = "night_owl";
+pub const GENRE_EXPLORER: &str = "genre_explorer";
+pub const DAY_STREAK: &str = "100_day_streak";
+
+// Plays between midnight and this hour count as night plays
+const NIGHT_ENDS_AT: u32 = 5;
+
+pub const ACHIEVEMENTS: [Achievement; 3] = [
+	Achievement {
+		id: NIGHT_OWL,
+		name: "Night Owl",
+		description: "Play 50 songs between midnight and 5 AM",
+		target: 50,
+	},
+	Achievement {
+		id: GENRE_EXPLORER,
+		name: "Genre Explorer",
+		description: "Listen to songs from 10 different genres",
+		target: 10,
+	},
+	Achievement {
+		id: DAY_STREAK,
+		name: "100-day streak",
+		description: "Listen to music 100 days in a row",
+		target: 100,
+	},
+];
+
+pub fn achievement(id: &str) -> Option<&'static Achievement> {
+	ACHIEVEMENTS.iter().find(|achievement| achievement.id == id)
+}
+
+// Keeps the progress up to date from the events on the bus
+pub fn start(event_bus: &EventBus, db_pool: DatabasePool, user_pool: UserPool) {
+	let mut receiver = event_bus.subscribe();
+	tokio::spawn(async move {
+		loop {
+			let event = match receiver.recv().await {
+				Ok(event) => event,
+				Err(RecvError::Lagged(missed)) => {
+					println!("Achievements fell behind, {missed} events were skipped");
+					continue;
+				}
+				Err(RecvError::Closed) => return,
+			};
+
+			let db_pool = db_pool.clone();
+			let user_pool = user_pool.clone();
+			let result = tokio::task::spawn_blocking(move || handle_event(event, &db_pool, &user_pool)).await;
+			if let Ok(Err(err)) = result {
+				println!("Failed to update achievements: {err}");
+			}
+		}
+	});
+}
+
+fn handle_event(event: Event, db_pool: &DatabasePool, user_pool: &UserPool) -> Result<(), String> {
+	match event {
+		Event::SongPlayed {
+			user_id,
+			played_date_time,
+		} => {
+			let mut db_conn = db_pool
+				.get()
+				.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+
+			let played = DateTime::parse_from_rfc3339(&played_date_time)
+				.map_err(|err| format!("Invalid play date time {played_date_time}: {err}"))?
+				.with_timezone(&Local);
+
+			let night_owl =
+				current_progress(&user_id, NIGHT_OWL, &mut db_conn) + (played.hour() < NIGHT_ENDS_AT) as i32;
+			let genres = genre_count(&user_id, &mut db_conn).map_err(|err| err.to_string())?;
+			let streak = day_streak(&user_id, played.date_naive(), &mut db_conn).map_err(|err| err.to_string())?;
+
+			for (id, progress) in [(NIGHT_OWL, night_owl), (GENRE_EXPLORER, genres), (DAY_STREAK, streak)] {
+				let unlocked = record_progress(&user_id, id, progress, &mut db_conn).map_err(|err| err.to_string())?;
+				if unlocked {
+					let achievement = achievement(id).unwrap();
+					let locale = Locale::for_user(&user_id, &mut db_conn).unwrap_or(Locale::DEFAULT);
+					let notif = Notification::new(
+						OpCode::ACHIEVEMENT_UNLOCKED,
+						json!({
+							"achievement_id": achievement.id,
+							"name": achievement.name,
+							"message": locale.tf("notification.achievement_unlocked", &[("name", achievement.name)]),
+						}),
+					);
+					notify(&user_id, notif, db_pool, user_pool);
+				}
+			}
+			Ok(())
+		}
+	}
+}
+
+fn current_progress(user_id: &str, achievement_id: &str, db_conn: &mut SqliteConnection) -> i32 {
+	user_achievements::table
+		.filter(user_achievements::user_id.eq(user_id))
+		.filter(user_achievements::achievement_id.eq(achievement_id))
+		.select(user_achievements::progress)
+		.first::<i32>(db_conn)
+		.unwrap_or(0)
+}
+
+fn genre_count(user_id: &str, db_conn: &mut SqliteConnection) -> QueryResult<i32> {
+	// Every genre of the track counts, not only the primary one
+	let genres = play_rollups_monthly::table
+		.filter(play_rollups_monthly::user_id.eq(user_id))
+		.inner_join(track_genres::table.on(track_genres::music_id.eq(play_rollups_monthly::music_id)))
+		.select(track_genres::genre)
+		.distinct()
+		.load::<String>(db_conn)?;
+	Ok(genres.len() as i32)
+}
+
+// Days in a row ending on the given day with at least one play
+fn day_streak(user_id: &str, day: NaiveDate, db_conn: &mut SqliteConnection) -> QueryResult<i32> {
+	let target = achievement(DAY_STREAK).unwrap().target;
+	let since = (day - Duration::days(target as i64)).to_string();
+	let played: HashSet<NaiveDate> = play_rollups_daily::table
+		.filter(play_rollups_daily::user_id.eq(user_id))
+		.filter(play_rollups_daily::day.ge(since))
+		.select(play_rollups_daily::day)
+		.load::<String>(db_conn)?
+		.iter()
+		.filter_map(|day| day.parse().ok())
+		.collect();
+
+	let mut streak = 0;
+	let mut curr_day = day;
+	while streak < target && played.contains(&curr_day) {
+		streak += 1;
+		curr_day = match curr_day.pred_opt() {
+			Some(prev) => prev,
+			None => break,
+		};
+	}
+	Ok(streak)
+}
+
+// Saves the progress, returns true if the achievement just got unlocked
+fn record_progress(
+	user_id: &str,
+	achievement_id: &str,
+	progress: i32,
+	db_conn: &mut SqliteConnection,
+) -> QueryResult<bool> {
+	let target = achievement(achievement_id)
+		.map(|achievement| achievement.target)
+		.unwrap_or(i32::MAX);
+	let prev = user_achievements::table
+		.filter(user_achievements::user_id.eq(user_id))
+		.filter(user_achievements::achievement_id.eq(achievement_id))
+		.first::<UserAchievement>(db_conn)
+		.optional()?;
+
+	// Unlocked achievements stay unlocked, even when a streak breaks
+	let prev_unlocked = prev.as_ref().and_then(|prev| prev.unlocked_date_time.clone());
+	let just_unlocked = prev_unlocked.is_none() && progress >= target;
+	let entry = UserAchievement {
+		user_id: user_id.to_string(),
+		achievement_id: achievement_id.to_string(),
+		progress: progress.min(target),
+		unlocked_date_time: match just_unlocked {
+			true => Some(Utc::now().to_rfc3339()),
+			false => prev_unlocked,
+		},
+	};
+
+	diesel::insert_into(user_achievements::table)
+		.values(&entry)
+		.on_conflict((user_achievements::user_id, user_achievements::achievement_id))
+		.do_update()
+		.set((
+			user_achievements::progress.eq(entry.progress),
+			user_achievements::unlocked_date_time.eq(&entry.unlocked_date_time),
+		))
+		.execute(db_conn)?;
+
+	Ok(just_unlocked)
+}
@@ -0,0 +1,227 @@
+use crate::core::app_state::AppState;
+use crate::lobic_db::models::{AnalyticsDay, RetentionEntry, TopContentEntry};
+use crate::schema::{analytics_daily, analytics_retention, analytics_top_content, first_listens, music, play_events};
+
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, NaiveDate, Utc};
+use diesel::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// Days of the daily summary rebuilt on every run, older days are left as they are
+pub const DAILY_WINDOW: i64 = 31;
+pub const RETENTION_WEEKS: i64 = 12;
+pub const TOP_CONTENT_DAYS: i64 = 30;
+pub const TOP_CONTENT_LIMIT: usize = 10;
+
+// Analytics days and weeks are UTC
+fn day_of(date_time: &str) -> Option<NaiveDate> {
+	DateTime::parse_from_rfc3339(date_time)
+		.ok()
+		.map(|date_time| date_time.with_timezone(&Utc).date_naive())
+}
+
+fn week_of(day: NaiveDate) -> NaiveDate {
+	day - ChronoDuration::days(day.weekday().num_days_from_monday() as i64)
+}
+
+// Rebuilds the summary tables from the play events
+pub fn aggregate(app_state: &AppState) -> Result<(), String> {
+	let mut db_conn = app_state
+		.db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+
+	let now = Utc::now();
+	let today = now.date_naive();
+
+	// Enough history for the trailing month of the oldest rebuilt day and for every cohort
+	let history_days = (DAILY_WINDOW + 30).max(RETENTION_WEEKS * 7 + 7);
+	let since = (today - ChronoDuration::days(history_days)).to_string();
+	let events = play_events::table
+		.filter(play_events::played_date_time.ge(since))
+		.select((
+			play_events::user_id,
+			play_events::music_id,
+			play_events::played_date_time,
+			play_events::listened_secs,
+		))
+		.load::<(String, String, String, i64)>(&mut db_conn)
+		.map_err(|err| format!("Failed to load play events: {err}"))?;
+
+	let events: Vec<(String, String, NaiveDate, i64)> = events
+		.into_iter()
+		.filter_map(|(user_id, music_id, played, secs)| Some((user_id, music_id, day_of(&played)?, secs)))
+		.collect();
+
+	let daily = daily_summary(&events, today, &now.to_rfc3339());
+	let retention = retention(&events, today, &mut db_conn).map_err(|err| err.to_string())?;
+	let top_content = top_content(&events, today, &mut db_conn).map_err(|err| err.to_string())?;
+
+	let oldest_day = (today - ChronoDuration::days(DAILY_WINDOW - 1)).to_string();
+	db_conn
+		.transaction::<_, diesel::result::Error, _>(|conn| {
+			diesel::delete(analytics_daily::table.filter(analytics_daily::day.ge(&oldest_day))).execute(conn)?;
+			diesel::insert_into(analytics_daily::table)
+				.values(&daily)
+				.execute(conn)?;
+
+			diesel::delete(analytics_retention::table).execute(conn)?;
+			diesel::insert_into(analytics_retention::table)
+				.values(&retention)
+				.execute(conn)?;
+
+			diesel::delete(analytics_top_content::table).execute(conn)?;
+			diesel::insert_into(analytics_top_content::table)
+				.values(&top_content)
+				.execute(conn)?;
+			Ok(())
+		})
+		.map_err(|err| format!("Failed to save analytics: {err}"))
+}
+
+fn daily_summary(events: &[(String, String, NaiveDate, i64)], today: NaiveDate, refreshed: &str) -> Vec<AnalyticsDay> {
+	let mut users_by_day: HashMap<NaiveDate, HashSet<&String>> = HashMap::new();
+	let mut plays_by_day: HashMap<NaiveDate, (i32, i64)> = HashMap::new();
+	for (user_id, _, day, secs) in events {
+		users_by_day.entry(*day).or_default().insert(user_id);
+		let plays = plays_by_day.entry(*day).or_default();
+		plays.0 += 1;
+		plays.1 += secs;
+	}
+
+	// Distinct users over the days leading up to and including the day
+	let active_over = |day: NaiveDate, days: i64| -> i32 {
+		let mut users: HashSet<&String> = HashSet::new();
+		for offset in 0..days {
+			if let Some(day_users) = users_by_day.get(&(day - ChronoDuration::days(offset))) {
+				users.extend(day_users);
+			}
+		}
+		users.len() as i32
+	};
+
+	(0..DAILY_WINDOW)
+		.map(|offset| {
+			let day = today - ChronoDuration::days(offset);
+			let (plays, secs) = plays_by_day.get(&day).copied().unwrap_or_default();
+			AnalyticsDay {
+				day: day.to_string(),
+				active_users: active_over(day, 1),
+				weekly_active_users: active_over(day, 7),
+				monthly_active_users: active_over(day, 30),
+				plays,
+				minutes_listened: secs / 60,
+				refreshed_date_time: refreshed.to_string(),
+			}
+		})
+		.collect()
+}
+
+fn retention(
+	events: &[(String, String, NaiveDate, i64)],
+	today: NaiveDate,
+	db_conn: &mut SqliteConnection,
+) -> QueryResult<Vec<RetentionEntry>> {
+	let this_week = week_of(today);
+	let first_week = this_week - ChronoDuration::weeks(RETENTION_WEEKS - 1);
+
+	// The cohort of a user is the week of their first play ever, the first listens outlive the pruned play events
+	let first_plays = first_listens::table
+		.filter(first_listens::target_type.eq("track"))
+		.group_by(first_listens::user_id)
+		.select((
+			first_listens::user_id,
+			diesel::dsl::min(first_listens::first_played_date_time),
+		))
+		.load::<(String, Option<String>)>(db_conn)?;
+
+	let mut cohorts: HashMap<NaiveDate, Vec<String>> = HashMap::new();
+	for (user_id, first_play) in first_plays {
+		if let Some(week) = first_play.as_deref().and_then(day_of).map(week_of) {
+			if week >= first_week {
+				cohorts.entry(week).or_default().push(user_id);
+			}
+		}
+	}
+
+	let mut active_weeks: HashMap<&String, HashSet<NaiveDate>> = HashMap::new();
+	for (user_id, _, day, _) in events {
+		active_weeks.entry(user_id).or_default().insert(week_of(*day));
+	}
+
+	let mut entries = Vec::new();
+	for (cohort_week, users) in cohorts {
+		let weeks_since = (this_week - cohort_week).num_weeks();
+		for week_offset in 0..=weeks_since {
+			let week = cohort_week + ChronoDuration::weeks(week_offset);
+			let retained_users = users
+				.iter()
+				.filter(|user_id| active_weeks.get(user_id).is_some_and(|weeks| weeks.contains(&week)))
+				.count();
+			entries.push(RetentionEntry {
+				cohort_week: cohort_week.to_string(),
+				week_offset: week_offset as i32,
+				cohort_size: users.len() as i32,
+				retained_users: retained_users as i32,
+			});
+		}
+	}
+	Ok(entries)
+}
+
+fn top_content(
+	events: &[(String, String, NaiveDate, i64)],
+	today: NaiveDate,
+	db_conn: &mut SqliteConnection,
+) -> QueryResult<Vec<TopContentEntry>> {
+	let since = today - ChronoDuration::days(TOP_CONTENT_DAYS - 1);
+
+	let mut track_plays: HashMap<&String, i32> = HashMap::new();
+	for (_, music_id, day, _) in events {
+		if *day >= since {
+			*track_plays.entry(music_id).or_insert(0) += 1;
+		}
+	}
+
+	let music_ids: Vec<&String> = track_plays.keys().copied().collect();
+	let artists: HashMap<String, String> = music::table
+		.filter(music::music_id.eq_any(music_ids))
+		.select((music::music_id, music::artist))
+		.load::<(String, String)>(db_conn)?
+		.into_iter()
+		.collect();
+
+	let mut artist_plays: HashMap<String, i32> = HashMap::new();
+	for (music_id, plays) in &track_plays {
+		if let Some(artist) = artists.get(*music_id) {
+			*artist_plays.entry(artist.clone()).or_insert(0) += plays;
+		}
+	}
+
+	let ranked = |kind: &str, counts: Vec<(String, i32)>| -> Vec<TopContentEntry> {
+		let mut counts = counts;
+		counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+		counts
+			.into_iter()
+			.take(TOP_CONTENT_LIMIT)
+			.enumerate()
+			.map(|(index, (target_id, plays))| TopContentEntry {
+				kind: kind.to_string(),
+				rank: index as i32 + 1,
+				target_id,
+				plays,
+			})
+			.collect()
+	};
+
+	let mut entries = ranked(
+		"track",
+		track_plays
+			.into_iter()
+			.map(|(music_id, plays)| (music_id.clone(), plays))
+			.collect(),
+	);
+	entries.extend(ranked("artist", artist_plays.into_iter().collect()));
+	Ok(entries)
+}
@@ -1,20 +1,41 @@
+use crate::core::event_bus::EventBus;
+use crate::core::federation::SharedLobbies;
+use crate::core::imports::ImportPool;
 use crate::core::lobby::LobbyPool;
+use crate::core::now_playing::NowPlayingPool;
+use crate::core::recommendations::{self, Recommender};
 use crate::core::user_pool::UserPool;
 use crate::lobic_db::db::*;
 
+use std::sync::Arc;
+
 #[derive(Debug, Clone)]
 pub struct AppState {
 	pub db_pool: DatabasePool,
 	pub lobby_pool: LobbyPool,
 	pub user_pool: UserPool,
+	pub now_playing_pool: NowPlayingPool,
+	pub event_bus: EventBus,
+	pub import_pool: ImportPool,
+	pub shared_lobbies: SharedLobbies,     // our users in lobbies hosted on a peer
+	pub recommender: Arc<dyn Recommender>, // picked by RECOMMENDER at startup
 }
 
 impl AppState {
 	pub fn new() -> AppState {
+		AppState::with_db_pool(generate_db_pool())
+	}
+
+	pub fn with_db_pool(db_pool: DatabasePool) -> AppState {
 		AppState {
-			db_pool: generate_db_pool(),
+			db_pool,
 			lobby_pool: LobbyPool::new(),
 			user_pool: UserPool::new(),
+			now_playing_pool: NowPlayingPool::new(),
+			event_bus: EventBus::new(),
+			import_pool: ImportPool::new(),
+			shared_lobbies: SharedLobbies::new(),
+			recommender: recommendations::from_env(),
 		}
 	}
 }
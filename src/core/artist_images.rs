@@ -0,0 +1,335 @@
+use crate::config::{ARTIST_IMG_STORAGE, MAX_ARTIST_IMAGE_BYTES};
+use crate::core::artwork;
+use crate::lobic_db::db::DatabasePool;
+use crate::lobic_db::models::ArtistImage;
+use crate::schema::{artist_images, music};
+
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub const PROVIDER: &str = "deezer";
+const DEFAULT_PROVIDER_URL: &str = "https://api.deezer.com"; // ARTIST_IMAGE_PROVIDER_URL points elsewhere
+const DEFAULT_IMAGE_HOST: &str = "dzcdn.net"; // where it keeps the pictures, under subdomains of it
+
+// Only the credits, splitting on "&" or "," would break up the names that have them
+const FEATURING: [&str; 5] = [" feat. ", " feat ", " ft. ", " ft ", " featuring "];
+
+#[derive(Debug, Default, Serialize)]
+pub struct FetchReport {
+	pub fetched: Vec<String>,
+	pub not_found: Vec<String>, // the provider knows no artist by the name
+	pub failed: Vec<(String, String)>,
+	pub remaining: usize, // still without an image, for the next request
+}
+
+fn http_client() -> &'static reqwest::Client {
+	static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+	CLIENT.get_or_init(|| {
+		reqwest::Client::builder()
+			.timeout(Duration::from_secs(10))
+			.redirect(reqwest::redirect::Policy::none())
+			.build()
+			.expect("Failed to build the artist image http client")
+	})
+}
+
+// The artist a track is credited to first, lowercased. The artist images are kept under it.
+pub fn main_artist(artist: &str) -> String {
+	let artist = artist.trim().to_lowercase();
+	let end = FEATURING
+		.iter()
+		.filter_map(|credit| artist.find(credit))
+		.min()
+		.unwrap_or(artist.len());
+	artist[..end].trim().to_string()
+}
+
+pub fn image_file(img_uuid: &str) -> PathBuf {
+	PathBuf::from(ARTIST_IMG_STORAGE).join(format!("{img_uuid}.png"))
+}
+
+// The biggest picture of the artist with the name in the search results, none rather than someone else's
+fn pick_picture(results: &Value, artist: &str) -> Option<String> {
+	results["data"]
+		.as_array()?
+		.iter()
+		.find(|found| found["name"].as_str().map(main_artist).as_deref() == Some(artist))
+		.and_then(|found| {
+			["picture_xl", "picture_big"]
+				.iter()
+				.filter_map(|size| found[*size].as_str())
+				.find(|url| !url.is_empty())
+		})
+		.map(str::to_string)
+}
+
+// Pictures only come from the image host of the provider, a provider set with ARTIST_IMAGE_PROVIDER_URL serves
+// them itself
+fn from_image_host(picture_url: &str, provider_url: &str) -> bool {
+	let (Ok(picture), Ok(provider)) = (reqwest::Url::parse(picture_url), reqwest::Url::parse(provider_url)) else {
+		return false;
+	};
+	let Some(host) = picture.host_str() else {
+		return false;
+	};
+	match provider_url == DEFAULT_PROVIDER_URL {
+		true => {
+			picture.scheme() == "https"
+				&& (host == DEFAULT_IMAGE_HOST || host.ends_with(&format!(".{DEFAULT_IMAGE_HOST}")))
+		}
+		false => {
+			picture.scheme() == provider.scheme()
+				&& picture.host_str() == provider.host_str()
+				&& picture.port_or_known_default() == provider.port_or_known_default()
+		}
+	}
+}
+
+// The main artists of the library without an image yet
+pub fn missing(db_pool: &DatabasePool) -> Result<Vec<String>, String> {
+	let mut db_conn = db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+	let artists = music::table
+		.select(music::artist)
+		.distinct()
+		.load::<String>(&mut db_conn)
+		.map_err(|err| err.to_string())?;
+	let known = artist_images::table
+		.select(artist_images::artist)
+		.load::<String>(&mut db_conn)
+		.map_err(|err| err.to_string())?;
+	Ok(artists
+		.iter()
+		.map(|artist| main_artist(artist))
+		.filter(|artist| !artist.is_empty() && !known.contains(artist))
+		.collect::<BTreeSet<String>>()
+		.into_iter()
+		.collect())
+}
+
+// Looks the artist up with the provider and keeps the picture, in place of the one fetched before
+pub async fn fetch(db_pool: &DatabasePool, artist: &str) -> Result<Option<ArtistImage>, String> {
+	let provider_url = std::env::var("ARTIST_IMAGE_PROVIDER_URL")
+		.ok()
+		.filter(|url| !url.is_empty())
+		.unwrap_or_else(|| DEFAULT_PROVIDER_URL.to_string());
+	fetch_from(db_pool, &provider_url, artist).await
+}
+
+async fn fetch_from(db_pool: &DatabasePool, provider_url: &str, artist: &str) -> Result<Option<ArtistImage>, String> {
+	let results = http_client()
+		.get(format!("{}/search/artist", provider_url.trim_end_matches('/')))
+		.query(&[("q", artist)])
+		.send()
+		.await
+		.and_then(|response| response.error_for_status())
+		.map_err(|err| format!("Failed to search the provider: {err}"))?
+		.json::<Value>()
+		.await
+		.map_err(|err| format!("Unexpected provider response: {err}"))?;
+	let Some(picture_url) = pick_picture(&results, artist) else {
+		return Ok(None);
+	};
+	if !from_image_host(&picture_url, provider_url) {
+		return Err(format!(
+			"The picture isn't on the image host of the provider: {picture_url}"
+		));
+	}
+
+	let mut response = http_client()
+		.get(&picture_url)
+		.send()
+		.await
+		.map_err(|err| format!("Failed to download the picture: {err}"))?;
+	// redirects aren't followed, they could lead anywhere
+	if !response.status().is_success() {
+		return Err(format!("Failed to download the picture: {}", response.status()));
+	}
+	let too_big = format!("The picture is larger than {MAX_ARTIST_IMAGE_BYTES} bytes");
+	if response
+		.content_length()
+		.is_some_and(|length| length > MAX_ARTIST_IMAGE_BYTES as u64)
+	{
+		return Err(too_big);
+	}
+	let mut bytes = Vec::new();
+	while let Some(chunk) = response
+		.chunk()
+		.await
+		.map_err(|err| format!("Failed to download the picture: {err}"))?
+	{
+		if bytes.len() + chunk.len() > MAX_ARTIST_IMAGE_BYTES {
+			return Err(too_big);
+		}
+		bytes.extend_from_slice(&chunk);
+	}
+	match artwork::decode(&bytes) {
+		Ok(pixels) if !pixels.is_empty() => {}
+		_ => return Err("The picture isn't a png or jpeg".to_string()),
+	}
+
+	let image = ArtistImage {
+		artist: artist.to_string(),
+		img_uuid: Uuid::new_v4().to_string(),
+		source: PROVIDER.to_string(),
+		source_url: Some(picture_url),
+		fetched_date_time: Utc::now().to_rfc3339(),
+	};
+	fs::create_dir_all(ARTIST_IMG_STORAGE)
+		.and_then(|_| fs::write(image_file(&image.img_uuid), &bytes))
+		.map_err(|err| format!("Failed to save the picture: {err}"))?;
+
+	let mut db_conn = db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+	let previous = artist_images::table
+		.find(artist)
+		.select(artist_images::img_uuid)
+		.first::<String>(&mut db_conn)
+		.optional()
+		.map_err(|err| err.to_string())?;
+	diesel::replace_into(artist_images::table)
+		.values(&image)
+		.execute(&mut db_conn)
+		.map_err(|err| err.to_string())?;
+	if let Some(previous) = previous {
+		let _ = fs::remove_file(image_file(&previous));
+	}
+	Ok(Some(image))
+}
+
+// The given artists, or the ones still without an image, up to `max` of them
+pub async fn fetch_many(
+	db_pool: &DatabasePool,
+	artists: Option<Vec<String>>,
+	max: usize,
+) -> Result<FetchReport, String> {
+	let mut artists = match artists {
+		Some(artists) => artists
+			.iter()
+			.map(|artist| main_artist(artist))
+			.filter(|artist| !artist.is_empty())
+			.collect::<BTreeSet<String>>()
+			.into_iter()
+			.collect(),
+		None => missing(db_pool)?,
+	};
+	let rest = artists.split_off(artists.len().min(max));
+
+	let mut report = FetchReport {
+		remaining: rest.len(),
+		..FetchReport::default()
+	};
+	for artist in artists {
+		match fetch(db_pool, &artist).await {
+			Ok(Some(_)) => report.fetched.push(artist),
+			Ok(None) => report.not_found.push(artist),
+			Err(err) => report.failed.push((artist, err)),
+		}
+	}
+	Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::artwork::placeholder::Placeholder;
+	use crate::test_support::TestApp;
+
+	use axum::{
+		extract::{Query, State},
+		http::{header, StatusCode},
+		response::IntoResponse,
+		routing::get,
+		Json, Router,
+	};
+	use serde_json::json;
+	use std::collections::HashMap;
+
+	async fn search(State(base): State<String>, Query(query): Query<HashMap<String, String>>) -> Json<Value> {
+		let picture = match query["q"].as_str() {
+			"elsewhere" => "http://pictures.example/elsewhere.png".to_string(),
+			name => format!("{base}/{name}"),
+		};
+		Json(json!({ "data": [{ "name": query["q"], "picture_xl": picture }] }))
+	}
+
+	#[test]
+	fn the_picture_is_of_the_main_artist_only() {
+		assert_eq!(main_artist("Joji feat. Diplo"), "joji");
+		assert_eq!(main_artist(" TV Girl ft. Jordana "), "tv girl");
+		assert_eq!(main_artist("Simon & Garfunkel"), "simon & garfunkel");
+		assert_eq!(
+			main_artist("Tyler, The Creator featuring Kali Uchis"),
+			"tyler, the creator"
+		);
+
+		let results = json!({ "data": [
+			{ "name": "Joji Tribute Band", "picture_xl": "https://pictures.example/tribute.jpg" },
+			{ "name": "JOJI", "picture_big": "https://pictures.example/joji.jpg", "picture_xl": "" },
+		] });
+		assert_eq!(
+			pick_picture(&results, "joji"),
+			Some("https://pictures.example/joji.jpg".to_string())
+		);
+		assert_eq!(pick_picture(&results, "tv girl"), None);
+		assert_eq!(pick_picture(&json!({ "error": "quota" }), "joji"), None);
+	}
+
+	#[test]
+	fn pictures_come_from_the_image_host_only() {
+		assert!(from_image_host(
+			"https://e-cdns-images.dzcdn.net/images/artist/1/1000x1000.jpg",
+			DEFAULT_PROVIDER_URL
+		));
+		assert!(!from_image_host(
+			"http://e-cdns-images.dzcdn.net/a.jpg",
+			DEFAULT_PROVIDER_URL
+		));
+		assert!(!from_image_host(
+			"https://dzcdn.net.example/a.jpg",
+			DEFAULT_PROVIDER_URL
+		));
+		assert!(!from_image_host("http://169.254.169.254/latest", DEFAULT_PROVIDER_URL));
+		assert!(from_image_host("http://127.0.0.1:8080/a.png", "http://127.0.0.1:8080"));
+		assert!(!from_image_host("http://127.0.0.1:8081/a.png", "http://127.0.0.1:8080"));
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn downloads_stay_small_and_where_they_were_pointed() {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let base = format!("http://{}", listener.local_addr().unwrap());
+		let picture = Placeholder::new(1, "A".to_string()).png(32);
+		let provider = Router::new()
+			.route("/search/artist", get(search))
+			.route("/huge", get(|| async { vec![0u8; MAX_ARTIST_IMAGE_BYTES + 1] }))
+			.route(
+				"/moved",
+				get(|| async { (StatusCode::FOUND, [(header::LOCATION, "/picture")]).into_response() }),
+			)
+			.route("/picture", get(move || async move { picture }))
+			.with_state(base.clone());
+		tokio::spawn(async move { axum::serve(listener, provider).await });
+
+		let test_app = TestApp::new();
+		let db_pool = &test_app.app_state.db_pool;
+		for (artist, reason) in [("elsewhere", "image host"), ("huge", "larger than"), ("moved", "302")] {
+			let err = fetch_from(db_pool, &base, artist).await.unwrap_err();
+			assert!(err.contains(reason), "{artist}: {err}");
+		}
+		let stored = artist_images::table
+			.count()
+			.get_result::<i64>(&mut test_app.db_conn())
+			.unwrap();
+		assert_eq!(stored, 0);
+	}
+}
@@ -0,0 +1,103 @@
+use crate::config::MAX_ANIMATED_COVER_SECS;
+
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+// Uploads are re-encoded by ffmpeg into a small muted h264 mp4 that every client can loop, along with a
+// jpeg of the first frame for clients that can't or don't want to play it.
+
+// Longest side of the transcoded video
+const MAX_DIMENSION: u32 = 720;
+
+pub fn ffmpeg() -> String {
+	std::env::var("FFMPEG_PATH").unwrap_or_else(|_| "ffmpeg".to_string())
+}
+
+fn ffprobe() -> String {
+	std::env::var("FFPROBE_PATH").unwrap_or_else(|_| "ffprobe".to_string())
+}
+
+// Checked once, installing ffmpeg needs a restart to be picked up
+pub fn available() -> bool {
+	static AVAILABLE: OnceLock<bool> = OnceLock::new();
+	*AVAILABLE.get_or_init(|| {
+		Command::new(ffmpeg())
+			.arg("-version")
+			.output()
+			.is_ok_and(|output| output.status.success())
+	})
+}
+
+// Tells the container by its magic bytes, the extension of the upload can't be trusted
+pub fn detect_format(bytes: &[u8]) -> Option<&'static str> {
+	if bytes.get(4..8) == Some(b"ftyp") {
+		match bytes.get(8..10) {
+			Some(b"qt") => Some("mov"),
+			_ => Some("mp4"),
+		}
+	} else if bytes.starts_with(&[0x1a, 0x45, 0xdf, 0xa3]) {
+		Some("webm")
+	} else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+		Some("gif")
+	} else {
+		None
+	}
+}
+
+// Length of the upload in seconds, gifs don't always carry one
+pub fn probe_duration(input: &Path) -> Result<Option<f64>, String> {
+	let output = Command::new(ffprobe())
+		.args(["-v", "error", "-show_entries", "format=duration", "-of", "csv=p=0"])
+		.arg(input)
+		.output()
+		.map_err(|err| format!("Failed to run ffprobe: {err}"))?;
+	if !output.status.success() {
+		return Err("Not a playable video".to_string());
+	}
+	Ok(String::from_utf8_lossy(&output.stdout).trim().parse::<f64>().ok())
+}
+
+pub fn transcode(input: &Path, video: &Path, poster: &Path) -> Result<(), String> {
+	let scale = format!(
+		"scale=w='min({MAX_DIMENSION},iw)':h='min({MAX_DIMENSION},ih)':force_original_aspect_ratio=decrease:force_divisible_by=2,fps=30"
+	);
+	run(Command::new(ffmpeg())
+		.args(["-y", "-v", "error", "-i"])
+		.arg(input)
+		.args(["-t", &MAX_ANIMATED_COVER_SECS.to_string(), "-an", "-vf", &scale])
+		.args([
+			"-c:v", "libx264", "-preset", "veryfast", "-crf", "28", "-pix_fmt", "yuv420p",
+		])
+		.args(["-movflags", "+faststart", "-f", "mp4"])
+		.arg(video))?;
+
+	run(Command::new(ffmpeg())
+		.args(["-y", "-v", "error", "-i"])
+		.arg(video)
+		.args(["-frames:v", "1", "-q:v", "3", "-f", "image2"])
+		.arg(poster))
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+	let output = command.output().map_err(|err| format!("Failed to run ffmpeg: {err}"))?;
+	if !output.status.success() {
+		let stderr = String::from_utf8_lossy(&output.stderr);
+		return Err(format!("Failed to transcode the video: {}", stderr.trim()));
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn formats_are_told_by_their_magic_bytes() {
+		assert_eq!(detect_format(b"\0\0\0\x18ftypisom\0\0"), Some("mp4"));
+		assert_eq!(detect_format(b"\0\0\0\x14ftypqt  \0\0"), Some("mov"));
+		assert_eq!(detect_format(&[0x1a, 0x45, 0xdf, 0xa3, 0x9f]), Some("webm"));
+		assert_eq!(detect_format(b"GIF89a\x01\x00"), Some("gif"));
+		assert_eq!(detect_format(&crate::core::artwork::png::SIGNATURE), None);
+	}
+}
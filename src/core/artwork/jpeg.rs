@@ -0,0 +1,438 @@
+use super::{Rgb, MAX_DIMENSION};
+
+// Only the DC coefficients are decoded, which gives the average color of every 8x8 block. That is an
+// image at 1/8 of the size, plenty for a palette and a lot cheaper than a full decode.
+
+pub const SIGNATURE: [u8; 3] = [0xff, 0xd8, 0xff];
+
+#[derive(Clone, Default)]
+struct HuffmanTable {
+	values: Vec<u8>,
+	min_code: [i32; 17],
+	max_code: [i32; 17],
+	value_ptr: [i32; 17],
+}
+
+impl HuffmanTable {
+	fn new(counts: &[u8], values: &[u8]) -> HuffmanTable {
+		let mut table = HuffmanTable {
+			values: values.to_vec(),
+			..Default::default()
+		};
+		let (mut code, mut k) = (0i32, 0i32);
+		for length in 1..=16 {
+			let count = counts[length - 1] as i32;
+			table.value_ptr[length] = k;
+			table.min_code[length] = code;
+			code += count;
+			k += count;
+			table.max_code[length] = if count > 0 { code - 1 } else { -1 };
+			code <<= 1;
+		}
+		table
+	}
+}
+
+struct Component {
+	id: u8,
+	h: usize,
+	v: usize,
+	quant_table: usize,
+	dc_table: usize,
+	ac_table: usize,
+	blocks_per_line: usize,
+	dc: Vec<i32>,
+}
+
+struct Frame {
+	width: usize,
+	height: usize,
+	progressive: bool,
+	components: Vec<Component>,
+	mcus_per_line: usize,
+	mcus_per_column: usize,
+	h_max: usize,
+	v_max: usize,
+}
+
+struct BitReader<'a> {
+	data: &'a [u8],
+	pos: usize,
+	bits: u32,
+	bit_count: u32,
+}
+
+impl BitReader<'_> {
+	fn bit(&mut self) -> u32 {
+		if self.bit_count == 0 {
+			// Markers stop the stream, the missing bits read as zeros
+			let byte = match (self.data.get(self.pos), self.data.get(self.pos + 1)) {
+				(Some(0xff), Some(0x00)) => {
+					self.pos += 2;
+					0xff
+				}
+				(Some(0xff), _) | (None, _) => 0,
+				(Some(byte), _) => {
+					self.pos += 1;
+					*byte
+				}
+			};
+			self.bits = byte as u32;
+			self.bit_count = 8;
+		}
+		self.bit_count -= 1;
+		(self.bits >> self.bit_count) & 1
+	}
+
+	fn receive(&mut self, length: u32) -> i32 {
+		(0..length).fold(0, |value, _| (value << 1) | self.bit() as i32)
+	}
+
+	// Differences are at most 16 bits long, longer ones would overflow the shifts
+	fn receive_extend(&mut self, length: u32) -> Result<i32, String> {
+		if length == 0 {
+			return Ok(0);
+		}
+		if length > 16 {
+			return Err(format!("Invalid jpeg coefficient length: {length}"));
+		}
+		let value = self.receive(length);
+		if value < 1 << (length - 1) {
+			Ok(value - (1 << length) + 1)
+		} else {
+			Ok(value)
+		}
+	}
+
+	fn decode(&mut self, table: &HuffmanTable) -> Result<u8, String> {
+		let mut code = self.bit() as i32;
+		for length in 1..=16 {
+			if code <= table.max_code[length] {
+				let index = table.value_ptr[length] + code - table.min_code[length];
+				return table
+					.values
+					.get(index as usize)
+					.copied()
+					.ok_or_else(|| "Invalid jpeg huffman code".to_string());
+			}
+			code = (code << 1) | self.bit() as i32;
+		}
+		Err("Invalid jpeg huffman code".to_string())
+	}
+
+	// Restart markers reset the bit buffer
+	fn restart(&mut self) {
+		self.bit_count = 0;
+		if self.data.get(self.pos) == Some(&0xff) && matches!(self.data.get(self.pos + 1), Some(0xd0..=0xd7)) {
+			self.pos += 2;
+		}
+	}
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<Rgb>, String> {
+	if !bytes.starts_with(&SIGNATURE) {
+		return Err("Not a jpeg".to_string());
+	}
+
+	let mut quant_tables = [[1u16; 64]; 4];
+	let mut dc_tables = vec![HuffmanTable::default(); 4];
+	let mut ac_tables = vec![HuffmanTable::default(); 4];
+	let mut restart_interval = 0;
+	let mut frame: Option<Frame> = None;
+
+	let mut pos = 2;
+	loop {
+		// Fill bytes may come before a marker
+		while bytes.get(pos) == Some(&0xff) && bytes.get(pos + 1) == Some(&0xff) {
+			pos += 1;
+		}
+		let marker = match (bytes.get(pos), bytes.get(pos + 1)) {
+			(Some(0xff), Some(marker)) => *marker,
+			_ => break,
+		};
+		pos += 2;
+		if marker == 0xd9 {
+			break;
+		}
+
+		let length = match bytes.get(pos..pos + 2) {
+			Some(length) => u16::from_be_bytes([length[0], length[1]]) as usize,
+			None => break,
+		};
+		let segment = bytes
+			.get(pos + 2..pos + length)
+			.ok_or_else(|| "Truncated jpeg segment".to_string())?;
+		pos += length;
+
+		match marker {
+			0xdb => read_quant_tables(segment, &mut quant_tables)?,
+			0xc4 => read_huffman_tables(segment, &mut dc_tables, &mut ac_tables)?,
+			0xdd if segment.len() >= 2 => restart_interval = u16::from_be_bytes([segment[0], segment[1]]) as usize,
+			0xc0..=0xc2 => frame = Some(read_frame(segment, marker == 0xc2)?),
+			0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => {
+				return Err("Unsupported jpeg encoding".to_string());
+			}
+			0xda => {
+				let frame = frame.as_mut().ok_or_else(|| "Jpeg scan before the frame".to_string())?;
+				let scan_length = entropy_length(&bytes[pos..]);
+				decode_scan(
+					frame,
+					segment,
+					&bytes[pos..pos + scan_length],
+					&dc_tables,
+					&ac_tables,
+					restart_interval,
+				)?;
+				pos += scan_length;
+			}
+			_ => {}
+		}
+	}
+
+	let frame = frame.ok_or_else(|| "Jpeg without a frame".to_string())?;
+	Ok(to_pixels(&frame, &quant_tables))
+}
+
+fn read_quant_tables(mut segment: &[u8], quant_tables: &mut [[u16; 64]; 4]) -> Result<(), String> {
+	while let Some(&info) = segment.first() {
+		let (precision, id) = (info >> 4, (info & 0x0f) as usize & 3);
+		let size = if precision == 0 { 64 } else { 128 };
+		let values = segment
+			.get(1..1 + size)
+			.ok_or_else(|| "Truncated jpeg quantization table".to_string())?;
+		for (i, value) in quant_tables[id].iter_mut().enumerate() {
+			*value = if precision == 0 {
+				values[i] as u16
+			} else {
+				u16::from_be_bytes([values[i * 2], values[i * 2 + 1]])
+			};
+		}
+		segment = &segment[1 + size..];
+	}
+	Ok(())
+}
+
+fn read_huffman_tables(
+	mut segment: &[u8],
+	dc_tables: &mut [HuffmanTable],
+	ac_tables: &mut [HuffmanTable],
+) -> Result<(), String> {
+	while segment.len() >= 17 {
+		let (class, id) = (segment[0] >> 4, (segment[0] & 0x0f) as usize & 3);
+		let counts = &segment[1..17];
+		let total: usize = counts.iter().map(|count| *count as usize).sum();
+		let values = segment
+			.get(17..17 + total)
+			.ok_or_else(|| "Truncated jpeg huffman table".to_string())?;
+		let table = HuffmanTable::new(counts, values);
+		if class == 0 {
+			dc_tables[id] = table;
+		} else {
+			ac_tables[id] = table;
+		}
+		segment = &segment[17 + total..];
+	}
+	Ok(())
+}
+
+fn read_frame(segment: &[u8], progressive: bool) -> Result<Frame, String> {
+	if segment.len() < 6 {
+		return Err("Truncated jpeg frame".to_string());
+	}
+	let height = u16::from_be_bytes([segment[1], segment[2]]) as usize;
+	let width = u16::from_be_bytes([segment[3], segment[4]]) as usize;
+	let count = segment[5] as usize;
+	if width == 0 || height == 0 || !(count == 1 || count == 3) {
+		return Err("Unsupported jpeg color space".to_string());
+	}
+	if width > MAX_DIMENSION || height > MAX_DIMENSION {
+		return Err(format!("Unsupported jpeg size: {width}x{height}"));
+	}
+
+	let mut components = Vec::with_capacity(count);
+	for i in 0..count {
+		let info = segment
+			.get(6 + i * 3..9 + i * 3)
+			.ok_or_else(|| "Truncated jpeg frame".to_string())?;
+		components.push(Component {
+			id: info[0],
+			h: (info[1] >> 4).max(1) as usize,
+			v: (info[1] & 0x0f).max(1) as usize,
+			quant_table: (info[2] & 3) as usize,
+			dc_table: 0,
+			ac_table: 0,
+			blocks_per_line: 0,
+			dc: Vec::new(),
+		});
+	}
+
+	let h_max = components.iter().map(|component| component.h).max().unwrap();
+	let v_max = components.iter().map(|component| component.v).max().unwrap();
+	let mcus_per_line = width.div_ceil(8 * h_max);
+	let mcus_per_column = height.div_ceil(8 * v_max);
+	for component in &mut components {
+		component.blocks_per_line = mcus_per_line * component.h;
+		component.dc = vec![0; component.blocks_per_line * mcus_per_column * component.v];
+	}
+
+	Ok(Frame {
+		width,
+		height,
+		progressive,
+		components,
+		mcus_per_line,
+		mcus_per_column,
+		h_max,
+		v_max,
+	})
+}
+
+// Entropy coded data runs until the first marker that isn't a stuffed byte or a restart
+fn entropy_length(data: &[u8]) -> usize {
+	let mut pos = 0;
+	while pos + 1 < data.len() {
+		if data[pos] == 0xff && !matches!(data[pos + 1], 0x00 | 0xd0..=0xd7 | 0xff) {
+			return pos;
+		}
+		pos += 1;
+	}
+	data.len()
+}
+
+fn decode_scan(
+	frame: &mut Frame,
+	header: &[u8],
+	data: &[u8],
+	dc_tables: &[HuffmanTable],
+	ac_tables: &[HuffmanTable],
+	restart_interval: usize,
+) -> Result<(), String> {
+	let count = *header.first().ok_or_else(|| "Truncated jpeg scan".to_string())? as usize;
+	let spectral = header
+		.get(1 + count * 2..4 + count * 2)
+		.ok_or_else(|| "Truncated jpeg scan".to_string())?;
+	let (spectral_start, spectral_end, approximation) = (spectral[0], spectral[1], spectral[2]);
+
+	// Progressive scans other than the first DC pass don't change the block averages enough to matter
+	if frame.progressive && (spectral_start != 0 || approximation >> 4 != 0) {
+		return Ok(());
+	}
+	let shift = if frame.progressive { approximation & 0x0f } else { 0 };
+
+	let mut scan_components = Vec::with_capacity(count);
+	for i in 0..count {
+		let (id, tables) = (header[1 + i * 2], header[2 + i * 2]);
+		let index = frame
+			.components
+			.iter()
+			.position(|component| component.id == id)
+			.ok_or_else(|| "Jpeg scan of an unknown component".to_string())?;
+		frame.components[index].dc_table = (tables >> 4) as usize & 3;
+		frame.components[index].ac_table = (tables & 0x0f) as usize & 3;
+		scan_components.push(index);
+	}
+
+	let mut reader = BitReader {
+		data,
+		pos: 0,
+		bits: 0,
+		bit_count: 0,
+	};
+	let mut predictions = vec![0i32; frame.components.len()];
+	let decode_block =
+		|reader: &mut BitReader, frame: &mut Frame, predictions: &mut [i32], index: usize, block: usize| {
+			let component = &mut frame.components[index];
+			let length = reader.decode(&dc_tables[component.dc_table])? as u32;
+			predictions[index] = predictions[index].wrapping_add(reader.receive_extend(length)?);
+			if let Some(dc) = component.dc.get_mut(block) {
+				*dc = predictions[index] << shift;
+			}
+
+			// The AC coefficients still have to be read to get to the next block
+			let mut k = 1;
+			while k <= spectral_end as usize {
+				let symbol = reader.decode(&ac_tables[component.ac_table])?;
+				let (run, size) = ((symbol >> 4) as usize, (symbol & 0x0f) as u32);
+				if size == 0 {
+					if run != 15 {
+						break;
+					}
+					k += 16;
+					continue;
+				}
+				reader.receive(size);
+				k += run + 1;
+			}
+			Ok::<(), String>(())
+		};
+
+	// A single component scan goes block by block instead of by MCU
+	let (units_per_line, units) = if count == 1 {
+		let component = &frame.components[scan_components[0]];
+		let per_line = (frame.width * component.h).div_ceil(frame.h_max).div_ceil(8);
+		let lines = (frame.height * component.v).div_ceil(frame.v_max).div_ceil(8);
+		(per_line, per_line * lines)
+	} else {
+		(frame.mcus_per_line, frame.mcus_per_line * frame.mcus_per_column)
+	};
+
+	for unit in 0..units {
+		if restart_interval > 0 && unit > 0 && unit % restart_interval == 0 {
+			reader.restart();
+			predictions.iter_mut().for_each(|prediction| *prediction = 0);
+		}
+
+		let (unit_x, unit_y) = (unit % units_per_line, unit / units_per_line);
+		if count == 1 {
+			let index = scan_components[0];
+			let block = unit_y * frame.components[index].blocks_per_line + unit_x;
+			decode_block(&mut reader, frame, &mut predictions, index, block)?;
+			continue;
+		}
+
+		for &index in &scan_components {
+			let (h, v, per_line) = {
+				let component = &frame.components[index];
+				(component.h, component.v, component.blocks_per_line)
+			};
+			for y in 0..v {
+				for x in 0..h {
+					let block = (unit_y * v + y) * per_line + unit_x * h + x;
+					decode_block(&mut reader, frame, &mut predictions, index, block)?;
+				}
+			}
+		}
+	}
+	Ok(())
+}
+
+fn to_pixels(frame: &Frame, quant_tables: &[[u16; 64]; 4]) -> Vec<Rgb> {
+	let (width, height) = (frame.width.div_ceil(8), frame.height.div_ceil(8));
+	let sample = |component: &Component, x: usize, y: usize| -> f32 {
+		let block_x = x * component.h / frame.h_max;
+		let block_y = y * component.v / frame.v_max;
+		let dc = component.dc[block_y * component.blocks_per_line + block_x];
+		dc.saturating_mul(quant_tables[component.quant_table][0] as i32) as f32 / 8.0 + 128.0
+	};
+
+	let mut pixels = Vec::with_capacity(width * height);
+	for y in 0..height {
+		for x in 0..width {
+			let luma = sample(&frame.components[0], x, y);
+			if frame.components.len() == 1 {
+				let gray = luma.clamp(0.0, 255.0) as u8;
+				pixels.push([gray; 3]);
+				continue;
+			}
+
+			let cb = sample(&frame.components[1], x, y) - 128.0;
+			let cr = sample(&frame.components[2], x, y) - 128.0;
+			pixels.push([
+				(luma + 1.402 * cr).clamp(0.0, 255.0) as u8,
+				(luma - 0.344_136 * cb - 0.714_136 * cr).clamp(0.0, 255.0) as u8,
+				(luma + 1.772 * cb).clamp(0.0, 255.0) as u8,
+			]);
+		}
+	}
+	pixels
+}
@@ -0,0 +1,203 @@
+pub mod animated;
+pub mod jpeg;
+pub mod placeholder;
+pub mod png;
+pub mod urls;
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use uuid::Uuid;
+
+pub type Rgb = [u8; 3];
+
+// Widest or tallest image decoded, the headers of bigger ones are refused before anything is allocated
+pub const MAX_DIMENSION: usize = 8192;
+
+// Most colors a palette holds, requests for fewer get the first ones
+pub const PALETTE_SIZE: usize = 8;
+// Colors closer than this (squared rgb distance) count as the same color
+const MIN_DISTANCE: u32 = 48 * 48;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PaletteColor {
+	pub hex: String,
+	pub rgb: Rgb,
+	pub population: f32,    // share of the pixels in the color's bucket
+	pub text_color: String, // black or white, whichever reads better on top
+}
+
+impl PaletteColor {
+	fn new(rgb: Rgb, population: f32) -> PaletteColor {
+		let luminance = 0.299 * rgb[0] as f32 + 0.587 * rgb[1] as f32 + 0.114 * rgb[2] as f32;
+		PaletteColor {
+			hex: format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2]),
+			rgb,
+			population,
+			text_color: if luminance > 150.0 { "#000000" } else { "#ffffff" }.to_string(),
+		}
+	}
+}
+
+// Every track of an album shares its cover, stored and served under this uuid
+pub fn cover_uuid(artist: &str, album: &str) -> Uuid {
+	let mut hasher = DefaultHasher::new();
+	artist.hash(&mut hasher);
+	album.hash(&mut hasher);
+	let hash = hasher.finish();
+	Uuid::from_u64_pair(hash, hash)
+}
+
+// Pixels of a png or jpeg, the format is told by the content since covers are all saved as .png
+pub fn decode(bytes: &[u8]) -> Result<Vec<Rgb>, String> {
+	if bytes.starts_with(&png::SIGNATURE) {
+		png::decode(bytes)
+	} else if bytes.starts_with(&jpeg::SIGNATURE) {
+		jpeg::decode(bytes)
+	} else {
+		Err("Unsupported image format".to_string())
+	}
+}
+
+// Pixels are bucketed by their 4 high bits per channel, the fullest buckets that are far enough apart
+// make the palette
+pub fn extract_palette(pixels: &[Rgb]) -> Vec<PaletteColor> {
+	if pixels.is_empty() {
+		return Vec::new();
+	}
+
+	let mut buckets = vec![([0u64; 3], 0u64); 16 * 16 * 16];
+	for pixel in pixels {
+		let index = (pixel[0] as usize >> 4) << 8 | (pixel[1] as usize >> 4) << 4 | pixel[2] as usize >> 4;
+		let (sum, count) = &mut buckets[index];
+		for channel in 0..3 {
+			sum[channel] += pixel[channel] as u64;
+		}
+		*count += 1;
+	}
+
+	let mut buckets: Vec<(Rgb, u64)> = buckets
+		.into_iter()
+		.filter(|(_, count)| *count > 0)
+		.map(|(sum, count)| (sum.map(|channel| (channel / count) as u8), count))
+		.collect();
+	buckets.sort_by_key(|(_, count)| Reverse(*count));
+
+	let mut palette: Vec<PaletteColor> = Vec::with_capacity(PALETTE_SIZE);
+	for (rgb, count) in buckets {
+		if palette.len() == PALETTE_SIZE {
+			break;
+		}
+		if palette.iter().all(|color| distance(color.rgb, rgb) >= MIN_DISTANCE) {
+			palette.push(PaletteColor::new(rgb, count as f32 / pixels.len() as f32));
+		}
+	}
+	palette
+}
+
+fn distance(a: Rgb, b: Rgb) -> u32 {
+	(0..3).map(|i| (a[i] as i32 - b[i] as i32).pow(2) as u32).sum()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use flate2::{write::ZlibEncoder, Compression};
+	use std::io::Write;
+
+	fn chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+		// The crc isn't checked while decoding
+		[&(data.len() as u32).to_be_bytes(), kind, data, &[0; 4]].concat()
+	}
+
+	// 4x2 rgb png, three red pixels and a blue one per row, every row with a different filter
+	fn two_color_png() -> Vec<u8> {
+		let rows: [[u8; 13]; 2] = [
+			[0, 255, 0, 0, 255, 0, 0, 255, 0, 0, 0, 0, 255],
+			[2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], // same as the row above
+		];
+		let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+		encoder.write_all(&rows.concat()).unwrap();
+
+		let header = [&4u32.to_be_bytes()[..], &2u32.to_be_bytes(), &[8, 2, 0, 0, 0]].concat();
+		[
+			png::SIGNATURE.to_vec(),
+			chunk(b"IHDR", &header),
+			chunk(b"IDAT", &encoder.finish().unwrap()),
+			chunk(b"IEND", &[]),
+		]
+		.concat()
+	}
+
+	#[test]
+	fn png_palette_is_ordered_by_population() {
+		let pixels = decode(&two_color_png()).unwrap();
+		assert_eq!(pixels.len(), 8);
+		assert_eq!(&pixels[4..], &[[255, 0, 0], [255, 0, 0], [255, 0, 0], [0, 0, 255]]);
+
+		let palette = extract_palette(&pixels);
+		let hexes: Vec<&str> = palette.iter().map(|color| color.hex.as_str()).collect();
+		assert_eq!(hexes, ["#ff0000", "#0000ff"]);
+		assert_eq!(palette[0].population, 0.75);
+	}
+
+	#[test]
+	fn hostile_headers_are_refused() {
+		let huge = [&u32::MAX.to_be_bytes()[..], &u32::MAX.to_be_bytes(), &[8, 6, 0, 0, 0]].concat();
+		let png = [png::SIGNATURE.to_vec(), chunk(b"IHDR", &huge), chunk(b"IEND", &[])].concat();
+		assert!(decode(&png).is_err());
+
+		// One 8x8 gray block whose only DC code claims a 200 bit difference
+		let segment =
+			|marker: u8, data: &[u8]| [&[0xff, marker][..], &(data.len() as u16 + 2).to_be_bytes(), data].concat();
+		let jpeg = |width: u16| {
+			let mut counts = [0u8; 16];
+			counts[0] = 1;
+			[
+				vec![0xff, 0xd8],
+				segment(0xc4, &[&[0x00][..], &counts, &[200]].concat()),
+				segment(
+					0xc0,
+					&[&[8][..], &8u16.to_be_bytes(), &width.to_be_bytes(), &[1, 1, 0x11, 0]].concat(),
+				),
+				segment(0xda, &[1, 1, 0x00, 0, 0, 0]),
+				vec![0x00, 0xff, 0xd9],
+			]
+			.concat()
+		};
+		assert!(decode(&jpeg(8)).is_err());
+		assert!(decode(&jpeg(u16::MAX)).is_err());
+	}
+
+	#[test]
+	fn similar_colors_are_merged() {
+		let mut pixels = vec![[250, 250, 250]; 10];
+		pixels.extend([[240, 240, 240]; 5]);
+		pixels.extend([[10, 20, 30]; 2]);
+
+		let palette = extract_palette(&pixels);
+		assert_eq!(palette.len(), 2);
+		assert_eq!(palette[0].text_color, "#000000");
+		assert_eq!(palette[1].text_color, "#ffffff");
+	}
+
+	#[test]
+	fn demo_song_covers_decode() {
+		let dir = std::path::Path::new("demo_songs/The Neighbourhood_ радио");
+		let mut decoded = 0;
+		for entry in std::fs::read_dir(dir).unwrap() {
+			let Ok(tag) = id3::Tag::read_from_path(entry.unwrap().path()) else {
+				continue;
+			};
+			let Some(picture) = tag.pictures().next() else {
+				continue;
+			};
+			let pixels = decode(&picture.data).unwrap();
+			assert!(!extract_palette(&pixels).is_empty());
+			decoded += 1;
+		}
+		assert!(decoded > 0);
+	}
+}
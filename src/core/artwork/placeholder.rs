@@ -0,0 +1,203 @@
+use super::{png, PaletteColor, Rgb};
+
+pub const DEFAULT_SIZE: usize = 300;
+pub const MIN_SIZE: usize = 32;
+pub const MAX_SIZE: usize = 1024;
+
+// 5x7 glyphs for the png, a row per byte with the leftmost pixel in the 5th bit. The svg shows any
+// letter, the png leaves out the ones it has no glyph for.
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const GLYPHS: [(char, [u8; GLYPH_HEIGHT]); 36] = [
+	('A', [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+	('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+	('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+	('D', [0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E]),
+	('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+	('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+	('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+	('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+	('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+	('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+	('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+	('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+	('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+	('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+	('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+	('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+	('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+	('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+	('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+	('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+	('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+	('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+	('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+	('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+	('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+	('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+	('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+	('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+	('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+	('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+	('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+	('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+	('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+	('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+	('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+	('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+];
+
+// Cover art for the albums without one, a diagonal gradient with the initials on top. The same
+// seed always gives the same art.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placeholder {
+	pub from: Rgb, // top left
+	pub to: Rgb,   // bottom right
+	pub initials: String,
+}
+
+impl Placeholder {
+	pub fn new(seed: u64, initials: String) -> Placeholder {
+		let hue = (seed % 360) as f32;
+		let shift = 30.0 + ((seed >> 16) % 90) as f32;
+		Placeholder {
+			from: hsl(hue, 0.6, 0.55),
+			to: hsl((hue + shift) % 360.0, 0.65, 0.3),
+			initials,
+		}
+	}
+
+	// Black or white, whichever reads better on the middle of the gradient
+	pub fn text_color(&self) -> Rgb {
+		let middle: Vec<f32> = (0..3)
+			.map(|i| (self.from[i] as f32 + self.to[i] as f32) / 2.0)
+			.collect();
+		let luminance = 0.299 * middle[0] + 0.587 * middle[1] + 0.114 * middle[2];
+		if luminance > 150.0 {
+			[0, 0, 0]
+		} else {
+			[255, 255, 255]
+		}
+	}
+
+	// Stands in for the palette of the missing cover
+	pub fn palette(&self) -> Vec<PaletteColor> {
+		super::extract_palette(&[self.from, self.to])
+	}
+
+	pub fn svg(&self, size: usize) -> String {
+		format!(
+			concat!(
+				r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {size} {size}">"#,
+				r#"<defs><linearGradient id="bg" x1="0" y1="0" x2="1" y2="1">"#,
+				r#"<stop offset="0" stop-color="{from}"/><stop offset="1" stop-color="{to}"/></linearGradient></defs>"#,
+				r#"<rect width="100%" height="100%" fill="url(#bg)"/>"#,
+				r#"<text x="50%" y="50%" dominant-baseline="central" text-anchor="middle" font-family="sans-serif" "#,
+				r#"font-weight="bold" font-size="{font_size}" fill="{text}">{initials}</text></svg>"#
+			),
+			size = size,
+			from = hex(self.from),
+			to = hex(self.to),
+			font_size = size * 9 / 25,
+			text = hex(self.text_color()),
+			initials = self.initials,
+		)
+	}
+
+	pub fn png(&self, size: usize) -> Vec<u8> {
+		let span = (2 * (size - 1)).max(1) as f32;
+		let mut pixels: Vec<Rgb> = (0..size * size)
+			.map(|index| {
+				let t = (index % size + index / size) as f32 / span;
+				[0, 1, 2].map(|i| (self.from[i] as f32 + (self.to[i] as f32 - self.from[i] as f32) * t) as u8)
+			})
+			.collect();
+
+		let glyphs: Vec<&[u8; GLYPH_HEIGHT]> = self.initials.chars().filter_map(glyph).collect();
+		if !glyphs.is_empty() {
+			// about a third of the height, a glyph column apart
+			let scale = (size / 3 / GLYPH_HEIGHT).max(1);
+			let width = (glyphs.len() * (GLYPH_WIDTH + 1) - 1) * scale;
+			let (left, top) = ((size.saturating_sub(width)) / 2, (size - GLYPH_HEIGHT * scale) / 2);
+			let text = self.text_color();
+			for (nth, rows) in glyphs.iter().enumerate() {
+				for (row, bits) in rows.iter().enumerate() {
+					for column in (0..GLYPH_WIDTH).filter(|column| bits >> (GLYPH_WIDTH - 1 - column) & 1 == 1) {
+						let x = left + (nth * (GLYPH_WIDTH + 1) + column) * scale;
+						let y = top + row * scale;
+						for (dx, dy) in (0..scale).flat_map(|dx| (0..scale).map(move |dy| (dx, dy))) {
+							if x + dx < size && y + dy < size {
+								pixels[(y + dy) * size + x + dx] = text;
+							}
+						}
+					}
+				}
+			}
+		}
+		png::encode(size, size, &pixels)
+	}
+}
+
+// The first letter of the first two words of the album, of the artist when the album isn't known
+pub fn initials(artist: &str, album: &str) -> String {
+	let name = if album.trim().is_empty() || album == "Unknown Album" {
+		artist
+	} else {
+		album
+	};
+	name.split_whitespace()
+		.filter_map(|word| word.chars().find(|c| c.is_alphanumeric()))
+		.take(2)
+		.flat_map(char::to_uppercase)
+		.collect()
+}
+
+fn glyph(c: char) -> Option<&'static [u8; GLYPH_HEIGHT]> {
+	GLYPHS.iter().find(|(glyph, _)| *glyph == c).map(|(_, rows)| rows)
+}
+
+fn hex(rgb: Rgb) -> String {
+	format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+fn hsl(hue: f32, saturation: f32, lightness: f32) -> Rgb {
+	let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+	let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+	let (r, g, b) = match hue as u32 / 60 {
+		0 => (chroma, x, 0.0),
+		1 => (x, chroma, 0.0),
+		2 => (0.0, chroma, x),
+		3 => (0.0, x, chroma),
+		4 => (x, 0.0, chroma),
+		_ => (chroma, 0.0, x),
+	};
+	let m = lightness - chroma / 2.0;
+	[r, g, b].map(|channel| ((channel + m) * 255.0).round() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::artwork;
+
+	#[test]
+	fn the_same_album_always_gets_the_same_art() {
+		assert_eq!(initials("Joji", "Nectar"), "N");
+		assert_eq!(initials("The 1975", "Unknown Album"), "T1");
+		assert_eq!(initials("TV Girl", "who really cares"), "WR");
+
+		let placeholder = Placeholder::new(42, initials("Joji", "In Tongues"));
+		assert_eq!(placeholder, Placeholder::new(42, "IT".to_string()));
+		assert_ne!(placeholder.from, Placeholder::new(43, String::new()).from);
+
+		let bytes = placeholder.png(64);
+		assert_eq!(bytes, placeholder.png(64));
+		let pixels = artwork::decode(&bytes).unwrap();
+		assert_eq!(pixels.len(), 64 * 64);
+		assert_eq!(pixels[0], placeholder.from);
+		assert_eq!(pixels[64 * 64 - 1], placeholder.to);
+		// the initials are drawn in the middle
+		assert!(pixels.contains(&placeholder.text_color()));
+		assert!(placeholder.svg(64).contains(">IT</text>"));
+	}
+}
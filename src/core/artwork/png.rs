@@ -0,0 +1,204 @@
+use super::{Rgb, MAX_DIMENSION};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use std::io::{Read, Write};
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+struct Header {
+	width: usize,
+	height: usize,
+	bit_depth: u8,
+	color_type: u8,
+}
+
+impl Header {
+	fn channels(&self) -> usize {
+		match self.color_type {
+			0 | 3 => 1,
+			2 => 3,
+			4 => 2,
+			_ => 4,
+		}
+	}
+
+	fn bits_per_pixel(&self) -> usize {
+		self.channels() * self.bit_depth as usize
+	}
+}
+
+// An 8 bit rgb png of the pixels, row by row
+pub fn encode(width: usize, height: usize, pixels: &[Rgb]) -> Vec<u8> {
+	let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+	for row in pixels.chunks(width).take(height) {
+		let line: Vec<u8> = std::iter::once(0).chain(row.iter().flatten().copied()).collect();
+		encoder.write_all(&line).unwrap();
+	}
+	let data = encoder.finish().unwrap();
+
+	let header = [
+		&(width as u32).to_be_bytes()[..],
+		&(height as u32).to_be_bytes(),
+		&[8, 2, 0, 0, 0],
+	]
+	.concat();
+	[
+		SIGNATURE.to_vec(),
+		chunk(b"IHDR", &header),
+		chunk(b"IDAT", &data),
+		chunk(b"IEND", &[]),
+	]
+	.concat()
+}
+
+fn chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+	let mut crc = Crc::new();
+	crc.update(kind);
+	crc.update(data);
+	[&(data.len() as u32).to_be_bytes(), kind, data, &crc.sum().to_be_bytes()].concat()
+}
+
+// Opaque pixels of a non-interlaced png
+pub fn decode(bytes: &[u8]) -> Result<Vec<Rgb>, String> {
+	if !bytes.starts_with(&SIGNATURE) {
+		return Err("Not a png".to_string());
+	}
+
+	let mut header = None;
+	let mut palette: &[u8] = &[];
+	let mut compressed = Vec::new();
+	let mut pos = SIGNATURE.len();
+	while pos + 8 <= bytes.len() {
+		let length = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+		let kind = &bytes[pos + 4..pos + 8];
+		let data = bytes
+			.get(pos + 8..pos + 8 + length)
+			.ok_or_else(|| "Truncated png chunk".to_string())?;
+		pos += 12 + length;
+
+		match kind {
+			b"IHDR" if data.len() >= 13 => {
+				if data[12] != 0 {
+					return Err("Interlaced pngs are not supported".to_string());
+				}
+				header = Some(Header {
+					width: u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize,
+					height: u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize,
+					bit_depth: data[8],
+					color_type: data[9],
+				});
+			}
+			b"PLTE" => palette = data,
+			b"IDAT" => compressed.extend_from_slice(data),
+			b"IEND" => break,
+			_ => {}
+		}
+	}
+
+	let header = header.ok_or_else(|| "Png without a header".to_string())?;
+	if !matches!(header.color_type, 0 | 2 | 3 | 4 | 6) || !matches!(header.bit_depth, 1 | 2 | 4 | 8 | 16) {
+		return Err("Unsupported png color type".to_string());
+	}
+	if !(1..=MAX_DIMENSION).contains(&header.width) || !(1..=MAX_DIMENSION).contains(&header.height) {
+		return Err(format!("Unsupported png size: {}x{}", header.width, header.height));
+	}
+
+	let too_large = || "Png is too large".to_string();
+	let stride = header
+		.width
+		.checked_mul(header.bits_per_pixel())
+		.ok_or_else(too_large)?
+		.div_ceil(8);
+	let expected = (stride + 1).checked_mul(header.height).ok_or_else(too_large)?;
+	let pixel_count = header.width.checked_mul(header.height).ok_or_else(too_large)?;
+
+	// Anything past the expected rows is left compressed
+	let mut raw = Vec::with_capacity(expected);
+	ZlibDecoder::new(compressed.as_slice())
+		.take(expected as u64)
+		.read_to_end(&mut raw)
+		.map_err(|err| format!("Invalid png data: {err}"))?;
+	if raw.len() < expected {
+		return Err("Truncated png data".to_string());
+	}
+
+	let bytes_per_pixel = header.bits_per_pixel().div_ceil(8);
+	let mut previous = vec![0u8; stride];
+	let mut pixels = Vec::with_capacity(pixel_count);
+	for row in raw.chunks_exact(stride + 1).take(header.height) {
+		let mut line = row[1..].to_vec();
+		unfilter(row[0], &mut line, &previous, bytes_per_pixel)?;
+		for x in 0..header.width {
+			if let Some(pixel) = pixel_at(&header, &line, palette, x) {
+				pixels.push(pixel);
+			}
+		}
+		previous = line;
+	}
+	Ok(pixels)
+}
+
+fn unfilter(filter: u8, line: &mut [u8], previous: &[u8], bpp: usize) -> Result<(), String> {
+	for i in 0..line.len() {
+		let left = if i >= bpp { line[i - bpp] } else { 0 };
+		let up = previous[i];
+		let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+		let predicted = match filter {
+			0 => 0,
+			1 => left,
+			2 => up,
+			3 => ((left as u16 + up as u16) / 2) as u8,
+			4 => paeth(left, up, up_left),
+			_ => return Err(format!("Unknown png filter: {filter}")),
+		};
+		line[i] = line[i].wrapping_add(predicted);
+	}
+	Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+	let p = a as i16 + b as i16 - c as i16;
+	let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+	if pa <= pb && pa <= pc {
+		a
+	} else if pb <= pc {
+		b
+	} else {
+		c
+	}
+}
+
+// Samples are scaled down to 8 bits, transparent pixels are skipped
+fn pixel_at(header: &Header, line: &[u8], palette: &[u8], x: usize) -> Option<Rgb> {
+	let depth = header.bit_depth as usize;
+	let sample = |index: usize| -> u8 {
+		match depth {
+			16 => line[index * 2],
+			8 => line[index],
+			_ => {
+				let bit = index * depth;
+				let value = (line[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8;
+				if header.color_type == 3 {
+					value
+				} else {
+					(value as u16 * 255 / ((1 << depth) - 1)) as u8
+				}
+			}
+		}
+	};
+
+	let channels = header.channels();
+	let base = x * channels;
+	match header.color_type {
+		0 => Some([sample(base); 3]),
+		2 => Some([sample(base), sample(base + 1), sample(base + 2)]),
+		3 => {
+			let index = sample(base) as usize * 3;
+			palette.get(index..index + 3).map(|rgb| [rgb[0], rgb[1], rgb[2]])
+		}
+		4 => (sample(base + 1) >= 128).then(|| [sample(base); 3]),
+		_ => (sample(base + 3) >= 128).then(|| [sample(base), sample(base + 1), sample(base + 2)]),
+	}
+}
@@ -0,0 +1,79 @@
+use crate::config::SIGNED_IMAGE_URL_SECS;
+use crate::utils::{exp, jwt};
+
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+
+// Signed urls for the cover images. The signature only changes once every SIGNED_IMAGE_URL_SECS, so a grid asking
+// again gets the same urls and the caches in front keep serving them.
+
+// The start of the window the time is in, and when the urls handed out in it run out
+pub fn window(now: usize) -> (usize, usize) {
+	let secs = SIGNED_IMAGE_URL_SECS as usize;
+	let start = now - now % secs;
+	(start, start + 2 * secs)
+}
+
+// On the cdn when IMAGE_CDN_URL is set, it forwards to /image here
+pub fn signed_url(img_uuid: &str, size: Option<usize>) -> Option<String> {
+	let secret_key = jwt::purpose_key(&std::env::var("JWT_SECRET_KEY").ok()?, "image");
+	let (start, expires) = window(exp::now());
+	let claims = jwt::Claims {
+		id: signed_id(img_uuid, size),
+		exp: expires,
+		iat: start,
+	};
+	let signature = jwt::generate(claims, &secret_key).ok()?;
+	let base = std::env::var("IMAGE_CDN_URL").unwrap_or_default();
+	Some(match size {
+		Some(size) => format!(
+			"{}/image/{img_uuid}?size={size}&sig={signature}",
+			base.trim_end_matches('/')
+		),
+		None => format!("{}/image/{img_uuid}?sig={signature}", base.trim_end_matches('/')),
+	})
+}
+
+// Not turned down by a revocation of the sessions, the urls carry no user
+pub fn valid_signature(img_uuid: &str, size: Option<usize>, signature: &str) -> bool {
+	let Ok(secret_key) = std::env::var("JWT_SECRET_KEY") else {
+		return false;
+	};
+	decode::<jwt::Claims>(
+		signature,
+		&DecodingKey::from_secret(jwt::purpose_key(&secret_key, "image").as_bytes()),
+		&Validation::new(Algorithm::HS256),
+	)
+	.is_ok_and(|data| data.claims.id == signed_id(img_uuid, size))
+}
+
+fn signed_id(img_uuid: &str, size: Option<usize>) -> String {
+	match size {
+		Some(size) => format!("image:{img_uuid}:{size}"),
+		None => format!("image:{img_uuid}"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn urls_stay_the_same_within_a_window() {
+		let secs = SIGNED_IMAGE_URL_SECS as usize;
+		assert_eq!(window(secs * 10 + 5), (secs * 10, secs * 12));
+		assert_eq!(window(secs * 10 + 5).0, window(secs * 11 - 1).0);
+
+		std::env::set_var("JWT_SECRET_KEY", crate::test_support::JWT_SECRET_KEY);
+		let img_uuid = "3f1f0b5e-4a57-4c39-9a43-1a2b3c4d5e6f";
+		let url = signed_url(img_uuid, Some(64)).unwrap();
+		assert_eq!(url, signed_url(img_uuid, Some(64)).unwrap());
+		assert_ne!(url, signed_url(img_uuid, Some(300)).unwrap());
+
+		let signature = url.split_once("sig=").unwrap().1;
+		assert!(valid_signature(img_uuid, Some(64), signature));
+		assert!(!valid_signature(img_uuid, Some(300), signature));
+		assert!(!valid_signature(img_uuid, None, signature));
+		// never a session
+		assert!(jwt::verify(signature, crate::test_support::JWT_SECRET_KEY).is_err());
+	}
+}
@@ -0,0 +1,339 @@
+use crate::core::artwork::animated;
+use crate::lobic_db::models::Mood;
+
+use std::f32::consts::PI;
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+// Tempo and key of the tracks without TBPM/TKEY tags, estimated from the first minutes of audio that ffmpeg
+// decodes to mono pcm. Tempo comes from the autocorrelation of the onset envelope and the key from matching
+// the chroma against the Krumhansl-Kessler key profiles.
+
+pub const SAMPLE_RATE: u32 = 11025;
+// Enough to settle both estimates without decoding whole mixes
+const ANALYZED_SECS: u32 = 180;
+
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+// Tempos are folded towards this one to pick between double and half time
+const PREFERRED_BPM: f32 = 120.0;
+
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+const MAJOR_PROFILE: [f32; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+const MINOR_PROFILE: [f32; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+// Turned on with AUDIO_ANALYSIS=true, it slows the scans down and needs ffmpeg
+pub fn enabled() -> bool {
+	static ENABLED: OnceLock<bool> = OnceLock::new();
+	*ENABLED.get_or_init(|| {
+		std::env::var("AUDIO_ANALYSIS").is_ok_and(|value| value == "true" || value == "1") && animated::available()
+	})
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MusicalKey {
+	pub pitch_class: u8, // 0 is C
+	pub minor: bool,
+}
+
+impl MusicalKey {
+	// Takes Am, A minor, A#m, Bbm or the Camelot 8A, TKEY frames hold any of them
+	pub fn parse(key: &str) -> Option<MusicalKey> {
+		let key = key.trim();
+		if let Some(camelot) = parse_camelot(key) {
+			return Some(camelot);
+		}
+
+		let mut chars = key.chars();
+		let letter = chars.next()?.to_ascii_uppercase();
+		let natural = NOTE_NAMES
+			.iter()
+			.position(|name| name.starts_with(letter) && name.len() == 1)? as i32;
+		let rest = chars.as_str();
+		let (shift, rest) = match rest.chars().next() {
+			Some('#') | Some('♯') => (1, &rest[rest.chars().next().unwrap().len_utf8()..]),
+			Some('b') | Some('♭') => (-1, &rest[rest.chars().next().unwrap().len_utf8()..]),
+			_ => (0, rest),
+		};
+		let minor = match rest.trim().to_lowercase().as_str() {
+			"" | "maj" | "major" => false,
+			"m" | "min" | "minor" => true,
+			_ => return None,
+		};
+		Some(MusicalKey {
+			pitch_class: (natural + shift).rem_euclid(12) as u8,
+			minor,
+		})
+	}
+
+	pub fn name(&self) -> String {
+		let note = NOTE_NAMES[self.pitch_class as usize];
+		if self.minor {
+			format!("{note}m")
+		} else {
+			note.to_string()
+		}
+	}
+
+	// Position on the Camelot wheel, neighbours on it mix well
+	pub fn camelot(&self) -> String {
+		let major_root = if self.minor {
+			(self.pitch_class + 3) % 12
+		} else {
+			self.pitch_class
+		};
+		let number = match (7 * major_root as u32 + 8) % 12 {
+			0 => 12,
+			number => number,
+		};
+		format!("{number}{}", if self.minor { 'A' } else { 'B' })
+	}
+}
+
+fn parse_camelot(key: &str) -> Option<MusicalKey> {
+	// The letter may not be ascii in a bad tag, so the split goes by chars
+	let letter_at = key.char_indices().last()?.0;
+	let (number, letter) = key.split_at(letter_at);
+	let number: u32 = number.parse().ok().filter(|number| (1..=12).contains(number))?;
+	let minor = match letter {
+		"A" | "a" => true,
+		"B" | "b" => false,
+		_ => return None,
+	};
+	// Inverse of (7 * root + 8) % 12, 7 is its own inverse mod 12
+	let major_root = (7 * (number + 4) % 12) as u8;
+	Some(MusicalKey {
+		pitch_class: if minor { (major_root + 9) % 12 } else { major_root },
+		minor,
+	})
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Analysis {
+	pub bpm: Option<f64>,
+	pub key: Option<MusicalKey>,
+}
+
+pub fn analyze(input: &Path) -> Result<Analysis, String> {
+	let samples = decode(input)?;
+	Ok(Analysis {
+		bpm: estimate_bpm(&samples, SAMPLE_RATE).map(|bpm| (bpm as f64 * 10.0).round() / 10.0),
+		key: estimate_key(&samples, SAMPLE_RATE),
+	})
+}
+
+// A rough mood from the tempo and mode, energy rises with the tempo and minor keys go darker.
+// Tracks without a known key are taken as major.
+pub fn derive_mood(bpm: f64, key: Option<MusicalKey>) -> (Mood, f64) {
+	let energy = ((bpm - MIN_BPM as f64) / (MAX_BPM - MIN_BPM) as f64).clamp(0.0, 1.0);
+	let energy = (energy * 100.0).round() / 100.0;
+	let minor = key.is_some_and(|key| key.minor);
+	let mood = match (energy, minor) {
+		(energy, false) if energy < 0.35 => Mood::Chill,
+		(energy, true) if energy < 0.35 => Mood::Melancholic,
+		(energy, false) if energy < 0.65 => Mood::Upbeat,
+		(energy, true) if energy < 0.65 => Mood::Dark,
+		(_, false) => Mood::Energetic,
+		(_, true) => Mood::Intense,
+	};
+	(mood, energy)
+}
+
+fn decode(input: &Path) -> Result<Vec<f32>, String> {
+	let output = Command::new(animated::ffmpeg())
+		.args(["-v", "error", "-i"])
+		.arg(input)
+		.args([
+			"-t",
+			&ANALYZED_SECS.to_string(),
+			"-ac",
+			"1",
+			"-ar",
+			&SAMPLE_RATE.to_string(),
+		])
+		.args(["-f", "s16le", "-"])
+		.output()
+		.map_err(|err| format!("Failed to run ffmpeg: {err}"))?;
+	if !output.status.success() {
+		let stderr = String::from_utf8_lossy(&output.stderr);
+		return Err(format!("Failed to decode the audio: {}", stderr.trim()));
+	}
+	Ok(output
+		.stdout
+		.chunks_exact(2)
+		.map(|sample| i16::from_le_bytes([sample[0], sample[1]]) as f32 / i16::MAX as f32)
+		.collect())
+}
+
+pub fn estimate_bpm(samples: &[f32], sample_rate: u32) -> Option<f32> {
+	const HOP: usize = 256;
+	let frame_rate = sample_rate as f32 / HOP as f32;
+
+	// Rises in the log energy of each hop mark the onsets
+	let energies: Vec<f32> = samples
+		.chunks(HOP)
+		.map(|hop| (hop.iter().map(|sample| sample * sample).sum::<f32>() / hop.len() as f32 + 1e-9).ln())
+		.collect();
+	let mut onsets: Vec<f32> = energies.windows(2).map(|pair| (pair[1] - pair[0]).max(0.0)).collect();
+	let mean = onsets.iter().sum::<f32>() / onsets.len().max(1) as f32;
+	onsets.iter_mut().for_each(|onset| *onset -= mean);
+
+	let min_lag = (frame_rate * 60.0 / MAX_BPM).floor() as usize;
+	let max_lag = (frame_rate * 60.0 / MIN_BPM).ceil() as usize;
+	if onsets.len() < max_lag * 4 {
+		return None;
+	}
+
+	let correlation: Vec<f32> = (0..=max_lag + 1)
+		.map(|lag| onsets.iter().zip(&onsets[lag..]).map(|(a, b)| a * b).sum())
+		.collect();
+	let weighted = |lag: usize| {
+		let bpm = 60.0 * frame_rate / lag as f32;
+		let octaves = (bpm / PREFERRED_BPM).log2();
+		correlation[lag] * (-0.5 * octaves * octaves).exp()
+	};
+	let best = (min_lag..=max_lag).max_by(|a, b| weighted(*a).total_cmp(&weighted(*b)))?;
+	if correlation[best] <= 0.0 {
+		return None;
+	}
+
+	// Parabolic interpolation between the neighbouring lags for a finer tempo
+	let (before, peak, after) = (correlation[best - 1], correlation[best], correlation[best + 1]);
+	let denominator = before - 2.0 * peak + after;
+	let offset = if denominator.abs() > f32::EPSILON {
+		(0.5 * (before - after) / denominator).clamp(-0.5, 0.5)
+	} else {
+		0.0
+	};
+	Some(60.0 * frame_rate / (best as f32 + offset))
+}
+
+pub fn estimate_key(samples: &[f32], sample_rate: u32) -> Option<MusicalKey> {
+	const FRAME: usize = 4096;
+	// C2 to B6, where the harmony of most music sits
+	let notes: Vec<(usize, f32)> = (36..96)
+		.map(|midi| {
+			let frequency = 440.0 * 2f32.powf((midi as f32 - 69.0) / 12.0);
+			(
+				midi as usize % 12,
+				2.0 * (2.0 * PI * frequency / sample_rate as f32).cos(),
+			)
+		})
+		.collect();
+
+	let mut chroma = [0f32; 12];
+	for frame in samples.chunks_exact(FRAME) {
+		for (pitch_class, coefficient) in &notes {
+			// Goertzel, the power of a single frequency without a full fft
+			let (mut previous, mut before) = (0f32, 0f32);
+			for sample in frame {
+				let current = sample + coefficient * previous - before;
+				before = previous;
+				previous = current;
+			}
+			let power = previous * previous + before * before - coefficient * previous * before;
+			chroma[*pitch_class] += power.max(0.0).sqrt();
+		}
+	}
+	if chroma.iter().all(|energy| *energy <= f32::EPSILON) {
+		return None;
+	}
+
+	(0..24)
+		.map(|index| MusicalKey {
+			pitch_class: (index % 12) as u8,
+			minor: index >= 12,
+		})
+		.max_by(|a, b| key_fit(&chroma, a).total_cmp(&key_fit(&chroma, b)))
+}
+
+// Pearson correlation between the chroma and the key's profile
+fn key_fit(chroma: &[f32; 12], key: &MusicalKey) -> f32 {
+	let profile = if key.minor { &MINOR_PROFILE } else { &MAJOR_PROFILE };
+	let rotated: Vec<f32> = (0..12)
+		.map(|pitch_class| profile[(pitch_class + 12 - key.pitch_class as usize) % 12])
+		.collect();
+
+	let mean_chroma = chroma.iter().sum::<f32>() / 12.0;
+	let mean_profile = rotated.iter().sum::<f32>() / 12.0;
+	let (mut covariance, mut chroma_variance, mut profile_variance) = (0.0, 0.0, 0.0);
+	for (energy, weight) in chroma.iter().zip(&rotated) {
+		let (x, y) = (energy - mean_chroma, weight - mean_profile);
+		covariance += x * y;
+		chroma_variance += x * x;
+		profile_variance += y * y;
+	}
+	covariance / (chroma_variance * profile_variance).sqrt().max(f32::EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn sine(frequency: f32, index: usize) -> f32 {
+		(2.0 * PI * frequency * index as f32 / SAMPLE_RATE as f32).sin()
+	}
+
+	#[test]
+	fn keys_are_parsed_in_every_notation() {
+		let a_minor = MusicalKey {
+			pitch_class: 9,
+			minor: true,
+		};
+		for notation in ["Am", "A minor", "a min", "8A", "8a"] {
+			assert_eq!(MusicalKey::parse(notation), Some(a_minor), "{notation}");
+		}
+		assert_eq!(MusicalKey::parse("Bbm").unwrap().name(), "A#m");
+		assert_eq!(MusicalKey::parse("Db").unwrap().camelot(), "3B");
+		assert_eq!(MusicalKey::parse("E").unwrap().camelot(), "12B");
+		assert_eq!(MusicalKey::parse("12A").unwrap().name(), "C#m");
+		assert_eq!(MusicalKey::parse("13A"), None);
+		assert_eq!(MusicalKey::parse("H"), None);
+		assert_eq!(MusicalKey::parse("12é"), None);
+		assert_eq!(MusicalKey::parse("ü"), None);
+
+		// Every key survives a round trip through the wheel
+		for index in 0..24 {
+			let key = MusicalKey {
+				pitch_class: index % 12,
+				minor: index >= 12,
+			};
+			assert_eq!(MusicalKey::parse(&key.camelot()), Some(key));
+		}
+	}
+
+	#[test]
+	fn moods_follow_tempo_and_mode() {
+		let minor = MusicalKey::parse("Am");
+		assert_eq!(derive_mood(70.0, None), (Mood::Chill, 0.07));
+		assert_eq!(derive_mood(70.0, minor).0, Mood::Melancholic);
+		assert_eq!(derive_mood(124.0, MusicalKey::parse("C")).0, Mood::Upbeat);
+		assert_eq!(derive_mood(174.0, minor).0, Mood::Intense);
+		assert_eq!(derive_mood(240.0, None), (Mood::Energetic, 1.0));
+	}
+
+	#[test]
+	fn click_track_tempo_is_found() {
+		// 20 seconds of short 1 kHz clicks at 128 bpm
+		let beat = (SAMPLE_RATE as f32 * 60.0 / 128.0) as usize;
+		let samples: Vec<f32> = (0..SAMPLE_RATE as usize * 20)
+			.map(|index| if index % beat < 200 { sine(1000.0, index) } else { 0.0 })
+			.collect();
+
+		let bpm = estimate_bpm(&samples, SAMPLE_RATE).unwrap();
+		assert!((bpm - 128.0).abs() < 1.5, "{bpm}");
+		assert_eq!(estimate_bpm(&samples[..SAMPLE_RATE as usize], SAMPLE_RATE), None);
+	}
+
+	#[test]
+	fn triad_key_is_found() {
+		// A minor triad, A3 C4 E4
+		let samples: Vec<f32> = (0..SAMPLE_RATE as usize * 3)
+			.map(|index| sine(220.0, index) + sine(261.63, index) + sine(329.63, index))
+			.collect();
+
+		assert_eq!(estimate_key(&samples, SAMPLE_RATE).unwrap().name(), "Am");
+		assert_eq!(estimate_key(&[0.0; 8192], SAMPLE_RATE), None);
+	}
+}
@@ -0,0 +1,123 @@
+use crate::config::{
+	MAX_ANIMATED_COVER_BYTES, MAX_COVER_ART_BYTES, MAX_CURATION_IMPORT_BYTES, MAX_JSON_BODY_BYTES, MAX_UPLOAD_BYTES,
+};
+
+use axum::{
+	body::{to_bytes, Body},
+	extract::Request,
+	http::{header, StatusCode},
+	middleware::Next,
+	response::Response,
+};
+use serde_json::json;
+
+// Routes taking a file as the body, anything else is a json api and gets MAX_JSON_BODY_BYTES
+const UPLOADS: [(&str, &str, usize); 6] = [
+	("/user/update_pfp", "", MAX_UPLOAD_BYTES),
+	("/playlist/new", "", MAX_UPLOAD_BYTES), // the cover comes along as the body
+	("/playlist/update_cover_img", "", MAX_UPLOAD_BYTES),
+	("/animated_cover/", "/upload", MAX_ANIMATED_COVER_BYTES),
+	("/cover_art/", "/upload", MAX_COVER_ART_BYTES),
+	("/user/import/curation", "", MAX_CURATION_IMPORT_BYTES),
+];
+
+// The body limit of the path, uploads are matched on their prefix and suffix
+pub fn limit_for(path: &str) -> usize {
+	UPLOADS
+		.iter()
+		.find(|(prefix, suffix, _)| path.starts_with(prefix) && path.ends_with(suffix))
+		.map_or(MAX_JSON_BODY_BYTES, |(_, _, limit)| *limit)
+}
+
+// Turns away bodies over the limit of their route with a 413 before any handler reads them. The
+// declared length is checked first, bodies without one are read up to the limit.
+pub async fn enforce(req: Request<Body>, next: Next) -> Response {
+	let limit = limit_for(req.uri().path());
+	let declared = req
+		.headers()
+		.get(header::CONTENT_LENGTH)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.parse::<usize>().ok());
+	match declared {
+		Some(length) if length > limit => too_large(limit, Some(length)),
+		Some(_) => next.run(req).await,
+		None => {
+			let (parts, body) = req.into_parts();
+			match to_bytes(body, limit).await {
+				Ok(bytes) => next.run(Request::from_parts(parts, Body::from(bytes))).await,
+				Err(_) => too_large(limit, None),
+			}
+		}
+	}
+}
+
+fn too_large(limit: usize, length: Option<usize>) -> Response {
+	Response::builder()
+		.status(StatusCode::PAYLOAD_TOO_LARGE)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(Body::from(
+			json!({
+				"error": "payload_too_large",
+				"message": format!("The request body can be at most {limit} bytes"),
+				"limit": limit,
+				"length": length,
+			})
+			.to_string(),
+		))
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support::TestApp;
+
+	use axum::http::Method;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn json_apis_get_a_smaller_limit_than_uploads() {
+		assert_eq!(limit_for("/music/lookup"), MAX_JSON_BODY_BYTES);
+		assert_eq!(limit_for("/animated_cover/album/abc/upload"), MAX_ANIMATED_COVER_BYTES);
+		assert_eq!(limit_for("/animated_cover/album/abc/remove"), MAX_JSON_BODY_BYTES);
+
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
+		let music_ids = vec!["x".repeat(64); MAX_JSON_BODY_BYTES / 64];
+		let response = test_app
+			.request(
+				Method::POST,
+				"/music/lookup",
+				Some(json!({ "music_ids": music_ids })),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+		let body = response.json();
+		assert_eq!(body["error"], "payload_too_large");
+		assert_eq!(body["limit"], MAX_JSON_BODY_BYTES);
+
+		let response = test_app
+			.request(
+				Method::POST,
+				"/music/lookup",
+				Some(json!({ "music_ids": ["x"] })),
+				&cookies,
+			)
+			.await;
+		assert_ne!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+	}
+
+	#[tokio::test]
+	async fn playlist_covers_get_the_upload_limit() {
+		let test_app = TestApp::seeded();
+		// turned down by the handler for the query, so nothing gets saved, but not for the size of the cover
+		let uri = "/playlist/new?playlist_name=Covered&user_id=nobody&is_playlist_combined=maybe";
+
+		let response = test_app.upload(uri, vec![0u8; MAX_JSON_BODY_BYTES * 2], &[]).await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST, "{}", response.body);
+
+		let response = test_app.upload(uri, vec![0u8; MAX_UPLOAD_BYTES + 1], &[]).await;
+		assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+	}
+}
@@ -0,0 +1,250 @@
+use crate::lobic_db::models::{ClientError, Music, MusicResponse};
+use crate::schema::{client_errors, music};
+
+use chrono::{Duration as ChronoDuration, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+// What the players can report, anything else is turned down so the counts stay comparable
+pub const ERROR_CODES: [&str; 7] = [
+	"decode",             // the player got the file but couldn't play it
+	"unsupported_format", // the player doesn't know the format
+	"network",            // the stream broke off
+	"timeout",            // the stream didn't start in time
+	"not_found",          // the server had no file for the track
+	"forbidden",          // the server turned the stream down
+	"unknown",
+];
+pub const NETWORK_TYPES: [&str; 5] = ["wifi", "cellular", "ethernet", "offline", "unknown"];
+
+// The codes that point at the file or the way it's streamed rather than the connection
+const DECODE_CODES: [&str; 2] = ["decode", "unsupported_format"];
+const NETWORK_CODES: [&str; 2] = ["network", "timeout"];
+
+pub const DEFAULT_REPORT_DAYS: i64 = 7;
+pub const MAX_REPORT_DAYS: i64 = 90;
+const REPORT_TRACK_LIMIT: usize = 50;
+const REPORT_DEVICE_LIMIT: usize = 20;
+
+// The library's side of a failing track, what the file on disk looks like
+#[derive(Debug, Serialize, PartialEq)]
+pub struct FileHealth {
+	pub exists: bool,
+	pub bytes: u64,
+	pub problem: Option<&'static str>, // missing_file, empty_file or no_duration
+}
+
+pub fn file_health(track: &Music, storage: &Path) -> FileHealth {
+	let path = storage.join(format!("{}.mp3", track.music_id));
+	let bytes = fs::metadata(&path)
+		.ok()
+		.filter(|meta| meta.is_file())
+		.map(|meta| meta.len());
+	let problem = match bytes {
+		None => Some("missing_file"),
+		Some(0) => Some("empty_file"),
+		Some(_) if track.duration <= 0 => Some("no_duration"),
+		Some(_) => None,
+	};
+	FileHealth {
+		exists: bytes.is_some(),
+		bytes: bytes.unwrap_or(0),
+		problem,
+	}
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LikelyCause {
+	BrokenFile, // the file is missing, empty or unreadable to the scanner
+	Transcoder, // the file looks fine but fails to decode on several devices, the stream is at fault
+	Device,     // fails to decode on a single device only
+	Network,    // mostly broken off or slow streams
+}
+
+// A guess from the file and the codes, none when the reports don't lean anywhere
+pub fn likely_cause(health: &FileHealth, errors: &[&ClientError]) -> Option<LikelyCause> {
+	if health.problem.is_some() {
+		return Some(LikelyCause::BrokenFile);
+	}
+	let count = |codes: &[&str]| {
+		errors
+			.iter()
+			.filter(|error| codes.contains(&error.error_code.as_str()))
+			.count()
+	};
+	let (decode, network) = (count(&DECODE_CODES), count(&NETWORK_CODES));
+	if decode * 2 > errors.len() {
+		let devices: BTreeSet<Option<&str>> = errors
+			.iter()
+			.filter(|error| DECODE_CODES.contains(&error.error_code.as_str()))
+			.map(|error| error.device.as_deref())
+			.collect();
+		return Some(match devices.len() {
+			1 => LikelyCause::Device,
+			_ => LikelyCause::Transcoder,
+		});
+	}
+	(network * 2 > errors.len()).then_some(LikelyCause::Network)
+}
+
+#[derive(Debug, Serialize)]
+pub struct CodeSummary {
+	pub error_code: String,
+	pub reports: usize,
+	pub tracks: usize, // a code spread over many tracks is rarely about the files
+	pub devices: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackErrors {
+	pub music: MusicResponse,
+	pub reports: usize,
+	pub users: usize,
+	pub codes: BTreeMap<String, usize>,
+	pub health: FileHealth,
+	pub likely_cause: Option<LikelyCause>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClientErrorReport {
+	pub days: i64,
+	pub reports: usize,
+	pub broken_files: usize, // failing tracks with a file that's missing or empty, over all of them
+	pub by_code: Vec<CodeSummary>,
+	pub by_network_type: BTreeMap<String, usize>,
+	pub by_device: Vec<(String, usize)>, // the most reported first
+	pub tracks: Vec<TrackErrors>,        // the most reported first
+}
+
+// The errors of the last days grouped by code and by track, each track checked against its file in the storage
+pub fn report(days: i64, storage: &Path, db_conn: &mut SqliteConnection) -> QueryResult<ClientErrorReport> {
+	let since = (Utc::now() - ChronoDuration::days(days)).to_rfc3339();
+	let errors = client_errors::table
+		.filter(client_errors::reported_date_time.ge(since))
+		.load::<ClientError>(db_conn)?;
+
+	let mut by_code: BTreeMap<&str, Vec<&ClientError>> = BTreeMap::new();
+	let mut by_network_type: BTreeMap<String, usize> = BTreeMap::new();
+	let mut by_device: HashMap<&str, usize> = HashMap::new();
+	let mut by_track: HashMap<&str, Vec<&ClientError>> = HashMap::new();
+	for error in &errors {
+		by_code.entry(&error.error_code).or_default().push(error);
+		*by_network_type
+			.entry(error.network_type.clone().unwrap_or_else(|| "unknown".to_string()))
+			.or_default() += 1;
+		*by_device
+			.entry(error.device.as_deref().unwrap_or("unknown"))
+			.or_default() += 1;
+		by_track.entry(&error.music_id).or_default().push(error);
+	}
+
+	let music_ids: Vec<&str> = by_track.keys().copied().collect();
+	let tracks = music::table
+		.filter(music::music_id.eq_any(music_ids))
+		.load::<Music>(db_conn)?;
+	let mut track_errors: Vec<TrackErrors> = tracks
+		.into_iter()
+		.map(|track| {
+			let errors = &by_track[track.music_id.as_str()];
+			let health = file_health(&track, storage);
+			let mut codes: BTreeMap<String, usize> = BTreeMap::new();
+			for error in errors {
+				*codes.entry(error.error_code.clone()).or_default() += 1;
+			}
+			TrackErrors {
+				reports: errors.len(),
+				users: errors.iter().map(|error| &error.user_id).collect::<BTreeSet<_>>().len(),
+				codes,
+				likely_cause: likely_cause(&health, errors),
+				health,
+				music: Music::create_music_response(track),
+			}
+		})
+		.collect();
+	let broken_files = track_errors
+		.iter()
+		.filter(|track| track.likely_cause == Some(LikelyCause::BrokenFile))
+		.count();
+	track_errors.sort_by_key(|track| (Reverse(track.reports), Reverse(track.users)));
+	track_errors.truncate(REPORT_TRACK_LIMIT);
+
+	let mut by_device: Vec<(String, usize)> = by_device
+		.into_iter()
+		.map(|(device, reports)| (device.to_string(), reports))
+		.collect();
+	by_device.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+	by_device.truncate(REPORT_DEVICE_LIMIT);
+
+	Ok(ClientErrorReport {
+		days,
+		reports: errors.len(),
+		broken_files,
+		by_code: by_code
+			.into_iter()
+			.map(|(error_code, errors)| CodeSummary {
+				error_code: error_code.to_string(),
+				reports: errors.len(),
+				tracks: errors
+					.iter()
+					.map(|error| &error.music_id)
+					.collect::<BTreeSet<_>>()
+					.len(),
+				devices: errors.iter().map(|error| &error.device).collect::<BTreeSet<_>>().len(),
+			})
+			.collect(),
+		by_network_type,
+		by_device,
+		tracks: track_errors,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn error(code: &str, device: Option<&str>) -> ClientError {
+		ClientError {
+			error_id: "error".to_string(),
+			user_id: "user".to_string(),
+			music_id: "track".to_string(),
+			error_code: code.to_string(),
+			device: device.map(str::to_string),
+			network_type: None,
+			message: None,
+			reported_date_time: Utc::now().to_rfc3339(),
+		}
+	}
+
+	#[test]
+	fn the_cause_follows_the_file_then_the_codes() {
+		let healthy = FileHealth {
+			exists: true,
+			bytes: 1024,
+			problem: None,
+		};
+		let missing = FileHealth {
+			exists: false,
+			bytes: 0,
+			problem: Some("missing_file"),
+		};
+
+		let decode = [
+			error("decode", Some("pixel 7")),
+			error("unsupported_format", Some("iphone 15")),
+		];
+		let decode: Vec<&ClientError> = decode.iter().collect();
+		assert_eq!(likely_cause(&missing, &decode), Some(LikelyCause::BrokenFile));
+		assert_eq!(likely_cause(&healthy, &decode), Some(LikelyCause::Transcoder));
+		assert_eq!(likely_cause(&healthy, &decode[..1]), Some(LikelyCause::Device));
+
+		let mixed = [error("timeout", None), error("network", None), error("decode", None)];
+		let mixed: Vec<&ClientError> = mixed.iter().collect();
+		assert_eq!(likely_cause(&healthy, &mixed), Some(LikelyCause::Network));
+		assert_eq!(likely_cause(&healthy, &mixed[1..]), None);
+	}
+}
@@ -0,0 +1,555 @@
+use crate::lobic_db::models::TrackRating;
+use crate::schema::{liked_songs, music, track_ratings};
+
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+pub const FORMATS: [&str; 3] = ["navidrome", "plex", "itunes"];
+const MAX_LISTED: usize = 100; // of each kind of entry left out, the counts have them all
+const MAX_PLIST_DEPTH: usize = 64; // of dicts and arrays, a library needs a handful
+
+// A track of the export, with what the user thought of it there
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportedTrack {
+	pub title: String,
+	pub artist: String,
+	pub album: Option<String>,
+	pub rating: Option<i32>, // 1 to 5 stars
+	pub favorite: bool,
+}
+
+// The imported rating left out for the one the user already gave the track here
+#[derive(Debug, Serialize)]
+pub struct RatingConflict {
+	pub music_id: String,
+	pub title: String,
+	pub artist: String,
+	pub local: i32,
+	pub imported: i32,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CurationReport {
+	pub entries: usize,
+	pub matched: usize,
+	pub favorites_added: usize,
+	pub ratings_set: usize,
+	pub not_found_count: usize,
+	pub not_found: Vec<ImportedTrack>,
+	pub ambiguous_count: usize,
+	pub ambiguous: Vec<ImportedTrack>, // more than one local track and no album to tell them apart
+	pub conflicts: Vec<RatingConflict>,
+	pub dry_run: bool,
+}
+
+pub fn parse(format: &str, content: &str) -> Result<Vec<ImportedTrack>, String> {
+	match format {
+		"navidrome" => parse_navidrome(content),
+		"plex" => parse_plex(content),
+		"itunes" => parse_itunes(content),
+		_ => Err(format!("format is one of {}", FORMATS.join(", "))),
+	}
+}
+
+// The getStarred2 response of the subsonic api, or the song list of it
+fn parse_navidrome(content: &str) -> Result<Vec<ImportedTrack>, String> {
+	let body: Value = serde_json::from_str(content).map_err(|err| format!("Not a navidrome export: {err}"))?;
+	let songs = match &body {
+		Value::Array(songs) => songs,
+		_ => body["subsonic-response"]["starred2"]["song"]
+			.as_array()
+			.or_else(|| body["song"].as_array())
+			.ok_or("Not a navidrome export: no song list in it")?,
+	};
+	Ok(songs
+		.iter()
+		.filter_map(|song| {
+			Some(ImportedTrack {
+				title: song["title"].as_str()?.to_string(),
+				artist: song["artist"].as_str()?.to_string(),
+				album: song["album"].as_str().map(str::to_string),
+				rating: song["userRating"]
+					.as_i64()
+					.filter(|rating| (1..=5).contains(rating))
+					.map(|rating| rating as i32),
+				// the starred songs are the favorites, the plain song lists say it on every song
+				favorite: song["starred"].is_string() || song["starred"].as_bool() == Some(true),
+			})
+		})
+		.collect())
+}
+
+// A csv with a header naming the title, artist, album and rating columns, the ratings out of 10 like the
+// plex api gives them
+fn parse_plex(content: &str) -> Result<Vec<ImportedTrack>, String> {
+	let mut rows = content.lines().filter(|line| !line.trim().is_empty()).map(csv_fields);
+	let header: Vec<String> = rows
+		.next()
+		.ok_or("Not a plex export: it's empty")?
+		.iter()
+		.map(|name| name.trim().to_lowercase())
+		.collect();
+	let column = |names: &[&str]| header.iter().position(|name| names.contains(&name.as_str()));
+	let (Some(title), Some(artist)) = (column(&["title", "track"]), column(&["artist", "grandparenttitle"])) else {
+		return Err("Not a plex export: the header needs a title and an artist column".to_string());
+	};
+	let album = column(&["album", "parenttitle"]);
+	let rating = column(&["rating", "userrating"]);
+
+	Ok(rows
+		.filter_map(|row| {
+			let field = |index: Option<usize>| index.and_then(|index| row.get(index)).map(|field| field.trim());
+			let rating = field(rating)
+				.and_then(|rating| rating.parse::<f64>().ok())
+				.filter(|rating| *rating > 0.0)
+				.map(|rating| ((rating / 2.0).ceil() as i32).clamp(1, 5));
+			Some(ImportedTrack {
+				title: field(Some(title)).filter(|title| !title.is_empty())?.to_string(),
+				artist: field(Some(artist))?.to_string(),
+				album: field(album).filter(|album| !album.is_empty()).map(str::to_string),
+				rating,
+				favorite: false, // plex only has the stars
+			})
+		})
+		.collect())
+}
+
+fn csv_fields(line: &str) -> Vec<String> {
+	let mut fields = vec![String::new()];
+	let mut quoted = false;
+	let mut chars = line.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'"' if quoted && chars.peek() == Some(&'"') => {
+				chars.next();
+				fields.last_mut().unwrap().push('"');
+			}
+			'"' => quoted = !quoted,
+			',' if !quoted => fields.push(String::new()),
+			_ => fields.last_mut().unwrap().push(c),
+		}
+	}
+	fields
+}
+
+// The Library.xml itunes and the music app export, the ratings out of 100. The ones itunes worked out
+// from the album rating aren't the user's.
+fn parse_itunes(content: &str) -> Result<Vec<ImportedTrack>, String> {
+	let mut reader = PlistReader {
+		rest: content,
+		depth: 0,
+	};
+	let library = match reader.open() {
+		Some(("plist", false)) => reader.open().and_then(|(name, empty)| reader.value(name, empty)),
+		Some((name, empty)) => reader.value(name, empty),
+		None => None,
+	};
+	let library = match library {
+		Some(library) => library,
+		None if reader.depth > MAX_PLIST_DEPTH => {
+			return Err(format!("Not an itunes library: nested deeper than {MAX_PLIST_DEPTH}"));
+		}
+		None => return Err("Not an itunes library: it isn't a plist".to_string()),
+	};
+	let Some(Plist::Dict(tracks)) = library.get("Tracks") else {
+		return Err("Not an itunes library: it has no tracks".to_string());
+	};
+
+	Ok(tracks
+		.iter()
+		.filter_map(|(_, track)| {
+			let computed = track.get("Rating Computed") == Some(&Plist::Bool(true));
+			Some(ImportedTrack {
+				title: track.get("Name")?.string()?.to_string(),
+				artist: track.get("Artist")?.string()?.to_string(),
+				album: track.get("Album").and_then(Plist::string).map(str::to_string),
+				rating: match track.get("Rating") {
+					Some(Plist::Integer(rating)) if !computed && *rating > 0 => {
+						Some(((*rating as f64 / 20.0).round() as i32).clamp(1, 5))
+					}
+					_ => None,
+				},
+				favorite: track.get("Loved") == Some(&Plist::Bool(true))
+					|| track.get("Favorited") == Some(&Plist::Bool(true)),
+			})
+		})
+		.collect())
+}
+
+#[derive(Debug, PartialEq)]
+enum Plist {
+	Dict(Vec<(String, Plist)>),
+	String(String),
+	Integer(i64),
+	Bool(bool),
+	Other, // arrays, reals, dates and data, nothing the import reads
+}
+
+impl Plist {
+	fn get(&self, key: &str) -> Option<&Plist> {
+		match self {
+			Plist::Dict(entries) => entries.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+			_ => None,
+		}
+	}
+
+	fn string(&self) -> Option<&str> {
+		match self {
+			Plist::String(value) => Some(value),
+			_ => None,
+		}
+	}
+}
+
+// Just enough xml for the plists, the elements have no attributes worth reading and no mixed content
+struct PlistReader<'a> {
+	rest: &'a str,
+	depth: usize, // of the dict or array being read, past MAX_PLIST_DEPTH when it gave up on it
+}
+
+impl<'a> PlistReader<'a> {
+	// The name of the next opening tag and whether it closes itself, past the prolog and the comments
+	fn open(&mut self) -> Option<(&'a str, bool)> {
+		loop {
+			let start = self.rest.find('<')?;
+			let rest = &self.rest[start..];
+			if rest.starts_with("<?") || rest.starts_with("<!") {
+				let end = if rest.starts_with("<!--") {
+					rest.find("-->")? + 3
+				} else {
+					rest.find('>')? + 1
+				};
+				self.rest = &rest[end..];
+				continue;
+			}
+			if rest.starts_with("</") {
+				return None;
+			}
+			let end = rest.find('>')?;
+			let tag = &rest[1..end];
+			self.rest = &rest[end + 1..];
+			let empty = tag.ends_with('/');
+			let name = tag.trim_end_matches('/').split_whitespace().next()?;
+			return Some((name, empty));
+		}
+	}
+
+	// Past the next closing tag, when it comes before any opening one
+	fn close(&mut self) -> bool {
+		let Some(start) = self.rest.find('<') else {
+			return false;
+		};
+		if !self.rest[start..].starts_with("</") {
+			return false;
+		}
+		match self.rest[start..].find('>') {
+			Some(end) => {
+				self.rest = &self.rest[start + end + 1..];
+				true
+			}
+			None => false,
+		}
+	}
+
+	fn text(&mut self, name: &str) -> Option<String> {
+		let end = self.rest.find(&format!("</{name}>"))?;
+		let text = unescape(&self.rest[..end]);
+		self.rest = &self.rest[end + name.len() + 3..];
+		Some(text)
+	}
+
+	fn value(&mut self, name: &str, empty: bool) -> Option<Plist> {
+		if empty {
+			return Some(match name {
+				"true" => Plist::Bool(true),
+				"false" => Plist::Bool(false),
+				"dict" => Plist::Dict(Vec::new()),
+				"string" => Plist::String(String::new()),
+				_ => Plist::Other,
+			});
+		}
+		if matches!(name, "dict" | "array") {
+			self.depth += 1;
+			if self.depth > MAX_PLIST_DEPTH {
+				return None;
+			}
+		}
+		let value = match name {
+			"dict" => {
+				let mut entries = Vec::new();
+				while !self.close() {
+					let ("key", false) = self.open()? else {
+						return None;
+					};
+					let key = self.text("key")?;
+					let (name, empty) = self.open()?;
+					entries.push((key, self.value(name, empty)?));
+				}
+				Some(Plist::Dict(entries))
+			}
+			"array" => {
+				while !self.close() {
+					let (name, empty) = self.open()?;
+					self.value(name, empty)?;
+				}
+				Some(Plist::Other)
+			}
+			"string" => self.text(name).map(Plist::String),
+			"integer" => self.text(name)?.trim().parse().ok().map(Plist::Integer),
+			_ => self.text(name).map(|_| Plist::Other),
+		};
+		if value.is_some() && matches!(name, "dict" | "array") {
+			self.depth -= 1;
+		}
+		value
+	}
+}
+
+fn unescape(text: &str) -> String {
+	let mut unescaped = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(start) = rest.find('&') {
+		unescaped.push_str(&rest[..start]);
+		rest = &rest[start..];
+		let entity = rest.find(';').map(|end| (&rest[1..end], end));
+		let c = entity.and_then(|(entity, _)| match entity {
+			"amp" => Some('&'),
+			"lt" => Some('<'),
+			"gt" => Some('>'),
+			"quot" => Some('"'),
+			"apos" => Some('\''),
+			_ => match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+				Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+				None => entity.strip_prefix('#')?.parse().ok().and_then(char::from_u32),
+			},
+		});
+		match (c, entity) {
+			(Some(c), Some((_, end))) => {
+				unescaped.push(c);
+				rest = &rest[end + 1..];
+			}
+			_ => {
+				unescaped.push('&');
+				rest = &rest[1..];
+			}
+		}
+	}
+	unescaped.push_str(rest);
+	unescaped
+}
+
+// Case and spacing differ between servers, the words don't
+fn normalize(text: &str) -> String {
+	text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+// Matches the tracks against the library on their title and artist, the album telling apart the tracks
+// that share both. Favorites become liked songs, ratings only replace different ones with overwrite.
+pub fn import(
+	db_conn: &mut SqliteConnection,
+	user_id: &str,
+	tracks: Vec<ImportedTrack>,
+	overwrite: bool,
+	dry_run: bool,
+) -> Result<CurationReport, diesel::result::Error> {
+	let mut library: HashMap<(String, String), Vec<(String, String)>> = HashMap::new();
+	for (music_id, title, artist, album) in music::table
+		.select((music::music_id, music::title, music::artist, music::album))
+		.load::<(String, String, String, String)>(db_conn)?
+	{
+		library
+			.entry((normalize(&title), normalize(&artist)))
+			.or_default()
+			.push((music_id, normalize(&album)));
+	}
+
+	let mut report = CurationReport {
+		entries: tracks.len(),
+		dry_run,
+		..CurationReport::default()
+	};
+	let mut matched: Vec<(String, ImportedTrack)> = Vec::new();
+	for track in tracks {
+		let candidates = library
+			.get(&(normalize(&track.title), normalize(&track.artist)))
+			.map(Vec::as_slice)
+			.unwrap_or_default();
+		let on_album: Vec<&(String, String)> = match &track.album {
+			Some(album) if candidates.len() > 1 => candidates
+				.iter()
+				.filter(|(_, local)| *local == normalize(album))
+				.collect(),
+			_ => candidates.iter().collect(),
+		};
+		match on_album.as_slice() {
+			[] => {
+				report.not_found_count += 1;
+				if report.not_found.len() < MAX_LISTED {
+					report.not_found.push(track);
+				}
+			}
+			[(music_id, _)] => matched.push((music_id.clone(), track)),
+			_ => {
+				report.ambiguous_count += 1;
+				if report.ambiguous.len() < MAX_LISTED {
+					report.ambiguous.push(track);
+				}
+			}
+		}
+	}
+	report.matched = matched.len();
+
+	db_conn.transaction(|db_conn| {
+		let now = Utc::now().to_rfc3339();
+		let liked: Vec<String> = liked_songs::table
+			.filter(liked_songs::user_id.eq(user_id))
+			.select(liked_songs::music_id)
+			.load(db_conn)?;
+		let rated: HashMap<String, i32> = track_ratings::table
+			.filter(track_ratings::user_id.eq(user_id))
+			.select((track_ratings::music_id, track_ratings::rating))
+			.load::<(String, i32)>(db_conn)?
+			.into_iter()
+			.collect();
+
+		let mut likes: Vec<String> = Vec::new();
+		let mut ratings = Vec::new();
+		for (music_id, track) in matched {
+			if track.favorite && !liked.contains(&music_id) && !likes.contains(&music_id) {
+				likes.push(music_id.clone());
+			}
+			let Some(rating) = track.rating else {
+				continue;
+			};
+			match rated.get(&music_id) {
+				Some(local) if *local == rating => continue,
+				Some(local) if !overwrite => {
+					report.conflicts.push(RatingConflict {
+						music_id,
+						title: track.title,
+						artist: track.artist,
+						local: *local,
+						imported: rating,
+					});
+					continue;
+				}
+				_ => {}
+			}
+			ratings.retain(|earlier: &TrackRating| earlier.music_id != music_id);
+			ratings.push(TrackRating {
+				user_id: user_id.to_string(),
+				music_id,
+				rating,
+				rated_date_time: now.clone(),
+			});
+		}
+		report.favorites_added = likes.len();
+		report.ratings_set = ratings.len();
+
+		if !dry_run {
+			let rows: Vec<_> = likes
+				.iter()
+				.map(|music_id| {
+					(
+						liked_songs::user_id.eq(user_id),
+						liked_songs::music_id.eq(music_id),
+						liked_songs::song_added_date_time.eq(&now),
+					)
+				})
+				.collect();
+			diesel::insert_into(liked_songs::table).values(&rows).execute(db_conn)?;
+			for rating in &ratings {
+				diesel::replace_into(track_ratings::table)
+					.values(rating)
+					.execute(db_conn)?;
+			}
+		}
+		Ok::<(), diesel::result::Error>(())
+	})?;
+	Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn every_format_reads_the_ratings_and_favorites() {
+		let navidrome = r#"{"subsonic-response": {"status": "ok", "starred2": {"song": [
+			{"title": "Glimpse of Us", "artist": "Joji", "album": "Smithereens", "userRating": 4, "starred": "2024-01-01T00:00:00Z"},
+			{"title": "Sanctuary", "artist": "Joji"}
+		]}}}"#;
+		let tracks = parse("navidrome", navidrome).unwrap();
+		assert_eq!(tracks.len(), 2);
+		assert_eq!((tracks[0].rating, tracks[0].favorite), (Some(4), true));
+		assert_eq!(
+			(tracks[1].rating, tracks[1].favorite, tracks[1].album.clone()),
+			(None, false, None)
+		);
+
+		let plex = "Title,Artist,Album,Rating\n\"Lovers Rock\",TV Girl,\"French Exit\",10\n\"Hi, Bye\",\"The \"\"Band\"\"\",,3\nNo Rating,Someone,,\n";
+		let tracks = parse("plex", plex).unwrap();
+		assert_eq!(tracks.len(), 3);
+		assert_eq!(tracks[0].rating, Some(5));
+		assert_eq!(
+			(tracks[1].title.as_str(), tracks[1].artist.as_str()),
+			("Hi, Bye", "The \"Band\"")
+		);
+		assert_eq!((tracks[1].rating, tracks[1].album.clone()), (Some(2), None));
+		assert_eq!(tracks[2].rating, None);
+
+		let itunes = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>Major Version</key><integer>1</integer>
+	<key>Tracks</key>
+	<dict>
+		<key>101</key>
+		<dict>
+			<key>Track ID</key><integer>101</integer>
+			<key>Name</key><string>Rock &amp; Roll</string>
+			<key>Artist</key><string>Caf&#233; Tacvba</string>
+			<key>Album</key><string>Re</string>
+			<key>Rating</key><integer>60</integer>
+			<key>Loved</key><true/>
+			<key>Total Time</key><real>201.5</real>
+		</dict>
+		<key>102</key>
+		<dict>
+			<key>Name</key><string>Album Rated</string>
+			<key>Artist</key><string>Someone</string>
+			<key>Rating</key><integer>80</integer>
+			<key>Rating Computed</key><true/>
+		</dict>
+	</dict>
+	<key>Playlists</key><array><dict><key>Name</key><string>Library</string></dict></array>
+</dict>
+</plist>"#;
+		let tracks = parse("itunes", itunes).unwrap();
+		assert_eq!(
+			tracks[0],
+			ImportedTrack {
+				title: "Rock & Roll".to_string(),
+				artist: "Café Tacvba".to_string(),
+				album: Some("Re".to_string()),
+				rating: Some(3),
+				favorite: true,
+			}
+		);
+		assert_eq!((tracks[1].rating, tracks[1].favorite), (None, false));
+
+		assert!(parse("itunes", "not xml").is_err());
+		let nested = format!("<plist>{}", "<array>".repeat(100_000));
+		assert_eq!(
+			parse("itunes", &nested),
+			Err(format!("Not an itunes library: nested deeper than {MAX_PLIST_DEPTH}"))
+		);
+		assert!(parse("plex", "Name\nsomething").is_err());
+		assert!(parse("spotify", "").is_err());
+	}
+}
@@ -0,0 +1,348 @@
+use crate::config::{
+	ANIMATED_COVER_STORAGE, ARTIST_IMG_STORAGE, COVER_IMG_STORAGE, MUSIC_STORAGE, PLAYLIST_COVER_IMG_STORAGE,
+	TRANSCODE_CACHE_STORAGE, USER_PFP_STORAGE,
+};
+use crate::core::artwork::animated;
+use crate::core::client_errors::{self, DEFAULT_REPORT_DAYS};
+use crate::core::ip_filter::Cidr;
+use crate::lobic_db::db::DatabasePool;
+
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Text};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::SmtpTransport;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use uuid::Uuid;
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(5);
+const MIN_JWT_SECRET_LEN: usize = 32;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+	Ok,
+	Warning, // something won't work, the rest does
+	Error,   // the instance is broken or unsafe as it is
+}
+
+#[derive(Debug, Serialize)]
+pub struct Finding {
+	pub check: &'static str,
+	pub severity: Severity,
+	pub message: String,
+	pub fix: Option<String>, // what the self-hoster can do about it
+}
+
+impl Finding {
+	fn ok(check: &'static str, message: impl Into<String>) -> Finding {
+		Finding {
+			check,
+			severity: Severity::Ok,
+			message: message.into(),
+			fix: None,
+		}
+	}
+
+	fn warning(check: &'static str, message: impl Into<String>, fix: impl Into<String>) -> Finding {
+		Finding {
+			check,
+			severity: Severity::Warning,
+			message: message.into(),
+			fix: Some(fix.into()),
+		}
+	}
+
+	fn error(check: &'static str, message: impl Into<String>, fix: impl Into<String>) -> Finding {
+		Finding {
+			check,
+			severity: Severity::Error,
+			message: message.into(),
+			fix: Some(fix.into()),
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct DoctorReport {
+	pub healthy: bool, // no errors, warnings are fine
+	pub findings: Vec<Finding>,
+}
+
+// Every check, the smtp one talks to the mail server so this blocks for a few seconds at worst
+pub fn diagnose(db_pool: &DatabasePool) -> DoctorReport {
+	let mut findings = check_config();
+	findings.extend(check_media_roots());
+	findings.push(check_ffmpeg());
+	findings.push(check_smtp());
+	findings.extend(check_database(db_pool));
+	findings.push(check_playback_errors(db_pool));
+
+	DoctorReport {
+		healthy: findings.iter().all(|finding| finding.severity != Severity::Error),
+		findings,
+	}
+}
+
+// Prints what needs attention, run once the server starts
+pub fn print_problems(report: &DoctorReport) {
+	for finding in report
+		.findings
+		.iter()
+		.filter(|finding| finding.severity != Severity::Ok)
+	{
+		let fix = finding.fix.as_deref().unwrap_or_default();
+		println!(
+			"[doctor] {:?} {}: {} {fix}",
+			finding.severity, finding.check, finding.message
+		);
+	}
+}
+
+fn check_config() -> Vec<Finding> {
+	let mut findings = Vec::new();
+	match std::env::var("JWT_SECRET_KEY") {
+		Ok(secret) if secret.len() >= MIN_JWT_SECRET_LEN => {
+			findings.push(Finding::ok("config.jwt_secret", "JWT_SECRET_KEY is set"))
+		}
+		Ok(_) => findings.push(Finding::warning(
+			"config.jwt_secret",
+			format!("JWT_SECRET_KEY is shorter than {MIN_JWT_SECRET_LEN} characters"),
+			"Use a long random value, e.g. `openssl rand -hex 32`",
+		)),
+		Err(_) => findings.push(Finding::error(
+			"config.jwt_secret",
+			"JWT_SECRET_KEY is not set, nobody can log in",
+			"Set JWT_SECRET_KEY in .env",
+		)),
+	}
+
+	if std::env::var("INSTANCE_BASE_URL").is_err() {
+		findings.push(Finding::warning(
+			"config.base_url",
+			"INSTANCE_BASE_URL is not set, links and federation use the local ip",
+			"Set INSTANCE_BASE_URL to the public url of the instance",
+		));
+	}
+
+	let recommender = std::env::var("RECOMMENDER").unwrap_or_default();
+	let recommender_url = std::env::var("RECOMMENDER_URL").unwrap_or_default();
+	match recommender.as_str() {
+		"" | "co_occurrence" => {}
+		"http" if !recommender_url.is_empty() => {}
+		"http" => findings.push(Finding::warning(
+			"config.recommender",
+			"RECOMMENDER is http but RECOMMENDER_URL is not set, the built-in recommender is used",
+			"Point RECOMMENDER_URL at the recommendation service",
+		)),
+		other => findings.push(Finding::warning(
+			"config.recommender",
+			format!("RECOMMENDER {other} is unknown, the built-in recommender is used"),
+			"Set RECOMMENDER to co_occurrence or http",
+		)),
+	}
+
+	let proxies = std::env::var("TRUSTED_PROXIES").unwrap_or_default();
+	let invalid: Vec<&str> = proxies
+		.split(',')
+		.map(str::trim)
+		.filter(|entry| !entry.is_empty() && Cidr::parse(entry).is_none())
+		.collect();
+	if !invalid.is_empty() {
+		findings.push(Finding::warning(
+			"config.trusted_proxies",
+			format!(
+				"TRUSTED_PROXIES entries that aren't addresses or ranges are ignored: {}",
+				invalid.join(", ")
+			),
+			"Use addresses or ranges like 10.0.0.0/8, separated by commas",
+		));
+	}
+	findings
+}
+
+// The storage directories have to exist and take new files
+fn check_media_roots() -> Vec<Finding> {
+	let roots = [
+		MUSIC_STORAGE,
+		COVER_IMG_STORAGE,
+		USER_PFP_STORAGE,
+		PLAYLIST_COVER_IMG_STORAGE,
+		ANIMATED_COVER_STORAGE,
+		ARTIST_IMG_STORAGE,
+		TRANSCODE_CACHE_STORAGE,
+	];
+	roots
+		.iter()
+		.map(|root| {
+			let path = Path::new(root);
+			if !path.is_dir() {
+				return Finding::error(
+					"media_root",
+					format!("{root} is missing"),
+					"Start the server once to create it, or create it by hand",
+				);
+			}
+			let probe = path.join(format!(".doctor-{}", Uuid::new_v4()));
+			match fs::write(&probe, b"") {
+				Ok(()) => {
+					let _ = fs::remove_file(&probe);
+					Finding::ok("media_root", format!("{root} is writable"))
+				}
+				Err(err) => Finding::error(
+					"media_root",
+					format!("{root} is not writable: {err}"),
+					"Give the user running Lobic write access to it",
+				),
+			}
+		})
+		.collect()
+}
+
+fn check_ffmpeg() -> Finding {
+	let analysis_on = std::env::var("AUDIO_ANALYSIS").is_ok_and(|value| value == "true" || value == "1");
+	match (animated::available(), analysis_on) {
+		(true, _) => Finding::ok("ffmpeg", format!("{} runs", animated::ffmpeg())),
+		(false, true) => Finding::error(
+			"ffmpeg",
+			"AUDIO_ANALYSIS is on but ffmpeg can't be run",
+			"Install ffmpeg or point FFMPEG_PATH at it, then restart",
+		),
+		(false, false) => Finding::warning(
+			"ffmpeg",
+			"ffmpeg can't be run, animated covers and audio analysis are off",
+			"Install ffmpeg or point FFMPEG_PATH at it, then restart",
+		),
+	}
+}
+
+fn check_smtp() -> Finding {
+	let (Ok(host), Ok(username), Ok(password)) = (
+		std::env::var("SMTP_HOST"),
+		std::env::var("SMTP_USERNAME"),
+		std::env::var("SMTP_PASSWORD"),
+	) else {
+		return Finding::warning(
+			"smtp",
+			"SMTP_HOST, SMTP_USERNAME or SMTP_PASSWORD is not set, no verification or reset emails go out",
+			"Set the three of them in .env",
+		);
+	};
+
+	let transport = match SmtpTransport::starttls_relay(&host) {
+		Ok(transport) => transport,
+		Err(err) => return Finding::error("smtp", format!("Invalid SMTP_HOST {host}: {err}"), "Check SMTP_HOST"),
+	};
+	let mailer = transport
+		.credentials(Credentials::new(username, password))
+		.timeout(Some(SMTP_TIMEOUT))
+		.build();
+	match mailer.test_connection() {
+		Ok(true) => Finding::ok("smtp", format!("Connected to {host}")),
+		Ok(false) => Finding::error(
+			"smtp",
+			format!("{host} didn't accept the connection"),
+			"Check the host and that it speaks STARTTLS on port 587",
+		),
+		Err(err) => Finding::error(
+			"smtp",
+			format!("Couldn't reach {host}: {err}"),
+			"Check the host, the credentials and that outgoing port 587 is open",
+		),
+	}
+}
+
+#[derive(QueryableByName)]
+struct IntegrityRow {
+	#[diesel(sql_type = Text)]
+	integrity_check: String,
+}
+
+#[derive(QueryableByName)]
+struct CountRow {
+	#[diesel(sql_type = BigInt)]
+	count: i64,
+}
+
+fn check_database(db_pool: &DatabasePool) -> Vec<Finding> {
+	let mut db_conn = match db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return vec![Finding::error(
+				"database",
+				format!("Can't connect to the database: {err}"),
+				"Check DATABASE_URL and the permissions of the file",
+			)]
+		}
+	};
+
+	let integrity = diesel::sql_query("PRAGMA integrity_check").load::<IntegrityRow>(&mut db_conn);
+	let integrity = match integrity {
+		Ok(rows) if rows.iter().all(|row| row.integrity_check == "ok") => {
+			Finding::ok("database.integrity", "The database passes the integrity check")
+		}
+		Ok(rows) => Finding::error(
+			"database.integrity",
+			rows.into_iter()
+				.map(|row| row.integrity_check)
+				.collect::<Vec<_>>()
+				.join("; "),
+			"Restore the latest backup (`Lobic db backup` makes them)",
+		),
+		Err(err) => Finding::error(
+			"database.integrity",
+			format!("The integrity check failed to run: {err}"),
+			"Check the database file",
+		),
+	};
+
+	let orphans = diesel::sql_query("SELECT COUNT(*) AS count FROM pragma_foreign_key_check")
+		.get_result::<CountRow>(&mut db_conn)
+		.map(|row| row.count);
+	let foreign_keys = match orphans {
+		Ok(0) => Finding::ok("database.foreign_keys", "Every reference points at an existing row"),
+		Ok(count) => Finding::warning(
+			"database.foreign_keys",
+			format!("{count} rows reference rows that no longer exist"),
+			"Run `PRAGMA foreign_key_check` on the database to see them",
+		),
+		Err(err) => Finding::error(
+			"database.foreign_keys",
+			format!("The foreign key check failed to run: {err}"),
+			"Check the database file",
+		),
+	};
+	vec![integrity, foreign_keys]
+}
+
+// The tracks the players failed on lately whose file is missing or empty
+fn check_playback_errors(db_pool: &DatabasePool) -> Finding {
+	let report = db_pool.get().map_err(|err| err.to_string()).and_then(|mut db_conn| {
+		client_errors::report(DEFAULT_REPORT_DAYS, Path::new(MUSIC_STORAGE), &mut db_conn)
+			.map_err(|err| err.to_string())
+	});
+	match report {
+		Ok(report) if report.broken_files == 0 => Finding::ok(
+			"library.playback_errors",
+			format!(
+				"{} playback errors in the last {DEFAULT_REPORT_DAYS} days, no broken files among them",
+				report.reports
+			),
+		),
+		Ok(report) => Finding::warning(
+			"library.playback_errors",
+			format!(
+				"{} tracks that failed to play have a missing or empty file",
+				report.broken_files
+			),
+			"See /admin/analytics/client_errors for the tracks, then upload them again or rescan the library",
+		),
+		Err(err) => Finding::error(
+			"library.playback_errors",
+			format!("The playback errors couldn't be checked: {err}"),
+			"Check the database file",
+		),
+	}
+}
@@ -0,0 +1,34 @@
+use tokio::sync::broadcast;
+
+// Events a subscriber may still catch up on before it starts missing them
+const BUS_CAPACITY: usize = 1024;
+
+// Things that happened in the app, for the parts which react to them instead of being called directly
+#[derive(Debug, Clone)]
+pub enum Event {
+	SongPlayed {
+		user_id: String,
+		played_date_time: String,
+	},
+}
+
+#[derive(Debug, Clone)]
+pub struct EventBus {
+	sender: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+	pub fn new() -> EventBus {
+		let (sender, _) = broadcast::channel(BUS_CAPACITY);
+		EventBus { sender }
+	}
+
+	// Does nothing when nobody is listening
+	pub fn publish(&self, event: Event) {
+		let _ = self.sender.send(event);
+	}
+
+	pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+		self.sender.subscribe()
+	}
+}
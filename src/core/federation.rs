@@ -0,0 +1,256 @@
+use crate::config::{OpCode, SocketResponse};
+use crate::core::outbox::Outbox;
+use crate::core::user_pool::UserPool;
+use crate::lobic_db::db::DatabasePool;
+use crate::lobic_db::models::{Availability, FederationPeer};
+use crate::schema::{federation_peers, music};
+
+use axum::{
+	extract::ws::Message,
+	http::{header, status::StatusCode, HeaderMap},
+	response::Response,
+};
+use diesel::prelude::*;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+// Members coming from a peer are tracked as "<user_id>@<peer_id>" inside the lobbies
+pub const REMOTE_MEMBER_SEPARATOR: char = '@';
+
+pub const PEER_INVITED: &str = "invited";
+pub const PEER_ACTIVE: &str = "active";
+pub const PEER_REVOKED: &str = "revoked";
+
+// Our users taking part in a lobby hosted on a peer, the events the peer sends are only let through for them
+#[derive(Debug, Clone, Default)]
+pub struct SharedLobbies {
+	inner: Arc<Mutex<HashMap<String, (String, String)>>>, // user id -> (peer id, lobby id)
+}
+
+impl SharedLobbies {
+	pub fn new() -> SharedLobbies {
+		SharedLobbies::default()
+	}
+
+	pub fn join(&self, user_id: &str, peer_id: &str, lobby_id: &str) {
+		self.inner
+			.lock()
+			.unwrap()
+			.insert(user_id.to_string(), (peer_id.to_string(), lobby_id.to_string()));
+	}
+
+	pub fn leave(&self, user_id: &str, peer_id: &str) {
+		let mut inner = self.inner.lock().unwrap();
+		if inner
+			.get(user_id)
+			.is_some_and(|(joined_peer_id, _)| joined_peer_id == peer_id)
+		{
+			inner.remove(user_id);
+		}
+	}
+
+	pub fn is_member(&self, user_id: &str, peer_id: &str) -> bool {
+		self.inner
+			.lock()
+			.unwrap()
+			.get(user_id)
+			.is_some_and(|(joined_peer_id, _)| joined_peer_id == peer_id)
+	}
+}
+
+pub fn generate_token() -> String {
+	format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub fn remote_member_id(user_id: &str, peer_id: &str) -> String {
+	format!("{}{}{}", user_id, REMOTE_MEMBER_SEPARATOR, peer_id)
+}
+
+pub fn is_remote_member(member_id: &str) -> bool {
+	member_id.contains(REMOTE_MEMBER_SEPARATOR)
+}
+
+pub fn peer_by_id(id: &str, db_pool: &DatabasePool) -> Option<FederationPeer> {
+	let mut db_conn = db_pool.get().ok()?;
+	federation_peers::table
+		.filter(federation_peers::peer_id.eq(id))
+		.first::<FederationPeer>(&mut db_conn)
+		.ok()
+}
+
+pub fn peer_by_token(token: &str, db_pool: &DatabasePool) -> Option<FederationPeer> {
+	let mut db_conn = db_pool.get().ok()?;
+	federation_peers::table
+		.filter(federation_peers::local_token.eq(token))
+		.first::<FederationPeer>(&mut db_conn)
+		.ok()
+}
+
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+	headers
+		.get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "))
+}
+
+// Returns the peer making the request, or the response to send back if it isn't an active peer
+#[allow(clippy::result_large_err)]
+pub fn require_peer(headers: &HeaderMap, db_pool: &DatabasePool) -> Result<FederationPeer, Response<String>> {
+	let peer = bearer_token(headers).and_then(|token| peer_by_token(token, db_pool));
+	match peer {
+		Some(peer) if peer.status == PEER_ACTIVE => Ok(peer),
+		_ => Err(Response::builder()
+			.status(StatusCode::UNAUTHORIZED)
+			.body("Unknown federation peer".to_string())
+			.unwrap()),
+	}
+}
+
+fn http_client() -> &'static reqwest::Client {
+	static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+	CLIENT.get_or_init(|| {
+		reqwest::Client::builder()
+			.timeout(Duration::from_secs(10))
+			.build()
+			.expect("Failed to build the federation http client")
+	})
+}
+
+fn peer_url(peer: &FederationPeer, path: &str) -> Result<String, String> {
+	match &peer.base_url {
+		Some(base_url) => Ok(format!("{}{}", base_url.trim_end_matches('/'), path)),
+		None => Err(format!("Peer {} has not completed the handshake", peer.peer_id)),
+	}
+}
+
+// Sends a json request to the peer, authenticated with the token the peer gave us
+pub async fn post_to_peer(peer: &FederationPeer, path: &str, body: &Value) -> Result<Value, String> {
+	let url = peer_url(peer, path)?;
+	let token = peer.remote_token.clone().unwrap_or_default();
+
+	let response = http_client()
+		.post(url)
+		.bearer_auth(token)
+		.json(body)
+		.send()
+		.await
+		.map_err(|err| format!("Failed to reach peer {}: {err}", peer.name))?;
+
+	let status = response.status();
+	let text = response.text().await.unwrap_or_default();
+	if !status.is_success() {
+		return Err(format!("Peer {} responded with {}: {}", peer.name, status, text));
+	}
+
+	Ok(serde_json::from_str(&text).unwrap_or(Value::String(text)))
+}
+
+// Fetches the raw response from the peer, used for relaying the music streams
+pub async fn get_from_peer(peer: &FederationPeer, path: &str) -> Result<reqwest::Response, String> {
+	let url = peer_url(peer, path)?;
+	let token = peer.remote_token.clone().unwrap_or_default();
+
+	// Streams can run for a while so only the connection is bounded here
+	let client = reqwest::Client::builder()
+		.connect_timeout(Duration::from_secs(10))
+		.build()
+		.map_err(|err| err.to_string())?;
+
+	client
+		.get(url)
+		.bearer_auth(token)
+		.send()
+		.await
+		.map_err(|err| format!("Failed to reach peer {}: {err}", peer.name))
+}
+
+// Registers a remote lobby member in the user pool. Everything the lobby sends to the member
+// is forwarded to the peer it came from, which hands it over to the user's socket.
+pub fn attach_remote_member(member_id: &str, user_id: &str, peer: FederationPeer, user_pool: &UserPool) {
+	let outbox = Outbox::new();
+	user_pool.insert(member_id, &outbox);
+
+	let member_id = member_id.to_string();
+	let user_id = user_id.to_string();
+	let user_pool = user_pool.clone();
+	tokio::spawn(async move {
+		loop {
+			let text = match outbox.next().await {
+				Some(Message::Text(text)) => text,
+				Some(_) => continue,
+				None => break, // removed from the pool
+			};
+
+			let body = json!({ "user_id": user_id, "message": text });
+			if let Err(err) = post_to_peer(&peer, "/federation/remote/event", &body).await {
+				println!("Error {}:{}: {err}", file!(), line!());
+			}
+
+			// The member is out of the lobby once it gets deleted
+			let left = serde_json::from_str::<SocketResponse>(&text)
+				.map(|response| response.r#for == OpCode::LEAVE_LOBBY)
+				.unwrap_or(false);
+			if left {
+				user_pool.remove(&member_id);
+				break;
+			}
+		}
+	});
+}
+
+// Rewrites the tracks in a lobby event coming from a peer so they point into our own library,
+// falling back to relaying the stream through the peer when we don't have the track.
+pub fn resolve_tracks(message: &str, peer: &FederationPeer, db_pool: &DatabasePool) -> String {
+	let mut response = match serde_json::from_str::<SocketResponse>(message) {
+		Ok(response) => response,
+		Err(_) => return message.to_string(),
+	};
+
+	let mut db_conn = match db_pool.get() {
+		Ok(conn) => conn,
+		Err(_) => return message.to_string(),
+	};
+
+	match (&response.r#for, &mut response.value) {
+		(OpCode::SYNC_MUSIC, Value::Object(_)) => resolve_track(&mut response.value, peer, &mut db_conn),
+		(OpCode::SYNC_QUEUE, Value::Array(tracks)) => {
+			for track in tracks {
+				resolve_track(track, peer, &mut db_conn);
+			}
+		}
+		_ => return message.to_string(),
+	};
+
+	response.to_string()
+}
+
+fn resolve_track(track: &mut Value, peer: &FederationPeer, db_conn: &mut SqliteConnection) {
+	let remote_id = track["id"].as_str().unwrap_or_default().to_string();
+	if remote_id.is_empty() {
+		return;
+	}
+
+	let title = track["title"].as_str().unwrap_or_default();
+	let artist = track["artist"].as_str().unwrap_or_default();
+	let local_id = music::table
+		.filter(music::title.eq(title))
+		.filter(music::artist.eq(artist))
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.select(music::music_id)
+		.first::<String>(db_conn)
+		.ok();
+
+	match local_id {
+		Some(local_id) => {
+			track["id"] = local_id.into();
+			track["source"] = "local".into();
+		}
+		None => {
+			track["source"] = "relay".into();
+			track["relay_url"] = format!("/federation/relay/{}/{}", peer.peer_id, remote_id).into();
+		}
+	}
+}
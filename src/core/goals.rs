@@ -0,0 +1,210 @@
+use crate::config::OpCode;
+use crate::core::event_bus::{Event, EventBus};
+use crate::core::rollups::day_of;
+use crate::core::user_pool::UserPool;
+use crate::i18n::Locale;
+use crate::lobic_db::db::DatabasePool;
+use crate::lobic_db::models::{ListeningGoal, Notification};
+use crate::routes::notify::notify;
+use crate::schema::{first_listens, listening_goals, play_rollups_daily};
+
+use chrono::{Datelike, Duration, Local, Months, NaiveDate, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GoalKind {
+	Plays,
+	Minutes,
+	NewArtists, // artists played for the first time ever during the period
+	NewTracks,
+}
+
+impl GoalKind {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			GoalKind::Plays => "plays",
+			GoalKind::Minutes => "minutes",
+			GoalKind::NewArtists => "new_artists",
+			GoalKind::NewTracks => "new_tracks",
+		}
+	}
+
+	pub fn parse(value: &str) -> Option<GoalKind> {
+		match value {
+			"plays" => Some(GoalKind::Plays),
+			"minutes" => Some(GoalKind::Minutes),
+			"new_artists" => Some(GoalKind::NewArtists),
+			"new_tracks" => Some(GoalKind::NewTracks),
+			_ => None,
+		}
+	}
+
+	// As the goal is named in the notification, 50 new artists
+	pub fn describe(&self, target: i32) -> String {
+		let unit = match self {
+			GoalKind::Plays => "plays",
+			GoalKind::Minutes => "minutes",
+			GoalKind::NewArtists => "new artists",
+			GoalKind::NewTracks => "new tracks",
+		};
+		format!("{target} {unit}")
+	}
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GoalPeriod {
+	Week,
+	Month,
+	Year,
+}
+
+impl GoalPeriod {
+	// The calendar week, month or year the day is in, weeks start on monday
+	pub fn around(&self, day: NaiveDate) -> (NaiveDate, NaiveDate) {
+		match self {
+			GoalPeriod::Week => {
+				let start = day - Duration::days(day.weekday().num_days_from_monday() as i64);
+				(start, start + Duration::days(6))
+			}
+			GoalPeriod::Month => {
+				let start = day.with_day(1).unwrap();
+				(start, start + Months::new(1) - Duration::days(1))
+			}
+			GoalPeriod::Year => (
+				NaiveDate::from_ymd_opt(day.year(), 1, 1).unwrap(),
+				NaiveDate::from_ymd_opt(day.year(), 12, 31).unwrap(),
+			),
+		}
+	}
+}
+
+// Counts what the goal is about within its period, from the rollups and the first listens
+pub fn progress(goal: &ListeningGoal, db_conn: &mut SqliteConnection) -> QueryResult<i32> {
+	let Some(kind) = GoalKind::parse(&goal.kind) else {
+		return Ok(0);
+	};
+	match kind {
+		GoalKind::Plays | GoalKind::Minutes => {
+			let days = play_rollups_daily::table
+				.filter(play_rollups_daily::user_id.eq(&goal.user_id))
+				.filter(play_rollups_daily::day.between(&goal.period_start, &goal.period_end))
+				.select((play_rollups_daily::play_count, play_rollups_daily::listened_secs))
+				.load::<(i32, i64)>(db_conn)?;
+			Ok(match kind {
+				GoalKind::Plays => days.iter().map(|(play_count, _)| *play_count).sum(),
+				_ => (days.iter().map(|(_, listened_secs)| *listened_secs).sum::<i64>() / 60) as i32,
+			})
+		}
+		GoalKind::NewArtists | GoalKind::NewTracks => {
+			let target_type = if kind == GoalKind::NewArtists {
+				"artist"
+			} else {
+				"track"
+			};
+			let first_played = first_listens::table
+				.filter(first_listens::user_id.eq(&goal.user_id))
+				.filter(first_listens::target_type.eq(target_type))
+				.select(first_listens::first_played_date_time)
+				.load::<String>(db_conn)?;
+			// Stored in utc, the period is in local days like the rollups
+			let (start, end) = (goal.period_start.parse().ok(), goal.period_end.parse().ok());
+			Ok(first_played
+				.iter()
+				.filter_map(|date_time| day_of(date_time))
+				.filter(|day| start.is_some_and(|start| *day >= start) && end.is_some_and(|end| *day <= end))
+				.count() as i32)
+		}
+	}
+}
+
+// Keeps the progress up to date from the events on the bus
+pub fn start(event_bus: &EventBus, db_pool: DatabasePool, user_pool: UserPool) {
+	let mut receiver = event_bus.subscribe();
+	tokio::spawn(async move {
+		loop {
+			let event = match receiver.recv().await {
+				Ok(event) => event,
+				Err(RecvError::Lagged(missed)) => {
+					println!("Listening goals fell behind, {missed} events were skipped");
+					continue;
+				}
+				Err(RecvError::Closed) => return,
+			};
+
+			let db_pool = db_pool.clone();
+			let user_pool = user_pool.clone();
+			let result = tokio::task::spawn_blocking(move || match event {
+				Event::SongPlayed { user_id, .. } => update_progress(&user_id, &db_pool, &user_pool),
+			})
+			.await;
+			if let Ok(Err(err)) = result {
+				println!("Failed to update listening goals: {err}");
+			}
+		}
+	});
+}
+
+// Counts the goals of the user running today again, notifying about the ones just reached
+pub fn update_progress(user_id: &str, db_pool: &DatabasePool, user_pool: &UserPool) -> Result<(), String> {
+	let mut db_conn = db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+
+	let today = Local::now().date_naive().to_string();
+	let running = listening_goals::table
+		.filter(listening_goals::user_id.eq(user_id))
+		.filter(listening_goals::completed_date_time.is_null())
+		.filter(listening_goals::period_start.le(&today))
+		.filter(listening_goals::period_end.ge(&today))
+		.load::<ListeningGoal>(&mut db_conn)
+		.map_err(|err| err.to_string())?;
+
+	for goal in running {
+		let curr_progress = progress(&goal, &mut db_conn).map_err(|err| err.to_string())?;
+		let completed = curr_progress >= goal.target;
+		diesel::update(listening_goals::table.find(&goal.goal_id))
+			.set((
+				listening_goals::progress.eq(curr_progress.min(goal.target)),
+				listening_goals::completed_date_time.eq(completed.then(|| Utc::now().to_rfc3339())),
+			))
+			.execute(&mut db_conn)
+			.map_err(|err| err.to_string())?;
+
+		if completed {
+			let description = GoalKind::parse(&goal.kind)
+				.map(|kind| kind.describe(goal.target))
+				.unwrap_or_default();
+			let locale = Locale::for_user(user_id, &mut db_conn).unwrap_or(Locale::DEFAULT);
+			let notif = Notification::new(
+				OpCode::GOAL_COMPLETED,
+				json!({
+					"goal_id": goal.goal_id,
+					"kind": goal.kind,
+					"target": goal.target,
+					"message": locale.tf("notification.goal_completed", &[("goal", &description)]),
+				}),
+			);
+			notify(user_id, notif, db_pool, user_pool);
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn periods_are_calendar_ones() {
+		let day = NaiveDate::from_ymd_opt(2024, 2, 15).unwrap();
+		let date = |month, day| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+		assert_eq!(GoalPeriod::Week.around(day), (date(2, 12), date(2, 18)));
+		assert_eq!(GoalPeriod::Month.around(day), (date(2, 1), date(2, 29)));
+		assert_eq!(GoalPeriod::Year.around(day), (date(1, 1), date(12, 31)));
+	}
+}
@@ -0,0 +1,233 @@
+use crate::config::{OpCode, SocketResponse};
+use crate::core::app_state::AppState;
+use crate::core::user_pool::{Topic, UserPool};
+use crate::lobic_db::db::DatabasePool;
+use crate::schema::users;
+use crate::services::music::{ScanReport, ScanStep};
+use crate::services::{MusicService, ServiceError};
+
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use uuid::Uuid;
+
+// The progress goes out every this many files, the start, the errors and the end always do
+const EMIT_EVERY: usize = 25;
+const MAX_KEPT_IMPORTS: usize = 20; // the oldest finished ones are forgotten
+const MAX_KEPT_ERRORS: usize = 100; // per import, error_count still counts them all
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+	Discovering,
+	Processing,
+	Finished,
+	Failed, // the scan itself couldn't go on, not a single file
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportProgress {
+	pub import_id: String,
+	pub path: String,
+	pub status: ImportStatus,
+	pub discovered: usize,
+	pub processed: usize,
+	pub saved: usize,
+	pub error_count: usize,
+	pub errors: Vec<String>, // the first MAX_KEPT_ERRORS
+	pub workers: usize,      // reading the files at the same time
+	pub files_per_sec: f64,
+	pub seq: u64, // bumped on every change, a client that fetched the progress skips the events not newer than it
+	pub started_date_time: String,
+	pub finished_date_time: Option<String>,
+	#[serde(skip)]
+	started: Instant,
+}
+
+impl ImportProgress {
+	fn step(&mut self, step: ScanStep) {
+		match step {
+			ScanStep::Discovered { files, workers } => {
+				self.discovered = files;
+				self.workers = workers;
+				self.status = ImportStatus::Processing;
+			}
+			ScanStep::Saved => {
+				self.processed += 1;
+				self.saved += 1;
+			}
+			ScanStep::Failed(error) => {
+				self.processed += 1;
+				self.error_count += 1;
+				if self.errors.len() < MAX_KEPT_ERRORS {
+					self.errors.push(error);
+				}
+			}
+		}
+		self.files_per_sec = self.processed as f64 / self.started.elapsed().as_secs_f64().max(0.001);
+		self.seq += 1;
+	}
+
+	fn finish(&mut self, status: ImportStatus) {
+		self.status = status;
+		self.finished_date_time = Some(Utc::now().to_rfc3339());
+		self.seq += 1;
+	}
+}
+
+// The imports of this instance, running and recent
+#[derive(Debug, Clone)]
+pub struct ImportPool {
+	inner: Arc<Mutex<VecDeque<ImportProgress>>>, // oldest first
+}
+
+impl ImportPool {
+	pub fn new() -> ImportPool {
+		ImportPool {
+			inner: Arc::new(Mutex::new(VecDeque::new())),
+		}
+	}
+
+	pub fn start(&self, path: &str) -> ImportProgress {
+		let progress = ImportProgress {
+			import_id: Uuid::new_v4().to_string(),
+			path: path.to_string(),
+			status: ImportStatus::Discovering,
+			discovered: 0,
+			processed: 0,
+			saved: 0,
+			error_count: 0,
+			errors: Vec::new(),
+			workers: 0,
+			files_per_sec: 0.0,
+			seq: 0,
+			started_date_time: Utc::now().to_rfc3339(),
+			finished_date_time: None,
+			started: Instant::now(),
+		};
+		let mut inner = self.inner.lock().unwrap();
+		inner.push_back(progress.clone());
+		while inner.len() > MAX_KEPT_IMPORTS {
+			let Some(finished) = inner.iter().position(|import| import.finished_date_time.is_some()) else {
+				break;
+			};
+			inner.remove(finished);
+		}
+		progress
+	}
+
+	pub fn get(&self, import_id: &str) -> Option<ImportProgress> {
+		let inner = self.inner.lock().unwrap();
+		inner.iter().find(|import| import.import_id == import_id).cloned()
+	}
+
+	// Newest first
+	pub fn list(&self) -> Vec<ImportProgress> {
+		let inner = self.inner.lock().unwrap();
+		inner.iter().rev().cloned().collect()
+	}
+
+	fn update(&self, import_id: &str, change: impl FnOnce(&mut ImportProgress)) -> Option<ImportProgress> {
+		let mut inner = self.inner.lock().unwrap();
+		let import = inner.iter_mut().find(|import| import.import_id == import_id)?;
+		change(import);
+		Some(import.clone())
+	}
+}
+
+// Sends the progress to the admins connected to this instance, to everyone else it means nothing
+pub fn emit(progress: &ImportProgress, db_pool: &DatabasePool, user_pool: &UserPool) {
+	let Ok(mut db_conn) = db_pool.get() else {
+		return;
+	};
+	let admin_ids = users::table
+		.filter(users::is_admin.eq(true))
+		.select(users::user_id)
+		.load::<String>(&mut db_conn)
+		.unwrap_or_default();
+	drop(db_conn);
+
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::IMPORT_PROGRESS,
+		value: serde_json::to_value(progress).unwrap(),
+	}
+	.to_string();
+	for admin_id in admin_ids {
+		user_pool.send_local(&admin_id, &Topic::Imports, response.clone());
+	}
+}
+
+// Scans the path while keeping its progress in the pool and the admins told about it. Blocks until done.
+pub fn run(
+	app_state: &AppState,
+	import: &ImportProgress,
+	curr_uploader_id: Option<&str>,
+) -> Result<ScanReport, ServiceError> {
+	let AppState {
+		db_pool,
+		user_pool,
+		import_pool,
+		..
+	} = app_state;
+	emit(import, db_pool, user_pool);
+
+	let result = MusicService::new(db_pool).scan_with_progress(&import.path, curr_uploader_id, |step| {
+		let always = !matches!(step, ScanStep::Saved);
+		let Some(progress) = import_pool.update(&import.import_id, |progress| progress.step(step)) else {
+			return;
+		};
+		if always || progress.processed % EMIT_EVERY == 0 {
+			emit(&progress, db_pool, user_pool);
+		}
+	});
+
+	let status = match result {
+		Ok(_) => ImportStatus::Finished,
+		Err(_) => ImportStatus::Failed,
+	};
+	if let Some(progress) = import_pool.update(&import.import_id, |progress| progress.finish(status)) {
+		emit(&progress, db_pool, user_pool);
+	}
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn progress_counts_the_files_and_keeps_the_errors() {
+		let import_pool = ImportPool::new();
+		let import = import_pool.start("/music/new");
+		let steps = [
+			ScanStep::Discovered { files: 3, workers: 2 },
+			ScanStep::Saved,
+			ScanStep::Failed("/music/new/b.mp3: no duration".to_string()),
+			ScanStep::Saved,
+		];
+		for step in steps {
+			import_pool.update(&import.import_id, |progress| progress.step(step));
+		}
+		import_pool.update(&import.import_id, |progress| progress.finish(ImportStatus::Finished));
+
+		let progress = import_pool.get(&import.import_id).unwrap();
+		assert_eq!(progress.status, ImportStatus::Finished);
+		assert_eq!((progress.discovered, progress.processed, progress.saved), (3, 3, 2));
+		assert_eq!(progress.errors, vec!["/music/new/b.mp3: no duration".to_string()]);
+		assert_eq!(progress.seq, 5);
+
+		// only the finished ones make room for newer imports
+		let running = import_pool.start("/music/running");
+		for _ in 0..MAX_KEPT_IMPORTS {
+			let import = import_pool.start("/music/more");
+			import_pool.update(&import.import_id, |progress| progress.finish(ImportStatus::Finished));
+		}
+		assert!(import_pool.get(&import.import_id).is_none());
+		assert!(import_pool.get(&running.import_id).is_some());
+		assert_eq!(import_pool.list().len(), MAX_KEPT_IMPORTS);
+	}
+}
@@ -0,0 +1,91 @@
+use crate::config::{server_ip, PORT};
+use crate::lobic_db::db::DatabasePool;
+use crate::schema::users;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::Serialize;
+use std::time::Duration;
+
+pub const DEFAULT_HEARTBEAT_SECS: u64 = 60 * 60;
+
+// What the instance advertises about itself to the directory and to other tools
+#[derive(Debug, Serialize)]
+pub struct InstanceInfo {
+	pub name: String,
+	pub description: String,
+	pub base_url: String,
+	pub version: String,
+	pub user_count: i64,
+	pub open_registrations: bool,
+}
+
+pub fn instance_name() -> String {
+	std::env::var("INSTANCE_NAME").unwrap_or_else(|_| "Lobic".to_string())
+}
+
+pub fn instance_description() -> String {
+	std::env::var("INSTANCE_DESCRIPTION").unwrap_or_default()
+}
+
+// The url other servers and tools use to reach this instance
+pub fn instance_base_url() -> String {
+	std::env::var("INSTANCE_BASE_URL").unwrap_or_else(|_| format!("http://{}:{}", server_ip(), PORT))
+}
+
+pub fn open_registrations() -> bool {
+	match std::env::var("OPEN_REGISTRATIONS") {
+		Ok(value) => !matches!(value.to_lowercase().as_str(), "false" | "0" | "no"),
+		Err(_) => true,
+	}
+}
+
+pub fn instance_info(db_pool: &DatabasePool) -> InstanceInfo {
+	let user_count = match db_pool.get() {
+		Ok(mut db_conn) => users::table.count().get_result::<i64>(&mut db_conn).unwrap_or(0),
+		Err(_) => 0,
+	};
+
+	InstanceInfo {
+		name: instance_name(),
+		description: instance_description(),
+		base_url: instance_base_url(),
+		version: env!("CARGO_PKG_VERSION").to_string(),
+		user_count,
+		open_registrations: open_registrations(),
+	}
+}
+
+// Registers the instance with the directory at INSTANCE_DIRECTORY_URL and keeps the entry fresh.
+// Nothing is sent unless the directory url is set.
+pub fn start_directory_heartbeat(db_pool: DatabasePool) {
+	let directory_url = match std::env::var("INSTANCE_DIRECTORY_URL") {
+		Ok(url) if !url.is_empty() => url,
+		_ => return,
+	};
+	let interval = std::env::var("INSTANCE_HEARTBEAT_SECS")
+		.ok()
+		.and_then(|secs| secs.parse::<u64>().ok())
+		.unwrap_or(DEFAULT_HEARTBEAT_SECS)
+		.max(60);
+
+	tokio::spawn(async move {
+		let client = reqwest::Client::new();
+		let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+		loop {
+			ticker.tick().await;
+
+			// Directory entry format: the instance info along with when it was sent
+			let mut body = serde_json::to_value(instance_info(&db_pool)).unwrap();
+			body["heartbeat_date_time"] = Utc::now().to_rfc3339().into();
+
+			match client.post(&directory_url).json(&body).send().await {
+				Ok(response) if !response.status().is_success() => {
+					println!("Directory heartbeat rejected: {}", response.status());
+				}
+				Ok(_) => (),
+				Err(err) => println!("Failed to reach the instance directory: {err}"),
+			}
+		}
+	});
+}
@@ -0,0 +1,173 @@
+use crate::core::app_state::AppState;
+use crate::lobic_db::models::IpRule;
+use crate::schema::ip_rules;
+
+use axum::{
+	body::Body,
+	extract::{ConnectInfo, Request, State},
+	http::{header, HeaderMap, StatusCode},
+	middleware::Next,
+	response::Response,
+};
+use diesel::prelude::*;
+use serde_json::json;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+
+// Proxies trusted when TRUSTED_PROXIES isn't set, a reverse proxy on the same machine
+const DEFAULT_TRUSTED_PROXIES: &str = "127.0.0.1,::1";
+
+// The address the request came from, behind the trusted proxies. Handlers get it as an extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClientIp(pub IpAddr);
+
+// A single address or a range of them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+	network: IpAddr,
+	prefix: u8,
+}
+
+impl Cidr {
+	// `203.0.113.7`, `203.0.113.0/24` or `2001:db8::/32`
+	pub fn parse(value: &str) -> Option<Cidr> {
+		let value = value.trim();
+		let (network, prefix) = match value.split_once('/') {
+			Some((network, prefix)) => (network, Some(prefix)),
+			None => (value, None),
+		};
+		let network = network.parse::<IpAddr>().ok()?.to_canonical();
+		let bits = if network.is_ipv4() { 32 } else { 128 };
+		let prefix = match prefix {
+			Some(prefix) => prefix.parse::<u8>().ok().filter(|prefix| *prefix <= bits)?,
+			None => bits,
+		};
+		Some(Cidr { network, prefix })
+	}
+
+	pub fn contains(&self, ip: IpAddr) -> bool {
+		let (network, ip, bits) = match (self.network, ip.to_canonical()) {
+			(IpAddr::V4(network), IpAddr::V4(ip)) => (u32::from(network) as u128, u32::from(ip) as u128, 32),
+			(IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(ip), 128),
+			_ => return false,
+		};
+		let host_bits = (bits - self.prefix) as u32;
+		network.checked_shr(host_bits).unwrap_or(0) == ip.checked_shr(host_bits).unwrap_or(0)
+	}
+}
+
+impl fmt::Display for Cidr {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}/{}", self.network, self.prefix)
+	}
+}
+
+// The comma separated TRUSTED_PROXIES, entries that don't parse are left out
+pub fn trusted_proxies() -> Vec<Cidr> {
+	std::env::var("TRUSTED_PROXIES")
+		.unwrap_or_else(|_| DEFAULT_TRUSTED_PROXIES.to_string())
+		.split(',')
+		.filter(|entry| !entry.trim().is_empty())
+		.filter_map(Cidr::parse)
+		.collect()
+}
+
+// Walks X-Forwarded-For from the right, past the proxies we trust, to the first address we don't.
+// Anything left of that was made up by the client and is ignored. Without a peer there is no address.
+pub fn client_ip(headers: &HeaderMap, peer: Option<IpAddr>, trusted: &[Cidr]) -> Option<IpAddr> {
+	let peer = peer?.to_canonical();
+	let is_trusted = |ip: IpAddr| trusted.iter().any(|cidr| cidr.contains(ip));
+	if !is_trusted(peer) {
+		return Some(peer);
+	}
+
+	let hops: Vec<&str> = headers
+		.get_all("x-forwarded-for")
+		.iter()
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(','))
+		.map(str::trim)
+		.collect();
+	let mut client = peer;
+	for hop in hops.iter().rev() {
+		let Ok(ip) = hop.parse::<IpAddr>() else {
+			break;
+		};
+		client = ip.to_canonical();
+		if !is_trusted(client) {
+			break;
+		}
+	}
+	Some(client)
+}
+
+// The client address of a request, as the server was started with the connect info
+pub fn resolve(req: &Request<Body>) -> Option<IpAddr> {
+	let peer = req
+		.extensions()
+		.get::<ConnectInfo<SocketAddr>>()
+		.map(|ConnectInfo(addr)| addr.ip());
+	client_ip(req.headers(), peer, &trusted_proxies())
+}
+
+// Denied when a deny rule covers the address and no allow rule does
+pub fn is_denied(rules: &[IpRule], ip: IpAddr) -> bool {
+	let covered = |action: &str| {
+		rules
+			.iter()
+			.filter(|rule| rule.action == action)
+			.filter_map(|rule| Cidr::parse(&rule.cidr))
+			.any(|cidr| cidr.contains(ip))
+	};
+	covered(IpRule::DENY) && !covered(IpRule::ALLOW)
+}
+
+// Tags the request with its ClientIp and turns away the denied addresses with a 403
+pub async fn guard(State(app_state): State<AppState>, mut req: Request<Body>, next: Next) -> Response {
+	let Some(ip) = resolve(&req) else {
+		return next.run(req).await;
+	};
+	req.extensions_mut().insert(ClientIp(ip));
+
+	let rules = match app_state.db_pool.get() {
+		Ok(mut db_conn) => ip_rules::table.load::<IpRule>(&mut db_conn).unwrap_or_default(),
+		Err(_) => return next.run(req).await,
+	};
+	if !is_denied(&rules, ip) {
+		return next.run(req).await;
+	}
+	Response::builder()
+		.status(StatusCode::FORBIDDEN)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(Body::from(
+			json!({ "blocked": true, "message": "Requests from your address are blocked on this instance" })
+				.to_string(),
+		))
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn forwarded_for_is_only_believed_from_trusted_proxies() {
+		let trusted = vec![Cidr::parse("127.0.0.1").unwrap(), Cidr::parse("10.0.0.0/8").unwrap()];
+		let mut headers = HeaderMap::new();
+		headers.insert("x-forwarded-for", "6.6.6.6, 203.0.113.7, 10.1.2.3".parse().unwrap());
+
+		let behind_proxy = client_ip(&headers, "127.0.0.1".parse().ok(), &trusted);
+		assert_eq!(behind_proxy, "203.0.113.7".parse().ok());
+		let direct = client_ip(&headers, "198.51.100.1".parse().ok(), &trusted);
+		assert_eq!(direct, "198.51.100.1".parse().ok());
+		let mapped = client_ip(&HeaderMap::new(), "::ffff:198.51.100.1".parse().ok(), &trusted);
+		assert_eq!(mapped, "198.51.100.1".parse().ok());
+		assert_eq!(client_ip(&headers, None, &trusted), None);
+
+		let range = Cidr::parse("2001:db8::/32").unwrap();
+		assert!(range.contains("2001:db8:1::5".parse().unwrap()));
+		assert!(!range.contains("2001:db9::5".parse().unwrap()));
+		assert!(Cidr::parse("0.0.0.0/0").unwrap().contains("8.8.8.8".parse().unwrap()));
+		assert_eq!(Cidr::parse("10.0.0.0/33"), None);
+	}
+}
@@ -0,0 +1,68 @@
+use crate::lobic_db::db::DatabasePool;
+use crate::lobic_db::models::LeaderboardEntry;
+use crate::schema::{leaderboard_entries, music, play_events};
+
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, Utc};
+use diesel::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+// Weeks start on monday, midnight UTC
+pub fn week_start(now: DateTime<Utc>) -> String {
+	let days = now.weekday().num_days_from_monday() as i64;
+	(now - ChronoDuration::days(days))
+		.date_naive()
+		.and_hms_opt(0, 0, 0)
+		.unwrap()
+		.and_utc()
+		.to_rfc3339()
+}
+
+// Rebuilds the standings of the current week from the play events
+pub fn refresh(db_pool: &DatabasePool) -> Result<(), String> {
+	let mut db_conn = db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+
+	let now = Utc::now();
+	let week = week_start(now);
+	let plays = play_events::table
+		.filter(play_events::played_date_time.ge(&week))
+		.inner_join(music::table)
+		.select((play_events::user_id, music::artist, play_events::listened_secs))
+		.load::<(String, String, i64)>(&mut db_conn)
+		.map_err(|err| format!("Failed to load play events: {err}"))?;
+
+	// Listened seconds and artists per user
+	let mut totals: HashMap<String, (i64, HashSet<String>)> = HashMap::new();
+	for (user_id, artist, listened_secs) in plays {
+		let total = totals.entry(user_id).or_default();
+		total.0 += listened_secs;
+		total.1.insert(artist);
+	}
+
+	let refreshed_date_time = now.to_rfc3339();
+	let entries: Vec<LeaderboardEntry> = totals
+		.into_iter()
+		.map(|(user_id, (listened_secs, artists))| LeaderboardEntry {
+			week_start: week.clone(),
+			user_id,
+			minutes_listened: listened_secs / 60,
+			distinct_artists: artists.len() as i32,
+			refreshed_date_time: refreshed_date_time.clone(),
+		})
+		.collect();
+
+	db_conn
+		.transaction::<_, diesel::result::Error, _>(|conn| {
+			diesel::delete(leaderboard_entries::table.filter(leaderboard_entries::week_start.eq(&week)))
+				.execute(conn)?;
+			diesel::insert_into(leaderboard_entries::table)
+				.values(&entries)
+				.execute(conn)?;
+			Ok(())
+		})
+		.map_err(|err| format!("Failed to save leaderboards: {err}"))
+}
@@ -1,25 +1,64 @@
-use crate::config::{MusicState, OpCode, SocketResponse};
-use crate::core::user_pool::UserPool;
+use crate::config::{MusicState, OpCode, SocketResponse, MAX_DJ_TURN_MINUTES, MAX_SLEEP_TIMER_MINUTES};
+use crate::core::federation::is_remote_member;
+use crate::core::realtime::{self, RealtimeEvent};
+use crate::core::user_pool::{Topic, UserPool};
 use crate::lobic_db::db::*;
-use crate::lobic_db::models::Notification;
+use crate::lobic_db::models::{Availability, ContentType, Music as MusicEntry, Notification};
 use crate::routes::notify::notify;
 use crate::utils::timestamp;
 use crate::lobic_db::models::UserFriendship;
-use crate::schema::user_friendship;
+use crate::schema::{blocked_content, music, user_friendship};
+use crate::services::profile::BlockTarget;
 
 use diesel::prelude::*;
-use axum::extract::ws::Message;
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+pub const MAX_POLL_OPTIONS: usize = 6;
+pub const MIN_POLL_SECS: u64 = 10;
+pub const MAX_POLL_SECS: u64 = 600;
+// A member may send this many reactions within the window
+pub const REACTION_LIMIT: usize = 5;
+pub const REACTION_WINDOW: Duration = Duration::from_secs(10);
+// Tracks kept for the transcript of a lobby, the oldest go first
+pub const MAX_PLAYED_HISTORY: usize = 500;
+
+// The reactions the clients have sounds and animations for
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reaction {
+	Airhorn,
+	Fire,
+	Clap,
+	Heart,
+	Laugh,
+}
+
+impl Reaction {
+	pub fn parse(reaction: &str) -> Option<Reaction> {
+		match reaction {
+			"airhorn" => Some(Reaction::Airhorn),
+			"fire" => Some(Reaction::Fire),
+			"clap" => Some(Reaction::Clap),
+			"heart" => Some(Reaction::Heart),
+			"laugh" => Some(Reaction::Laugh),
+			_ => None,
+		}
+	}
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatValue {
 	pub user_id: String,
 	pub message: String,
 	pub timestamp: String,
+	#[serde(default)]
+	pub sent_at: i64, // unix timestamp in milliseconds, the timestamp above is only the time of day
 }
 type Chat = Vec<ChatValue>;
 
@@ -58,7 +97,138 @@ impl From<Music> for Value {
 	}
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayedTrack {
+	pub music_id: String,
+	pub title: String,
+	pub artist: String,
+	pub started_at: i64, // unix timestamp in milliseconds
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SleepTimer {
+	pub id: String,
+	pub ends_at: i64, // unix timestamp in seconds
+	pub fade_secs: u64,
+	pub expired: bool,
+}
+
+impl From<SleepTimer> for Value {
+	fn from(timer: SleepTimer) -> Self {
+		serde_json::to_value(&timer).unwrap()
+	}
+}
+
+// Guest DJ mode, the right to control the music passes among the opted in members
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DjRotation {
+	pub turn_id: String,     // changes every turn, so the timer of an earlier turn bails out
+	pub dj_ids: Vec<String>, // opted in members in turn order
+	pub current: usize,
+	pub every_tracks: Option<u32>,
+	pub every_minutes: Option<u64>,
+	pub tracks_played: u32,        // tracks the current dj started this turn
+	pub turn_ends_at: Option<i64>, // unix timestamp in seconds, for turns measured in minutes
+}
+
+impl DjRotation {
+	pub fn dj_id(&self) -> &str {
+		&self.dj_ids[self.current % self.dj_ids.len()]
+	}
+
+	pub fn next_dj_id(&self) -> &str {
+		&self.dj_ids[(self.current + 1) % self.dj_ids.len()]
+	}
+
+	// Hands the turn to the given position in the rotation
+	fn start_turn(&mut self, position: usize) {
+		self.current = position % self.dj_ids.len();
+		self.turn_id = Uuid::new_v4().to_string();
+		self.tracks_played = 0;
+		self.turn_ends_at = self
+			.every_minutes
+			.and_then(|minutes| minutes.checked_mul(60))
+			.and_then(|secs| i64::try_from(secs).ok())
+			.map(|secs| Utc::now().timestamp() + secs);
+	}
+}
+
+impl From<DjRotation> for Value {
+	fn from(rotation: DjRotation) -> Self {
+		let mut value = serde_json::to_value(&rotation).unwrap();
+		value["dj_id"] = json!(rotation.dj_id());
+		value["next_dj_id"] = json!(rotation.next_dj_id());
+		value
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollOption {
+	pub label: String,
+	pub music_id: Option<String>, // the track, or
+	pub album: Option<String>,    // the album which gets queued when the option wins
+	#[serde(default)]
+	pub voter_ids: Vec<String>,
+}
+
+// What the host asks for
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewPoll {
+	pub question: String,
+	pub options: Vec<PollOption>,
+	pub secs: u64, // how long voting stays open
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Poll {
+	pub id: String,
+	pub question: String,
+	pub options: Vec<PollOption>,
+	pub ends_at: i64, // unix timestamp in seconds
+	pub closed: bool,
+	pub winner: Option<usize>, // index of the option, None on no votes
+}
+
+impl Poll {
+	// The most voted option, the earlier one on a tie
+	fn leading_option(&self) -> Option<usize> {
+		let (index, option) = self
+			.options
+			.iter()
+			.enumerate()
+			.rev()
+			.max_by_key(|(_, option)| option.voter_ids.len())?;
+		(!option.voter_ids.is_empty()).then_some(index)
+	}
+}
+
+// What the members see, vote counts instead of who voted for what
+impl From<Poll> for Value {
+	fn from(poll: Poll) -> Self {
+		let options: Vec<Value> = poll
+			.options
+			.iter()
+			.map(|option| {
+				json!({
+					"label": option.label,
+					"music_id": option.music_id,
+					"album": option.album,
+					"votes": option.voter_ids.len(),
+				})
+			})
+			.collect();
+		json!({
+			"id": poll.id,
+			"question": poll.question,
+			"options": options,
+			"ends_at": poll.ends_at,
+			"closed": poll.closed,
+			"winner": poll.winner,
+		})
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Lobby {
 	pub id: String,
 	pub host_id: String,
@@ -67,17 +237,40 @@ pub struct Lobby {
 	pub music: Music,
 	pub queue: Vec<Music>,
 	pub requested_musics: HashMap<String, Music>,
+	pub sleep_timer: Option<SleepTimer>,
+	#[serde(default)]
+	pub dj_rotation: Option<DjRotation>,
+	#[serde(default)]
+	pub poll: Option<Poll>,
+	#[serde(default)]
+	pub reactions_off: bool, // the host turned the soundboard off
+	#[serde(default)]
+	pub family_filter: bool, // explicit tracks can't be queued or played
+	#[serde(default)]
+	pub played: Vec<PlayedTrack>,
+}
+
+impl Lobby {
+	// Who gets to change the music, the dj of the turn in guest dj mode and the host otherwise
+	pub fn controller_id(&self) -> &str {
+		match &self.dj_rotation {
+			Some(rotation) => rotation.dj_id(),
+			None => &self.host_id,
+		}
+	}
 }
 
 #[derive(Debug, Clone)]
 pub struct LobbyPool {
 	inner: Arc<Mutex<HashMap<String, Lobby>>>,
+	recent_reactions: Arc<Mutex<HashMap<String, VecDeque<Instant>>>>, // per user, for the rate limit
 }
 
 impl LobbyPool {
 	pub fn new() -> LobbyPool {
 		LobbyPool {
 			inner: Arc::new(Mutex::new(HashMap::new())),
+			recent_reactions: Arc::new(Mutex::new(HashMap::new())),
 		}
 	}
 
@@ -136,9 +329,21 @@ impl LobbyPool {
 
 	pub fn insert(&self, key: &str, lobby: Lobby) {
 		let mut inner = self.inner.lock().unwrap();
+		replicate(&lobby);
 		inner.insert(key.to_string(), lobby);
 	}
 
+	// Applies a lobby change made on another instance
+	pub fn apply_replica(&self, lobby: Lobby) {
+		let mut inner = self.inner.lock().unwrap();
+		inner.insert(lobby.id.clone(), lobby);
+	}
+
+	pub fn remove_replica(&self, lobby_id: &str) {
+		let mut inner = self.inner.lock().unwrap();
+		inner.remove(lobby_id);
+	}
+
 	pub fn create_lobby(&self, host_id: &str, db_pool: &DatabasePool) -> Result<Value, String> {
 		if !user_exists(host_id, db_pool) {
 			return Err(format!("Invalid host id: {}", host_id));
@@ -159,6 +364,12 @@ impl LobbyPool {
 			music: Music::new(),
 			queue: Vec::new(),
 			requested_musics: HashMap::new(),
+			sleep_timer: None,
+			dj_rotation: None,
+			poll: None,
+			reactions_off: false,
+			family_filter: false,
+			played: Vec::new(),
 		};
 		self.insert(&lobby_id, lobby);
 
@@ -170,6 +381,38 @@ impl LobbyPool {
 		Ok(response)
 	}
 
+	// The lobby the user is hosting, if any
+	pub fn hosted_by(&self, host_id: &str) -> Option<Lobby> {
+		let inner = self.inner.lock().unwrap();
+		inner.values().find(|lobby| lobby.host_id == host_id).cloned()
+	}
+
+	// A lobby for following a single user, hosted by them and already playing what they are
+	pub fn create_listen_along(&self, host_id: &str, follower_id: &str, music: Music) -> String {
+		let mut lobby_id = Uuid::new_v4().to_string();
+		while self.exists(&lobby_id) {
+			lobby_id = Uuid::new_v4().to_string();
+		}
+
+		let lobby = Lobby {
+			id: lobby_id.clone(),
+			host_id: host_id.to_string(),
+			clients: vec![host_id.to_string(), follower_id.to_string()],
+			chat: Vec::new(),
+			music,
+			queue: Vec::new(),
+			requested_musics: HashMap::new(),
+			sleep_timer: None,
+			dj_rotation: None,
+			poll: None,
+			reactions_off: false,
+			family_filter: false,
+			played: Vec::new(),
+		};
+		self.insert(&lobby_id, lobby);
+		lobby_id
+	}
+
 	pub fn join_lobby(
 		&self,
 		lobby_id: &str,
@@ -177,7 +420,7 @@ impl LobbyPool {
 		db_pool: &DatabasePool,
 		user_pool: &UserPool,
 	) -> Result<Value, String> {
-		if !user_exists(client_id, db_pool) {
+		if !member_exists(client_id, db_pool, user_pool) {
 			return Err(format!("Invalid client id: {}", client_id));
 		}
 
@@ -198,17 +441,13 @@ impl LobbyPool {
 		lobby.clients.push(client_id.to_string());
 
 		// Broadcasting to the members of the lobby that someone has left
-		for client in &lobby.clients {
-			if let Some(conn) = user_pool.get(&client) {
-				let response = SocketResponse {
-					op_code: OpCode::OK,
-					r#for: OpCode::GET_LOBBY_MEMBERS,
-					value: lobby.clients.clone().into(),
-				}
-				.to_string();
-				let _ = conn.send(Message::Text(response));
-			}
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for: OpCode::GET_LOBBY_MEMBERS,
+			value: lobby.clients.clone().into(),
 		}
+		.to_string();
+		broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
 
 		// Pushing the new lobby
 		self.insert(lobby_id, lobby);
@@ -228,7 +467,7 @@ impl LobbyPool {
 		db_pool: &DatabasePool,
 		user_pool: &UserPool,
 	) -> Result<String, String> {
-		if !user_exists(client_id, db_pool) {
+		if !member_exists(client_id, db_pool, user_pool) {
 			return Err(format!("Invalid client id: {}", client_id));
 		}
 
@@ -242,19 +481,17 @@ impl LobbyPool {
 		};
 
 		lobby.clients.retain(|id| id != client_id);
+		self.remove_dj(lobby, client_id, user_pool);
 
 		// Broadcasting to the members of the lobby that someone has left
-		for client in &lobby.clients {
-			if let Some(conn) = user_pool.get(&client) {
-				let response = SocketResponse {
-					op_code: OpCode::OK,
-					r#for: OpCode::GET_LOBBY_MEMBERS,
-					value: lobby.clients.clone().into(),
-				}
-				.to_string();
-				let _ = conn.send(Message::Text(response));
-			}
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for: OpCode::GET_LOBBY_MEMBERS,
+			value: lobby.clients.clone().into(),
 		}
+		.to_string();
+		broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
+		replicate(lobby);
 
 		Ok("Sucessfully left lobby".to_string())
 	}
@@ -269,21 +506,20 @@ impl LobbyPool {
 		};
 
 		// Notifying all the clients in the lobby to leave
-		for client in lobby.clients {
-			if let Some(conn) = user_pool.get(&client) {
-				let response = SocketResponse {
-					op_code: OpCode::OK,
-					r#for: OpCode::LEAVE_LOBBY,
-					value: "Host disconnected".into(),
-				}
-				.to_string();
-				let _ = conn.send(Message::Text(response));
-			}
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for: OpCode::LEAVE_LOBBY,
+			value: "Host disconnected".into(),
 		}
+		.to_string();
+		broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
 
 		// Deleting the lobby
 		let mut inner = self.inner.lock().unwrap();
 		let _ = inner.remove(lobby_id);
+		realtime::publish(RealtimeEvent::LobbyDelete {
+			lobby_id: lobby_id.to_string(),
+		});
 
 		Ok("Sucessfully deleted lobby".to_string())
 	}
@@ -295,7 +531,8 @@ impl LobbyPool {
 		msg: &str,
 		db_pool: &DatabasePool,
 	) -> Result<(), String> {
-		if !user_exists(client_id, db_pool) {
+		// Remote members are checked against the lobby membership below
+		if !is_remote_member(client_id) && !user_exists(client_id, db_pool) {
 			return Err(format!("Invalid client id: {}", client_id));
 		}
 
@@ -315,11 +552,19 @@ impl LobbyPool {
 			user_id: client_id.to_string(),
 			message: msg.to_string(),
 			timestamp: timestamp::now(),
+			sent_at: Utc::now().timestamp_millis(),
 		});
+		replicate(lobby);
 		Ok(())
 	}
 
-	pub fn set_music_state(&self, lobby_id: &str, user_id: &str, music: Music) -> Result<(), String> {
+	pub fn set_music_state(
+		&self,
+		lobby_id: &str,
+		user_id: &str,
+		music: Music,
+		user_pool: &UserPool,
+	) -> Result<(), String> {
 		let mut inner = self.inner.lock().unwrap();
 		let lobby = match inner.get_mut(lobby_id) {
 			Some(lobby) => lobby,
@@ -328,10 +573,61 @@ impl LobbyPool {
 			}
 		};
 
-		if lobby.host_id != user_id {
-			return Err(format!("User {} is not the host of lobby {}", user_id, lobby_id));
+		if lobby.controller_id() != user_id {
+			return Err(format!("User {} is not the dj of lobby {}", user_id, lobby_id));
+		}
+
+		// Once the sleep timer runs out the lobby stays paused until the host cancels the timer
+		if let Some(timer) = &lobby.sleep_timer {
+			if timer.expired && matches!(music.state, MusicState::PLAY | MusicState::CHANGE_MUSIC) {
+				return Err(format!("Sleep timer has ended for lobby {}", lobby_id));
+			}
+		}
+
+		// A dj measured in tracks hands over once their last track has started
+		let new_track = !music.id.is_empty() && music.id != lobby.music.id;
+		if new_track {
+			if lobby.played.len() >= MAX_PLAYED_HISTORY {
+				lobby.played.remove(0);
+			}
+			lobby.played.push(PlayedTrack {
+				music_id: music.id.clone(),
+				title: music.title.clone(),
+				artist: music.artist.clone(),
+				started_at: Utc::now().timestamp_millis(),
+			});
 		}
 		lobby.music = music;
+		if let Some(rotation) = lobby.dj_rotation.as_mut().filter(|_| new_track) {
+			rotation.tracks_played += 1;
+			if rotation.every_tracks.is_some_and(|n| rotation.tracks_played >= n) {
+				rotation.start_turn(rotation.current + 1);
+				self.announce_dj_turn(lobby, user_pool);
+			}
+		}
+		replicate(lobby);
+		Ok(())
+	}
+
+	// Keeps the playback position of the lobby fresh from the host's heartbeats, without notifying anyone
+	pub fn sync_position(&self, lobby_id: &str, user_id: &str, music_id: &str, position: f64) -> Result<(), String> {
+		let mut inner = self.inner.lock().unwrap();
+		let lobby = match inner.get_mut(lobby_id) {
+			Some(lobby) => lobby,
+			None => {
+				return Err(format!("Invalid lobby id: {}", lobby_id));
+			}
+		};
+
+		if lobby.controller_id() != user_id {
+			return Err(format!("User {} is not the dj of lobby {}", user_id, lobby_id));
+		}
+		if lobby.music.id != music_id {
+			return Err(format!("Music {} is not playing in lobby {}", music_id, lobby_id));
+		}
+
+		lobby.music.timestamp = position;
+		replicate(lobby);
 		Ok(())
 	}
 
@@ -345,6 +641,7 @@ impl LobbyPool {
 		};
 
 		lobby.queue = queue;
+		replicate(lobby);
 		Ok(())
 	}
 
@@ -368,6 +665,1001 @@ impl LobbyPool {
 		notify(&lobby.host_id, notif, db_pool, user_pool);
 
 		lobby.requested_musics.insert(music.id.clone(), music);
+		replicate(lobby);
+		Ok(())
+	}
+
+	pub fn set_sleep_timer(
+		&self,
+		lobby_id: &str,
+		user_id: &str,
+		minutes: u64,
+		fade_secs: u64,
+		user_pool: &UserPool,
+	) -> Result<SleepTimer, String> {
+		let timer = {
+			let mut inner = self.inner.lock().unwrap();
+			let lobby = match inner.get_mut(lobby_id) {
+				Some(lobby) => lobby,
+				None => {
+					return Err(format!("Invalid lobby id: {}", lobby_id));
+				}
+			};
+
+			if lobby.host_id != user_id {
+				return Err(format!("User {} is not the host of lobby {}", user_id, lobby_id));
+			}
+
+			if minutes == 0 {
+				return Err("Sleep timer must be at least a minute long".to_string());
+			}
+			if minutes > MAX_SLEEP_TIMER_MINUTES {
+				return Err(format!("Sleep timer can be at most {MAX_SLEEP_TIMER_MINUTES} minutes long"));
+			}
+
+			// Replacing any previous timer, the old task notices the id change and bails out
+			let timer = SleepTimer {
+				id: Uuid::new_v4().to_string(),
+				ends_at: Utc::now().timestamp() + (minutes * 60) as i64,
+				fade_secs: fade_secs.min(minutes * 60),
+				expired: false,
+			};
+			lobby.sleep_timer = Some(timer.clone());
+
+			let response = SocketResponse {
+				op_code: OpCode::OK,
+				r#for: OpCode::SET_SLEEP_TIMER,
+				value: timer.clone().into(),
+			}
+			.to_string();
+			broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
+			replicate(lobby);
+
+			timer
+		};
+
+		// Running the timer in the background
+		let lobby_pool = self.clone();
+		let user_pool = user_pool.clone();
+		let lobby_id = lobby_id.to_string();
+		let task_timer = timer.clone();
+		tokio::spawn(async move {
+			let remaining = (task_timer.ends_at - Utc::now().timestamp()).max(0) as u64;
+			let fade_starts_in = remaining.saturating_sub(task_timer.fade_secs);
+			tokio::time::sleep(Duration::from_secs(fade_starts_in)).await;
+
+			// Telling the clients to start fading out
+			match lobby_pool.get(&lobby_id) {
+				Some(lobby) if lobby.sleep_timer.as_ref().map(|t| &t.id) == Some(&task_timer.id) => {
+					let response = SocketResponse {
+						op_code: OpCode::OK,
+						r#for: OpCode::FADE_OUT,
+						value: json!({ "fade_secs": task_timer.fade_secs }),
+					}
+					.to_string();
+					broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, &user_pool);
+				}
+				_ => return,
+			};
+
+			tokio::time::sleep(Duration::from_secs(task_timer.fade_secs)).await;
+			lobby_pool.expire_sleep_timer(&lobby_id, &task_timer.id, &user_pool);
+		});
+
+		Ok(timer)
+	}
+
+	pub fn cancel_sleep_timer(&self, lobby_id: &str, user_id: &str, user_pool: &UserPool) -> Result<(), String> {
+		let mut inner = self.inner.lock().unwrap();
+		let lobby = match inner.get_mut(lobby_id) {
+			Some(lobby) => lobby,
+			None => {
+				return Err(format!("Invalid lobby id: {}", lobby_id));
+			}
+		};
+
+		if lobby.host_id != user_id {
+			return Err(format!("User {} is not the host of lobby {}", user_id, lobby_id));
+		}
+
+		if lobby.sleep_timer.take().is_none() {
+			return Err(format!("Lobby {} has no sleep timer", lobby_id));
+		}
+
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for: OpCode::CANCEL_SLEEP_TIMER,
+			value: "Sleep timer cancelled".into(),
+		}
+		.to_string();
+		broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
+		replicate(lobby);
+
+		Ok(())
+	}
+
+	// Starts guest dj mode with the host as the first dj, turns last the given number of tracks or minutes
+	pub fn start_dj_rotation(
+		&self,
+		lobby_id: &str,
+		user_id: &str,
+		every_tracks: Option<u32>,
+		every_minutes: Option<u64>,
+		user_pool: &UserPool,
+	) -> Result<DjRotation, String> {
+		let mut inner = self.inner.lock().unwrap();
+		let lobby = match inner.get_mut(lobby_id) {
+			Some(lobby) => lobby,
+			None => {
+				return Err(format!("Invalid lobby id: {}", lobby_id));
+			}
+		};
+
+		if lobby.host_id != user_id {
+			return Err(format!("User {} is not the host of lobby {}", user_id, lobby_id));
+		}
+
+		let (every_tracks, every_minutes) = match (every_tracks.filter(|n| *n > 0), every_minutes.filter(|n| *n > 0)) {
+			(Some(tracks), None) => (Some(tracks), None),
+			(None, Some(minutes)) => (None, Some(minutes)),
+			_ => return Err("A dj turn lasts either a number of tracks or of minutes".to_string()),
+		};
+		if every_minutes.is_some_and(|minutes| minutes > MAX_DJ_TURN_MINUTES) {
+			return Err(format!("A dj turn can be at most {MAX_DJ_TURN_MINUTES} minutes long"));
+		}
+
+		let mut rotation = DjRotation {
+			turn_id: String::new(),
+			dj_ids: vec![lobby.host_id.clone()],
+			current: 0,
+			every_tracks,
+			every_minutes,
+			tracks_played: 0,
+			turn_ends_at: None,
+		};
+		rotation.start_turn(0);
+		lobby.dj_rotation = Some(rotation.clone());
+		self.announce_dj_turn(lobby, user_pool);
+		replicate(lobby);
+
+		Ok(rotation)
+	}
+
+	// Back to the host controlling the music
+	pub fn stop_dj_rotation(&self, lobby_id: &str, user_id: &str, user_pool: &UserPool) -> Result<(), String> {
+		let mut inner = self.inner.lock().unwrap();
+		let lobby = match inner.get_mut(lobby_id) {
+			Some(lobby) => lobby,
+			None => {
+				return Err(format!("Invalid lobby id: {}", lobby_id));
+			}
+		};
+
+		if lobby.host_id != user_id {
+			return Err(format!("User {} is not the host of lobby {}", user_id, lobby_id));
+		}
+
+		if lobby.dj_rotation.take().is_none() {
+			return Err(format!("Lobby {} is not in guest dj mode", lobby_id));
+		}
+
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for: OpCode::DJ_TURN,
+			value: Value::Null,
+		}
+		.to_string();
+		broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
+		replicate(lobby);
+
+		Ok(())
+	}
+
+	// Members opting in wait for their turn at the end of the rotation
+	pub fn set_dj_opt_in(
+		&self,
+		lobby_id: &str,
+		user_id: &str,
+		opt_in: bool,
+		user_pool: &UserPool,
+	) -> Result<DjRotation, String> {
+		let mut inner = self.inner.lock().unwrap();
+		let lobby = match inner.get_mut(lobby_id) {
+			Some(lobby) => lobby,
+			None => {
+				return Err(format!("Invalid lobby id: {}", lobby_id));
+			}
+		};
+
+		if !lobby.clients.iter().any(|id| id == user_id) {
+			return Err(format!("User {} is not in lobby {}", user_id, lobby_id));
+		}
+		let Some(rotation) = lobby.dj_rotation.as_mut() else {
+			return Err(format!("Lobby {} is not in guest dj mode", lobby_id));
+		};
+
+		let opted_in = rotation.dj_ids.iter().any(|id| id == user_id);
+		if opt_in && !opted_in {
+			rotation.dj_ids.push(user_id.to_string());
+			// The next dj may have changed, everyone gets the updated rotation
+			self.announce_dj_turn(lobby, user_pool);
+		} else if !opt_in && opted_in {
+			if rotation.dj_ids.len() == 1 {
+				return Err("The last dj can't opt out, stop guest dj mode instead".to_string());
+			}
+			self.remove_dj(lobby, user_id, user_pool);
+		}
+		replicate(lobby);
+
+		Ok(lobby.dj_rotation.clone().unwrap())
+	}
+
+	// Replaces any earlier poll, it closes by itself after the given number of seconds
+	pub fn create_poll(
+		&self,
+		lobby_id: &str,
+		user_id: &str,
+		new_poll: NewPoll,
+		db_pool: &DatabasePool,
+		user_pool: &UserPool,
+	) -> Result<Poll, String> {
+		let NewPoll {
+			question,
+			options,
+			secs,
+		} = new_poll;
+		if !(2..=MAX_POLL_OPTIONS).contains(&options.len()) {
+			return Err(format!("A poll has between 2 and {} options", MAX_POLL_OPTIONS));
+		}
+		if !(MIN_POLL_SECS..=MAX_POLL_SECS).contains(&secs) {
+			return Err(format!(
+				"A poll runs between {} and {} seconds",
+				MIN_POLL_SECS, MAX_POLL_SECS
+			));
+		}
+		let family_filter = self.get(lobby_id).is_some_and(|lobby| lobby.family_filter);
+		for option in &options {
+			let refers_to_music = option.music_id.is_some() || option.album.is_some();
+			if refers_to_music && poll_tracks(option, family_filter, &[], db_pool).is_empty() {
+				return Err(format!("Nothing to queue for the option {}", option.label));
+			}
+		}
+
+		let poll = {
+			let mut inner = self.inner.lock().unwrap();
+			let lobby = match inner.get_mut(lobby_id) {
+				Some(lobby) => lobby,
+				None => {
+					return Err(format!("Invalid lobby id: {}", lobby_id));
+				}
+			};
+
+			if lobby.host_id != user_id {
+				return Err(format!("User {} is not the host of lobby {}", user_id, lobby_id));
+			}
+
+			let poll = Poll {
+				id: Uuid::new_v4().to_string(),
+				question,
+				options: options
+					.into_iter()
+					.map(|option| PollOption {
+						voter_ids: Vec::new(),
+						..option
+					})
+					.collect(),
+				ends_at: Utc::now().timestamp() + secs as i64,
+				closed: false,
+				winner: None,
+			};
+			lobby.poll = Some(poll.clone());
+
+			let response = SocketResponse {
+				op_code: OpCode::OK,
+				r#for: OpCode::POLL_UPDATE,
+				value: poll.clone().into(),
+			}
+			.to_string();
+			broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
+			replicate(lobby);
+
+			poll
+		};
+
+		// Closing the poll in the background
+		let lobby_pool = self.clone();
+		let db_pool = db_pool.clone();
+		let user_pool = user_pool.clone();
+		let lobby_id = lobby_id.to_string();
+		let poll_id = poll.id.clone();
+		tokio::spawn(async move {
+			tokio::time::sleep(Duration::from_secs(secs)).await;
+			lobby_pool.close_poll(&lobby_id, &poll_id, &db_pool, &user_pool);
+		});
+
+		Ok(poll)
+	}
+
+	// Members have a single vote, voting again moves it to the other option
+	pub fn vote_poll(
+		&self,
+		lobby_id: &str,
+		user_id: &str,
+		poll_id: &str,
+		option: usize,
+		user_pool: &UserPool,
+	) -> Result<Poll, String> {
+		let mut inner = self.inner.lock().unwrap();
+		let lobby = match inner.get_mut(lobby_id) {
+			Some(lobby) => lobby,
+			None => {
+				return Err(format!("Invalid lobby id: {}", lobby_id));
+			}
+		};
+
+		if !lobby.clients.iter().any(|id| id == user_id) {
+			return Err(format!("User {} is not in lobby {}", user_id, lobby_id));
+		}
+		let poll = match lobby.poll.as_mut() {
+			Some(poll) if poll.id == poll_id && !poll.closed => poll,
+			_ => return Err(format!("Poll {} is not open in lobby {}", poll_id, lobby_id)),
+		};
+		if option >= poll.options.len() {
+			return Err(format!("Invalid poll option: {}", option));
+		}
+
+		for poll_option in poll.options.iter_mut() {
+			poll_option.voter_ids.retain(|id| id != user_id);
+		}
+		poll.options[option].voter_ids.push(user_id.to_string());
+		let poll = poll.clone();
+
+		// Live counts for everyone in the lobby
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for: OpCode::POLL_UPDATE,
+			value: poll.clone().into(),
+		}
+		.to_string();
+		broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
+		replicate(lobby);
+
+		Ok(poll)
+	}
+
+	// Announces the result and queues what the winning option refers to
+	fn close_poll(&self, lobby_id: &str, poll_id: &str, db_pool: &DatabasePool, user_pool: &UserPool) {
+		// Looking the tracks up before taking the lock
+		let Some(lobby) = self.get(lobby_id) else {
+			return;
+		};
+		let winning_option = match &lobby.poll {
+			Some(poll) if poll.id == poll_id && !poll.closed => {
+				poll.leading_option().map(|index| poll.options[index].clone())
+			}
+			_ => return,
+		};
+		let tracks = winning_option
+			.map(|option| poll_tracks(&option, lobby.family_filter, &lobby.clients, db_pool))
+			.unwrap_or_default();
+
+		let mut inner = self.inner.lock().unwrap();
+		let lobby = match inner.get_mut(lobby_id) {
+			Some(lobby) => lobby,
+			None => return,
+		};
+		let poll = match lobby.poll.as_mut() {
+			Some(poll) if poll.id == poll_id && !poll.closed => poll,
+			_ => return,
+		};
+		poll.closed = true;
+		poll.winner = poll.leading_option();
+
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for: OpCode::POLL_RESULT,
+			value: poll.clone().into(),
+		}
+		.to_string();
+		broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
+
+		if !tracks.is_empty() {
+			lobby.queue.extend(tracks);
+			let response = SocketResponse {
+				op_code: OpCode::OK,
+				r#for: OpCode::SYNC_QUEUE,
+				value: lobby.queue.clone().into(),
+			}
+			.to_string();
+			broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
+		}
+		replicate(lobby);
+	}
+
+	pub fn set_family_filter(
+		&self,
+		lobby_id: &str,
+		user_id: &str,
+		enabled: bool,
+		user_pool: &UserPool,
+	) -> Result<(), String> {
+		let mut inner = self.inner.lock().unwrap();
+		let lobby = match inner.get_mut(lobby_id) {
+			Some(lobby) => lobby,
+			None => {
+				return Err(format!("Invalid lobby id: {}", lobby_id));
+			}
+		};
+
+		if lobby.host_id != user_id {
+			return Err(format!("User {} is not the host of lobby {}", user_id, lobby_id));
+		}
+
+		lobby.family_filter = enabled;
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for: OpCode::SET_FAMILY_FILTER,
+			value: json!({ "enabled": enabled }),
+		}
+		.to_string();
+		broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
+		replicate(lobby);
+
+		Ok(())
+	}
+
+	// Fails when the lobby has the family filter on and any of the musics is explicit
+	pub fn check_family_filter(
+		&self,
+		lobby_id: &str,
+		music_ids: &[String],
+		db_pool: &DatabasePool,
+	) -> Result<(), String> {
+		let family_filter = self.get(lobby_id).is_some_and(|lobby| lobby.family_filter);
+		match explicit_music_ids(music_ids, db_pool).first() {
+			Some(music_id) if family_filter => Err(format!(
+				"Music {} is explicit and lobby {} has the family filter on",
+				music_id, lobby_id
+			)),
+			_ => Ok(()),
+		}
+	}
+
+	// Drops the chat messages and played tracks from before the cutoff
+	pub fn prune_history(&self, lobby_id: &str, cutoff: i64) {
+		let mut inner = self.inner.lock().unwrap();
+		if let Some(lobby) = inner.get_mut(lobby_id) {
+			let before = lobby.chat.len() + lobby.played.len();
+			lobby.chat.retain(|chat| chat.sent_at >= cutoff);
+			lobby.played.retain(|track| track.started_at >= cutoff);
+			if lobby.chat.len() + lobby.played.len() != before {
+				replicate(lobby);
+			}
+		}
+	}
+
+	// Plays the reaction for everyone in the lobby
+	pub fn react(&self, lobby_id: &str, user_id: &str, reaction: Reaction, user_pool: &UserPool) -> Result<(), String> {
+		let lobby = match self.get(lobby_id) {
+			Some(lobby) => lobby,
+			None => {
+				return Err(format!("Invalid lobby id: {}", lobby_id));
+			}
+		};
+
+		if !lobby.clients.iter().any(|id| id == user_id) {
+			return Err(format!("User {} is not in lobby {}", user_id, lobby_id));
+		}
+		if lobby.reactions_off {
+			return Err(format!("Reactions are turned off in lobby {}", lobby_id));
+		}
+
+		{
+			let mut recent_reactions = self.recent_reactions.lock().unwrap();
+			let sent = recent_reactions.entry(user_id.to_string()).or_default();
+			while sent.front().is_some_and(|at| at.elapsed() > REACTION_WINDOW) {
+				sent.pop_front();
+			}
+			if sent.len() >= REACTION_LIMIT {
+				return Err("Too many reactions, slow down".to_string());
+			}
+			sent.push_back(Instant::now());
+		}
+
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for: OpCode::SEND_REACTION,
+			value: json!({ "user_id": user_id, "reaction": reaction }),
+		}
+		.to_string();
+		broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
+		Ok(())
+	}
+
+	pub fn set_reactions(
+		&self,
+		lobby_id: &str,
+		user_id: &str,
+		enabled: bool,
+		user_pool: &UserPool,
+	) -> Result<(), String> {
+		let mut inner = self.inner.lock().unwrap();
+		let lobby = match inner.get_mut(lobby_id) {
+			Some(lobby) => lobby,
+			None => {
+				return Err(format!("Invalid lobby id: {}", lobby_id));
+			}
+		};
+
+		if lobby.host_id != user_id {
+			return Err(format!("User {} is not the host of lobby {}", user_id, lobby_id));
+		}
+
+		lobby.reactions_off = !enabled;
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for: OpCode::SET_REACTIONS,
+			value: json!({ "enabled": enabled }),
+		}
+		.to_string();
+		broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
+		replicate(lobby);
+
 		Ok(())
 	}
+
+	// Tells the lobby whose turn it is and who is next, and times the turn when it's measured in minutes
+	fn announce_dj_turn(&self, lobby: &Lobby, user_pool: &UserPool) {
+		let Some(rotation) = lobby.dj_rotation.clone() else {
+			return;
+		};
+
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for: OpCode::DJ_TURN,
+			value: rotation.clone().into(),
+		}
+		.to_string();
+		broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
+
+		let Some(ends_at) = rotation.turn_ends_at else {
+			return;
+		};
+		let lobby_pool = self.clone();
+		let user_pool = user_pool.clone();
+		let lobby_id = lobby.id.clone();
+		tokio::spawn(async move {
+			let remaining = (ends_at - Utc::now().timestamp()).max(0) as u64;
+			tokio::time::sleep(Duration::from_secs(remaining)).await;
+			lobby_pool.end_dj_turn(&lobby_id, &rotation.turn_id, &user_pool);
+		});
+	}
+
+	// Takes the user out of the dj rotation, the next dj takes over when it was their turn
+	fn remove_dj(&self, lobby: &mut Lobby, user_id: &str, user_pool: &UserPool) {
+		let Some(rotation) = lobby.dj_rotation.as_mut() else {
+			return;
+		};
+		let Some(position) = rotation.dj_ids.iter().position(|id| id == user_id) else {
+			return;
+		};
+
+		let was_current = position == rotation.current;
+		rotation.dj_ids.remove(position);
+		if rotation.dj_ids.is_empty() {
+			lobby.dj_rotation = None;
+			let response = SocketResponse {
+				op_code: OpCode::OK,
+				r#for: OpCode::DJ_TURN,
+				value: Value::Null,
+			}
+			.to_string();
+			broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
+			return;
+		}
+
+		if was_current {
+			rotation.start_turn(position);
+		} else if position < rotation.current {
+			rotation.current -= 1;
+		}
+		self.announce_dj_turn(lobby, user_pool);
+	}
+
+	// Passes the turn on once the time of the turn (with the given id) runs out
+	fn end_dj_turn(&self, lobby_id: &str, turn_id: &str, user_pool: &UserPool) {
+		let mut inner = self.inner.lock().unwrap();
+		let lobby = match inner.get_mut(lobby_id) {
+			Some(lobby) => lobby,
+			None => return,
+		};
+
+		match lobby.dj_rotation.as_mut() {
+			Some(rotation) if rotation.turn_id == turn_id => rotation.start_turn(rotation.current + 1),
+			_ => return,
+		};
+
+		self.announce_dj_turn(lobby, user_pool);
+		replicate(lobby);
+	}
+
+	// Pauses the lobby once the timer (with the given id) runs out
+	fn expire_sleep_timer(&self, lobby_id: &str, timer_id: &str, user_pool: &UserPool) {
+		let mut inner = self.inner.lock().unwrap();
+		let lobby = match inner.get_mut(lobby_id) {
+			Some(lobby) => lobby,
+			None => return,
+		};
+
+		match lobby.sleep_timer.as_mut() {
+			Some(timer) if timer.id == timer_id => timer.expired = true,
+			_ => return,
+		};
+
+		lobby.music.state = MusicState::PAUSE;
+
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for: OpCode::SYNC_MUSIC,
+			value: lobby.music.clone().into(),
+		}
+		.to_string();
+		broadcast(&lobby.clients, &Topic::lobby(&lobby.id), &response, user_pool);
+		replicate(lobby);
+	}
+}
+
+// The available tracks a poll option refers to, the album ones in title order. Tracks any of the
+// listeners blocked, or whose artist they blocked, are left out.
+fn poll_tracks(option: &PollOption, family_filter: bool, listener_ids: &[String], db_pool: &DatabasePool) -> Vec<Music> {
+	let mut db_conn = match db_pool.get() {
+		Ok(conn) => conn,
+		Err(_) => return Vec::new(),
+	};
+
+	let mut query = music::table
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.into_boxed();
+	if family_filter {
+		query = query.filter(music::explicit.eq(false));
+	}
+	let blocked = |target: BlockTarget| {
+		blocked_content::table
+			.filter(blocked_content::user_id.eq_any(listener_ids.to_vec()))
+			.filter(blocked_content::target_type.eq(target.as_str()))
+			.select(blocked_content::target_id)
+	};
+	query = query
+		.filter(music::music_id.ne_all(blocked(BlockTarget::Track)))
+		.filter(music::artist.ne_all(blocked(BlockTarget::Artist)));
+	query = match (&option.music_id, &option.album) {
+		(Some(music_id), _) => query.filter(music::music_id.eq(music_id)),
+		(None, Some(album)) => query
+			.filter(music::album.eq(album))
+			.filter(music::content_type.eq(ContentType::Music.as_str()))
+			.order(music::title),
+		(None, None) => return Vec::new(),
+	};
+	query
+		.load::<MusicEntry>(&mut db_conn)
+		.unwrap_or_default()
+		.into_iter()
+		.map(|entry| {
+			let entry = MusicEntry::create_music_response(entry);
+			Music {
+				id: entry.id,
+				title: entry.title,
+				artist: entry.artist,
+				image_url: entry.image_url,
+				..Music::new()
+			}
+		})
+		.collect()
+}
+
+// Shares the lobby state with the other instances when the realtime fan-out is on
+fn replicate(lobby: &Lobby) {
+	if realtime::enabled() {
+		realtime::publish(RealtimeEvent::LobbyUpsert {
+			lobby: Box::new(lobby.clone()),
+		});
+	}
+}
+
+// Sends the response to every connected client in the list listening to the topic
+fn broadcast(clients: &[String], topic: &Topic, response: &str, user_pool: &UserPool) {
+	for client in clients {
+		user_pool.send(client, topic, response.to_string());
+	}
+}
+
+// Local users have to exist in the db, remote ones have to be attached through federation
+fn member_exists(client_id: &str, db_pool: &DatabasePool, user_pool: &UserPool) -> bool {
+	if is_remote_member(client_id) {
+		return user_pool.exists(client_id);
+	}
+	user_exists(client_id, db_pool)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support::TestApp;
+
+	fn track(id: &str) -> Music {
+		Music {
+			id: id.to_string(),
+			state: MusicState::PLAY,
+			..Music::new()
+		}
+	}
+
+	#[tokio::test]
+	async fn dj_turns_pass_after_the_set_number_of_tracks() {
+		let test_app = TestApp::seeded();
+		let state = &test_app.app_state;
+		let (lobby_pool, user_pool) = (&state.lobby_pool, &state.user_pool);
+		let host_id = test_app.user_id("seed_user_0");
+		let guest_id = test_app.user_id("seed_user_1");
+		let lobby_id = lobby_pool.create_lobby(&host_id, &state.db_pool).unwrap()["lobby_id"]
+			.as_str()
+			.unwrap()
+			.to_string();
+		lobby_pool
+			.join_lobby(&lobby_id, &guest_id, &state.db_pool, user_pool)
+			.unwrap();
+
+		assert!(lobby_pool
+			.start_dj_rotation(&lobby_id, &guest_id, Some(2), None, user_pool)
+			.is_err());
+		assert!(lobby_pool
+			.start_dj_rotation(&lobby_id, &host_id, Some(2), Some(5), user_pool)
+			.is_err());
+		lobby_pool
+			.start_dj_rotation(&lobby_id, &host_id, Some(2), None, user_pool)
+			.unwrap();
+		let rotation = lobby_pool.set_dj_opt_in(&lobby_id, &guest_id, true, user_pool).unwrap();
+		assert_eq!(rotation.next_dj_id(), guest_id);
+
+		assert!(lobby_pool
+			.set_music_state(&lobby_id, &guest_id, track("a"), user_pool)
+			.is_err());
+		lobby_pool
+			.set_music_state(&lobby_id, &host_id, track("a"), user_pool)
+			.unwrap();
+		// Pausing and resuming the same track doesn't use up the turn
+		lobby_pool
+			.set_music_state(&lobby_id, &host_id, track("a"), user_pool)
+			.unwrap();
+		assert_eq!(lobby_pool.get(&lobby_id).unwrap().controller_id(), host_id);
+		lobby_pool
+			.set_music_state(&lobby_id, &host_id, track("b"), user_pool)
+			.unwrap();
+		assert_eq!(lobby_pool.get(&lobby_id).unwrap().controller_id(), guest_id);
+		assert!(lobby_pool
+			.set_music_state(&lobby_id, &host_id, track("c"), user_pool)
+			.is_err());
+
+		lobby_pool
+			.leave_lobby(&lobby_id, &guest_id, &state.db_pool, user_pool)
+			.unwrap();
+		assert_eq!(lobby_pool.get(&lobby_id).unwrap().controller_id(), host_id);
+		lobby_pool.stop_dj_rotation(&lobby_id, &host_id, user_pool).unwrap();
+		assert!(lobby_pool.get(&lobby_id).unwrap().dj_rotation.is_none());
+	}
+
+	#[tokio::test]
+	async fn sleep_timers_are_at_most_a_day_long() {
+		let test_app = TestApp::seeded();
+		let state = &test_app.app_state;
+		let host_id = test_app.user_id("seed_user_0");
+		let lobby_id = state.lobby_pool.create_lobby(&host_id, &state.db_pool).unwrap()["lobby_id"]
+			.as_str()
+			.unwrap()
+			.to_string();
+
+		for minutes in [0, MAX_SLEEP_TIMER_MINUTES + 1, u64::MAX] {
+			assert!(state
+				.lobby_pool
+				.set_sleep_timer(&lobby_id, &host_id, minutes, 10, &state.user_pool)
+				.is_err());
+		}
+		let timer = state
+			.lobby_pool
+			.set_sleep_timer(&lobby_id, &host_id, MAX_SLEEP_TIMER_MINUTES, u64::MAX, &state.user_pool)
+			.unwrap();
+		assert_eq!(timer.fade_secs, MAX_SLEEP_TIMER_MINUTES * 60);
+	}
+
+	#[tokio::test]
+	async fn dj_turns_are_at_most_a_day_long() {
+		let test_app = TestApp::seeded();
+		let state = &test_app.app_state;
+		let host_id = test_app.user_id("seed_user_0");
+		let lobby_id = state.lobby_pool.create_lobby(&host_id, &state.db_pool).unwrap()["lobby_id"]
+			.as_str()
+			.unwrap()
+			.to_string();
+
+		for minutes in [MAX_DJ_TURN_MINUTES + 1, u64::MAX] {
+			assert!(state
+				.lobby_pool
+				.start_dj_rotation(&lobby_id, &host_id, None, Some(minutes), &state.user_pool)
+				.is_err());
+		}
+		let rotation = state
+			.lobby_pool
+			.start_dj_rotation(&lobby_id, &host_id, None, Some(MAX_DJ_TURN_MINUTES), &state.user_pool)
+			.unwrap();
+		assert!(rotation.turn_ends_at.unwrap() > Utc::now().timestamp());
+	}
+
+	#[tokio::test]
+	async fn winning_poll_options_get_queued() {
+		let test_app = TestApp::seeded();
+		let state = &test_app.app_state;
+		let (lobby_pool, user_pool, db_pool) = (&state.lobby_pool, &state.user_pool, &state.db_pool);
+		let host_id = test_app.user_id("seed_user_0");
+		let guest_ids = [test_app.user_id("seed_user_1"), test_app.user_id("seed_user_2")];
+		let lobby_id = lobby_pool.create_lobby(&host_id, db_pool).unwrap()["lobby_id"]
+			.as_str()
+			.unwrap()
+			.to_string();
+		for guest_id in &guest_ids {
+			lobby_pool.join_lobby(&lobby_id, guest_id, db_pool, user_pool).unwrap();
+		}
+		let album = music::table
+			.select(music::album)
+			.first::<String>(&mut test_app.db_conn())
+			.unwrap();
+		let album_size = music::table
+			.filter(music::album.eq(&album))
+			.count()
+			.get_result::<i64>(&mut test_app.db_conn())
+			.unwrap();
+		// An audiobook sharing the album's name stays out of the queue
+		let book_id = music::table
+			.filter(music::album.ne(&album))
+			.select(music::music_id)
+			.first::<String>(&mut test_app.db_conn())
+			.unwrap();
+		diesel::update(music::table.find(&book_id))
+			.set((
+				music::album.eq(&album),
+				music::content_type.eq(ContentType::Audiobook.as_str()),
+			))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let new_poll = |options: Vec<PollOption>| NewPoll {
+			question: "Next?".to_string(),
+			options,
+			secs: 60,
+		};
+		let option = |label: &str, album: Option<&str>| PollOption {
+			label: label.to_string(),
+			music_id: None,
+			album: album.map(str::to_string),
+			voter_ids: Vec::new(),
+		};
+
+		let missing = vec![option("Nope", Some("No such album")), option("Skip", None)];
+		assert!(lobby_pool
+			.create_poll(&lobby_id, &host_id, new_poll(missing), db_pool, user_pool)
+			.is_err());
+		let options = vec![option(&album, Some(&album)), option("Something else", None)];
+		assert!(lobby_pool
+			.create_poll(&lobby_id, &guest_ids[0], new_poll(options.clone()), db_pool, user_pool)
+			.is_err());
+		let poll = lobby_pool
+			.create_poll(&lobby_id, &host_id, new_poll(options), db_pool, user_pool)
+			.unwrap();
+
+		lobby_pool
+			.vote_poll(&lobby_id, &guest_ids[0], &poll.id, 1, user_pool)
+			.unwrap();
+		lobby_pool
+			.vote_poll(&lobby_id, &guest_ids[1], &poll.id, 0, user_pool)
+			.unwrap();
+		// Changing the vote instead of voting twice
+		let poll = lobby_pool
+			.vote_poll(&lobby_id, &guest_ids[0], &poll.id, 0, user_pool)
+			.unwrap();
+		assert_eq!(Value::from(poll.clone())["options"][0]["votes"], 2);
+		assert_eq!(Value::from(poll.clone())["options"][1]["votes"], 0);
+
+		lobby_pool.close_poll(&lobby_id, &poll.id, db_pool, user_pool);
+		let lobby = lobby_pool.get(&lobby_id).unwrap();
+		assert_eq!(lobby.poll.unwrap().winner, Some(0));
+		assert_eq!(lobby.queue.len() as i64, album_size);
+		assert!(lobby.queue.iter().all(|queued| queued.id != book_id));
+		assert!(lobby_pool
+			.vote_poll(&lobby_id, &host_id, &poll.id, 1, user_pool)
+			.is_err());
+	}
+
+	#[tokio::test]
+	async fn reactions_are_rate_limited_and_can_be_turned_off() {
+		let test_app = TestApp::seeded();
+		let state = &test_app.app_state;
+		let (lobby_pool, user_pool) = (&state.lobby_pool, &state.user_pool);
+		let host_id = test_app.user_id("seed_user_0");
+		let guest_id = test_app.user_id("seed_user_1");
+		let lobby_id = lobby_pool.create_lobby(&host_id, &state.db_pool).unwrap()["lobby_id"]
+			.as_str()
+			.unwrap()
+			.to_string();
+		lobby_pool
+			.join_lobby(&lobby_id, &guest_id, &state.db_pool, user_pool)
+			.unwrap();
+
+		for _ in 0..REACTION_LIMIT {
+			lobby_pool
+				.react(&lobby_id, &guest_id, Reaction::Airhorn, user_pool)
+				.unwrap();
+		}
+		assert!(lobby_pool
+			.react(&lobby_id, &guest_id, Reaction::Fire, user_pool)
+			.is_err());
+		lobby_pool
+			.react(&lobby_id, &host_id, Reaction::Fire, user_pool)
+			.unwrap();
+
+		assert!(lobby_pool
+			.set_reactions(&lobby_id, &guest_id, false, user_pool)
+			.is_err());
+		lobby_pool.set_reactions(&lobby_id, &host_id, false, user_pool).unwrap();
+		assert!(lobby_pool
+			.react(&lobby_id, &host_id, Reaction::Clap, user_pool)
+			.is_err());
+		assert_eq!(Reaction::parse("airhorn"), Some(Reaction::Airhorn));
+		assert_eq!(Reaction::parse("explosion"), None);
+	}
+
+	#[tokio::test]
+	async fn family_filter_keeps_explicit_tracks_out() {
+		let test_app = TestApp::seeded();
+		let state = &test_app.app_state;
+		let (lobby_pool, user_pool, db_pool) = (&state.lobby_pool, &state.user_pool, &state.db_pool);
+		let host_id = test_app.user_id("seed_user_0");
+		let lobby_id = lobby_pool.create_lobby(&host_id, db_pool).unwrap()["lobby_id"]
+			.as_str()
+			.unwrap()
+			.to_string();
+		let explicit_id = music::table
+			.filter(music::explicit.eq(true))
+			.select(music::music_id)
+			.first::<String>(&mut test_app.db_conn())
+			.unwrap();
+		let clean_id = music::table
+			.filter(music::explicit.eq(false))
+			.select(music::music_id)
+			.first::<String>(&mut test_app.db_conn())
+			.unwrap();
+		let ids = [clean_id.clone(), explicit_id.clone()];
+
+		lobby_pool.check_family_filter(&lobby_id, &ids, db_pool).unwrap();
+		lobby_pool
+			.set_family_filter(&lobby_id, &host_id, true, user_pool)
+			.unwrap();
+		let err = lobby_pool.check_family_filter(&lobby_id, &ids, db_pool).unwrap_err();
+		assert!(err.contains(&explicit_id));
+		lobby_pool
+			.check_family_filter(&lobby_id, std::slice::from_ref(&clean_id), db_pool)
+			.unwrap();
+
+		let option = |music_id: &str| PollOption {
+			label: music_id.to_string(),
+			music_id: Some(music_id.to_string()),
+			album: None,
+			voter_ids: Vec::new(),
+		};
+		let new_poll = NewPoll {
+			question: "Next?".to_string(),
+			options: vec![option(&clean_id), option(&explicit_id)],
+			secs: 60,
+		};
+		assert!(lobby_pool
+			.create_poll(&lobby_id, &host_id, new_poll, db_pool, user_pool)
+			.is_err());
+	}
 }
@@ -0,0 +1,126 @@
+use crate::config::{OpCode, SocketResponse};
+use crate::core::app_state::AppState;
+use crate::core::user_pool::Topic;
+use crate::i18n::Locale;
+use crate::lobic_db::db::{get_instance_setting, set_instance_setting, user_is_admin};
+use crate::schema::instance_settings;
+use crate::utils::auth::session_user_id;
+
+use axum::{
+	body::Body,
+	extract::{Request, State},
+	http::{header, StatusCode},
+	middleware::Next,
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+// Key in instance_settings, holds the Maintenance as json while the instance is down
+pub const MAINTENANCE: &str = "maintenance";
+
+// Left reachable so the admins can log in and the clients can tell what is going on
+const OPEN_PATHS: [&str; 4] = ["/login", "/logout", "/api/capabilities", "/instance/info"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Maintenance {
+	pub message: String,
+	pub eta: Option<String>, // rfc3339, when the instance is expected back
+	pub started_date_time: String,
+}
+
+impl Maintenance {
+	// Seconds until the eta, for Retry-After
+	fn retry_after(&self) -> Option<i64> {
+		let eta = DateTime::parse_from_rfc3339(self.eta.as_deref()?).ok()?;
+		Some((eta.with_timezone(&Utc) - Utc::now()).num_seconds().max(1))
+	}
+}
+
+pub fn current(db_conn: &mut SqliteConnection) -> Option<Maintenance> {
+	get_instance_setting(MAINTENANCE, db_conn).and_then(|value| serde_json::from_str(&value).ok())
+}
+
+// Starts maintenance, or updates the message and eta of the ongoing one, and tells the connected clients
+pub fn enable(app_state: &AppState, message: String, eta: Option<String>) -> Result<Maintenance, String> {
+	let mut db_conn = app_state
+		.db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+	let started_date_time = current(&mut db_conn)
+		.map(|maintenance| maintenance.started_date_time)
+		.unwrap_or_else(|| Utc::now().to_rfc3339());
+	let maintenance = Maintenance {
+		message,
+		eta,
+		started_date_time,
+	};
+	set_instance_setting(MAINTENANCE, &serde_json::to_string(&maintenance).unwrap(), &mut db_conn)
+		.map_err(|err| format!("Failed to save the setting: {err}"))?;
+
+	announce(app_state, json!({ "enabled": true, "maintenance": maintenance }));
+	Ok(maintenance)
+}
+
+pub fn disable(app_state: &AppState) -> Result<bool, String> {
+	let mut db_conn = app_state
+		.db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+	let removed = diesel::delete(instance_settings::table.filter(instance_settings::key.eq(MAINTENANCE)))
+		.execute(&mut db_conn)
+		.map_err(|err| format!("Failed to clear the setting: {err}"))?;
+	if removed > 0 {
+		announce(app_state, json!({ "enabled": false }));
+	}
+	Ok(removed > 0)
+}
+
+fn announce(app_state: &AppState, value: serde_json::Value) {
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::MAINTENANCE,
+		value,
+	}
+	.to_string();
+	app_state.user_pool.broadcast(&Topic::Notifications, &response);
+}
+
+// Turns away everyone but the admins with a 503 while in maintenance. Responses already on their
+// way out, like a track being streamed, aren't touched and get to finish.
+pub async fn guard(State(app_state): State<AppState>, req: Request<Body>, next: Next) -> Response {
+	if OPEN_PATHS.contains(&req.uri().path()) {
+		return next.run(req).await;
+	}
+	let Ok(mut db_conn) = app_state.db_pool.get() else {
+		return next.run(req).await;
+	};
+	let Some(maintenance) = current(&mut db_conn) else {
+		drop(db_conn);
+		return next.run(req).await;
+	};
+	drop(db_conn);
+
+	let jar = CookieJar::from_headers(req.headers());
+	if session_user_id(&jar).is_some_and(|user_id| user_is_admin(&user_id, &app_state.db_pool)) {
+		return next.run(req).await;
+	}
+
+	let locale = Locale::negotiate(req.headers(), &jar, &app_state.db_pool);
+	let body = json!({
+		"maintenance": true,
+		"message": if maintenance.message.is_empty() { locale.t("maintenance.message") } else { maintenance.message.clone() },
+		"eta": maintenance.eta,
+		"since": maintenance.started_date_time,
+	});
+	let mut response = Response::builder()
+		.status(StatusCode::SERVICE_UNAVAILABLE)
+		.header(header::CONTENT_TYPE, "application/json");
+	if let Some(secs) = maintenance.retry_after() {
+		response = response.header(header::RETRY_AFTER, secs);
+	}
+	response.body(Body::from(body.to_string())).unwrap()
+}
@@ -1,6 +1,44 @@
+pub mod achievements;
+pub mod analytics;
 pub mod app_state;
+pub mod artist_images;
+pub mod artwork;
+pub mod audio_analysis;
+pub mod body_limit;
+pub mod client_errors;
+pub mod curation_import;
+pub mod doctor;
+pub mod event_bus;
+pub mod federation;
+pub mod goals;
+pub mod imports;
+pub mod instance;
+pub mod ip_filter;
+pub mod leaderboard;
 pub mod lobby;
+pub mod maintenance;
 pub mod migrations;
+pub mod mpd;
+pub mod new_releases;
+pub mod now_playing;
+pub mod oauth;
+pub mod on_this_day;
+pub mod outbox;
+pub mod play_rule;
+pub mod playlist_releases;
+pub mod query_log;
+pub mod queue_snapshots;
+pub mod quotas;
+pub mod realtime;
+pub mod recommendations;
+pub mod retention;
+pub mod rollups;
 pub mod routes;
+pub mod scheduler;
 pub mod server;
+pub mod telemetry;
+pub mod tokens;
+pub mod transcode_cache;
 pub mod user_pool;
+pub mod webhooks;
+pub mod weekly_email;
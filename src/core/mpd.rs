@@ -0,0 +1,772 @@
+use crate::config::{MusicState, OpCode, SocketResponse};
+use crate::core::app_state::AppState;
+use crate::core::lobby::{Lobby, Music};
+use crate::core::user_pool::Topic;
+use crate::lobic_db::db::unavailable_music_ids;
+use crate::lobic_db::models::{Availability, ContentType, Music as MusicEntry, MusicResponse, ScrobbleToken};
+use crate::schema::{music, scrobble_tokens};
+
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream};
+
+// A subset of the MPD protocol over the queue of the lobby the user hosts, so console clients like
+// ncmpcpp can drive it. The MPD playlist is the track playing in the lobby followed by its queue.
+
+const GREETING: &str = "OK MPD 0.23.0\n";
+const IDLE_POLL: Duration = Duration::from_millis(500);
+const MAX_SEARCH_RESULTS: i64 = 200;
+// Clients sending longer lines or lists are dropped, nothing the bridge knows needs more
+const MAX_LINE_BYTES: usize = 4096;
+const MAX_COMMAND_LIST: usize = 256;
+
+// MPD's error codes, the ones the bridge runs into
+const ACK_ARG: u32 = 2;
+const ACK_PASSWORD: u32 = 3;
+const ACK_PERMISSION: u32 = 4;
+const ACK_UNKNOWN: u32 = 5;
+const ACK_NO_EXIST: u32 = 50;
+const ACK_SYSTEM: u32 = 52;
+
+const COMMANDS: &[&str] = &[
+	"add",
+	"addid",
+	"close",
+	"commands",
+	"currentsong",
+	"find",
+	"idle",
+	"next",
+	"noidle",
+	"password",
+	"pause",
+	"ping",
+	"play",
+	"playid",
+	"playlistinfo",
+	"search",
+	"status",
+	"stop",
+];
+
+pub fn enabled() -> bool {
+	std::env::var("MPD_BRIDGE_ADDR").is_ok_and(|addr| !addr.is_empty())
+}
+
+// Listens on MPD_BRIDGE_ADDR (e.g. 127.0.0.1:6600) when it is set
+pub fn start(app_state: AppState) {
+	if !enabled() {
+		return;
+	}
+	let addr = std::env::var("MPD_BRIDGE_ADDR").unwrap();
+
+	tokio::spawn(async move {
+		let listener = match TcpListener::bind(&addr).await {
+			Ok(listener) => listener,
+			Err(err) => {
+				eprintln!("Failed to start the MPD bridge on {addr}: {err}");
+				return;
+			}
+		};
+		println!("MPD bridge listening on {addr}");
+
+		loop {
+			let stream = match listener.accept().await {
+				Ok((stream, _)) => stream,
+				Err(err) => {
+					eprintln!("MPD bridge failed to accept a client: {err}");
+					continue;
+				}
+			};
+			let app_state = app_state.clone();
+			tokio::spawn(async move {
+				if let Err(err) = serve(stream, app_state).await {
+					eprintln!("MPD client dropped: {err}");
+				}
+			});
+		}
+	});
+}
+
+async fn serve(stream: TcpStream, app_state: AppState) -> std::io::Result<()> {
+	let (reader, mut writer) = stream.into_split();
+	let mut lines = LineReader::new(reader);
+	writer.write_all(GREETING.as_bytes()).await?;
+
+	let mut session = Session::new(app_state);
+	// The commands of an open command list, and whether each one gets a list_OK
+	let mut command_list: Option<(Vec<String>, bool)> = None;
+	while let Some(line) = lines.next_line().await? {
+		let line = line.trim_end().to_string();
+		let reply = match (line.as_str(), command_list.as_mut()) {
+			("command_list_begin", None) => {
+				command_list = Some((Vec::new(), false));
+				continue;
+			}
+			("command_list_ok_begin", None) => {
+				command_list = Some((Vec::new(), true));
+				continue;
+			}
+			("command_list_end", Some(_)) => {
+				let (commands, list_ok) = command_list.take().unwrap();
+				session.run_list(&commands, list_ok)
+			}
+			(_, Some((commands, _))) if commands.len() >= MAX_COMMAND_LIST => {
+				let msg = format!("Command list longer than {MAX_COMMAND_LIST} commands");
+				return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg));
+			}
+			(_, Some((commands, _))) => {
+				commands.push(line);
+				continue;
+			}
+			("close", None) => return Ok(()),
+			("idle", None) => idle(&session, &mut lines).await?,
+			(_, None) if line.starts_with("idle ") => idle(&session, &mut lines).await?,
+			(_, None) => session.run(&line),
+		};
+		writer.write_all(reply.as_bytes()).await?;
+	}
+	Ok(())
+}
+
+// Lines of at most MAX_LINE_BYTES. What was read of a line is kept between calls, so a call can
+// be dropped in a select like the one of idle.
+struct LineReader {
+	reader: BufReader<OwnedReadHalf>,
+	line: Vec<u8>,
+}
+
+impl LineReader {
+	fn new(reader: OwnedReadHalf) -> LineReader {
+		LineReader {
+			reader: BufReader::new(reader),
+			line: Vec::new(),
+		}
+	}
+
+	async fn next_line(&mut self) -> std::io::Result<Option<String>> {
+		loop {
+			if self.line.len() > MAX_LINE_BYTES {
+				let msg = format!("Line longer than {MAX_LINE_BYTES} bytes");
+				return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg));
+			}
+			let limit = (MAX_LINE_BYTES + 1 - self.line.len()) as u64;
+			let read = (&mut self.reader).take(limit).read_until(b'\n', &mut self.line).await?;
+			if read > 0 && !self.line.ends_with(b"\n") {
+				continue;
+			}
+			if self.line.is_empty() {
+				return Ok(None);
+			}
+
+			let line = std::mem::take(&mut self.line);
+			return String::from_utf8(line)
+				.map(|line| Some(line.trim_end_matches(['\n', '\r']).to_string()))
+				.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err));
+		}
+	}
+}
+
+// Waits for the lobby to change, or for the client to send noidle
+async fn idle(session: &Session, lines: &mut LineReader) -> std::io::Result<String> {
+	let before = session.snapshot();
+	loop {
+		tokio::select! {
+			line = lines.next_line() => {
+				return Ok(match line? {
+					Some(_) => "OK\n".to_string(),
+					None => String::new(),
+				});
+			}
+			_ = tokio::time::sleep(IDLE_POLL) => {
+				let changed = session.changes(&before);
+				if !changed.is_empty() {
+					let changed: String = changed.iter().map(|subsystem| format!("changed: {subsystem}\n")).collect();
+					return Ok(changed + "OK\n");
+				}
+			}
+		}
+	}
+}
+
+#[derive(Debug, PartialEq)]
+struct Ack {
+	code: u32,
+	message: String,
+}
+
+impl Ack {
+	fn new(code: u32, message: impl Into<String>) -> Ack {
+		Ack {
+			code,
+			message: message.into(),
+		}
+	}
+}
+
+// What idle compares: the track, its state and the queue
+#[derive(Debug, PartialEq)]
+struct Snapshot {
+	music_id: String,
+	state: &'static str,
+	queue: Vec<String>,
+}
+
+pub struct Session {
+	app_state: AppState,
+	user_id: Option<String>, // set by a password command with an mpd token
+}
+
+impl Session {
+	pub fn new(app_state: AppState) -> Session {
+		Session {
+			app_state,
+			user_id: None,
+		}
+	}
+
+	// The reply to a single command line, ending in OK or an ACK
+	pub fn run(&mut self, line: &str) -> String {
+		match self.execute(line) {
+			Ok(reply) => reply + "OK\n",
+			Err(ack) => ack_line(&ack, 0, line),
+		}
+	}
+
+	// Runs the commands until one fails, like MPD does
+	pub fn run_list(&mut self, commands: &[String], list_ok: bool) -> String {
+		let mut reply = String::new();
+		for (index, line) in commands.iter().enumerate() {
+			match self.execute(line) {
+				Ok(output) => {
+					reply += &output;
+					if list_ok {
+						reply += "list_OK\n";
+					}
+				}
+				Err(ack) => return reply + &ack_line(&ack, index, line),
+			}
+		}
+		reply + "OK\n"
+	}
+
+	fn execute(&mut self, line: &str) -> Result<String, Ack> {
+		let words = tokenize(line).ok_or_else(|| Ack::new(ACK_ARG, "Invalid quoting"))?;
+		let Some((command, args)) = words.split_first() else {
+			return Err(Ack::new(ACK_UNKNOWN, "No command given"));
+		};
+
+		match command.as_str() {
+			"ping" => Ok(String::new()),
+			"password" => self.password(args),
+			"commands" => Ok(COMMANDS.iter().map(|command| format!("command: {command}\n")).collect()),
+			// Clients ask for these on connect, there is nothing to list
+			"notcommands" | "tagtypes" | "outputs" | "decoders" | "urlhandlers" => Ok(String::new()),
+			_ if self.user_id.is_none() => Err(Ack::new(
+				ACK_PERMISSION,
+				format!("you don't have permission for \"{command}\""),
+			)),
+			"status" => self.status(),
+			"currentsong" => {
+				let lobby = self.lobby()?;
+				let current = playlist(&lobby).into_iter().take(current_offset(&lobby)).collect();
+				self.songs(current)
+			}
+			"playlistinfo" => self.songs(playlist(&self.lobby()?)),
+			"play" => self.play(position_arg(args.first(), 0)?),
+			"playid" => self.play(position_arg(args.first(), 1)?),
+			"next" => {
+				let lobby = self.lobby()?;
+				if lobby.queue.is_empty() {
+					return Err(Ack::new(ACK_NO_EXIST, "No next song"));
+				}
+				self.play(Some(current_offset(&lobby)))
+			}
+			"pause" => match args.first().map(String::as_str) {
+				Some("1") => self.set_state(MusicState::PAUSE),
+				Some("0") => self.set_state(MusicState::PLAY),
+				Some(_) => Err(Ack::new(ACK_ARG, "Boolean (0/1) expected")),
+				None if playback_state(&self.lobby()?) == "play" => self.set_state(MusicState::PAUSE),
+				None => self.set_state(MusicState::PLAY),
+			},
+			"stop" => self.set_state(MusicState::PAUSE),
+			"add" => self.add(args).map(|_| String::new()),
+			"addid" => self.add(args).map(|id| format!("Id: {id}\n")),
+			"search" => self.search(args, false),
+			"find" => self.search(args, true),
+			_ => Err(Ack::new(ACK_UNKNOWN, format!("unknown command \"{command}\""))),
+		}
+	}
+
+	fn password(&mut self, args: &[String]) -> Result<String, Ack> {
+		let [token] = args else {
+			return Err(Ack::new(ACK_ARG, "wrong number of arguments for \"password\""));
+		};
+		let mut db_conn = self.db_conn()?;
+		let user_id = scrobble_tokens::table
+			.filter(scrobble_tokens::token.eq(token))
+			.filter(scrobble_tokens::scope.eq(ScrobbleToken::MPD))
+			.select(scrobble_tokens::user_id)
+			.first::<String>(&mut db_conn)
+			.optional()
+			.map_err(|err| Ack::new(ACK_SYSTEM, format!("Database error: {err}")))?
+			.ok_or_else(|| Ack::new(ACK_PASSWORD, "incorrect password"))?;
+		let _ = diesel::update(scrobble_tokens::table.filter(scrobble_tokens::token.eq(token)))
+			.set(scrobble_tokens::last_used_date_time.eq(Utc::now().to_rfc3339()))
+			.execute(&mut db_conn);
+
+		self.user_id = Some(user_id);
+		Ok(String::new())
+	}
+
+	fn status(&self) -> Result<String, Ack> {
+		let lobby = self.lobby()?;
+		let playlist = playlist(&lobby);
+		let mut version = DefaultHasher::new();
+		playlist.iter().for_each(|music| music.id.hash(&mut version));
+
+		let state = playback_state(&lobby);
+		let mut status = format!(
+			"volume: -1\nrepeat: 0\nrandom: 0\nsingle: 0\nconsume: 1\nplaylist: {}\nplaylistlength: {}\nstate: {state}\n",
+			version.finish() as u32,
+			playlist.len(),
+		);
+		if state != "stop" {
+			let duration = self
+				.tracks(std::slice::from_ref(&lobby.music.id))?
+				.get(&lobby.music.id)
+				.map_or(0, |track| track.duration);
+			status += &format!(
+				"song: 0\nsongid: 1\ntime: {}:{duration}\nelapsed: {:.3}\nduration: {duration}\n",
+				lobby.music.timestamp as i64, lobby.music.timestamp,
+			);
+		}
+		if playlist.len() > current_offset(&lobby) {
+			let next = current_offset(&lobby);
+			status += &format!("nextsong: {next}\nnextsongid: {}\n", next + 1);
+		}
+		Ok(status)
+	}
+
+	// Plays the track at the position: the one playing resumes, a queued one is taken out of the queue
+	fn play(&self, position: Option<usize>) -> Result<String, Ack> {
+		let lobby = self.lobby()?;
+		let offset = current_offset(&lobby);
+		let position = position.unwrap_or(0);
+		if position == 0 && offset == 1 {
+			return self.set_state(MusicState::PLAY);
+		}
+
+		let mut queue = lobby.queue.clone();
+		if position < offset || position - offset >= queue.len() {
+			return Err(Ack::new(ACK_ARG, "Bad song index"));
+		}
+		let mut music = queue.remove(position - offset);
+		let unavailable = unavailable_music_ids(std::slice::from_ref(&music.id), &self.app_state.db_pool)
+			.map_err(|err| Ack::new(ACK_SYSTEM, err))?;
+		if !unavailable.is_empty() {
+			return Err(Ack::new(ACK_NO_EXIST, format!("Music {} is not available", music.id)));
+		}
+		music.timestamp = 0.0;
+		music.state = MusicState::CHANGE_MUSIC;
+
+		self.change(&lobby, music)?;
+		self.app_state
+			.lobby_pool
+			.set_queue(&lobby.id, queue)
+			.map_err(|err| Ack::new(ACK_SYSTEM, err))?;
+		self.sync(&lobby.id, OpCode::SYNC_QUEUE, |lobby| lobby.queue.clone().into());
+		Ok(String::new())
+	}
+
+	fn set_state(&self, state: MusicState) -> Result<String, Ack> {
+		let lobby = self.lobby()?;
+		if lobby.music.id.is_empty() {
+			return match state {
+				MusicState::PLAY => self.play(Some(0)),
+				_ => Ok(String::new()),
+			};
+		}
+		let music = Music {
+			state,
+			..lobby.music.clone()
+		};
+		self.change(&lobby, music)?;
+		Ok(String::new())
+	}
+
+	fn change(&self, lobby: &Lobby, music: Music) -> Result<(), Ack> {
+		let user_id = self.user_id.as_deref().unwrap_or_default();
+		self.app_state
+			.lobby_pool
+			.set_music_state(&lobby.id, user_id, music, &self.app_state.user_pool)
+			.map_err(|err| Ack::new(ACK_PERMISSION, err))?;
+		self.sync(&lobby.id, OpCode::SYNC_MUSIC, |lobby| lobby.music.clone().into());
+		Ok(())
+	}
+
+	// Queues the track, returning its song id
+	fn add(&self, args: &[String]) -> Result<usize, Ack> {
+		let [music_id] = args else {
+			return Err(Ack::new(ACK_ARG, "wrong number of arguments for \"add\""));
+		};
+		let lobby = self.lobby()?;
+		let unavailable = unavailable_music_ids(std::slice::from_ref(music_id), &self.app_state.db_pool)
+			.map_err(|err| Ack::new(ACK_SYSTEM, err))?;
+		let track = self
+			.tracks(std::slice::from_ref(music_id))?
+			.remove(music_id)
+			.filter(|_| unavailable.is_empty())
+			.ok_or_else(|| Ack::new(ACK_NO_EXIST, "No such song"))?;
+		self.app_state
+			.lobby_pool
+			.check_family_filter(&lobby.id, std::slice::from_ref(music_id), &self.app_state.db_pool)
+			.map_err(|err| Ack::new(ACK_PERMISSION, err))?;
+
+		let mut queue = lobby.queue.clone();
+		queue.push(Music {
+			id: track.id,
+			title: track.title,
+			artist: track.artist,
+			image_url: track.image_url,
+			timestamp: 0.0,
+			state: MusicState::PAUSE,
+		});
+		let length = queue.len() + current_offset(&lobby);
+		self.app_state
+			.lobby_pool
+			.set_queue(&lobby.id, queue)
+			.map_err(|err| Ack::new(ACK_SYSTEM, err))?;
+		self.sync(&lobby.id, OpCode::SYNC_QUEUE, |lobby| lobby.queue.clone().into());
+		Ok(length)
+	}
+
+	// Tag and value pairs, any/title/artist/album/file. find matches exactly, search case-insensitively in part.
+	fn search(&self, args: &[String], exact: bool) -> Result<String, Ack> {
+		if args.is_empty() || !args.len().is_multiple_of(2) {
+			return Err(Ack::new(ACK_ARG, "Incorrect number of filter arguments"));
+		}
+
+		let mut query = music::table
+			.filter(music::availability.eq(Availability::Available.as_str()))
+			.filter(music::content_type.eq(ContentType::Music.as_str()))
+			.into_boxed();
+		for pair in args.chunks(2) {
+			let value = if exact {
+				pair[1].clone()
+			} else {
+				format!("%{}%", pair[1])
+			};
+			query = match (pair[0].to_lowercase().as_str(), exact) {
+				("file", _) => query.filter(music::music_id.eq(pair[1].clone())),
+				("title", true) => query.filter(music::title.eq(value)),
+				("title", false) => query.filter(music::title.like(value)),
+				("artist", true) => query.filter(music::artist.eq(value)),
+				("artist", false) => query.filter(music::artist.like(value)),
+				("album", true) => query.filter(music::album.eq(value)),
+				("album", false) => query.filter(music::album.like(value)),
+				("any", true) => query.filter(
+					music::title
+						.eq(value.clone())
+						.or(music::artist.eq(value.clone()))
+						.or(music::album.eq(value)),
+				),
+				("any", false) => query.filter(
+					music::title
+						.like(value.clone())
+						.or(music::artist.like(value.clone()))
+						.or(music::album.like(value)),
+				),
+				(tag, _) => return Err(Ack::new(ACK_ARG, format!("Unknown filter type: {tag}"))),
+			};
+		}
+
+		let mut db_conn = self.db_conn()?;
+		let tracks = query
+			.order(music::title.asc())
+			.limit(MAX_SEARCH_RESULTS)
+			.load::<MusicEntry>(&mut db_conn)
+			.map_err(|err| Ack::new(ACK_SYSTEM, format!("Database error: {err}")))?;
+		Ok(tracks
+			.into_iter()
+			.map(|track| song_block(&MusicEntry::create_music_response(track)))
+			.collect())
+	}
+
+	// The playlist entries with their positions and ids
+	fn songs(&self, playlist: Vec<Music>) -> Result<String, Ack> {
+		let ids: Vec<String> = playlist.iter().map(|music| music.id.clone()).collect();
+		let tracks = self.tracks(&ids)?;
+		Ok(playlist
+			.iter()
+			.enumerate()
+			.map(|(position, music)| {
+				let block = match tracks.get(&music.id) {
+					Some(track) => song_block(track),
+					None => format!("file: {}\nTitle: {}\nArtist: {}\n", music.id, music.title, music.artist),
+				};
+				format!("{block}Pos: {position}\nId: {}\n", position + 1)
+			})
+			.collect())
+	}
+
+	fn tracks(&self, ids: &[String]) -> Result<HashMap<String, MusicResponse>, Ack> {
+		let mut db_conn = self.db_conn()?;
+		let tracks = music::table
+			.filter(music::music_id.eq_any(ids))
+			.load::<MusicEntry>(&mut db_conn)
+			.map_err(|err| Ack::new(ACK_SYSTEM, format!("Database error: {err}")))?;
+		Ok(tracks
+			.into_iter()
+			.map(|track| (track.music_id.clone(), MusicEntry::create_music_response(track)))
+			.collect())
+	}
+
+	fn lobby(&self) -> Result<Lobby, Ack> {
+		let user_id = self.user_id.as_deref().unwrap_or_default();
+		self.app_state
+			.lobby_pool
+			.hosted_by(user_id)
+			.ok_or_else(|| Ack::new(ACK_NO_EXIST, "You are not hosting a lobby"))
+	}
+
+	fn db_conn(&self) -> Result<PooledConnection<ConnectionManager<SqliteConnection>>, Ack> {
+		self.app_state
+			.db_pool
+			.get()
+			.map_err(|err| Ack::new(ACK_SYSTEM, format!("Failed to get DB from pool: {err}")))
+	}
+
+	// Tells everyone in the lobby, the host's browser included, what the bridge changed
+	fn sync(&self, lobby_id: &str, r#for: OpCode, value: impl Fn(&Lobby) -> Value) {
+		let Some(lobby) = self.app_state.lobby_pool.get(lobby_id) else {
+			return;
+		};
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for,
+			value: value(&lobby),
+		}
+		.to_string();
+		for client_id in &lobby.clients {
+			self.app_state
+				.user_pool
+				.send(client_id, &Topic::lobby(lobby_id), response.clone());
+		}
+	}
+
+	fn snapshot(&self) -> Option<Snapshot> {
+		let lobby = self.lobby().ok()?;
+		Some(Snapshot {
+			music_id: lobby.music.id.clone(),
+			state: playback_state(&lobby),
+			queue: lobby.queue.iter().map(|music| music.id.clone()).collect(),
+		})
+	}
+
+	// The MPD subsystems that changed since the snapshot
+	fn changes(&self, before: &Option<Snapshot>) -> Vec<&'static str> {
+		let after = self.snapshot();
+		let mut changed = Vec::new();
+		match (before, &after) {
+			(Some(before), Some(after)) => {
+				if before.music_id != after.music_id || before.state != after.state {
+					changed.push("player");
+				}
+				if before.music_id != after.music_id || before.queue != after.queue {
+					changed.push("playlist");
+				}
+			}
+			(None, None) => {}
+			_ => changed.extend(["player", "playlist"]),
+		}
+		changed
+	}
+}
+
+fn ack_line(ack: &Ack, index: usize, line: &str) -> String {
+	let command = line.split_whitespace().next().unwrap_or_default();
+	format!("ACK [{}@{index}] {{{command}}} {}\n", ack.code, ack.message)
+}
+
+// Splits the line into words, double quoted ones may hold spaces and backslash escapes
+fn tokenize(line: &str) -> Option<Vec<String>> {
+	let mut words = Vec::new();
+	let mut chars = line.trim().chars().peekable();
+	while let Some(&c) = chars.peek() {
+		if c.is_whitespace() {
+			chars.next();
+			continue;
+		}
+		let mut word = String::new();
+		if c == '"' {
+			chars.next();
+			loop {
+				match chars.next()? {
+					'"' => break,
+					'\\' => word.push(chars.next()?),
+					c => word.push(c),
+				}
+			}
+		} else {
+			while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+				word.push(c);
+			}
+		}
+		words.push(word);
+	}
+	Some(words)
+}
+
+// Positions start at 0 and song ids at 1, the id is the position shifted by one
+fn position_arg(arg: Option<&String>, first: usize) -> Result<Option<usize>, Ack> {
+	match arg {
+		None => Ok(None),
+		Some(arg) => arg
+			.parse::<usize>()
+			.ok()
+			.and_then(|value| value.checked_sub(first))
+			.map(Some)
+			.ok_or_else(|| Ack::new(ACK_ARG, format!("Integer expected: {arg}"))),
+	}
+}
+
+fn current_offset(lobby: &Lobby) -> usize {
+	usize::from(!lobby.music.id.is_empty())
+}
+
+fn playlist(lobby: &Lobby) -> Vec<Music> {
+	let current = Some(lobby.music.clone()).filter(|music| !music.id.is_empty());
+	current.into_iter().chain(lobby.queue.iter().cloned()).collect()
+}
+
+fn playback_state(lobby: &Lobby) -> &'static str {
+	match lobby.music.state {
+		_ if lobby.music.id.is_empty() => "stop",
+		MusicState::PLAY | MusicState::CHANGE_MUSIC => "play",
+		_ => "pause",
+	}
+}
+
+fn song_block(track: &MusicResponse) -> String {
+	format!(
+		"file: {}\nTitle: {}\nArtist: {}\nAlbum: {}\nGenre: {}\nTime: {}\nduration: {}\n",
+		track.id, track.title, track.artist, track.album, track.genre, track.duration, track.duration
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support::TestApp;
+
+	#[tokio::test]
+	async fn mpd_clients_drive_the_hosts_queue() {
+		let test_app = TestApp::seeded();
+		let app_state = test_app.app_state.clone();
+		let user_id = test_app.user_id("seed_user_0");
+		let mut db_conn = test_app.db_conn();
+		let tracks = music::table
+			.filter(music::content_type.eq(ContentType::Music.as_str()))
+			.order(music::title.asc())
+			.limit(2)
+			.load::<MusicEntry>(&mut db_conn)
+			.unwrap();
+		let ids: Vec<String> = tracks.iter().map(|track| track.music_id.clone()).collect();
+		diesel::insert_into(scrobble_tokens::table)
+			.values(ScrobbleToken {
+				token_id: "mpd-token".to_string(),
+				user_id: user_id.clone(),
+				name: "ncmpcpp".to_string(),
+				token: "secret".to_string(),
+				created_date_time: Utc::now().to_rfc3339(),
+				last_used_date_time: None,
+				scope: ScrobbleToken::MPD.to_string(),
+			})
+			.execute(&mut db_conn)
+			.unwrap();
+		app_state.lobby_pool.create_lobby(&user_id, &app_state.db_pool).unwrap();
+
+		let mut session = Session::new(app_state.clone());
+		assert_eq!(
+			session.run("status"),
+			"ACK [4@0] {status} you don't have permission for \"status\"\n"
+		);
+		assert_eq!(
+			session.run("password wrong"),
+			"ACK [3@0] {password} incorrect password\n"
+		);
+		assert_eq!(session.run("password \"secret\""), "OK\n");
+		assert!(session.run("status").contains("playlistlength: 0\nstate: stop\n"));
+
+		let title = tracks[0].title.replace('"', "\\\"");
+		let found = session.run(&format!("search title \"{title}\""));
+		assert!(found.contains(&format!("file: {}\n", ids[0])), "{found}");
+		assert_eq!(session.run(&format!("addid {}", ids[0])), "Id: 1\nOK\n");
+		assert_eq!(session.run(&format!("add {}", ids[1])), "OK\n");
+		assert_eq!(session.run("add missing"), "ACK [50@0] {add} No such song\n");
+
+		assert_eq!(session.run("play"), "OK\n");
+		let lobby = app_state.lobby_pool.hosted_by(&user_id).unwrap();
+		assert_eq!(lobby.music.id, ids[0]);
+		assert_eq!(lobby.queue.len(), 1);
+		let status = session.run("status");
+		assert!(status.contains("playlistlength: 2\nstate: play\nsong: 0\n"), "{status}");
+
+		assert_eq!(session.run("next"), "OK\n");
+		assert_eq!(session.run("pause 1"), "OK\n");
+		let lobby = app_state.lobby_pool.hosted_by(&user_id).unwrap();
+		assert_eq!(lobby.music.id, ids[1]);
+		assert_eq!(lobby.music.state, MusicState::PAUSE);
+		assert!(lobby.queue.is_empty());
+		let playlist = session.run("playlistinfo");
+		assert!(playlist.starts_with(&format!("file: {}\n", ids[1])), "{playlist}");
+		assert!(playlist.ends_with("Pos: 0\nId: 1\nOK\n"), "{playlist}");
+		assert_eq!(session.run("next"), "ACK [50@0] {next} No next song\n");
+
+		let commands = ["ping".to_string(), "bogus".to_string(), "ping".to_string()];
+		assert_eq!(
+			session.run_list(&commands, true),
+			"list_OK\nACK [5@1] {bogus} unknown command \"bogus\"\n"
+		);
+	}
+
+	#[tokio::test]
+	async fn clients_sending_too_much_are_dropped() {
+		let test_app = TestApp::seeded();
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		let app_state = test_app.app_state.clone();
+		tokio::spawn(async move {
+			loop {
+				let (stream, _) = listener.accept().await.unwrap();
+				tokio::spawn(serve(stream, app_state.clone()));
+			}
+		});
+
+		// Everything the client gets until the bridge hangs up
+		let replies = |request: Vec<u8>| async move {
+			let mut client = TcpStream::connect(addr).await.unwrap();
+			client.write_all(&request).await.unwrap();
+			let mut replies = String::new();
+			client.read_to_string(&mut replies).await.unwrap();
+			replies
+		};
+
+		// Nothing is sent after what is too much, so the bridge has read it all when it hangs up
+		assert_eq!(replies(vec![b'p'; MAX_LINE_BYTES + 1]).await, GREETING);
+		let long_list = std::iter::once("command_list_begin\n")
+			.chain(std::iter::repeat_n("ping\n", MAX_COMMAND_LIST + 1))
+			.collect::<String>();
+		assert_eq!(replies(long_list.into_bytes()).await, GREETING);
+		assert_eq!(replies(b"ping\nclose\n".to_vec()).await, format!("{GREETING}OK\n"));
+	}
+}
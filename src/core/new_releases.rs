@@ -0,0 +1,98 @@
+use crate::config::OpCode;
+use crate::core::app_state::AppState;
+use crate::i18n::Locale;
+use crate::lobic_db::models::{Music, NewRelease, Notification};
+use crate::routes::notify::notify;
+use crate::schema::{artist_follows, new_releases};
+
+use chrono::Utc;
+use diesel::prelude::*;
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::Duration;
+use uuid::Uuid;
+
+// Scans from the cli are picked up by the running server on its next check
+pub const NOTIFY_INTERVAL: Duration = Duration::from_secs(60);
+// How many releases a single notification mentions
+const NOTIFY_LIMIT: usize = 3;
+
+// Puts the tracks a scan added in the feed of everyone following their artist, one item per album
+pub fn record(added: &[Music], db_conn: &mut SqliteConnection) -> QueryResult<usize> {
+	let mut albums: Vec<((&str, &str), Vec<&str>)> = Vec::new();
+	for added_music in added {
+		let key = (added_music.artist.as_str(), added_music.album.as_str());
+		match albums.iter_mut().find(|(album, _)| *album == key) {
+			Some((_, music_ids)) => music_ids.push(&added_music.music_id),
+			None => albums.push((key, vec![&added_music.music_id])),
+		}
+	}
+
+	let released_date_time = Utc::now().to_rfc3339();
+	let mut releases = Vec::new();
+	for ((artist, album), music_ids) in albums {
+		let followers = artist_follows::table
+			.filter(artist_follows::artist.eq(artist))
+			.select(artist_follows::user_id)
+			.load::<String>(db_conn)?;
+		releases.extend(followers.into_iter().map(|user_id| NewRelease {
+			release_id: Uuid::new_v4().to_string(),
+			user_id,
+			artist: artist.to_string(),
+			album: album.to_string(),
+			music_ids: serde_json::to_string(&music_ids).unwrap(),
+			released_date_time: released_date_time.clone(),
+			notified: false,
+		}));
+	}
+	diesel::insert_into(new_releases::table)
+		.values(&releases)
+		.execute(db_conn)
+}
+
+// Sends every follower a single notification for the releases they haven't heard about yet
+pub fn notify_followers(app_state: &AppState) -> Result<(), String> {
+	let mut db_conn = app_state
+		.db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+
+	let pending = new_releases::table
+		.filter(new_releases::notified.eq(false))
+		.order(new_releases::released_date_time.asc())
+		.load::<NewRelease>(&mut db_conn)
+		.map_err(|err| err.to_string())?;
+	let mut by_user: HashMap<String, Vec<NewRelease>> = HashMap::new();
+	for release in pending {
+		by_user.entry(release.user_id.clone()).or_default().push(release);
+	}
+
+	for (user_id, releases) in by_user {
+		let release_ids: Vec<&str> = releases.iter().map(|release| release.release_id.as_str()).collect();
+		diesel::update(new_releases::table.filter(new_releases::release_id.eq_any(&release_ids)))
+			.set(new_releases::notified.eq(true))
+			.execute(&mut db_conn)
+			.map_err(|err| err.to_string())?;
+
+		let locale = Locale::for_user(&user_id, &mut db_conn).unwrap_or(Locale::DEFAULT);
+		let count = releases.len().to_string();
+		let notif = Notification::new(
+			OpCode::NEW_RELEASE,
+			json!({
+				"count": releases.len(),
+				"releases": releases
+					.iter()
+					.take(NOTIFY_LIMIT)
+					.map(|release| json!({
+						"release_id": release.release_id,
+						"artist": release.artist,
+						"album": release.album,
+					}))
+					.collect::<Vec<_>>(),
+				"message": locale.tf("notification.new_releases", &[("count", &count)]),
+			}),
+		);
+		notify(&user_id, notif, &app_state.db_pool, &app_state.user_pool);
+	}
+	Ok(())
+}
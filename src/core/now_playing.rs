@@ -0,0 +1,422 @@
+use crate::config::{MusicState, OpCode, SocketResponse};
+use crate::core::lobby::{LobbyPool, Music};
+use crate::core::user_pool::{Topic, UserPool};
+use crate::lobic_db::db::{get_user_settings, in_private_session, user_exists, DatabasePool};
+use crate::lobic_db::models::{Music as MusicEntry, MusicResponse, Notification};
+use crate::routes::notify::notify;
+use crate::schema::{music, user_friendship};
+
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// A client which hasn't reported for this long is no longer considered to be listening
+pub const STALE_AFTER_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NowPlaying {
+	pub user_id: String,
+	pub music_id: String,
+	pub title: String,
+	pub artist: String,
+	pub position: f64, // seconds into the track
+	pub state: MusicState,
+	pub lobby_id: Option<String>,
+	pub updated_at: i64, // unix timestamp in seconds
+	pub private: bool,   // in a private session, only the user themselves sees it
+	#[serde(skip)]
+	pub volume: Option<f64>, // only shown in the user's own player state
+	#[serde(skip)]
+	pub queue: Option<Vec<String>>, // the music ids up next, kept for the queue snapshots
+}
+
+impl NowPlaying {
+	pub fn is_live(&self) -> bool {
+		Utc::now().timestamp() - self.updated_at <= STALE_AFTER_SECS
+	}
+}
+
+// What the clients report every few seconds
+#[derive(Debug, Clone, Deserialize)]
+pub struct Heartbeat {
+	pub music_id: String,
+	pub position: f64,
+	pub state: MusicState,
+	pub lobby_id: Option<String>,
+	#[serde(default)]
+	pub volume: Option<f64>, // 0 to 1, older clients leave it out
+	#[serde(default)]
+	pub queue: Option<Vec<String>>, // the whole queue, current track first, sent when it changes
+}
+
+// The player of the user as desktop integrations see it, in the shape of MPRIS
+#[derive(Debug, Serialize)]
+pub struct PlayerState {
+	pub status: &'static str, // playing | paused | stopped
+	pub track: Option<MusicResponse>,
+	pub position: f64, // moved along since the last heartbeat while playing
+	pub volume: Option<f64>,
+	pub lobby_id: Option<String>,
+	pub updated_at: Option<i64>,
+}
+
+// What PUT /player/state asks the user's active player to do, relayed to it as PLAYER_COMMAND
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PlayerIntent {
+	Play,
+	Pause,
+	Seek { position: f64 },
+	Volume { volume: f64 },
+}
+
+impl PlayerIntent {
+	pub fn validate(&self) -> Result<(), String> {
+		match self {
+			PlayerIntent::Seek { position } if !position.is_finite() || *position < 0.0 => {
+				Err(format!("Invalid position: {position}"))
+			}
+			PlayerIntent::Volume { volume } if !(0.0..=1.0).contains(volume) => {
+				Err(format!("The volume has to be between 0 and 1, got {volume}"))
+			}
+			_ => Ok(()),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct NowPlayingPool {
+	inner: Arc<Mutex<HashMap<String, NowPlaying>>>,
+}
+
+impl NowPlayingPool {
+	pub fn new() -> NowPlayingPool {
+		NowPlayingPool {
+			inner: Arc::new(Mutex::new(HashMap::new())),
+		}
+	}
+
+	// The last thing the user reported, however old it is
+	pub fn get(&self, user_id: &str) -> Option<NowPlaying> {
+		let inner = self.inner.lock().unwrap();
+		inner.get(user_id).cloned()
+	}
+
+	// What the user is listening to right now
+	pub fn get_live(&self, user_id: &str) -> Option<NowPlaying> {
+		self.get(user_id).filter(|now_playing| now_playing.is_live())
+	}
+
+	// What friends may see the user listening to, nothing during a private session
+	pub fn get_public(&self, user_id: &str) -> Option<NowPlaying> {
+		self.get_live(user_id).filter(|now_playing| !now_playing.private)
+	}
+
+	// Stores the report, returns true when the track or the play state changed
+	fn record(&self, now_playing: NowPlaying) -> bool {
+		let mut inner = self.inner.lock().unwrap();
+		let changed = match inner.get(&now_playing.user_id) {
+			Some(prev) => {
+				!prev.is_live()
+					|| prev.music_id != now_playing.music_id
+					|| prev.state != now_playing.state
+					|| prev.private != now_playing.private
+			}
+			None => true,
+		};
+		inner.insert(now_playing.user_id.clone(), now_playing);
+		changed
+	}
+}
+
+pub fn record_heartbeat(
+	user_id: &str,
+	heartbeat: Heartbeat,
+	now_playing_pool: &NowPlayingPool,
+	lobby_pool: &LobbyPool,
+	user_pool: &UserPool,
+	db_pool: &DatabasePool,
+) -> Result<NowPlaying, String> {
+	if !user_exists(user_id, db_pool) {
+		return Err(format!("Invalid user id: {}", user_id));
+	}
+
+	// Only looking the track up when it changes
+	let prev = now_playing_pool.get(user_id);
+	let (title, artist) = match prev.clone() {
+		Some(prev) if prev.music_id == heartbeat.music_id => (prev.title, prev.artist),
+		_ => {
+			let mut db_conn = db_pool
+				.get()
+				.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+			music::table
+				.filter(music::music_id.eq(&heartbeat.music_id))
+				.select((music::title, music::artist))
+				.first::<(String, String)>(&mut db_conn)
+				.map_err(|_| format!("Invalid music id: {}", heartbeat.music_id))?
+		}
+	};
+
+	let now_playing = NowPlaying {
+		user_id: user_id.to_string(),
+		music_id: heartbeat.music_id,
+		title,
+		artist,
+		position: heartbeat.position.max(0.0),
+		state: heartbeat.state,
+		lobby_id: heartbeat.lobby_id,
+		updated_at: Utc::now().timestamp(),
+		private: in_private_session(user_id, db_pool),
+		volume: heartbeat.volume.or_else(|| prev.as_ref().and_then(|prev| prev.volume)),
+		queue: heartbeat.queue.or_else(|| prev.and_then(|prev| prev.queue)),
+	};
+
+	// The host's position keeps the lobby in sync for the clients joining late
+	if let Some(lobby_id) = &now_playing.lobby_id {
+		let _ = lobby_pool.sync_position(lobby_id, user_id, &now_playing.music_id, now_playing.position);
+	}
+
+	// Friends only hear about it when something other than the position changes
+	if now_playing_pool.record(now_playing.clone()) && !now_playing.private {
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for: OpCode::FRIEND_ACTIVITY,
+			value: serde_json::to_value(&now_playing).unwrap(),
+		}
+		.to_string();
+		for friend_id in friend_ids(user_id, db_pool) {
+			user_pool.send(&friend_id, &Topic::FriendActivity, response.clone());
+		}
+	}
+
+	Ok(now_playing)
+}
+
+// Puts the follower in a lobby with the friend they want to listen along to. The friend's own lobby
+// is joined when they are in one, otherwise a two person lobby hosted by the friend is made for it.
+pub fn listen_along(
+	follower_id: &str,
+	followed_id: &str,
+	now_playing_pool: &NowPlayingPool,
+	lobby_pool: &LobbyPool,
+	user_pool: &UserPool,
+	db_pool: &DatabasePool,
+) -> Result<Value, String> {
+	if follower_id == followed_id {
+		return Err("Can't listen along to yourself".to_string());
+	}
+	if !user_exists(follower_id, db_pool) {
+		return Err(format!("Invalid user id: {}", follower_id));
+	}
+	if !friend_ids(followed_id, db_pool).iter().any(|id| id == follower_id) {
+		return Err(format!("Only friends of {} can listen along", followed_id));
+	}
+
+	let mut db_conn = db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+	if !get_user_settings(followed_id, &mut db_conn).allow_listen_along {
+		return Err(format!("User {} doesn't allow listening along", followed_id));
+	}
+	let Some(now_playing) = now_playing_pool.get_public(followed_id) else {
+		return Err(format!("User {} isn't listening to anything right now", followed_id));
+	};
+
+	let hosted = now_playing
+		.lobby_id
+		.as_deref()
+		.and_then(|lobby_id| lobby_pool.get(lobby_id))
+		.or_else(|| lobby_pool.hosted_by(followed_id));
+	let lobby_id = match hosted {
+		Some(lobby) if lobby.clients.iter().any(|id| id == follower_id) => lobby.id,
+		Some(lobby) => {
+			lobby_pool.join_lobby(&lobby.id, follower_id, db_pool, user_pool)?;
+			lobby.id
+		}
+		None => {
+			let image_url = music::table
+				.filter(music::music_id.eq(&now_playing.music_id))
+				.first::<MusicEntry>(&mut db_conn)
+				.map(|entry| MusicEntry::create_music_response(entry).image_url)
+				.unwrap_or_default();
+			let music = Music {
+				id: now_playing.music_id.clone(),
+				title: now_playing.title.clone(),
+				artist: now_playing.artist.clone(),
+				image_url,
+				timestamp: now_playing.position,
+				state: now_playing.state.clone(),
+			};
+			let lobby_id = lobby_pool.create_listen_along(followed_id, follower_id, music);
+			user_pool.follow_lobby(followed_id, &lobby_id);
+			lobby_id
+		}
+	};
+	drop(db_conn);
+
+	// The friend's client takes it from here, hosting the lobby like one it made itself
+	let notif = Notification::new(
+		OpCode::LISTEN_ALONG,
+		json!({ "user_id": follower_id, "lobby_id": lobby_id }),
+	);
+	notify(followed_id, notif, db_pool, user_pool);
+
+	Ok(json!({
+		"lobby_id": lobby_id,
+		"user_id": followed_id,
+		"music": lobby_pool.get(&lobby_id).map(|lobby| lobby.music),
+	}))
+}
+
+pub fn player_state(
+	user_id: &str,
+	now_playing_pool: &NowPlayingPool,
+	db_pool: &DatabasePool,
+) -> Result<PlayerState, String> {
+	let Some(now_playing) = now_playing_pool.get_live(user_id) else {
+		return Ok(PlayerState {
+			status: "stopped",
+			track: None,
+			position: 0.0,
+			volume: None,
+			lobby_id: None,
+			updated_at: None,
+		});
+	};
+
+	let mut db_conn = db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+	let track = music::table
+		.filter(music::music_id.eq(&now_playing.music_id))
+		.first::<MusicEntry>(&mut db_conn)
+		.optional()
+		.map_err(|err| format!("Database error: {err}"))?
+		.map(MusicEntry::create_music_response);
+
+	let playing = matches!(now_playing.state, MusicState::PLAY | MusicState::CHANGE_MUSIC);
+	let mut position = now_playing.position;
+	if playing {
+		position += (Utc::now().timestamp() - now_playing.updated_at) as f64;
+	}
+	if let Some(track) = &track {
+		position = position.min(track.duration as f64);
+	}
+
+	Ok(PlayerState {
+		status: if playing { "playing" } else { "paused" },
+		track,
+		position,
+		volume: now_playing.volume,
+		lobby_id: now_playing.lobby_id,
+		updated_at: Some(now_playing.updated_at),
+	})
+}
+
+// Hands the intent to the user's connected player, false when there is none to take it
+pub fn relay_intent(user_id: &str, intent: &PlayerIntent, user_pool: &UserPool) -> bool {
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::PLAYER_COMMAND,
+		value: serde_json::to_value(intent).unwrap(),
+	}
+	.to_string();
+	user_pool.send(user_id, &Topic::Player, response)
+}
+
+pub fn friend_ids(user_id: &str, db_pool: &DatabasePool) -> Vec<String> {
+	let mut db_conn = match db_pool.get() {
+		Ok(conn) => conn,
+		Err(_) => return vec![],
+	};
+
+	user_friendship::table
+		.filter(user_friendship::user_id.eq(user_id))
+		.select(user_friendship::friend_id)
+		.load::<String>(&mut db_conn)
+		.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::schema::{notifications, user_settings, users};
+	use crate::test_support::TestApp;
+
+	#[tokio::test]
+	async fn friends_listen_along_in_a_lobby_hosted_by_the_friend() {
+		let test_app = TestApp::seeded();
+		let state = &test_app.app_state;
+		let mut db_conn = test_app.db_conn();
+		let music_id = music::table
+			.select(music::music_id)
+			.first::<String>(&mut db_conn)
+			.unwrap();
+		let user_ids = users::table
+			.select(users::user_id)
+			.load::<String>(&mut db_conn)
+			.unwrap();
+		drop(db_conn);
+		let (followed_id, follower_id) = user_ids
+			.iter()
+			.find_map(|user_id| {
+				let friends = friend_ids(user_id, &state.db_pool);
+				friends.first().map(|friend_id| (user_id.clone(), friend_id.clone()))
+			})
+			.unwrap();
+		let follow = || {
+			listen_along(
+				&follower_id,
+				&followed_id,
+				&state.now_playing_pool,
+				&state.lobby_pool,
+				&state.user_pool,
+				&state.db_pool,
+			)
+		};
+
+		assert!(follow().is_err(), "nothing is playing yet");
+		let heartbeat = Heartbeat {
+			music_id: music_id.clone(),
+			position: 42.0,
+			state: MusicState::PLAY,
+			lobby_id: None,
+			volume: None,
+			queue: None,
+		};
+		record_heartbeat(
+			&followed_id,
+			heartbeat,
+			&state.now_playing_pool,
+			&state.lobby_pool,
+			&state.user_pool,
+			&state.db_pool,
+		)
+		.unwrap();
+
+		let response = follow().unwrap();
+		let lobby = state.lobby_pool.get(response["lobby_id"].as_str().unwrap()).unwrap();
+		assert_eq!(lobby.host_id, followed_id);
+		assert_eq!(lobby.clients, vec![followed_id.clone(), follower_id.clone()]);
+		assert_eq!(lobby.music.id, music_id);
+		assert_eq!(lobby.music.timestamp, 42.0);
+		assert_eq!(follow().unwrap()["lobby_id"], response["lobby_id"]);
+		let notified = notifications::table
+			.filter(notifications::user_id.eq(&followed_id))
+			.count()
+			.get_result::<i64>(&mut test_app.db_conn())
+			.unwrap();
+		assert_eq!(notified, 2);
+
+		let mut settings = get_user_settings(&followed_id, &mut test_app.db_conn());
+		settings.allow_listen_along = false;
+		diesel::replace_into(user_settings::table)
+			.values(&settings)
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		assert!(follow().is_err());
+	}
+}
@@ -0,0 +1,223 @@
+use crate::config::{OAUTH_ACCESS_TOKEN_SECS, OAUTH_REFRESH_TOKEN_DAYS};
+use crate::core::app_state::AppState;
+use crate::core::federation::{bearer_token, generate_token};
+use crate::lobic_db::models::OAuthToken;
+use crate::schema::{oauth_codes, oauth_tokens};
+use crate::utils::{auth::OAUTH_SESSION_COOKIE, exp, jwt};
+
+use axum::{
+	body::Body,
+	extract::State,
+	http::{header, HeaderValue, Method, Request, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+// What an app can be allowed to do, read is GET requests, write every other one and playback the
+// player and play logging, whatever the method
+pub const SCOPES: [&str; 3] = ["read", "write", "playback"];
+
+// Never open to apps, whatever the user allowed them. The socket would be a full session for as long
+// as it stays open.
+const CLOSED_PREFIXES: [&str; 12] = [
+	"/oauth",
+	"/admin",
+	"/login",
+	"/logout",
+	"/signup",
+	"/otp",
+	"/change_password",
+	"/user/settings",
+	"/user/scrobble_tokens",
+	"/user/embed_tokens",
+	"/federation",
+	"/ws",
+];
+
+// A space separated scope as asked for, None when it names a scope we don't have
+pub fn parse_scope(scope: &str) -> Option<Vec<&'static str>> {
+	let mut parsed = Vec::new();
+	for name in scope.split_whitespace() {
+		let known = SCOPES.iter().find(|known| **known == name)?;
+		if !parsed.contains(known) {
+			parsed.push(*known);
+		}
+	}
+	(!parsed.is_empty()).then_some(parsed)
+}
+
+// Secrets, codes and tokens are only stored hashed, what's in the database can't be used as it is
+pub fn hash_secret(secret: &str) -> String {
+	Sha256::digest(secret.as_bytes())
+		.iter()
+		.map(|byte| format!("{byte:02x}"))
+		.collect()
+}
+
+// Against the stored hash, in constant time. Nothing matches an empty hash.
+pub fn secret_matches(secret: &str, stored_hash: &str) -> bool {
+	!stored_hash.is_empty() && bool::from(hash_secret(secret).as_bytes().ct_eq(stored_hash.as_bytes()))
+}
+
+// PKCE with S256 (RFC 7636), the challenge is the base64url sha256 of the verifier
+pub fn pkce_matches(code_verifier: &str, code_challenge: &str) -> bool {
+	if !(43..=128).contains(&code_verifier.len()) {
+		return false;
+	}
+	let computed = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+	bool::from(computed.as_bytes().ct_eq(code_challenge.as_bytes()))
+}
+
+// The scope a token needs for the request, None when apps can't make it at all
+pub fn required_scope(method: &Method, path: &str) -> Option<&'static str> {
+	if CLOSED_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) {
+		return None;
+	}
+	if path.starts_with("/player/") || path == "/music/log_song_play" {
+		return Some("playback");
+	}
+	match *method {
+		Method::GET | Method::HEAD => Some("read"),
+		_ => Some("write"),
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+	pub access_token: String,
+	pub token_type: &'static str,
+	pub expires_in: i64,
+	pub refresh_token: String,
+	pub scope: String,
+}
+
+// A fresh access and refresh token pair for the app acting for the user
+pub fn issue_tokens(
+	client_id: &str,
+	user_id: &str,
+	scope: &str,
+	db_conn: &mut SqliteConnection,
+) -> QueryResult<TokenResponse> {
+	let now = Utc::now();
+	let (access_token, refresh_token) = (generate_token(), generate_token());
+	let token = OAuthToken {
+		access_token: hash_secret(&access_token),
+		refresh_token: hash_secret(&refresh_token),
+		client_id: client_id.to_string(),
+		user_id: user_id.to_string(),
+		scope: scope.to_string(),
+		expires_date_time: (now + Duration::seconds(OAUTH_ACCESS_TOKEN_SECS)).to_rfc3339(),
+		created_date_time: now.to_rfc3339(),
+	};
+	diesel::insert_into(oauth_tokens::table)
+		.values(&token)
+		.execute(db_conn)?;
+	Ok(TokenResponse {
+		access_token,
+		token_type: "Bearer",
+		expires_in: OAUTH_ACCESS_TOKEN_SECS,
+		refresh_token,
+		scope: token.scope,
+	})
+}
+
+// Refresh tokens older than this are turned down, and pruned
+pub fn refresh_cutoff() -> String {
+	(Utc::now() - Duration::days(OAUTH_REFRESH_TOKEN_DAYS)).to_rfc3339()
+}
+
+// Drops the expired codes and the tokens whose refresh token ran out
+pub fn prune(db_conn: &mut SqliteConnection) -> QueryResult<usize> {
+	let codes = diesel::delete(oauth_codes::table.filter(oauth_codes::expires_date_time.lt(Utc::now().to_rfc3339())))
+		.execute(db_conn)?;
+	let tokens = diesel::delete(oauth_tokens::table.filter(oauth_tokens::created_date_time.lt(refresh_cutoff())))
+		.execute(db_conn)?;
+	Ok(codes + tokens)
+}
+
+// Lets an app's bearer token stand in for the session cookies, checked against the scope the route
+// needs. Other bearer tokens, like the scrobble and federation ones, go through untouched.
+pub async fn bearer_session(State(app_state): State<AppState>, mut req: Request<Body>, next: Next) -> Response {
+	if req.headers().contains_key(header::COOKIE) {
+		return next.run(req).await;
+	}
+	let Some(bearer) = bearer_token(req.headers()).map(str::to_string) else {
+		return next.run(req).await;
+	};
+	let Ok(mut db_conn) = app_state.db_pool.get() else {
+		return next.run(req).await;
+	};
+	let token = oauth_tokens::table
+		.find(hash_secret(&bearer))
+		.first::<OAuthToken>(&mut db_conn)
+		.optional();
+	drop(db_conn);
+	let token = match token {
+		Ok(Some(token)) => token,
+		_ => return next.run(req).await,
+	};
+
+	if token.expires_date_time < Utc::now().to_rfc3339() {
+		return (StatusCode::UNAUTHORIZED, "The access token expired, refresh it").into_response();
+	}
+	let allowed = required_scope(req.method(), req.uri().path())
+		.is_some_and(|needed| token.scope.split_whitespace().any(|scope| scope == needed));
+	if !allowed {
+		return (StatusCode::FORBIDDEN, "The app wasn't allowed to do this").into_response();
+	}
+
+	// A session of a minute, the handlers see the user as logged in. Marked as the app's, so it never
+	// passes require_admin.
+	let Ok(secret_key) = std::env::var("JWT_SECRET_KEY") else {
+		return next.run(req).await;
+	};
+	let claims = jwt::Claims {
+		id: token.user_id,
+		exp: exp::expiration_from_min(1),
+		iat: exp::now(),
+	};
+	let cookie = |jwt: String| {
+		HeaderValue::from_str(&format!(
+			"access_token={jwt}; {OAUTH_SESSION_COOKIE}={}",
+			token.client_id
+		))
+	};
+	if let Ok(Ok(cookie)) = jwt::generate(claims, &secret_key).map(cookie) {
+		req.headers_mut().insert(header::COOKIE, cookie);
+	}
+	next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn apps_never_reach_the_account_routes() {
+		assert_eq!(parse_scope("read  playback read"), Some(vec!["read", "playback"]));
+		assert_eq!(parse_scope("read admin"), None);
+		assert_eq!(parse_scope(""), None);
+		assert_eq!(required_scope(&Method::GET, "/music/get_music"), Some("read"));
+		assert_eq!(required_scope(&Method::POST, "/playlist/new"), Some("write"));
+		assert_eq!(required_scope(&Method::PUT, "/player/state"), Some("playback"));
+		assert_eq!(required_scope(&Method::GET, "/user/settings"), None);
+		assert_eq!(required_scope(&Method::POST, "/oauth/authorize"), None);
+		assert_eq!(required_scope(&Method::GET, "/ws"), None);
+	}
+
+	#[test]
+	fn pkce_verifiers_are_checked_against_the_s256_challenge() {
+		let code_verifier = "dBjftJeZ4CVP-mJ92K9qqUBT3Ouo2hlt9l5Nx9MZK1U";
+		assert!(pkce_matches(code_verifier, "9dJM_5fZ0r2xszgD0OyXYnXvK2NYZ51UUkRmcGxVK7Y"));
+		assert!(!pkce_matches(code_verifier, "9dJM_5fZ0r2xszgD0OyXYnXvK2NYZ51UUkRmcGxVK7Z"));
+		assert!(!pkce_matches("too-short", "9dJM_5fZ0r2xszgD0OyXYnXvK2NYZ51UUkRmcGxVK7Y"));
+		assert!(secret_matches("secret", &hash_secret("secret")));
+		assert!(!secret_matches("", ""));
+	}
+}
@@ -0,0 +1,155 @@
+use crate::config::OpCode;
+use crate::core::app_state::AppState;
+use crate::i18n::Locale;
+use crate::lobic_db::models::{Availability, FirstListen, Notification};
+use crate::routes::notify::notify;
+use crate::schema::{first_listens, music};
+
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, Local, NaiveDate};
+use diesel::prelude::*;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// How many anniversaries a single notification mentions
+const NOTIFY_LIMIT: usize = 3;
+
+#[derive(Debug, Serialize)]
+pub struct Anniversary {
+	#[serde(skip_serializing)]
+	pub user_id: String,
+	pub target_type: String, // track | artist
+	pub target_id: String,
+	pub title: Option<String>, // only for tracks
+	pub artist: String,
+	pub first_played_date_time: String,
+	pub years: i32,
+	#[serde(skip_serializing)]
+	pub notified_year: Option<i32>,
+}
+
+// Tracks and artists first played on this day in an earlier year, of a single user or of everyone
+pub fn anniversaries(
+	user_id: Option<&str>,
+	today: NaiveDate,
+	db_conn: &mut SqliteConnection,
+) -> QueryResult<Vec<Anniversary>> {
+	// The stored times are UTC, the neighbouring days cover the local offset
+	let patterns: Vec<String> = [today - ChronoDuration::days(1), today, today + ChronoDuration::days(1)]
+		.iter()
+		.map(|day| format!("____-{:02}-{:02}%", day.month(), day.day()))
+		.collect();
+
+	let mut query = first_listens::table
+		.filter(
+			first_listens::first_played_date_time
+				.like(&patterns[0])
+				.or(first_listens::first_played_date_time.like(&patterns[1]))
+				.or(first_listens::first_played_date_time.like(&patterns[2])),
+		)
+		.into_boxed();
+	if let Some(user_id) = user_id {
+		query = query.filter(first_listens::user_id.eq(user_id));
+	}
+	let candidates = query.load::<FirstListen>(db_conn)?;
+
+	let candidates: Vec<(FirstListen, i32)> = candidates
+		.into_iter()
+		.filter_map(|entry| {
+			let played = DateTime::parse_from_rfc3339(&entry.first_played_date_time)
+				.ok()?
+				.with_timezone(&Local)
+				.date_naive();
+			let years = today.year() - played.year();
+			(played.month() == today.month() && played.day() == today.day() && years > 0).then_some((entry, years))
+		})
+		.collect();
+
+	// Title and artist of the tracks, the unavailable ones are left out
+	let music_ids: Vec<&String> = candidates
+		.iter()
+		.filter(|(entry, _)| entry.target_type == "track")
+		.map(|(entry, _)| &entry.target_id)
+		.collect();
+	let tracks: HashMap<String, (String, String)> = music::table
+		.filter(music::music_id.eq_any(music_ids))
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.select((music::music_id, music::title, music::artist))
+		.load::<(String, String, String)>(db_conn)?
+		.into_iter()
+		.map(|(music_id, title, artist)| (music_id, (title, artist)))
+		.collect();
+
+	let mut result: Vec<Anniversary> = candidates
+		.into_iter()
+		.filter_map(|(entry, years)| {
+			let (title, artist) = match entry.target_type.as_str() {
+				"track" => {
+					let (title, artist) = tracks.get(&entry.target_id)?.clone();
+					(Some(title), artist)
+				}
+				_ => (None, entry.target_id.clone()),
+			};
+			Some(Anniversary {
+				user_id: entry.user_id,
+				target_type: entry.target_type,
+				target_id: entry.target_id,
+				title,
+				artist,
+				first_played_date_time: entry.first_played_date_time,
+				years,
+				notified_year: entry.notified_year,
+			})
+		})
+		.collect();
+	result.sort_by_key(|anniversary| std::cmp::Reverse(anniversary.years));
+	Ok(result)
+}
+
+// Sends every user a single nostalgia notification on the days they have anniversaries
+pub fn notify_anniversaries(app_state: &AppState) -> Result<(), String> {
+	let mut db_conn = app_state
+		.db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+
+	let today = Local::now().date_naive();
+	let mut pending: HashMap<String, Vec<Anniversary>> = HashMap::new();
+	for anniversary in anniversaries(None, today, &mut db_conn).map_err(|err| err.to_string())? {
+		if anniversary.notified_year != Some(today.year()) {
+			pending
+				.entry(anniversary.user_id.clone())
+				.or_default()
+				.push(anniversary);
+		}
+	}
+
+	for (user_id, user_anniversaries) in pending {
+		for anniversary in &user_anniversaries {
+			diesel::update(
+				first_listens::table
+					.filter(first_listens::user_id.eq(&user_id))
+					.filter(first_listens::target_type.eq(&anniversary.target_type))
+					.filter(first_listens::target_id.eq(&anniversary.target_id)),
+			)
+			.set(first_listens::notified_year.eq(today.year()))
+			.execute(&mut db_conn)
+			.map_err(|err| err.to_string())?;
+		}
+
+		let locale = Locale::for_user(&user_id, &mut db_conn).unwrap_or(Locale::DEFAULT);
+		let count = user_anniversaries.len().to_string();
+		let notif = Notification::new(
+			OpCode::ON_THIS_DAY,
+			json!({
+				"count": user_anniversaries.len(),
+				"anniversaries": user_anniversaries.iter().take(NOTIFY_LIMIT).collect::<Vec<_>>(),
+				"message": locale.tf("notification.on_this_day", &[("count", &count)]),
+			}),
+		);
+		notify(&user_id, notif, &app_state.db_pool, &app_state.user_pool);
+	}
+	Ok(())
+}
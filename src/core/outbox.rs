@@ -0,0 +1,138 @@
+use crate::config::{OpCode, SocketResponse};
+
+use axum::extract::ws::Message;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{watch, Notify};
+
+// Messages waiting to be written before the oldest ones start getting dropped
+pub const OUTBOX_LIMIT: usize = 256;
+// A client that made us drop this many messages is considered stuck and gets disconnected
+pub const MAX_DROPPED: usize = 512;
+// How long a single write to the client may take
+pub const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+struct Pending {
+	key: Option<OpCode>,
+	message: Message,
+}
+
+#[derive(Debug, Default)]
+struct OutboxState {
+	queue: VecDeque<Pending>,
+	dropped: usize,
+}
+
+// Per connection outbound queue. Bounded, and state like events (music sync, queue, members, chat)
+// replace the pending one of the same kind instead of piling up behind a slow client.
+#[derive(Debug, Clone)]
+pub struct Outbox {
+	state: Arc<Mutex<OutboxState>>,
+	notify: Arc<Notify>,
+	closed: Arc<watch::Sender<bool>>,
+}
+
+impl Outbox {
+	pub fn new() -> Outbox {
+		let (closed, _) = watch::channel(false);
+		Outbox {
+			state: Arc::new(Mutex::new(OutboxState::default())),
+			notify: Arc::new(Notify::new()),
+			closed: Arc::new(closed),
+		}
+	}
+
+	// Queues the message, fails once the connection has been closed
+	pub fn send(&self, message: Message) -> Result<(), String> {
+		if self.is_closed() {
+			return Err("Connection is closed".to_string());
+		}
+
+		let key = coalesce_key(&message);
+		{
+			let mut state = self.state.lock().unwrap();
+
+			let pending = state
+				.queue
+				.iter_mut()
+				.find(|pending| key.is_some() && pending.key == key);
+			match pending {
+				Some(pending) => pending.message = message,
+				None => {
+					if state.queue.len() >= OUTBOX_LIMIT {
+						state.queue.pop_front();
+						state.dropped += 1;
+					}
+					state.queue.push_back(Pending { key, message });
+				}
+			};
+
+			if state.dropped >= MAX_DROPPED {
+				drop(state);
+				self.close();
+				return Err("Slow consumer disconnected".to_string());
+			}
+		}
+
+		self.notify.notify_one();
+		Ok(())
+	}
+
+	// Waits for the next message to write, None once the connection is closed
+	pub async fn next(&self) -> Option<Message> {
+		loop {
+			if self.is_closed() {
+				return None;
+			}
+
+			if let Some(pending) = self.state.lock().unwrap().queue.pop_front() {
+				return Some(pending.message);
+			}
+
+			let mut closed = self.closed.subscribe();
+			tokio::select! {
+				_ = self.notify.notified() => (),
+				_ = closed.wait_for(|closed| *closed) => (),
+			}
+		}
+	}
+
+	pub fn close(&self) {
+		self.closed.send_replace(true);
+		self.state.lock().unwrap().queue.clear();
+	}
+
+	pub fn is_closed(&self) -> bool {
+		*self.closed.borrow()
+	}
+
+	// Resolves once the connection has been closed
+	pub async fn closed(&self) {
+		let mut closed = self.closed.subscribe();
+		let _ = closed.wait_for(|closed| *closed).await;
+	}
+
+	pub fn same_as(&self, other: &Outbox) -> bool {
+		Arc::ptr_eq(&self.state, &other.state)
+	}
+}
+
+// Events which carry the whole state, only the latest one is worth sending
+fn coalesce_key(message: &Message) -> Option<OpCode> {
+	let text = match message {
+		Message::Text(text) => text,
+		_ => return None,
+	};
+
+	let response = serde_json::from_str::<SocketResponse>(text).ok()?;
+	match response.r#for {
+		OpCode::SYNC_MUSIC
+		| OpCode::SYNC_QUEUE
+		| OpCode::GET_LOBBY_MEMBERS
+		| OpCode::GET_MESSAGES
+		| OpCode::GET_LOBBY_IDS => Some(response.r#for),
+		_ => None,
+	}
+}
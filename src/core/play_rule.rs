@@ -0,0 +1,53 @@
+use crate::config::{DEFAULT_PLAY_MIN_PERCENT, DEFAULT_PLAY_MIN_SECS};
+use crate::lobic_db::db::get_instance_setting;
+
+use diesel::prelude::*;
+use serde::Serialize;
+
+// Keys in instance_settings
+pub const PLAY_MIN_PERCENT: &str = "play_min_percent";
+pub const PLAY_MIN_SECS: &str = "play_min_secs";
+
+// What counts as a play on this instance, both parts have to hold. Shorter listens aren't recorded at all.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct PlayRule {
+	pub min_percent: i64, // of the track
+	pub min_secs: i64,    // a track shorter than this has to be played through
+}
+
+impl PlayRule {
+	pub fn load(db_conn: &mut SqliteConnection) -> PlayRule {
+		let setting = |key: &str, default: i64, db_conn: &mut SqliteConnection| {
+			get_instance_setting(key, db_conn)
+				.and_then(|value| value.parse().ok())
+				.unwrap_or(default)
+		};
+		PlayRule {
+			min_percent: setting(PLAY_MIN_PERCENT, DEFAULT_PLAY_MIN_PERCENT, db_conn),
+			min_secs: setting(PLAY_MIN_SECS, DEFAULT_PLAY_MIN_SECS, db_conn),
+		}
+	}
+
+	pub fn counts(&self, listened_secs: i64, duration: i64) -> bool {
+		listened_secs >= self.min_secs.min(duration) && listened_secs * 100 >= self.min_percent * duration
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn both_parts_of_the_rule_have_to_hold() {
+		let rule = PlayRule {
+			min_percent: 50,
+			min_secs: 30,
+		};
+		assert!(rule.counts(100, 200));
+		assert!(!rule.counts(99, 200));
+		assert!(!rule.counts(20, 30));
+		// a jingle played through
+		assert!(rule.counts(12, 12));
+		assert!(!rule.counts(11, 12));
+	}
+}
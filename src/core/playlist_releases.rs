@@ -0,0 +1,74 @@
+use crate::config::OpCode;
+use crate::core::app_state::AppState;
+use crate::i18n::Locale;
+use crate::lobic_db::models::{Availability, Notification, PlaylistRelease};
+use crate::routes::notify::notify;
+use crate::schema::{playlist_releases, playlists, user_friendship, users};
+
+use chrono::Utc;
+use diesel::prelude::*;
+use serde_json::json;
+use std::time::Duration;
+
+pub const RELEASE_INTERVAL: Duration = Duration::from_secs(60);
+
+// Makes the playlists due for release public and tells everyone who has their owner as a friend. The ones sitting
+// in the trash wait until they are restored.
+pub fn release_due(app_state: &AppState) -> Result<(), String> {
+	let mut db_conn = app_state
+		.db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+
+	let now = Utc::now().to_rfc3339();
+	let due = playlist_releases::table
+		.inner_join(playlists::table.on(playlists::playlist_id.eq(playlist_releases::playlist_id)))
+		.inner_join(users::table.on(users::user_id.eq(playlist_releases::user_id)))
+		.filter(playlist_releases::release_date_time.le(&now))
+		.select((PlaylistRelease::as_select(), playlists::playlist_name, users::username))
+		.load::<(PlaylistRelease, String, String)>(&mut db_conn)
+		.map_err(|err| err.to_string())?;
+
+	for (release, playlist_name, owner) in due {
+		let released = db_conn
+			.transaction(|conn| {
+				diesel::delete(playlist_releases::table.find(&release.playlist_id)).execute(conn)?;
+				// a takedown in the meantime wins
+				diesel::update(playlists::table.find(&release.playlist_id))
+					.filter(playlists::availability.eq(Availability::Private.as_str()))
+					.set((
+						playlists::availability.eq(Availability::Available.as_str()),
+						playlists::availability_reason.eq(None::<String>),
+						playlists::last_updated_date_time.eq(&now),
+					))
+					.execute(conn)
+			})
+			.map_err(|err: diesel::result::Error| err.to_string())?;
+		if released == 0 {
+			continue;
+		}
+
+		let follower_ids = user_friendship::table
+			.filter(user_friendship::friend_id.eq(&release.user_id))
+			.select(user_friendship::user_id)
+			.load::<String>(&mut db_conn)
+			.map_err(|err| err.to_string())?;
+		for follower_id in follower_ids {
+			let locale = Locale::for_user(&follower_id, &mut db_conn).unwrap_or(Locale::DEFAULT);
+			let notif = Notification::new(
+				OpCode::PLAYLIST_RELEASED,
+				json!({
+					"playlist_id": release.playlist_id,
+					"playlist_name": playlist_name,
+					"owner_id": release.user_id,
+					"message": locale.tf(
+						"notification.playlist_released",
+						&[("owner", &owner), ("playlist", &playlist_name)],
+					),
+				}),
+			);
+			notify(&follower_id, notif, &app_state.db_pool, &app_state.user_pool);
+		}
+	}
+	Ok(())
+}
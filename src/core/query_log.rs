@@ -0,0 +1,134 @@
+use axum::{body::Body, extract::Request, middleware::Next, response::Response};
+use chrono::Utc;
+use colored::*;
+use diesel::connection::{set_default_instrumentation, Instrumentation, InstrumentationEvent};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+// Slowest queries kept around for /admin/slow_queries
+pub const SLOW_QUERIES_KEPT: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryTiming {
+	pub sql: String,
+	pub duration_ms: f64,
+	pub route: Option<String>, // None for the queries run outside of a request
+	pub executed_date_time: String,
+}
+
+#[derive(Default)]
+struct RequestTrace {
+	route: String,
+	queries: Vec<QueryTiming>,
+}
+
+tokio::task_local! {
+	static REQUEST_TRACE: RefCell<RequestTrace>;
+}
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+static SLOWEST: Mutex<Vec<QueryTiming>> = Mutex::new(Vec::new());
+
+// Turned on with QUERY_LOG=true, meant for debugging since every query gets timed
+pub fn enabled() -> bool {
+	*ENABLED.get_or_init(|| std::env::var("QUERY_LOG").is_ok_and(|value| value == "true" || value == "1"))
+}
+
+// Has to run before the first connection is established, the connections pick it up on creation
+pub fn install() {
+	if enabled() {
+		set_default_instrumentation(|| Some(Box::new(QueryTimer { started: None })))
+			.expect("Failed to install the query instrumentation");
+	}
+}
+
+pub fn slowest(limit: usize) -> Vec<QueryTiming> {
+	let slowest = SLOWEST.lock().unwrap();
+	slowest.iter().take(limit).cloned().collect()
+}
+
+struct QueryTimer {
+	started: Option<Instant>,
+}
+
+impl Instrumentation for QueryTimer {
+	fn on_connection_event(&mut self, event: InstrumentationEvent<'_>) {
+		match event {
+			InstrumentationEvent::StartQuery { .. } => self.started = Some(Instant::now()),
+			InstrumentationEvent::FinishQuery { query, .. } => {
+				if let Some(started) = self.started.take() {
+					record(query.to_string(), started.elapsed());
+				}
+			}
+			_ => (),
+		}
+	}
+}
+
+fn record(query: String, elapsed: Duration) {
+	// The binds may hold user data, only the statement itself is kept
+	let sql = match query.split_once(" -- binds:") {
+		Some((sql, _)) => sql.to_string(),
+		None => query,
+	};
+
+	let mut timing = QueryTiming {
+		sql,
+		duration_ms: elapsed.as_secs_f64() * 1000.0,
+		route: None,
+		executed_date_time: Utc::now().to_rfc3339(),
+	};
+
+	// Queries run inside a request also go to its trace
+	let _ = REQUEST_TRACE.try_with(|trace| {
+		let mut trace = trace.borrow_mut();
+		timing.route = Some(trace.route.clone());
+		trace.queries.push(timing.clone());
+	});
+
+	let mut slowest = SLOWEST.lock().unwrap();
+	if slowest.len() >= SLOW_QUERIES_KEPT
+		&& slowest
+			.last()
+			.is_some_and(|last| last.duration_ms >= timing.duration_ms)
+	{
+		return;
+	}
+	let index = slowest.partition_point(|query| query.duration_ms >= timing.duration_ms);
+	slowest.insert(index, timing);
+	slowest.truncate(SLOW_QUERIES_KEPT);
+}
+
+// Prints the queries of every request along with their timings
+pub async fn trace_queries(req: Request<Body>, next: Next) -> Response {
+	if !enabled() {
+		return next.run(req).await;
+	}
+
+	let trace = RefCell::new(RequestTrace {
+		route: format!("{} {}", req.method(), req.uri().path()),
+		queries: Vec::new(),
+	});
+	let (response, trace) = REQUEST_TRACE
+		.scope(trace, async {
+			let response = next.run(req).await;
+			(response, REQUEST_TRACE.with(|trace| trace.take()))
+		})
+		.await;
+
+	let total_ms: f64 = trace.queries.iter().map(|query| query.duration_ms).sum();
+	println!(
+		"{} {} | queries: {} | db time: {:.2}ms",
+		"[sql]".bright_magenta(),
+		trace.route.bright_white(),
+		trace.queries.len(),
+		total_ms
+	);
+	for query in &trace.queries {
+		println!("    {:>8.2}ms {}", query.duration_ms, query.sql);
+	}
+
+	response
+}
@@ -0,0 +1,56 @@
+use crate::config::{MAX_AUTOMATIC_QUEUE_SNAPSHOTS, MAX_LOOKUP_IDS};
+use crate::core::now_playing::NowPlaying;
+use crate::lobic_db::models::QueueSnapshot;
+use crate::schema::queue_snapshots;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+// Longer queues are cut to what a restore can look up in one go
+pub fn save(
+	user_id: &str,
+	name: &str,
+	music_ids: &[String],
+	position: f64,
+	automatic: bool,
+	db_conn: &mut SqliteConnection,
+) -> QueryResult<QueueSnapshot> {
+	let snapshot = QueueSnapshot {
+		snapshot_id: Uuid::new_v4().to_string(),
+		user_id: user_id.to_string(),
+		name: name.to_string(),
+		music_ids: serde_json::to_string(&music_ids[..music_ids.len().min(MAX_LOOKUP_IDS)]).unwrap(),
+		position: position.max(0.0),
+		automatic,
+		created_date_time: Utc::now().to_rfc3339(),
+	};
+	diesel::insert_into(queue_snapshots::table)
+		.values(&snapshot)
+		.execute(db_conn)?;
+
+	// Only the latest automatic ones are kept, the named ones stay until deleted
+	if automatic {
+		let stale = queue_snapshots::table
+			.filter(queue_snapshots::user_id.eq(user_id))
+			.filter(queue_snapshots::automatic.eq(true))
+			.order(queue_snapshots::created_date_time.desc())
+			.offset(MAX_AUTOMATIC_QUEUE_SNAPSHOTS)
+			.select(queue_snapshots::snapshot_id)
+			.load::<String>(db_conn)?;
+		diesel::delete(queue_snapshots::table.filter(queue_snapshots::snapshot_id.eq_any(&stale))).execute(db_conn)?;
+	}
+	Ok(snapshot)
+}
+
+// Keeps the queue the user was last heard with, nothing is saved when the client never sent one
+pub fn snapshot_on_logout(
+	now_playing: &NowPlaying,
+	db_conn: &mut SqliteConnection,
+) -> QueryResult<Option<QueueSnapshot>> {
+	let Some(queue) = now_playing.queue.as_ref().filter(|queue| !queue.is_empty()) else {
+		return Ok(None);
+	};
+	let name = format!("Before logging out {}", Utc::now().format("%Y-%m-%d %H:%M"));
+	save(&now_playing.user_id, &name, queue, now_playing.position, true, db_conn).map(Some)
+}
@@ -0,0 +1,262 @@
+use crate::config::{DEFAULT_EXPORT_QUOTA, DEFAULT_METADATA_LOOKUP_QUOTA, DEFAULT_TRANSCODE_QUOTA};
+use crate::lobic_db::db::get_instance_setting;
+use crate::lobic_db::models::Quota;
+use crate::schema::quotas;
+
+use axum::{
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use chrono::{Days, NaiveDate, Utc};
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use serde::Serialize;
+use serde_json::json;
+use std::fmt;
+
+// The features that cost the instance more than a request, each with a daily limit per user
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+	Transcode,      // an animated cover started through ffmpeg
+	Export,         // a lobby chat export
+	MetadataLookup, // an artist looked up with the image provider
+}
+
+impl Feature {
+	pub const ALL: [Feature; 3] = [Feature::Transcode, Feature::Export, Feature::MetadataLookup];
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Feature::Transcode => "transcode",
+			Feature::Export => "export",
+			Feature::MetadataLookup => "metadata_lookup",
+		}
+	}
+
+	// Key in instance_settings
+	pub fn setting_key(&self) -> String {
+		format!("quota_{}_per_day", self.as_str())
+	}
+
+	fn default_limit(&self) -> i64 {
+		match self {
+			Feature::Transcode => DEFAULT_TRANSCODE_QUOTA,
+			Feature::Export => DEFAULT_EXPORT_QUOTA,
+			Feature::MetadataLookup => DEFAULT_METADATA_LOOKUP_QUOTA,
+		}
+	}
+
+	// The limit per user and day, 0 when the admins turned the feature off
+	pub fn limit(&self, db_conn: &mut SqliteConnection) -> i64 {
+		get_instance_setting(&self.setting_key(), db_conn)
+			.and_then(|value| value.parse().ok())
+			.unwrap_or(self.default_limit())
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct Usage {
+	pub feature: Feature,
+	pub limit: i64,
+	pub used: i64,
+	pub remaining: i64,
+	pub resets_date_time: String, // the next UTC midnight
+}
+
+#[derive(Debug)]
+pub enum QuotaError {
+	Exceeded(Usage),
+	Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for QuotaError {
+	fn from(err: diesel::result::Error) -> Self {
+		QuotaError::Database(err)
+	}
+}
+
+impl fmt::Display for QuotaError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			QuotaError::Exceeded(usage) => write!(
+				f,
+				"Daily {} quota used up ({} of {}), it resets at {}",
+				usage.feature.as_str(),
+				usage.used,
+				usage.limit,
+				usage.resets_date_time
+			),
+			QuotaError::Database(err) => write!(f, "Database error: {err}"),
+		}
+	}
+}
+
+impl QuotaError {
+	// 429 with the quota_exceeded code and the usage, so the clients can tell it from the other limits
+	pub fn into_response(self) -> Response<String> {
+		let QuotaError::Exceeded(ref usage) = self else {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(self.to_string())
+				.unwrap();
+		};
+		let retry_after = chrono::DateTime::parse_from_rfc3339(&usage.resets_date_time)
+			.map(|resets| (resets.timestamp() - Utc::now().timestamp()).max(1))
+			.unwrap_or(1);
+		Response::builder()
+			.status(StatusCode::TOO_MANY_REQUESTS)
+			.header(header::CONTENT_TYPE, "application/json")
+			.header(header::RETRY_AFTER, retry_after.to_string())
+			.body(
+				json!({
+					"error": "quota_exceeded",
+					"message": self.to_string(),
+					"feature": usage.feature,
+					"limit": usage.limit,
+					"used": usage.used,
+					"resets_date_time": usage.resets_date_time,
+				})
+				.to_string(),
+			)
+			.unwrap()
+	}
+}
+
+fn today() -> NaiveDate {
+	Utc::now().date_naive()
+}
+
+fn usage(feature: Feature, limit: i64, used: i64, day: NaiveDate) -> Usage {
+	let resets = day.checked_add_days(Days::new(1)).unwrap_or(day);
+	Usage {
+		feature,
+		limit,
+		used,
+		remaining: (limit - used).max(0),
+		resets_date_time: resets.and_hms_opt(0, 0, 0).unwrap().and_utc().to_rfc3339(),
+	}
+}
+
+// What the user used of the feature today
+pub fn usage_of(user_id: &str, feature: Feature, db_conn: &mut SqliteConnection) -> QueryResult<Usage> {
+	let day = today();
+	let used = quotas::table
+		.find((user_id, feature.as_str(), day.to_string()))
+		.select(quotas::used)
+		.first::<i32>(db_conn)
+		.optional()?
+		.unwrap_or(0);
+	Ok(usage(feature, feature.limit(db_conn), used as i64, day))
+}
+
+// Counts `amount` uses against today's quota, all of them or none
+pub fn take(user_id: &str, feature: Feature, amount: i64, db_conn: &mut SqliteConnection) -> Result<Usage, QuotaError> {
+	take_at_most(user_id, feature, amount, false, db_conn).map(|(usage, _)| usage)
+}
+
+// Counts as many of the `wanted` uses as are left today, along with how many that was. Turned down only when
+// none are left.
+pub fn take_up_to(
+	user_id: &str,
+	feature: Feature,
+	wanted: i64,
+	db_conn: &mut SqliteConnection,
+) -> Result<(Usage, i64), QuotaError> {
+	take_at_most(user_id, feature, wanted, true, db_conn)
+}
+
+fn take_at_most(
+	user_id: &str,
+	feature: Feature,
+	wanted: i64,
+	partial: bool,
+	db_conn: &mut SqliteConnection,
+) -> Result<(Usage, i64), QuotaError> {
+	let limit = feature.limit(db_conn);
+	let day = today();
+	// The write lock up front, two requests can't both take the last use
+	db_conn.immediate_transaction(|conn| {
+		let used = quotas::table
+			.find((user_id, feature.as_str(), day.to_string()))
+			.select(quotas::used)
+			.first::<i32>(conn)
+			.optional()?
+			.unwrap_or(0) as i64;
+		let remaining = (limit - used).max(0);
+		let granted = match partial {
+			true => wanted.min(remaining),
+			false if wanted <= remaining => wanted,
+			false => 0,
+		};
+		if granted == 0 && wanted > 0 {
+			return Err(QuotaError::Exceeded(usage(feature, limit, used, day)));
+		}
+
+		let quota = Quota {
+			user_id: user_id.to_string(),
+			feature: feature.as_str().to_string(),
+			day: day.to_string(),
+			used: granted as i32,
+		};
+		diesel::insert_into(quotas::table)
+			.values(&quota)
+			.on_conflict((quotas::user_id, quotas::feature, quotas::day))
+			.do_update()
+			.set(quotas::used.eq(quotas::used + excluded(quotas::used)))
+			.execute(conn)?;
+		Ok((usage(feature, limit, used + granted, day), granted))
+	})
+}
+
+// Hands back uses taken today for work that failed, the count never goes below 0
+pub fn give_back(user_id: &str, feature: Feature, amount: i64, db_conn: &mut SqliteConnection) -> QueryResult<usize> {
+	diesel::update(quotas::table.find((user_id, feature.as_str(), today().to_string())))
+		.filter(quotas::used.ge(amount as i32))
+		.set(quotas::used.eq(quotas::used - amount as i32))
+		.execute(db_conn)
+}
+
+// Only today's counts matter, the older days are dropped with the rest of the retention
+pub fn prune(db_conn: &mut SqliteConnection) -> QueryResult<usize> {
+	diesel::delete(quotas::table.filter(quotas::day.lt(today().to_string()))).execute(db_conn)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::lobic_db::db::set_instance_setting;
+	use crate::test_support::TestApp;
+
+	#[test]
+	fn the_last_uses_are_handed_out_once() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let mut db_conn = test_app.db_conn();
+		set_instance_setting(&Feature::MetadataLookup.setting_key(), "30", &mut db_conn).unwrap();
+
+		let (usage, granted) = take_up_to(&user_id, Feature::MetadataLookup, 20, &mut db_conn).unwrap();
+		assert_eq!((usage.used, usage.remaining, granted), (20, 10, 20));
+		let (usage, granted) = take_up_to(&user_id, Feature::MetadataLookup, 20, &mut db_conn).unwrap();
+		assert_eq!((usage.used, usage.remaining, granted), (30, 0, 10));
+		match take_up_to(&user_id, Feature::MetadataLookup, 1, &mut db_conn) {
+			Err(QuotaError::Exceeded(usage)) => assert_eq!(usage.used, 30),
+			other => panic!("Expected the quota to be used up, got {other:?}"),
+		}
+
+		// all or nothing, and the other features keep their own count
+		set_instance_setting(&Feature::Export.setting_key(), "2", &mut db_conn).unwrap();
+		assert!(matches!(
+			take(&user_id, Feature::Export, 3, &mut db_conn),
+			Err(QuotaError::Exceeded(_))
+		));
+		assert_eq!(take(&user_id, Feature::Export, 2, &mut db_conn).unwrap().remaining, 0);
+		assert_eq!(usage_of(&user_id, Feature::Transcode, &mut db_conn).unwrap().used, 0);
+
+		// a failed use is handed back
+		give_back(&user_id, Feature::Export, 1, &mut db_conn).unwrap();
+		assert_eq!(usage_of(&user_id, Feature::Export, &mut db_conn).unwrap().remaining, 1);
+		assert_eq!(give_back(&user_id, Feature::Export, 5, &mut db_conn).unwrap(), 0);
+		assert_eq!(give_back(&user_id, Feature::Transcode, 1, &mut db_conn).unwrap(), 0);
+	}
+}
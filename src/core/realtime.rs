@@ -0,0 +1,194 @@
+use crate::core::lobby::{Lobby, LobbyPool};
+use crate::core::user_pool::{Topic, UserPool};
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+pub const REALTIME_CHANNEL: &str = "lobic:realtime";
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// Events shared between the app instances serving the same lobbies
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RealtimeEvent {
+	Deliver {
+		user_id: String,
+		topic: String,
+		message: String,
+	},
+	Broadcast {
+		topic: String,
+		message: String,
+	},
+	LobbyUpsert {
+		lobby: Box<Lobby>,
+	},
+	LobbyDelete {
+		lobby_id: String,
+	},
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+	origin: String,
+	event: RealtimeEvent,
+}
+
+struct Realtime {
+	instance_id: String,
+	publisher: mpsc::UnboundedSender<String>,
+}
+
+static REALTIME: OnceLock<Realtime> = OnceLock::new();
+
+pub fn enabled() -> bool {
+	REALTIME.get().is_some()
+}
+
+// Hands the event over to the other instances, does nothing when running on a single instance
+pub fn publish(event: RealtimeEvent) -> bool {
+	let realtime = match REALTIME.get() {
+		Some(realtime) => realtime,
+		None => return false,
+	};
+
+	let envelope = Envelope {
+		origin: realtime.instance_id.clone(),
+		event,
+	};
+	realtime
+		.publisher
+		.send(serde_json::to_string(&envelope).unwrap())
+		.is_ok()
+}
+
+// Starts the redis fan-out when REDIS_URL is set
+pub fn start(user_pool: UserPool, lobby_pool: LobbyPool) {
+	let redis_url = match std::env::var("REDIS_URL") {
+		Ok(url) if !url.is_empty() => url,
+		_ => return,
+	};
+
+	let client = match redis::Client::open(redis_url) {
+		Ok(client) => client,
+		Err(err) => {
+			println!("Invalid REDIS_URL, running without realtime fan-out: {err}");
+			return;
+		}
+	};
+
+	let instance_id = Uuid::new_v4().to_string();
+	let (publisher, receiver) = mpsc::unbounded_channel::<String>();
+	let realtime = Realtime {
+		instance_id: instance_id.clone(),
+		publisher,
+	};
+	if REALTIME.set(realtime).is_err() {
+		return;
+	}
+
+	tokio::spawn(run_publisher(client.clone(), receiver));
+	tokio::spawn(run_subscriber(client, instance_id, user_pool, lobby_pool));
+}
+
+async fn run_publisher(client: redis::Client, mut receiver: mpsc::UnboundedReceiver<String>) {
+	let mut pending: Option<String> = None;
+	loop {
+		let mut conn = match client.get_multiplexed_async_connection().await {
+			Ok(conn) => conn,
+			Err(err) => {
+				println!("Failed to connect to redis: {err}");
+				tokio::time::sleep(RECONNECT_DELAY).await;
+				continue;
+			}
+		};
+
+		loop {
+			let payload = match pending.take() {
+				Some(payload) => payload,
+				None => match receiver.recv().await {
+					Some(payload) => payload,
+					None => return,
+				},
+			};
+
+			let result = redis::cmd("PUBLISH")
+				.arg(REALTIME_CHANNEL)
+				.arg(&payload)
+				.query_async::<i64>(&mut conn)
+				.await;
+			if let Err(err) = result {
+				println!("Failed to publish to redis: {err}");
+				pending = Some(payload);
+				break;
+			}
+		}
+
+		tokio::time::sleep(RECONNECT_DELAY).await;
+	}
+}
+
+async fn run_subscriber(client: redis::Client, instance_id: String, user_pool: UserPool, lobby_pool: LobbyPool) {
+	loop {
+		let mut pubsub = match client.get_async_pubsub().await {
+			Ok(pubsub) => pubsub,
+			Err(err) => {
+				println!("Failed to connect to redis: {err}");
+				tokio::time::sleep(RECONNECT_DELAY).await;
+				continue;
+			}
+		};
+
+		if let Err(err) = pubsub.subscribe(REALTIME_CHANNEL).await {
+			println!("Failed to subscribe to {}: {err}", REALTIME_CHANNEL);
+			tokio::time::sleep(RECONNECT_DELAY).await;
+			continue;
+		}
+
+		let mut messages = pubsub.on_message();
+		while let Some(msg) = messages.next().await {
+			let payload: String = match msg.get_payload() {
+				Ok(payload) => payload,
+				Err(_) => continue,
+			};
+			let envelope: Envelope = match serde_json::from_str(&payload) {
+				Ok(envelope) => envelope,
+				Err(_) => continue,
+			};
+
+			// Our own events have already been applied locally
+			if envelope.origin == instance_id {
+				continue;
+			}
+			apply(envelope.event, &user_pool, &lobby_pool);
+		}
+
+		println!("Lost the redis subscription, reconnecting");
+		tokio::time::sleep(RECONNECT_DELAY).await;
+	}
+}
+
+fn apply(event: RealtimeEvent, user_pool: &UserPool, lobby_pool: &LobbyPool) {
+	match event {
+		RealtimeEvent::Deliver {
+			user_id,
+			topic,
+			message,
+		} => {
+			if let Some(topic) = Topic::parse(&topic) {
+				user_pool.send_local(&user_id, &topic, message);
+			}
+		}
+		RealtimeEvent::Broadcast { topic, message } => {
+			if let Some(topic) = Topic::parse(&topic) {
+				user_pool.broadcast_local(&topic, &message);
+			}
+		}
+		RealtimeEvent::LobbyUpsert { lobby } => lobby_pool.apply_replica(*lobby),
+		RealtimeEvent::LobbyDelete { lobby_id } => lobby_pool.remove_replica(&lobby_id),
+	}
+}
@@ -0,0 +1,166 @@
+use super::{Recommendation, RecommendationRequest, Recommender};
+use crate::lobic_db::db::DatabasePool;
+use crate::lobic_db::models::{Availability, ContentType};
+use crate::schema::{blocked_content, music, play_log};
+use crate::services::profile::BlockTarget;
+
+use diesel::prelude::*;
+use futures::future::BoxFuture;
+use std::collections::{HashMap, HashSet};
+
+// Tracks played by the listeners who also played the seeds, the more of the seeds they played and the more
+// they played the track the higher. Tracks everyone plays are damped so the picks don't all end up the hits.
+#[derive(Debug, Clone, Copy)]
+pub struct CoOccurrence;
+
+impl Recommender for CoOccurrence {
+	fn name(&self) -> &'static str {
+		"co_occurrence"
+	}
+
+	fn recommend<'a>(
+		&'a self,
+		db_pool: &'a DatabasePool,
+		request: &'a RecommendationRequest,
+	) -> BoxFuture<'a, Result<Vec<Recommendation>, String>> {
+		let (db_pool, request) = (db_pool.clone(), request.clone());
+		Box::pin(async move {
+			tokio::task::spawn_blocking(move || score(&db_pool, &request))
+				.await
+				.map_err(|err| format!("The recommendations didn't finish: {err}"))?
+		})
+	}
+}
+
+fn score(db_pool: &DatabasePool, request: &RecommendationRequest) -> Result<Vec<Recommendation>, String> {
+	let mut db_conn = db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+	let (seed_plays, candidate_plays) = diesel::alias!(play_log as seed_plays, play_log as candidate_plays);
+	// only the listeners who played one of the seeds have a say
+	let seed_listeners = || {
+		seed_plays
+			.filter(seed_plays.field(play_log::music_id).eq_any(&request.seeds))
+			.filter(seed_plays.field(play_log::user_times_played).ge(1))
+			.filter(seed_plays.field(play_log::user_id).ne(&request.user_id))
+			.select(seed_plays.field(play_log::user_id))
+	};
+	// left out before the ranking, so they don't take up the places of the ones after them
+	let blocked = |target: BlockTarget| {
+		blocked_content::table
+			.filter(blocked_content::user_id.eq(&request.user_id))
+			.filter(blocked_content::target_type.eq(target.as_str()))
+			.select(blocked_content::target_id)
+	};
+	let plays = play_log::table
+		.inner_join(music::table)
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.filter(music::content_type.eq(ContentType::Music.as_str()))
+		.filter(music::music_id.ne_all(blocked(BlockTarget::Track)))
+		.filter(music::artist.ne_all(blocked(BlockTarget::Artist)))
+		.filter(play_log::user_times_played.ge(1))
+		.filter(play_log::user_id.eq_any(seed_listeners()))
+		.select((play_log::user_id, play_log::music_id, play_log::user_times_played))
+		.load::<(String, String, i32)>(&mut db_conn)
+		.map_err(|err| format!("Database error: {err}"))?;
+	// everyone's listeners of the tracks they played, for the damping
+	let candidates = candidate_plays
+		.filter(candidate_plays.field(play_log::user_id).eq_any(seed_listeners()))
+		.select(candidate_plays.field(play_log::music_id));
+	let audience = play_log::table
+		.filter(play_log::music_id.eq_any(candidates))
+		.filter(play_log::user_times_played.ge(1))
+		.filter(play_log::user_id.ne(&request.user_id))
+		.group_by(play_log::music_id)
+		.select((play_log::music_id, diesel::dsl::count_star()))
+		.load::<(String, i64)>(&mut db_conn)
+		.map_err(|err| format!("Database error: {err}"))?
+		.into_iter()
+		.collect();
+	Ok(rank(plays, &audience, request))
+}
+
+fn rank(
+	plays: Vec<(String, String, i32)>,
+	audience: &HashMap<String, i64>, // listeners of each track
+	request: &RecommendationRequest,
+) -> Vec<Recommendation> {
+	// listener -> track -> plays, dampened so a track on repeat doesn't outweigh the rest
+	let mut listeners: HashMap<String, HashMap<String, f64>> = HashMap::new();
+	for (listener, music_id, plays) in plays {
+		listeners
+			.entry(listener)
+			.or_default()
+			.insert(music_id, (1.0 + plays as f64).ln());
+	}
+
+	let seeds: HashSet<&String> = request.seeds.iter().collect();
+	let excluded: HashSet<&String> = request.exclude.iter().chain(&request.seeds).collect();
+	let mut scores: HashMap<&String, f64> = HashMap::new();
+	for tracks in listeners.values() {
+		let overlap: f64 = tracks
+			.iter()
+			.filter(|(music_id, _)| seeds.contains(music_id))
+			.map(|(_, plays)| plays)
+			.sum();
+		if overlap == 0.0 {
+			continue;
+		}
+		for (music_id, plays) in tracks.iter().filter(|(music_id, _)| !excluded.contains(music_id)) {
+			*scores.entry(music_id).or_default() += overlap * plays;
+		}
+	}
+
+	let mut recommendations: Vec<Recommendation> = scores
+		.into_iter()
+		.map(|(music_id, score)| Recommendation {
+			music_id: music_id.clone(),
+			score: (score / (audience.get(music_id).copied().unwrap_or(1).max(1) as f64).sqrt() * 1000.0).round()
+				/ 1000.0,
+		})
+		.collect();
+	recommendations.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.music_id.cmp(&b.music_id)));
+	// a few spare for the ones the caller drops
+	recommendations.truncate(request.limit * 2);
+	recommendations
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn play(listener: &str, music_id: &str, plays: i32) -> (String, String, i32) {
+		(listener.to_string(), music_id.to_string(), plays)
+	}
+
+	#[test]
+	fn tracks_of_the_listeners_sharing_the_seeds_come_first() {
+		// only the listeners of the seed are loaded, the others only count towards the audience
+		let plays = vec![
+			play("ana", "seed", 5),
+			play("ana", "b-side", 4),
+			play("ana", "hit", 1),
+			play("bikash", "seed", 2),
+			play("bikash", "b-side", 1),
+			play("bikash", "already played", 9),
+		];
+		let audience = HashMap::from([
+			("seed".to_string(), 2),
+			("b-side".to_string(), 2),
+			("hit".to_string(), 3),
+			("already played".to_string(), 1),
+		]);
+		let request = RecommendationRequest {
+			user_id: "me".to_string(),
+			seeds: vec!["seed".to_string()],
+			exclude: vec!["already played".to_string()],
+			limit: 10,
+		};
+		let music_ids: Vec<String> = rank(plays, &audience, &request)
+			.into_iter()
+			.map(|recommendation| recommendation.music_id)
+			.collect();
+		// nobody who played the seed played "unrelated", and the hit is everyone's
+		assert_eq!(music_ids, ["b-side", "hit"]);
+	}
+}
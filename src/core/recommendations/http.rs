@@ -0,0 +1,133 @@
+use super::{Recommendation, RecommendationRequest, Recommender, MAX_LIMIT};
+use crate::core::oauth::hash_secret;
+use crate::lobic_db::db::DatabasePool;
+use crate::utils::jwt;
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+fn http_client() -> &'static reqwest::Client {
+	static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+	CLIENT.get_or_init(|| {
+		reqwest::Client::builder()
+			.timeout(Duration::from_secs(10))
+			.build()
+			.expect("Failed to build the recommender http client")
+	})
+}
+
+// What the service gets of a request, the user only by an id of its own and nothing of what they played
+#[derive(Debug, Serialize)]
+struct HttpRequest<'a> {
+	user: String,
+	seeds: &'a [String],
+	limit: usize, // as many as it gives, the played ones are left out here
+}
+
+#[derive(Debug, Deserialize)]
+struct HttpAnswer {
+	recommendations: Vec<Recommendation>,
+}
+
+// The same for the user on every request so the service can learn their taste, tied to nothing outside of it
+fn opaque_user(user_id: &str) -> String {
+	let secret_key = std::env::var("JWT_SECRET_KEY").unwrap_or_default();
+	hash_secret(&format!("{}:{user_id}", jwt::purpose_key(&secret_key, "recommender")))
+}
+
+// Posts `{ "user", "seeds", "limit" }` as json to RECOMMENDER_URL and takes back
+// `{ "recommendations": [{ "music_id", "score" }] }`, best first. RECOMMENDER_TOKEN goes along as a bearer token.
+#[derive(Debug, Clone)]
+pub struct HttpRecommender {
+	url: String,
+	token: Option<String>,
+}
+
+impl HttpRecommender {
+	pub fn new(url: String, token: Option<String>) -> HttpRecommender {
+		HttpRecommender { url, token }
+	}
+}
+
+impl Recommender for HttpRecommender {
+	fn name(&self) -> &'static str {
+		"http"
+	}
+
+	fn recommend<'a>(
+		&'a self,
+		_db_pool: &'a DatabasePool,
+		request: &'a RecommendationRequest,
+	) -> BoxFuture<'a, Result<Vec<Recommendation>, String>> {
+		Box::pin(async move {
+			let body = HttpRequest {
+				user: opaque_user(&request.user_id),
+				seeds: &request.seeds,
+				limit: MAX_LIMIT,
+			};
+			let mut post = http_client().post(&self.url).json(&body);
+			if let Some(token) = &self.token {
+				post = post.bearer_auth(token);
+			}
+			let answer = post
+				.send()
+				.await
+				.and_then(|response| response.error_for_status())
+				.map_err(|err| format!("Failed to reach the recommender: {err}"))?
+				.json::<HttpAnswer>()
+				.await
+				.map_err(|err| format!("Unexpected recommender response: {err}"))?;
+			Ok(answer.recommendations)
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support::TestApp;
+
+	use axum::{http::HeaderMap, routing::post, Json, Router};
+	use serde_json::{json, Value};
+
+	async fn answer(headers: HeaderMap, Json(request): Json<Value>) -> Json<Value> {
+		let authorized = headers.get("authorization").and_then(|value| value.to_str().ok()) == Some("Bearer secret");
+		let private = request["user"] != "me" && request.get("exclude").is_none();
+		let music_id = match (authorized, private) {
+			(false, _) => "unauthorized",
+			(true, false) => "leaked",
+			(true, true) => "from the model",
+		};
+		Json(json!({ "recommendations": [
+			{ "music_id": music_id, "score": 0.9 },
+			{ "music_id": request["seeds"][0], "score": 0.5 },
+		] }))
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn the_request_goes_to_the_service() {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let url = format!("http://{}/recommend", listener.local_addr().unwrap());
+		tokio::spawn(async move { axum::serve(listener, Router::new().route("/recommend", post(answer))).await });
+
+		let test_app = TestApp::new();
+		let recommender = HttpRecommender::new(url, Some("secret".to_string()));
+		let request = RecommendationRequest {
+			user_id: "me".to_string(),
+			seeds: vec!["seed".to_string()],
+			exclude: vec!["played".to_string()],
+			limit: 5,
+		};
+		let recommendations = recommender
+			.recommend(&test_app.app_state.db_pool, &request)
+			.await
+			.unwrap();
+		assert_eq!(recommendations[0].music_id, "from the model");
+		assert_eq!(recommendations[1].music_id, "seed");
+
+		let broken = HttpRecommender::new("http://127.0.0.1:9/recommend".to_string(), None);
+		assert!(broken.recommend(&test_app.app_state.db_pool, &request).await.is_err());
+	}
+}
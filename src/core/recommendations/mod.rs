@@ -0,0 +1,122 @@
+pub mod co_occurrence;
+pub mod http;
+
+pub use co_occurrence::CoOccurrence;
+pub use http::HttpRecommender;
+
+use crate::core::app_state::AppState;
+use crate::lobic_db::db::DatabasePool;
+use crate::lobic_db::models::{Availability, ContentType, Music};
+use crate::schema::{blocked_content, music};
+use crate::services::profile::BlockTarget;
+
+use diesel::prelude::*;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+pub const DEFAULT_LIMIT: usize = 20;
+pub const MAX_LIMIT: usize = 100;
+pub const MAX_SEEDS: usize = 20;
+
+// What a recommender gets to go on
+#[derive(Debug, Clone)]
+pub struct RecommendationRequest {
+	pub user_id: String,
+	pub seeds: Vec<String>,   // music ids the recommendations should go with
+	pub exclude: Vec<String>, // never recommended, the seeds and what the user already played
+	pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Recommendation {
+	pub music_id: String,
+	pub score: f64, // higher is better, only comparable within one answer
+}
+
+// A source of track recommendations. The built-in one goes by who played what, RECOMMENDER=http hands the
+// requests to an outside service instead.
+pub trait Recommender: fmt::Debug + Send + Sync {
+	fn name(&self) -> &'static str;
+
+	fn recommend<'a>(
+		&'a self,
+		db_pool: &'a DatabasePool,
+		request: &'a RecommendationRequest,
+	) -> BoxFuture<'a, Result<Vec<Recommendation>, String>>;
+}
+
+// The recommender picked by RECOMMENDER, co_occurrence unless it says http and RECOMMENDER_URL is set
+pub fn from_env() -> Arc<dyn Recommender> {
+	let url = std::env::var("RECOMMENDER_URL").ok().filter(|url| !url.is_empty());
+	match (std::env::var("RECOMMENDER").ok().as_deref(), url) {
+		(Some("http"), Some(url)) => {
+			let token = std::env::var("RECOMMENDER_TOKEN")
+				.ok()
+				.filter(|token| !token.is_empty());
+			Arc::new(HttpRecommender::new(url, token))
+		}
+		_ => Arc::new(CoOccurrence),
+	}
+}
+
+pub struct Recommendations {
+	pub backend: &'static str, // the one that answered
+	pub tracks: Vec<(Recommendation, Music)>,
+}
+
+// Asks the configured recommender, the built-in one answers when it fails. Whatever comes back is kept to the
+// tracks that exist, are available and weren't excluded or blocked by the user.
+pub async fn recommend(app_state: &AppState, request: &RecommendationRequest) -> Result<Recommendations, String> {
+	let recommender = &app_state.recommender;
+	let (backend, recommendations) = match recommender.recommend(&app_state.db_pool, request).await {
+		Ok(recommendations) => (recommender.name(), recommendations),
+		Err(err) if recommender.name() != CoOccurrence.name() => {
+			tracing::warn!(
+				"The {} recommender failed, using the built-in one: {err}",
+				recommender.name()
+			);
+			(
+				CoOccurrence.name(),
+				CoOccurrence.recommend(&app_state.db_pool, request).await?,
+			)
+		}
+		Err(err) => return Err(err),
+	};
+
+	let music_ids: Vec<&String> = recommendations
+		.iter()
+		.filter(|recommendation| !request.exclude.contains(&recommendation.music_id))
+		.map(|recommendation| &recommendation.music_id)
+		.collect();
+	let mut db_conn = app_state
+		.db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+	let blocked = |target: BlockTarget| {
+		blocked_content::table
+			.filter(blocked_content::user_id.eq(&request.user_id))
+			.filter(blocked_content::target_type.eq(target.as_str()))
+			.select(blocked_content::target_id)
+	};
+	let mut found = music::table
+		.filter(music::music_id.eq_any(music_ids))
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.filter(music::content_type.eq(ContentType::Music.as_str()))
+		.filter(music::music_id.ne_all(blocked(BlockTarget::Track)))
+		.filter(music::artist.ne_all(blocked(BlockTarget::Artist)))
+		.load::<Music>(&mut db_conn)
+		.map_err(|err| format!("Database error: {err}"))?;
+
+	let mut tracks = Vec::new();
+	for recommendation in recommendations {
+		if tracks.len() == request.limit {
+			break;
+		}
+		if let Some(index) = found.iter().position(|track| track.music_id == recommendation.music_id) {
+			tracks.push((recommendation, found.swap_remove(index)));
+		}
+	}
+	Ok(Recommendations { backend, tracks })
+}
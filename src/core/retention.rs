@@ -0,0 +1,184 @@
+use crate::config::PLAYLIST_COVER_IMG_STORAGE;
+use crate::config::{
+	CLIENT_ERROR_RETENTION_DAYS, DEFAULT_AUDIT_LOG_RETENTION_DAYS, DEFAULT_CHAT_RETENTION_HOURS,
+	DEFAULT_PLAY_EVENT_RETENTION_DAYS,
+};
+use crate::core::app_state::AppState;
+use crate::core::{oauth, quotas, rollups};
+use crate::lobic_db::db::get_instance_setting;
+use crate::schema::{
+	client_errors, play_events, play_rollups_daily, playlist_releases, playlist_trash, playlist_undo, takedown_events,
+	takedowns,
+};
+
+use chrono::{Duration as ChronoDuration, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+pub const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Keys in instance_settings
+pub const CHAT_RETENTION_HOURS: &str = "chat_retention_hours";
+pub const PLAY_EVENT_RETENTION_DAYS: &str = "play_event_retention_days";
+pub const AUDIT_LOG_RETENTION_DAYS: &str = "audit_log_retention_days";
+
+// How long things are kept, the defaults until the admins set otherwise
+#[derive(Debug, Serialize, PartialEq)]
+pub struct RetentionPolicy {
+	pub chat_hours: i64,      // lobby chat and played tracks
+	pub play_event_days: i64, // single plays and daily rollups, only the monthly ones stay after that
+	pub audit_log_days: i64,  // the takedown trail, open appeals keep theirs
+}
+
+impl RetentionPolicy {
+	pub fn load(db_conn: &mut SqliteConnection) -> RetentionPolicy {
+		let setting = |key: &str, default: i64, db_conn: &mut SqliteConnection| {
+			get_instance_setting(key, db_conn)
+				.and_then(|value| value.parse().ok())
+				.unwrap_or(default)
+		};
+		RetentionPolicy {
+			chat_hours: setting(CHAT_RETENTION_HOURS, DEFAULT_CHAT_RETENTION_HOURS, db_conn),
+			play_event_days: setting(PLAY_EVENT_RETENTION_DAYS, DEFAULT_PLAY_EVENT_RETENTION_DAYS, db_conn),
+			audit_log_days: setting(AUDIT_LOG_RETENTION_DAYS, DEFAULT_AUDIT_LOG_RETENTION_DAYS, db_conn),
+		}
+	}
+}
+
+// Drops whatever is past its retention window
+pub fn prune(app_state: &AppState) -> Result<(), String> {
+	let mut db_conn = app_state
+		.db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+	let policy = RetentionPolicy::load(&mut db_conn);
+	let now = Utc::now();
+
+	let chat_cutoff = now.timestamp_millis() - policy.chat_hours * 60 * 60 * 1000;
+	for lobby_id in app_state.lobby_pool.get_ids() {
+		app_state.lobby_pool.prune_history(&lobby_id, chat_cutoff);
+	}
+
+	let play_cutoff = now - ChronoDuration::days(policy.play_event_days);
+	prune_play_events(&play_cutoff.to_rfc3339(), &mut db_conn)
+		.map_err(|err| format!("Failed to prune play events: {err}"))?;
+
+	let audit_cutoff = (now - ChronoDuration::days(policy.audit_log_days)).to_rfc3339();
+	prune_audit_log(&audit_cutoff, &mut db_conn).map_err(|err| format!("Failed to prune the audit log: {err}"))?;
+
+	let client_error_cutoff = (now - ChronoDuration::days(CLIENT_ERROR_RETENTION_DAYS)).to_rfc3339();
+	diesel::delete(client_errors::table.filter(client_errors::reported_date_time.lt(client_error_cutoff)))
+		.execute(&mut db_conn)
+		.map_err(|err| format!("Failed to prune the playback error reports: {err}"))?;
+
+	quotas::prune(&mut db_conn).map_err(|err| format!("Failed to prune the quotas: {err}"))?;
+	oauth::prune(&mut db_conn).map_err(|err| format!("Failed to prune the app codes and tokens: {err}"))?;
+
+	// Expired undo tokens are turned down anyway, this only frees the snapshots
+	diesel::delete(playlist_undo::table.filter(playlist_undo::expires_date_time.lt(now.to_rfc3339())))
+		.execute(&mut db_conn)
+		.map_err(|err| format!("Failed to prune the playlist undo snapshots: {err}"))?;
+
+	// The trash is emptied as it goes, the covers are only kept for a restore
+	let purged = playlist_trash::table
+		.filter(playlist_trash::purge_date_time.lt(now.to_rfc3339()))
+		.select(playlist_trash::playlist_id)
+		.load::<String>(&mut db_conn)
+		.map_err(|err| format!("Failed to empty the playlist trash: {err}"))?;
+	diesel::delete(playlist_trash::table.filter(playlist_trash::playlist_id.eq_any(&purged)))
+		.execute(&mut db_conn)
+		.map_err(|err| format!("Failed to empty the playlist trash: {err}"))?;
+	diesel::delete(playlist_releases::table.filter(playlist_releases::playlist_id.eq_any(&purged)))
+		.execute(&mut db_conn)
+		.map_err(|err| format!("Failed to empty the playlist trash: {err}"))?;
+	for playlist_id in purged {
+		let _ = fs::remove_file(Path::new(PLAYLIST_COVER_IMG_STORAGE).join(format!("{playlist_id}.png")));
+	}
+	Ok(())
+}
+
+// Deletes the play events and daily rollups before the cutoff, the monthly rollups already count them
+pub fn prune_play_events(cutoff: &str, db_conn: &mut SqliteConnection) -> QueryResult<usize> {
+	let cutoff_day = rollups::day_of(cutoff).map(|day| day.to_string()).unwrap_or_default();
+	db_conn.transaction(|conn| {
+		diesel::delete(play_rollups_daily::table.filter(play_rollups_daily::day.lt(cutoff_day))).execute(conn)?;
+		diesel::delete(play_events::table.filter(play_events::played_date_time.lt(cutoff))).execute(conn)
+	})
+}
+
+fn prune_audit_log(cutoff: &str, db_conn: &mut SqliteConnection) -> QueryResult<usize> {
+	let open_appeals = takedowns::table
+		.filter(takedowns::status.eq("appealed"))
+		.select(takedowns::takedown_id);
+	diesel::delete(
+		takedown_events::table
+			.filter(takedown_events::created_date_time.lt(cutoff))
+			.filter(takedown_events::takedown_id.ne_all(open_appeals)),
+	)
+	.execute(db_conn)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::lobic_db::models::{MonthlyPlayRollup, PlayEvent};
+	use crate::schema::{music, play_rollups_monthly};
+	use crate::test_support::TestApp;
+
+	#[tokio::test]
+	async fn pruned_plays_stay_in_the_monthly_rollups() {
+		let test_app = TestApp::seeded();
+		let mut db_conn = test_app.db_conn();
+		let user_id = test_app.user_id("seed_user_0");
+		let music_id = music::table
+			.select(music::music_id)
+			.first::<String>(&mut db_conn)
+			.unwrap();
+
+		let played = |date_time: &str, listened_secs: i64| PlayEvent {
+			event_id: uuid::Uuid::new_v4().to_string(),
+			user_id: user_id.clone(),
+			music_id: music_id.clone(),
+			played_date_time: date_time.to_string(),
+			listened_secs,
+			device: None,
+		};
+		let events = vec![
+			played("2020-03-10T12:00:00+00:00", 100),
+			played("2020-03-20T12:00:00+00:00", 50),
+			played("2024-06-01T12:00:00+00:00", 10),
+		];
+		diesel::insert_into(play_events::table)
+			.values(&events)
+			.execute(&mut db_conn)
+			.unwrap();
+		rollups::roll_up(&events, &mut db_conn).unwrap();
+
+		let removed = prune_play_events("2021-01-01T00:00:00+00:00", &mut db_conn).unwrap();
+		assert_eq!(removed, 2);
+
+		let march = play_rollups_monthly::table
+			.filter(play_rollups_monthly::user_id.eq(&user_id))
+			.filter(play_rollups_monthly::month.eq("2020-03"))
+			.first::<MonthlyPlayRollup>(&mut db_conn)
+			.unwrap();
+		assert_eq!((march.play_count, march.listened_secs), (2, 150));
+		let days = play_rollups_daily::table
+			.filter(play_rollups_daily::user_id.eq(&user_id))
+			.filter(play_rollups_daily::day.lt("2021-01-01"))
+			.count()
+			.get_result::<i64>(&mut db_conn)
+			.unwrap();
+		assert_eq!(days, 0);
+		let days = play_rollups_daily::table
+			.filter(play_rollups_daily::user_id.eq(&user_id))
+			.filter(play_rollups_daily::day.eq("2024-06-01"))
+			.count()
+			.get_result::<i64>(&mut db_conn)
+			.unwrap();
+		assert_eq!(days, 1);
+	}
+}
@@ -0,0 +1,88 @@
+use crate::lobic_db::models::{DailyPlayRollup, MonthlyPlayRollup, PlayEvent};
+use crate::schema::{play_rollups_daily, play_rollups_monthly};
+
+use chrono::{DateTime, Duration, Local, NaiveDate};
+use diesel::prelude::*;
+use std::collections::HashMap;
+
+// Days, today included, that the stats take from the single plays, everything before comes from the rollups
+pub const RECENT_DAYS: i64 = 2;
+
+// Rollup days and months are local time, like the stats
+pub fn day_of(date_time: &str) -> Option<NaiveDate> {
+	DateTime::parse_from_rfc3339(date_time)
+		.ok()
+		.map(|date_time| date_time.with_timezone(&Local).date_naive())
+}
+
+pub fn month_of(day: NaiveDate) -> String {
+	day.format("%Y-%m").to_string()
+}
+
+// The first day the stats read from the play events
+pub fn recent_since() -> NaiveDate {
+	Local::now().date_naive() - Duration::days(RECENT_DAYS - 1)
+}
+
+// Adds the plays onto the daily and monthly rollups, in the same transaction that stores them
+pub fn roll_up(events: &[PlayEvent], conn: &mut SqliteConnection) -> QueryResult<()> {
+	let mut daily: HashMap<(&str, NaiveDate, &str), (i32, i64)> = HashMap::new();
+	for event in events {
+		let Some(day) = day_of(&event.played_date_time) else {
+			continue;
+		};
+		let (play_count, listened_secs) = daily.entry((&event.user_id, day, &event.music_id)).or_default();
+		*play_count += 1;
+		*listened_secs += event.listened_secs;
+	}
+
+	let mut monthly: HashMap<(&str, String, &str), (i32, i64)> = HashMap::new();
+	for ((user_id, day, music_id), (play_count, listened_secs)) in daily {
+		let rollup = monthly.entry((user_id, month_of(day), music_id)).or_default();
+		rollup.0 += play_count;
+		rollup.1 += listened_secs;
+
+		diesel::insert_into(play_rollups_daily::table)
+			.values(DailyPlayRollup {
+				user_id: user_id.to_string(),
+				day: day.to_string(),
+				music_id: music_id.to_string(),
+				play_count,
+				listened_secs,
+			})
+			.on_conflict((
+				play_rollups_daily::user_id,
+				play_rollups_daily::day,
+				play_rollups_daily::music_id,
+			))
+			.do_update()
+			.set((
+				play_rollups_daily::play_count.eq(play_rollups_daily::play_count + play_count),
+				play_rollups_daily::listened_secs.eq(play_rollups_daily::listened_secs + listened_secs),
+			))
+			.execute(conn)?;
+	}
+
+	for ((user_id, month, music_id), (play_count, listened_secs)) in monthly {
+		diesel::insert_into(play_rollups_monthly::table)
+			.values(MonthlyPlayRollup {
+				user_id: user_id.to_string(),
+				month,
+				music_id: music_id.to_string(),
+				play_count,
+				listened_secs,
+			})
+			.on_conflict((
+				play_rollups_monthly::user_id,
+				play_rollups_monthly::month,
+				play_rollups_monthly::music_id,
+			))
+			.do_update()
+			.set((
+				play_rollups_monthly::play_count.eq(play_rollups_monthly::play_count + play_count),
+				play_rollups_monthly::listened_secs.eq(play_rollups_monthly::listened_secs + listened_secs),
+			))
+			.execute(conn)?;
+	}
+	Ok(())
+}
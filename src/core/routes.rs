@@ -1,6 +1,19 @@
 use crate::{
-	core::app_state::AppState,
+	core::{app_state::AppState, body_limit, ip_filter, maintenance, oauth},
 	routes::{
+		animated_cover::{
+			get_animated_cover, get_animated_cover_poster, get_animated_cover_video, remove_animated_cover,
+			upload_animated_cover,
+		},
+		cover_art::{get_cover_art, get_cover_art_file, select_cover_art, upload_cover_art},
+		achievements::get_achievements,
+		goals::{create_goal, delete_goal, get_goal, get_goals},
+		analytics::{get_daily_analytics, get_retention, get_top_content},
+		artist_images::{fetch_artist_images, get_artist_images},
+		audiobooks::{
+			get_audiobook_authors, get_audiobook_series, get_audiobooks, get_audiobooks_in_progress,
+			set_audiobook_progress, set_content_type,
+		},
 		auth::{
 			change_password::change_password,
 			login::login,
@@ -9,49 +22,126 @@ use crate::{
 			signup::signup,
 			verify::{verify, verify_email},
 		},
-		get_lobby::get_lobby,
+		capabilities::get_capabilities,
+		client_errors::{get_client_errors, report_client_error},
+		federation::{
+			handshake::handshake,
+			peers::{add_peer, create_invite, get_peers, revoke_peer},
+			remote::{remote_event, remote_join, remote_leave, remote_message, remote_stream},
+			shared_lobby::{join_shared_lobby, leave_shared_lobby, relay_music, send_shared_lobby_message},
+		},
+		doctor::get_doctor,
+		embed::{
+			create_embed_token, get_embed_tokens, get_embedded_playlist, get_embedded_profile, revoke_embed_token,
+		},
+		feed::get_new_releases,
+		get_lobby::{get_lobby, get_lobby_queue},
+		history::get_listening_sessions,
+		imports::{get_import, get_imports, get_ratings, import_curation},
+		lobby_chat::{export_chat, get_chat_retention, set_chat_retention},
+		maintenance::{get_maintenance, set_maintenance},
+		instance_info::get_instance_info,
+		ip_rules::{add_ip_rule, get_ip_rules, remove_ip_rule},
+		mail_preview::{get_mail_templates, preview_mail},
 		music::{
+			availability::{check_availability::check_availability, set_availability::set_availability},
 			browse_category::{
 				browse_albums::browse_albums, browse_artists::browse_artists, browse_genres::browse_genres,
+				browse_years::browse_years,
 			},
 			get_cover_image::get_cover_image,
+			get_cover_palette::get_cover_palette,
 			get_music::get_music,
+			get_playback_info::get_playback_info,
+			labels::get_labels,
 			liked_songs::{
 				add_to_liked_song::add_to_liked_songs, get_liked_songs::get_liked_songs, is_song_liked::is_song_liked,
 				remove_from_liked_songs::remove_from_liked_songs, toggle_liked_song::toggle_liked_song,
 			},
 			log_song_play::log_song_play,
+			alt_names::{get_alt_names, set_alt_names},
+			artist_follows::{follow_artist, get_followed_artists, unfollow_artist},
+			lookup::lookup_music,
+			moods::{get_moods, set_mood},
+			playlist_membership::get_playlist_membership,
+			recommendations::get_recommendations,
+			related_artists::get_related_artists,
 			recently_played::get_recently_played::get_recently_played,
 			save_music::save_music,
 			search_music::search_music,
 			send_music::send_music,
+			sign_images::sign_images,
 			top_tracks::get_top_tracks::get_top_tracks,
 			trending::get_trending_songs::get_trending_songs,
 		},
 		notify::{get_all_notif, remove_notif},
+		overlay::get_overlay_now_playing,
+		oauth::{
+			authorize_oauth_client, delete_oauth_client, get_oauth_clients, get_oauth_consent, get_oauth_grants,
+			oauth_token, register_oauth_client, reset_oauth_client_secret, revoke_oauth_grant,
+		},
+		player::{get_friends_activity, get_now_playing, get_player_state, get_resume, heartbeat, set_player_state},
+		queue_snapshots::{delete_queue_snapshot, get_queue_snapshots, restore_queue_snapshot, save_queue_snapshot},
 		playlist::{
 			add_song_to_playlist::add_song_to_playlist,
+			clear_playlist::clear_playlist,
 			combined_playlist::{
 				add_contributor::add_contributor, fetch_all_contributors::fetch_all_contributors,
 				remove_contributor::remove_contributor,
 			},
 			create_new_playlist::create_playlist,
 			delete_playlist::delete_playlist,
+			folders::{
+				create_playlist_folder, delete_playlist_folder, get_playlist_folders, move_playlist, move_playlist_folder,
+				rename_playlist_folder,
+			},
 			get_playlist_cover_img::get_playlist_cover_img,
 			get_playlist_music::get_playlist_music,
 			get_users_playlists::get_users_playlists,
+			releases::{cancel_playlist_release, get_playlist_releases, schedule_playlist_release},
 			remove_song_from_playlist::remove_song_from_playlist,
+			saved_searches::{delete_saved_search, get_saved_search, get_users_saved_searches, save_search},
+			trash::{get_playlist_trash, purge_trashed_playlist, restore_trashed_playlist},
+			undo_playlist_edit::undo_playlist_edit,
 			update_playlist_cover_img::update_playlist_cover_img,
 		},
+		play_rule::{get_play_rule, set_play_rule},
+		quotas::{get_quota_limits, get_quotas, set_quota_limits},
+		retention::{get_retention_policy, set_retention_policy},
+		scrobble::{create_scrobble_token, get_scrobble_tokens, report_play, revoke_scrobble_token},
 		search::search,
+		slow_queries::get_slow_queries,
 		socket::websocket_handler,
+		stats::{
+			compare_stats::compare_stats, get_genres_over_time::get_genres_over_time, get_heatmap::get_heatmap,
+			get_leaderboard::get_leaderboard, get_on_this_day::get_on_this_day,
+		},
+		tags::{
+			add_tag, block_tag, get_blocked_tags, get_tags, get_target_tags, moderate_remove_tag, remove_tag, unblock_tag,
+		},
+		takedown::{appeal_takedown, create_takedown, get_takedown, get_takedowns, resolve_takedown},
+		telemetry::{get_telemetry, set_telemetry},
+		transcode_cache::{
+			evict_transcode_cache, get_transcode_cache, purge_transcode_cache, set_transcode_cache_limits,
+		},
 		users::{
-			add_friend::add_friend, get_friend::get_friend, get_user::get_user, get_user_data::get_user_data,
-			get_user_pfp::get_user_pfp, remove_friend::remove_friend, search_user::search_user, update_pfp::update_pfp,
+			add_friend::add_friend,
+			anthem::{clear_anthem, get_anthem_preview, set_anthem},
+			blocked_content::{block_content, get_blocked_content, unblock_content},
+			get_friend::get_friend, get_user::get_user, get_user_data::get_user_data,
+			get_user_pfp::get_user_pfp,
+			pins::{get_pins, pin_item, unpin_item},
+			remove_friend::remove_friend,
+			search_user::search_user,
+			settings::{confirm_unsubscribe, get_settings, unsubscribe_weekly_email, update_settings},
+			update_pfp::update_pfp,
 		},
+		webhooks::{add_webhook, get_webhooks, remove_webhook},
 	},
 };
 use axum::{
+	extract::DefaultBodyLimit,
+	middleware,
 	routing::{get, post},
 	Router,
 };
@@ -62,6 +152,8 @@ pub fn configure_routes(app_state: AppState) -> Router {
 		.route("/save_music", post(save_music))
 		//auth
 		.route("/", get(index))
+		.route("/instance/info", get(get_instance_info)) //what the instance advertises about itself
+		.route("/api/capabilities", get(get_capabilities)) //features the clients can rely on
 		.route("/get_user", get(get_user))
 		.route("/signup", post(signup))
 		.route("/login", post(login))
@@ -76,18 +168,59 @@ pub fn configure_routes(app_state: AppState) -> Router {
 		// email routes
 		.route("/email/verify/:id", get(verify_email))
 		//base
-		.route("/music/:music_id", get(send_music)) //get actual mp3 music
-		.route("/image/:img_uuid", get(get_cover_image)) //get the png cover image
+		.route("/music/:music_id", get(send_music)) //get actual mp3 music, ?bitrate= for a transcode when ffmpeg runs
+		.route("/music/playback_info/:music_id", get(get_playback_info)) //track info, stream url and chapters
+		.route("/music/:music_id/playlist_membership", get(get_playlist_membership)) //the session user's playlists that have it
+		.route("/music/lookup", post(lookup_music)) //{ music_ids }, the tracks in that order along with the ids not found
+		.route("/image/:img_uuid", get(get_cover_image)) //the album's cover, else one of the album with other credits, the artist's picture or a placeholder (?format=png|svg&size=), x-cover-source says which
+		.route("/images/sign", post(sign_images)) //{ img_uuids, sizes? }, signed urls with per-size variants for the grids
+		.route("/image/:img_uuid/palette", get(get_cover_palette)) //optional ?count=, colors for theming the player
 		//music data
 		.route("/search_music", get(search_music))
 		.route("/music/get_music", get(get_music))
 		//browse category
 		.route("/music/browse_artists", get(browse_artists)) //returns Vec<artist, song_count ,Vec<image_uuid>>/ the image_uuids is capped to 4
 		.route("/music/browse_albums", get(browse_albums)) //returns Vec<album, song_count ,Vec<image_uuid>>/ the image_uuids is capped to 4
-		.route("/music/browse_genres", get(browse_genres)) //returns Vec<genre, song_count >
+		.route("/music/browse_genres", get(browse_genres)) //returns Vec<genre, label, song_count >
+		.route("/music/browse/years", get(browse_years)) //returns Vec<decade, song_count, Vec<year, song_count>>, latest first
+		//moods, radio is get_music?mood=&randomizer=true
+		.route("/music/moods", get(get_moods)) //returns Vec<mood, label, song_count, average_energy>
+		.route("/music/labels", get(get_labels)) //display names of the genres and moods in the user's language
+		.route("/music/mood/set", post(set_mood)) //admins only, replaces the mood worked out from the tempo and key
+		.route("/music/artist/followed", get(get_followed_artists))
+		.route("/music/artist/follow", post(follow_artist)) //{ artist }, its tracks the scanner adds go in /feed/new_releases
+		.route("/music/artist/unfollow", post(unfollow_artist))
+		.route("/feed/new_releases", get(get_new_releases)) //paged, the latest first
+		.route("/music/recommendations", get(get_recommendations)) //?seeds=id,id&limit=, the most played when no seeds, a list of { score, music } with the backend and seeds
+		.route("/music/artist/:artist/related", get(get_related_artists)) //fans also listen to, a list of { artist, weight, shared_listeners } with the artist and its listeners
+		.route("/music/alt_names/:music_id", get(get_alt_names)) //other forms of the title and artist, searched along with them
+		.route("/music/alt_names/set", post(set_alt_names)) //admins only, { music_id, names: [{ field, name, kind }] }
+		//availability
+		.route("/music/availability/set", post(set_availability))
+		.route("/music/availability/check", post(check_availability)) //flags musics whose files are missing
+		.route("/music/content_type/set", post(set_content_type)) //music, audiobook or podcast, admins only
+		//audiobooks, kept out of shuffle, trending and browse
+		.route("/audiobooks", get(get_audiobooks)) //optional ?author=&series=, with the progress of the logged in user
+		.route("/audiobooks/authors", get(get_audiobook_authors))
+		.route("/audiobooks/series", get(get_audiobook_series)) //optional ?author=
+		.route("/audiobooks/in_progress", get(get_audiobooks_in_progress)) //most recently listened first
+		.route("/audiobooks/progress", post(set_audiobook_progress))
 		//recently played
 		.route("/music/log_song_play", post(log_song_play))
 		.route("/music/get_recently_played", get(get_recently_played))
+		//headless players, they only get to report plays
+		.route("/user/scrobble_tokens", get(get_scrobble_tokens))
+		.route("/user/scrobble_tokens/new", post(create_scrobble_token)) //{ name }, the token is only shown once
+		.route("/user/scrobble_tokens/revoke/:token_id", post(revoke_scrobble_token))
+		.route("/playlog/report", post(report_play)) //Authorization: Bearer <scrobble token>
+		.route("/client_errors", post(report_client_error)) //{ music_id, error_code, device?, network_type?, message? } when a track fails to play
+		//widgets on other sites, read only and for a single playlist or profile
+		.route("/user/embed_tokens", get(get_embed_tokens))
+		.route("/user/embed_tokens/new", post(create_embed_token)) //{ target_type: playlist | profile, target_id }, the token is only shown once
+		.route("/user/embed_tokens/revoke/:token_id", post(revoke_embed_token))
+		.route("/embed/playlist/:playlist_id", get(get_embedded_playlist)) //?token=
+		.route("/embed/profile/:user_id", get(get_embedded_profile)) //?token=
+		.route("/overlay/:token/now_playing", get(get_overlay_now_playing)) //?format=json|html, for OBS browser sources
 		//trending songs
 		.route("/music/get_trending", get(get_trending_songs))
 		//top tracks of a particular user
@@ -98,6 +231,10 @@ pub fn configure_routes(app_state: AppState) -> Router {
 		.route("/music/liked_song/get", get(get_liked_songs))
 		.route("/music/liked_song/is_song_liked", get(is_song_liked))
 		.route("/music/liked_song/toggle_like", post(toggle_liked_song))
+		//curation brought over from other servers
+		.route("/user/import/curation", post(import_curation)) //?format=navidrome|plex|itunes&overwrite=&dry_run=, the export file as the body
+		.route("/user/ratings", get(get_ratings)) //the highest first
+		.route("/user/quotas", get(get_quotas)) //today's use of the transcode, export and metadata_lookup quotas
 		//playlist stuff
 		.route("/playlist/new", post(create_playlist))
 		.route("/playlist/add_song", post(add_song_to_playlist))
@@ -105,8 +242,41 @@ pub fn configure_routes(app_state: AppState) -> Router {
 		.route("/playlist/get_users_playlists", get(get_users_playlists))
 		.route("/playlist/update_cover_img", post(update_playlist_cover_img))
 		.route("/playlist/cover_img/:playlist_id", get(get_playlist_cover_img))
+		//animated covers, target is album (by cover image uuid) or playlist
+		.route("/animated_cover/:target/:target_id", get(get_animated_cover))
+		.route("/animated_cover/:target/:target_id/video", get(get_animated_cover_video)) //looping mp4
+		.route("/animated_cover/:target/:target_id/poster", get(get_animated_cover_poster)) //falls back to the static cover
+		.route("/animated_cover/:target/:target_id/upload", post(upload_animated_cover)) //up to MAX_ANIMATED_COVER_BYTES
+		.route("/animated_cover/:target/:target_id/remove", post(remove_animated_cover))
+		//cover art, target is album (by cover image uuid) or track, the tracks of an album share its art
+		.route("/cover_art/:target/:target_id", get(get_cover_art))
+		.route("/cover_art/:target/:target_id/upload", post(upload_cover_art)) //multipart, the image field
+		.route("/cover_art/:target/:target_id/select/:art_id", post(select_cover_art))
+		.route("/cover_art/file/:art_id", get(get_cover_art_file))
 		.route("/playlist/remove_song_from_playlist", post(remove_song_from_playlist))
 		.route("/playlist/delete/:curr_playlist_id", post(delete_playlist))
+		.route("/playlist/clear", post(clear_playlist))
+		.route("/undo/:token", post(undo_playlist_edit)) //removals, clears and deletes hand out the token
+		//deleted playlists, kept for PLAYLIST_TRASH_DAYS and only shown to their owner
+		.route("/playlists/trash", get(get_playlist_trash))
+		.route("/playlists/trash/restore/:playlist_id", post(restore_trashed_playlist))
+		.route("/playlists/trash/delete/:playlist_id", post(purge_trashed_playlist)) //for good
+		//scheduled releases, private until then
+		.route("/playlist/releases", get(get_playlist_releases)) //the user's upcoming ones, soonest first
+		.route("/playlist/releases/schedule", post(schedule_playlist_release)) //{ playlist_id, release_date_time }
+		.route("/playlist/releases/cancel/:playlist_id", post(cancel_playlist_release))
+		//folders, per user and only for where they see the playlists
+		.route("/playlist/folders", get(get_playlist_folders)) //the whole tree, folders first
+		.route("/playlist/folders/new", post(create_playlist_folder)) //{ name, parent_id? }
+		.route("/playlist/folders/rename", post(rename_playlist_folder))
+		.route("/playlist/folders/move", post(move_playlist_folder)) //{ folder_id, parent_id?, position? }
+		.route("/playlist/folders/delete/:folder_id", post(delete_playlist_folder)) //its content moves up a level
+		.route("/playlist/folders/move_playlist", post(move_playlist)) //{ playlist_id, folder_id?, position? }
+		//saved searches, listed with the playlists and run again on every open
+		.route("/playlist/saved_search/new", post(save_search))
+		.route("/playlist/saved_search/get_users_saved_searches", get(get_users_saved_searches))
+		.route("/playlist/saved_search/get/:search_id", get(get_saved_search))
+		.route("/playlist/saved_search/delete/:search_id", post(delete_saved_search))
 		//combined playlists
 		.route("/playlist/combined/add_contributor", post(add_contributor))
 		.route("/playlist/combined/remove_contributor", post(remove_contributor))
@@ -119,16 +289,143 @@ pub fn configure_routes(app_state: AppState) -> Router {
 		.route("/user/get_pfp/:filename", get(get_user_pfp)) // @TODO : support non png
 		.route("/user/get_user_data", get(get_user_data))
 		.route("/user/search", get(search_user))
+		.route("/user/settings", get(get_settings))
+		.route("/user/settings/update", post(update_settings)) //only the given fields are changed
+		.route("/mail/unsubscribe", get(confirm_unsubscribe).post(unsubscribe_weekly_email)) //?token= from the weekly email, no login needed. get asks, post unsubscribes
+		.route("/user/pins/get/:user_id", get(get_pins)) //also part of get_user_data
+		.route("/user/pins/pin", post(pin_item)) //playlist, album or track, optional position
+		.route("/user/pins/unpin", post(unpin_item))
+		.route("/user/anthem/set", post(set_anthem)) //track and optional start offset, also part of get_user_data
+		.route("/user/anthem/clear", post(clear_anthem))
+		.route("/users/me/blocked_content", get(get_blocked_content)) //never play this, { tracks, artists }
+		.route("/users/me/blocked_content/add", post(block_content)) //{ target_type: track | artist, target_id }
+		.route("/users/me/blocked_content/remove", post(unblock_content))
+		.route("/user/anthem/:user_id/preview", get(get_anthem_preview)) //the mp3 from the start offset, ANTHEM_PREVIEW_SECS long
 		//friends stuff
 		.route("/friend/add", post(add_friend))
 		.route("/friend/remove", post(remove_friend))
 		.route("/friend/get/:user_id", get(get_friend))
+		//stats
+		.route("/stats/compare", get(compare_stats)) //?user=A&friend=B, both have to be friends and not keep their stats private
+		.route("/stats/leaderboard", get(get_leaderboard)) //?metric=minutes|artists, this week among the friends
+		.route("/stats/heatmap", get(get_heatmap)) //?user_id=&year=, listening minutes per day
+		.route("/stats/genres_over_time", get(get_genres_over_time)) //?user_id=&year=, monthly genre shares
+		.route("/stats/on_this_day", get(get_on_this_day)) //?user_id=, first listens on this day in earlier years
+		.route("/history/sessions", get(get_listening_sessions)) //?days=, the user's own plays grouped into sessions, latest first
+		.route("/achievements/:user_id", get(get_achievements)) //optional ?status=earned|locked
+		.route("/goals", get(get_goals)) //optional ?status=active|completed|expired
+		.route("/goals/new", post(create_goal)) //{kind: plays|minutes|new_artists|new_tracks, target, period: week|month|year}
+		.route("/goals/get/:goal_id", get(get_goal))
+		.route("/goals/delete/:goal_id", post(delete_goal))
 		//notification
 		.route("/notif/get/:client_id", get(get_all_notif))
 		.route("/notif/delete/:notif_id", post(remove_notif))
+		//player
+		.route("/player/heartbeat", post(heartbeat)) //same as the PLAYER_HEARTBEAT ws op
+		.route("/player/now_playing/:user_id", get(get_now_playing)) //null when the user isn't listening
+		.route("/player/resume", get(get_resume))
+		.route("/player/friends_activity", get(get_friends_activity))
+		.route("/player/state", get(get_player_state).put(set_player_state)) //put {action: play|pause|seek|volume}, relayed as PLAYER_COMMAND
+		.route("/player/queue_snapshots", get(get_queue_snapshots))
+		.route("/player/queue_snapshots/new", post(save_queue_snapshot)) //{name, music_ids?, position?}, the last reported queue when left out
+		.route("/player/queue_snapshots/restore/:snapshot_id", post(restore_queue_snapshot))
+		.route("/player/queue_snapshots/delete/:snapshot_id", post(delete_queue_snapshot))
+		//listener tags on tracks and albums, get_music?tag= plays them
+		.route("/tags", get(get_tags)) //optional ?prefix=, returns Vec<tag, use_count, track_count, album_count>
+		.route("/tags/:target_type/:target_id", get(get_target_tags)) //track or album, albums by name
+		.route("/tags/add", post(add_tag))
+		.route("/tags/remove", post(remove_tag)) //only the user's own tag
+		.route("/admin/tags/remove", post(moderate_remove_tag)) //for every user
+		.route("/admin/tags/block", post(block_tag)) //removes every use and keeps it from coming back
+		.route("/admin/tags/unblock", post(unblock_tag))
+		.route("/admin/tags/blocked", get(get_blocked_tags))
+		//takedowns
+		.route("/admin/takedown", post(create_takedown))
+		.route("/admin/takedown/resolve", post(resolve_takedown))
+		.route("/admin/takedowns", get(get_takedowns)) //optional ?status=active|appealed|reinstated|upheld
+		.route("/takedown/appeal", post(appeal_takedown))
+		.route("/takedown/:takedown_id", get(get_takedown)) //takedown along with its trail
+		//instance analytics, refreshed hourly
+		.route("/admin/analytics/daily", get(get_daily_analytics)) //?days=, DAU/WAU/MAU and plays per day
+		.route("/admin/analytics/retention", get(get_retention)) //weekly cohorts by first play
+		.route("/admin/analytics/top_content", get(get_top_content)) //last 30 days
+		.route("/admin/analytics/client_errors", get(get_client_errors)) //?days=, live, failing tracks checked against their files
+		//query log, needs QUERY_LOG=true
+		.route("/admin/slow_queries", get(get_slow_queries)) //?limit=, slowest first
+		//library scans, also sent live to the admins on the imports topic
+		.route("/admin/imports", get(get_imports))
+		.route("/admin/imports/:import_id", get(get_import))
+		//artist pictures from the provider, what the covers fall back to after the album's
+		.route("/admin/artist_images", get(get_artist_images))
+		.route("/admin/artist_images/fetch", post(fetch_artist_images)) //{ artists? }, the ones without a picture when left out
+		//mail templates rendered with sample data
+		.route("/admin/mail/templates", get(get_mail_templates))
+		.route("/admin/mail/preview/:template", get(preview_mail)) //?lang=en|ne&format=html|text
+		//telemetry, off unless the admins opt in
+		.route("/admin/telemetry", get(get_telemetry)) //shows the exact payload that would be sent
+		.route("/admin/telemetry", post(set_telemetry))
+		//how long chat, single plays and the takedown trail are kept, older plays become monthly rollups
+		.route("/admin/retention", get(get_retention_policy))
+		.route("/admin/retention", post(set_retention_policy)) //only the windows given change
+		.route("/admin/play_rule", get(get_play_rule))
+		.route("/admin/play_rule", post(set_play_rule)) //{ min_percent?, min_secs? }, what counts as a play
+		.route("/admin/quotas", get(get_quota_limits))
+		.route("/admin/quotas", post(set_quota_limits)) //{ transcode?, export?, metadata_lookup? }, per user and day, 0 turns it off
+		.route("/admin/transcode_cache", get(get_transcode_cache)) //size, limits and the biggest tracks
+		.route("/admin/transcode_cache", post(set_transcode_cache_limits)) //{ max_mb?, low_water_percent? }
+		.route("/admin/transcode_cache/evict", post(evict_transcode_cache)) //the least recently streamed first, when over max_mb
+		.route("/admin/transcode_cache/purge/:music_id", post(purge_transcode_cache)) //every bitrate of the track
+		.route("/admin/webhooks", get(get_webhooks))
+		.route("/admin/webhooks/add", post(add_webhook)) //{ url }, library changes get posted there
+		.route("/admin/webhooks/remove/:webhook_id", post(remove_webhook))
+		//ip rules, client addresses come from X-Forwarded-For when the peer is in TRUSTED_PROXIES
+		.route("/admin/ip_rules", get(get_ip_rules))
+		.route("/admin/ip_rules/add", post(add_ip_rule)) //{ cidr, action: allow|deny, note }
+		.route("/admin/ip_rules/remove/:rule_id", post(remove_ip_rule))
+		//federation, admin side
+		.route("/admin/federation/invite", post(create_invite)) //returns the token to hand over to the other instance
+		.route("/admin/federation/peer", post(add_peer))
+		.route("/admin/federation/peers", get(get_peers))
+		.route("/admin/federation/revoke/:peer_id", post(revoke_peer))
+		//federation, called by the peers
+		.route("/federation/handshake", post(handshake))
+		.route("/federation/remote/join", post(remote_join))
+		.route("/federation/remote/leave", post(remote_leave))
+		.route("/federation/remote/message", post(remote_message))
+		.route("/federation/remote/event", post(remote_event))
+		.route("/federation/remote/stream/:music_id", get(remote_stream))
+		//federation, called by our users
+		.route("/federation/lobby/join", post(join_shared_lobby))
+		.route("/federation/lobby/leave", post(leave_shared_lobby))
+		.route("/federation/lobby/message", post(send_shared_lobby_message))
+		.route("/federation/relay/:peer_id/:music_id", get(relay_music))
+		//oauth, third party apps get tokens for the users who allow them, used as Authorization: Bearer
+		.route("/oauth/clients", get(get_oauth_clients)) //the apps the user registered
+		.route("/oauth/clients/new", post(register_oauth_client)) //{ name, redirect_uris, public }, the secret is only shown once, public apps get none
+		.route("/oauth/clients/reset_secret/:client_id", post(reset_oauth_client_secret)) //the new secret is only shown once
+		.route("/oauth/clients/delete/:client_id", post(delete_oauth_client))
+		.route("/oauth/authorize", get(get_oauth_consent).post(authorize_oauth_client)) //get ?response_type=code&client_id=&redirect_uri=&scope=&state=&code_challenge=&code_challenge_method=S256 for the consent screen, post { ..., approve }
+		.route("/oauth/token", post(oauth_token)) //form encoded, grant_type=authorization_code|refresh_token
+		.route("/oauth/grants", get(get_oauth_grants)) //the apps the user allowed in
+		.route("/oauth/grants/revoke/:client_id", post(revoke_oauth_grant))
 		//ws
 		.route("/ws", get(websocket_handler))
 		.route("/get_lobby/:lobby_id", get(get_lobby))
+		.route("/lobby/:lobby_id/queue", get(get_lobby_queue)) //current track and queue, with prefetch hints for each next track
+		.route("/lobby/:lobby_id/chat/export", get(export_chat)) //?format=json|text, host only
+		.route("/admin/lobby/chat_retention", get(get_chat_retention))
+		.route("/admin/lobby/chat_retention", post(set_chat_retention)) //hours the chat and played tracks are kept
+		//maintenance, everyone but the admins gets a 503 while it is on
+		.route("/admin/maintenance", get(get_maintenance))
+		.route("/admin/maintenance", post(set_maintenance)) //{ enabled, message, eta }
+		//doctor, config, media roots, ffmpeg, smtp and the database
+		.route("/admin/doctor", get(get_doctor))
+		.layer(middleware::from_fn_with_state(app_state.clone(), oauth::bearer_session))
+		.layer(middleware::from_fn_with_state(app_state.clone(), maintenance::guard))
+		.layer(middleware::from_fn_with_state(app_state.clone(), ip_filter::guard))
+		//the limits of body_limit apply instead, per route class
+		.layer(middleware::from_fn(body_limit::enforce))
+		.layer(DefaultBodyLimit::disable())
 		.with_state(app_state)
 }
 
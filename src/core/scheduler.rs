@@ -0,0 +1,97 @@
+use crate::core::app_state::AppState;
+use crate::core::{
+	analytics, leaderboard, new_releases, on_this_day, playlist_releases, retention, telemetry, tokens,
+	transcode_cache, webhooks, weekly_email,
+};
+
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+// Background work that runs every so often, off the async runtime since it is all diesel
+struct Job {
+	name: &'static str,
+	every: Duration,
+	run: fn(&AppState) -> Result<(), String>,
+}
+
+fn jobs() -> Vec<Job> {
+	vec![
+		Job {
+			name: "leaderboards",
+			every: leaderboard::REFRESH_INTERVAL,
+			run: |app_state| leaderboard::refresh(&app_state.db_pool),
+		},
+		Job {
+			name: "on_this_day",
+			every: on_this_day::CHECK_INTERVAL,
+			run: on_this_day::notify_anniversaries,
+		},
+		Job {
+			name: "new_releases",
+			every: new_releases::NOTIFY_INTERVAL,
+			run: new_releases::notify_followers,
+		},
+		Job {
+			name: "playlist_releases",
+			every: playlist_releases::RELEASE_INTERVAL,
+			run: playlist_releases::release_due,
+		},
+		Job {
+			name: "analytics",
+			every: analytics::REFRESH_INTERVAL,
+			run: analytics::aggregate,
+		},
+		Job {
+			name: "telemetry",
+			every: telemetry::REPORT_INTERVAL,
+			run: telemetry::report,
+		},
+		Job {
+			name: "retention",
+			every: retention::PRUNE_INTERVAL,
+			run: retention::prune,
+		},
+		Job {
+			name: "token_revocations",
+			every: tokens::RELOAD_INTERVAL,
+			run: tokens::reload,
+		},
+		Job {
+			name: "transcode_cache",
+			every: transcode_cache::EVICT_INTERVAL,
+			run: transcode_cache::evict,
+		},
+		Job {
+			name: "webhooks",
+			every: webhooks::DELIVERY_INTERVAL,
+			run: webhooks::deliver,
+		},
+		Job {
+			name: "weekly_email",
+			every: weekly_email::SEND_INTERVAL,
+			run: weekly_email::send_summaries,
+		},
+	]
+}
+
+// Runs every job once right away and then on its interval
+pub fn start(app_state: AppState) {
+	for job in jobs() {
+		let app_state = app_state.clone();
+		tokio::spawn(async move {
+			let mut ticker = tokio::time::interval(job.every);
+			ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+			loop {
+				ticker.tick().await;
+
+				let app_state = app_state.clone();
+				let run = job.run;
+				match tokio::task::spawn_blocking(move || run(&app_state)).await {
+					Ok(Ok(())) => (),
+					Ok(Err(err)) => println!("Scheduled job {} failed: {err}", job.name),
+					Err(err) => println!("Scheduled job {} panicked: {err}", job.name),
+				}
+			}
+		});
+	}
+}
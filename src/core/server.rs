@@ -1,21 +1,110 @@
+use crate::config::server_ip;
+use crate::core::ip_filter;
+
 use axum::{
 	body::Body,
 	extract::Request,
-	http::{header, Method},
+	http::{header, request::Parts, HeaderName, HeaderValue, Method},
 	middleware::Next,
 	response::Response,
 	Router,
 };
 use colored::*;
+use std::net::SocketAddr;
+use std::str::FromStr;
 use std::time::Instant;
-use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::cors::{AllowCredentials, AllowOrigin, CorsLayer};
+
+// Share endpoints, covers, embeds, overlays and instance info, any site may read them but never with the session
+const PUBLIC_PATHS: [&str; 10] = [
+	"/image/",
+	"/animated_cover/",
+	"/cover_art/file/",
+	"/playlist/cover_img/",
+	"/user/get_pfp/",
+	"/music/playback_info/",
+	"/instance/info",
+	"/api/capabilities",
+	"/embed/",
+	"/overlay/",
+];
+
+// Exposed to the frontend unless CORS_EXPOSED_HEADERS says otherwise
+const DEFAULT_EXPOSED_HEADERS: &str = "content-disposition,content-length,retry-after";
+
+// Read from the env once at startup
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+	pub allowed_origins: Vec<HeaderValue>, // CORS_ALLOWED_ORIGINS, comma separated
+	pub allow_credentials: bool,           // CORS_ALLOW_CREDENTIALS, the session cookie goes along
+	pub exposed_headers: Vec<HeaderName>,  // CORS_EXPOSED_HEADERS, comma separated
+}
+
+impl CorsConfig {
+	pub fn from_env() -> CorsConfig {
+		let allowed_origins = match std::env::var("CORS_ALLOWED_ORIGINS") {
+			Ok(origins) => comma_separated(&origins),
+			Err(_) => dev_origins(),
+		};
+		let allow_credentials = std::env::var("CORS_ALLOW_CREDENTIALS").map_or(true, |value| value != "false");
+		let exposed_headers = comma_separated(
+			&std::env::var("CORS_EXPOSED_HEADERS").unwrap_or_else(|_| DEFAULT_EXPOSED_HEADERS.to_string()),
+		);
+		CorsConfig {
+			allowed_origins,
+			allow_credentials,
+			exposed_headers,
+		}
+	}
+}
+
+// The vite dev server on this machine, when no origins are configured
+fn dev_origins() -> Vec<HeaderValue> {
+	let mut origins = Vec::new();
+	let ips = ["localhost".to_string(), "127.0.0.1".to_string(), server_ip()];
+	for port in 5173..5175 {
+		for ip in &ips {
+			origins.extend(HeaderValue::from_str(&format!("http://{ip}:{port}")).ok());
+		}
+	}
+	origins
+}
+
+// Entries that aren't valid header values are left out
+fn comma_separated<T: FromStr>(value: &str) -> Vec<T> {
+	value
+		.split(',')
+		.map(str::trim)
+		.filter(|entry| !entry.is_empty())
+		.filter_map(|entry| entry.parse().ok())
+		.collect()
+}
+
+pub fn is_public_path(path: &str) -> bool {
+	PUBLIC_PATHS.iter().any(|public| path.starts_with(public))
+}
 
 pub fn configure_cors() -> CorsLayer {
+	cors_layer(CorsConfig::from_env())
+}
+
+// The public paths take any origin and never the credentials, everything else only the configured origins
+pub fn cors_layer(config: CorsConfig) -> CorsLayer {
+	let CorsConfig {
+		allowed_origins,
+		allow_credentials,
+		exposed_headers,
+	} = config;
 	CorsLayer::new()
-		.allow_origin(AllowOrigin::predicate(crate::config::allowed_origins))
-		.allow_credentials(true)
-		.allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+		.allow_origin(AllowOrigin::predicate(move |origin: &HeaderValue, parts: &Parts| {
+			is_public_path(parts.uri.path()) || allowed_origins.contains(origin)
+		}))
+		.allow_credentials(AllowCredentials::predicate(
+			move |_origin: &HeaderValue, parts: &Parts| allow_credentials && !is_public_path(parts.uri.path()),
+		))
+		.allow_methods([Method::GET, Method::POST, Method::PUT, Method::OPTIONS])
 		.allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE])
+		.expose_headers(exposed_headers)
 }
 
 pub async fn start_server(app: Router, ip: &str, port: &str) {
@@ -26,13 +115,17 @@ pub async fn start_server(app: Router, ip: &str, port: &str) {
 	);
 
 	let listener = tokio::net::TcpListener::bind(format!("{ip}:{port}")).await.unwrap();
-	axum::serve(listener, app).await.unwrap();
+	// the peer address is what X-Forwarded-For gets checked against
+	axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+		.await
+		.unwrap();
 }
 
 pub async fn logger(req: Request<Body>, next: Next) -> Response {
 	let start = Instant::now();
 	let method = req.method().to_string();
 	let uri = req.uri().to_string();
+	let client = ip_filter::resolve(&req).map(|ip| ip.to_string()).unwrap_or_default();
 
 	let response = next.run(req).await;
 
@@ -56,12 +149,64 @@ pub async fn logger(req: Request<Body>, next: Next) -> Response {
 	};
 
 	println!(
-		"{:<6} {:<20} | status: {:<4} | latency: {:<10.2?}",
+		"{:<6} {:<20} | status: {:<4} | latency: {:<10.2?} | client: {}",
 		colored_method,
 		uri.bright_white(),
 		colored_status,
-		start.elapsed()
+		start.elapsed(),
+		client
 	);
 
 	response
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use axum::routing::get;
+	use tower::ServiceExt;
+
+	#[tokio::test]
+	async fn share_endpoints_take_any_origin_without_credentials() {
+		let config = CorsConfig {
+			allowed_origins: vec![HeaderValue::from_static("https://lobic.example")],
+			allow_credentials: true,
+			exposed_headers: comma_separated(DEFAULT_EXPOSED_HEADERS),
+		};
+		let app = Router::new()
+			.route("/image/:img_uuid", get(|| async { "cover" }))
+			.route("/music/get_music", get(|| async { "[]" }))
+			.layer(cors_layer(config));
+		let cors = |uri: &str, origin: &str| {
+			let request = Request::builder()
+				.uri(uri)
+				.header(header::ORIGIN, origin)
+				.body(Body::empty())
+				.unwrap();
+			let app = app.clone();
+			async move {
+				let response = app.oneshot(request).await.unwrap();
+				let value = |name| {
+					response
+						.headers()
+						.get(name)
+						.map(|value: &HeaderValue| value.to_str().unwrap().to_string())
+				};
+				(
+					value(header::ACCESS_CONTROL_ALLOW_ORIGIN),
+					value(header::ACCESS_CONTROL_ALLOW_CREDENTIALS),
+				)
+			}
+		};
+
+		let (origin, credentials) = cors("/music/get_music", "https://lobic.example").await;
+		assert_eq!(origin.as_deref(), Some("https://lobic.example"));
+		assert_eq!(credentials.as_deref(), Some("true"));
+		let (origin, _) = cors("/music/get_music", "https://elsewhere.example").await;
+		assert_eq!(origin, None);
+		let (origin, credentials) = cors("/image/abc", "https://elsewhere.example").await;
+		assert_eq!(origin.as_deref(), Some("https://elsewhere.example"));
+		assert_eq!(credentials, None);
+	}
+}
@@ -0,0 +1,123 @@
+use crate::core::app_state::AppState;
+use crate::core::federation::PEER_ACTIVE;
+use crate::core::{instance::open_registrations, realtime};
+use crate::lobic_db::db::{get_instance_setting, set_instance_setting};
+use crate::schema::{federation_peers, playlists, takedowns, users};
+
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::Serialize;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Keys in instance_settings
+pub const TELEMETRY_ENABLED: &str = "telemetry_enabled";
+pub const TELEMETRY_ID: &str = "telemetry_id";
+pub const TELEMETRY_LAST_SENT: &str = "telemetry_last_sent";
+
+// Everything that ever leaves the instance, nothing about individual users or their content
+#[derive(Debug, Serialize)]
+pub struct TelemetryReport {
+	pub report_id: String, // random, only ties the reports of this instance together
+	pub version: String,
+	pub user_count_bucket: &'static str,
+	pub features: TelemetryFeatures,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TelemetryFeatures {
+	pub lobbies: bool,
+	pub federation: bool,
+	pub realtime_fanout: bool,
+	pub directory_listed: bool,
+	pub open_registrations: bool,
+	pub combined_playlists: bool,
+	pub takedowns: bool,
+}
+
+// Where the reports go, nothing is ever sent without it
+pub fn telemetry_url() -> Option<String> {
+	std::env::var("TELEMETRY_URL").ok().filter(|url| !url.is_empty())
+}
+
+pub fn is_enabled(db_conn: &mut SqliteConnection) -> bool {
+	get_instance_setting(TELEMETRY_ENABLED, db_conn).is_some_and(|value| value == "true")
+}
+
+fn user_count_bucket(count: i64) -> &'static str {
+	match count {
+		0..=10 => "1-10",
+		11..=50 => "11-50",
+		51..=200 => "51-200",
+		201..=1000 => "201-1000",
+		_ => "1000+",
+	}
+}
+
+// The exact report that would be sent right now
+pub fn build_report(app_state: &AppState, db_conn: &mut SqliteConnection) -> QueryResult<TelemetryReport> {
+	let report_id = match get_instance_setting(TELEMETRY_ID, db_conn) {
+		Some(id) => id,
+		None => {
+			let id = Uuid::new_v4().to_string();
+			set_instance_setting(TELEMETRY_ID, &id, db_conn)?;
+			id
+		}
+	};
+
+	let user_count = users::table.count().get_result::<i64>(db_conn)?;
+	let active_peers = federation_peers::table
+		.filter(federation_peers::status.eq(PEER_ACTIVE))
+		.count()
+		.get_result::<i64>(db_conn)?;
+	let combined_playlists = playlists::table
+		.filter(playlists::is_playlist_combined.eq(true))
+		.count()
+		.get_result::<i64>(db_conn)?;
+	let takedown_count = takedowns::table.count().get_result::<i64>(db_conn)?;
+
+	Ok(TelemetryReport {
+		report_id,
+		version: env!("CARGO_PKG_VERSION").to_string(),
+		user_count_bucket: user_count_bucket(user_count),
+		features: TelemetryFeatures {
+			lobbies: !app_state.lobby_pool.get_ids().is_empty(),
+			federation: active_peers > 0,
+			realtime_fanout: realtime::enabled(),
+			directory_listed: std::env::var("INSTANCE_DIRECTORY_URL").is_ok_and(|url| !url.is_empty()),
+			open_registrations: open_registrations(),
+			combined_playlists: combined_playlists > 0,
+			takedowns: takedown_count > 0,
+		},
+	})
+}
+
+// Sends the report when the admins opted in, runs on the scheduler
+pub fn report(app_state: &AppState) -> Result<(), String> {
+	let url = match telemetry_url() {
+		Some(url) => url,
+		None => return Ok(()),
+	};
+
+	let mut db_conn = app_state
+		.db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+	if !is_enabled(&mut db_conn) {
+		return Ok(());
+	}
+
+	let report = build_report(app_state, &mut db_conn).map_err(|err| err.to_string())?;
+	let runtime = tokio::runtime::Handle::current();
+	let response = runtime
+		.block_on(reqwest::Client::new().post(&url).json(&report).send())
+		.map_err(|err| format!("Failed to send telemetry: {err}"))?;
+	if !response.status().is_success() {
+		return Err(format!("Telemetry rejected: {}", response.status()));
+	}
+
+	set_instance_setting(TELEMETRY_LAST_SENT, &Utc::now().to_rfc3339(), &mut db_conn).map_err(|err| err.to_string())?;
+	Ok(())
+}
@@ -0,0 +1,34 @@
+use crate::core::app_state::AppState;
+use crate::lobic_db::db::{get_instance_setting, set_instance_setting};
+use crate::utils::{exp, jwt};
+
+use diesel::prelude::*;
+use std::time::Duration;
+
+// How quickly a revocation made from the cli reaches the running server
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+// Key in instance_settings, unix time before which every token is rejected
+pub const TOKENS_REVOKED_BEFORE: &str = "tokens_revoked_before";
+
+// Logs everyone out, the sessions issued from now on are unaffected
+pub fn revoke_all(db_conn: &mut SqliteConnection) -> QueryResult<usize> {
+	let now = exp::now();
+	set_instance_setting(TOKENS_REVOKED_BEFORE, &now.to_string(), db_conn)?;
+	jwt::set_revoked_before(now);
+	Ok(now)
+}
+
+// Picks up the revocations made by other processes
+pub fn reload(app_state: &AppState) -> Result<(), String> {
+	let mut db_conn = app_state
+		.db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+
+	let revoked_before = get_instance_setting(TOKENS_REVOKED_BEFORE, &mut db_conn)
+		.and_then(|value| value.parse::<usize>().ok())
+		.unwrap_or(0);
+	jwt::set_revoked_before(revoked_before);
+	Ok(())
+}
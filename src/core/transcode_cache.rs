@@ -0,0 +1,423 @@
+use crate::config::{
+	DEFAULT_TRANSCODE_CACHE_LOW_WATER_PERCENT, DEFAULT_TRANSCODE_CACHE_MAX_MB, TRANSCODE_CACHE_STORAGE,
+};
+use crate::core::app_state::AppState;
+use crate::core::artwork::animated;
+use crate::lobic_db::db::get_instance_setting;
+
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+// Streams asked for at a lower bitrate are transcoded once by ffmpeg and kept under
+// {TRANSCODE_CACHE_STORAGE}/{music_id}/{bitrate}.mp3. Every hit touches the file, so its modified time is when
+// it was last streamed and the eviction drops the ones unused the longest.
+
+pub const EVICT_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const REPORT_TRACK_LIMIT: usize = 20;
+const PART_SUFFIX: &str = ".part"; // a transcode still being written, or left behind by one that died
+
+// Keys in instance_settings
+pub const CACHE_MAX_MB: &str = "transcode_cache_max_mb";
+pub const CACHE_LOW_WATER_PERCENT: &str = "transcode_cache_low_water_percent";
+
+// When the eviction starts and where it stops, the defaults until the admins set otherwise
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct CacheLimits {
+	pub max_mb: i64,            // the eviction starts once the cache is bigger
+	pub low_water_percent: i64, // of max_mb, it frees space down to there so it doesn't run again right away
+}
+
+impl CacheLimits {
+	pub fn load(db_conn: &mut SqliteConnection) -> CacheLimits {
+		let setting = |key: &str, default: i64, db_conn: &mut SqliteConnection| {
+			get_instance_setting(key, db_conn)
+				.and_then(|value| value.parse().ok())
+				.unwrap_or(default)
+		};
+		CacheLimits {
+			max_mb: setting(CACHE_MAX_MB, DEFAULT_TRANSCODE_CACHE_MAX_MB, db_conn),
+			low_water_percent: setting(
+				CACHE_LOW_WATER_PERCENT,
+				DEFAULT_TRANSCODE_CACHE_LOW_WATER_PERCENT,
+				db_conn,
+			),
+		}
+	}
+
+	pub fn max_bytes(&self) -> u64 {
+		self.max_mb.max(0) as u64 * 1024 * 1024
+	}
+
+	pub fn low_water_bytes(&self) -> u64 {
+		self.max_bytes() / 100 * self.low_water_percent.clamp(0, 100) as u64
+	}
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+	music_id: String,
+	path: PathBuf,
+	bytes: u64,
+	last_used: SystemTime,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct TrackUsage {
+	pub music_id: String,
+	pub bytes: u64,
+	pub files: usize,        // one per bitrate
+	pub last_used_secs: u64, // unix time of the last stream
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheReport {
+	pub bytes: u64,
+	pub files: usize,
+	pub tracks: usize,
+	pub limits: CacheLimits,
+	pub largest: Vec<TrackUsage>, // the biggest tracks first
+}
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct Freed {
+	pub files: usize,
+	pub bytes: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TranscodeCache {
+	dir: PathBuf,
+}
+
+impl TranscodeCache {
+	pub fn new() -> TranscodeCache {
+		TranscodeCache::at(PathBuf::from(TRANSCODE_CACHE_STORAGE))
+	}
+
+	pub fn at(dir: PathBuf) -> TranscodeCache {
+		TranscodeCache { dir }
+	}
+
+	// The cached stream of the track at the bitrate, transcoded from the source when there is none yet or the
+	// source changed since
+	pub fn get_or_transcode(&self, source: &Path, music_id: &str, bitrate: u32) -> Result<PathBuf, String> {
+		let track_dir = self.track_dir(music_id)?;
+		let cached = track_dir.join(format!("{bitrate}.mp3"));
+		let source_modified = fs::metadata(source)
+			.and_then(|meta| meta.modified())
+			.map_err(|err| format!("Failed to read the source: {err}"))?;
+		if let Ok(file) = File::options().append(true).open(&cached) {
+			if file
+				.metadata()
+				.and_then(|meta| meta.modified())
+				.is_ok_and(|modified| modified >= source_modified)
+			{
+				let _ = file.set_modified(SystemTime::now());
+				return Ok(cached);
+			}
+		}
+
+		fs::create_dir_all(&track_dir).map_err(|err| format!("Failed to create directory: {err}"))?;
+		// written next to it and moved in place, a stream of the old one keeps reading the old file
+		let part = track_dir.join(format!("{bitrate}.{}.mp3{PART_SUFFIX}", Uuid::new_v4()));
+		let transcoded = transcode(source, &part, bitrate)
+			.and_then(|()| fs::rename(&part, &cached).map_err(|err| format!("Failed to save the transcode: {err}")));
+		if transcoded.is_err() {
+			let _ = fs::remove_file(&part);
+		}
+		transcoded.map(|()| cached)
+	}
+
+	pub fn report(&self, limits: CacheLimits) -> io::Result<CacheReport> {
+		let entries = self.entries()?;
+		let mut by_track: HashMap<&str, TrackUsage> = HashMap::new();
+		for entry in &entries {
+			let usage = by_track.entry(&entry.music_id).or_insert_with(|| TrackUsage {
+				music_id: entry.music_id.clone(),
+				bytes: 0,
+				files: 0,
+				last_used_secs: 0,
+			});
+			usage.bytes += entry.bytes;
+			usage.files += 1;
+			usage.last_used_secs = usage.last_used_secs.max(unix_secs(entry.last_used));
+		}
+		let tracks = by_track.len();
+		let mut largest: Vec<TrackUsage> = by_track.into_values().collect();
+		largest.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.music_id.cmp(&b.music_id)));
+		largest.truncate(REPORT_TRACK_LIMIT);
+
+		Ok(CacheReport {
+			bytes: entries.iter().map(|entry| entry.bytes).sum(),
+			files: entries.len(),
+			tracks,
+			limits,
+			largest,
+		})
+	}
+
+	// Drops the least recently streamed files once the cache is over max_mb, down to the low water mark
+	pub fn evict(&self, limits: CacheLimits) -> io::Result<Freed> {
+		let mut entries = self.entries()?;
+		let mut bytes: u64 = entries.iter().map(|entry| entry.bytes).sum();
+		let mut freed = Freed::default();
+		if bytes <= limits.max_bytes() {
+			return Ok(freed);
+		}
+
+		entries.sort_by_key(|entry| entry.last_used);
+		for entry in entries {
+			if bytes <= limits.low_water_bytes() {
+				break;
+			}
+			// purged or evicted by an admin in the meantime
+			if fs::remove_file(&entry.path).is_err() {
+				continue;
+			}
+			bytes -= entry.bytes;
+			freed.files += 1;
+			freed.bytes += entry.bytes;
+		}
+		self.remove_empty_dirs();
+		Ok(freed)
+	}
+
+	// Every bitrate of the track, e.g. after its file was replaced or taken down
+	pub fn purge(&self, music_id: &str) -> Result<Freed, String> {
+		let track_dir = self.track_dir(music_id)?;
+		let mut freed = Freed::default();
+		let Ok(files) = fs::read_dir(&track_dir) else {
+			return Ok(freed);
+		};
+		for file in files.flatten() {
+			let bytes = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+			if fs::remove_file(file.path()).is_ok() {
+				freed.files += 1;
+				freed.bytes += bytes;
+			}
+		}
+		let _ = fs::remove_dir(&track_dir);
+		Ok(freed)
+	}
+
+	// The music ids come from the urls, they can't be allowed to point out of the cache
+	fn track_dir(&self, music_id: &str) -> Result<PathBuf, String> {
+		let valid = !music_id.is_empty()
+			&& music_id.len() < 100
+			&& music_id.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+		if !valid {
+			return Err(format!("Invalid music id: {music_id}"));
+		}
+		Ok(self.dir.join(music_id))
+	}
+
+	// The finished transcodes, a part left behind by a transcode that died is dropped once it's an hour old
+	fn entries(&self) -> io::Result<Vec<CacheEntry>> {
+		let mut entries = Vec::new();
+		let track_dirs = match fs::read_dir(&self.dir) {
+			Ok(track_dirs) => track_dirs,
+			Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(entries),
+			Err(err) => return Err(err),
+		};
+		for track_dir in track_dirs.flatten().filter(|dir| dir.path().is_dir()) {
+			let music_id = track_dir.file_name().to_string_lossy().to_string();
+			for file in fs::read_dir(track_dir.path())?.flatten() {
+				let Ok(meta) = file.metadata() else {
+					continue;
+				};
+				let last_used = meta.modified().unwrap_or(UNIX_EPOCH);
+				if file.file_name().to_string_lossy().ends_with(PART_SUFFIX) {
+					if last_used.elapsed().is_ok_and(|age| age > Duration::from_secs(60 * 60)) {
+						let _ = fs::remove_file(file.path());
+					}
+					continue;
+				}
+				entries.push(CacheEntry {
+					music_id: music_id.clone(),
+					path: file.path(),
+					bytes: meta.len(),
+					last_used,
+				});
+			}
+		}
+		Ok(entries)
+	}
+
+	fn remove_empty_dirs(&self) {
+		let Ok(track_dirs) = fs::read_dir(&self.dir) else {
+			return;
+		};
+		for track_dir in track_dirs.flatten() {
+			// fails on the ones that still have files
+			let _ = fs::remove_dir(track_dir.path());
+		}
+	}
+}
+
+fn transcode(source: &Path, output: &Path, bitrate: u32) -> Result<(), String> {
+	let output = Command::new(animated::ffmpeg())
+		.args(["-y", "-v", "error", "-i"])
+		.arg(source)
+		.args([
+			"-map",
+			"0:a:0",
+			"-c:a",
+			"libmp3lame",
+			"-b:a",
+			&format!("{bitrate}k"),
+			"-f",
+			"mp3",
+		])
+		.arg(output)
+		.output()
+		.map_err(|err| format!("Failed to run ffmpeg: {err}"))?;
+	if !output.status.success() {
+		let stderr = String::from_utf8_lossy(&output.stderr);
+		return Err(format!("Failed to transcode the track: {}", stderr.trim()));
+	}
+	Ok(())
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+	time.duration_since(UNIX_EPOCH)
+		.map(|since| since.as_secs())
+		.unwrap_or(0)
+}
+
+// Run by the scheduler, keeps the cache under the limits set by the admins
+pub fn evict(app_state: &AppState) -> Result<(), String> {
+	let mut db_conn = app_state
+		.db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+	let limits = CacheLimits::load(&mut db_conn);
+	drop(db_conn);
+
+	let freed = TranscodeCache::new()
+		.evict(limits)
+		.map_err(|err| format!("Failed to evict from the transcode cache: {err}"))?;
+	if freed.files > 0 {
+		tracing::info!(
+			"Evicted {} files, {} bytes, from the transcode cache",
+			freed.files,
+			freed.bytes
+		);
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn cache_file(cache: &TranscodeCache, music_id: &str, bitrate: u32, bytes: usize, age_secs: u64) -> PathBuf {
+		let path = cache.dir.join(music_id).join(format!("{bitrate}.mp3"));
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		fs::write(&path, vec![0u8; bytes]).unwrap();
+		let file = File::options().append(true).open(&path).unwrap();
+		file.set_modified(SystemTime::now() - Duration::from_secs(age_secs))
+			.unwrap();
+		path
+	}
+
+	#[test]
+	fn the_least_recently_streamed_are_evicted_down_to_the_low_water_mark() {
+		let dir = std::env::temp_dir().join(format!("lobic_transcode_cache_{}", Uuid::new_v4()));
+		let cache = TranscodeCache::at(dir.clone());
+		let mb = 1024 * 1024;
+		let oldest = cache_file(&cache, "oldest", 128, mb, 300);
+		let older = cache_file(&cache, "older", 64, mb, 200);
+		let newer = cache_file(&cache, "newer", 128, mb, 100);
+		let newest = cache_file(&cache, "newer", 64, mb, 0);
+
+		let report = cache
+			.report(CacheLimits {
+				max_mb: 4,
+				low_water_percent: 50,
+			})
+			.unwrap();
+		assert_eq!((report.bytes, report.files, report.tracks), (4 * mb as u64, 4, 3));
+		assert_eq!(report.largest[0].music_id, "newer");
+		assert_eq!(report.largest[0].files, 2);
+
+		// at the limit nothing goes
+		let limits = CacheLimits {
+			max_mb: 4,
+			low_water_percent: 50,
+		};
+		assert_eq!(cache.evict(limits).unwrap(), Freed::default());
+		let limits = CacheLimits {
+			max_mb: 3,
+			low_water_percent: 70,
+		};
+		let freed = cache.evict(limits).unwrap();
+		let left = [&oldest, &older, &newer, &newest].map(|path| path.exists());
+		let oldest_dir_left = dir.join("oldest").exists();
+
+		let purged = cache.purge("newer").unwrap();
+		let newer_dir_left = dir.join("newer").exists();
+		let outside = cache.purge("../music_db");
+		fs::remove_dir_all(&dir).unwrap();
+
+		assert_eq!(
+			freed,
+			Freed {
+				files: 2,
+				bytes: 2 * mb as u64
+			}
+		);
+		assert_eq!(left, [false, false, true, true]);
+		assert!(!oldest_dir_left);
+		assert_eq!(
+			purged,
+			Freed {
+				files: 2,
+				bytes: 2 * mb as u64
+			}
+		);
+		assert!(!newer_dir_left);
+		assert!(outside.is_err());
+	}
+
+	#[test]
+	fn a_cached_stream_is_kept_until_the_source_changes() {
+		let dir = std::env::temp_dir().join(format!("lobic_transcode_cache_{}", Uuid::new_v4()));
+		let cache = TranscodeCache::at(dir.join("cache"));
+		let source = dir.join("track.mp3");
+		fs::create_dir_all(&dir).unwrap();
+		fs::write(&source, b"ID3 not really an mp3").unwrap();
+		File::options()
+			.append(true)
+			.open(&source)
+			.unwrap()
+			.set_modified(SystemTime::now() - Duration::from_secs(60))
+			.unwrap();
+		let cached = cache_file(&cache, "track", 128, 16, 120);
+
+		// older than the source, so it's transcoded again, which fails on the fake mp3 and keeps no part
+		let stale = cache.get_or_transcode(&source, "track", 128);
+		let parts = fs::read_dir(dir.join("cache").join("track")).unwrap().count();
+
+		File::options()
+			.append(true)
+			.open(&cached)
+			.unwrap()
+			.set_modified(SystemTime::now() - Duration::from_secs(30))
+			.unwrap();
+		let hit = cache.get_or_transcode(&source, "track", 128);
+		let touched = fs::metadata(&cached).unwrap().modified().unwrap().elapsed().unwrap();
+		fs::remove_dir_all(&dir).unwrap();
+
+		assert!(stale.is_err());
+		assert_eq!(parts, 1);
+		assert_eq!(hit, Ok(cached));
+		assert!(touched < Duration::from_secs(10));
+	}
+}
@@ -1,11 +1,64 @@
+use crate::core::outbox::Outbox;
+use crate::core::realtime::{self, RealtimeEvent};
+
 use axum::extract::ws::Message;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::sync::{Arc, Mutex};
-use tokio::sync::broadcast;
+
+// Event topics a connection can subscribe to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Topic {
+	Lobby(String),
+	Notifications,
+	FriendActivity,
+	LibraryUpdates,
+	Player,  // commands for the user's own player, from /player/state
+	Imports, // library scan progress, only sent to the admins
+}
+
+impl Topic {
+	pub fn lobby(lobby_id: &str) -> Topic {
+		Topic::Lobby(lobby_id.to_string())
+	}
+
+	pub fn parse(value: &str) -> Option<Topic> {
+		match value {
+			"notifications" => Some(Topic::Notifications),
+			"friend-activity" => Some(Topic::FriendActivity),
+			"library-updates" => Some(Topic::LibraryUpdates),
+			"player" => Some(Topic::Player),
+			"imports" => Some(Topic::Imports),
+			_ => match value.strip_prefix("lobby:") {
+				Some(lobby_id) if !lobby_id.is_empty() => Some(Topic::lobby(lobby_id)),
+				_ => None,
+			},
+		}
+	}
+}
+
+impl fmt::Display for Topic {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Topic::Lobby(lobby_id) => write!(f, "lobby:{}", lobby_id),
+			Topic::Notifications => write!(f, "notifications"),
+			Topic::FriendActivity => write!(f, "friend-activity"),
+			Topic::LibraryUpdates => write!(f, "library-updates"),
+			Topic::Player => write!(f, "player"),
+			Topic::Imports => write!(f, "imports"),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+struct Connection {
+	outbox: Outbox,
+	topics: Option<HashSet<Topic>>, // None until the client subscribes, it receives everything till then
+}
 
 #[derive(Debug, Clone)]
 pub struct UserPool {
-	inner: Arc<Mutex<HashMap<String, broadcast::Sender<Message>>>>,
+	inner: Arc<Mutex<HashMap<String, Connection>>>,
 }
 
 impl UserPool {
@@ -20,14 +73,14 @@ impl UserPool {
 		inner.clone().into_keys().collect()
 	}
 
-	pub fn get_conns(&self) -> Vec<broadcast::Sender<Message>> {
+	pub fn get_conns(&self) -> Vec<Outbox> {
 		let inner = self.inner.lock().unwrap();
-		inner.clone().into_values().collect()
+		inner.values().map(|conn| conn.outbox.clone()).collect()
 	}
 
-	pub fn get(&self, key: &str) -> Option<broadcast::Sender<Message>> {
+	pub fn get(&self, key: &str) -> Option<Outbox> {
 		let inner = self.inner.lock().unwrap();
-		inner.get(key).cloned()
+		inner.get(key).map(|conn| conn.outbox.clone())
 	}
 
 	pub fn exists(&self, key: &str) -> bool {
@@ -35,16 +88,127 @@ impl UserPool {
 		inner.contains_key(key)
 	}
 
-	pub fn insert(&self, id: &str, sender: &broadcast::Sender<Message>) {
+	pub fn insert(&self, id: &str, outbox: &Outbox) {
 		let mut inner = self.inner.lock().unwrap();
-		inner.insert(id.to_string(), sender.clone());
+		let conn = Connection {
+			outbox: outbox.clone(),
+			topics: None,
+		};
+		inner.insert(id.to_string(), conn);
 	}
 
 	pub fn remove(&self, id: &str) -> bool {
 		let mut inner = self.inner.lock().unwrap();
 		match inner.remove(id) {
-			Some(_) => true,
+			Some(conn) => {
+				conn.outbox.close();
+				true
+			}
+			None => false,
+		}
+	}
+
+	// Removes the user only if the given outbox is still the registered one,
+	// a newer connection of the same user is left alone
+	pub fn remove_conn(&self, id: &str, outbox: &Outbox) -> bool {
+		let mut inner = self.inner.lock().unwrap();
+		match inner.get(id) {
+			Some(conn) if conn.outbox.same_as(outbox) => {
+				inner.remove(id);
+				true
+			}
+			_ => false,
+		}
+	}
+
+	pub fn is_subscribed(&self, id: &str, topic: &Topic) -> bool {
+		let inner = self.inner.lock().unwrap();
+		match inner.get(id) {
+			Some(conn) => conn.topics.as_ref().is_none_or(|topics| topics.contains(topic)),
+			None => false,
+		}
+	}
+
+	// Sends the message if the user is listening to the topic, users connected to
+	// another instance are reached through the realtime fan-out
+	pub fn send(&self, id: &str, topic: &Topic, msg: String) -> bool {
+		if !self.exists(id) {
+			return realtime::publish(RealtimeEvent::Deliver {
+				user_id: id.to_string(),
+				topic: topic.to_string(),
+				message: msg,
+			});
+		}
+		self.send_local(id, topic, msg)
+	}
+
+	// Same as `send` but only for the users connected to this instance
+	pub fn send_local(&self, id: &str, topic: &Topic, msg: String) -> bool {
+		if !self.is_subscribed(id, topic) {
+			return false;
+		}
+		match self.get(id) {
+			Some(outbox) => outbox.send(Message::Text(msg)).is_ok(),
 			None => false,
 		}
 	}
+
+	// Sends the message to every user listening to the topic
+	pub fn broadcast(&self, topic: &Topic, msg: &str) {
+		self.broadcast_local(topic, msg);
+		realtime::publish(RealtimeEvent::Broadcast {
+			topic: topic.to_string(),
+			message: msg.to_string(),
+		});
+	}
+
+	pub fn broadcast_local(&self, topic: &Topic, msg: &str) {
+		for id in self.get_ids() {
+			self.send_local(&id, topic, msg.to_string());
+		}
+	}
+
+	// Whether a message to the user can be delivered, here or on another instance
+	pub fn is_reachable(&self, id: &str) -> bool {
+		self.exists(id) || realtime::enabled()
+	}
+
+	pub fn subscribe(&self, id: &str, topics: &[Topic]) -> Result<Vec<String>, String> {
+		let mut inner = self.inner.lock().unwrap();
+		let conn = match inner.get_mut(id) {
+			Some(conn) => conn,
+			None => return Err(format!("User {} is not connected", id)),
+		};
+
+		let subscribed = conn.topics.get_or_insert_with(HashSet::new);
+		subscribed.extend(topics.iter().cloned());
+		Ok(subscribed.iter().map(|topic| topic.to_string()).collect())
+	}
+
+	pub fn unsubscribe(&self, id: &str, topics: &[Topic]) -> Result<Vec<String>, String> {
+		let mut inner = self.inner.lock().unwrap();
+		let conn = match inner.get_mut(id) {
+			Some(conn) => conn,
+			None => return Err(format!("User {} is not connected", id)),
+		};
+
+		let subscribed = conn.topics.get_or_insert_with(HashSet::new);
+		subscribed.retain(|topic| !topics.contains(topic));
+		Ok(subscribed.iter().map(|topic| topic.to_string()).collect())
+	}
+
+	// Keeps the lobby events flowing to a filtering client once it joins the lobby
+	pub fn follow_lobby(&self, id: &str, lobby_id: &str) {
+		let mut inner = self.inner.lock().unwrap();
+		if let Some(topics) = inner.get_mut(id).and_then(|conn| conn.topics.as_mut()) {
+			topics.insert(Topic::lobby(lobby_id));
+		}
+	}
+
+	pub fn unfollow_lobby(&self, id: &str, lobby_id: &str) {
+		let mut inner = self.inner.lock().unwrap();
+		if let Some(topics) = inner.get_mut(id).and_then(|conn| conn.topics.as_mut()) {
+			topics.remove(&Topic::lobby(lobby_id));
+		}
+	}
 }
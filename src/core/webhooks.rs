@@ -0,0 +1,103 @@
+use crate::core::app_state::AppState;
+use crate::lobic_db::models::{LibraryChangeEntry, Webhook};
+use crate::schema::{library_changes, webhooks};
+
+use chrono::{Duration as ChronoDuration, Utc};
+use diesel::prelude::*;
+use serde_json::json;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+pub const DELIVERY_INTERVAL: Duration = Duration::from_secs(30);
+
+// Changes posted to a webhook at once, the rest follow on the next run
+const MAX_CHANGES_PER_DELIVERY: i64 = 500;
+// A webhook that is down keeps the changes it missed for this long, it has to resync after that
+const MAX_CHANGE_AGE_DAYS: i64 = 7;
+// Most changes kept for the webhooks that are behind, the oldest ones are dropped first
+const MAX_PENDING_CHANGES: i32 = 100_000;
+
+fn http_client() -> &'static reqwest::Client {
+	static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+	CLIENT.get_or_init(|| {
+		reqwest::Client::builder()
+			.timeout(Duration::from_secs(10))
+			.build()
+			.expect("Failed to build the webhook http client")
+	})
+}
+
+// New webhooks only get what changes from now on
+pub fn latest_change_id(db_conn: &mut SqliteConnection) -> QueryResult<i32> {
+	library_changes::table
+		.select(diesel::dsl::max(library_changes::change_id))
+		.first::<Option<i32>>(db_conn)
+		.map(|latest| latest.unwrap_or(0))
+}
+
+// Posts every webhook the library changes it hasn't had yet, in order. One that fails gets the same
+// changes again on the next run, the changes every webhook got are dropped. So are the ones older
+// than MAX_CHANGE_AGE_DAYS or past MAX_PENDING_CHANGES, whether they were delivered or not.
+pub fn deliver(app_state: &AppState) -> Result<(), String> {
+	let mut db_conn = app_state
+		.db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+	let hooks = webhooks::table
+		.load::<Webhook>(&mut db_conn)
+		.map_err(|err| err.to_string())?;
+
+	let runtime = tokio::runtime::Handle::current();
+	let mut delivered = Vec::new();
+	for hook in hooks {
+		let changes = library_changes::table
+			.filter(library_changes::change_id.gt(hook.delivered_change_id))
+			.order(library_changes::change_id.asc())
+			.limit(MAX_CHANGES_PER_DELIVERY)
+			.load::<LibraryChangeEntry>(&mut db_conn)
+			.map_err(|err| err.to_string())?;
+		let Some(last_change_id) = changes.last().map(|change| change.change_id) else {
+			delivered.push(hook.delivered_change_id);
+			continue;
+		};
+
+		let body = json!({ "webhook_id": hook.webhook_id, "changes": changes });
+		let request = http_client()
+			.post(&hook.url)
+			.bearer_auth(&hook.secret)
+			.json(&body)
+			.send();
+		let last_error = match runtime.block_on(request) {
+			Ok(response) if response.status().is_success() => None,
+			Ok(response) => Some(format!("Rejected with {}", response.status())),
+			Err(err) => Some(format!("Failed to reach the webhook: {err}")),
+		};
+		let delivered_change_id = match last_error {
+			None => last_change_id,
+			Some(_) => hook.delivered_change_id,
+		};
+		diesel::update(webhooks::table.find(&hook.webhook_id))
+			.set((
+				webhooks::delivered_change_id.eq(delivered_change_id),
+				webhooks::last_error.eq(&last_error),
+			))
+			.execute(&mut db_conn)
+			.map_err(|err| err.to_string())?;
+		delivered.push(delivered_change_id);
+	}
+
+	let delivered_to_all = delivered.into_iter().min().unwrap_or(i32::MAX);
+	let cutoff = (Utc::now() - ChronoDuration::days(MAX_CHANGE_AGE_DAYS)).to_rfc3339();
+	let too_old = library_changes::table
+		.filter(library_changes::changed_date_time.lt(cutoff))
+		.select(diesel::dsl::max(library_changes::change_id))
+		.first::<Option<i32>>(&mut db_conn)
+		.map_err(|err| err.to_string())?
+		.unwrap_or(0);
+	let too_many = latest_change_id(&mut db_conn).map_err(|err| err.to_string())? - MAX_PENDING_CHANGES;
+	let prune_up_to = delivered_to_all.max(too_old).max(too_many);
+	diesel::delete(library_changes::table.filter(library_changes::change_id.le(prune_up_to)))
+		.execute(&mut db_conn)
+		.map_err(|err| format!("Failed to prune the library changes: {err}"))?;
+	Ok(())
+}
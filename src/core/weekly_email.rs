@@ -0,0 +1,296 @@
+use crate::core::app_state::AppState;
+use crate::core::instance::instance_base_url;
+use crate::core::rollups::day_of;
+use crate::i18n::Locale;
+use crate::lobic_db::db::get_user_settings;
+use crate::lobic_db::models::WeeklyEmail;
+use crate::mail::mailer::{is_configured, send_mail};
+use crate::mail::templates::Template;
+use crate::schema::{first_listens, music, play_rollups_daily, users, weekly_emails};
+use crate::utils::{exp, jwt};
+
+use chrono::{Datelike, Duration as ChronoDuration, Local, NaiveDate, Utc};
+use diesel::prelude::*;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::Message;
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Sent on the first check after the week is over
+pub const SEND_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const TOP_TRACKS: usize = 3;
+const UNSUBSCRIBE_LINK_DAYS: u64 = 60;
+// Keeps the unsubscribe tokens from ever passing for an access token of the user
+const UNSUBSCRIBE_PREFIX: &str = "unsubscribe:";
+
+#[derive(Debug, PartialEq)]
+pub struct WeeklySummary {
+	pub plays: i32,
+	pub minutes: i64,
+	pub top_tracks: Vec<String>, // titles, most played first
+	pub top_artist: String,
+	pub new_artists: usize, // played for the first time ever during the week
+	pub new_tracks: usize,
+}
+
+// The monday of the last full week before the day
+pub fn last_week(today: NaiveDate) -> NaiveDate {
+	today - ChronoDuration::days(today.weekday().num_days_from_monday() as i64 + 7)
+}
+
+// What the user listened to in the week starting on the monday, None when they didn't play anything
+pub fn summarize(
+	user_id: &str,
+	week_start: NaiveDate,
+	db_conn: &mut SqliteConnection,
+) -> QueryResult<Option<WeeklySummary>> {
+	let week_end = week_start + ChronoDuration::days(6);
+	let plays = play_rollups_daily::table
+		.inner_join(music::table)
+		.filter(play_rollups_daily::user_id.eq(user_id))
+		.filter(play_rollups_daily::day.between(week_start.to_string(), week_end.to_string()))
+		.select((
+			music::music_id,
+			music::title,
+			music::artist,
+			play_rollups_daily::play_count,
+			play_rollups_daily::listened_secs,
+		))
+		.load::<(String, String, String, i32, i64)>(db_conn)?;
+	if plays.is_empty() {
+		return Ok(None);
+	}
+
+	let mut tracks: HashMap<&str, (&str, i32)> = HashMap::new();
+	let mut artists: HashMap<&str, i32> = HashMap::new();
+	for (music_id, title, artist, play_count, _) in &plays {
+		tracks.entry(music_id).or_insert((title, 0)).1 += play_count;
+		*artists.entry(artist).or_default() += play_count;
+	}
+	let mut tracks: Vec<(&str, i32)> = tracks.into_values().collect();
+	tracks.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+	let top_artist = artists
+		.into_iter()
+		.max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+		.map(|(artist, _)| artist.to_string())
+		.unwrap_or_default();
+
+	let mut new_artists = 0;
+	let mut new_tracks = 0;
+	let firsts = first_listens::table
+		.filter(first_listens::user_id.eq(user_id))
+		.select((first_listens::target_type, first_listens::first_played_date_time))
+		.load::<(String, String)>(db_conn)?;
+	// Stored in utc, the week is in local days like the rollups
+	for (target_type, first_played) in firsts {
+		if !day_of(&first_played).is_some_and(|day| week_start <= day && day <= week_end) {
+			continue;
+		}
+		match target_type.as_str() {
+			"artist" => new_artists += 1,
+			_ => new_tracks += 1,
+		}
+	}
+
+	Ok(Some(WeeklySummary {
+		plays: plays.iter().map(|play| play.3).sum(),
+		minutes: plays.iter().map(|play| play.4).sum::<i64>() / 60,
+		top_tracks: tracks
+			.iter()
+			.take(TOP_TRACKS)
+			.map(|(title, _)| title.to_string())
+			.collect(),
+		top_artist,
+		new_artists,
+		new_tracks,
+	}))
+}
+
+pub fn unsubscribe_url(user_id: &str) -> Option<String> {
+	let secret_key = jwt::purpose_key(&std::env::var("JWT_SECRET_KEY").ok()?, "unsubscribe");
+	let claims = jwt::Claims {
+		id: format!("{UNSUBSCRIBE_PREFIX}{user_id}"),
+		exp: exp::expiration_from_days(UNSUBSCRIBE_LINK_DAYS),
+		iat: exp::now(),
+	};
+	let token = jwt::generate(claims, &secret_key).ok()?;
+	Some(format!("{}/mail/unsubscribe?token={token}", instance_base_url()))
+}
+
+// The user the unsubscribe link was made for
+pub fn unsubscribing_user(token: &str) -> Option<String> {
+	let secret_key = std::env::var("JWT_SECRET_KEY").ok()?;
+	let data = jwt::verify(token, &jwt::purpose_key(&secret_key, "unsubscribe")).ok()?;
+	data.claims.id.strip_prefix(UNSUBSCRIBE_PREFIX).map(str::to_string)
+}
+
+// Lets mail clients show their own unsubscribe button, which posts to the link without asking again
+fn add_unsubscribe_headers(mail: &mut Message, unsubscribe_url: &str) {
+	let headers = mail.headers_mut();
+	headers.insert_raw(HeaderValue::new(
+		HeaderName::new_from_ascii_str("List-Unsubscribe"),
+		format!("<{unsubscribe_url}>"),
+	));
+	headers.insert_raw(HeaderValue::new(
+		HeaderName::new_from_ascii_str("List-Unsubscribe-Post"),
+		"List-Unsubscribe=One-Click".to_string(),
+	));
+}
+
+// Mails last week's summary to everyone who played something in it and didn't turn it off
+pub fn send_summaries(app_state: &AppState) -> Result<(), String> {
+	if !is_configured() {
+		return Ok(());
+	}
+	let mut db_conn = app_state
+		.db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+
+	let week_start = last_week(Local::now().date_naive());
+	let week_end = week_start + ChronoDuration::days(6);
+	let listeners = play_rollups_daily::table
+		.filter(play_rollups_daily::day.between(week_start.to_string(), week_end.to_string()))
+		.filter(
+			play_rollups_daily::user_id.ne_all(
+				weekly_emails::table
+					.filter(weekly_emails::week_start.eq(week_start.to_string()))
+					.select(weekly_emails::user_id),
+			),
+		)
+		.inner_join(users::table)
+		.filter(users::email_verified.eq(true))
+		.select((users::user_id, users::username, users::email))
+		.distinct()
+		.load::<(String, String, String)>(&mut db_conn)
+		.map_err(|err| err.to_string())?;
+
+	for (user_id, username, email) in listeners {
+		if !get_user_settings(&user_id, &mut db_conn).weekly_email {
+			continue;
+		}
+		let Some(summary) = summarize(&user_id, week_start, &mut db_conn).map_err(|err| err.to_string())? else {
+			continue;
+		};
+		let Some(unsubscribe_url) = unsubscribe_url(&user_id) else {
+			return Err("JWT_SECRET_KEY is not set, no unsubscribe links can be made".to_string());
+		};
+
+		// Marked first, a failing mail server doesn't get anyone the same summary twice
+		diesel::insert_into(weekly_emails::table)
+			.values(WeeklyEmail {
+				user_id: user_id.clone(),
+				week_start: week_start.to_string(),
+				sent_date_time: Utc::now().to_rfc3339(),
+			})
+			.execute(&mut db_conn)
+			.map_err(|err| err.to_string())?;
+
+		let locale = Locale::for_user(&user_id, &mut db_conn).unwrap_or(Locale::DEFAULT);
+		let (plays, minutes) = (summary.plays.to_string(), summary.minutes.to_string());
+		let (new_artists, new_tracks) = (summary.new_artists.to_string(), summary.new_tracks.to_string());
+		let top_tracks = summary.top_tracks.join(", ");
+		let mut mail = Template::Digest.message(
+			&email,
+			locale,
+			&[
+				("username", &username),
+				("plays", &plays),
+				("minutes", &minutes),
+				("top_tracks", &top_tracks),
+				("top_artist", &summary.top_artist),
+				("new_artists", &new_artists),
+				("new_tracks", &new_tracks),
+				("unsubscribe_url", &unsubscribe_url),
+			],
+		);
+		add_unsubscribe_headers(&mut mail, &unsubscribe_url);
+		send_mail(mail);
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::lobic_db::models::DailyPlayRollup;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+
+	#[tokio::test]
+	async fn last_week_is_summed_up_and_the_link_unsubscribes() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let mut db_conn = test_app.db_conn();
+		// far enough back that the seeded plays don't fall in it
+		let week_start = NaiveDate::from_ymd_opt(2001, 1, 1).unwrap();
+		assert_eq!(last_week(NaiveDate::from_ymd_opt(2001, 1, 10).unwrap()), week_start);
+		assert_eq!(summarize(&user_id, week_start, &mut db_conn).unwrap(), None);
+
+		let tracks = music::table
+			.select((music::music_id, music::title))
+			.limit(2)
+			.load::<(String, String)>(&mut db_conn)
+			.unwrap();
+		let rollups: Vec<DailyPlayRollup> = [(0, 1, 2), (1, 3, 5), (0, 6, 1)]
+			.into_iter()
+			.map(|(track, day, play_count)| DailyPlayRollup {
+				user_id: user_id.clone(),
+				day: (week_start + ChronoDuration::days(day)).to_string(),
+				music_id: tracks[track].0.clone(),
+				play_count,
+				listened_secs: play_count as i64 * 120,
+			})
+			.collect();
+		diesel::insert_into(play_rollups_daily::table)
+			.values(&rollups)
+			.execute(&mut db_conn)
+			.unwrap();
+		drop(db_conn);
+
+		let summary = summarize(&user_id, week_start, &mut test_app.db_conn())
+			.unwrap()
+			.unwrap();
+		assert_eq!((summary.plays, summary.minutes), (8, 16));
+		assert_eq!(summary.top_tracks, vec![tracks[1].1.clone(), tracks[0].1.clone()]);
+
+		let url = unsubscribe_url(&user_id).unwrap();
+		let path = &url[url.find("/mail/").unwrap()..];
+		// the link lives for days, it can't be passed off as a session
+		let token = &url[url.find("token=").unwrap() + "token=".len()..];
+		let response = test_app
+			.request(Method::GET, "/music/artist/followed", None, &[format!("access_token={token}")])
+			.await;
+		assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+		for method in [Method::GET, Method::POST] {
+			let response = test_app
+				.request(method, "/mail/unsubscribe?token=forged", None, &[])
+				.await;
+			assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		}
+		// opening the link only asks
+		let response = test_app.get(path).await;
+		assert_eq!(response.status, StatusCode::OK);
+		assert!(response.body.contains("<form method=\"post\">"));
+		assert!(get_user_settings(&user_id, &mut test_app.db_conn()).weekly_email);
+		let response = test_app.request(Method::POST, path, None, &[]).await;
+		assert_eq!(response.status, StatusCode::OK);
+		assert!(!get_user_settings(&user_id, &mut test_app.db_conn()).weekly_email);
+
+		let mut mail = Message::builder()
+			.from("lobic@example.com".parse().unwrap())
+			.to("listener@example.com".parse().unwrap())
+			.body(String::new())
+			.unwrap();
+		add_unsubscribe_headers(&mut mail, &url);
+		assert_eq!(
+			mail.headers().get_raw("List-Unsubscribe"),
+			Some(format!("<{url}>").as_str())
+		);
+		assert_eq!(
+			mail.headers().get_raw("List-Unsubscribe-Post"),
+			Some("List-Unsubscribe=One-Click")
+		);
+	}
+}
@@ -0,0 +1,184 @@
+use crate::lobic_db::db::{get_user_settings, DatabasePool};
+use crate::utils::auth::session_user_id;
+
+use axum::http::{header, HeaderMap};
+use axum_extra::extract::cookie::CookieJar;
+use diesel::prelude::*;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// Message catalogs are compiled into the binary, english is the fallback for missing keys
+const CATALOGS: [(Locale, &str); 2] = [
+	(Locale::En, include_str!("locales/en.json")),
+	(Locale::Ne, include_str!("locales/ne.json")),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Locale {
+	En,
+	Ne,
+}
+
+impl Locale {
+	pub const DEFAULT: Locale = Locale::En;
+
+	// Accepts bare or regional tags, e.g. `ne` and `ne-NP`
+	pub fn parse(tag: &str) -> Option<Locale> {
+		let language = tag.trim().split(['-', '_']).next()?.to_lowercase();
+		match language.as_str() {
+			"en" => Some(Locale::En),
+			"ne" => Some(Locale::Ne),
+			_ => None,
+		}
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Locale::En => "en",
+			Locale::Ne => "ne",
+		}
+	}
+
+	// Highest weighted supported language of an Accept-Language header
+	pub fn from_accept_language(value: &str) -> Option<Locale> {
+		let mut candidates: Vec<(f32, Locale)> = value
+			.split(',')
+			.filter_map(|entry| {
+				let mut parts = entry.split(';');
+				let locale = Locale::parse(parts.next()?)?;
+				let weight = parts
+					.find_map(|param| param.trim().strip_prefix("q="))
+					.and_then(|q| q.parse::<f32>().ok())
+					.unwrap_or(1.0);
+				(weight > 0.0).then_some((weight, locale))
+			})
+			.collect();
+
+		// Stable sort keeps the header order between equal weights
+		candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+		candidates.first().map(|(_, locale)| *locale)
+	}
+
+	// The language the user picked in their settings
+	pub fn for_user(user_id: &str, db_conn: &mut SqliteConnection) -> Option<Locale> {
+		get_user_settings(user_id, db_conn)
+			.language
+			.and_then(|language| Locale::parse(&language))
+	}
+
+	// The user setting wins over Accept-Language
+	pub fn negotiate(headers: &HeaderMap, jar: &CookieJar, db_pool: &DatabasePool) -> Locale {
+		let from_settings = session_user_id(jar).and_then(|user_id| {
+			let mut db_conn = db_pool.get().ok()?;
+			Locale::for_user(&user_id, &mut db_conn)
+		});
+
+		from_settings.unwrap_or_else(|| Locale::from_headers(headers))
+	}
+
+	// For requests without a session, like signing up
+	pub fn from_headers(headers: &HeaderMap) -> Locale {
+		headers
+			.get(header::ACCEPT_LANGUAGE)
+			.and_then(|value| value.to_str().ok())
+			.and_then(Locale::from_accept_language)
+			.unwrap_or(Locale::DEFAULT)
+	}
+
+	pub fn t(&self, key: &str) -> String {
+		self.tf(key, &[])
+	}
+
+	// Replaces `{name}` placeholders with the given arguments
+	pub fn tf(&self, key: &str, args: &[(&str, &str)]) -> String {
+		let message = self.lookup(key).unwrap_or_else(|| key.to_string());
+
+		args.iter().fold(message, |message, (name, value)| {
+			message.replace(&format!("{{{name}}}"), value)
+		})
+	}
+
+	fn lookup(&self, key: &str) -> Option<String> {
+		let catalogs = catalogs();
+		catalogs[self]
+			.get(key)
+			.or_else(|| catalogs[&Locale::DEFAULT].get(key))
+			.cloned()
+	}
+
+	// What to show for a genre, the api still takes and gives the genre as tagged. The ones without a
+	// translation are shown as tagged.
+	pub fn genre_label(&self, genre: &str) -> String {
+		self.lookup(&format!("genre.{}", label_key(genre)))
+			.unwrap_or_else(|| genre.to_string())
+	}
+
+	pub fn mood_label(&self, mood: &str) -> String {
+		self.lookup(&format!("mood.{}", label_key(mood)))
+			.unwrap_or_else(|| mood.to_string())
+	}
+}
+
+// "Hip-Hop", "hip hop" and "HIP_HOP" are all the same genre to the catalogs
+fn label_key(name: &str) -> String {
+	name.to_lowercase()
+		.split(|c: char| !c.is_alphanumeric())
+		.filter(|word| !word.is_empty())
+		.collect::<Vec<_>>()
+		.join("_")
+}
+
+fn catalogs() -> &'static HashMap<Locale, HashMap<String, String>> {
+	static CATALOGS_CELL: OnceLock<HashMap<Locale, HashMap<String, String>>> = OnceLock::new();
+	CATALOGS_CELL.get_or_init(|| {
+		CATALOGS
+			.iter()
+			.map(|(locale, source)| {
+				let messages = serde_json::from_str(source)
+					.unwrap_or_else(|err| panic!("Invalid message catalog for {}: {err}", locale.as_str()));
+				(*locale, messages)
+			})
+			.collect()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn accept_language_picks_the_highest_weighted_supported_locale() {
+		assert_eq!(Locale::from_accept_language("ne-NP,en;q=0.8"), Some(Locale::Ne));
+		assert_eq!(Locale::from_accept_language("fr, en;q=0.5, ne;q=0.9"), Some(Locale::Ne));
+		assert_eq!(Locale::from_accept_language("de, ne;q=0"), None);
+		assert_eq!(Locale::from_accept_language("*"), None);
+	}
+
+	#[test]
+	fn messages_are_interpolated_and_fall_back_to_english() {
+		assert_eq!(
+			Locale::En.tf("settings.invalid_language", &[("language", "fr")]),
+			"Unsupported language: fr"
+		);
+		assert_eq!(Locale::Ne.t("missing.key"), "missing.key");
+	}
+
+	#[test]
+	fn genres_are_labelled_however_they_were_tagged() {
+		assert_eq!(Locale::Ne.genre_label("Hip-Hop"), Locale::Ne.genre_label("hip hop"));
+		assert_ne!(Locale::Ne.genre_label("R&B"), "R&B");
+		assert_eq!(Locale::En.genre_label("r&b"), "R&B");
+		assert_eq!(Locale::Ne.genre_label("Vaporwave Deluxe"), "Vaporwave Deluxe");
+		assert_eq!(Locale::En.mood_label("melancholic"), "Melancholic");
+	}
+
+	#[test]
+	fn every_catalog_has_the_english_keys() {
+		let catalogs = catalogs();
+		for (locale, messages) in catalogs {
+			for key in catalogs[&Locale::DEFAULT].keys() {
+				assert!(messages.contains_key(key), "{} is missing {key}", locale.as_str());
+			}
+		}
+	}
+}
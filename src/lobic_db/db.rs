@@ -1,17 +1,34 @@
-use crate::lobic_db::models::User;
+use crate::lobic_db::models::{Availability, LibraryChange, StatsVisibility, User, UserSettings};
+use crate::schema::{instance_settings, library_changes, music, user_friendship, user_settings};
 use crate::schema::users::dsl::*;
 
 use diesel::prelude::*;
-use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, Pool};
 
 pub type DatabasePool = Pool<ConnectionManager<SqliteConnection>>;
 
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
+// Writers wait for each other instead of failing with "database is locked"
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionOptions;
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for ConnectionOptions {
+	fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+		diesel::sql_query(format!("PRAGMA busy_timeout = {BUSY_TIMEOUT_MS}"))
+			.execute(conn)
+			.map(|_| ())
+			.map_err(diesel::r2d2::Error::QueryError)
+	}
+}
+
 pub fn generate_db_pool() -> DatabasePool {
 	let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set in .env file");
 
 	let manager = ConnectionManager::<SqliteConnection>::new(database_url);
 	Pool::builder()
 		.max_size(5)
+		.connection_customizer(Box::new(ConnectionOptions))
 		.build(manager)
 		.expect("Failed to create pool")
 }
@@ -29,3 +46,214 @@ pub fn user_exists(id: &str, db_pool: &DatabasePool) -> bool {
 
 	query.is_ok()
 }
+
+pub fn user_is_admin(id: &str, db_pool: &DatabasePool) -> bool {
+	let mut db_conn = match db_pool.get() {
+		Ok(conn) => conn,
+		Err(_) => {
+			println!("[user_is_admin]: Cannot get databse through pool");
+			return false;
+		}
+	};
+
+	users
+		.filter(user_id.eq(id))
+		.select(is_admin)
+		.first::<bool>(&mut db_conn)
+		.unwrap_or(false)
+}
+
+// Returns the availability along with the reason, None if the music doesn't exist
+pub fn music_availability(id: &str, db_conn: &mut SqliteConnection) -> Option<(Availability, Option<String>)> {
+	let query = music::table
+		.filter(music::music_id.eq(id))
+		.select((music::availability, music::availability_reason))
+		.first::<(String, Option<String>)>(db_conn);
+
+	match query {
+		Ok((status, reason)) => Some((Availability::parse(&status).unwrap_or(Availability::Available), reason)),
+		Err(_) => None,
+	}
+}
+
+pub fn set_music_availability(
+	id: &str,
+	status: Availability,
+	reason: Option<String>,
+	db_conn: &mut SqliteConnection,
+) -> QueryResult<usize> {
+	let previous = music_availability(id, db_conn).map(|(previous, _)| previous);
+	let updated = diesel::update(music::table.filter(music::music_id.eq(id)))
+		.set((
+			music::availability.eq(status.as_str()),
+			music::availability_reason.eq(reason),
+		))
+		.execute(db_conn)?;
+
+	// To the integrations a track only comes or goes
+	match previous {
+		Some(previous) if previous != Availability::Available && status == Availability::Available => {
+			record_library_change(id, LibraryChange::Added, db_conn)?;
+		}
+		Some(Availability::Available) if status != Availability::Available => {
+			record_library_change(id, LibraryChange::Removed, db_conn)?;
+		}
+		_ => (),
+	}
+	Ok(updated)
+}
+
+// Queues the change up for the webhooks
+pub fn record_library_change(id: &str, change: LibraryChange, db_conn: &mut SqliteConnection) -> QueryResult<usize> {
+	diesel::insert_into(library_changes::table)
+		.values((
+			library_changes::music_id.eq(id),
+			library_changes::change.eq(change.as_str()),
+			library_changes::changed_date_time.eq(chrono::Utc::now().to_rfc3339()),
+		))
+		.execute(db_conn)
+}
+
+// Filters out the ids of the musics which cannot be played right now, errs when that can't be told
+pub fn unavailable_music_ids(ids: &[String], db_pool: &DatabasePool) -> Result<Vec<String>, String> {
+	let mut db_conn = db_pool
+		.get()
+		.map_err(|err| format!("Failed to get DB from pool: {err}"))?;
+
+	let available: Vec<String> = music::table
+		.filter(music::music_id.eq_any(ids))
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.select(music::music_id)
+		.load::<String>(&mut db_conn)
+		.map_err(|err| format!("Database error: {err}"))?;
+
+	Ok(ids.iter().filter(|id| !available.contains(id)).cloned().collect())
+}
+
+// The ids of the musics marked explicit
+pub fn explicit_music_ids(ids: &[String], db_pool: &DatabasePool) -> Vec<String> {
+	let mut db_conn = match db_pool.get() {
+		Ok(conn) => conn,
+		Err(_) => {
+			println!("[explicit_music_ids]: Cannot get databse through pool");
+			return Vec::new();
+		}
+	};
+
+	music::table
+		.filter(music::music_id.eq_any(ids))
+		.filter(music::explicit.eq(true))
+		.select(music::music_id)
+		.load::<String>(&mut db_conn)
+		.unwrap_or_default()
+}
+
+// Friendship goes both ways, each side has to have added the other
+pub fn are_friends(id: &str, other_id: &str, db_pool: &DatabasePool) -> bool {
+	let mut db_conn = match db_pool.get() {
+		Ok(conn) => conn,
+		Err(_) => {
+			println!("[are_friends]: Cannot get databse through pool");
+			return false;
+		}
+	};
+
+	let count = user_friendship::table
+		.filter(
+			(user_friendship::user_id.eq(id).and(user_friendship::friend_id.eq(other_id)))
+				.or(user_friendship::user_id.eq(other_id).and(user_friendship::friend_id.eq(id))),
+		)
+		.count()
+		.get_result::<i64>(&mut db_conn)
+		.unwrap_or(0);
+
+	count == 2
+}
+
+// The users who added the user as a friend and were added back
+pub fn mutual_friend_ids(id: &str, db_pool: &DatabasePool) -> Vec<String> {
+	let mut db_conn = match db_pool.get() {
+		Ok(conn) => conn,
+		Err(_) => {
+			println!("[mutual_friend_ids]: Cannot get databse through pool");
+			return Vec::new();
+		}
+	};
+
+	let added: Vec<String> = user_friendship::table
+		.filter(user_friendship::user_id.eq(id))
+		.select(user_friendship::friend_id)
+		.load::<String>(&mut db_conn)
+		.unwrap_or_default();
+
+	user_friendship::table
+		.filter(user_friendship::friend_id.eq(id))
+		.filter(user_friendship::user_id.eq_any(&added))
+		.select(user_friendship::user_id)
+		.load::<String>(&mut db_conn)
+		.unwrap_or_default()
+}
+
+// The settings of the user, the defaults if they never changed them
+pub fn get_user_settings(id: &str, db_conn: &mut SqliteConnection) -> UserSettings {
+	user_settings::table
+		.filter(user_settings::user_id.eq(id))
+		.first::<UserSettings>(db_conn)
+		.unwrap_or_else(|_| UserSettings::default_for(id))
+}
+
+pub fn in_private_session(id: &str, db_pool: &DatabasePool) -> bool {
+	match db_pool.get() {
+		Ok(mut db_conn) => get_user_settings(id, &mut db_conn).in_private_session(),
+		Err(_) => false,
+	}
+}
+
+// Do not disturb, or inside the user's quiet hours right now
+pub fn is_quiet(id: &str, db_pool: &DatabasePool) -> bool {
+	match db_pool.get() {
+		Ok(mut db_conn) => get_user_settings(id, &mut db_conn).is_quiet_at(chrono::Local::now().time()),
+		Err(_) => false,
+	}
+}
+
+// Whether the viewer is allowed to look at the listening stats of the owner
+pub fn can_view_stats(viewer_id: &str, owner_id: &str, db_pool: &DatabasePool) -> bool {
+	if viewer_id == owner_id {
+		return true;
+	}
+
+	let mut db_conn = match db_pool.get() {
+		Ok(conn) => conn,
+		Err(_) => {
+			println!("[can_view_stats]: Cannot get databse through pool");
+			return false;
+		}
+	};
+
+	let settings = get_user_settings(owner_id, &mut db_conn);
+	drop(db_conn);
+
+	match settings.stats_visibility() {
+		StatsVisibility::Public => true,
+		StatsVisibility::Friends => are_friends(viewer_id, owner_id, db_pool),
+		StatsVisibility::Private => false,
+	}
+}
+
+pub fn get_instance_setting(key: &str, db_conn: &mut SqliteConnection) -> Option<String> {
+	instance_settings::table
+		.filter(instance_settings::key.eq(key))
+		.select(instance_settings::value)
+		.first::<String>(db_conn)
+		.ok()
+}
+
+pub fn set_instance_setting(key: &str, value: &str, db_conn: &mut SqliteConnection) -> QueryResult<usize> {
+	diesel::insert_into(instance_settings::table)
+		.values((instance_settings::key.eq(key), instance_settings::value.eq(value)))
+		.on_conflict(instance_settings::key)
+		.do_update()
+		.set(instance_settings::value.eq(value))
+		.execute(db_conn)
+}
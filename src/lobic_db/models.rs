@@ -1,9 +1,11 @@
-use std::hash::{DefaultHasher, Hash, Hasher};
-
 use crate::config::OpCode;
+use crate::core::artwork::cover_uuid;
+use crate::core::audio_analysis::MusicalKey;
 use crate::schema::*;
+use crate::utils::list::ListResponse;
 
-use diesel::{prelude::Insertable, Queryable, Selectable};
+use diesel::prelude::*;
+use diesel::upsert::excluded;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use uuid::Uuid;
@@ -19,6 +21,7 @@ pub struct User {
 	pub otp: String,
 	pub otp_expires_at: String,
 	pub otp_verified: Option<String>,
+	pub is_admin: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -44,6 +47,9 @@ pub struct Playlist {
 	pub creation_date_time: String,
 	pub last_updated_date_time: String,
 	pub is_playlist_combined: bool,
+	pub availability: String,
+	pub availability_reason: Option<String>,
+	pub version: i32, // bumped on every edit
 }
 //for response
 #[derive(Debug, Serialize)]
@@ -55,19 +61,82 @@ pub struct PlaylistInfo {
 	pub last_updated_date_time: String,
 	pub is_playlist_combined: bool,
 }
+// The user the playlists were asked for next to the usual list fields
 #[derive(Debug, Serialize)]
 pub struct UserPlaylistsResponse {
 	pub user_id: String,
-	pub playlists: Vec<PlaylistInfo>,
+	#[serde(flatten)]
+	pub playlists: ListResponse<PlaylistInfo>,
 }
 
-#[derive(Insertable, Queryable, Debug)]
+// A play queue kept to go back to
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = queue_snapshots)]
+pub struct QueueSnapshot {
+	pub snapshot_id: String,
+	pub user_id: String,
+	pub name: String,
+	pub music_ids: String, // json array, in queue order
+	pub position: f64,     // seconds into the first track
+	pub automatic: bool,   // taken on logout
+	pub created_date_time: String,
+}
+
+// What a user used of an expensive feature on a UTC day
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = quotas)]
+pub struct Quota {
+	pub user_id: String,
+	pub feature: String, // transcode, export or metadata_lookup
+	pub day: String,     // YYYY-MM-DD
+	pub used: i32,
+}
+
+// A search with its filters kept as a collection, the songs are looked up again on every open
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = saved_searches)]
+pub struct SavedSearch {
+	pub search_id: String,
+	pub user_id: String,
+	pub name: String,
+	pub search_string: String,
+	pub genre: Option<String>,
+	pub decade: Option<String>,
+	pub bpm_min: Option<f64>,
+	pub bpm_max: Option<f64>,
+	pub musical_key: Option<String>,
+	pub created_date_time: String,
+}
+#[derive(Insertable, Queryable, Debug, Serialize, Deserialize)]
 #[diesel(table_name = playlist_songs)]
 pub struct PlaylistSong {
 	pub playlist_id: String,
 	pub music_id: String,
 	pub song_adder_id: String,
 	pub song_added_date_time: String,
+	#[serde(default)]
+	pub position: i32, // order in the playlist, the same track can be in it more than once
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = playlist_folders)]
+pub struct PlaylistFolder {
+	pub folder_id: String,
+	pub user_id: String,
+	pub name: String,
+	pub parent_id: Option<String>, // none at the top
+	pub position: i32,             // among the folders of the parent
+	pub created_date_time: String,
+}
+
+// Where the user put the playlist, the ones without it are at the top
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = playlist_folder_items)]
+pub struct PlaylistFolderItem {
+	pub user_id: String,
+	pub playlist_id: String,
+	pub folder_id: Option<String>,
+	pub position: i32, // among the playlists of the folder
 }
 
 #[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
@@ -77,6 +146,41 @@ pub struct PlaylistShare {
 	pub contributor_user_id: String,
 }
 
+// A deleted playlist in the trash of its owner
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = playlist_trash)]
+pub struct TrashedPlaylist {
+	pub playlist_id: String,
+	pub user_id: String,
+	pub playlist_name: String,
+	pub song_count: i32,
+	#[serde(skip)]
+	pub snapshot: String, // PlaylistState as json
+	pub deleted_date_time: String,
+	pub purge_date_time: String, // gone for good after it
+}
+
+// A playlist kept private until its release_date_time, then made public and its owner's friends told
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = playlist_releases)]
+pub struct PlaylistRelease {
+	pub playlist_id: String,
+	pub user_id: String,
+	pub release_date_time: String,
+	#[serde(skip)]
+	pub previous_availability: String, // what calling the release off puts back
+	pub scheduled_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug)]
+#[diesel(table_name = playlist_undo)]
+pub struct PlaylistUndo {
+	pub token: String,
+	pub playlist_id: String,
+	pub snapshot: String, // UndoSnapshot as json
+	pub expires_date_time: String,
+}
+
 #[derive(Insertable, Queryable, Debug)]
 #[diesel(table_name = play_log)]
 pub struct PlayLog {
@@ -86,6 +190,151 @@ pub struct PlayLog {
 	pub user_times_played: i32,
 }
 
+impl PlayLog {
+	// Adds the plays to the user's count and the track's in one statement each, nothing is read back first so
+	// plays reported from several devices at once all count
+	pub fn record(
+		user_id: &str,
+		music_id: &str,
+		played_date_time: &str,
+		plays: i32,
+		conn: &mut SqliteConnection,
+	) -> QueryResult<()> {
+		let new_play_log = PlayLog {
+			user_id: user_id.to_string(),
+			music_id: music_id.to_string(),
+			music_played_date_time: played_date_time.to_string(),
+			user_times_played: plays,
+		};
+		diesel::insert_into(play_log::table)
+			.values(&new_play_log)
+			.on_conflict((play_log::user_id, play_log::music_id))
+			.do_update()
+			.set((
+				play_log::music_played_date_time.eq(excluded(play_log::music_played_date_time)),
+				play_log::user_times_played.eq(play_log::user_times_played + excluded(play_log::user_times_played)),
+			))
+			.execute(conn)?;
+		diesel::update(music::table.filter(music::music_id.eq(music_id)))
+			.set(music::times_played.eq(music::times_played + plays))
+			.execute(conn)?;
+		Ok(())
+	}
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = play_events)]
+pub struct PlayEvent {
+	pub event_id: String,
+	pub user_id: String,
+	pub music_id: String,
+	pub played_date_time: String,
+	pub listened_secs: i64,
+	pub device: Option<String>, // the client's name for itself, or the name of the scrobble token
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = play_rollups_daily)]
+pub struct DailyPlayRollup {
+	pub user_id: String,
+	pub day: String, // YYYY-MM-DD, local time
+	pub music_id: String,
+	pub play_count: i32,
+	pub listened_secs: i64,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = play_rollups_monthly)]
+pub struct MonthlyPlayRollup {
+	pub user_id: String,
+	pub month: String, // YYYY-MM, local time
+	pub music_id: String,
+	pub play_count: i32,
+	pub listened_secs: i64,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = animated_covers)]
+pub struct AnimatedCover {
+	pub target_type: String, // album | playlist
+	pub target_id: String,
+	pub uploader_id: String,
+	pub duration_secs: f64,
+	pub uploaded_date_time: String,
+}
+
+// A picture of the artist, shown for the albums of theirs without a cover
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = artist_images)]
+pub struct ArtistImage {
+	pub artist: String, // the main artist, lowercased
+	pub img_uuid: String,
+	pub source: String, // the provider it came from
+	pub source_url: Option<String>,
+	pub fetched_date_time: String,
+}
+
+// Artwork for an album, uploaded or the one from its tags. The canonical one is copied to the album's cover.
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = cover_art)]
+pub struct CoverArt {
+	pub art_id: String,
+	pub img_uuid: String,
+	pub uploader_id: Option<String>,
+	pub is_original: bool,
+	pub is_canonical: bool,
+	pub uploaded_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable)]
+#[diesel(table_name = cover_palettes)]
+pub struct CoverPalette {
+	pub img_uuid: String,
+	pub colors: String, // json array of PaletteColor
+	pub extracted_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = first_listens)]
+pub struct FirstListen {
+	pub user_id: String,
+	pub target_type: String, // track | artist
+	pub target_id: String,
+	pub first_played_date_time: String,
+	pub notified_year: Option<i32>,
+}
+
+// Never play this, for a track or every track of an artist
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = blocked_content)]
+pub struct BlockedContent {
+	pub user_id: String,
+	pub target_type: String, // track or artist
+	pub target_id: String,
+	pub blocked_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = artist_follows)]
+pub struct ArtistFollow {
+	pub user_id: String,
+	pub artist: String,
+	pub followed_date_time: String,
+}
+
+// Tracks of a followed artist the scanner added, one per album and follower
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = new_releases)]
+pub struct NewRelease {
+	pub release_id: String,
+	pub user_id: String,
+	pub artist: String,
+	pub album: String,
+	pub music_ids: String, // json array
+	pub released_date_time: String,
+	pub notified: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LikedSongs {
 	pub user_id: String,
@@ -145,14 +394,20 @@ pub struct Music {
 	pub genre: String,
 	pub times_played: i32,
 	pub duration: i64,
+	pub availability: String,
+	pub availability_reason: Option<String>,
+	pub uploader_id: Option<String>,
+	pub content_type: String,
+	pub series: Option<String>,
+	pub series_index: Option<i32>,
+	pub release_year: Option<i32>,
+	pub bpm: Option<f64>,
+	pub musical_key: Option<String>,
+	pub explicit: bool,
 }
 impl Music {
 	pub fn create_music_response(entry: Music) -> MusicResponse {
-		let mut hasher = DefaultHasher::new();
-		entry.artist.hash(&mut hasher);
-		entry.album.hash(&mut hasher);
-		let hash = hasher.finish();
-		let img_uuid = Uuid::from_u64_pair(hash, hash);
+		let img_uuid = cover_uuid(&entry.artist, &entry.album);
 		MusicResponse {
 			id: entry.music_id.clone(),
 			artist: entry.artist,
@@ -162,10 +417,61 @@ impl Music {
 			times_played: entry.times_played,
 			duration: entry.duration,
 			image_url: img_uuid.to_string(),
+			content_type: entry.content_type,
+			release_year: entry.release_year,
+			bpm: entry.bpm,
+			camelot: entry
+				.musical_key
+				.as_deref()
+				.and_then(MusicalKey::parse)
+				.map(|key| key.camelot()),
+			musical_key: entry.musical_key,
+			explicit: entry.explicit,
 		}
 	}
 }
-#[derive(Debug, Serialize, Deserialize)]
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize, Clone, PartialEq)]
+#[diesel(table_name = music_chapters)]
+pub struct MusicChapter {
+	pub music_id: String,
+	pub chapter_index: i32,
+	pub title: String,
+	pub start_ms: i64,
+	pub end_ms: i64,
+}
+
+impl MusicChapter {
+	pub fn contains(&self, position_ms: i64) -> bool {
+		self.start_ms <= position_ms && position_ms < self.end_ms
+	}
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize, Clone, PartialEq)]
+#[diesel(table_name = track_genres)]
+pub struct TrackGenre {
+	pub music_id: String,
+	pub genre: String,
+	pub position: i32,
+}
+
+// Another form of a track's title or artist, searched along with the tagged one
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize, Clone, PartialEq)]
+#[diesel(table_name = music_alt_names)]
+pub struct MusicAltName {
+	pub music_id: String,
+	pub field: String, // title or artist
+	pub name: String,
+	pub kind: String, // original, romanized or translated
+}
+
+impl MusicAltName {
+	pub const TITLE: &str = "title";
+	pub const ARTIST: &str = "artist";
+	pub const KINDS: [&str; 3] = ["original", "romanized", "translated"];
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MusicResponse {
 	pub id: String,
 	pub artist: String,
@@ -175,4 +481,531 @@ pub struct MusicResponse {
 	pub times_played: i32,
 	pub duration: i64,
 	pub image_url: String,
+	pub content_type: String,
+	pub release_year: Option<i32>,
+	pub bpm: Option<f64>,
+	pub musical_key: Option<String>,
+	pub camelot: Option<String>, // the key on the Camelot wheel, 8A for Am
+	pub explicit: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Availability {
+	Available,
+	MissingFile,
+	TakenDown,
+	Private,
+}
+
+impl Availability {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Availability::Available => "available",
+			Availability::MissingFile => "missing_file",
+			Availability::TakenDown => "taken_down",
+			Availability::Private => "private",
+		}
+	}
+
+	pub fn parse(value: &str) -> Option<Availability> {
+		match value {
+			"available" => Some(Availability::Available),
+			"missing_file" => Some(Availability::MissingFile),
+			"taken_down" => Some(Availability::TakenDown),
+			"private" => Some(Availability::Private),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentType {
+	Music,
+	Audiobook,
+	Podcast,
+}
+
+impl ContentType {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			ContentType::Music => "music",
+			ContentType::Audiobook => "audiobook",
+			ContentType::Podcast => "podcast",
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mood {
+	Chill,
+	Melancholic,
+	Upbeat,
+	Dark,
+	Energetic,
+	Intense,
+}
+
+impl Mood {
+	pub const ALL: [Mood; 6] = [
+		Mood::Chill,
+		Mood::Melancholic,
+		Mood::Upbeat,
+		Mood::Dark,
+		Mood::Energetic,
+		Mood::Intense,
+	];
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Mood::Chill => "chill",
+			Mood::Melancholic => "melancholic",
+			Mood::Upbeat => "upbeat",
+			Mood::Dark => "dark",
+			Mood::Energetic => "energetic",
+			Mood::Intense => "intense",
+		}
+	}
+
+	pub fn parse(value: &str) -> Option<Mood> {
+		Mood::ALL
+			.into_iter()
+			.find(|mood| mood.as_str().eq_ignore_ascii_case(value.trim()))
+	}
+
+	// Where the mood sits on the energy scale, for manual tags that leave the energy out
+	pub fn typical_energy(&self) -> f64 {
+		match self {
+			Mood::Chill | Mood::Melancholic => 0.2,
+			Mood::Upbeat | Mood::Dark => 0.5,
+			Mood::Energetic | Mood::Intense => 0.8,
+		}
+	}
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = track_moods)]
+pub struct TrackMood {
+	pub music_id: String,
+	pub mood: String,
+	pub energy: f64,
+	pub source: String, // manual or analysis
+	pub tagged_by: Option<String>,
+	pub updated_date_time: String,
+}
+
+// From 1 to 5 stars, imported from other servers for now
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = track_ratings)]
+pub struct TrackRating {
+	pub user_id: String,
+	pub music_id: String,
+	pub rating: i32,
+	pub rated_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = user_tags)]
+pub struct UserTag {
+	pub user_id: String,
+	pub target_type: String, // track or album
+	pub target_id: String,   // music_id, or the album name
+	pub tag: String,
+	pub created_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = profile_pins)]
+pub struct ProfilePin {
+	pub user_id: String,
+	pub target_type: String, // playlist, album or track
+	pub target_id: String,   // playlist_id, music_id, or the album name
+	pub position: i32,
+	pub pinned_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = profile_anthems)]
+pub struct ProfileAnthem {
+	pub user_id: String,
+	pub music_id: String,
+	pub start_secs: f64,
+	pub updated_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = blocked_tags)]
+pub struct BlockedTag {
+	pub tag: String,
+	pub blocked_by: String,
+	pub blocked_date_time: String,
+}
+
+// A track that failed to play, as the player reported it
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = client_errors)]
+pub struct ClientError {
+	pub error_id: String,
+	pub user_id: String,
+	pub music_id: String,
+	pub error_code: String, // one of client_errors::ERROR_CODES
+	pub device: Option<String>,
+	pub network_type: Option<String>,
+	pub message: Option<String>,
+	pub reported_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = audiobook_progress)]
+pub struct AudiobookProgress {
+	pub user_id: String,
+	pub music_id: String,
+	pub position: f64,
+	pub finished: bool,
+	pub updated_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = takedowns)]
+pub struct Takedown {
+	pub takedown_id: String,
+	pub target_type: String,
+	pub target_id: String,
+	pub reason: String,
+	pub admin_id: String,
+	pub status: String,
+	pub created_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = takedown_events)]
+pub struct TakedownEvent {
+	pub event_id: String,
+	pub takedown_id: String,
+	pub actor_id: String,
+	pub action: String,
+	pub message: String,
+	pub created_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = federation_peers)]
+pub struct FederationPeer {
+	pub peer_id: String,
+	pub name: String,
+	pub base_url: Option<String>,
+	#[serde(skip_serializing)]
+	pub local_token: String,
+	#[serde(skip_serializing)]
+	pub remote_token: Option<String>,
+	pub status: String,
+	pub created_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize)]
+#[diesel(table_name = scrobble_tokens)]
+pub struct ScrobbleToken {
+	pub token_id: String,
+	pub user_id: String,
+	pub name: String,
+	#[serde(skip_serializing)]
+	pub token: String,
+	pub created_date_time: String,
+	pub last_used_date_time: Option<String>,
+	pub scope: String,
+}
+
+impl ScrobbleToken {
+	// What the token can be used for
+	pub const SCROBBLE: &str = "scrobble";
+	pub const MPD: &str = "mpd";
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize)]
+#[diesel(table_name = embed_tokens)]
+pub struct EmbedToken {
+	pub token_id: String,
+	pub user_id: String,
+	pub target_type: String,
+	pub target_id: String,
+	#[serde(skip_serializing)]
+	pub token: String,
+	pub created_date_time: String,
+	pub last_used_date_time: Option<String>,
+}
+
+impl EmbedToken {
+	// What the token shows
+	pub const PLAYLIST: &str = "playlist";
+	pub const PROFILE: &str = "profile";
+	pub const NOW_PLAYING: &str = "now_playing"; // the user's current track, for the stream overlays
+	pub const LOBBY: &str = "lobby"; // the current track of a lobby the user hosts, same overlays
+
+	pub fn is_overlay(&self) -> bool {
+		self.target_type == EmbedToken::NOW_PLAYING || self.target_type == EmbedToken::LOBBY
+	}
+}
+
+// A third party app, the secret is only shown when it gets registered or reset
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize)]
+#[diesel(table_name = oauth_clients)]
+pub struct OAuthClient {
+	pub client_id: String,
+	#[serde(skip_serializing)]
+	pub client_secret: String, // sha256, empty for the public apps
+	pub name: String,
+	pub redirect_uris: String, // json array
+	pub owner_id: String,
+	pub created_date_time: String,
+	pub is_public: bool, // can't keep a secret, its codes need PKCE
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize)]
+#[diesel(table_name = oauth_grants)]
+pub struct OAuthGrant {
+	pub user_id: String,
+	pub client_id: String,
+	pub scope: String,
+	pub granted_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable)]
+#[diesel(table_name = oauth_codes)]
+pub struct OAuthCode {
+	pub code: String, // sha256
+	pub client_id: String,
+	pub user_id: String,
+	pub redirect_uri: String,
+	pub scope: String,
+	pub expires_date_time: String,
+	pub code_challenge: Option<String>, // S256 of the code_verifier the app has to trade it with
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable)]
+#[diesel(table_name = oauth_tokens)]
+pub struct OAuthToken {
+	pub access_token: String,  // sha256
+	pub refresh_token: String, // sha256
+	pub client_id: String,
+	pub user_id: String,
+	pub scope: String,
+	pub expires_date_time: String,
+	pub created_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Clone)]
+#[diesel(table_name = webhooks)]
+pub struct Webhook {
+	pub webhook_id: String,
+	pub url: String,
+	#[serde(skip_serializing)]
+	pub secret: String,
+	pub delivered_change_id: i32,
+	pub last_error: Option<String>,
+	pub created_date_time: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LibraryChange {
+	Added,    // new, or available again
+	Removed,  // deleted, missing, taken down or made private
+	Retagged, // its mood or content type changed
+}
+
+impl LibraryChange {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			LibraryChange::Added => "added",
+			LibraryChange::Removed => "removed",
+			LibraryChange::Retagged => "retagged",
+		}
+	}
+}
+
+#[derive(Queryable, Debug, Selectable, Serialize)]
+#[diesel(table_name = library_changes)]
+pub struct LibraryChangeEntry {
+	pub change_id: i32,
+	pub music_id: String,
+	pub change: String,
+	pub changed_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Clone)]
+#[diesel(table_name = ip_rules)]
+pub struct IpRule {
+	pub rule_id: String,
+	pub cidr: String,
+	pub action: String,
+	pub note: Option<String>,
+	pub created_date_time: String,
+}
+
+impl IpRule {
+	pub const ALLOW: &str = "allow";
+	pub const DENY: &str = "deny";
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = user_settings)]
+pub struct UserSettings {
+	pub user_id: String,
+	pub stats_visibility: String,
+	pub leaderboard_opt_out: bool,
+	pub language: Option<String>,
+	pub private_session_until: Option<String>, // rfc3339, turns itself off after it
+	pub do_not_disturb: bool,
+	pub quiet_hours_start: Option<String>, // HH:MM in server local time
+	pub quiet_hours_end: Option<String>,
+	pub allow_listen_along: bool, // friends may follow the user's playback
+	pub weekly_email: bool,       // the "your week in music" summary
+}
+
+impl UserSettings {
+	pub fn default_for(user_id: &str) -> UserSettings {
+		UserSettings {
+			user_id: user_id.to_string(),
+			stats_visibility: StatsVisibility::Friends.as_str().to_string(),
+			leaderboard_opt_out: false,
+			language: None,
+			private_session_until: None,
+			do_not_disturb: false,
+			quiet_hours_start: None,
+			quiet_hours_end: None,
+			allow_listen_along: true,
+			weekly_email: true,
+		}
+	}
+
+	// Whether notifications should be stored without being pushed to the user at the given local time
+	pub fn is_quiet_at(&self, time: chrono::NaiveTime) -> bool {
+		if self.do_not_disturb {
+			return true;
+		}
+		let parse = |value: &Option<String>| {
+			value
+				.as_deref()
+				.and_then(|value| chrono::NaiveTime::parse_from_str(value, "%H:%M").ok())
+		};
+		match (parse(&self.quiet_hours_start), parse(&self.quiet_hours_end)) {
+			// Quiet hours like 22:00 to 07:00 go over midnight
+			(Some(start), Some(end)) if start <= end => start <= time && time < end,
+			(Some(start), Some(end)) => time >= start || time < end,
+			_ => false,
+		}
+	}
+
+	pub fn in_private_session(&self) -> bool {
+		self.private_session_until
+			.as_deref()
+			.and_then(|until| chrono::DateTime::parse_from_rfc3339(until).ok())
+			.is_some_and(|until| until > chrono::Utc::now())
+	}
+
+	pub fn stats_visibility(&self) -> StatsVisibility {
+		StatsVisibility::parse(&self.stats_visibility).unwrap_or(StatsVisibility::Friends)
+	}
+}
+
+// Who gets to look at the listening stats of a user
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsVisibility {
+	Public,
+	Friends,
+	Private,
+}
+
+impl StatsVisibility {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			StatsVisibility::Public => "public",
+			StatsVisibility::Friends => "friends",
+			StatsVisibility::Private => "private",
+		}
+	}
+
+	pub fn parse(value: &str) -> Option<StatsVisibility> {
+		match value {
+			"public" => Some(StatsVisibility::Public),
+			"friends" => Some(StatsVisibility::Friends),
+			"private" => Some(StatsVisibility::Private),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = leaderboard_entries)]
+pub struct LeaderboardEntry {
+	pub week_start: String,
+	pub user_id: String,
+	pub minutes_listened: i64,
+	pub distinct_artists: i32,
+	pub refreshed_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = user_achievements)]
+pub struct UserAchievement {
+	pub user_id: String,
+	pub achievement_id: String,
+	pub progress: i32,
+	pub unlocked_date_time: Option<String>,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable)]
+#[diesel(table_name = weekly_emails)]
+pub struct WeeklyEmail {
+	pub user_id: String,
+	pub week_start: String,
+	pub sent_date_time: String,
+}
+
+// A target the user set for themselves, like 50 new artists this year
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = listening_goals)]
+pub struct ListeningGoal {
+	pub goal_id: String,
+	pub user_id: String,
+	pub kind: String,
+	pub target: i32,
+	pub period_start: String,
+	pub period_end: String,
+	pub progress: i32,
+	pub completed_date_time: Option<String>,
+	pub created_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = analytics_daily)]
+pub struct AnalyticsDay {
+	pub day: String,
+	pub active_users: i32,
+	pub weekly_active_users: i32,
+	pub monthly_active_users: i32,
+	pub plays: i32,
+	pub minutes_listened: i64,
+	pub refreshed_date_time: String,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = analytics_retention)]
+pub struct RetentionEntry {
+	pub cohort_week: String,
+	pub week_offset: i32,
+	pub cohort_size: i32,
+	pub retained_users: i32,
+}
+
+#[derive(Insertable, Queryable, Debug, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = analytics_top_content)]
+pub struct TopContentEntry {
+	pub kind: String,
+	pub rank: i32,
+	pub target_id: String,
+	pub plays: i32,
 }
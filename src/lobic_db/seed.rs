@@ -0,0 +1,392 @@
+use crate::core::audio_analysis::MusicalKey;
+use crate::core::rollups;
+use crate::lobic_db::models::{
+	Availability, ContentType, FirstListen, Music, PlayEvent, PlayLog, Playlist, PlaylistSong, TrackGenre, TrackMood,
+	User, UserFriendship,
+};
+use crate::schema::{
+	first_listens, liked_songs, music, play_events, play_log, playlist_songs, playlists, track_genres, track_moods,
+	user_friendship, users,
+};
+use crate::services::music::analysis_mood;
+
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use pwhash::bcrypt;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+// Rows per insert statement, keeps the statements below the sqlite bind limit
+const BATCH_SIZE: usize = 500;
+// Password of every seeded user
+pub const SEED_PASSWORD: &str = "password";
+
+const GENRES: [&str; 12] = [
+	"Pop",
+	"Rock",
+	"Hip-Hop",
+	"Jazz",
+	"Classical",
+	"Electronic",
+	"Folk",
+	"Metal",
+	"R&B",
+	"Country",
+	"Reggae",
+	"Blues",
+];
+const WORDS: [&str; 24] = [
+	"Midnight", "Golden", "River", "Echo", "Neon", "Silent", "Summer", "Broken", "Electric", "Velvet", "Paper", "Wild",
+	"Ocean", "Glass", "Fire", "Dream", "Shadow", "Highway", "Crystal", "Thunder", "Honey", "Moon", "Static", "Garden",
+];
+
+#[derive(Debug)]
+pub struct SeedOptions {
+	pub tracks: usize,
+	pub users: usize,
+	pub plays_per_user: usize,
+	pub playlists_per_user: usize,
+	pub friends_per_user: usize,
+	pub seed: u64, // same seed, same data
+}
+
+impl Default for SeedOptions {
+	fn default() -> Self {
+		SeedOptions {
+			tracks: 1000,
+			users: 50,
+			plays_per_user: 500,
+			playlists_per_user: 3,
+			friends_per_user: 5,
+			seed: 42,
+		}
+	}
+}
+
+impl SeedOptions {
+	// Parses `--tracks 5000` and `--tracks=5000` style flags
+	pub fn parse(args: &[String]) -> Result<SeedOptions, String> {
+		let mut options = SeedOptions::default();
+		let mut args = args.iter();
+		while let Some(arg) = args.next() {
+			let (flag, value) = match arg.split_once('=') {
+				Some((flag, value)) => (flag, value.to_string()),
+				None => (
+					arg.as_str(),
+					args.next().cloned().ok_or(format!("Missing value for {arg}"))?,
+				),
+			};
+			let number = value
+				.parse::<u64>()
+				.map_err(|_| format!("Invalid value for {flag}: {value}"))?;
+			match flag {
+				"--tracks" => options.tracks = number.max(1) as usize,
+				"--users" => options.users = number.max(1) as usize,
+				"--plays" => options.plays_per_user = number as usize,
+				"--playlists" => options.playlists_per_user = number as usize,
+				"--friends" => options.friends_per_user = number as usize,
+				"--seed" => options.seed = number,
+				_ => return Err(format!("Unknown flag {flag}")),
+			}
+		}
+		Ok(options)
+	}
+}
+
+#[derive(Debug)]
+pub struct SeedSummary {
+	pub users: usize,
+	pub tracks: usize,
+	pub plays: usize,
+	pub playlists: usize,
+	pub friendships: usize,
+	pub liked_songs: usize,
+}
+
+fn uuid(rng: &mut StdRng) -> String {
+	Uuid::from_u128(rng.random::<u128>()).to_string()
+}
+
+fn words(rng: &mut StdRng, count: usize) -> String {
+	(0..count)
+		.map(|_| WORDS[rng.random_range(0..WORDS.len())])
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+// Skewed towards the first indices so that a few tracks end up far more popular than the rest
+fn popular_index(rng: &mut StdRng, len: usize) -> usize {
+	((rng.random::<f64>().powf(2.5) * len as f64) as usize).min(len - 1)
+}
+
+// Inserts the rows a batch at a time
+macro_rules! insert_batched {
+	($table:expr, $rows:expr, $db_conn:expr) => {
+		for chunk in $rows.chunks(BATCH_SIZE) {
+			diesel::insert_into($table).values(chunk).execute($db_conn)?;
+		}
+	};
+}
+
+// Fills the database with a synthetic library, listeners and their history, all in one transaction
+pub fn seed(options: &SeedOptions, db_conn: &mut SqliteConnection) -> QueryResult<SeedSummary> {
+	let mut rng = StdRng::seed_from_u64(options.seed);
+	let now = Utc::now();
+	let pwd_hash = bcrypt::hash(SEED_PASSWORD).unwrap();
+
+	// Users
+	let seeded_users: Vec<User> = (0..options.users)
+		.map(|index| User {
+			user_id: uuid(&mut rng),
+			username: format!("seed_user_{index}"),
+			email: format!("seed_user_{index}@seed.lobic"),
+			pwd_hash: pwd_hash.clone(),
+			email_verified: true,
+			otp: "000000".to_string(),
+			otp_expires_at: now.to_string(),
+			otp_verified: None,
+			is_admin: false,
+		})
+		.collect();
+
+	// Tracks, grouped into artists and albums
+	let artist_count = (options.tracks / 10).max(1);
+	let artists: Vec<(String, String)> = (0..artist_count)
+		.map(|index| {
+			let artist = format!("{} {index}", words(&mut rng, 2));
+			let genre = GENRES[rng.random_range(0..GENRES.len())].to_string();
+			(artist, genre)
+		})
+		.collect();
+	let mut tracks: Vec<Music> = (0..options.tracks)
+		.map(|index| {
+			let (artist, genre) = &artists[popular_index(&mut rng, artists.len())];
+			let title_words = rng.random_range(1..4);
+			Music {
+				music_id: uuid(&mut rng),
+				artist: artist.clone(),
+				title: format!("{} {index}", words(&mut rng, title_words)),
+				album: format!("{} ({artist})", words(&mut rng, 2)),
+				genre: genre.clone(),
+				times_played: 0,
+				duration: rng.random_range(120..420),
+				availability: Availability::Available.as_str().to_string(),
+				availability_reason: None,
+				uploader_id: None,
+				content_type: ContentType::Music.as_str().to_string(),
+				series: None,
+				series_index: None,
+				release_year: Some(1970 + (index * 7 % 55) as i32), // spread over the decades since the 70s
+				bpm: Some(70.0 + (index * 13 % 110) as f64),
+				musical_key: Some(MusicalKey::parse(&format!("{}A", index % 12 + 1)).unwrap().name()),
+				explicit: index % 10 == 9,
+			}
+		})
+		.collect();
+
+	// Every third track also gets the genre following its primary one
+	let genres: Vec<TrackGenre> = tracks
+		.iter()
+		.enumerate()
+		.flat_map(|(index, track)| {
+			let primary = GENRES.iter().position(|genre| *genre == track.genre).unwrap_or(0);
+			let count = if index % 3 == 0 { 2 } else { 1 };
+			(0..count).map(move |position| TrackGenre {
+				music_id: track.music_id.clone(),
+				genre: GENRES[(primary + position) % GENRES.len()].to_string(),
+				position: position as i32,
+			})
+		})
+		.collect();
+
+	let moods: Vec<TrackMood> = tracks
+		.iter()
+		.map(|track| analysis_mood(&track.music_id, track.bpm.unwrap(), track.musical_key.as_deref()))
+		.collect();
+
+	// Play history over the last year
+	let mut events: Vec<PlayEvent> = Vec::new();
+	let mut logs: HashMap<(usize, usize), (String, i32)> = HashMap::new();
+	for (user_index, user) in seeded_users.iter().enumerate() {
+		for _ in 0..options.plays_per_user {
+			let track_index = popular_index(&mut rng, tracks.len());
+			let played = now - Duration::seconds(rng.random_range(0..365 * 24 * 60 * 60));
+			let played = played.to_rfc3339();
+			let duration = tracks[track_index].duration;
+
+			tracks[track_index].times_played += 1;
+			let log = logs.entry((user_index, track_index)).or_insert((played.clone(), 0));
+			log.0 = log.0.clone().max(played.clone());
+			log.1 += 1;
+
+			events.push(PlayEvent {
+				event_id: uuid(&mut rng),
+				user_id: user.user_id.clone(),
+				music_id: tracks[track_index].music_id.clone(),
+				played_date_time: played,
+				listened_secs: rng.random_range(duration / 4..=duration),
+				device: None,
+			});
+		}
+	}
+	events.sort_by(|a, b| a.played_date_time.cmp(&b.played_date_time));
+
+	let play_logs: Vec<PlayLog> = logs
+		.iter()
+		.map(|((user_index, track_index), (played, count))| PlayLog {
+			user_id: seeded_users[*user_index].user_id.clone(),
+			music_id: tracks[*track_index].music_id.clone(),
+			music_played_date_time: played.clone(),
+			user_times_played: *count,
+		})
+		.collect();
+
+	// Events are sorted, so the first one seen is the first listen
+	let mut seen: HashSet<(String, String, String)> = HashSet::new();
+	let mut firsts: Vec<FirstListen> = Vec::new();
+	let artist_of: HashMap<&String, &String> = tracks.iter().map(|track| (&track.music_id, &track.artist)).collect();
+	for event in &events {
+		for (target_type, target_id) in [("track", &event.music_id), ("artist", artist_of[&event.music_id])] {
+			if seen.insert((event.user_id.clone(), target_type.to_string(), target_id.clone())) {
+				firsts.push(FirstListen {
+					user_id: event.user_id.clone(),
+					target_type: target_type.to_string(),
+					target_id: target_id.clone(),
+					first_played_date_time: event.played_date_time.clone(),
+					notified_year: None,
+				});
+			}
+		}
+	}
+
+	// Some of the played tracks get liked
+	let liked: Vec<(String, String, String)> = play_logs
+		.iter()
+		.filter(|log| log.user_times_played > 1 || rng.random_bool(0.05))
+		.map(|log| {
+			(
+				log.user_id.clone(),
+				log.music_id.clone(),
+				log.music_played_date_time.clone(),
+			)
+		})
+		.collect();
+
+	// Friendships go both ways, like the ones made through add_friend
+	let mut friend_pairs: HashSet<(usize, usize)> = HashSet::new();
+	if seeded_users.len() > 1 {
+		for user_index in 0..seeded_users.len() {
+			for _ in 0..options.friends_per_user {
+				let friend_index = rng.random_range(0..seeded_users.len());
+				if friend_index != user_index {
+					friend_pairs.insert((user_index.min(friend_index), user_index.max(friend_index)));
+				}
+			}
+		}
+	}
+	let friendships: Vec<UserFriendship> = friend_pairs
+		.iter()
+		.flat_map(|(a, b)| {
+			[(a, b), (b, a)].map(|(user, friend)| UserFriendship {
+				user_id: seeded_users[*user].user_id.clone(),
+				friend_id: seeded_users[*friend].user_id.clone(),
+			})
+		})
+		.collect();
+
+	// Playlists
+	let mut seeded_playlists: Vec<Playlist> = Vec::new();
+	let mut songs: Vec<PlaylistSong> = Vec::new();
+	for user in &seeded_users {
+		for index in 0..options.playlists_per_user {
+			let playlist_id = uuid(&mut rng);
+			let created = (now - Duration::days(rng.random_range(0..365))).to_rfc3339();
+			let mut added: HashSet<usize> = HashSet::new();
+			for _ in 0..rng.random_range(10..50) {
+				let track_index = popular_index(&mut rng, tracks.len());
+				if added.insert(track_index) {
+					songs.push(PlaylistSong {
+						playlist_id: playlist_id.clone(),
+						music_id: tracks[track_index].music_id.clone(),
+						song_adder_id: user.user_id.clone(),
+						song_added_date_time: created.clone(),
+						position: added.len() as i32 - 1,
+					});
+				}
+			}
+			seeded_playlists.push(Playlist {
+				playlist_id,
+				playlist_name: format!("{} {index}", words(&mut rng, 2)),
+				user_id: user.user_id.clone(),
+				creation_date_time: created.clone(),
+				last_updated_date_time: created,
+				is_playlist_combined: false,
+				availability: Availability::Available.as_str().to_string(),
+				availability_reason: None,
+				version: 1,
+			});
+		}
+	}
+
+	db_conn.transaction(|conn| {
+		insert_batched!(users::table, seeded_users, conn);
+		insert_batched!(music::table, tracks, conn);
+		insert_batched!(track_genres::table, genres, conn);
+		insert_batched!(track_moods::table, moods, conn);
+		insert_batched!(play_events::table, events, conn);
+		rollups::roll_up(&events, conn)?;
+		insert_batched!(play_log::table, play_logs, conn);
+		insert_batched!(first_listens::table, firsts, conn);
+		insert_batched!(user_friendship::table, friendships, conn);
+		insert_batched!(playlists::table, seeded_playlists, conn);
+		insert_batched!(playlist_songs::table, songs, conn);
+		for chunk in liked.chunks(BATCH_SIZE) {
+			let rows: Vec<_> = chunk
+				.iter()
+				.map(|(user_id, music_id, added)| {
+					(
+						liked_songs::user_id.eq(user_id),
+						liked_songs::music_id.eq(music_id),
+						liked_songs::song_added_date_time.eq(added),
+					)
+				})
+				.collect();
+			diesel::insert_into(liked_songs::table).values(&rows).execute(conn)?;
+		}
+
+		Ok(SeedSummary {
+			users: seeded_users.len(),
+			tracks: tracks.len(),
+			plays: events.len(),
+			playlists: seeded_playlists.len(),
+			friendships: friend_pairs.len(),
+			liked_songs: liked.len(),
+		})
+	})
+}
+
+// `Lobic seed [--tracks N] [--users N] [--plays N] [--playlists N] [--friends N] [--seed N]`
+pub fn run(args: &[String], db_conn: &mut SqliteConnection) -> Result<(), String> {
+	let options = SeedOptions::parse(args)?;
+
+	// Seeding the same database twice would only clash on the ids
+	let already_seeded = users::table
+		.filter(users::email.like("%@seed.lobic"))
+		.count()
+		.get_result::<i64>(db_conn)
+		.map_err(|err| err.to_string())?;
+	if already_seeded > 0 {
+		return Err("The database was already seeded, use a fresh one".to_string());
+	}
+
+	println!("Seeding with {options:?}");
+	let summary = seed(&options, db_conn).map_err(|err| format!("Failed to seed the database: {err}"))?;
+	println!(
+		"Seeded {} users, {} tracks, {} plays, {} playlists, {} friendships and {} liked songs",
+		summary.users, summary.tracks, summary.plays, summary.playlists, summary.friendships, summary.liked_songs
+	);
+	println!("Every seeded user logs in with the password \"{SEED_PASSWORD}\"");
+	Ok(())
+}
@@ -1,6 +1,13 @@
 use lettre::transport::smtp::authentication::Credentials;
 use lettre::{Message, SmtpTransport, Transport};
 
+// Mails that nobody asked for right then, like the weekly summary, are skipped without a server
+pub fn is_configured() -> bool {
+	["SMTP_HOST", "SMTP_USERNAME", "SMTP_PASSWORD"]
+		.iter()
+		.all(|var| std::env::var(var).is_ok_and(|value| !value.is_empty()))
+}
+
 pub fn send_mail(email: Message) {
 	let smtp_host = std::env::var("SMTP_HOST").expect("'SMTP_HOST' must be set in .env file");
 	let smtp_username = std::env::var("SMTP_USERNAME").expect("'SMTP_USERNAME' must be set in .env file");
@@ -1,2 +1,2 @@
 pub mod mailer;
-pub mod otp_mail;
+pub mod templates;
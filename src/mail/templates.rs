@@ -0,0 +1,194 @@
+use crate::i18n::Locale;
+
+use lettre::message::MultiPart;
+use lettre::Message;
+
+// Templates are compiled into the binary. `{{name}}` is replaced by a variable and `{{@key}}` by the
+// catalog message of the locale, which can use the same variables.
+const LAYOUT: &str = include_str!("templates/layout.html");
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Template {
+	Verification,
+	PasswordReset,
+	Digest,
+	LobbyInvite,
+}
+
+pub struct RenderedMail {
+	pub subject: String,
+	pub html: String,
+	pub text: String,
+}
+
+impl Template {
+	pub const ALL: [Template; 4] = [
+		Template::Verification,
+		Template::PasswordReset,
+		Template::Digest,
+		Template::LobbyInvite,
+	];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Template::Verification => "verification",
+			Template::PasswordReset => "password_reset",
+			Template::Digest => "digest",
+			Template::LobbyInvite => "lobby_invite",
+		}
+	}
+
+	pub fn parse(name: &str) -> Option<Template> {
+		Template::ALL.into_iter().find(|template| template.name() == name)
+	}
+
+	fn sources(&self) -> (&'static str, &'static str) {
+		match self {
+			Template::Verification => (
+				include_str!("templates/verification.html"),
+				include_str!("templates/verification.txt"),
+			),
+			Template::PasswordReset => (
+				include_str!("templates/password_reset.html"),
+				include_str!("templates/password_reset.txt"),
+			),
+			Template::Digest => (
+				include_str!("templates/digest.html"),
+				include_str!("templates/digest.txt"),
+			),
+			Template::LobbyInvite => (
+				include_str!("templates/lobby_invite.html"),
+				include_str!("templates/lobby_invite.txt"),
+			),
+		}
+	}
+
+	// Made up variables for previewing the template
+	pub fn sample_vars(&self) -> Vec<(&'static str, &'static str)> {
+		let mut vars = vec![("username", "lobic_listener")];
+		match self {
+			Template::Verification => vars.push(("otp", "123456")),
+			Template::PasswordReset => vars.push(("code", "654321")),
+			Template::Digest => vars.extend([
+				("plays", "128"),
+				("minutes", "412"),
+				("top_tracks", "Resham Firiri, Syndicate, Parelima"),
+				("top_artist", "Sabin Rai"),
+				("new_artists", "4"),
+				("new_tracks", "17"),
+				("unsubscribe_url", "https://lobic.example/mail/unsubscribe?token=sample"),
+			]),
+			Template::LobbyInvite => vars.extend([
+				("inviter", "dj_friend"),
+				("lobby_name", "Friday Night"),
+				("lobby_url", "https://lobic.example/lobby/sample"),
+			]),
+		}
+		vars
+	}
+
+	pub fn render(&self, locale: Locale, vars: &[(&str, &str)]) -> RenderedMail {
+		let (html, text) = self.sources();
+		let subject = locale.tf(&format!("mail.{}.subject", self.name()), vars);
+
+		let mut layout_vars = vars.to_vec();
+		layout_vars.extend([("lang", locale.as_str()), ("subject", subject.as_str())]);
+		let content = render_str(html, locale, vars, true);
+		let html = render_str(LAYOUT, locale, &layout_vars, true).replace("{{content}}", &content);
+
+		RenderedMail {
+			text: render_str(text, locale, vars, false),
+			html,
+			subject,
+		}
+	}
+
+	pub fn message(&self, to: &str, locale: Locale, vars: &[(&str, &str)]) -> Message {
+		let smtp_username = std::env::var("SMTP_USERNAME").expect("'SMTP_USERNAME' must be set in .env file");
+		let rendered = self.render(locale, vars);
+
+		Message::builder()
+			.from(smtp_username.parse().unwrap())
+			.to(to.parse().unwrap())
+			.subject(rendered.subject)
+			.multipart(MultiPart::alternative_plain_html(rendered.text, rendered.html))
+			.unwrap()
+	}
+}
+
+// Unknown variables are left as they are
+fn render_str(source: &str, locale: Locale, vars: &[(&str, &str)], escape: bool) -> String {
+	let mut rendered = String::with_capacity(source.len());
+	let mut rest = source;
+
+	while let Some(start) = rest.find("{{") {
+		rendered.push_str(&rest[..start]);
+		let Some(end) = rest[start..].find("}}") else {
+			break;
+		};
+
+		let tag = &rest[start..start + end + 2];
+		let name = tag[2..tag.len() - 2].trim();
+		let value = match name.strip_prefix('@') {
+			Some(key) => Some(locale.tf(key, vars)),
+			None => vars
+				.iter()
+				.find(|(var, _)| *var == name)
+				.map(|(_, value)| value.to_string()),
+		};
+
+		match value {
+			Some(value) if escape => rendered.push_str(&escape_html(&value)),
+			Some(value) => rendered.push_str(&value),
+			None => rendered.push_str(tag),
+		}
+		rest = &rest[start + end + 2..];
+	}
+
+	rendered.push_str(rest);
+	rendered
+}
+
+pub fn escape_html(value: &str) -> String {
+	value
+		.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+		.replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn every_template_renders_without_leftover_tags() {
+		for template in Template::ALL {
+			for locale in [Locale::En, Locale::Ne] {
+				let rendered = template.render(locale, &template.sample_vars());
+				assert!(
+					!rendered.html.contains("{{"),
+					"{} html: {}",
+					template.name(),
+					rendered.html
+				);
+				assert!(
+					!rendered.text.contains("{{"),
+					"{} text: {}",
+					template.name(),
+					rendered.text
+				);
+				assert!(!rendered.subject.contains('{'), "{} subject", template.name());
+			}
+		}
+	}
+
+	#[test]
+	fn variables_are_escaped_in_html_only() {
+		let rendered = Template::Verification.render(Locale::En, &[("username", "<b>me</b>"), ("otp", "1")]);
+		assert!(rendered.html.contains("Hi &lt;b&gt;me&lt;/b&gt;,"));
+		assert!(rendered.text.contains("Hi <b>me</b>,"));
+		assert_eq!(rendered.subject, "OTP Verification");
+	}
+}
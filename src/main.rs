@@ -1,15 +1,23 @@
 use std::fs;
 use std::path::Path;
 
+mod cli;
 mod config;
 mod core;
+mod i18n;
 mod lobic_db;
 mod mail;
 mod routes;
 mod schema;
+mod services;
+#[cfg(test)]
+mod test_support;
 mod utils;
 
-use config::{COVER_IMG_STORAGE, server_ip, MUSIC_STORAGE, PLAYLIST_COVER_IMG_STORAGE, PORT, USER_PFP_STORAGE};
+use config::{
+	server_ip, ANIMATED_COVER_STORAGE, ARTIST_IMG_STORAGE, COVER_IMG_STORAGE, MUSIC_STORAGE, PLAYLIST_COVER_IMG_STORAGE, PORT,
+	TRANSCODE_CACHE_STORAGE, USER_PFP_STORAGE,
+};
 use core::{app_state::AppState, migrations::run_migrations};
 use dotenv::dotenv;
 
@@ -20,12 +28,33 @@ async fn main() {
 	dotenv().ok();
 	tracing_subscriber::fmt().pretty().init();
 
+	core::query_log::install();
+
 	let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set in .env file");
 	run_migrations(&db_url);
 
+	// `Lobic <command> ...` manages the instance instead of starting the server
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	if !args.is_empty() {
+		if let Err(err) = cli::run(&args) {
+			eprintln!("{err}");
+			std::process::exit(1);
+		}
+		return;
+	}
+
 	let app_state = AppState::new();
+	core::instance::start_directory_heartbeat(app_state.db_pool.clone());
+	core::realtime::start(app_state.user_pool.clone(), app_state.lobby_pool.clone());
+	core::scheduler::start(app_state.clone());
+	core::mpd::start(app_state.clone());
+	let db_pool = app_state.db_pool.clone();
+	tokio::task::spawn_blocking(move || core::doctor::print_problems(&core::doctor::diagnose(&db_pool)));
+	core::achievements::start(&app_state.event_bus, app_state.db_pool.clone(), app_state.user_pool.clone());
+	core::goals::start(&app_state.event_bus, app_state.db_pool.clone(), app_state.user_pool.clone());
 
 	let app = core::routes::configure_routes(app_state)
+		.layer(axum::middleware::from_fn(core::query_log::trace_queries))
 		.layer(axum::middleware::from_fn(core::server::logger))
 		.layer(core::server::configure_cors());
 
@@ -44,6 +73,9 @@ fn create_storage_directories() -> std::io::Result<()> {
 		MUSIC_STORAGE,
 		USER_PFP_STORAGE,
 		PLAYLIST_COVER_IMG_STORAGE,
+		ANIMATED_COVER_STORAGE,
+		ARTIST_IMG_STORAGE,
+		TRANSCODE_CACHE_STORAGE,
 	];
 
 	for dir in subdirectories {
@@ -0,0 +1,117 @@
+use crate::core::achievements::ACHIEVEMENTS;
+use crate::core::app_state::AppState;
+use crate::lobic_db::db::{can_view_stats, user_exists};
+use crate::lobic_db::models::UserAchievement;
+use crate::schema::user_achievements;
+use crate::utils::auth::require_user;
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::{Path, Query, State},
+	http::status::StatusCode,
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+pub struct AchievementsQuery {
+	pub status: Option<String>, // earned | locked, both when not given
+}
+
+#[derive(Debug, Serialize)]
+struct Badge {
+	id: &'static str,
+	name: &'static str,
+	description: &'static str,
+	target: i32,
+	progress: i32,
+	earned: bool,
+	unlocked_date_time: Option<String>,
+}
+
+// :get_achievements
+// Every badge along with the progress of the user towards it
+pub async fn get_achievements(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(user_id): Path<String>,
+	Query(params): Query<AchievementsQuery>,
+) -> Response<String> {
+	let viewer_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	if !user_exists(&user_id, &app_state.db_pool) {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid user_id: {}", user_id))
+			.unwrap();
+	}
+
+	// The badges are made out of the listening stats
+	if !can_view_stats(&viewer_id, &user_id, &app_state.db_pool) {
+		return Response::builder()
+			.status(StatusCode::FORBIDDEN)
+			.body(format!("User {} keeps their stats private", user_id))
+			.unwrap();
+	}
+
+	let earned_filter = match params.status.as_deref() {
+		None => None,
+		Some("earned") => Some(true),
+		Some("locked") => Some(false),
+		Some(status) => {
+			return Response::builder()
+				.status(StatusCode::BAD_REQUEST)
+				.body(format!("Invalid status: {}", status))
+				.unwrap();
+		}
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let progress = match user_achievements::table
+		.filter(user_achievements::user_id.eq(&user_id))
+		.load::<UserAchievement>(&mut db_conn)
+	{
+		Ok(progress) => progress,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	let badges: Vec<Badge> = ACHIEVEMENTS
+		.iter()
+		.map(|achievement| {
+			let entry = progress.iter().find(|entry| entry.achievement_id == achievement.id);
+			let unlocked_date_time = entry.and_then(|entry| entry.unlocked_date_time.clone());
+			Badge {
+				id: achievement.id,
+				name: achievement.name,
+				description: achievement.description,
+				target: achievement.target,
+				progress: entry.map(|entry| entry.progress).unwrap_or(0),
+				earned: unlocked_date_time.is_some(),
+				unlocked_date_time,
+			}
+		})
+		.filter(|badge| earned_filter.is_none_or(|earned| badge.earned == earned))
+		.collect();
+
+	ListResponse::all(badges).into_response()
+}
@@ -0,0 +1,226 @@
+use crate::core::app_state::AppState;
+use crate::lobic_db::models::{AnalyticsDay, Music, MusicResponse, RetentionEntry, TopContentEntry};
+use crate::schema::{analytics_daily, analytics_retention, analytics_top_content, music};
+use crate::utils::auth::require_admin;
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::{Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+// :get_daily_analytics
+// Active users, plays and listening minutes per day, newest first
+#[derive(Debug, Deserialize)]
+pub struct DailyAnalyticsQuery {
+	pub days: Option<i64>, // defaults to 30
+}
+
+pub async fn get_daily_analytics(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(params): Query<DailyAnalyticsQuery>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	match analytics_daily::table
+		.order(analytics_daily::day.desc())
+		.limit(params.days.unwrap_or(30).max(1))
+		.load::<AnalyticsDay>(&mut db_conn)
+	{
+		Ok(days) => ListResponse::all(days).into_response(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap(),
+	}
+}
+
+// :get_retention
+// Weekly cohorts along with the share of users still listening in each following week
+#[derive(Debug, Serialize)]
+struct RetentionWeek {
+	week_offset: i32,
+	retained_users: i32,
+	percentage: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct Cohort {
+	cohort_week: String,
+	cohort_size: i32,
+	weeks: Vec<RetentionWeek>,
+}
+
+pub async fn get_retention(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let entries = match analytics_retention::table
+		.order((
+			analytics_retention::cohort_week.desc(),
+			analytics_retention::week_offset.asc(),
+		))
+		.load::<RetentionEntry>(&mut db_conn)
+	{
+		Ok(entries) => entries,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	// The entries come ordered by cohort, grouping the consecutive ones
+	let mut cohorts: Vec<Cohort> = Vec::new();
+	for entry in entries {
+		if cohorts
+			.last()
+			.is_none_or(|cohort| cohort.cohort_week != entry.cohort_week)
+		{
+			cohorts.push(Cohort {
+				cohort_week: entry.cohort_week.clone(),
+				cohort_size: entry.cohort_size,
+				weeks: Vec::new(),
+			});
+		}
+		let percentage = match entry.cohort_size {
+			0 => 0.0,
+			size => (entry.retained_users as f64 * 1000.0 / size as f64).round() / 10.0,
+		};
+		cohorts.last_mut().unwrap().weeks.push(RetentionWeek {
+			week_offset: entry.week_offset,
+			retained_users: entry.retained_users,
+			percentage,
+		});
+	}
+
+	ListResponse::all(cohorts).into_response()
+}
+
+// :get_top_content
+// Most played tracks and artists of the last month
+#[derive(Debug, Serialize)]
+struct TopTrack {
+	rank: i32,
+	plays: i32,
+	music: MusicResponse,
+}
+
+#[derive(Debug, Serialize)]
+struct TopArtist {
+	rank: i32,
+	plays: i32,
+	artist: String,
+}
+
+pub async fn get_top_content(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let entries = match analytics_top_content::table
+		.order((analytics_top_content::kind.asc(), analytics_top_content::rank.asc()))
+		.load::<TopContentEntry>(&mut db_conn)
+	{
+		Ok(entries) => entries,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	let music_ids: Vec<&String> = entries
+		.iter()
+		.filter(|entry| entry.kind == "track")
+		.map(|entry| &entry.target_id)
+		.collect();
+	let mut tracks = match music::table
+		.filter(music::music_id.eq_any(music_ids))
+		.load::<Music>(&mut db_conn)
+	{
+		Ok(tracks) => tracks,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	let mut top_tracks: Vec<TopTrack> = Vec::new();
+	let mut top_artists: Vec<TopArtist> = Vec::new();
+	for entry in entries {
+		match entry.kind.as_str() {
+			"track" => {
+				// Tracks deleted since the last refresh are skipped
+				if let Some(index) = tracks.iter().position(|track| track.music_id == entry.target_id) {
+					top_tracks.push(TopTrack {
+						rank: entry.rank,
+						plays: entry.plays,
+						music: Music::create_music_response(tracks.swap_remove(index)),
+					});
+				}
+			}
+			_ => top_artists.push(TopArtist {
+				rank: entry.rank,
+				plays: entry.plays,
+				artist: entry.target_id,
+			}),
+		}
+	}
+
+	let response = json!({
+		"tracks": top_tracks,
+		"artists": top_artists,
+	});
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(response.to_string())
+		.unwrap()
+}
@@ -0,0 +1,219 @@
+use crate::core::app_state::AppState;
+use crate::services::{ArtworkService, ArtworkTarget};
+use crate::utils::auth::require_user;
+
+use axum::{
+	body::{Body, Bytes},
+	extract::{Path, State},
+	http::{header, status::StatusCode},
+	response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde_json::json;
+use tokio::fs::File;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+// Albums and playlists are both known by uuids, anything else never reaches the storage paths
+#[allow(clippy::result_large_err)]
+fn parse_target(target: &str, target_id: &str) -> Result<ArtworkTarget, Response<String>> {
+	let (status, msg) = match ArtworkTarget::parse(target) {
+		Some(target) if Uuid::parse_str(target_id).is_ok() => return Ok(target),
+		Some(_) => (StatusCode::NOT_FOUND, format!("No {target}: {target_id}")),
+		None => (StatusCode::BAD_REQUEST, format!("Invalid cover target: {target}")),
+	};
+	Err(Response::builder().status(status).body(msg).unwrap())
+}
+
+fn cover_json(target: ArtworkTarget, target_id: &str, duration_secs: f64) -> String {
+	let base = format!("/animated_cover/{}/{target_id}", target.as_str());
+	json!({
+		"target_type": target.as_str(),
+		"target_id": target_id,
+		"duration_secs": duration_secs,
+		"video_url": format!("{base}/video"),
+		"poster_url": format!("{base}/poster"),
+	})
+	.to_string()
+}
+
+// :upload_animated_cover
+// The body is the raw video or gif
+pub async fn upload_animated_cover(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path((target, target_id)): Path<(String, String)>,
+	body: Bytes,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	let target = match parse_target(&target, &target_id) {
+		Ok(target) => target,
+		Err(response) => return response,
+	};
+
+	// ffmpeg takes a while, keeping it off the runtime
+	let service = ArtworkService::new(&app_state.db_pool);
+	let id = target_id.clone();
+	let result = tokio::task::spawn_blocking(move || service.set_animated_cover(target, &id, &user_id, &body)).await;
+	match result {
+		Ok(Ok(cover)) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(cover_json(target, &target_id, cover.duration_secs))
+			.unwrap(),
+		Ok(Err(err)) => err.into_response(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to transcode: {err}"))
+			.unwrap(),
+	}
+}
+
+// :get_animated_cover
+pub async fn get_animated_cover(
+	State(app_state): State<AppState>,
+	Path((target, target_id)): Path<(String, String)>,
+) -> Response<String> {
+	let target = match parse_target(&target, &target_id) {
+		Ok(target) => target,
+		Err(response) => return response,
+	};
+
+	match ArtworkService::new(&app_state.db_pool).animated_cover(target, &target_id) {
+		Ok(cover) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(cover_json(target, &target_id, cover.duration_secs))
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :get_animated_cover_video
+pub async fn get_animated_cover_video(
+	State(app_state): State<AppState>,
+	Path((target, target_id)): Path<(String, String)>,
+) -> Response {
+	let target = match parse_target(&target, &target_id) {
+		Ok(target) => target,
+		Err(response) => return response.into_response(),
+	};
+
+	let (video, _) = ArtworkService::new(&app_state.db_pool).animated_cover_files(target, &target_id);
+	match File::open(&video).await {
+		Ok(file) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "video/mp4")
+			.header(header::CACHE_CONTROL, "public, max-age=3600")
+			.body(Body::from_stream(ReaderStream::new(file)))
+			.unwrap(),
+		Err(_) => (StatusCode::NOT_FOUND, "No animated cover").into_response(),
+	}
+}
+
+// :get_animated_cover_poster
+// The first frame of the animated cover, or the static cover when there is none
+pub async fn get_animated_cover_poster(
+	State(app_state): State<AppState>,
+	Path((target, target_id)): Path<(String, String)>,
+) -> Response {
+	let target = match parse_target(&target, &target_id) {
+		Ok(target) => target,
+		Err(response) => return response.into_response(),
+	};
+
+	let (_, poster) = ArtworkService::new(&app_state.db_pool).animated_cover_files(target, &target_id);
+	let bytes = match tokio::fs::read(&poster).await {
+		Ok(bytes) => bytes,
+		Err(_) => match tokio::fs::read(target.static_cover(&target_id)).await {
+			Ok(bytes) => bytes,
+			Err(_) => return (StatusCode::NOT_FOUND, "No cover").into_response(),
+		},
+	};
+
+	// Static covers are saved as .png whatever they really are
+	let content_type = if bytes.starts_with(&[0xff, 0xd8]) {
+		"image/jpeg"
+	} else {
+		"image/png"
+	};
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, content_type)
+		.header(header::CACHE_CONTROL, "public, max-age=3600")
+		.body(Body::from(bytes))
+		.unwrap()
+}
+
+// :remove_animated_cover
+pub async fn remove_animated_cover(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path((target, target_id)): Path<(String, String)>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	let target = match parse_target(&target, &target_id) {
+		Ok(target) => target,
+		Err(response) => return response,
+	};
+
+	match ArtworkService::new(&app_state.db_pool).remove_animated_cover(target, &target_id, &user_id) {
+		Ok(()) => Response::builder()
+			.status(StatusCode::OK)
+			.body("Animated cover removed".to_string())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::playlists;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+
+	#[tokio::test]
+	async fn only_owners_can_upload_valid_covers() {
+		let test_app = TestApp::seeded();
+		let owner_id = test_app.user_id("seed_user_0");
+		let playlist_id = playlists::table
+			.filter(playlists::user_id.eq(&owner_id))
+			.select(playlists::playlist_id)
+			.first::<String>(&mut test_app.db_conn())
+			.unwrap();
+		let upload = format!("/animated_cover/playlist/{playlist_id}/upload");
+
+		let cookies = test_app.login("seed_user_1").await;
+		let response = test_app.request(Method::POST, &upload, None, &cookies).await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+		// Not a video, rejected before ffmpeg is needed
+		let cookies = test_app.login("seed_user_0").await;
+		let response = test_app.request(Method::POST, &upload, None, &cookies).await;
+		assert_eq!(response.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+		let response = test_app.get(&format!("/animated_cover/playlist/{playlist_id}")).await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+	}
+
+	#[tokio::test]
+	async fn targets_are_validated() {
+		let test_app = TestApp::new();
+
+		let response = test_app
+			.get("/animated_cover/artist/3f1f0b5e-4a57-4c39-9a43-1a2b3c4d5e6f")
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+		let response = test_app.get("/animated_cover/album/..%2Fsecret/poster").await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+	}
+}
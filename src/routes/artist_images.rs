@@ -0,0 +1,128 @@
+use crate::config::MAX_ARTIST_IMAGE_FETCHES;
+use crate::core::app_state::AppState;
+use crate::core::artist_images;
+use crate::core::quotas::{self, Feature};
+use crate::lobic_db::models::ArtistImage;
+use crate::schema::artist_images as artist_images_table;
+use crate::utils::auth::require_admin;
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeSet;
+
+// :get_artist_images
+// The pictures the covers fall back to, and the artists still without one
+pub async fn get_artist_images(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+	let images = match artist_images_table::table
+		.order(artist_images_table::artist.asc())
+		.load::<ArtistImage>(&mut db_conn)
+	{
+		Ok(images) => images,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+	drop(db_conn);
+	let missing = match artist_images::missing(&app_state.db_pool) {
+		Ok(missing) => missing,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(err)
+				.unwrap();
+		}
+	};
+
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(json!({ "images": images, "missing": missing }).to_string())
+		.unwrap()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FetchArtistImagesPayload {
+	pub artists: Option<Vec<String>>, // the ones without a picture when left out
+}
+
+// :fetch_artist_images
+// Looks the artists up with the provider, the ones given are fetched again even if they have a picture. Each
+// lookup counts against the metadata_lookup quota of the admin, the ones past it are left for another day.
+pub async fn fetch_artist_images(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<FetchArtistImagesPayload>,
+) -> Response<String> {
+	let admin_id = match require_admin(&jar, &app_state.db_pool) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let wanted = match &payload.artists {
+		Some(artists) => artists
+			.iter()
+			.map(|artist| artist_images::main_artist(artist))
+			.filter(|artist| !artist.is_empty())
+			.collect::<BTreeSet<String>>()
+			.len(),
+		None => match artist_images::missing(&app_state.db_pool) {
+			Ok(missing) => missing.len(),
+			Err(err) => {
+				return Response::builder()
+					.status(StatusCode::INTERNAL_SERVER_ERROR)
+					.body(err)
+					.unwrap();
+			}
+		},
+	};
+	let wanted = wanted.min(MAX_ARTIST_IMAGE_FETCHES) as i64;
+	let granted = match app_state.db_pool.get() {
+		Ok(mut db_conn) => quotas::take_up_to(&admin_id, Feature::MetadataLookup, wanted, &mut db_conn),
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+	let granted = match granted {
+		Ok((_, granted)) => granted as usize,
+		Err(err) => return err.into_response(),
+	};
+
+	match artist_images::fetch_many(&app_state.db_pool, payload.artists, granted).await {
+		Ok(report) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&report).unwrap())
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(err)
+			.unwrap(),
+	}
+}
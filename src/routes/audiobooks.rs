@@ -0,0 +1,270 @@
+use crate::core::app_state::AppState;
+use crate::lobic_db::db::record_library_change;
+use crate::lobic_db::models::{ContentType, LibraryChange};
+use crate::schema::music;
+use crate::services::audiobook::AudiobookFilter;
+use crate::services::AudiobookService;
+use crate::utils::auth::{require_admin, require_user, session_user_id};
+use crate::utils::list::ListResponse;
+use crate::utils::response::json_response;
+
+use axum::{
+	extract::{Query, State},
+	http::status::StatusCode,
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use diesel::prelude::*;
+use serde::Deserialize;
+
+// :get_audiobooks
+// Books along with the progress of the logged in user, narrowed down by ?author= and ?series=
+pub async fn get_audiobooks(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(filter): Query<AudiobookFilter>,
+) -> Response<String> {
+	let user_id = session_user_id(&jar);
+	match AudiobookService::new(&app_state.db_pool).books(&filter, user_id.as_deref()) {
+		Ok(books) => books.into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :get_audiobook_authors
+pub async fn get_audiobook_authors(State(app_state): State<AppState>) -> Response<String> {
+	match AudiobookService::new(&app_state.db_pool).authors() {
+		Ok(authors) => ListResponse::all(authors).into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SeriesQuery {
+	pub author: Option<String>,
+}
+
+// :get_audiobook_series
+pub async fn get_audiobook_series(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(params): Query<SeriesQuery>,
+) -> Response<String> {
+	let user_id = session_user_id(&jar);
+	match AudiobookService::new(&app_state.db_pool).series(params.author, user_id.as_deref()) {
+		Ok(series) => ListResponse::all(series).into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :get_audiobooks_in_progress
+// The books to continue listening to
+pub async fn get_audiobooks_in_progress(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	match AudiobookService::new(&app_state.db_pool).in_progress(&user_id) {
+		Ok(books) => ListResponse::all(books).into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProgressPayload {
+	pub music_id: String,
+	pub position: f64,          // seconds into the book
+	pub finished: Option<bool>, // worked out from the position when left out
+}
+
+// :set_audiobook_progress
+pub async fn set_audiobook_progress(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<ProgressPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let service = AudiobookService::new(&app_state.db_pool);
+	match service.set_progress(&user_id, &payload.music_id, payload.position, payload.finished) {
+		Ok(progress) => json_response(StatusCode::OK, &progress),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContentTypePayload {
+	pub music_id: String,
+	pub content_type: ContentType,
+	pub series: Option<String>,
+	pub series_index: Option<i32>,
+}
+
+// :set_content_type
+// Fixes up files whose tags didn't tell what they are
+pub async fn set_content_type(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<ContentTypePayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+
+	let updated = db_conn.transaction(|conn| {
+		let updated = diesel::update(music::table.find(&payload.music_id))
+			.set((
+				music::content_type.eq(payload.content_type.as_str()),
+				music::series.eq(&payload.series),
+				music::series_index.eq(payload.series_index),
+			))
+			.execute(conn)?;
+		if updated > 0 {
+			record_library_change(&payload.music_id, LibraryChange::Retagged, conn)?;
+		}
+		Ok::<_, diesel::result::Error>(updated)
+	});
+	match updated {
+		Ok(0) => Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("Invalid music id: {}", payload.music_id))
+			.unwrap(),
+		Ok(_) => Response::builder()
+			.status(StatusCode::OK)
+			.body(format!(
+				"Music {} is now {}",
+				payload.music_id,
+				payload.content_type.as_str()
+			))
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::lobic_db::models::{Availability, ContentType, Music};
+	use crate::schema::music;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	const FIRST_BOOK: &str = "0b0b0b0b-0000-4000-8000-000000000001";
+	const SECOND_BOOK: &str = "0b0b0b0b-0000-4000-8000-000000000002";
+
+	fn insert_series(test_app: &TestApp) {
+		let books: Vec<Music> = [(SECOND_BOOK, 2), (FIRST_BOOK, 1)]
+			.into_iter()
+			.map(|(music_id, series_index)| Music {
+				music_id: music_id.to_string(),
+				artist: "Author".to_string(),
+				title: format!("Book {series_index}"),
+				album: format!("Book {series_index}"),
+				genre: "Audiobook".to_string(),
+				times_played: 1000, // would top the trending list
+				duration: 600,
+				availability: Availability::Available.as_str().to_string(),
+				availability_reason: None,
+				uploader_id: None,
+				content_type: ContentType::Audiobook.as_str().to_string(),
+				series: Some("Saga".to_string()),
+				series_index: Some(series_index),
+				release_year: None,
+				bpm: None,
+				musical_key: None,
+				explicit: false,
+			})
+			.collect();
+		diesel::insert_into(music::table)
+			.values(&books)
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+	}
+
+	#[tokio::test]
+	async fn audiobooks_are_kept_out_of_music_lists() {
+		let test_app = TestApp::seeded();
+		insert_series(&test_app);
+
+		let body = test_app.get("/music/get_music?randomizer=true").await.json();
+		assert_eq!(body["total_count"], 40);
+		let body = test_app.get("/music/get_trending").await.json();
+		assert_ne!(body["items"][0]["content_type"], "audiobook");
+		let body = test_app.get("/music/get_music?content_type=audiobook").await.json();
+		assert_eq!(body["total_count"], 2);
+		let body = test_app
+			.get(&format!("/music/get_music?uuid={FIRST_BOOK}"))
+			.await
+			.json();
+		assert_eq!(body["total_count"], 1);
+
+		let body = test_app.get("/audiobooks/series").await.json();
+		assert_eq!(body["items"][0]["series"], "Saga");
+		assert_eq!(body["items"][0]["books"][0]["id"], FIRST_BOOK);
+		let body = test_app.get("/audiobooks/authors").await.json();
+		assert_eq!(body["items"][0]["book_count"], 2);
+	}
+
+	#[tokio::test]
+	async fn progress_is_tracked_per_book() {
+		let test_app = TestApp::seeded();
+		insert_series(&test_app);
+		let cookies = test_app.login("seed_user_0").await;
+		let set_progress = |music_id: &str, position: f64| {
+			let body = json!({ "music_id": music_id, "position": position });
+			test_app.request(Method::POST, "/audiobooks/progress", Some(body), &cookies)
+		};
+
+		let response = set_progress(FIRST_BOOK, 100.0).await;
+		assert_eq!(response.json()["finished"], false);
+		let body = test_app
+			.request(Method::GET, "/audiobooks/in_progress", None, &cookies)
+			.await
+			.json();
+		assert_eq!(body["items"][0]["id"], FIRST_BOOK);
+		assert_eq!(body["items"][0]["progress"]["position"], 100.0);
+
+		// Close enough to the end
+		let response = set_progress(FIRST_BOOK, 590.0).await;
+		assert_eq!(response.json()["finished"], true);
+		let body = test_app
+			.request(Method::GET, "/audiobooks/in_progress", None, &cookies)
+			.await
+			.json();
+		assert_eq!(body["items"], json!([]));
+
+		let body = test_app
+			.request(Method::GET, "/audiobooks", None, &cookies)
+			.await
+			.json();
+		assert_eq!(body["items"][0]["progress"]["finished"], true);
+		assert_eq!(body["items"][1]["progress"], json!(null));
+
+		let music_id = test_app.get("/music/get_music").await.json()["items"][0]["id"]
+			.as_str()
+			.unwrap()
+			.to_string();
+		let response = set_progress(&music_id, 10.0).await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+	}
+}
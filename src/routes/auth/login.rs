@@ -1,14 +1,16 @@
 use crate::core::app_state::AppState;
+use crate::i18n::Locale;
 use crate::lobic_db::models::User;
 use crate::schema::users::dsl::*;
 use crate::utils::{cookie, exp, jwt};
 
 use axum::{
 	extract::State,
-	http::{header, status::StatusCode},
+	http::{header, status::StatusCode, HeaderMap},
 	response::Response,
 	Json,
 };
+use axum_extra::extract::cookie::CookieJar;
 use diesel::prelude::*;
 use pwhash::bcrypt;
 use serde::{Deserialize, Serialize};
@@ -19,7 +21,14 @@ pub struct LoginPayload {
 	pub password: String,
 }
 
-pub async fn login(State(app_state): State<AppState>, Json(payload): Json<LoginPayload>) -> Response<String> {
+pub async fn login(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	jar: CookieJar,
+	Json(payload): Json<LoginPayload>,
+) -> Response<String> {
+	let locale = Locale::negotiate(&headers, &jar, &app_state.db_pool);
+
 	// Getting db from pool
 	let mut db_conn = match app_state.db_pool.get() {
 		Ok(conn) => conn,
@@ -41,7 +50,7 @@ pub async fn login(State(app_state): State<AppState>, Json(payload): Json<LoginP
 		Err(_) => {
 			return Response::builder()
 				.status(StatusCode::BAD_REQUEST)
-				.body(format!("Account with email {} doesn't exists", &payload.email))
+				.body(locale.tf("login.unknown_email", &[("email", &payload.email)]))
 				.unwrap();
 		}
 	};
@@ -50,7 +59,7 @@ pub async fn login(State(app_state): State<AppState>, Json(payload): Json<LoginP
 	if !bcrypt::verify(&payload.password, &user.pwd_hash) {
 		return Response::builder()
 			.status(StatusCode::BAD_REQUEST)
-			.body("Incorrent password".to_string())
+			.body(locale.t("login.wrong_password"))
 			.unwrap();
 	}
 
@@ -60,6 +69,7 @@ pub async fn login(State(app_state): State<AppState>, Json(payload): Json<LoginP
 	let access_claims = jwt::Claims {
 		id: user.user_id.clone(),
 		exp: exp::expiration_from_min(60),
+		iat: exp::now(),
 	};
 	let access_token = match jwt::generate(access_claims, &jwt_secret_key) {
 		Ok(token) => token,
@@ -74,6 +84,7 @@ pub async fn login(State(app_state): State<AppState>, Json(payload): Json<LoginP
 	let refresh_claims = jwt::Claims {
 		id: user.user_id.clone(),
 		exp: exp::expiration_from_days(7),
+		iat: exp::now(),
 	};
 	let refresh_token = match jwt::generate(refresh_claims, &jwt_secret_key) {
 		Ok(token) => token,
@@ -98,3 +109,62 @@ pub async fn login(State(app_state): State<AppState>, Json(payload): Json<LoginP
 		.body("OK".to_string())
 		.unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn login_sets_a_working_session() {
+		let test_app = TestApp::seeded();
+
+		let cookies = test_app.login("seed_user_0").await;
+		assert!(cookies.iter().any(|cookie| cookie.starts_with("access_token=")));
+		assert!(cookies.iter().any(|cookie| cookie.starts_with("refresh_token=")));
+
+		let response = test_app.request(Method::GET, "/verify", None, &cookies).await;
+		assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+	}
+
+	#[tokio::test]
+	async fn rejects_a_wrong_password() {
+		let test_app = TestApp::seeded();
+
+		let response = test_app
+			.post(
+				"/login",
+				json!({ "email": "seed_user_0@seed.lobic", "password": "not the password" }),
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		assert!(response.cookies.is_empty());
+	}
+
+	#[tokio::test]
+	async fn rejects_an_unknown_email() {
+		let test_app = TestApp::new();
+
+		let response = test_app
+			.post(
+				"/login",
+				json!({ "email": "nobody@seed.lobic", "password": "password" }),
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+	}
+
+	#[tokio::test]
+	async fn protected_routes_need_a_session() {
+		let test_app = TestApp::seeded();
+
+		assert_eq!(test_app.get("/verify").await.status, StatusCode::UNAUTHORIZED);
+		assert_eq!(test_app.get("/user/settings").await.status, StatusCode::UNAUTHORIZED);
+
+		let cookies = test_app.login("seed_user_2").await;
+		let response = test_app.request(Method::GET, "/user/settings", None, &cookies).await;
+		assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+	}
+}
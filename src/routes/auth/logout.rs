@@ -1,5 +1,6 @@
 use crate::core::app_state::AppState;
-use crate::utils::cookie;
+use crate::core::queue_snapshots::snapshot_on_logout;
+use crate::utils::{auth::session_user_id, cookie};
 
 use axum::{
 	extract::State,
@@ -7,6 +8,7 @@ use axum::{
 	response::Response,
 	Json,
 };
+use axum_extra::extract::cookie::CookieJar;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -14,7 +16,19 @@ pub struct LogoutPayload {
 	pub user_id: String,
 }
 
-pub async fn logout(State(app_state): State<AppState>, Json(payload): Json<LogoutPayload>) -> Response<String> {
+pub async fn logout(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<LogoutPayload>,
+) -> Response<String> {
+	// The queue survives the client clearing it, restored from /player/queue_snapshots. Only the queue
+	// of the user the session is for, not the one named in the body.
+	if let (Some(now_playing), Ok(mut db_conn)) = (
+		session_user_id(&jar).and_then(|user_id| app_state.now_playing_pool.get(&user_id)),
+		app_state.db_pool.get(),
+	) {
+		let _ = snapshot_on_logout(&now_playing, &mut db_conn);
+	}
 	let _ = app_state.user_pool.remove(&payload.user_id);
 
 	let user_cookie = cookie::create("user_id", "", 0);
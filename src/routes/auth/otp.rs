@@ -1,15 +1,17 @@
 use crate::core::app_state::AppState;
+use crate::i18n::Locale;
 use crate::lobic_db::models::User;
 use crate::mail::mailer::send_mail;
-use crate::mail::otp_mail::otp_mail;
+use crate::mail::templates::Template;
 use crate::schema::users;
 
 use axum::{
 	extract::{Path, State},
-	http::status::StatusCode,
+	http::{status::StatusCode, HeaderMap},
 	response::Response,
 	Json,
 };
+use axum_extra::extract::cookie::CookieJar;
 use chrono::{DateTime, Duration, Utc};
 use diesel::prelude::*;
 use rand::Rng;
@@ -134,7 +136,14 @@ pub async fn verify_otp(State(app_state): State<AppState>, Json(payload): Json<V
 		.unwrap();
 }
 
-pub async fn resend_otp(State(app_state): State<AppState>, Path(identifier): Path<String>) -> Response<String> {
+pub async fn resend_otp(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	jar: CookieJar,
+	Path(identifier): Path<String>,
+) -> Response<String> {
+	let locale = Locale::negotiate(&headers, &jar, &app_state.db_pool);
+
 	let mut db_conn = match app_state.db_pool.get() {
 		Ok(conn) => conn,
 		Err(err) => {
@@ -161,7 +170,7 @@ pub async fn resend_otp(State(app_state): State<AppState>, Path(identifier): Pat
 		Err(_) => {
 			return Response::builder()
 				.status(StatusCode::BAD_REQUEST)
-				.body(format!("Username or Email is not registered: {}", &identifier))
+				.body(locale.tf("otp.unknown_user", &[("identifier", &identifier)]))
 				.unwrap();
 		}
 	};
@@ -187,11 +196,11 @@ pub async fn resend_otp(State(app_state): State<AppState>, Path(identifier): Pat
 	}
 
 	// Send the otp mail
-	let mail = otp_mail(&user.email, new_otp);
+	let mail = Template::Verification.message(&user.email, locale, &[("username", &user.username), ("otp", &new_otp)]);
 	send_mail(mail);
 
 	Response::builder()
 		.status(StatusCode::OK)
-		.body("Sucessfully sent a new otp".to_string())
+		.body(locale.t("otp.sent"))
 		.unwrap()
 }
@@ -1,13 +1,15 @@
 use crate::core::app_state::AppState;
+use crate::core::instance::open_registrations;
+use crate::i18n::Locale;
 use crate::lobic_db::models::User;
 use crate::mail::mailer::send_mail;
-use crate::mail::otp_mail::otp_mail;
+use crate::mail::templates::Template;
 use crate::schema::users::dsl::*;
 use crate::utils::{cookie, exp, jwt};
 
 use axum::{
 	extract::State,
-	http::{header, status::StatusCode},
+	http::{header, status::StatusCode, HeaderMap},
 	response::Response,
 	Json,
 };
@@ -25,7 +27,18 @@ pub struct SignupPayload {
 	pub password: String,
 }
 
-pub async fn signup(State(app_state): State<AppState>, Json(payload): Json<SignupPayload>) -> Response<String> {
+pub async fn signup(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	Json(payload): Json<SignupPayload>,
+) -> Response<String> {
+	if !open_registrations() {
+		return Response::builder()
+			.status(StatusCode::FORBIDDEN)
+			.body("Registrations are closed on this instance".to_string())
+			.unwrap();
+	}
+
 	// Getting db from pool
 	let mut db_conn = match app_state.db_pool.get() {
 		Ok(conn) => conn,
@@ -75,7 +88,11 @@ pub async fn signup(State(app_state): State<AppState>, Json(payload): Json<Signu
 	let new_otp = rng.random_range(100_000..1_000_000).to_string();
 
 	// Send the otp mail
-	let mail = otp_mail(&payload.email, new_otp.clone());
+	let mail = Template::Verification.message(
+		&payload.email,
+		Locale::from_headers(&headers),
+		&[("username", &payload.username), ("otp", &new_otp)],
+	);
 	send_mail(mail);
 
 	// Create new user
@@ -89,6 +106,7 @@ pub async fn signup(State(app_state): State<AppState>, Json(payload): Json<Signu
 		otp: new_otp,
 		otp_expires_at: (Utc::now() + Duration::minutes(5)).to_string(),
 		otp_verified: None,
+		is_admin: false,
 	};
 
 	// Insert into the database
@@ -103,6 +121,7 @@ pub async fn signup(State(app_state): State<AppState>, Json(payload): Json<Signu
 	let access_claims = jwt::Claims {
 		id: new_user_id.clone(),
 		exp: exp::expiration_from_min(60),
+		iat: exp::now(),
 	};
 	let access_token = match jwt::generate(access_claims, &jwt_secret_key) {
 		Ok(token) => token,
@@ -117,6 +136,7 @@ pub async fn signup(State(app_state): State<AppState>, Json(payload): Json<Signu
 	let refresh_claims = jwt::Claims {
 		id: new_user_id.clone(),
 		exp: exp::expiration_from_days(7),
+		iat: exp::now(),
 	};
 	let refresh_token = match jwt::generate(refresh_claims, &jwt_secret_key) {
 		Ok(token) => token,
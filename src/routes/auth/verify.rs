@@ -57,6 +57,7 @@ pub async fn verify(jar: CookieJar) -> Response<String> {
 			let access_claims = jwt::Claims {
 				id: claims.id.clone(),
 				exp: exp::expiration_from_sec(10),
+				iat: exp::now(),
 			};
 			let access_token = match jwt::generate(access_claims, &secret_key) {
 				Ok(token) => token,
@@ -0,0 +1,105 @@
+use crate::config::{
+	ANIMATED_COVER_FORMATS, API_VERSION, IMAGE_FORMATS, IMAGE_SIZES, MAX_ANIMATED_COVER_BYTES, MAX_ANIMATED_COVER_SECS,
+	MAX_COVER_ART_BYTES, MAX_CURATION_IMPORT_BYTES, MAX_JSON_BODY_BYTES, MAX_SIGNED_IMAGES, MAX_UPLOAD_BYTES,
+	STREAM_FORMATS, TRANSCODE_BITRATES,
+};
+use crate::core::{
+	app_state::AppState, artwork::animated, audio_analysis, client_errors, curation_import,
+	instance::open_registrations, mpd, play_rule::PlayRule, realtime,
+};
+
+use crate::utils::{fields, negotiate};
+
+use axum::{
+	extract::State,
+	http::{status::StatusCode, HeaderMap},
+	response::Response,
+};
+use serde_json::json;
+
+// Lets the clients find out what this instance supports instead of assuming it
+pub async fn get_capabilities(State(app_state): State<AppState>, headers: HeaderMap) -> Response<String> {
+	let directory_listed = std::env::var("INSTANCE_DIRECTORY_URL").is_ok_and(|url| !url.is_empty());
+	let play_rule = app_state
+		.db_pool
+		.get()
+		.ok()
+		.map(|mut db_conn| PlayRule::load(&mut db_conn));
+	// the same ffmpeg the animated covers go through
+	let transcode_bitrates: &[u32] = if animated::available() {
+		&TRANSCODE_BITRATES
+	} else {
+		&[]
+	};
+
+	let capabilities = json!({
+		"api_version": API_VERSION,
+		"server_version": env!("CARGO_PKG_VERSION"),
+		"features": {
+			"lobbies": true,
+			"lobby_chat": true,
+			"sleep_timer": true,
+			"federation": true,
+			"takedowns": true,
+			"audiobooks": true,
+			"playlist_undo": true,
+			"playlist_trash": true,
+			"playlist_folders": true,
+			"playlist_releases": true,
+			"library_webhooks": true,
+			"embeds": true,
+			"stream_overlays": true,
+			"mpd_bridge": mpd::enabled(),
+			"audio_analysis": audio_analysis::enabled(),
+			"directory_listed": directory_listed,
+			"realtime_fanout": realtime::enabled(),
+		},
+		"streaming": {
+			"formats": STREAM_FORMATS,
+			"transcoding": transcode_bitrates, // kbps, asked for with ?bitrate= on the stream
+			"federation_relay": true,
+		},
+		"uploads": {
+			"max_bytes": MAX_UPLOAD_BYTES,
+			"max_json_bytes": MAX_JSON_BODY_BYTES, // every other request body
+			"image_formats": IMAGE_FORMATS,
+			"animated_covers": {
+				"enabled": animated::available(),
+				"formats": ANIMATED_COVER_FORMATS,
+				"max_bytes": MAX_ANIMATED_COVER_BYTES,
+				"max_secs": MAX_ANIMATED_COVER_SECS,
+			},
+			"cover_art": {
+				"formats": ["png", "jpeg"],
+				"max_bytes": MAX_COVER_ART_BYTES,
+			},
+			"curation_import": {
+				"formats": curation_import::FORMATS,
+				"max_bytes": MAX_CURATION_IMPORT_BYTES,
+			},
+		},
+		"signed_images": {
+			"sizes": IMAGE_SIZES,
+			"max_per_request": MAX_SIGNED_IMAGES,
+		},
+		"plays": {
+			"rule": play_rule, // report listened_secs, shorter listens aren't counted
+		},
+		"client_errors": {
+			"error_codes": client_errors::ERROR_CODES, // for POST /client_errors when a track fails to play
+			"network_types": client_errors::NETWORK_TYPES,
+		},
+		"auth": {
+			"modes": ["cookie_jwt", "oauth2"],
+			"email_otp": true,
+			"open_registrations": open_registrations(),
+		},
+		"websocket": {
+			"path": "/ws",
+		},
+		"response_formats": ["json", "xml"], // by Accept, on the endpoints that support it
+		"music_field_groups": fields::GROUPS.map(|(group, _)| group), // for ?include= and ?exclude= on the track lists
+	});
+
+	negotiate::respond(&headers, StatusCode::OK, "capabilities", &capabilities)
+}
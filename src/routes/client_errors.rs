@@ -0,0 +1,289 @@
+use crate::config::{MAX_CLIENT_ERRORS_PER_HOUR, MUSIC_STORAGE};
+use crate::core::app_state::AppState;
+use crate::core::client_errors::{self, DEFAULT_REPORT_DAYS, ERROR_CODES, MAX_REPORT_DAYS, NETWORK_TYPES};
+use crate::lobic_db::models::ClientError;
+use crate::schema::{client_errors as client_errors_table, music};
+use crate::utils::auth::{require_admin, require_user};
+
+use axum::{
+	extract::{Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{Duration as ChronoDuration, Utc};
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use std::path::Path;
+use uuid::Uuid;
+
+const MAX_DEVICE_LEN: usize = 100;
+const MAX_MESSAGE_LEN: usize = 500;
+
+// :report_client_error
+#[derive(Debug, Deserialize)]
+pub struct ClientErrorPayload {
+	pub music_id: String,
+	pub error_code: String,
+	pub device: Option<String>,       // e.g. "Pixel 7 / Android 14", cut at 100 characters
+	pub network_type: Option<String>, // wifi, cellular, ethernet, offline or unknown
+	pub message: Option<String>,      // whatever the player said, cut at 500 characters
+}
+
+fn trimmed(value: Option<String>, max_len: usize) -> Option<String> {
+	value
+		.map(|value| value.trim().chars().take(max_len).collect::<String>())
+		.filter(|value| !value.is_empty())
+}
+
+// Called by the players when a track fails to play
+pub async fn report_client_error(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<ClientErrorPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let error_code = payload.error_code.trim().to_lowercase();
+	if !ERROR_CODES.contains(&error_code.as_str()) {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("error_code must be one of {}", ERROR_CODES.join(", ")))
+			.unwrap();
+	}
+	let network_type = payload
+		.network_type
+		.map(|network_type| network_type.trim().to_lowercase());
+	if network_type
+		.as_deref()
+		.is_some_and(|network_type| !NETWORK_TYPES.contains(&network_type))
+	{
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("network_type must be one of {}", NETWORK_TYPES.join(", ")))
+			.unwrap();
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+	match music::table
+		.find(&payload.music_id)
+		.select(music::music_id)
+		.first::<String>(&mut db_conn)
+		.optional()
+	{
+		Ok(Some(_)) => {}
+		Ok(None) => {
+			return Response::builder()
+				.status(StatusCode::NOT_FOUND)
+				.body("Music not found".to_string())
+				.unwrap();
+		}
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	}
+
+	let now = Utc::now();
+	let hour_ago = (now - ChronoDuration::hours(1)).to_rfc3339();
+	let recent = client_errors_table::table
+		.filter(client_errors_table::user_id.eq(&user_id))
+		.filter(client_errors_table::reported_date_time.ge(hour_ago))
+		.count()
+		.get_result::<i64>(&mut db_conn);
+	match recent {
+		Ok(recent) if recent >= MAX_CLIENT_ERRORS_PER_HOUR => {
+			return Response::builder()
+				.status(StatusCode::TOO_MANY_REQUESTS)
+				.header(header::RETRY_AFTER, "3600")
+				.body("Too many error reports, try again later".to_string())
+				.unwrap();
+		}
+		Ok(_) => {}
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	}
+
+	let error = ClientError {
+		error_id: Uuid::new_v4().to_string(),
+		user_id,
+		music_id: payload.music_id,
+		error_code,
+		device: trimmed(payload.device, MAX_DEVICE_LEN),
+		network_type,
+		message: trimmed(payload.message, MAX_MESSAGE_LEN),
+		reported_date_time: now.to_rfc3339(),
+	};
+	match diesel::insert_into(client_errors_table::table)
+		.values(&error)
+		.execute(&mut db_conn)
+	{
+		Ok(_) => Response::builder()
+			.status(StatusCode::CREATED)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(json!({ "error_id": error.error_id }).to_string())
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to save the report: {err}"))
+			.unwrap(),
+	}
+}
+
+// :get_client_errors
+// The playback errors of the last days by code, device and track, every track along with the state of its file
+#[derive(Debug, Deserialize)]
+pub struct ClientErrorsQuery {
+	pub days: Option<i64>, // defaults to 7, at most 90
+}
+
+pub async fn get_client_errors(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(params): Query<ClientErrorsQuery>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+
+	let days = params.days.unwrap_or(DEFAULT_REPORT_DAYS).clamp(1, MAX_REPORT_DAYS);
+	match client_errors::report(days, Path::new(MUSIC_STORAGE), &mut db_conn) {
+		Ok(report) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&report).unwrap())
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::core::client_errors::{self, LikelyCause};
+	use crate::schema::{music, users};
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+	use std::fs;
+	use uuid::Uuid;
+
+	#[tokio::test]
+	async fn playback_errors_are_checked_against_the_files() {
+		let test_app = TestApp::seeded();
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let admin = test_app.login("seed_user_1").await;
+		let listener = test_app.login("seed_user_0").await;
+		let music_ids = music::table
+			.select(music::music_id)
+			.order(music::music_id.asc())
+			.limit(2)
+			.load::<String>(&mut test_app.db_conn())
+			.unwrap();
+		let (missing, on_disk) = (&music_ids[0], &music_ids[1]);
+
+		let report = |music_id: &str, code: &str, device: &str| {
+			json!({ "music_id": music_id, "error_code": code, "device": device, "network_type": "WiFi" })
+		};
+		for (cookies, body) in [
+			(&listener, report(missing, "not_found", "pixel 7")),
+			(&admin, report(missing, "not_found", "iphone 15")),
+			(&listener, report(on_disk, "decode", "pixel 7")),
+			(&admin, report(on_disk, "decode", "iphone 15")),
+		] {
+			let response = test_app
+				.request(Method::POST, "/client_errors", Some(body), cookies)
+				.await;
+			assert_eq!(response.status, StatusCode::CREATED);
+		}
+		let response = test_app
+			.request(
+				Method::POST,
+				"/client_errors",
+				Some(report(on_disk, "exploded", "pixel 7")),
+				&listener,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+		let response = test_app
+			.request(Method::GET, "/admin/analytics/client_errors", None, &listener)
+			.await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+		let body = test_app
+			.request(Method::GET, "/admin/analytics/client_errors?days=1", None, &admin)
+			.await
+			.json();
+		assert_eq!(body["reports"], 4);
+		assert_eq!(body["by_network_type"]["wifi"], 4);
+		let decode = body["by_code"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.find(|code| code["error_code"] == "decode")
+			.unwrap();
+		assert_eq!(
+			(decode["reports"].as_u64(), decode["devices"].as_u64()),
+			(Some(2), Some(2))
+		);
+		let track = |music_id: &str| {
+			body["tracks"]
+				.as_array()
+				.unwrap()
+				.iter()
+				.find(|track| track["music"]["id"] == music_id)
+				.unwrap()
+				.clone()
+		};
+		assert_eq!(track(missing)["health"]["problem"], "missing_file");
+		assert_eq!(track(missing)["likely_cause"], "broken_file");
+		assert_eq!(track(on_disk)["users"], 2);
+
+		// checked against a storage that has the other one
+		let storage = std::env::temp_dir().join(format!("lobic_music_{}", Uuid::new_v4()));
+		fs::create_dir_all(&storage).unwrap();
+		fs::write(storage.join(format!("{on_disk}.mp3")), b"ID3 not really an mp3").unwrap();
+		let report = client_errors::report(1, &storage, &mut test_app.db_conn()).unwrap();
+		fs::remove_dir_all(&storage).unwrap();
+		assert_eq!(report.broken_files, 1);
+		let track = report.tracks.iter().find(|track| track.music.id == *on_disk).unwrap();
+		assert!(track.health.exists);
+		assert_eq!(track.likely_cause, Some(LikelyCause::Transcoder));
+	}
+}
@@ -0,0 +1,197 @@
+use crate::core::app_state::AppState;
+use crate::lobic_db::models::CoverArt;
+use crate::services::ArtworkService;
+use crate::utils::auth::require_user;
+
+use axum::{
+	body::Body,
+	extract::{multipart::MultipartError, Multipart, Path, State},
+	http::{header, status::StatusCode},
+	response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+fn art_json(art: &CoverArt) -> Value {
+	json!({
+		"art_id": art.art_id,
+		"img_uuid": art.img_uuid,
+		"uploader_id": art.uploader_id,
+		"is_original": art.is_original,
+		"is_canonical": art.is_canonical,
+		"uploaded_date_time": art.uploaded_date_time,
+		"url": format!("/cover_art/file/{}", art.art_id),
+	})
+}
+
+fn multipart_error(err: MultipartError) -> Response<String> {
+	Response::builder().status(err.status()).body(err.body_text()).unwrap()
+}
+
+// :get_cover_art
+// Every version of the album's art, target is album (by cover image uuid) or track
+pub async fn get_cover_art(
+	State(app_state): State<AppState>,
+	Path((target, target_id)): Path<(String, String)>,
+) -> Response<String> {
+	let service = ArtworkService::new(&app_state.db_pool);
+	let result = service
+		.cover_art_album(&target, &target_id)
+		.and_then(|img_uuid| Ok((service.cover_art(&img_uuid)?, img_uuid)));
+	match result {
+		Ok((versions, img_uuid)) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(
+				json!({
+					"img_uuid": img_uuid,
+					"image_url": format!("/image/{img_uuid}"),
+					"versions": versions.iter().map(art_json).collect::<Vec<_>>(),
+				})
+				.to_string(),
+			)
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :upload_cover_art
+// Multipart with the png or jpeg in the `image` field, it becomes the album's cover right away
+pub async fn upload_cover_art(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path((target, target_id)): Path<(String, String)>,
+	mut multipart: Multipart,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut image = None;
+	loop {
+		let field = match multipart.next_field().await {
+			Ok(Some(field)) => field,
+			Ok(None) => break,
+			Err(err) => return multipart_error(err),
+		};
+		if field.name() == Some("image") {
+			match field.bytes().await {
+				Ok(bytes) => image = Some(bytes),
+				Err(err) => return multipart_error(err),
+			}
+		}
+	}
+	let Some(image) = image else {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body("Missing the image field".to_string())
+			.unwrap();
+	};
+
+	let service = ArtworkService::new(&app_state.db_pool);
+	let result = tokio::task::spawn_blocking(move || {
+		let img_uuid = service.cover_art_album(&target, &target_id)?;
+		service.upload_cover_art(&img_uuid, &user_id, &image)
+	})
+	.await;
+	match result {
+		Ok(Ok(art)) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(art_json(&art).to_string())
+			.unwrap(),
+		Ok(Err(err)) => err.into_response(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to save the cover art: {err}"))
+			.unwrap(),
+	}
+}
+
+// :select_cover_art
+// Makes an earlier version the album's cover again, the original one included
+pub async fn select_cover_art(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path((target, target_id, art_id)): Path<(String, String, String)>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let service = ArtworkService::new(&app_state.db_pool);
+	let result = service
+		.cover_art_album(&target, &target_id)
+		.and_then(|img_uuid| service.select_cover_art(&img_uuid, &art_id, &user_id));
+	match result {
+		Ok(art) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(art_json(&art).to_string())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :get_cover_art_file
+pub async fn get_cover_art_file(State(app_state): State<AppState>, Path(art_id): Path<String>) -> Response {
+	// only ever a uuid, anything else would read outside the storage
+	if Uuid::parse_str(&art_id).is_err() {
+		return (StatusCode::NOT_FOUND, "No cover art").into_response();
+	}
+
+	let path = ArtworkService::new(&app_state.db_pool).cover_art_file(&art_id);
+	let bytes = match tokio::fs::read(&path).await {
+		Ok(bytes) => bytes,
+		Err(_) => return (StatusCode::NOT_FOUND, "No cover art").into_response(),
+	};
+	let content_type = if bytes.starts_with(&[0xff, 0xd8]) {
+		"image/jpeg"
+	} else {
+		"image/png"
+	};
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, content_type)
+		.header(header::CACHE_CONTROL, "public, max-age=31536000") // a version never changes
+		.body(Body::from(bytes))
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::core::artwork::cover_uuid;
+	use crate::schema::music;
+	use crate::test_support::TestApp;
+
+	use axum::http::StatusCode;
+	use diesel::prelude::*;
+
+	#[tokio::test]
+	async fn tracks_share_the_art_of_their_album() {
+		let test_app = TestApp::seeded();
+		let (music_id, artist, album) = music::table
+			.select((music::music_id, music::artist, music::album))
+			.first::<(String, String, String)>(&mut test_app.db_conn())
+			.unwrap();
+		let img_uuid = cover_uuid(&artist, &album).to_string();
+
+		let by_track = test_app.get(&format!("/cover_art/track/{music_id}")).await.json();
+		let by_album = test_app.get(&format!("/cover_art/album/{img_uuid}")).await.json();
+		assert_eq!(by_track, by_album);
+		assert_eq!(by_track["img_uuid"], img_uuid.as_str());
+		assert_eq!(by_track["versions"].as_array().unwrap().len(), 0);
+
+		let response = test_app
+			.get("/cover_art/album/3f1f0b5e-4a57-4c39-9a43-1a2b3c4d5e6f")
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+		let response = test_app.get(&format!("/cover_art/artist/{img_uuid}")).await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		let response = test_app.get("/cover_art/file/..%2F..%2Fsecret").await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+	}
+}
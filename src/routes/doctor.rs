@@ -0,0 +1,65 @@
+use crate::core::app_state::AppState;
+use crate::core::doctor;
+use crate::utils::auth::require_admin;
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+
+// :get_doctor
+// Same checks as `Lobic doctor`, every finding that isn't ok comes with what to do about it
+pub async fn get_doctor(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let db_pool = app_state.db_pool.clone();
+	match tokio::task::spawn_blocking(move || doctor::diagnose(&db_pool)).await {
+		Ok(report) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&report).unwrap())
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("The checks didn't finish: {err}"))
+			.unwrap(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::users;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+
+	#[tokio::test]
+	async fn admins_get_the_doctors_findings() {
+		let test_app = TestApp::seeded();
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let admin = test_app.login("seed_user_1").await;
+		let listener = test_app.login("seed_user_0").await;
+
+		let response = test_app.request(Method::GET, "/admin/doctor", None, &listener).await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+		let response = test_app.request(Method::GET, "/admin/doctor", None, &admin).await;
+		assert_eq!(response.status, StatusCode::OK);
+		let body = response.json();
+		let findings = body["findings"].as_array().unwrap();
+		let finding = |check: &str| findings.iter().find(|finding| finding["check"] == check).unwrap();
+		assert_eq!(finding("database.integrity")["severity"], "ok");
+		assert_eq!(finding("database.foreign_keys")["severity"], "ok");
+		// the test env has no mail server, that is worth a warning but nothing more
+		assert_eq!(finding("smtp")["severity"], "warning");
+		assert!(finding("smtp")["fix"].is_string());
+	}
+}
@@ -0,0 +1,443 @@
+use crate::config::{EMBED_CACHE_SECS, MAX_EMBED_TOKENS};
+use crate::core::app_state::AppState;
+use crate::core::federation::generate_token;
+use crate::lobic_db::models::{Availability, EmbedToken, Music, MusicResponse, Playlist, User};
+use crate::schema::{embed_tokens, music, playlist_songs, playlists, users};
+use crate::services::ProfileService;
+use crate::utils::auth::require_user;
+use crate::utils::list::ListResponse;
+use crate::utils::response::db_error;
+
+use axum::{
+	extract::{Path, Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateEmbedTokenPayload {
+	pub target_type: String,       // playlist | profile | now_playing | lobby
+	pub target_id: Option<String>, // the playlist_id or lobby_id, left out for the user's own profile and player
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbedQuery {
+	pub token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbeddedPlaylist {
+	pub playlist_id: String,
+	pub playlist_name: String,
+	pub owner: String, // username
+	pub cover_url: String,
+	pub last_updated_date_time: String,
+	pub version: i32,
+	pub songs: Vec<MusicResponse>, // in playlist order, the unavailable ones are left out
+}
+
+fn embed_url(target_type: &str, target_id: &str, token: &str) -> String {
+	match target_type {
+		EmbedToken::NOW_PLAYING | EmbedToken::LOBBY => format!("/overlay/{token}/now_playing"),
+		_ => format!("/embed/{target_type}/{target_id}?token={token}"),
+	}
+}
+
+// The token when it was made for exactly this target, its use is noted
+#[allow(clippy::result_large_err)]
+fn check_token(
+	token: &str,
+	target_type: &str,
+	target_id: &str,
+	db_conn: &mut SqliteConnection,
+) -> Result<EmbedToken, Response<String>> {
+	let embed_token = embed_tokens::table
+		.filter(embed_tokens::token.eq(token))
+		.filter(embed_tokens::target_type.eq(target_type))
+		.filter(embed_tokens::target_id.eq(target_id))
+		.first::<EmbedToken>(db_conn)
+		.optional()
+		.map_err(db_error)?;
+	let Some(embed_token) = embed_token else {
+		return Err(Response::builder()
+			.status(StatusCode::UNAUTHORIZED)
+			.body(format!("The token doesn't embed this {target_type}"))
+			.unwrap());
+	};
+	let _ = diesel::update(embed_tokens::table.find(&embed_token.token_id))
+		.set(embed_tokens::last_used_date_time.eq(Utc::now().to_rfc3339()))
+		.execute(db_conn);
+	Ok(embed_token)
+}
+
+fn embed_response(body: String) -> Response<String> {
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.header(header::CACHE_CONTROL, format!("public, max-age={EMBED_CACHE_SECS}"))
+		.body(body)
+		.unwrap()
+}
+
+// :create_embed_token
+// The token is only shown here, along with the url the widget fetches. Only the user's own public
+// playlists and their own profile can be embedded, and the overlays only show what the user or a lobby
+// they host is playing.
+pub async fn create_embed_token(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<CreateEmbedTokenPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+
+	let target_id = match payload.target_type.as_str() {
+		EmbedToken::PROFILE | EmbedToken::NOW_PLAYING => user_id.clone(),
+		EmbedToken::LOBBY => {
+			let Some(lobby_id) = payload.target_id else {
+				return Response::builder()
+					.status(StatusCode::BAD_REQUEST)
+					.body("Which lobby, target_id is missing".to_string())
+					.unwrap();
+			};
+			match app_state.lobby_pool.get(&lobby_id) {
+				Some(lobby) if lobby.host_id == user_id => lobby_id,
+				_ => {
+					return Response::builder()
+						.status(StatusCode::NOT_FOUND)
+						.body(format!("No lobby {lobby_id} hosted by you"))
+						.unwrap()
+				}
+			}
+		}
+		EmbedToken::PLAYLIST => {
+			let Some(playlist_id) = payload.target_id else {
+				return Response::builder()
+					.status(StatusCode::BAD_REQUEST)
+					.body("Which playlist, target_id is missing".to_string())
+					.unwrap();
+			};
+			let playlist = playlists::table
+				.find(&playlist_id)
+				.filter(playlists::user_id.eq(&user_id))
+				.first::<Playlist>(&mut db_conn)
+				.optional();
+			match playlist {
+				Ok(Some(playlist)) if playlist.availability == Availability::Available.as_str() => {}
+				Ok(Some(_)) => {
+					return Response::builder()
+						.status(StatusCode::BAD_REQUEST)
+						.body("Only public playlists can be embedded".to_string())
+						.unwrap()
+				}
+				Ok(None) => {
+					return Response::builder()
+						.status(StatusCode::NOT_FOUND)
+						.body(format!("No playlist {playlist_id} of yours"))
+						.unwrap()
+				}
+				Err(err) => return db_error(err),
+			}
+			playlist_id
+		}
+		target_type => {
+			return Response::builder()
+				.status(StatusCode::BAD_REQUEST)
+				.body(format!("Invalid target type: {target_type}"))
+				.unwrap()
+		}
+	};
+
+	let token_count = embed_tokens::table
+		.filter(embed_tokens::user_id.eq(&user_id))
+		.count()
+		.get_result::<i64>(&mut db_conn)
+		.unwrap_or(0);
+	if token_count >= MAX_EMBED_TOKENS {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("At most {MAX_EMBED_TOKENS} embed tokens, revoke one first"))
+			.unwrap();
+	}
+
+	let embed_token = EmbedToken {
+		token_id: Uuid::new_v4().to_string(),
+		user_id,
+		target_type: payload.target_type,
+		target_id,
+		token: generate_token(),
+		created_date_time: Utc::now().to_rfc3339(),
+		last_used_date_time: None,
+	};
+	if let Err(err) = diesel::insert_into(embed_tokens::table)
+		.values(&embed_token)
+		.execute(&mut db_conn)
+	{
+		return db_error(err);
+	}
+
+	let mut body = json!(embed_token);
+	body["token"] = embed_token.token.clone().into();
+	body["embed_url"] = embed_url(&embed_token.target_type, &embed_token.target_id, &embed_token.token).into();
+	Response::builder()
+		.status(StatusCode::CREATED)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(body.to_string())
+		.unwrap()
+}
+
+// :get_embed_tokens
+pub async fn get_embed_tokens(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	match embed_tokens::table
+		.filter(embed_tokens::user_id.eq(&user_id))
+		.order(embed_tokens::created_date_time.asc())
+		.load::<EmbedToken>(&mut db_conn)
+	{
+		Ok(tokens) => ListResponse::all(tokens).into_response(),
+		Err(err) => db_error(err),
+	}
+}
+
+// :revoke_embed_token
+// The widgets using it stop updating right away
+pub async fn revoke_embed_token(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(token_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	let revoked = diesel::delete(
+		embed_tokens::table
+			.filter(embed_tokens::token_id.eq(&token_id))
+			.filter(embed_tokens::user_id.eq(&user_id)),
+	)
+	.execute(&mut db_conn);
+	match revoked {
+		Ok(0) => Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("No embed token: {token_id}"))
+			.unwrap(),
+		Ok(_) => Response::builder()
+			.status(StatusCode::OK)
+			.body(format!("Embed token {token_id} revoked"))
+			.unwrap(),
+		Err(err) => db_error(err),
+	}
+}
+
+// :get_embedded_playlist
+// Read by the widget with ?token=, no session. Always the current songs, the playlist going private
+// or being taken down hides it.
+pub async fn get_embedded_playlist(
+	State(app_state): State<AppState>,
+	Path(playlist_id): Path<String>,
+	Query(params): Query<EmbedQuery>,
+) -> Response<String> {
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	if let Err(response) = check_token(&params.token, EmbedToken::PLAYLIST, &playlist_id, &mut db_conn) {
+		return response;
+	}
+
+	let playlist = match playlists::table
+		.find(&playlist_id)
+		.inner_join(users::table)
+		.select((Playlist::as_select(), users::username))
+		.first::<(Playlist, String)>(&mut db_conn)
+		.optional()
+	{
+		Ok(Some(playlist)) => playlist,
+		Ok(None) => {
+			return Response::builder()
+				.status(StatusCode::NOT_FOUND)
+				.body(format!("No playlist {playlist_id}"))
+				.unwrap()
+		}
+		Err(err) => return db_error(err),
+	};
+	let (playlist, owner) = playlist;
+	if playlist.availability != Availability::Available.as_str() {
+		return Response::builder()
+			.status(StatusCode::GONE)
+			.body("The playlist isn't public anymore".to_string())
+			.unwrap();
+	}
+
+	let songs = match playlist_songs::table
+		.filter(playlist_songs::playlist_id.eq(&playlist_id))
+		.inner_join(music::table)
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.order(playlist_songs::position.asc())
+		.select(Music::as_select())
+		.load::<Music>(&mut db_conn)
+	{
+		Ok(songs) => songs,
+		Err(err) => return db_error(err),
+	};
+
+	let embedded = EmbeddedPlaylist {
+		cover_url: format!("/playlist/cover_img/{}", playlist.playlist_id),
+		playlist_id: playlist.playlist_id,
+		playlist_name: playlist.playlist_name,
+		owner,
+		last_updated_date_time: playlist.last_updated_date_time,
+		version: playlist.version,
+		songs: songs.into_iter().map(Music::create_music_response).collect(),
+	};
+	embed_response(serde_json::to_string(&embedded).unwrap())
+}
+
+// :get_embedded_profile
+// The same public parts of the profile get_user_data shows, never the email
+pub async fn get_embedded_profile(
+	State(app_state): State<AppState>,
+	Path(user_id): Path<String>,
+	Query(params): Query<EmbedQuery>,
+) -> Response<String> {
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	if let Err(response) = check_token(&params.token, EmbedToken::PROFILE, &user_id, &mut db_conn) {
+		return response;
+	}
+	let user = match users::table.find(&user_id).first::<User>(&mut db_conn) {
+		Ok(user) => user,
+		Err(err) => return db_error(err),
+	};
+	drop(db_conn);
+
+	let profile_service = ProfileService::new(&app_state.db_pool);
+	let (pinned, anthem) = match (profile_service.pins(&user_id), profile_service.anthem(&user_id)) {
+		(Ok(pinned), Ok(anthem)) => (pinned, anthem),
+		(Err(err), _) | (_, Err(err)) => return err.into_response(),
+	};
+	let profile = json!({
+		"user_id": user.user_id,
+		"username": user.username,
+		"pfp_url": format!("/user/get_pfp/{user_id}.png"),
+		"pinned": pinned,
+		"anthem": anthem,
+	});
+	embed_response(profile.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::lobic_db::models::Availability;
+	use crate::schema::playlists;
+	use crate::test_support::TestApp;
+
+	use axum::http::{header, Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn embed_tokens_only_show_what_they_were_made_for() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let cookies = test_app.login("seed_user_0").await;
+		let (playlist_id, other_playlist_id) = {
+			let mut db_conn = test_app.db_conn();
+			let own = playlists::table
+				.filter(playlists::user_id.eq(&user_id))
+				.select(playlists::playlist_id)
+				.first::<String>(&mut db_conn)
+				.unwrap();
+			let other = playlists::table
+				.filter(playlists::user_id.ne(&user_id))
+				.select(playlists::playlist_id)
+				.first::<String>(&mut db_conn)
+				.unwrap();
+			(own, other)
+		};
+
+		let payload = json!({ "target_type": "playlist", "target_id": other_playlist_id });
+		let response = test_app
+			.request(Method::POST, "/user/embed_tokens/new", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+		let payload = json!({ "target_type": "playlist", "target_id": playlist_id });
+		let response = test_app
+			.request(Method::POST, "/user/embed_tokens/new", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::CREATED);
+		let created = response.json();
+		let embed_url = created["embed_url"].as_str().unwrap().to_string();
+		let token = created["token"].as_str().unwrap();
+
+		let response = test_app.get(&embed_url).await;
+		assert_eq!(response.status, StatusCode::OK);
+		assert!(response.headers[header::CACHE_CONTROL]
+			.to_str()
+			.unwrap()
+			.starts_with("public"));
+		assert_eq!(response.json()["playlist_id"], playlist_id);
+		// not the profile, nor another playlist
+		let response = test_app.get(&format!("/embed/profile/{user_id}?token={token}")).await;
+		assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+		let response = test_app
+			.get(&format!("/embed/playlist/{other_playlist_id}?token={token}"))
+			.await;
+		assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+		// made private, the widget stops showing it
+		diesel::update(playlists::table.find(&playlist_id))
+			.set(playlists::availability.eq(Availability::Private.as_str()))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		assert_eq!(test_app.get(&embed_url).await.status, StatusCode::GONE);
+
+		let profile = test_app
+			.request(
+				Method::POST,
+				"/user/embed_tokens/new",
+				Some(json!({ "target_type": "profile" })),
+				&cookies,
+			)
+			.await
+			.json();
+		let body = test_app.get(profile["embed_url"].as_str().unwrap()).await.json();
+		assert_eq!(body["username"], "seed_user_0");
+		assert!(body.get("email").is_none());
+
+		let uri = format!("/user/embed_tokens/revoke/{}", profile["token_id"].as_str().unwrap());
+		test_app.request(Method::POST, &uri, None, &cookies).await;
+		let response = test_app.get(profile["embed_url"].as_str().unwrap()).await;
+		assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+	}
+}
@@ -0,0 +1,75 @@
+use crate::core::app_state::AppState;
+use crate::core::federation::{bearer_token, generate_token, peer_by_token, PEER_ACTIVE, PEER_INVITED};
+use crate::schema::federation_peers;
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode, HeaderMap},
+	response::Response,
+	Json,
+};
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct HandshakePayload {
+	pub name: String,
+	pub base_url: String,
+	pub token: String,
+}
+
+// Called by the invited instance, authenticated with the invite token
+pub async fn handshake(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	Json(payload): Json<HandshakePayload>,
+) -> Response<String> {
+	let peer = match bearer_token(&headers).and_then(|token| peer_by_token(token, &app_state.db_pool)) {
+		Some(peer) if peer.status == PEER_INVITED => peer,
+		_ => {
+			return Response::builder()
+				.status(StatusCode::UNAUTHORIZED)
+				.body("Invalid or already used invite token".to_string())
+				.unwrap();
+		}
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	// Swapping the invite for a fresh token so the invite cannot be reused
+	let token = generate_token();
+	let result = diesel::update(federation_peers::table.filter(federation_peers::peer_id.eq(&peer.peer_id)))
+		.set((
+			federation_peers::base_url.eq(&payload.base_url),
+			federation_peers::local_token.eq(&token),
+			federation_peers::remote_token.eq(&payload.token),
+			federation_peers::status.eq(PEER_ACTIVE),
+		))
+		.execute(&mut db_conn);
+
+	if let Err(err) = result {
+		return Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to complete handshake: {err}"))
+			.unwrap();
+	}
+
+	println!("Peered with {} ({})", payload.name, payload.base_url);
+
+	let response = json!({ "token": token });
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(response.to_string())
+		.unwrap()
+}
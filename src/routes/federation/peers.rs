@@ -0,0 +1,225 @@
+use crate::core::app_state::AppState;
+use crate::core::federation::{generate_token, post_to_peer, PEER_ACTIVE, PEER_INVITED, PEER_REVOKED};
+use crate::core::instance::{instance_base_url, instance_name};
+use crate::lobic_db::models::FederationPeer;
+use crate::schema::federation_peers;
+use crate::utils::auth::require_admin;
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::{Path, State},
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+// :create_invite
+// Issues a token the admin of the other instance uses to peer with us
+#[derive(Debug, Deserialize)]
+pub struct CreateInvitePayload {
+	pub name: String,
+}
+
+pub async fn create_invite(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<CreateInvitePayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let peer = FederationPeer {
+		peer_id: Uuid::new_v4().to_string(),
+		name: payload.name,
+		base_url: None,
+		local_token: generate_token(),
+		remote_token: None,
+		status: PEER_INVITED.to_string(),
+		created_date_time: Utc::now().to_rfc3339(),
+	};
+
+	if let Err(err) = diesel::insert_into(federation_peers::table)
+		.values(&peer)
+		.execute(&mut db_conn)
+	{
+		return Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to create invite: {err}"))
+			.unwrap();
+	}
+
+	let response = json!({
+		"peer_id": peer.peer_id,
+		"base_url": instance_base_url(),
+		"invite_token": peer.local_token,
+	});
+	Response::builder()
+		.status(StatusCode::CREATED)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(response.to_string())
+		.unwrap()
+}
+
+// :add_peer
+// Completes the token exchange with an instance that invited us
+#[derive(Debug, Deserialize)]
+pub struct AddPeerPayload {
+	pub name: String,
+	pub base_url: String,
+	pub invite_token: String,
+}
+
+pub async fn add_peer(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<AddPeerPayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let mut peer = FederationPeer {
+		peer_id: Uuid::new_v4().to_string(),
+		name: payload.name,
+		base_url: Some(payload.base_url),
+		local_token: generate_token(),
+		remote_token: Some(payload.invite_token),
+		status: PEER_ACTIVE.to_string(),
+		created_date_time: Utc::now().to_rfc3339(),
+	};
+
+	// Handing our token over to the peer
+	let body = json!({
+		"name": instance_name(),
+		"base_url": instance_base_url(),
+		"token": peer.local_token,
+	});
+	let handshake = match post_to_peer(&peer, "/federation/handshake", &body).await {
+		Ok(value) => value,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::BAD_GATEWAY)
+				.body(format!("Handshake failed: {err}"))
+				.unwrap();
+		}
+	};
+
+	// The peer may hand out a fresh token in place of the invite
+	if let Some(token) = handshake["token"].as_str() {
+		peer.remote_token = Some(token.to_string());
+	}
+
+	if let Err(err) = diesel::insert_into(federation_peers::table)
+		.values(&peer)
+		.execute(&mut db_conn)
+	{
+		return Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to save peer: {err}"))
+			.unwrap();
+	}
+
+	Response::builder()
+		.status(StatusCode::CREATED)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(serde_json::to_string(&peer).unwrap())
+		.unwrap()
+}
+
+// :get_peers
+pub async fn get_peers(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	match federation_peers::table
+		.order(federation_peers::created_date_time.desc())
+		.load::<FederationPeer>(&mut db_conn)
+	{
+		Ok(peers) => ListResponse::all(peers).into_response(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap(),
+	}
+}
+
+// :revoke_peer
+pub async fn revoke_peer(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(curr_peer_id): Path<String>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	match diesel::update(federation_peers::table.filter(federation_peers::peer_id.eq(&curr_peer_id)))
+		.set(federation_peers::status.eq(PEER_REVOKED))
+		.execute(&mut db_conn)
+	{
+		Ok(0) => Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("Invalid peer id: {}", curr_peer_id))
+			.unwrap(),
+		Ok(_) => Response::builder()
+			.status(StatusCode::OK)
+			.body("Sucessfully revoked peer".to_string())
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap(),
+	}
+}
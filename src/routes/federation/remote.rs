@@ -0,0 +1,298 @@
+// Endpoints called by the peers, all of them are authenticated with the peer token
+use crate::config::{OpCode, SocketResponse};
+use crate::core::app_state::AppState;
+use crate::core::federation::{attach_remote_member, remote_member_id, require_peer, resolve_tracks};
+use crate::core::user_pool::Topic;
+use crate::routes::music::send_music::{send_music, StreamParams};
+
+use axum::{
+	extract::{ws::Message, Path, Query, State},
+	http::{header, status::StatusCode, HeaderMap},
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct RemoteLobbyPayload {
+	pub lobby_id: String,
+	pub user_id: String, // id of the user on the peer
+}
+
+// :remote_join
+pub async fn remote_join(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	Json(payload): Json<RemoteLobbyPayload>,
+) -> Response<String> {
+	let peer = match require_peer(&headers, &app_state.db_pool) {
+		Ok(peer) => peer,
+		Err(response) => return response,
+	};
+
+	let member_id = remote_member_id(&payload.user_id, &peer.peer_id);
+	if app_state.user_pool.exists(&member_id) {
+		let msg = format!("Client: {} is already in a lobby", payload.user_id);
+		return Response::builder().status(StatusCode::BAD_REQUEST).body(msg).unwrap();
+	}
+
+	attach_remote_member(&member_id, &payload.user_id, peer, &app_state.user_pool);
+
+	match app_state
+		.lobby_pool
+		.join_lobby(&payload.lobby_id, &member_id, &app_state.db_pool, &app_state.user_pool)
+	{
+		Ok(value) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(value.to_string())
+			.unwrap(),
+		Err(err) => {
+			app_state.user_pool.remove(&member_id);
+			Response::builder().status(StatusCode::BAD_REQUEST).body(err).unwrap()
+		}
+	}
+}
+
+// :remote_leave
+pub async fn remote_leave(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	Json(payload): Json<RemoteLobbyPayload>,
+) -> Response<String> {
+	let peer = match require_peer(&headers, &app_state.db_pool) {
+		Ok(peer) => peer,
+		Err(response) => return response,
+	};
+
+	let member_id = remote_member_id(&payload.user_id, &peer.peer_id);
+	let result =
+		app_state
+			.lobby_pool
+			.leave_lobby(&payload.lobby_id, &member_id, &app_state.db_pool, &app_state.user_pool);
+
+	// Dropping the member stops forwarding to the peer
+	app_state.user_pool.remove(&member_id);
+
+	match result {
+		Ok(msg) => Response::builder().status(StatusCode::OK).body(msg).unwrap(),
+		Err(err) => Response::builder().status(StatusCode::BAD_REQUEST).body(err).unwrap(),
+	}
+}
+
+// :remote_message
+#[derive(Debug, Deserialize)]
+pub struct RemoteMessagePayload {
+	pub lobby_id: String,
+	pub user_id: String,
+	pub message: String,
+}
+
+pub async fn remote_message(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	Json(payload): Json<RemoteMessagePayload>,
+) -> Response<String> {
+	let peer = match require_peer(&headers, &app_state.db_pool) {
+		Ok(peer) => peer,
+		Err(response) => return response,
+	};
+
+	let member_id = remote_member_id(&payload.user_id, &peer.peer_id);
+	if app_state.lobby_pool.get(&payload.lobby_id).is_none() {
+		let msg = format!("Invalid lobby id: {}", payload.lobby_id);
+		return Response::builder().status(StatusCode::NOT_FOUND).body(msg).unwrap();
+	}
+	if let Err(err) =
+		app_state
+			.lobby_pool
+			.append_message(&payload.lobby_id, &member_id, &payload.message, &app_state.db_pool)
+	{
+		return Response::builder().status(StatusCode::BAD_REQUEST).body(err).unwrap();
+	}
+
+	// Broadcasting the message to everyone in the lobby
+	let lobby = match app_state.lobby_pool.get(&payload.lobby_id) {
+		Some(lobby) => lobby,
+		None => {
+			let msg = format!("Invalid lobby id: {}", payload.lobby_id);
+			return Response::builder().status(StatusCode::NOT_FOUND).body(msg).unwrap();
+		}
+	};
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::GET_MESSAGES,
+		value: lobby.chat.clone().into(),
+	}
+	.to_string();
+	for client_id in &lobby.clients {
+		app_state
+			.user_pool
+			.send(client_id, &Topic::lobby(&lobby.id), response.clone());
+	}
+
+	Response::builder()
+		.status(StatusCode::OK)
+		.body("Sucessfully sent message".to_string())
+		.unwrap()
+}
+
+// :remote_event
+// A lobby hosted on the peer has something for one of our users, only the ones who joined it through us
+#[derive(Debug, Deserialize)]
+pub struct RemoteEventPayload {
+	pub user_id: String,
+	pub message: String,
+}
+
+pub async fn remote_event(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	Json(payload): Json<RemoteEventPayload>,
+) -> Response<String> {
+	let peer = match require_peer(&headers, &app_state.db_pool) {
+		Ok(peer) => peer,
+		Err(response) => return response,
+	};
+
+	if !app_state.shared_lobbies.is_member(&payload.user_id, &peer.peer_id) {
+		let msg = format!("User {} is not in a lobby of this peer", payload.user_id);
+		return Response::builder().status(StatusCode::FORBIDDEN).body(msg).unwrap();
+	}
+
+	let conn = match app_state.user_pool.get(&payload.user_id) {
+		Some(conn) => conn,
+		None => {
+			let msg = format!("User {} is not connected", payload.user_id);
+			return Response::builder().status(StatusCode::NOT_FOUND).body(msg).unwrap();
+		}
+	};
+
+	let message = resolve_tracks(&payload.message, &peer, &app_state.db_pool);
+	let _ = conn.send(Message::Text(message));
+
+	// Same as for the remote members here, the user is out once the lobby is gone
+	let left = serde_json::from_str::<SocketResponse>(&payload.message)
+		.map(|response| response.r#for == OpCode::LEAVE_LOBBY)
+		.unwrap_or(false);
+	if left {
+		app_state.shared_lobbies.leave(&payload.user_id, &peer.peer_id);
+	}
+
+	Response::builder()
+		.status(StatusCode::OK)
+		.body("Delivered".to_string())
+		.unwrap()
+}
+
+// :remote_stream
+// Streams one of our tracks to a peer that doesn't have it
+pub async fn remote_stream(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	Path(music_id): Path<String>,
+) -> Response {
+	if let Err(response) = require_peer(&headers, &app_state.db_pool) {
+		return response.into_response();
+	}
+
+	send_music(
+		Path(music_id),
+		Query(StreamParams {
+			sig: None,
+			bitrate: None,
+		}),
+		State(app_state),
+	)
+	.await
+	.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::core::outbox::Outbox;
+	use crate::schema::users;
+	use crate::test_support::TestApp;
+
+	use axum::http::{header, Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn peers_only_reach_the_users_who_joined_their_lobbies() {
+		let test_app = TestApp::seeded();
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let admin = test_app.login("seed_user_1").await;
+		let invite = test_app
+			.request(
+				Method::POST,
+				"/admin/federation/invite",
+				Some(json!({ "name": "elsewhere" })),
+				&admin,
+			)
+			.await
+			.json();
+		let peer_id = invite["peer_id"].as_str().unwrap().to_string();
+		let invite_token = format!("Bearer {}", invite["invite_token"].as_str().unwrap());
+
+		let handshake = json!({ "name": "elsewhere", "base_url": "http://127.0.0.1:9", "token": "theirs" });
+		let response = test_app
+			.request_with_headers(
+				Method::POST,
+				"/federation/handshake",
+				Some(handshake.clone()),
+				&[],
+				&[(header::AUTHORIZATION, &invite_token)],
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::OK);
+		let token = format!("Bearer {}", response.json()["token"].as_str().unwrap());
+		// the invite is used up
+		let response = test_app
+			.request_with_headers(
+				Method::POST,
+				"/federation/handshake",
+				Some(handshake),
+				&[],
+				&[(header::AUTHORIZATION, &invite_token)],
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+		let app_state = &test_app.app_state;
+		let user_id = test_app.user_id("seed_user_0");
+		let outbox = Outbox::new();
+		app_state.user_pool.insert(&user_id, &outbox);
+		let event = json!({ "user_id": user_id, "message": "hello" });
+		let as_peer = [(header::AUTHORIZATION, token.as_str())];
+		let send_event = || {
+			test_app.request_with_headers(
+				Method::POST,
+				"/federation/remote/event",
+				Some(event.clone()),
+				&[],
+				&as_peer,
+			)
+		};
+		assert_eq!(send_event().await.status, StatusCode::FORBIDDEN);
+		app_state.shared_lobbies.join(&user_id, &peer_id, "their lobby");
+		assert_eq!(send_event().await.status, StatusCode::OK);
+		assert!(matches!(outbox.next().await, Some(axum::extract::ws::Message::Text(text)) if text == "hello"));
+		app_state.shared_lobbies.leave(&user_id, &peer_id);
+		assert_eq!(send_event().await.status, StatusCode::FORBIDDEN);
+
+		let response = test_app
+			.request_with_headers(
+				Method::POST,
+				"/federation/remote/message",
+				Some(json!({ "lobby_id": "nowhere", "user_id": "them", "message": "hi" })),
+				&[],
+				&as_peer,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+	}
+}
@@ -0,0 +1,151 @@
+// Endpoints used by our own users to take part in lobbies hosted on a peer
+use crate::core::app_state::AppState;
+use crate::core::federation::{get_from_peer, peer_by_id, post_to_peer, PEER_ACTIVE};
+use crate::lobic_db::models::FederationPeer;
+use crate::utils::auth::require_user;
+
+use axum::{
+	body::Body,
+	extract::{Path, State},
+	http::{header, status::StatusCode},
+	response::{IntoResponse, Response},
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Debug, Deserialize)]
+pub struct SharedLobbyPayload {
+	pub peer_id: String,
+	pub lobby_id: String,
+}
+
+// :join_shared_lobby
+pub async fn join_shared_lobby(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<SharedLobbyPayload>,
+) -> Response<String> {
+	let curr_user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let body = json!({ "lobby_id": payload.lobby_id, "user_id": curr_user_id });
+	let response = forward(&app_state, &payload.peer_id, "/federation/remote/join", body).await;
+	if response.status() == StatusCode::OK {
+		app_state
+			.shared_lobbies
+			.join(&curr_user_id, &payload.peer_id, &payload.lobby_id);
+	}
+	response
+}
+
+// :leave_shared_lobby
+pub async fn leave_shared_lobby(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<SharedLobbyPayload>,
+) -> Response<String> {
+	let curr_user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	// The peer's events stop being let through even when it can't be reached
+	app_state.shared_lobbies.leave(&curr_user_id, &payload.peer_id);
+
+	let body = json!({ "lobby_id": payload.lobby_id, "user_id": curr_user_id });
+	forward(&app_state, &payload.peer_id, "/federation/remote/leave", body).await
+}
+
+// :send_shared_lobby_message
+#[derive(Debug, Deserialize)]
+pub struct SharedLobbyMessagePayload {
+	pub peer_id: String,
+	pub lobby_id: String,
+	pub message: String,
+}
+
+pub async fn send_shared_lobby_message(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<SharedLobbyMessagePayload>,
+) -> Response<String> {
+	let curr_user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let body = json!({
+		"lobby_id": payload.lobby_id,
+		"user_id": curr_user_id,
+		"message": payload.message,
+	});
+	forward(&app_state, &payload.peer_id, "/federation/remote/message", body).await
+}
+
+// :relay_music
+// Streams a track we don't have locally from the peer hosting the lobby
+pub async fn relay_music(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path((curr_peer_id, curr_music_id)): Path<(String, String)>,
+) -> Response {
+	if let Err(response) = require_user(&jar) {
+		return response.into_response();
+	}
+
+	let peer = match active_peer(&app_state, &curr_peer_id) {
+		Ok(peer) => peer,
+		Err(response) => return response.into_response(),
+	};
+
+	let remote = match get_from_peer(&peer, &format!("/federation/remote/stream/{}", curr_music_id)).await {
+		Ok(remote) => remote,
+		Err(err) => return (StatusCode::BAD_GATEWAY, err).into_response(),
+	};
+
+	// Passing the peer's status and content type through untouched
+	let status = StatusCode::from_u16(remote.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+	let content_type = remote
+		.headers()
+		.get(reqwest::header::CONTENT_TYPE)
+		.and_then(|value| value.to_str().ok())
+		.unwrap_or("audio/mpeg")
+		.to_string();
+
+	Response::builder()
+		.status(status)
+		.header(header::CONTENT_TYPE, content_type)
+		.body(Body::from_stream(remote.bytes_stream()))
+		.unwrap()
+}
+
+#[allow(clippy::result_large_err)]
+fn active_peer(app_state: &AppState, peer_id: &str) -> Result<FederationPeer, Response<String>> {
+	match peer_by_id(peer_id, &app_state.db_pool) {
+		Some(peer) if peer.status == PEER_ACTIVE => Ok(peer),
+		_ => Err(Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("Invalid peer id: {}", peer_id))
+			.unwrap()),
+	}
+}
+
+async fn forward(app_state: &AppState, peer_id: &str, path: &str, body: Value) -> Response<String> {
+	let peer = match active_peer(app_state, peer_id) {
+		Ok(peer) => peer,
+		Err(response) => return response,
+	};
+
+	match post_to_peer(&peer, path, &body).await {
+		Ok(value) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(json!({ "peer_id": peer.peer_id, "response": value }).to_string())
+			.unwrap(),
+		Err(err) => Response::builder().status(StatusCode::BAD_GATEWAY).body(err).unwrap(),
+	}
+}
@@ -0,0 +1,192 @@
+use crate::core::app_state::AppState;
+use crate::lobic_db::models::{Availability, Music, MusicResponse, NewRelease};
+use crate::schema::{music, new_releases};
+use crate::utils::auth::require_user;
+use crate::utils::list::ListResponse;
+use crate::utils::response::db_error;
+
+use axum::{
+	extract::{Query, State},
+	http::status::StatusCode,
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Deserialize)]
+pub struct FeedQuery {
+	#[serde(default)]
+	pub start_index: i64,
+	pub page_length: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NewReleaseResponse {
+	pub release_id: String,
+	pub artist: String,
+	pub album: String,
+	pub released_date_time: String,
+	pub songs: Vec<MusicResponse>, // the ones still available, in the order they were added
+}
+
+// :get_new_releases
+// What the scanner added for the artists the user follows, the latest first
+pub async fn get_new_releases(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(query): Query<FeedQuery>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let users_releases = new_releases::table.filter(new_releases::user_id.eq(&user_id));
+	let total_count = match users_releases.count().get_result::<i64>(&mut db_conn) {
+		Ok(count) => count,
+		Err(err) => return db_error(err),
+	};
+	let mut page = users_releases
+		.order(new_releases::released_date_time.desc())
+		.offset(query.start_index.max(0))
+		.into_boxed();
+	if let Some(page_length) = query.page_length.filter(|length| *length > 0) {
+		page = page.limit(page_length);
+	}
+	let releases = match page.load::<NewRelease>(&mut db_conn) {
+		Ok(releases) => releases,
+		Err(err) => return db_error(err),
+	};
+
+	let mut items = Vec::with_capacity(releases.len());
+	for release in releases {
+		let music_ids: Vec<String> = serde_json::from_str(&release.music_ids).unwrap_or_default();
+		let mut available: HashMap<String, Music> = match music::table
+			.filter(music::music_id.eq_any(&music_ids))
+			.filter(music::availability.eq(Availability::Available.as_str()))
+			.load::<Music>(&mut db_conn)
+		{
+			Ok(available) => available
+				.into_iter()
+				.map(|entry| (entry.music_id.clone(), entry))
+				.collect(),
+			Err(err) => return db_error(err),
+		};
+		let songs = music_ids
+			.iter()
+			.filter_map(|music_id| available.remove(music_id))
+			.map(Music::create_music_response)
+			.collect();
+		items.push(NewReleaseResponse {
+			release_id: release.release_id,
+			artist: release.artist,
+			album: release.album,
+			released_date_time: release.released_date_time,
+			songs,
+		});
+	}
+	ListResponse::page(items, total_count, query.start_index, query.page_length).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::core::new_releases;
+	use crate::lobic_db::models::{Music, NotifModel};
+	use crate::schema::{music, notifications};
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn followers_get_what_the_scanner_adds() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
+		let artist = music::table
+			.select(music::artist)
+			.first::<String>(&mut test_app.db_conn())
+			.unwrap();
+
+		let response = test_app
+			.request(
+				Method::POST,
+				"/music/artist/follow",
+				Some(json!({ "artist": "Nobody At All" })),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+		let body = test_app
+			.request(
+				Method::POST,
+				"/music/artist/follow",
+				Some(json!({ "artist": artist })),
+				&cookies,
+			)
+			.await
+			.json();
+		assert_eq!(body["items"][0]["artist"], artist.as_str());
+
+		// what a scan of the artist's tracks would have saved
+		let added = music::table
+			.filter(music::artist.eq(&artist))
+			.load::<Music>(&mut test_app.db_conn())
+			.unwrap();
+		new_releases::record(&added, &mut test_app.db_conn()).unwrap();
+
+		let body = test_app
+			.request(Method::GET, "/feed/new_releases", None, &cookies)
+			.await
+			.json();
+		let releases = body["items"].as_array().unwrap();
+		assert!(!releases.is_empty());
+		assert!(releases.iter().all(|release| release["artist"] == artist.as_str()));
+		let songs: usize = releases
+			.iter()
+			.map(|release| release["songs"].as_array().unwrap().len())
+			.sum();
+		assert_eq!(songs, added.len());
+		let others = test_app.login("seed_user_1").await;
+		let body = test_app
+			.request(Method::GET, "/feed/new_releases", None, &others)
+			.await
+			.json();
+		assert_eq!(body["total_count"], 0);
+
+		// a single notification however many albums
+		let user_id = test_app.user_id("seed_user_0");
+		new_releases::notify_followers(&test_app.app_state).unwrap();
+		new_releases::notify_followers(&test_app.app_state).unwrap();
+		let notifs = notifications::table
+			.filter(notifications::user_id.eq(&user_id))
+			.filter(notifications::op_code.eq("\"NEW_RELEASE\""))
+			.load::<NotifModel>(&mut test_app.db_conn())
+			.unwrap();
+		assert_eq!(notifs.len(), 1);
+
+		let body = test_app
+			.request(
+				Method::POST,
+				"/music/artist/unfollow",
+				Some(json!({ "artist": artist })),
+				&cookies,
+			)
+			.await
+			.json();
+		assert_eq!(body["items"], json!([]));
+	}
+}
@@ -1,68 +1,109 @@
 use crate::core::app_state::AppState;
-use crate::lobic_db::models::User;
-use crate::schema::users::dsl::*;
+use crate::services::LobbyService;
 
 use axum::{
 	extract::{Path, State},
-	http::StatusCode,
+	http::{header, StatusCode},
 	response::Response,
 };
-use diesel::prelude::*;
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct GetLobbyResponse {
-	pub id: String,
-	pub lobby_name: String,
-	pub lobby_icon: String,
-	pub listeners: i32,
-	pub song_name: String,
-	pub artist_name: String,
-}
 
 pub async fn get_lobby(State(app_state): State<AppState>, Path(lobby_id): Path<String>) -> Response<String> {
-	let mut db_conn = match app_state.db_pool.get() {
-		Ok(conn) => conn,
-		Err(err) => {
-			let msg = format!("Failed to get DB from pool: {err}");
-			return Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(msg)
-				.unwrap();
-		}
+	let response = match LobbyService::new(&app_state).summary(&lobby_id) {
+		Ok(summary) => summary,
+		Err(err) => return err.into_response(),
 	};
 
-	// Getting the required lobby
-	let lobby = match app_state.lobby_pool.get(&lobby_id) {
-		Some(lobby) => lobby,
-		None => {
-			let msg = format!("Invalid lobby id: {}", lobby_id);
-			return Response::builder().status(StatusCode::NOT_FOUND).body(msg).unwrap();
-		}
-	};
+	let response_str = serde_json::to_string(&response).unwrap();
+	Response::builder().status(StatusCode::OK).body(response_str).unwrap()
+}
 
-	// Getting the user data of the host
-	let user = match users.filter(user_id.eq(&lobby.host_id)).first::<User>(&mut db_conn) {
-		Ok(user) => user,
-		Err(err) => {
-			let msg = format!("Failed to fetch user: {err}");
-			return Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(msg)
-				.unwrap();
-		}
-	};
+// :get_lobby_queue
+pub async fn get_lobby_queue(State(app_state): State<AppState>, Path(lobby_id): Path<String>) -> Response<String> {
+	match LobbyService::new(&app_state).queue(&lobby_id) {
+		Ok(queue) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&queue).unwrap())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
 
-	// Building the response
-	let response = GetLobbyResponse {
-		id: lobby_id,
-		lobby_name: format!("{}'s Lobby", user.username),
-		lobby_icon: lobby.music.image_url,
-		listeners: lobby.clients.len() as i32,
-		song_name: lobby.music.title,
-		artist_name: lobby.music.artist,
-	};
+#[cfg(test)]
+mod tests {
+	use crate::config::MusicState;
+	use crate::core::lobby::Music;
+	use crate::schema::music;
+	use crate::services::music::signed_stream_url;
+	use crate::test_support::TestApp;
 
-	let response_str = serde_json::to_string(&response).unwrap();
-	Response::builder().status(StatusCode::OK).body(response_str).unwrap()
+	use axum::http::StatusCode;
+	use diesel::prelude::*;
+
+	#[tokio::test]
+	async fn albums_and_queues_only_hint_at_tracks_on_disk() {
+		let test_app = TestApp::seeded();
+		let mut db_conn = test_app.db_conn();
+		let ids = music::table
+			.filter(music::content_type.eq("music"))
+			.select(music::music_id)
+			.limit(3)
+			.load::<String>(&mut db_conn)
+			.unwrap();
+		let album = "Gapless Live";
+		diesel::update(music::table.filter(music::music_id.eq_any(&ids)))
+			.set(music::album.eq(album))
+			.execute(&mut db_conn)
+			.unwrap();
+		let url = reqwest::Url::parse_with_params(
+			"http://lobic/music/get_music",
+			&[("album", album), ("include", "image")],
+		)
+		.unwrap();
+		let tracks = test_app
+			.get(&format!("/music/get_music?{}", url.query().unwrap()))
+			.await
+			.json()["items"]
+			.as_array()
+			.unwrap()
+			.clone();
+		// The seeded tracks have no files, the hints are checked against a storage of their own in
+		// services::music
+		let body = test_app
+			.get(&format!("/music/get_music?{}", url.query().unwrap()))
+			.await
+			.json();
+		assert_eq!(body["prefetch"], serde_json::json!([]));
+		let signed = signed_stream_url(tracks[1]["id"].as_str().unwrap()).unwrap();
+		let other_track = format!(
+			"/music/{}?{}",
+			tracks[0]["id"].as_str().unwrap(),
+			signed.split_once('?').unwrap().1
+		);
+		assert_eq!(test_app.get(&other_track).await.status, StatusCode::FORBIDDEN);
+
+		let state = &test_app.app_state;
+		let host_id = test_app.user_id("seed_user_0");
+		let lobby_id = state.lobby_pool.create_lobby(&host_id, &state.db_pool).unwrap()["lobby_id"]
+			.as_str()
+			.unwrap()
+			.to_string();
+		let queue: Vec<Music> = tracks
+			.iter()
+			.take(2)
+			.map(|track| Music {
+				id: track["id"].as_str().unwrap().to_string(),
+				title: track["title"].as_str().unwrap().to_string(),
+				artist: track["artist"].as_str().unwrap().to_string(),
+				image_url: track["image_url"].as_str().unwrap().to_string(),
+				timestamp: 0.0,
+				state: MusicState::PAUSE,
+			})
+			.collect();
+		state.lobby_pool.set_queue(&lobby_id, queue).unwrap();
+		let body = test_app.get(&format!("/lobby/{lobby_id}/queue")).await.json();
+		assert_eq!(body["current"], serde_json::Value::Null);
+		assert_eq!(body["queue"].as_array().unwrap().len(), 2);
+		assert_eq!(body["prefetch"], serde_json::json!([]));
+	}
 }
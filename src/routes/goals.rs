@@ -0,0 +1,304 @@
+use crate::core::app_state::AppState;
+use crate::core::goals::{progress, GoalKind, GoalPeriod};
+use crate::lobic_db::models::ListeningGoal;
+use crate::schema::listening_goals;
+use crate::utils::auth::require_user;
+use crate::utils::list::ListResponse;
+use crate::utils::response::{db_error, json_response};
+
+use axum::{
+	extract::{Path, Query, State},
+	http::status::StatusCode,
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{Local, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct NewGoalPayload {
+	pub kind: String, // plays | minutes | new_artists | new_tracks
+	pub target: i32,
+	pub period: GoalPeriod, // the current week, month or year
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GoalsQuery {
+	pub status: Option<String>, // active | completed | expired, all of them when not given
+}
+
+#[derive(Debug, Serialize)]
+pub struct GoalProgress {
+	#[serde(flatten)]
+	pub goal: ListeningGoal,
+	pub status: &'static str,
+	pub percent: i32,
+}
+
+impl GoalProgress {
+	fn new(goal: ListeningGoal) -> GoalProgress {
+		let today = Local::now().date_naive().to_string();
+		let status = match goal.completed_date_time {
+			Some(_) => "completed",
+			None if goal.period_end < today => "expired",
+			None => "active",
+		};
+		GoalProgress {
+			percent: (goal.progress as i64 * 100 / goal.target.max(1) as i64).min(100) as i32,
+			status,
+			goal,
+		}
+	}
+}
+
+// :create_goal
+// The plays already made in the period count, a goal set in june starts where the year is at
+pub async fn create_goal(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<NewGoalPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	let Some(kind) = GoalKind::parse(&payload.kind) else {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid goal kind: {}", payload.kind))
+			.unwrap();
+	};
+	if payload.target <= 0 {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body("The target has to be above 0".to_string())
+			.unwrap();
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+
+	let (period_start, period_end) = payload.period.around(Local::now().date_naive());
+	let mut goal = ListeningGoal {
+		goal_id: Uuid::new_v4().to_string(),
+		user_id,
+		kind: kind.as_str().to_string(),
+		target: payload.target,
+		period_start: period_start.to_string(),
+		period_end: period_end.to_string(),
+		progress: 0,
+		completed_date_time: None,
+		created_date_time: Utc::now().to_rfc3339(),
+	};
+	goal.progress = match progress(&goal, &mut db_conn) {
+		Ok(curr_progress) => curr_progress.min(goal.target),
+		Err(err) => return db_error(err),
+	};
+	if goal.progress >= goal.target {
+		goal.completed_date_time = Some(goal.created_date_time.clone());
+	}
+
+	match diesel::insert_into(listening_goals::table)
+		.values(&goal)
+		.execute(&mut db_conn)
+	{
+		Ok(_) => json_response(StatusCode::OK, &GoalProgress::new(goal)),
+		Err(err) => db_error(err),
+	}
+}
+
+// :get_goals
+// The user's own goals, the ones ending last first
+pub async fn get_goals(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(params): Query<GoalsQuery>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	if let Some(status) = params
+		.status
+		.as_deref()
+		.filter(|status| !["active", "completed", "expired"].contains(status))
+	{
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid status: {}", status))
+			.unwrap();
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	let goals = match listening_goals::table
+		.filter(listening_goals::user_id.eq(&user_id))
+		.order((
+			listening_goals::period_end.desc(),
+			listening_goals::created_date_time.desc(),
+		))
+		.load::<ListeningGoal>(&mut db_conn)
+	{
+		Ok(goals) => goals,
+		Err(err) => return db_error(err),
+	};
+
+	let goals: Vec<GoalProgress> = goals
+		.into_iter()
+		.map(GoalProgress::new)
+		.filter(|goal| params.status.as_deref().is_none_or(|status| goal.status == status))
+		.collect();
+	ListResponse::all(goals).into_response()
+}
+
+// :get_goal
+pub async fn get_goal(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(goal_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	match listening_goals::table
+		.filter(listening_goals::goal_id.eq(&goal_id))
+		.filter(listening_goals::user_id.eq(&user_id))
+		.first::<ListeningGoal>(&mut db_conn)
+		.optional()
+	{
+		Ok(Some(goal)) => json_response(StatusCode::OK, &GoalProgress::new(goal)),
+		Ok(None) => Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("No goal {goal_id} of yours"))
+			.unwrap(),
+		Err(err) => db_error(err),
+	}
+}
+
+// :delete_goal
+pub async fn delete_goal(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(goal_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	let deleted = diesel::delete(
+		listening_goals::table
+			.filter(listening_goals::goal_id.eq(&goal_id))
+			.filter(listening_goals::user_id.eq(&user_id)),
+	)
+	.execute(&mut db_conn);
+	match deleted {
+		Ok(0) => Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("No goal {goal_id} of yours"))
+			.unwrap(),
+		Ok(_) => Response::builder()
+			.status(StatusCode::OK)
+			.body("Goal deleted".to_string())
+			.unwrap(),
+		Err(err) => db_error(err),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::core::goals::update_progress;
+	use crate::schema::{music, notifications};
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn goals_are_reached_by_playing() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let cookies = test_app.login("seed_user_0").await;
+
+		let payload = json!({ "kind": "songs", "target": 2, "period": "week" });
+		let response = test_app
+			.request(Method::POST, "/goals/new", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+		let goal = test_app
+			.request(
+				Method::POST,
+				"/goals/new",
+				Some(json!({ "kind": "plays", "target": 1000000, "period": "year" })),
+				&cookies,
+			)
+			.await
+			.json();
+		let before = goal["progress"].as_i64().unwrap();
+		assert_eq!(goal["status"], "active");
+
+		let music_id = music::table
+			.select(music::music_id)
+			.first::<String>(&mut test_app.db_conn())
+			.unwrap();
+		let play = json!({ "user_id": user_id, "music_id": music_id });
+		test_app
+			.request(Method::POST, "/music/log_song_play", Some(play), &cookies)
+			.await;
+		let state = &test_app.app_state;
+		update_progress(&user_id, &state.db_pool, &state.user_pool).unwrap();
+		let uri = format!("/goals/get/{}", goal["goal_id"].as_str().unwrap());
+		let body = test_app.request(Method::GET, &uri, None, &cookies).await.json();
+		assert_eq!(body["progress"].as_i64().unwrap(), before + 1);
+
+		// a goal one play away
+		let target = before + 2;
+		let goal = test_app
+			.request(
+				Method::POST,
+				"/goals/new",
+				Some(json!({ "kind": "plays", "target": target, "period": "year" })),
+				&cookies,
+			)
+			.await
+			.json();
+		assert_eq!(goal["status"], "active");
+		let play = json!({ "user_id": user_id, "music_id": music_id });
+		test_app
+			.request(Method::POST, "/music/log_song_play", Some(play), &cookies)
+			.await;
+		update_progress(&user_id, &state.db_pool, &state.user_pool).unwrap();
+		let body = test_app
+			.request(Method::GET, "/goals?status=completed", None, &cookies)
+			.await
+			.json();
+		assert_eq!(body["items"][0]["goal_id"], goal["goal_id"]);
+		assert_eq!(body["items"][0]["percent"], 100);
+		let notified = notifications::table
+			.filter(notifications::user_id.eq(&user_id))
+			.filter(notifications::op_code.eq("\"GOAL_COMPLETED\""))
+			.count()
+			.get_result::<i64>(&mut test_app.db_conn())
+			.unwrap();
+		assert_eq!(notified, 1);
+	}
+}
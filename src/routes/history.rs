@@ -0,0 +1,219 @@
+use crate::config::{MAX_HISTORY_DAYS, SESSION_GAP_MINUTES};
+use crate::core::app_state::AppState;
+use crate::lobic_db::models::PlayEvent;
+use crate::schema::{music, play_events};
+use crate::utils::{auth::require_user, list::ListResponse};
+
+use axum::{
+	extract::{Query, State},
+	http::status::StatusCode,
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+
+// The event with the title and artist of the track
+pub type Play = (PlayEvent, String, String);
+
+#[derive(Debug, Deserialize)]
+pub struct SessionsQuery {
+	pub days: Option<i64>, // how far back, 30 unless asked for
+	#[serde(default)]
+	pub start_index: i64,
+	pub page_length: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionTrack {
+	pub music_id: String,
+	pub title: String,
+	pub artist: String,
+	pub played_date_time: String,
+	pub listened_secs: i64,
+}
+
+// Plays less than SESSION_GAP_MINUTES apart
+#[derive(Debug, Serialize)]
+pub struct ListeningSession {
+	pub start_date_time: String,
+	pub end_date_time: String,
+	pub devices: Vec<String>, // the most plays first, empty when the clients didn't say
+	pub track_count: usize,
+	pub listened_secs: i64,
+	pub tracks: Vec<SessionTrack>, // in the order played
+}
+
+// The plays are in the order played, the sessions come out the same way
+pub fn group_sessions(plays: Vec<Play>, gap: Duration) -> Vec<ListeningSession> {
+	let mut sessions: Vec<(DateTime<Utc>, Vec<Play>)> = Vec::new();
+	for play in plays {
+		let Ok(played) = DateTime::parse_from_rfc3339(&play.0.played_date_time) else {
+			continue;
+		};
+		let played = played.with_timezone(&Utc);
+		match sessions.last_mut() {
+			Some((last_played, session)) if played - *last_played <= gap => {
+				*last_played = played;
+				session.push(play);
+			}
+			_ => sessions.push((played, vec![play])),
+		}
+	}
+
+	sessions
+		.into_iter()
+		.map(|(_, plays)| {
+			let mut devices: Vec<(String, usize)> = Vec::new();
+			for device in plays.iter().filter_map(|(event, _, _)| event.device.as_ref()) {
+				match devices.iter_mut().find(|(name, _)| name == device) {
+					Some((_, count)) => *count += 1,
+					None => devices.push((device.clone(), 1)),
+				}
+			}
+			devices.sort_by_key(|(_, count)| Reverse(*count));
+
+			ListeningSession {
+				start_date_time: plays[0].0.played_date_time.clone(),
+				end_date_time: plays[plays.len() - 1].0.played_date_time.clone(),
+				devices: devices.into_iter().map(|(name, _)| name).collect(),
+				track_count: plays.len(),
+				listened_secs: plays.iter().map(|(event, _, _)| event.listened_secs).sum(),
+				tracks: plays
+					.into_iter()
+					.map(|(event, title, artist)| SessionTrack {
+						music_id: event.music_id,
+						title,
+						artist,
+						played_date_time: event.played_date_time,
+						listened_secs: event.listened_secs,
+					})
+					.collect(),
+			}
+		})
+		.collect()
+}
+
+// :get_listening_sessions
+// The user's own plays grouped into sessions, the latest first, so the history reads like a journal
+pub async fn get_listening_sessions(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(params): Query<SessionsQuery>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	let days = params.days.unwrap_or(30);
+	if !(1..=MAX_HISTORY_DAYS).contains(&days) {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("days goes from 1 to {MAX_HISTORY_DAYS}"))
+			.unwrap();
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+	let since = (Utc::now() - Duration::days(days)).to_rfc3339();
+	let plays = match play_events::table
+		.inner_join(music::table)
+		.filter(play_events::user_id.eq(&user_id))
+		.filter(play_events::played_date_time.ge(&since))
+		.order(play_events::played_date_time.asc())
+		.select((PlayEvent::as_select(), music::title, music::artist))
+		.load::<Play>(&mut db_conn)
+	{
+		Ok(plays) => plays,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	let mut sessions = group_sessions(plays, Duration::minutes(SESSION_GAP_MINUTES));
+	sessions.reverse();
+	let total_count = sessions.len() as i64;
+	let page: Vec<ListeningSession> = sessions
+		.into_iter()
+		.skip(params.start_index.max(0) as usize)
+		.take(params.page_length.filter(|length| *length > 0).unwrap_or(i64::MAX) as usize)
+		.collect();
+	ListResponse::page(page, total_count, params.start_index, params.page_length).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support::TestApp;
+
+	use axum::http::Method;
+	use serde_json::json;
+
+	fn play(played_date_time: &str, device: Option<&str>) -> Play {
+		let event = PlayEvent {
+			event_id: uuid::Uuid::new_v4().to_string(),
+			user_id: "listener".to_string(),
+			music_id: "track".to_string(),
+			played_date_time: played_date_time.to_string(),
+			listened_secs: 180,
+			device: device.map(str::to_string),
+		};
+		(event, "Nectar".to_string(), "Joji".to_string())
+	}
+
+	#[test]
+	fn plays_far_enough_apart_start_a_new_session() {
+		let plays = vec![
+			play("2025-05-01T20:00:00+00:00", Some("Phone")),
+			play("2025-05-01T20:03:00+00:00", Some("Laptop")),
+			play("2025-05-01T20:25:00+00:00", Some("Laptop")),
+			play("2025-05-01T22:00:00+00:00", None),
+		];
+		let sessions = group_sessions(plays, Duration::minutes(30));
+
+		assert_eq!(sessions.len(), 2);
+		assert_eq!(sessions[0].track_count, 3);
+		assert_eq!(sessions[0].listened_secs, 540);
+		assert_eq!(sessions[0].devices, vec!["Laptop", "Phone"]);
+		assert_eq!(sessions[0].end_date_time, "2025-05-01T20:25:00+00:00");
+		assert!(sessions[1].devices.is_empty());
+	}
+
+	#[tokio::test]
+	async fn the_history_is_the_users_own_latest_first() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
+		let music_id = music::table
+			.select(music::music_id)
+			.first::<String>(&mut test_app.db_conn())
+			.unwrap();
+		let payload = json!({ "user_id": test_app.user_id("seed_user_0"), "music_id": music_id, "device": "Phone" });
+		test_app.post("/music/log_song_play", payload).await;
+
+		let sessions = test_app
+			.request(Method::GET, "/history/sessions?days=1&page_length=1", None, &cookies)
+			.await
+			.json();
+		let latest = &sessions["items"][0];
+		assert_eq!(latest["devices"][0], "Phone");
+		assert_eq!(
+			latest["tracks"].as_array().unwrap().last().unwrap()["music_id"],
+			music_id
+		);
+
+		let response = test_app.get("/history/sessions").await;
+		assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+	}
+}
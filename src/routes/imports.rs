@@ -0,0 +1,333 @@
+use crate::core::app_state::AppState;
+use crate::core::curation_import;
+use crate::schema::{music, track_ratings};
+use crate::utils::{
+	auth::{require_admin, require_user},
+	list::ListResponse,
+};
+
+use axum::{
+	extract::{Path, Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+pub struct CurationImportQuery {
+	pub format: String, // navidrome, plex or itunes
+	#[serde(default)]
+	pub overwrite: bool, // the imported ratings replace the different ones given here
+	#[serde(default)]
+	pub dry_run: bool, // only the report, nothing is saved
+}
+
+#[derive(Debug, Serialize)]
+pub struct RatedTrack {
+	pub music_id: String,
+	pub title: String,
+	pub artist: String,
+	pub rating: i32,
+	pub rated_date_time: String,
+}
+
+// :get_imports
+// The running and recent library scans of this instance, newest first
+pub async fn get_imports(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	ListResponse::all(app_state.import_pool.list()).into_response()
+}
+
+// :get_import
+// Where a scan is at, for the clients that connect after it started. The IMPORT_PROGRESS events with a
+// seq up to the one here are already part of it.
+pub async fn get_import(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(import_id): Path<String>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	match app_state.import_pool.get(&import_id) {
+		Some(progress) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&progress).unwrap())
+			.unwrap(),
+		None => Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("No import {import_id}, it may have been forgotten"))
+			.unwrap(),
+	}
+}
+
+// :import_curation
+// The favorites and ratings a user exported from another server, the export file as the body. The
+// report says what matched the library here and what didn't.
+pub async fn import_curation(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(params): Query<CurationImportQuery>,
+	content: String,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	let tracks = match curation_import::parse(&params.format, &content) {
+		Ok(tracks) => tracks,
+		Err(err) => {
+			return Response::builder().status(StatusCode::BAD_REQUEST).body(err).unwrap();
+		}
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+	match curation_import::import(&mut db_conn, &user_id, tracks, params.overwrite, params.dry_run) {
+		Ok(report) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&report).unwrap())
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap(),
+	}
+}
+
+// :get_ratings
+// The user's own star ratings, the highest first
+pub async fn get_ratings(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+
+	match track_ratings::table
+		.inner_join(music::table)
+		.filter(track_ratings::user_id.eq(&user_id))
+		.order((track_ratings::rating.desc(), music::title.asc()))
+		.select((
+			track_ratings::music_id,
+			music::title,
+			music::artist,
+			track_ratings::rating,
+			track_ratings::rated_date_time,
+		))
+		.load::<(String, String, String, i32, String)>(&mut db_conn)
+	{
+		Ok(rows) => {
+			let total_count = rows.len() as i64;
+			ListResponse::page(rows, total_count, 0, None)
+				.map(|(music_id, title, artist, rating, rated_date_time)| RatedTrack {
+					music_id,
+					title,
+					artist,
+					rating,
+					rated_date_time,
+				})
+				.into_response()
+		}
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::core::outbox::Outbox;
+	use crate::lobic_db::models::TrackRating;
+	use crate::schema::{liked_songs, music, track_ratings, users};
+	use crate::test_support::TestApp;
+
+	use axum::extract::ws::Message;
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::{json, Value};
+	use std::time::Duration;
+
+	#[tokio::test]
+	async fn admins_follow_the_scans_live_or_late() {
+		let test_app = TestApp::seeded();
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let (admin_id, user_id) = (test_app.user_id("seed_user_1"), test_app.user_id("seed_user_2"));
+		let (admin_outbox, user_outbox) = (Outbox::new(), Outbox::new());
+		let user_pool = &test_app.app_state.user_pool;
+		user_pool.insert(&admin_id, &admin_outbox);
+		user_pool.insert(&user_id, &user_outbox);
+
+		// nothing to save in it, but the scan goes through all the same
+		let dir = std::env::temp_dir().join(format!("lobic_import_{}", uuid::Uuid::new_v4()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let path = dir.to_str().unwrap();
+		let response = test_app.post("/save_music", json!({ "path": path })).await;
+		assert_eq!(response.status, StatusCode::OK);
+		std::fs::remove_dir_all(&dir).unwrap();
+
+		let mut statuses = Vec::new();
+		while statuses.last() != Some(&json!("finished")) {
+			let Some(Message::Text(sent)) = admin_outbox.next().await else {
+				panic!("the admin wasn't told");
+			};
+			let sent: Value = serde_json::from_str(&sent).unwrap();
+			assert_eq!(sent["for"], "IMPORT_PROGRESS");
+			statuses.push(sent["value"]["status"].clone());
+		}
+		assert_eq!(
+			statuses,
+			vec![json!("discovering"), json!("processing"), json!("finished")]
+		);
+		let told = tokio::time::timeout(Duration::from_millis(100), user_outbox.next()).await;
+		assert!(told.is_err(), "only the admins are told");
+
+		let cookies = test_app.login("seed_user_1").await;
+		let imports = test_app
+			.request(Method::GET, "/admin/imports", None, &cookies)
+			.await
+			.json();
+		assert_eq!(imports["items"][0]["path"], path);
+		let uri = format!("/admin/imports/{}", imports["items"][0]["import_id"].as_str().unwrap());
+		let import = test_app.request(Method::GET, &uri, None, &cookies).await.json();
+		assert_eq!(
+			(import["discovered"].clone(), import["seq"].clone()),
+			(json!(0), json!(2))
+		);
+
+		let cookies = test_app.login("seed_user_2").await;
+		let response = test_app.request(Method::GET, &uri, None, &cookies).await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+	}
+
+	#[tokio::test]
+	async fn imported_curation_keeps_the_ratings_given_here() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
+		let user_id = test_app.user_id("seed_user_0");
+		let tracks = music::table
+			.select((music::music_id, music::title, music::artist, music::album))
+			.limit(2)
+			.load::<(String, String, String, String)>(&mut test_app.db_conn())
+			.unwrap();
+		let music_ids: Vec<&String> = tracks.iter().map(|(music_id, ..)| music_id).collect();
+		diesel::delete(liked_songs::table.filter(liked_songs::user_id.eq(&user_id)))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		diesel::insert_into(track_ratings::table)
+			.values(&TrackRating {
+				user_id: user_id.clone(),
+				music_id: music_ids[1].clone(),
+				rating: 2,
+				rated_date_time: "2025-05-01T00:00:00+00:00".to_string(),
+			})
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+
+		let song = |(_, title, artist, album): &(String, String, String, String)| json!({ "title": title.to_uppercase(), "artist": artist, "album": album });
+		let (mut first, mut second) = (song(&tracks[0]), song(&tracks[1]));
+		first["userRating"] = json!(5);
+		first["starred"] = json!("2024-01-01T00:00:00Z");
+		second["userRating"] = json!(4);
+		let export = json!({ "subsonic-response": { "starred2": { "song": [
+			first,
+			second,
+			{ "title": "Not Here", "artist": "Nobody", "starred": "2024-01-01T00:00:00Z" },
+		] } } });
+
+		let report = test_app
+			.request(
+				Method::POST,
+				"/user/import/curation?format=navidrome&dry_run=true",
+				Some(export.clone()),
+				&cookies,
+			)
+			.await
+			.json();
+		assert_eq!(
+			(report["matched"].clone(), report["not_found_count"].clone()),
+			(json!(2), json!(1))
+		);
+		assert_eq!(report["conflicts"][0]["local"], 2);
+		assert_eq!(report["favorites_added"], 1);
+		let ratings = test_app
+			.request(Method::GET, "/user/ratings", None, &cookies)
+			.await
+			.json();
+		assert_eq!(ratings["total_count"], 1, "a dry run saves nothing");
+
+		let report = test_app
+			.request(
+				Method::POST,
+				"/user/import/curation?format=navidrome",
+				Some(export.clone()),
+				&cookies,
+			)
+			.await
+			.json();
+		assert_eq!(report["ratings_set"], 1);
+		let liked = liked_songs::table
+			.filter(liked_songs::user_id.eq(&user_id))
+			.select(liked_songs::music_id)
+			.load::<String>(&mut test_app.db_conn())
+			.unwrap();
+		assert_eq!(liked, vec![music_ids[0].clone()]);
+		let ratings = test_app
+			.request(Method::GET, "/user/ratings", None, &cookies)
+			.await
+			.json();
+		assert_eq!(ratings["items"][0]["rating"], 5);
+		assert_eq!(ratings["items"][1]["rating"], 2);
+
+		test_app
+			.request(
+				Method::POST,
+				"/user/import/curation?format=navidrome&overwrite=true",
+				Some(export),
+				&cookies,
+			)
+			.await;
+		let ratings = test_app
+			.request(Method::GET, "/user/ratings", None, &cookies)
+			.await
+			.json();
+		assert_eq!(ratings["items"][1]["rating"], 4);
+
+		let response = test_app
+			.request(
+				Method::POST,
+				"/user/import/curation?format=spotify",
+				Some(json!({})),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+	}
+}
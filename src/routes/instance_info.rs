@@ -0,0 +1,18 @@
+use crate::core::app_state::AppState;
+use crate::core::instance::instance_info;
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode},
+	response::Response,
+};
+
+pub async fn get_instance_info(State(app_state): State<AppState>) -> Response<String> {
+	let info = instance_info(&app_state.db_pool);
+
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(serde_json::to_string(&info).unwrap())
+		.unwrap()
+}
@@ -0,0 +1,269 @@
+use crate::core::app_state::AppState;
+use crate::core::ip_filter::{Cidr, ClientIp};
+use crate::lobic_db::models::IpRule;
+use crate::schema::ip_rules;
+use crate::utils::auth::require_admin;
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::{Path, State},
+	http::{header, status::StatusCode},
+	response::Response,
+	Extension, Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::Deserialize;
+use uuid::Uuid;
+
+// :get_ip_rules
+pub async fn get_ip_rules(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	match ip_rules::table
+		.order(ip_rules::created_date_time.asc())
+		.load::<IpRule>(&mut db_conn)
+	{
+		Ok(rules) => ListResponse::all(rules).into_response(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap(),
+	}
+}
+
+// :add_ip_rule
+// Deny rules turn the address or range away before anything else runs, allow rules exempt part of a
+// denied range. A deny rule covering the admin's own address is refused so nobody locks themselves out.
+#[derive(Debug, Deserialize)]
+pub struct AddIpRulePayload {
+	pub cidr: String,
+	pub action: String, // allow or deny
+	pub note: Option<String>,
+}
+
+pub async fn add_ip_rule(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	client_ip: Option<Extension<ClientIp>>,
+	Json(payload): Json<AddIpRulePayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+	let Some(cidr) = Cidr::parse(&payload.cidr) else {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid address or range: {}", payload.cidr))
+			.unwrap();
+	};
+	if payload.action != IpRule::ALLOW && payload.action != IpRule::DENY {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid action, expected allow or deny: {}", payload.action))
+			.unwrap();
+	}
+	if let Some(Extension(ClientIp(ip))) = client_ip {
+		if payload.action == IpRule::DENY && cidr.contains(ip) {
+			return Response::builder()
+				.status(StatusCode::BAD_REQUEST)
+				.body(format!("{cidr} covers your own address {ip}"))
+				.unwrap();
+		}
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let rule = IpRule {
+		rule_id: Uuid::new_v4().to_string(),
+		cidr: cidr.to_string(),
+		action: payload.action,
+		note: payload.note.filter(|note| !note.trim().is_empty()),
+		created_date_time: Utc::now().to_rfc3339(),
+	};
+	let exists = ip_rules::table
+		.filter(ip_rules::cidr.eq(&rule.cidr))
+		.count()
+		.get_result::<i64>(&mut db_conn)
+		.unwrap_or(0)
+		> 0;
+	if exists {
+		return Response::builder()
+			.status(StatusCode::CONFLICT)
+			.body(format!("There already is a rule for {}", rule.cidr))
+			.unwrap();
+	}
+
+	match diesel::insert_into(ip_rules::table).values(&rule).execute(&mut db_conn) {
+		Ok(_) => Response::builder()
+			.status(StatusCode::CREATED)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&rule).unwrap())
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to add the rule: {err}"))
+			.unwrap(),
+	}
+}
+
+// :remove_ip_rule
+pub async fn remove_ip_rule(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(rule_id): Path<String>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	match diesel::delete(ip_rules::table.find(&rule_id)).execute(&mut db_conn) {
+		Ok(0) => Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("No ip rule: {rule_id}"))
+			.unwrap(),
+		Ok(_) => Response::builder()
+			.status(StatusCode::OK)
+			.body(format!("Ip rule {rule_id} removed"))
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to remove the rule: {err}"))
+			.unwrap(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::users;
+	use crate::test_support::TestApp;
+
+	use axum::http::{HeaderName, Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn denied_ranges_are_turned_away_behind_the_proxy() {
+		let test_app = TestApp::seeded();
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let admin = test_app.login("seed_user_1").await;
+		let listener = test_app.login("seed_user_0").await;
+		let forwarded = |ip| [(HeaderName::from_static("x-forwarded-for"), ip)];
+
+		// the test requests come from 127.0.0.1, a trusted proxy unless TRUSTED_PROXIES says otherwise
+		let response = test_app
+			.request(
+				Method::POST,
+				"/admin/ip_rules/add",
+				Some(json!({ "cidr": "127.0.0.0/8", "action": "deny" })),
+				&admin,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		let payload = json!({ "cidr": "203.0.113.0/24", "action": "deny", "note": "scraper" });
+		let response = test_app
+			.request(Method::POST, "/admin/ip_rules/add", Some(payload.clone()), &admin)
+			.await;
+		assert_eq!(response.status, StatusCode::CREATED);
+		let response = test_app
+			.request(Method::POST, "/admin/ip_rules/add", Some(payload), &admin)
+			.await;
+		assert_eq!(response.status, StatusCode::CONFLICT);
+		let allow = json!({ "cidr": "203.0.113.9", "action": "allow" });
+		let response = test_app
+			.request(Method::POST, "/admin/ip_rules/add", Some(allow), &admin)
+			.await;
+		assert_eq!(response.status, StatusCode::CREATED);
+
+		let response = test_app
+			.request_with_headers(
+				Method::GET,
+				"/music/get_music",
+				None,
+				&listener,
+				&forwarded("203.0.113.7"),
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+		assert_eq!(response.json()["blocked"], true);
+		let response = test_app
+			.request_with_headers(
+				Method::GET,
+				"/music/get_music",
+				None,
+				&listener,
+				&forwarded("203.0.113.9"),
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::OK);
+		// made up hops left of the real client don't help
+		let response = test_app
+			.request_with_headers(
+				Method::GET,
+				"/music/get_music",
+				None,
+				&listener,
+				&forwarded("198.51.100.1, 203.0.113.7"),
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+		let rules = test_app
+			.request(Method::GET, "/admin/ip_rules", None, &admin)
+			.await
+			.json();
+		let rule_id = rules["items"][0]["rule_id"].as_str().unwrap();
+		let uri = format!("/admin/ip_rules/remove/{rule_id}");
+		assert_eq!(
+			test_app.request(Method::POST, &uri, None, &admin).await.status,
+			StatusCode::OK
+		);
+		let response = test_app
+			.request_with_headers(
+				Method::GET,
+				"/music/get_music",
+				None,
+				&listener,
+				&forwarded("203.0.113.7"),
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::OK);
+	}
+}
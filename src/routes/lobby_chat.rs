@@ -0,0 +1,185 @@
+use crate::core::app_state::AppState;
+use crate::core::quotas::{self, Feature};
+use crate::services::LobbyService;
+use crate::utils::auth::{require_admin, require_user};
+
+use axum::{
+	extract::{Path, Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+	pub format: Option<String>, // json or text, json when left out
+}
+
+// :export_chat
+// The chat and played tracks of the lobby, for its host
+pub async fn export_chat(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(lobby_id): Path<String>,
+	Query(query): Query<ExportQuery>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let transcript = match LobbyService::new(&app_state).transcript(&lobby_id, &user_id) {
+		Ok(transcript) => transcript,
+		Err(err) => return err.into_response(),
+	};
+
+	let format = query.format.as_deref().unwrap_or("json");
+	if matches!(format, "json" | "text") {
+		let taken = match app_state.db_pool.get() {
+			Ok(mut db_conn) => quotas::take(&user_id, Feature::Export, 1, &mut db_conn),
+			Err(err) => {
+				return Response::builder()
+					.status(StatusCode::INTERNAL_SERVER_ERROR)
+					.body(format!("Failed to get DB from pool: {err}"))
+					.unwrap();
+			}
+		};
+		if let Err(err) = taken {
+			return err.into_response();
+		}
+	}
+
+	match format {
+		"json" => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&transcript).unwrap())
+			.unwrap(),
+		"text" => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+			.header(
+				header::CONTENT_DISPOSITION,
+				format!("attachment; filename=\"lobby-{lobby_id}.txt\""),
+			)
+			.body(transcript.to_text())
+			.unwrap(),
+		format => Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid format: {format}"))
+			.unwrap(),
+	}
+}
+
+// :get_chat_retention
+pub async fn get_chat_retention(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	match LobbyService::new(&app_state).chat_retention_hours() {
+		Ok(hours) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(json!({ "hours": hours }).to_string())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :set_chat_retention
+#[derive(Debug, Deserialize)]
+pub struct ChatRetentionPayload {
+	pub hours: i64,
+}
+
+pub async fn set_chat_retention(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<ChatRetentionPayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	match LobbyService::new(&app_state).set_chat_retention_hours(payload.hours) {
+		Ok(hours) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(json!({ "hours": hours }).to_string())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::config::MusicState;
+	use crate::core::lobby::Music;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn hosts_export_the_lobby_chat() {
+		let test_app = TestApp::seeded();
+		let app_state = &test_app.app_state;
+		let host_id = test_app.user_id("seed_user_0");
+		let member_id = test_app.user_id("seed_user_1");
+		let lobby = app_state.lobby_pool.create_lobby(&host_id, &app_state.db_pool).unwrap();
+		let lobby_id = lobby["lobby_id"].as_str().unwrap().to_string();
+		app_state
+			.lobby_pool
+			.join_lobby(&lobby_id, &member_id, &app_state.db_pool, &app_state.user_pool)
+			.unwrap();
+
+		let music = Music {
+			id: "track-1".to_string(),
+			title: "Resham".to_string(),
+			artist: "Nepathya".to_string(),
+			state: MusicState::PLAY,
+			..Music::new()
+		};
+		app_state
+			.lobby_pool
+			.set_music_state(&lobby_id, &host_id, music, &app_state.user_pool)
+			.unwrap();
+		app_state
+			.lobby_pool
+			.append_message(&lobby_id, &member_id, "what a tune", &app_state.db_pool)
+			.unwrap();
+
+		let uri = format!("/lobby/{lobby_id}/chat/export");
+		let cookies = test_app.login("seed_user_1").await;
+		let response = test_app.request(Method::GET, &uri, None, &cookies).await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+		let cookies = test_app.login("seed_user_0").await;
+		let body = test_app.request(Method::GET, &uri, None, &cookies).await.json();
+		assert_eq!(body["entries"][0]["kind"], "track");
+		assert_eq!(body["entries"][0]["title"], "Resham");
+		assert_eq!(body["entries"][1]["kind"], "message");
+		assert_eq!(body["entries"][1]["username"], "seed_user_1");
+		assert_eq!(body["retention_hours"], json!(24));
+
+		let response = test_app
+			.request(Method::GET, &format!("{uri}?format=text"), None, &cookies)
+			.await;
+		assert!(response.body.contains("seed_user_1: what a tune"));
+		assert!(response.body.contains("Now playing: Resham by Nepathya"));
+
+		let response = test_app
+			.request(
+				Method::POST,
+				"/admin/lobby/chat_retention",
+				Some(json!({ "hours": 1 })),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+	}
+}
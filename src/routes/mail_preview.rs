@@ -0,0 +1,128 @@
+use crate::core::app_state::AppState;
+use crate::i18n::Locale;
+use crate::mail::templates::Template;
+use crate::utils::auth::require_admin;
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::{Path, Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+use serde_json::json;
+
+// :get_mail_templates
+pub async fn get_mail_templates(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let templates: Vec<&str> = Template::ALL.iter().map(|template| template.name()).collect();
+	ListResponse::all(templates).into_response()
+}
+
+// :preview_mail
+#[derive(Debug, Deserialize)]
+pub struct MailPreviewQuery {
+	pub lang: Option<String>,   // en | ne, defaults to en
+	pub format: Option<String>, // html | text, defaults to json with both variants
+}
+
+// Renders the template with sample variables
+pub async fn preview_mail(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(name): Path<String>,
+	Query(params): Query<MailPreviewQuery>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let template = match Template::parse(&name) {
+		Some(template) => template,
+		None => {
+			return Response::builder()
+				.status(StatusCode::NOT_FOUND)
+				.body(format!("No mail template named: {}", name))
+				.unwrap();
+		}
+	};
+
+	let locale = match params.lang.as_deref().map(Locale::parse) {
+		Some(Some(locale)) => locale,
+		Some(None) => {
+			return Response::builder()
+				.status(StatusCode::BAD_REQUEST)
+				.body(format!("Unsupported language: {}", params.lang.unwrap()))
+				.unwrap();
+		}
+		None => Locale::DEFAULT,
+	};
+
+	let rendered = template.render(locale, &template.sample_vars());
+	let (content_type, body) = match params.format.as_deref() {
+		Some("html") => ("text/html; charset=utf-8", rendered.html),
+		Some("text") => ("text/plain; charset=utf-8", rendered.text),
+		_ => (
+			"application/json",
+			json!({
+				"template": template.name(),
+				"lang": locale.as_str(),
+				"subject": rendered.subject,
+				"html": rendered.html,
+				"text": rendered.text,
+			})
+			.to_string(),
+		),
+	};
+
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, content_type)
+		.body(body)
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::users;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+
+	#[tokio::test]
+	async fn admins_can_preview_templates() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_1").await;
+
+		let response = test_app
+			.request(Method::GET, "/admin/mail/preview/verification", None, &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+
+		let response = test_app
+			.request(Method::GET, "/admin/mail/preview/lobby_invite?lang=ne", None, &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+		let preview = response.json();
+		assert_eq!(preview["subject"], "dj_friend ले तपाईंलाई सँगै सुन्न निम्तो दिनुभयो");
+		assert!(preview["html"]
+			.as_str()
+			.unwrap()
+			.contains("https://lobic.example/lobby/sample"));
+
+		let response = test_app
+			.request(Method::GET, "/admin/mail/preview/newsletter", None, &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+	}
+}
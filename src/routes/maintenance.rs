@@ -0,0 +1,152 @@
+use crate::core::app_state::AppState;
+use crate::core::maintenance::{self, Maintenance};
+use crate::utils::auth::require_admin;
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::DateTime;
+use serde::Deserialize;
+use serde_json::json;
+
+// :get_maintenance
+pub async fn get_maintenance(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let current = maintenance::current(&mut db_conn);
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(json!({ "enabled": current.is_some(), "maintenance": current }).to_string())
+		.unwrap()
+}
+
+// :set_maintenance
+// Turning it on again updates the message and eta, the clients hear about every change over the socket
+#[derive(Debug, Deserialize)]
+pub struct SetMaintenancePayload {
+	pub enabled: bool,
+	#[serde(default)]
+	pub message: String, // a generic one in the user's language when left empty
+	pub eta: Option<String>, // rfc3339
+}
+
+pub async fn set_maintenance(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<SetMaintenancePayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+	if let Some(eta) = payload
+		.eta
+		.as_deref()
+		.filter(|eta| DateTime::parse_from_rfc3339(eta).is_err())
+	{
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid eta, expected an rfc3339 date time: {eta}"))
+			.unwrap();
+	}
+
+	let result = match payload.enabled {
+		true => maintenance::enable(&app_state, payload.message.trim().to_string(), payload.eta).map(Some),
+		false => maintenance::disable(&app_state).map(|_| None::<Maintenance>),
+	};
+	match result {
+		Ok(current) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(json!({ "enabled": current.is_some(), "maintenance": current }).to_string())
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(err)
+			.unwrap(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::core::outbox::Outbox;
+	use crate::schema::users;
+	use crate::test_support::TestApp;
+
+	use axum::extract::ws::Message;
+	use axum::http::{header, Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn only_admins_get_through_during_maintenance() {
+		let test_app = TestApp::seeded();
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let admin = test_app.login("seed_user_1").await;
+		let listener = test_app.login("seed_user_0").await;
+		let outbox = Outbox::new();
+		test_app
+			.app_state
+			.user_pool
+			.insert(&test_app.user_id("seed_user_0"), &outbox);
+
+		let payload = json!({ "enabled": true, "eta": "2999-01-01T00:00:00+00:00" });
+		let response = test_app
+			.request(Method::POST, "/admin/maintenance", Some(payload.clone()), &listener)
+			.await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+		let body = test_app
+			.request(Method::POST, "/admin/maintenance", Some(payload), &admin)
+			.await
+			.json();
+		assert_eq!(body["enabled"], true);
+
+		let Some(Message::Text(sent)) = outbox.next().await else {
+			panic!("the clients weren't told");
+		};
+		assert!(sent.contains("MAINTENANCE"));
+		let response = test_app.request(Method::GET, "/music/get_music", None, &listener).await;
+		assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+		assert!(response.headers.contains_key(header::RETRY_AFTER));
+		let body = response.json();
+		assert_eq!(body["eta"], "2999-01-01T00:00:00+00:00");
+		assert_eq!(
+			body["message"],
+			"Lobic is down for maintenance, it will be back shortly"
+		);
+		let response = test_app.request(Method::GET, "/music/get_music", None, &admin).await;
+		assert_eq!(response.status, StatusCode::OK);
+		assert_eq!(test_app.get("/api/capabilities").await.status, StatusCode::OK);
+
+		test_app
+			.request(
+				Method::POST,
+				"/admin/maintenance",
+				Some(json!({ "enabled": false })),
+				&admin,
+			)
+			.await;
+		let response = test_app.request(Method::GET, "/music/get_music", None, &listener).await;
+		assert_eq!(response.status, StatusCode::OK);
+	}
+}
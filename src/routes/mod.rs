@@ -1,10 +1,21 @@
 pub mod music {
+	pub mod alt_names;
+	pub mod artist_follows;
 	pub mod get_cover_image;
+	pub mod get_cover_palette;
 	pub mod get_music;
+	pub mod get_playback_info;
+	pub mod labels;
 	pub mod log_song_play;
+	pub mod lookup;
+	pub mod moods;
+	pub mod playlist_membership;
+	pub mod recommendations;
+	pub mod related_artists;
 	pub mod save_music;
 	pub mod search_music;
 	pub mod send_music;
+	pub mod sign_images;
 	pub mod recently_played {
 		pub mod get_recently_played;
 	}
@@ -25,16 +36,27 @@ pub mod music {
 		pub mod browse_albums;
 		pub mod browse_artists;
 		pub mod browse_genres;
+		pub mod browse_years;
+	}
+	pub mod availability {
+		pub mod check_availability;
+		pub mod set_availability;
 	}
 }
 pub mod playlist {
 	pub mod add_song_to_playlist;
+	pub mod clear_playlist;
 	pub mod create_new_playlist;
 	pub mod delete_playlist;
+	pub mod folders;
 	pub mod get_playlist_cover_img;
 	pub mod get_playlist_music;
 	pub mod get_users_playlists;
 	pub mod remove_song_from_playlist;
+	pub mod releases;
+	pub mod saved_searches;
+	pub mod trash;
+	pub mod undo_playlist_edit;
 	pub mod update_playlist_cover_img;
 	pub mod combined_playlist {
 		pub mod add_contributor;
@@ -43,16 +65,46 @@ pub mod playlist {
 	}
 }
 pub mod users {
+	pub mod blocked_content;
 	pub mod get_user;
 	pub mod get_user_data;
 	pub mod get_user_pfp;
+	pub mod pins;
 	pub mod add_friend;
+	pub mod anthem;
 	pub mod remove_friend;
 	pub mod get_friend;
 	pub mod search_user;
+	pub mod settings;
 	pub mod update_pfp;
 }
+pub mod stats {
+	pub mod compare_stats;
+	pub mod get_genres_over_time;
+	pub mod get_heatmap;
+	pub mod get_leaderboard;
+	pub mod get_on_this_day;
+}
+pub mod achievements;
+pub mod animated_cover;
+pub mod artist_images;
+pub mod analytics;
+pub mod audiobooks;
+pub mod capabilities;
+pub mod client_errors;
+pub mod cover_art;
 pub mod search;
+pub mod mail_preview;
+pub mod slow_queries;
+pub mod federation {
+	pub mod handshake;
+	pub mod peers;
+	pub mod remote;
+	pub mod shared_lobby;
+}
+pub mod tags;
+pub mod takedown;
+pub mod telemetry;
 pub mod auth {
 	pub mod login;
 	pub mod logout;
@@ -61,6 +113,26 @@ pub mod auth {
 	pub mod verify;
 	pub mod change_password;
 }
+pub mod doctor;
+pub mod embed;
+pub mod feed;
 pub mod get_lobby;
+pub mod goals;
+pub mod history;
+pub mod imports;
+pub mod instance_info;
+pub mod ip_rules;
+pub mod lobby_chat;
+pub mod maintenance;
 pub mod notify;
+pub mod overlay;
+pub mod oauth;
+pub mod play_rule;
+pub mod player;
+pub mod queue_snapshots;
+pub mod quotas;
+pub mod retention;
+pub mod scrobble;
 pub mod socket;
+pub mod transcode_cache;
+pub mod webhooks;
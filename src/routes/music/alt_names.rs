@@ -0,0 +1,124 @@
+use crate::core::app_state::AppState;
+use crate::services::music::AltNameInput;
+use crate::services::MusicService;
+use crate::utils::auth::require_admin;
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::{Path, State},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+
+// :get_alt_names
+pub async fn get_alt_names(State(app_state): State<AppState>, Path(music_id): Path<String>) -> Response<String> {
+	match MusicService::new(&app_state.db_pool).alt_names(&music_id) {
+		Ok(alt_names) => ListResponse::all(alt_names).into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AltNamesPayload {
+	pub music_id: String,
+	pub names: Vec<AltNameInput>, // replaces the ones the track had, the sort order tags included
+}
+
+// :set_alt_names
+// Search finds the track by any of them, like the title in its original script and romanized
+pub async fn set_alt_names(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<AltNamesPayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	match MusicService::new(&app_state.db_pool).set_alt_names(&payload.music_id, payload.names) {
+		Ok(alt_names) => ListResponse::all(alt_names).into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::{music, users};
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn tracks_are_found_by_either_script() {
+		let test_app = TestApp::seeded();
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let admin = test_app.login("seed_user_1").await;
+		let music_id = music::table
+			.select(music::music_id)
+			.first::<String>(&mut test_app.db_conn())
+			.unwrap();
+		diesel::update(music::table.find(&music_id))
+			.set((music::title.eq("夜に駆ける"), music::artist.eq("YOASOBI")))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+
+		let payload = json!({
+			"music_id": music_id,
+			"names": [
+				{ "field": "title", "name": "Yoru ni Kakeru", "kind": "romanized" },
+				{ "field": "title", "name": "Racing into the Night", "kind": "translated" },
+				{ "field": "artist", "name": "ヨアソビ", "kind": "original" },
+			],
+		});
+		let response = test_app
+			.request(
+				Method::POST,
+				"/music/alt_names/set",
+				Some(payload.clone()),
+				&test_app.login("seed_user_0").await,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+		let body = test_app
+			.request(Method::POST, "/music/alt_names/set", Some(payload), &admin)
+			.await
+			.json();
+		assert_eq!(body["total_count"], 3);
+
+		let (test_app, music_id) = (&test_app, &music_id);
+		let found = |uri: String| async move {
+			let body = test_app.get(&uri).await.json();
+			let songs = body.get("songs").or(body.get("items")).cloned().unwrap();
+			songs
+				.as_array()
+				.unwrap()
+				.iter()
+				.any(|song| song["id"] == music_id.as_str())
+		};
+		assert!(found("/search?search_category=all&search_string=kakeru".to_string()).await);
+		assert!(found("/search?search_category=title&search_string=yoru%20ni%20kakeru".to_string()).await);
+		assert!(
+			found("/search?search_category=artist&search_string=%E3%83%A8%E3%82%A2%E3%82%BD%E3%83%93".to_string())
+				.await
+		);
+		assert!(found("/search_music?search_string=racing%20into%20the%20night".to_string()).await);
+		assert!(found("/search_music?search_string=%E5%A4%9C%E3%81%AB%E9%A7%86%E3%81%91%E3%82%8B".to_string()).await);
+
+		let response = test_app
+			.request(
+				Method::POST,
+				"/music/alt_names/set",
+				Some(json!({ "music_id": music_id, "names": [{ "field": "album", "name": "x", "kind": "original" }] })),
+				&admin,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+	}
+}
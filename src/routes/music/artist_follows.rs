@@ -0,0 +1,140 @@
+use crate::core::app_state::AppState;
+use crate::lobic_db::models::{ArtistFollow, Availability};
+use crate::schema::{artist_follows, music};
+use crate::utils::auth::require_user;
+use crate::utils::list::ListResponse;
+
+use axum::{extract::State, http::status::StatusCode, response::Response, Json};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct ArtistPayload {
+	pub artist: String,
+}
+
+fn followed_response(user_id: &str, db_conn: &mut SqliteConnection) -> Response<String> {
+	match artist_follows::table
+		.filter(artist_follows::user_id.eq(user_id))
+		.order(artist_follows::followed_date_time.desc())
+		.load::<ArtistFollow>(db_conn)
+	{
+		Ok(follows) => ListResponse::all(follows).into_response(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap(),
+	}
+}
+
+// :get_followed_artists
+pub async fn get_followed_artists(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+	followed_response(&user_id, &mut db_conn)
+}
+
+// :follow_artist
+// Tracks of the artist the scanner adds from now on show up in /feed/new_releases
+pub async fn follow_artist(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<ArtistPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let known = music::table
+		.filter(music::artist.eq(&payload.artist))
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.count()
+		.get_result::<i64>(&mut db_conn)
+		.unwrap_or(0);
+	if known == 0 {
+		return Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("No artist named {}", payload.artist))
+			.unwrap();
+	}
+
+	let follow = ArtistFollow {
+		user_id: user_id.clone(),
+		artist: payload.artist,
+		followed_date_time: Utc::now().to_rfc3339(),
+	};
+	if let Err(err) = diesel::insert_or_ignore_into(artist_follows::table)
+		.values(&follow)
+		.execute(&mut db_conn)
+	{
+		return Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap();
+	}
+	followed_response(&user_id, &mut db_conn)
+}
+
+// :unfollow_artist
+pub async fn unfollow_artist(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<ArtistPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	if let Err(err) = diesel::delete(
+		artist_follows::table
+			.filter(artist_follows::user_id.eq(&user_id))
+			.filter(artist_follows::artist.eq(&payload.artist)),
+	)
+	.execute(&mut db_conn)
+	{
+		return Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap();
+	}
+	followed_response(&user_id, &mut db_conn)
+}
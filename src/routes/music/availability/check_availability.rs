@@ -0,0 +1,85 @@
+use crate::config::MUSIC_STORAGE;
+use crate::core::app_state::AppState;
+use crate::lobic_db::db::set_music_availability;
+use crate::lobic_db::models::Availability;
+use crate::schema::music;
+use crate::utils::auth::require_admin;
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use diesel::prelude::*;
+use serde::Serialize;
+use std::path::PathBuf;
+
+#[derive(Debug, Serialize)]
+pub struct CheckAvailabilityResponse {
+	pub checked: usize,
+	pub marked_missing: Vec<String>,
+	pub restored: Vec<String>,
+}
+
+// Compares the music table against the files in the storage, flagging the missing ones and
+// restoring the ones whose files came back
+pub async fn check_availability(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+
+	let entries = match music::table
+		.select((music::music_id, music::availability))
+		.load::<(String, String)>(&mut db_conn)
+	{
+		Ok(entries) => entries,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	let mut response = CheckAvailabilityResponse {
+		checked: entries.len(),
+		marked_missing: Vec::new(),
+		restored: Vec::new(),
+	};
+
+	for (curr_music_id, status) in entries {
+		let mut path = PathBuf::from(MUSIC_STORAGE);
+		path.push(format!("{}.mp3", curr_music_id));
+		let file_exists = path.exists();
+
+		let status = Availability::parse(&status);
+		if !file_exists && status == Some(Availability::Available) {
+			let reason = Some("File not found in the music storage".to_string());
+			if set_music_availability(&curr_music_id, Availability::MissingFile, reason, &mut db_conn).is_ok() {
+				response.marked_missing.push(curr_music_id);
+			}
+		} else if file_exists
+			&& status == Some(Availability::MissingFile)
+			&& set_music_availability(&curr_music_id, Availability::Available, None, &mut db_conn).is_ok()
+		{
+			response.restored.push(curr_music_id);
+		}
+	}
+
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(serde_json::to_string(&response).unwrap())
+		.unwrap()
+}
@@ -0,0 +1,72 @@
+use crate::config::{OpCode, SocketResponse};
+use crate::core::{app_state::AppState, user_pool::Topic};
+use crate::lobic_db::db::set_music_availability;
+use crate::lobic_db::models::Availability;
+use crate::utils::auth::require_admin;
+
+use axum::{extract::State, http::status::StatusCode, response::Response, Json};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct SetAvailabilityPayload {
+	pub music_id: String,
+	pub availability: Availability,
+	pub reason: Option<String>,
+}
+
+pub async fn set_availability(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<SetAvailabilityPayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+
+	// The reason only makes sense for unavailable music
+	let reason = match payload.availability {
+		Availability::Available => None,
+		_ => payload.reason,
+	};
+
+	match set_music_availability(&payload.music_id, payload.availability, reason, &mut db_conn) {
+		Ok(0) => Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("Invalid music id: {}", payload.music_id))
+			.unwrap(),
+		Ok(_) => {
+			let response = SocketResponse {
+				op_code: OpCode::OK,
+				r#for: OpCode::LIBRARY_UPDATE,
+				value: json!({ "music_id": payload.music_id, "availability": payload.availability }),
+			}
+			.to_string();
+			app_state.user_pool.broadcast(&Topic::LibraryUpdates, &response);
+
+			Response::builder()
+				.status(StatusCode::OK)
+				.body(format!(
+					"Music {} is now {}",
+					payload.music_id,
+					payload.availability.as_str()
+				))
+				.unwrap()
+		}
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to update availability: {err}"))
+			.unwrap(),
+	}
+}
@@ -1,16 +1,16 @@
 use crate::core::app_state::AppState;
+use crate::core::artwork::cover_uuid;
+use crate::lobic_db::models::{Availability, ContentType};
 use axum::{
 	extract::{Query, State},
-	http::{header, StatusCode},
+	http::StatusCode,
 	response::Response,
 };
 use diesel::dsl::*;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
-use std::hash::{Hash, Hasher};
-use uuid::Uuid;
+
+use crate::utils::list::ListResponse;
 
 #[derive(Deserialize)]
 pub struct AlbumQuery {
@@ -26,30 +26,13 @@ struct AlbumResponse {
 	image_uuid: String,
 }
 
-fn generate_image_uuid(artist: &str, album: &str) -> String {
-	let mut hasher = DefaultHasher::new();
-	artist.hash(&mut hasher);
-	album.hash(&mut hasher);
-	let hash = hasher.finish();
-	Uuid::from_u64_pair(hash, hash).to_string()
-}
-
 fn process_grouped_items(items: Vec<(String, String, i64)>) -> Vec<AlbumResponse> {
-	let mut album_map: HashMap<String, (i64, String)> = HashMap::new();
-
-	for (artist, album, count) in items {
-		let entry = album_map
-			.entry(album.clone())
-			.or_insert((0, generate_image_uuid(&artist, &album)));
-		entry.0 = count;
-	}
-
-	album_map
+	items
 		.into_iter()
-		.map(|(album, (songs_count, image_uuid))| AlbumResponse {
+		.map(|(artist, album, songs_count)| AlbumResponse {
+			image_uuid: cover_uuid(&artist, &album).to_string(),
 			album,
 			songs_count,
-			image_uuid,
 		})
 		.collect()
 }
@@ -68,14 +51,17 @@ pub async fn browse_albums(State(app_state): State<AppState>, Query(params): Que
 
 	use crate::schema::music::dsl::*;
 
-	// Modified query to count distinct music_ids for each album using a subquery
+	// Albums sharing a name are one entry, the cover comes from the first of their artists
 	let mut query = music
-		.group_by((artist, album))
+		.filter(availability.eq(Availability::Available.as_str()))
+		.filter(content_type.eq(ContentType::Music.as_str()))
+		.group_by(album)
 		.select((
-			artist,
+			sql("MIN(artist)").into_sql::<diesel::sql_types::Text>(),
 			album,
 			sql("COUNT(DISTINCT music_id)").into_sql::<diesel::sql_types::BigInt>(),
 		))
+		.order(album)
 		.into_boxed();
 
 	query = query.offset(params.start_index);
@@ -86,22 +72,26 @@ pub async fn browse_albums(State(app_state): State<AppState>, Query(params): Que
 		}
 	}
 
+	let total_query = music
+		.filter(availability.eq(Availability::Available.as_str()))
+		.filter(content_type.eq(ContentType::Music.as_str()))
+		.select(count_distinct(album));
+	let total_count = match total_query.get_result::<i64>(&mut db_conn) {
+		Ok(count) => count,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
 	let result = query
 		.load::<(String, String, i64)>(&mut db_conn)
 		.map(process_grouped_items);
 
 	match result {
-		Ok(items) => match serde_json::to_string(&items) {
-			Ok(json) => Response::builder()
-				.status(StatusCode::OK)
-				.header(header::CONTENT_TYPE, "application/json")
-				.body(json)
-				.unwrap(),
-			Err(err) => Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(format!("Failed to serialize response: {err}"))
-				.unwrap(),
-		},
+		Ok(items) => ListResponse::page(items, total_count, params.start_index, params.page_length).into_response(),
 		Err(err) => Response::builder()
 			.status(StatusCode::INTERNAL_SERVER_ERROR)
 			.body(format!("Database error: {err}"))
@@ -1,14 +1,14 @@
 use crate::core::app_state::AppState;
+use crate::core::artwork::cover_uuid;
+use crate::lobic_db::models::{Availability, ContentType};
+use crate::utils::list::ListResponse;
 use axum::{
 	extract::{Query, State},
-	http::{header, StatusCode},
+	http::StatusCode,
 	response::Response,
 };
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
-use uuid::Uuid;
 
 #[derive(Deserialize)]
 pub struct ArtistQuery {
@@ -24,14 +24,6 @@ struct ArtistsResponse {
 	image_uuids: Vec<String>,
 }
 
-fn generate_image_uuid(artist: &str, album: &str) -> String {
-	let mut hasher = DefaultHasher::new();
-	artist.hash(&mut hasher);
-	album.hash(&mut hasher);
-	let hash = hasher.finish();
-	Uuid::from_u64_pair(hash, hash).to_string()
-}
-
 fn process_grouped_items(items: Vec<(String, Vec<String>, i64)>) -> Vec<ArtistsResponse> {
 	items
 		.into_iter()
@@ -39,7 +31,7 @@ fn process_grouped_items(items: Vec<(String, Vec<String>, i64)>) -> Vec<ArtistsR
 			let image_uuids: Vec<String> = albums
 				.iter()
 				.take(4)
-				.map(|album| generate_image_uuid(&artist, album))
+				.map(|album| cover_uuid(&artist, album).to_string())
 				.collect();
 
 			ArtistsResponse {
@@ -67,7 +59,10 @@ pub async fn browse_artists(State(app_state): State<AppState>, Query(params): Qu
 	use diesel::dsl::sql;
 
 	let mut query = music
+		.filter(availability.eq(Availability::Available.as_str()))
+		.filter(content_type.eq(ContentType::Music.as_str()))
 		.group_by(artist)
+		.order(artist)
 		.select((
 			artist,
 			sql("GROUP_CONCAT(DISTINCT album)").into_sql::<diesel::sql_types::Text>(),
@@ -83,6 +78,20 @@ pub async fn browse_artists(State(app_state): State<AppState>, Query(params): Qu
 		}
 	}
 
+	let total_query = music
+		.filter(availability.eq(Availability::Available.as_str()))
+		.filter(content_type.eq(ContentType::Music.as_str()))
+		.select(diesel::dsl::count_distinct(artist));
+	let total_count = match total_query.get_result::<i64>(&mut db_conn) {
+		Ok(count) => count,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
 	let result = query
 		.load::<(String, String, i64)>(&mut db_conn)
 		.map(|items| {
@@ -98,20 +107,59 @@ pub async fn browse_artists(State(app_state): State<AppState>, Query(params): Qu
 		.map(process_grouped_items);
 
 	match result {
-		Ok(items) => match serde_json::to_string(&items) {
-			Ok(json) => Response::builder()
-				.status(StatusCode::OK)
-				.header(header::CONTENT_TYPE, "application/json")
-				.body(json)
-				.unwrap(),
-			Err(err) => Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(format!("Failed to serialize response: {err}"))
-				.unwrap(),
-		},
+		Ok(items) => ListResponse::page(items, total_count, params.start_index, params.page_length).into_response(),
 		Err(err) => Response::builder()
 			.status(StatusCode::INTERNAL_SERVER_ERROR)
 			.body(format!("Database error: {err}"))
 			.unwrap(),
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::music;
+	use crate::test_support::TestApp;
+
+	use axum::http::StatusCode;
+	use diesel::prelude::*;
+
+	#[tokio::test]
+	async fn counts_every_available_track_once() {
+		let test_app = TestApp::seeded();
+
+		let response = test_app.get("/music/browse_artists").await;
+		assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+		let artists = response.json()["items"].clone();
+		let songs: i64 = artists
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|artist| artist["songs_count"].as_i64().unwrap())
+			.sum();
+		let tracks = music::table.count().get_result::<i64>(&mut test_app.db_conn()).unwrap();
+		assert_eq!(songs, tracks);
+		assert!(artists
+			.as_array()
+			.unwrap()
+			.iter()
+			.all(|artist| artist["image_uuids"].as_array().unwrap().len() <= 4));
+	}
+
+	#[tokio::test]
+	async fn respects_the_page_length() {
+		let test_app = TestApp::seeded();
+
+		let all = test_app.get("/music/browse_artists").await.json();
+		let response = test_app.get("/music/browse_artists?start_index=1&page_length=2").await;
+		assert_eq!(response.status, StatusCode::OK);
+
+		let page = response.json();
+		assert_eq!(page["items"].as_array().unwrap().len(), 2);
+		assert_eq!(page["items"][0]["artist"], all["items"][1]["artist"]);
+		assert_eq!(page["total_count"], all["items"].as_array().unwrap().len());
+		assert_eq!(page["start_index"], 1);
+		assert_eq!(page["page_length"], 2);
+		assert_eq!(page["next"], 3);
+	}
+}
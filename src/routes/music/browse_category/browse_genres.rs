@@ -1,9 +1,13 @@
 use crate::core::app_state::AppState;
+use crate::i18n::Locale;
+use crate::lobic_db::models::{Availability, ContentType};
+use crate::utils::list::ListResponse;
 use axum::{
 	extract::{Query, State},
-	http::{header, StatusCode},
+	http::{HeaderMap, StatusCode},
 	response::Response,
 };
+use axum_extra::extract::cookie::CookieJar;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -17,10 +21,17 @@ pub struct GenreQuery {
 #[derive(Serialize)]
 struct GenreResult {
 	genre: String,
+	label: String, // in the user's language, the genre is what the track lists filter on
 	song_count: i64,
 }
 
-pub async fn browse_genres(State(app_state): State<AppState>, Query(params): Query<GenreQuery>) -> Response<String> {
+pub async fn browse_genres(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	jar: CookieJar,
+	Query(params): Query<GenreQuery>,
+) -> Response<String> {
+	let locale = Locale::negotiate(&headers, &jar, &app_state.db_pool);
 	let mut db_conn = match app_state.db_pool.get() {
 		Ok(conn) => conn,
 		Err(err) => {
@@ -32,11 +43,16 @@ pub async fn browse_genres(State(app_state): State<AppState>, Query(params): Que
 		}
 	};
 
-	use crate::schema::music::dsl::*;
+	use crate::schema::{music, track_genres};
 
-	let mut query = music
-		.group_by(genre)
-		.select((genre, diesel::dsl::count(music_id)))
+	// Tracks are counted under every genre they have
+	let mut query = track_genres::table
+		.inner_join(music::table)
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.filter(music::content_type.eq(ContentType::Music.as_str()))
+		.group_by(track_genres::genre)
+		.order(track_genres::genre)
+		.select((track_genres::genre, diesel::dsl::count(track_genres::music_id)))
 		.into_boxed();
 	query = query.offset(params.start_index);
 
@@ -46,6 +62,21 @@ pub async fn browse_genres(State(app_state): State<AppState>, Query(params): Que
 		}
 	}
 
+	let total_query = track_genres::table
+		.inner_join(music::table)
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.filter(music::content_type.eq(ContentType::Music.as_str()))
+		.select(diesel::dsl::count_distinct(track_genres::genre));
+	let total_count = match total_query.get_result::<i64>(&mut db_conn) {
+		Ok(count) => count,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
 	let result = query.load::<(String, i64)>(&mut db_conn);
 
 	match result {
@@ -53,17 +84,13 @@ pub async fn browse_genres(State(app_state): State<AppState>, Query(params): Que
 			let category_results: Vec<GenreResult> = items
 				.into_iter()
 				.map(|(_genre, song_count)| GenreResult {
+					label: locale.genre_label(&_genre),
 					genre: _genre,
 					song_count,
 				})
 				.collect();
 
-			let response = serde_json::to_string(&category_results).unwrap();
-			Response::builder()
-				.status(StatusCode::OK)
-				.header(header::CONTENT_TYPE, "application/json")
-				.body(response)
-				.unwrap()
+			ListResponse::page(category_results, total_count, params.start_index, params.page_length).into_response()
 		}
 		Err(err) => Response::builder()
 			.status(StatusCode::INTERNAL_SERVER_ERROR)
@@ -71,3 +98,23 @@ pub async fn browse_genres(State(app_state): State<AppState>, Query(params): Que
 			.unwrap(),
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use crate::test_support::TestApp;
+
+	#[tokio::test]
+	async fn tracks_count_under_every_genre() {
+		let test_app = TestApp::seeded();
+
+		let body = test_app.get("/music/browse_genres").await.json();
+		let song_count: i64 = body["items"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|item| item["song_count"].as_i64().unwrap())
+			.sum();
+		// 40 tracks, every third of them with a second genre
+		assert_eq!(song_count, 40 + 14);
+	}
+}
@@ -0,0 +1,118 @@
+use crate::core::app_state::AppState;
+use crate::lobic_db::models::{Availability, ContentType};
+use crate::schema::music;
+use crate::utils::list::ListResponse;
+use axum::{
+	extract::{Query, State},
+	http::StatusCode,
+	response::Response,
+};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct YearQuery {
+	#[serde(default)]
+	start_index: i64, // in decades
+	page_length: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct YearResult {
+	year: i32,
+	song_count: i64,
+}
+
+#[derive(Serialize)]
+struct DecadeResult {
+	decade: i32, // its first year, 1990 for the 90s
+	song_count: i64,
+	years: Vec<YearResult>,
+}
+
+// Decades with the songs released in each of their years, the latest first
+pub async fn browse_years(State(app_state): State<AppState>, Query(params): Query<YearQuery>) -> Response<String> {
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let years = music::table
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.filter(music::content_type.eq(ContentType::Music.as_str()))
+		.filter(music::release_year.is_not_null())
+		.group_by(music::release_year)
+		.order(music::release_year.desc())
+		.select((
+			music::release_year.assume_not_null(),
+			diesel::dsl::count(music::music_id),
+		))
+		.load::<(i32, i64)>(&mut db_conn);
+	let years = match years {
+		Ok(years) => years,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	let mut decades: Vec<DecadeResult> = Vec::new();
+	for (year, song_count) in years {
+		let decade = year - year % 10;
+		if decades.last().is_none_or(|last| last.decade != decade) {
+			decades.push(DecadeResult {
+				decade,
+				song_count: 0,
+				years: Vec::new(),
+			});
+		}
+		let last = decades.last_mut().unwrap();
+		last.song_count += song_count;
+		last.years.push(YearResult { year, song_count });
+	}
+
+	let total_count = decades.len() as i64;
+	let page: Vec<DecadeResult> = decades
+		.into_iter()
+		.skip(params.start_index.max(0) as usize)
+		.take(params.page_length.filter(|length| *length > 0).unwrap_or(i64::MAX) as usize)
+		.collect();
+	ListResponse::page(page, total_count, params.start_index, params.page_length).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::test_support::TestApp;
+
+	#[tokio::test]
+	async fn years_are_grouped_into_decades() {
+		let test_app = TestApp::seeded();
+
+		let body = test_app.get("/music/browse/years").await.json();
+		let decades = body["items"].as_array().unwrap();
+		assert_eq!(decades[0]["decade"], 2020);
+		let song_count: i64 = decades
+			.iter()
+			.map(|decade| decade["song_count"].as_i64().unwrap())
+			.sum();
+		assert_eq!(song_count, 40);
+
+		let body = test_app.get("/music/get_music?decade=90s").await.json();
+		let items = body["items"].as_array().unwrap();
+		assert!(!items.is_empty());
+		assert!(items
+			.iter()
+			.all(|item| (1990..2000).contains(&item["release_year"].as_i64().unwrap())));
+
+		let response = test_app.get("/music/get_music?decade=1995").await;
+		assert_eq!(response.status, axum::http::StatusCode::BAD_REQUEST);
+	}
+}
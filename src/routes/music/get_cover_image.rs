@@ -1,63 +1,172 @@
-use crate::config::COVER_IMG_STORAGE;
+use crate::core::app_state::AppState;
+use crate::core::artwork::placeholder::{DEFAULT_SIZE, MAX_SIZE, MIN_SIZE};
+use crate::core::artwork::{jpeg, urls};
+use crate::services::artwork::CoverSource;
+use crate::services::ArtworkService;
+
 use axum::{
-	extract::Path,
+	extract::{Path, Query, State},
 	http::{header, StatusCode},
 	response::Response,
 };
-use std::path::PathBuf;
-use tokio::{fs::File, io::AsyncReadExt};
-
-pub async fn get_cover_image(Path(img_uuid): Path<String>) -> Response<axum::body::Body> {
-	let filename = format!("{img_uuid}.png");
-	let mut path = PathBuf::from(COVER_IMG_STORAGE);
-	path.push(&filename);
-
-	match File::open(&path).await {
-		Ok(mut file) => {
-			let mut file_bytes = Vec::new();
-			if let Err(_) = file.read_to_end(&mut file_bytes).await {
-				return serve_default_image().await;
+use serde::Deserialize;
+use uuid::Uuid;
+
+// Tells the clients which art they got, the fallbacks may be replaced by the album's own cover later
+const COVER_SOURCE: &str = "x-cover-source";
+
+#[derive(Debug, Deserialize)]
+pub struct CoverImageQuery {
+	pub format: Option<String>, // png or svg, only for the placeholder
+	pub size: Option<usize>,    // of the placeholder, in pixels
+	pub sig: Option<String>,    // from /images/sign, turned down once it runs out
+}
+
+pub async fn get_cover_image(
+	State(app_state): State<AppState>,
+	Path(img_uuid): Path<String>,
+	Query(params): Query<CoverImageQuery>,
+) -> Response<axum::body::Body> {
+	if params
+		.sig
+		.as_deref()
+		.is_some_and(|sig| !urls::valid_signature(&img_uuid, params.size, sig))
+	{
+		return Response::builder()
+			.status(StatusCode::FORBIDDEN)
+			.body(axum::body::Body::from("Invalid or expired signature"))
+			.unwrap();
+	}
+
+	// only ever a uuid, anything else would read outside the storage
+	let source = match Uuid::parse_str(&img_uuid) {
+		Ok(_) => {
+			let service = ArtworkService::new(&app_state.db_pool);
+			let lookup_uuid = img_uuid.clone();
+			match tokio::task::spawn_blocking(move || service.resolve_cover(&lookup_uuid)).await {
+				Ok(Ok(source)) => source,
+				Ok(Err(err)) => {
+					return Response::builder()
+						.status(err.status())
+						.body(axum::body::Body::from(err.to_string()))
+						.unwrap();
+				}
+				Err(err) => {
+					return Response::builder()
+						.status(StatusCode::INTERNAL_SERVER_ERROR)
+						.body(axum::body::Body::from(format!("Failed to find the cover: {err}")))
+						.unwrap();
+				}
 			}
+		}
+		Err(_) => CoverSource::Placeholder,
+	};
 
-			let mime_type = match path.extension().and_then(|ext| ext.to_str()) {
-				Some("jpg") | Some("jpeg") => "image/jpeg",
-				Some("png") => "image/png",
-				Some("gif") => "image/gif",
-				Some("webp") => "image/webp",
-				_ => "application/octet-stream",
+	if let Some(path) = source.path() {
+		if let Ok(file_bytes) = tokio::fs::read(path).await {
+			// saved as .png whatever they are
+			let mime_type = match file_bytes.starts_with(&jpeg::SIGNATURE) {
+				true => "image/jpeg",
+				false => "image/png",
 			};
-
-			Response::builder()
+			let mut response = Response::builder()
 				.status(StatusCode::OK)
 				.header(header::CONTENT_TYPE, mime_type)
-				.body(axum::body::Body::from(file_bytes))
-				.unwrap()
+				.header(COVER_SOURCE, source.as_str());
+			if !matches!(source, CoverSource::Cover(_)) {
+				response = response.header(header::CACHE_CONTROL, "public, max-age=3600");
+			}
+			return response.body(axum::body::Body::from(file_bytes)).unwrap();
 		}
-		Err(_) => serve_default_image().await,
 	}
-}
 
-async fn serve_default_image() -> Response<axum::body::Body> {
-	let default_path = PathBuf::from("assets/default_music_cover.png");
-	match File::open(&default_path).await {
-		Ok(mut default_file) => {
-			let mut default_bytes = Vec::new();
-			if let Err(_) = default_file.read_to_end(&mut default_bytes).await {
-				return Response::builder()
-					.status(StatusCode::INTERNAL_SERVER_ERROR)
-					.body(axum::body::Body::from("Failed to read default image file"))
-					.unwrap();
-			}
+	serve_placeholder(&app_state, img_uuid, params).await
+}
 
-			Response::builder()
-				.status(StatusCode::OK)
-				.header(header::CONTENT_TYPE, "image/png")
-				.body(axum::body::Body::from(default_bytes))
-				.unwrap()
+// No cover for it, the same album always gets the same art. Cached for a while only, a later scan may find
+// the real one.
+async fn serve_placeholder(
+	app_state: &AppState,
+	img_uuid: String,
+	params: CoverImageQuery,
+) -> Response<axum::body::Body> {
+	let service = ArtworkService::new(&app_state.db_pool);
+	let size = params.size.unwrap_or(DEFAULT_SIZE).clamp(MIN_SIZE, MAX_SIZE);
+	let svg = match params.format.as_deref() {
+		None | Some("png") => false,
+		Some("svg") => true,
+		Some(format) => {
+			return Response::builder()
+				.status(StatusCode::BAD_REQUEST)
+				.body(axum::body::Body::from(format!(
+					"Unknown format {format}, use png or svg"
+				)))
+				.unwrap();
 		}
-		Err(_) => Response::builder()
+	};
+
+	let rendered = tokio::task::spawn_blocking(move || {
+		let placeholder = service.placeholder(&img_uuid)?;
+		Ok::<_, crate::services::ServiceError>(match svg {
+			true => ("image/svg+xml", placeholder.svg(size).into_bytes()),
+			false => ("image/png", placeholder.png(size)),
+		})
+	})
+	.await;
+	match rendered {
+		Ok(Ok((mime_type, bytes))) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, mime_type)
+			.header(header::CACHE_CONTROL, "public, max-age=3600")
+			.header(COVER_SOURCE, CoverSource::Placeholder.as_str())
+			.body(axum::body::Body::from(bytes))
+			.unwrap(),
+		Ok(Err(err)) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(axum::body::Body::from(format!("Failed to render the cover: {err}")))
+			.unwrap(),
+		Err(err) => Response::builder()
 			.status(StatusCode::INTERNAL_SERVER_ERROR)
-			.body(axum::body::Body::from("Default image not found"))
+			.body(axum::body::Body::from(format!("Failed to render the cover: {err}")))
 			.unwrap(),
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use crate::core::artwork::cover_uuid;
+	use crate::schema::music;
+	use crate::test_support::TestApp;
+
+	use axum::http::{header, StatusCode};
+	use diesel::prelude::*;
+
+	#[tokio::test]
+	async fn covers_that_are_missing_get_a_placeholder() {
+		let test_app = TestApp::seeded();
+		let (artist, album) = music::table
+			.select((music::artist, music::album))
+			.first::<(String, String)>(&mut test_app.db_conn())
+			.unwrap();
+		let uri = format!("/image/{}", cover_uuid(&artist, &album));
+
+		let response = test_app.get(&format!("{uri}?size=64")).await;
+		assert_eq!(response.status, StatusCode::OK);
+		assert_eq!(response.headers[header::CONTENT_TYPE], "image/png");
+		assert_eq!(response.headers["x-cover-source"], "placeholder");
+		assert!(response.body.contains("IHDR"));
+		assert_eq!(test_app.get(&format!("{uri}?size=64")).await.body, response.body);
+
+		let response = test_app.get(&format!("{uri}?format=svg")).await;
+		assert_eq!(response.headers[header::CONTENT_TYPE], "image/svg+xml");
+		assert!(response.body.starts_with("<svg"));
+
+		// not even a uuid, still an image
+		let response = test_app.get("/image/..%2F..%2Fetc%2Fpasswd").await;
+		assert_eq!(response.status, StatusCode::OK);
+		assert_eq!(response.headers[header::CONTENT_TYPE], "image/png");
+
+		let response = test_app.get(&format!("{uri}?format=gif")).await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+	}
+}
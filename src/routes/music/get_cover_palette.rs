@@ -0,0 +1,85 @@
+use crate::core::app_state::AppState;
+use crate::core::artwork::PALETTE_SIZE;
+use crate::services::{ArtworkService, ServiceError};
+
+use axum::{
+	extract::{Path, Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct PaletteQuery {
+	pub count: Option<usize>, // defaults to 5, at most 8
+}
+
+// Dominant colors of the cover for theming the player, most common first
+pub async fn get_cover_palette(
+	State(app_state): State<AppState>,
+	Path(img_uuid): Path<String>,
+	Query(params): Query<PaletteQuery>,
+) -> Response<String> {
+	let service = ArtworkService::new(&app_state.db_pool);
+	let lookup_uuid = img_uuid.clone();
+	// the colors of the fallback for a cover that's missing, not cached as a later scan may find it
+	let palette = tokio::task::spawn_blocking(move || match service.palette(&lookup_uuid) {
+		Err(ServiceError::NotFound(_)) if Uuid::parse_str(&lookup_uuid).is_ok() => {
+			service.fallback_palette(&lookup_uuid).map(|colors| (colors, false))
+		}
+		result => result.map(|colors| (colors, true)),
+	});
+	let (colors, cached) = match palette.await {
+		Ok(Ok(palette)) => palette,
+		Ok(Err(err)) => return err.into_response(),
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to extract the palette: {err}"))
+				.unwrap();
+		}
+	};
+
+	let count = params.count.unwrap_or(5).clamp(1, PALETTE_SIZE);
+	let colors = &colors[..count.min(colors.len())];
+	let response = json!({
+		"img_uuid": img_uuid,
+		"dominant": colors.first(),
+		"colors": colors,
+	});
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.header(
+			header::CACHE_CONTROL,
+			match cached {
+				true => "public, max-age=31536000", // covers never change
+				false => "public, max-age=3600",
+			},
+		)
+		.body(response.to_string())
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::test_support::TestApp;
+
+	use axum::http::StatusCode;
+
+	#[tokio::test]
+	async fn missing_covers_get_the_placeholder_colors() {
+		let test_app = TestApp::new();
+
+		let response = test_app
+			.get("/image/3f1f0b5e-4a57-4c39-9a43-1a2b3c4d5e6f/palette")
+			.await;
+		assert_eq!(response.status, StatusCode::OK);
+		assert!(response.json()["dominant"]["hex"].is_string());
+
+		let response = test_app.get("/image/..%2F..%2Fetc/palette").await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+	}
+}
@@ -1,99 +1,75 @@
 use axum::{
 	extract::{Query, State},
-	http::{header, StatusCode},
+	http::{HeaderMap, StatusCode},
 	response::Response,
 };
-use diesel::{dsl::sql, prelude::*, sql_types::Integer};
-use serde::Deserialize;
+use axum_extra::extract::cookie::CookieJar;
+use serde_json::json;
 
 use crate::{
 	core::app_state::AppState,
-	lobic_db::models::{Music, MusicResponse},
-	schema::music::dsl::*,
+	services::{music::MusicFilter, MusicService},
+	utils::{auth::session_user_id, fields::FieldsQuery, negotiate},
 };
 
-#[derive(Deserialize)]
-pub struct MusicQuery {
-	title: Option<String>,
-	uuid: Option<String>,
-	artist: Option<String>,
-	album: Option<String>,
-	genre: Option<String>,
-	randomizer: Option<bool>,
-	#[serde(default)]
-	start_index: i64,
-	page_length: Option<i64>,
-}
-
-pub async fn get_music(State(app_state): State<AppState>, Query(params): Query<MusicQuery>) -> Response<String> {
-	let mut db_conn = match app_state.db_pool.get() {
-		Ok(conn) => conn,
-		Err(err) => {
-			let msg = format!("Failed to get DB from pool: {err}");
-			return Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(msg)
-				.unwrap();
-		}
+// An album is played through in order, so its tracks come with prefetch hints for the next one.
+// Served as xml to the clients that prefer it. Shuffles leave out what the listener blocked.
+pub async fn get_music(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	jar: CookieJar,
+	Query(mut params): Query<MusicFilter>,
+	Query(fields): Query<FieldsQuery>,
+) -> Response<String> {
+	let fields = match fields.fields() {
+		Ok(fields) => fields,
+		Err(msg) => return Response::builder().status(StatusCode::BAD_REQUEST).body(msg).unwrap(),
 	};
-
-	let mut query = music.into_boxed();
-
-	if let Some(title_val) = params.title {
-		query = query.filter(title.eq(title_val));
-	}
-	if let Some(uuid_val) = params.uuid {
-		query = query.filter(music_id.eq(uuid_val));
-	}
-	if let Some(artist_val) = params.artist {
-		query = query.filter(artist.eq(artist_val));
-	}
-	if let Some(album_val) = params.album {
-		query = query.filter(album.eq(album_val));
-	}
-	if let Some(genre_val) = params.genre {
-		query = query.filter(genre.eq(genre_val));
-	}
-	if params.randomizer.unwrap_or(false) {
-		query = query.order(sql::<Integer>("RANDOM()"));
+	params.listener_id = session_user_id(&jar);
+	let album = params.album.is_some();
+	let music_service = MusicService::new(&app_state.db_pool);
+	match music_service.find(params) {
+		Ok(list) if album => {
+			let ids: Vec<String> = list.items.iter().map(|track| track.id.clone()).collect();
+			let mut body = json!(list.map(|track| fields.project(&track)));
+			body["prefetch"] = json!(music_service.prefetch_hints(&ids));
+			negotiate::respond(&headers, StatusCode::OK, "music", &body)
+		}
+		Ok(list) => {
+			let list = list.map(|track| fields.project(&track));
+			negotiate::respond(&headers, StatusCode::OK, "music", &list)
+		}
+		Err(err) => err.into_response(),
 	}
+}
 
-	query = query.offset(params.start_index);
+#[cfg(test)]
+mod tests {
+	use crate::test_support::TestApp;
 
-	if let Some(length) = params.page_length {
-		if length > 0 {
-			query = query.limit(length);
-		}
-	}
+	use axum::http::{header, Method};
 
-	match query.load::<Music>(&mut db_conn) {
-		Ok(music_entries) => {
-			if music_entries.is_empty() {
-				return Response::builder()
-					.status(StatusCode::NOT_FOUND)
-					.body("No music entries found".to_string())
-					.unwrap();
-			}
+	#[tokio::test]
+	async fn xml_clients_get_the_same_list_as_xml() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
 
-			let responses: Vec<MusicResponse> = music_entries
-				.into_iter()
-				.map(|entry| Music::create_music_response(entry))
-				.collect();
-			match serde_json::to_string(&responses) {
-				Ok(json) => Response::builder()
-					.status(StatusCode::OK)
-					.header(header::CONTENT_TYPE, "application/json")
-					.body(json)
-					.unwrap(),
-				Err(err) => Response::builder()
-					.status(StatusCode::INTERNAL_SERVER_ERROR)
-					.body(format!("Failed to serialize response: {err}"))
-					.unwrap(),
-			}
-		}
-		Err(err) => Response::builder()
-			.status(StatusCode::INTERNAL_SERVER_ERROR)
-			.body(format!("Database error: {err}"))
-			.unwrap(),
+		let json = test_app.request(Method::GET, "/music/get_music", None, &cookies).await;
+		let total_count = json.json()["total_count"].as_i64().unwrap();
+		let xml = test_app
+			.request_with_headers(
+				Method::GET,
+				"/music/get_music",
+				None,
+				&cookies,
+				&[(header::ACCEPT, "application/xml")],
+			)
+			.await;
+		assert_eq!(xml.headers[header::CONTENT_TYPE], "application/xml");
+		assert!(xml
+			.body
+			.contains(&format!(r#"<music start_index="0" total_count="{total_count}">"#)));
+		let items = json.json()["items"].as_array().unwrap().len();
+		assert_eq!(xml.body.matches("<items ").count(), items);
 	}
 }
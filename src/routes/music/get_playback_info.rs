@@ -0,0 +1,109 @@
+use crate::core::app_state::AppState;
+use crate::services::MusicService;
+
+use axum::{
+	extract::{Path, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+
+// The track along with its stream url and chapters
+pub async fn get_playback_info(State(app_state): State<AppState>, Path(music_id): Path<String>) -> Response<String> {
+	match MusicService::new(&app_state.db_pool).playback_info(&music_id) {
+		Ok(info) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&info).unwrap())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::lobic_db::models::{Availability, ContentType, Music, MusicChapter};
+	use crate::schema::{music, music_chapters};
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	const MUSIC_ID: &str = "3f1f0b5e-4a57-4c39-9a43-1a2b3c4d5e6f";
+
+	fn insert_audiobook(test_app: &TestApp) {
+		let mut db_conn = test_app.db_conn();
+		diesel::insert_into(music::table)
+			.values(Music {
+				music_id: MUSIC_ID.to_string(),
+				artist: "Narrator".to_string(),
+				title: "Audiobook".to_string(),
+				album: "Audiobook".to_string(),
+				genre: "Spoken".to_string(),
+				times_played: 0,
+				duration: 600,
+				availability: Availability::Available.as_str().to_string(),
+				availability_reason: None,
+				uploader_id: None,
+				content_type: ContentType::Audiobook.as_str().to_string(),
+				series: None,
+				series_index: None,
+				release_year: None,
+				bpm: None,
+				musical_key: None,
+				explicit: false,
+			})
+			.execute(&mut db_conn)
+			.unwrap();
+		let chapters: Vec<MusicChapter> = [("Opening", 0, 240_000), ("Second", 240_000, 600_000)]
+			.into_iter()
+			.enumerate()
+			.map(|(index, (title, start_ms, end_ms))| MusicChapter {
+				music_id: MUSIC_ID.to_string(),
+				chapter_index: index as i32,
+				title: title.to_string(),
+				start_ms,
+				end_ms,
+			})
+			.collect();
+		diesel::insert_into(music_chapters::table)
+			.values(&chapters)
+			.execute(&mut db_conn)
+			.unwrap();
+	}
+
+	#[tokio::test]
+	async fn playback_info_lists_chapters() {
+		let test_app = TestApp::seeded();
+		insert_audiobook(&test_app);
+
+		let body = test_app.get(&format!("/music/playback_info/{MUSIC_ID}")).await.json();
+		assert_eq!(body["stream_url"], format!("/music/{MUSIC_ID}"));
+		assert_eq!(body["chapters"][1]["title"], "Second");
+		assert_eq!(body["chapters"][1]["start"], 240.0);
+
+		let response = test_app.get("/music/playback_info/nothing").await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+	}
+
+	#[tokio::test]
+	async fn resume_snaps_to_the_chapter_start() {
+		let test_app = TestApp::seeded();
+		insert_audiobook(&test_app);
+		let cookies = test_app.login("seed_user_0").await;
+
+		let heartbeat = json!({ "music_id": MUSIC_ID, "position": 250.0, "state": "PAUSE" });
+		let response = test_app
+			.request(Method::POST, "/player/heartbeat", Some(heartbeat), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::OK);
+
+		let body = test_app
+			.request(Method::GET, "/player/resume", None, &cookies)
+			.await
+			.json();
+		assert_eq!(body["position"], 250.0);
+		assert_eq!(body["resume_position"], 240.0);
+		assert_eq!(body["chapter"]["title"], "Second");
+	}
+}
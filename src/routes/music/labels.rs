@@ -0,0 +1,108 @@
+use crate::core::app_state::AppState;
+use crate::i18n::Locale;
+use crate::lobic_db::models::Mood;
+use crate::schema::track_genres;
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode, HeaderMap},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use diesel::prelude::*;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+// :get_labels
+// What to show for every genre in the library and every mood, in the user's language. The tracks keep
+// giving the genres and moods as tagged, this maps them for display.
+pub async fn get_labels(State(app_state): State<AppState>, headers: HeaderMap, jar: CookieJar) -> Response<String> {
+	let locale = Locale::negotiate(&headers, &jar, &app_state.db_pool);
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+	let genres = match track_genres::table
+		.select(track_genres::genre)
+		.distinct()
+		.load::<String>(&mut db_conn)
+	{
+		Ok(genres) => genres,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	let genres: BTreeMap<String, String> = genres
+		.into_iter()
+		.map(|genre| {
+			let label = locale.genre_label(&genre);
+			(genre, label)
+		})
+		.collect();
+	let moods: BTreeMap<&str, String> = Mood::ALL
+		.iter()
+		.map(|mood| (mood.as_str(), locale.mood_label(mood.as_str())))
+		.collect();
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.header(header::CONTENT_LANGUAGE, locale.as_str())
+		.body(json!({ "locale": locale.as_str(), "genres": genres, "moods": moods }).to_string())
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::test_support::TestApp;
+
+	use axum::http::{header, Method};
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn labels_follow_the_users_language() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
+
+		let body = test_app
+			.request(Method::GET, "/music/labels", None, &cookies)
+			.await
+			.json();
+		assert_eq!(body["locale"], "en");
+		assert_eq!(body["genres"]["Hip-Hop"], "Hip-Hop");
+		assert_eq!(body["moods"]["chill"], "Chill");
+
+		let nepali = [(header::ACCEPT_LANGUAGE, "ne-NP")];
+		let response = test_app
+			.request_with_headers(Method::GET, "/music/labels", None, &cookies, &nepali)
+			.await;
+		let body = response.json();
+		assert_eq!(body["genres"]["Hip-Hop"], "हिप-हप");
+		assert_eq!(body["moods"]["chill"], "शान्त");
+
+		// the setting wins over the header, and every list of genres says it the same way
+		let settings = json!({ "language": "en" });
+		test_app
+			.request(Method::POST, "/user/settings/update", Some(settings), &cookies)
+			.await;
+		let body = test_app
+			.request_with_headers(Method::GET, "/music/browse_genres", None, &cookies, &nepali)
+			.await
+			.json();
+		let hip_hop = body["items"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.find(|item| item["genre"] == "Hip-Hop")
+			.unwrap();
+		assert_eq!(hip_hop["label"], "Hip-Hop");
+	}
+}
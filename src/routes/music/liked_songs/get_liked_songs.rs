@@ -1,16 +1,17 @@
 use crate::{
 	core::app_state::AppState,
-	lobic_db::models::{Music, MusicResponse},
+	lobic_db::models::{Availability, Music, MusicResponse},
 };
 use axum::{
 	extract::{Query, State},
-	http::{header, StatusCode},
+	http::StatusCode,
 	response::Response,
 };
 use diesel::prelude::*;
 use serde::Deserialize;
 
 use crate::schema::{liked_songs, music};
+use crate::utils::list::ListResponse;
 
 // /music/liked_song/get?user_id=123&start_index=10&page_length=20
 // /music/liked_song/get?user_id=123&page_length=20
@@ -42,6 +43,7 @@ pub async fn get_liked_songs(
 		.filter(liked_songs::user_id.eq(&params.user_id))
 		.order(liked_songs::song_added_date_time.desc()) // Most recent first
 		.inner_join(music::table)
+		.filter(music::availability.eq(Availability::Available.as_str()))
 		.select(music::all_columns)
 		.offset(params.start_index)
 		.into_boxed();
@@ -52,31 +54,25 @@ pub async fn get_liked_songs(
 		}
 	}
 
-	match query.load::<Music>(&mut db_conn) {
-		Ok(music_entries) => {
-			if music_entries.is_empty() {
-				return Response::builder()
-					.status(StatusCode::NOT_FOUND)
-					.body("No top tracks found".to_string())
-					.unwrap();
-			}
+	let count_query = liked_songs::table
+		.filter(liked_songs::user_id.eq(&params.user_id))
+		.inner_join(music::table)
+		.filter(music::availability.eq(Availability::Available.as_str()));
 
-			let responses: Vec<MusicResponse> = music_entries
-				.into_iter()
-				.map(|entry| Music::create_music_response(entry))
-				.collect();
+	let total_count = match count_query.count().get_result::<i64>(&mut db_conn) {
+		Ok(count) => count,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
 
-			match serde_json::to_string(&responses) {
-				Ok(json) => Response::builder()
-					.status(StatusCode::OK)
-					.header(header::CONTENT_TYPE, "application/json")
-					.body(json)
-					.unwrap(),
-				Err(err) => Response::builder()
-					.status(StatusCode::INTERNAL_SERVER_ERROR)
-					.body(format!("Failed to serialize response: {err}"))
-					.unwrap(),
-			}
+	match query.load::<Music>(&mut db_conn) {
+		Ok(music_entries) => {
+			let responses: Vec<MusicResponse> = music_entries.into_iter().map(Music::create_music_response).collect();
+			ListResponse::page(responses, total_count, params.start_index, params.page_length).into_response()
 		}
 		Err(err) => Response::builder()
 			.status(StatusCode::INTERNAL_SERVER_ERROR)
@@ -1,23 +1,38 @@
 use crate::{
-	core::app_state::AppState,
-	lobic_db::models::PlayLog,
-	schema::{music, play_log},
+	core::{app_state::AppState, event_bus::Event, play_rule::PlayRule, rollups},
+	lobic_db::models::{FirstListen, PlayEvent, PlayLog},
+	schema::{first_listens, music, play_events},
+};
+use axum::{
+	extract::State,
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
 };
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use chrono::Utc;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use tracing::log::error;
+use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LogSongPlay {
 	pub user_id: String,
 	pub music_id: String,
+	pub listened_secs: Option<i64>, // the whole track when not given
+	#[serde(default)]
+	pub device: Option<String>, // shown with the listening sessions in the history
 }
 
 const MAX_RETRIES: u32 = 3;
 
 pub async fn log_song_play(State(app_state): State<AppState>, Json(payload): Json<LogSongPlay>) -> impl IntoResponse {
+	record_play(&app_state, payload).await
+}
+
+// Stores the play for the user, also used by the players reporting with a scrobble token. A listen too short for
+// the PlayRule of the instance is left out.
+pub async fn record_play(app_state: &AppState, payload: LogSongPlay) -> Response {
 	// Get database connection from pool
 	let mut db_conn = match app_state.db_pool.get() {
 		Ok(conn) => conn,
@@ -31,38 +46,61 @@ pub async fn log_song_play(State(app_state): State<AppState>, Json(payload): Jso
 		}
 	};
 
+	let play_rule = PlayRule::load(&mut db_conn);
+
 	// Retry logic for the combined transaction
 	let mut retries = 0;
 	let transaction_result = loop {
-		match db_conn.transaction::<_, diesel::result::Error, _>(|conn| {
+		// Takes the write lock up front, a deferred one would fail upgrading when another device's play got there first
+		match db_conn.immediate_transaction::<_, diesel::result::Error, _>(|conn| {
 			let curr_music_played_date_time = Utc::now().to_rfc3339();
 
-			// Create new play log entry
-			let new_play_log = PlayLog {
+			let (duration, artist) = music::table
+				.filter(music::music_id.eq(&payload.music_id))
+				.select((music::duration, music::artist))
+				.first::<(i64, String)>(conn)?;
+			let listened_secs = payload.listened_secs.unwrap_or(duration).clamp(0, duration);
+			if !play_rule.counts(listened_secs, duration) {
+				return Ok(None);
+			}
+
+			// The user's and the global play count
+			PlayLog::record(
+				&payload.user_id,
+				&payload.music_id,
+				&curr_music_played_date_time,
+				1,
+				conn,
+			)?;
+
+			// Keep the individual play around for the stats
+			let new_play_event = PlayEvent {
+				event_id: Uuid::new_v4().to_string(),
 				user_id: payload.user_id.clone(),
 				music_id: payload.music_id.clone(),
-				music_played_date_time: curr_music_played_date_time.clone(),
-				user_times_played: 1,
+				played_date_time: curr_music_played_date_time.clone(),
+				listened_secs,
+				device: payload.device.clone(),
 			};
-
-			// Update play log
-			diesel::insert_into(play_log::table)
-				.values(&new_play_log)
-				.on_conflict((play_log::user_id, play_log::music_id))
-				.do_update()
-				.set((
-					play_log::music_played_date_time.eq(curr_music_played_date_time),
-					play_log::user_times_played.eq(play_log::user_times_played + 1),
-				))
+			diesel::insert_into(play_events::table)
+				.values(&new_play_event)
 				.execute(conn)?;
+			rollups::roll_up(std::slice::from_ref(&new_play_event), conn)?;
 
-			// Update global play count
-			diesel::update(music::table)
-				.filter(music::music_id.eq(&payload.music_id))
-				.set(music::times_played.eq(music::times_played + 1))
+			// Remember the first time the track and the artist got played
+			let first_listens =
+				[("track", payload.music_id.clone()), ("artist", artist)].map(|(target_type, target_id)| FirstListen {
+					user_id: payload.user_id.clone(),
+					target_type: target_type.to_string(),
+					target_id,
+					first_played_date_time: curr_music_played_date_time.clone(),
+					notified_year: None,
+				});
+			diesel::insert_or_ignore_into(first_listens::table)
+				.values(&first_listens[..])
 				.execute(conn)?;
 
-			Ok(())
+			Ok(Some(new_play_event))
 		}) {
 			Ok(result) => break Ok(result),
 			Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::Unknown, _))
@@ -77,7 +115,21 @@ pub async fn log_song_play(State(app_state): State<AppState>, Json(payload): Jso
 	};
 
 	match transaction_result {
-		Ok(_) => (StatusCode::CREATED, "Song play logged successfully").into_response(),
+		Ok(None) => (
+			StatusCode::ACCEPTED,
+			format!(
+				"Too short to count as a play, it takes {}s and {}% of the track",
+				play_rule.min_secs, play_rule.min_percent
+			),
+		)
+			.into_response(),
+		Ok(Some(play_event)) => {
+			app_state.event_bus.publish(Event::SongPlayed {
+				user_id: play_event.user_id,
+				played_date_time: play_event.played_date_time,
+			});
+			(StatusCode::CREATED, "Song play logged successfully").into_response()
+		}
 		Err(err) => {
 			error!("Failed to log song play: {}", err);
 			(
@@ -88,3 +140,54 @@ pub async fn log_song_play(State(app_state): State<AppState>, Json(payload): Jso
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::{music, play_log};
+	use crate::test_support::TestApp;
+
+	use axum::http::StatusCode;
+	use diesel::prelude::*;
+	use futures::future::join_all;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn plays_from_several_devices_at_once_all_count() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let (music_id, times_played) = music::table
+			.select((music::music_id, music::times_played))
+			.first::<(String, i32)>(&mut test_app.db_conn())
+			.unwrap();
+		let plays_before = play_log::table
+			.filter(play_log::user_id.eq(&user_id))
+			.filter(play_log::music_id.eq(&music_id))
+			.select(play_log::user_times_played)
+			.first::<i32>(&mut test_app.db_conn())
+			.optional()
+			.unwrap()
+			.unwrap_or(0);
+
+		let plays = (0..8).map(|device| {
+			let play = json!({ "user_id": user_id, "music_id": music_id, "device": format!("device {device}") });
+			test_app.post("/music/log_song_play", play)
+		});
+		for response in join_all(plays).await {
+			assert_eq!(response.status, StatusCode::CREATED, "{}", response.body);
+		}
+
+		let plays_after = play_log::table
+			.filter(play_log::user_id.eq(&user_id))
+			.filter(play_log::music_id.eq(&music_id))
+			.select(play_log::user_times_played)
+			.first::<i32>(&mut test_app.db_conn())
+			.unwrap();
+		assert_eq!(plays_after, plays_before + 8);
+		let times_played_after = music::table
+			.find(&music_id)
+			.select(music::times_played)
+			.first::<i32>(&mut test_app.db_conn())
+			.unwrap();
+		assert_eq!(times_played_after, times_played + 8);
+	}
+}
@@ -0,0 +1,64 @@
+use crate::core::app_state::AppState;
+use crate::services::MusicService;
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct LookupPayload {
+	pub music_ids: Vec<String>,
+}
+
+// :lookup_music
+// For clients rebuilding a queue or playlist from the ids they stored
+pub async fn lookup_music(State(app_state): State<AppState>, Json(payload): Json<LookupPayload>) -> Response<String> {
+	match MusicService::new(&app_state.db_pool).lookup(&payload.music_ids) {
+		Ok(lookup) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&lookup).unwrap())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::config::MAX_LOOKUP_IDS;
+	use crate::test_support::TestApp;
+
+	use axum::http::StatusCode;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn tracks_come_back_in_the_order_asked_for() {
+		let test_app = TestApp::seeded();
+		let items = test_app.get("/music/get_music?page_length=2").await.json()["items"].clone();
+		let (first, second) = (items[0]["id"].clone(), items[1]["id"].clone());
+
+		let body = test_app
+			.post(
+				"/music/lookup",
+				json!({ "music_ids": [second, "no-such-track", first, second] }),
+			)
+			.await
+			.json();
+		let ids: Vec<_> = body["items"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|item| item["id"].clone())
+			.collect();
+		assert_eq!(ids, [second.clone(), first, second]);
+		assert_eq!(body["missing"], json!(["no-such-track"]));
+
+		let too_many = vec!["id"; MAX_LOOKUP_IDS + 1];
+		let response = test_app.post("/music/lookup", json!({ "music_ids": too_many })).await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+	}
+}
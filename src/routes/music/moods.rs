@@ -0,0 +1,129 @@
+use crate::core::app_state::AppState;
+use crate::i18n::Locale;
+use crate::lobic_db::models::Mood;
+use crate::services::music::MoodSummary;
+use crate::services::MusicService;
+use crate::utils::auth::require_admin;
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode, HeaderMap},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize)]
+pub struct LabelledMood {
+	#[serde(flatten)]
+	pub summary: MoodSummary,
+	pub label: String, // in the user's language, the radio takes the mood
+}
+
+// :get_moods
+// The moods to start a radio from, play one with /music/get_music?mood=chill&randomizer=true
+pub async fn get_moods(State(app_state): State<AppState>, headers: HeaderMap, jar: CookieJar) -> Response<String> {
+	let locale = Locale::negotiate(&headers, &jar, &app_state.db_pool);
+	match MusicService::new(&app_state.db_pool).moods() {
+		Ok(moods) => ListResponse::all(moods)
+			.map(|summary| LabelledMood {
+				label: locale.mood_label(&summary.mood),
+				summary,
+			})
+			.into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoodPayload {
+	pub music_id: String,
+	pub mood: Mood,
+	pub energy: Option<f64>, // 0 to 1, the usual energy of the mood when left out
+}
+
+// :set_mood
+pub async fn set_mood(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<MoodPayload>,
+) -> Response<String> {
+	let user_id = match require_admin(&jar, &app_state.db_pool) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let service = MusicService::new(&app_state.db_pool);
+	match service.set_mood(&payload.music_id, payload.mood, payload.energy, &user_id) {
+		Ok(tagged) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&tagged).unwrap())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::users;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn manual_moods_drive_the_radio() {
+		let test_app = TestApp::seeded();
+		let music_id = test_app.get("/music/get_music").await.json()["items"][0]["id"]
+			.as_str()
+			.unwrap()
+			.to_string();
+
+		// Seeded tracks are all in minor keys, none of them come out chill
+		let body = test_app.get("/music/get_music?mood=chill").await.json();
+		assert_eq!(body["total_count"], 0);
+		let body = test_app.get("/music/moods").await.json();
+		let counted: i64 = body["items"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|mood| mood["song_count"].as_i64().unwrap())
+			.sum();
+		assert_eq!(counted, 40);
+
+		let cookies = test_app.login("seed_user_1").await;
+		let payload = json!({ "music_id": music_id, "mood": "chill", "energy": 0.05 });
+		let response = test_app
+			.request(Method::POST, "/music/mood/set", Some(payload.clone()), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let response = test_app
+			.request(Method::POST, "/music/mood/set", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.json()["source"], "manual");
+
+		let body = test_app.get("/music/get_music?mood=chill&randomizer=true").await.json();
+		assert_eq!(body["items"][0]["id"], music_id.as_str());
+		let body = test_app.get("/music/get_music?energy_max=0.05").await.json();
+		assert_eq!(body["total_count"], 1);
+		let body = test_app.get(&format!("/music/playback_info/{music_id}")).await.json();
+		assert_eq!(body["mood"], "chill");
+
+		let response = test_app.get("/music/get_music?mood=sleepy").await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		let payload = json!({ "music_id": music_id, "mood": "chill", "energy": 2.0 });
+		let response = test_app
+			.request(Method::POST, "/music/mood/set", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+	}
+}
@@ -0,0 +1,97 @@
+use crate::core::app_state::AppState;
+use crate::services::PlaylistService;
+use crate::utils::auth::require_user;
+
+use axum::{
+	extract::{Path, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde_json::json;
+
+// :get_playlist_membership
+// Which of the session user's playlists, owned or shared with them, already have the track. For the checkmarks
+// of the add to playlist dialog, the private ones included.
+pub async fn get_playlist_membership(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(music_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	match PlaylistService::new(&app_state.db_pool).membership(&user_id, &music_id) {
+		Ok(playlists) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(json!({ "music_id": music_id, "playlists": playlists }).to_string())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::lobic_db::models::PlaylistSong;
+	use crate::schema::{music, playlist_songs, playlists};
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+
+	#[tokio::test]
+	async fn only_the_playlists_with_the_track_are_listed() {
+		let test_app = TestApp::seeded();
+		let (user_id, other_id) = (test_app.user_id("seed_user_0"), test_app.user_id("seed_user_1"));
+		let music_id = music::table
+			.select(music::music_id)
+			.order(music::music_id.asc())
+			.first::<String>(&mut test_app.db_conn())
+			.unwrap();
+		let playlist_of = |owner_id: &str| {
+			playlists::table
+				.filter(playlists::user_id.eq(owner_id))
+				.select(playlists::playlist_id)
+				.first::<String>(&mut test_app.db_conn())
+				.unwrap()
+		};
+		let (playlist_id, other_playlist_id) = (playlist_of(&user_id), playlist_of(&other_id));
+		diesel::delete(playlist_songs::table.filter(playlist_songs::music_id.eq(&music_id)))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		for (playlist_id, position) in [(&playlist_id, 1000), (&playlist_id, 1001), (&other_playlist_id, 1000)] {
+			diesel::insert_into(playlist_songs::table)
+				.values(PlaylistSong {
+					playlist_id: playlist_id.clone(),
+					music_id: music_id.clone(),
+					song_adder_id: user_id.clone(),
+					song_added_date_time: "2025-01-01T00:00:00+00:00".to_string(),
+					position,
+				})
+				.execute(&mut test_app.db_conn())
+				.unwrap();
+		}
+
+		let uri = format!("/music/{music_id}/playlist_membership");
+		let response = test_app.get(&uri).await;
+		assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+		// the playlists of the session user, whoever is asked for
+		let cookies = test_app.login("seed_user_0").await;
+		let body = test_app
+			.request(Method::GET, &format!("{uri}?user_id={other_id}"), None, &cookies)
+			.await
+			.json();
+		assert_eq!(body["playlists"].as_array().unwrap().len(), 1);
+		assert_eq!(body["playlists"][0]["playlist_id"], playlist_id.as_str());
+		assert_eq!(body["playlists"][0]["times"], 2);
+
+		let response = test_app
+			.request(Method::GET, "/music/not-a-track/playlist_membership", None, &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+	}
+}
@@ -1,11 +1,12 @@
 use crate::{
 	core::app_state::AppState,
-	lobic_db::models::{Music, MusicResponse},
+	lobic_db::models::{Availability, Music, MusicResponse},
 	schema::{music, play_log},
+	utils::list::ListResponse,
 };
 use axum::{
 	extract::{Query, State},
-	http::{header, StatusCode},
+	http::StatusCode,
 	response::Response,
 };
 use diesel::prelude::*;
@@ -40,6 +41,7 @@ pub async fn get_recently_played(
 		.filter(play_log::user_id.eq(&params.user_id))
 		.order(play_log::music_played_date_time.desc()) // Most recent first
 		.inner_join(music::table)
+		.filter(music::availability.eq(Availability::Available.as_str()))
 		.select(music::all_columns)
 		.offset(params.start_index)
 		.into_boxed();
@@ -48,31 +50,25 @@ pub async fn get_recently_played(
 			query = query.limit(length);
 		}
 	}
-	match query.load::<Music>(&mut db_conn) {
-		Ok(music_entries) => {
-			if music_entries.is_empty() {
-				return Response::builder()
-					.status(StatusCode::NOT_FOUND)
-					.body("No top tracks found".to_string())
-					.unwrap();
-			}
+	let count_query = play_log::table
+		.filter(play_log::user_id.eq(&params.user_id))
+		.inner_join(music::table)
+		.filter(music::availability.eq(Availability::Available.as_str()));
 
-			let responses: Vec<MusicResponse> = music_entries
-				.into_iter()
-				.map(|entry| Music::create_music_response(entry))
-				.collect();
+	let total_count = match count_query.count().get_result::<i64>(&mut db_conn) {
+		Ok(count) => count,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
 
-			match serde_json::to_string(&responses) {
-				Ok(json) => Response::builder()
-					.status(StatusCode::OK)
-					.header(header::CONTENT_TYPE, "application/json")
-					.body(json)
-					.unwrap(),
-				Err(err) => Response::builder()
-					.status(StatusCode::INTERNAL_SERVER_ERROR)
-					.body(format!("Failed to serialize response: {err}"))
-					.unwrap(),
-			}
+	match query.load::<Music>(&mut db_conn) {
+		Ok(music_entries) => {
+			let responses: Vec<MusicResponse> = music_entries.into_iter().map(Music::create_music_response).collect();
+			ListResponse::page(responses, total_count, params.start_index, params.page_length).into_response()
 		}
 		Err(err) => Response::builder()
 			.status(StatusCode::INTERNAL_SERVER_ERROR)
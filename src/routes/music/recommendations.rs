@@ -0,0 +1,250 @@
+use crate::core::app_state::AppState;
+use crate::core::recommendations::{self, RecommendationRequest, DEFAULT_LIMIT, MAX_LIMIT, MAX_SEEDS};
+use crate::lobic_db::models::{Music, MusicResponse};
+use crate::schema::play_log;
+use crate::utils::auth::require_user;
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::{Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// The user's most played tracks stand in for the seeds when none are given
+const DEFAULT_SEEDS: usize = 5;
+
+#[derive(Debug, Serialize)]
+pub struct RecommendedTrack {
+	pub score: f64,
+	pub music: MusicResponse,
+}
+
+// The recommender that answered and the seeds it went with next to the usual list fields
+#[derive(Debug, Serialize)]
+pub struct RecommendationsResponse {
+	pub backend: &'static str,
+	pub seeds: Vec<String>,
+	#[serde(flatten)]
+	pub recommendations: ListResponse<RecommendedTrack>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecommendationsQuery {
+	pub seeds: Option<String>, // music ids separated by commas, up to MAX_SEEDS
+	pub limit: Option<usize>,
+}
+
+// :get_recommendations
+// Tracks to go with the seeds that the user hasn't played yet, from the recommender set in RECOMMENDER
+pub async fn get_recommendations(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(query): Query<RecommendationsQuery>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+	let played = play_log::table
+		.filter(play_log::user_id.eq(&user_id))
+		.order(play_log::user_times_played.desc())
+		.select(play_log::music_id)
+		.load::<String>(&mut db_conn);
+	drop(db_conn);
+	let played = match played {
+		Ok(played) => played,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	let seeds: Vec<String> = match query.seeds {
+		Some(seeds) => seeds
+			.split(',')
+			.map(str::trim)
+			.filter(|seed| !seed.is_empty())
+			.map(str::to_string)
+			.collect(),
+		None => played.iter().take(DEFAULT_SEEDS).cloned().collect(),
+	};
+	if seeds.len() > MAX_SEEDS {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("At most {MAX_SEEDS} seeds per request"))
+			.unwrap();
+	}
+	if seeds.is_empty() {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body("Nothing to go on, give seeds or play something first".to_string())
+			.unwrap();
+	}
+
+	let request = RecommendationRequest {
+		user_id,
+		exclude: played,
+		seeds,
+		limit: query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+	};
+	match recommendations::recommend(&app_state, &request).await {
+		Ok(recommendations) => {
+			let response = RecommendationsResponse {
+				backend: recommendations.backend,
+				seeds: request.seeds,
+				recommendations: ListResponse::all(recommendations.tracks).map(|(recommendation, track)| {
+					RecommendedTrack {
+						score: recommendation.score,
+						music: Music::create_music_response(track),
+					}
+				}),
+			};
+			Response::builder()
+				.status(StatusCode::OK)
+				.header(header::CONTENT_TYPE, "application/json")
+				.body(serde_json::to_string(&response).unwrap())
+				.unwrap()
+		}
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(err)
+			.unwrap(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::core::recommendations::MAX_SEEDS;
+	use crate::schema::{music, play_log};
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn recommendations_leave_out_what_was_played() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
+		let user_id = test_app.user_id("seed_user_0");
+		let played = play_log::table
+			.filter(play_log::user_id.eq(&user_id))
+			.select(play_log::music_id)
+			.load::<String>(&mut test_app.db_conn())
+			.unwrap();
+
+		let body = test_app
+			.request(Method::GET, "/music/recommendations?limit=5", None, &cookies)
+			.await
+			.json();
+		assert_eq!(body["backend"], "co_occurrence");
+		let items = body["items"].as_array().unwrap();
+		assert!(!items.is_empty() && items.len() <= 5);
+		assert_eq!(body["total_count"], items.len());
+		assert_eq!(body["next"], serde_json::Value::Null);
+		for item in items {
+			assert!(!played.contains(&item["music"]["id"].as_str().unwrap().to_string()));
+		}
+		let scores: Vec<f64> = items.iter().map(|item| item["score"].as_f64().unwrap()).collect();
+		assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]));
+
+		// nor what was blocked
+		let (top_id, top_artist) = (&items[0]["music"]["id"], &items[1]["music"]["artist"]);
+		for payload in [
+			json!({ "target_type": "track", "target_id": top_id }),
+			json!({ "target_type": "artist", "target_id": top_artist }),
+		] {
+			test_app
+				.request(Method::POST, "/users/me/blocked_content/add", Some(payload), &cookies)
+				.await;
+		}
+		let body = test_app
+			.request(Method::GET, "/music/recommendations?limit=5", None, &cookies)
+			.await
+			.json();
+		for item in body["items"].as_array().unwrap() {
+			assert_ne!(&item["music"]["id"], top_id);
+			assert_ne!(&item["music"]["artist"], top_artist);
+		}
+
+		let seeds = vec![played[0].as_str(); MAX_SEEDS + 1].join(",");
+		let uri = format!("/music/recommendations?seeds={seeds}");
+		let response = test_app.request(Method::GET, &uri, None, &cookies).await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+		// somebody new has to say what to go with
+		diesel::delete(play_log::table.filter(play_log::user_id.eq(&user_id)))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let response = test_app
+			.request(Method::GET, "/music/recommendations", None, &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		let uri = format!("/music/recommendations?seeds={}", played[0]);
+		let body = test_app.request(Method::GET, &uri, None, &cookies).await.json();
+		assert!(body["items"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.all(|item| item["music"]["id"] != played[0].as_str()));
+	}
+
+	#[tokio::test]
+	async fn a_blocked_artist_leaves_a_full_page() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
+		let music_ids = music::table
+			.select(music::music_id)
+			.order(music::music_id.asc())
+			.limit(20)
+			.load::<String>(&mut test_app.db_conn())
+			.unwrap();
+		// the only other listener of the seed plays the blocked artist the most
+		diesel::delete(play_log::table)
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		for (index, music_id) in music_ids.iter().enumerate() {
+			if (1..=10).contains(&index) {
+				diesel::update(music::table.find(music_id))
+					.set(music::artist.eq("Blocked Band"))
+					.execute(&mut test_app.db_conn())
+					.unwrap();
+			}
+			diesel::insert_into(play_log::table)
+				.values((
+					play_log::user_id.eq(test_app.user_id("seed_user_1")),
+					play_log::music_id.eq(music_id),
+					play_log::music_played_date_time.eq("2025-05-08T09:00:00+00:00"),
+					play_log::user_times_played.eq(if (1..=10).contains(&index) { 50 } else { 1 }),
+				))
+				.execute(&mut test_app.db_conn())
+				.unwrap();
+		}
+		let payload = json!({ "target_type": "artist", "target_id": "Blocked Band" });
+		test_app
+			.request(Method::POST, "/users/me/blocked_content/add", Some(payload), &cookies)
+			.await;
+
+		let uri = format!("/music/recommendations?seeds={}&limit=5", music_ids[0]);
+		let body = test_app.request(Method::GET, &uri, None, &cookies).await.json();
+		let items = body["items"].as_array().unwrap();
+		assert_eq!(items.len(), 5);
+		assert!(items.iter().all(|item| item["music"]["artist"] != "Blocked Band"));
+	}
+}
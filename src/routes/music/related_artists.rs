@@ -0,0 +1,111 @@
+use crate::core::app_state::AppState;
+use crate::services::music::RELATED_ARTISTS_LIMIT;
+use crate::services::MusicService;
+use crate::utils::auth::session_user_id;
+
+use axum::{
+	extract::{Path, Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct RelatedArtistsQuery {
+	pub limit: Option<usize>,
+}
+
+// :get_related_artists
+// Fans also listen to, weighted by how much the listeners of both overlap
+pub async fn get_related_artists(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(artist): Path<String>,
+	Query(query): Query<RelatedArtistsQuery>,
+) -> Response<String> {
+	let limit = query.limit.unwrap_or(RELATED_ARTISTS_LIMIT);
+	let listener_id = session_user_id(&jar);
+	match MusicService::new(&app_state.db_pool).related_artists(&artist, limit, listener_id.as_deref()) {
+		Ok(related) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&related).unwrap())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::{music, play_log};
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+
+	#[tokio::test]
+	async fn artists_sharing_listeners_are_related() {
+		let test_app = TestApp::seeded();
+		let music_ids = music::table
+			.select(music::music_id)
+			.limit(3)
+			.load::<String>(&mut test_app.db_conn())
+			.unwrap();
+		for (music_id, name) in music_ids.iter().zip(["Daft Punk", "Justice", "Nobody Else"]) {
+			diesel::update(music::table.find(music_id))
+				.set(music::artist.eq(name))
+				.execute(&mut test_app.db_conn())
+				.unwrap();
+		}
+		diesel::delete(play_log::table.filter(play_log::music_id.eq_any(&music_ids)))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		for (username, music_id) in [
+			("seed_user_0", &music_ids[0]),
+			("seed_user_0", &music_ids[1]),
+			("seed_user_1", &music_ids[0]),
+			("seed_user_1", &music_ids[1]),
+			("seed_user_2", &music_ids[2]),
+		] {
+			diesel::insert_into(play_log::table)
+				.values((
+					play_log::user_id.eq(test_app.user_id(username)),
+					play_log::music_id.eq(music_id),
+					play_log::music_played_date_time.eq("2025-05-08T09:00:00+00:00"),
+					play_log::user_times_played.eq(3),
+				))
+				.execute(&mut test_app.db_conn())
+				.unwrap();
+		}
+
+		let body = test_app.get("/music/artist/Daft%20Punk/related").await.json();
+		assert_eq!(body["listeners"], 2);
+		// the seeded plays relate them to other artists too, none as much
+		let related = body["items"].as_array().unwrap();
+		assert_eq!(body["total_count"], related.len());
+		assert!(related.iter().all(|entry| entry["artist"] != "Nobody Else"));
+		assert_eq!(related[0]["artist"], "Justice");
+		assert_eq!(related[0]["weight"], 1.0);
+		assert_eq!(related[0]["shared_listeners"], 2);
+
+		// not for who blocked it
+		let cookies = test_app.login("seed_user_2").await;
+		let payload = serde_json::json!({ "target_type": "artist", "target_id": "Justice" });
+		test_app
+			.request(Method::POST, "/users/me/blocked_content/add", Some(payload), &cookies)
+			.await;
+		let body = test_app
+			.request(Method::GET, "/music/artist/Daft%20Punk/related", None, &cookies)
+			.await
+			.json();
+		assert!(body["items"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.all(|entry| entry["artist"] != "Justice"));
+
+		let response = test_app.get("/music/artist/Nobody%20At%20All/related").await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+	}
+}
@@ -1,60 +1,46 @@
-use crate::config::{COVER_IMG_STORAGE, MUSIC_STORAGE};
-use crate::core::app_state::AppState;
-use crate::lobic_db::models::Music;
-use crate::schema::music::dsl::*;
+use crate::config::{OpCode, SocketResponse};
+use crate::core::{app_state::AppState, imports, user_pool::Topic};
 
 use axum::{extract::State, http::status::StatusCode, response::Response, Json};
-use diesel::prelude::*;
-use id3::{frame::PictureType, Tag, TagLike};
 use serde::{Deserialize, Serialize};
-use std::collections::hash_map::DefaultHasher;
-use std::fs;
-use std::hash::{Hash, Hasher};
-use std::io::Write;
-use std::path::{Path, PathBuf};
-use uuid::Uuid;
-use walkdir::WalkDir;
+use serde_json::json;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MusicPath {
 	pub path: String,
+	pub uploader_id: Option<String>,
 }
 
+// The admins can follow along on the imports topic, or at /admin/imports when they connect late
 pub async fn save_music(State(app_state): State<AppState>, Json(payload): Json<MusicPath>) -> Response<String> {
-	let mut db_conn = match app_state.db_pool.get() {
-		Ok(conn) => conn,
+	let import = app_state.import_pool.start(&payload.path);
+	let scanning_state = app_state.clone();
+	let scanned =
+		tokio::task::spawn_blocking(move || imports::run(&scanning_state, &import, payload.uploader_id.as_deref()))
+			.await;
+	let report = match scanned {
+		Ok(Ok(result)) => result,
+		Ok(Err(err)) => return err.into_response(),
 		Err(err) => {
 			return Response::builder()
 				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(format!("Failed to get DB from pool: {err}"))
-				.unwrap();
+				.body(format!("The scan stopped: {err}"))
+				.unwrap()
 		}
 	};
 
-	// Convert Windows path to WSL path if needed
-	let path = normalize_path(&payload.path);
-	let path = Path::new(&path);
-
-	let mut saved_count = 0;
-	let mut errors = Vec::new();
-
-	if path.is_dir() {
-		for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-			if is_music_file(entry.path()) {
-				match process_music_file(entry.path(), &mut db_conn) {
-					Ok(_) => saved_count += 1,
-					Err(e) => errors.push(format!("{}: {}", entry.path().display(), e)),
-				}
-			}
-		}
-	} else if is_music_file(path) {
-		match process_music_file(path, &mut db_conn) {
-			Ok(_) => saved_count += 1,
-			Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+	// Letting the clients know the library has changed
+	if report.saved > 0 {
+		let response = SocketResponse {
+			op_code: OpCode::OK,
+			r#for: OpCode::LIBRARY_UPDATE,
+			value: json!({ "added": report.saved }),
 		}
+		.to_string();
+		app_state.user_pool.broadcast(&Topic::LibraryUpdates, &response);
 	}
 
-	let status = if errors.is_empty() {
+	let status = if report.errors.is_empty() {
 		StatusCode::OK
 	} else {
 		StatusCode::PARTIAL_CONTENT
@@ -64,129 +50,12 @@ pub async fn save_music(State(app_state): State<AppState>, Json(payload): Json<M
 		.status(status)
 		.body(format!(
 			"Processed {} files. {}",
-			saved_count,
-			if !errors.is_empty() {
-				format!("\nErrors: {}", errors.join("\n"))
+			report.saved,
+			if !report.errors.is_empty() {
+				format!("\nErrors: {}", report.errors.join("\n"))
 			} else {
 				String::new()
 			}
 		))
 		.unwrap()
 }
-
-fn normalize_path(path: &str) -> String {
-	if cfg!(windows) {
-		// On Windows, convert forward slashes to backslashes
-		path.replace('/', "\\")
-	} else {
-		// If running under WSL, convert Windows paths to WSL paths
-		if path.contains('\\') || path.contains(':') {
-			convert_windows_to_wsl_path(path)
-		} else {
-			path.to_string()
-		}
-	}
-}
-
-fn convert_windows_to_wsl_path(windows_path: &str) -> String {
-	let path = windows_path.replace('\\', "/");
-
-	// Check if it's a Windows-style path (e.g., C:\...)
-	if path.chars().nth(1) == Some(':') {
-		let drive_letter = path.chars().next().unwrap().to_lowercase().to_string();
-		format!("/mnt/{}/{}", drive_letter, &path[3..])
-	} else {
-		path
-	}
-}
-
-fn is_music_file(path: &Path) -> bool {
-	match path.extension() {
-		Some(ext) => matches!(
-			ext.to_str().unwrap_or("").to_lowercase().as_str(),
-			"mp3" | "m4a" | "flac" | "wav" | "ogg"
-		),
-		None => false,
-	}
-}
-
-fn process_music_file(path: &Path, db_conn: &mut SqliteConnection) -> Result<(), Box<dyn std::error::Error>> {
-	let path_str = path.to_str().ok_or("Invalid path")?;
-
-	// Read ID3 tags
-	let tag = Tag::read_from_path(path_str).unwrap_or_else(|_| Tag::new());
-
-	let curr_artist = tag.artist().unwrap_or("Unknown Artist");
-	let curr_title = tag.title().unwrap_or("Unknown Title");
-	let curr_album = tag.album().unwrap_or("Unknown Album");
-
-	let curr_music_id = generate_uuid_from_metadata(curr_artist, curr_title, curr_album);
-
-	// Create the music_db directory if it doesn't exist
-	let music_db_dir = PathBuf::from(MUSIC_STORAGE);
-	fs::create_dir_all(&music_db_dir)?;
-
-	let new_file_path = music_db_dir.join(format!("{}.mp3", curr_music_id));
-
-	// Copy the music file to the new location
-	fs::copy(path, &new_file_path)?;
-
-	let file = fs::File::open(path_str)?;
-	let duration_u64 = mp3_duration::from_file(&file)?.as_secs();
-	let curr_duration = i64::try_from(duration_u64)?; // Convert u64 to i64, will error if too large
-
-	let curr_music = Music {
-		music_id: curr_music_id.to_string(),
-		artist: curr_artist.to_string(),
-		title: curr_title.to_string(),
-		album: curr_album.to_string(),
-		genre: tag.genre().unwrap_or("Unknown Genre").to_string(),
-		times_played: 0,
-		duration: curr_duration,
-	};
-
-	extract_cover_art(path_str, &curr_artist, &curr_album)?;
-
-	diesel::insert_into(music).values(&curr_music).execute(db_conn)?;
-
-	Ok(())
-}
-
-fn extract_cover_art(mp3_path: &str, curr_artist: &str, curr_album: &str) -> Result<(), Box<dyn std::error::Error>> {
-	let tag = Tag::read_from_path(mp3_path)?;
-	let pictures: Vec<_> = tag.pictures().collect();
-
-	if let Some(picture) = pictures.iter().find(|pic| pic.picture_type == PictureType::CoverFront) {
-		// Create platform-independent path for cover_images directory
-
-		//create a uuid from artist and album name to store image
-		let mut hasher = DefaultHasher::new();
-		curr_artist.hash(&mut hasher);
-		curr_album.hash(&mut hasher);
-		let hash = hasher.finish();
-		let img_uuid = Uuid::from_u64_pair(hash, hash);
-
-		let cover_dir = PathBuf::from(COVER_IMG_STORAGE);
-		fs::create_dir_all(&cover_dir)?;
-
-		let output_path = cover_dir.join(format!("{}.png", img_uuid));
-		let mut file = fs::File::create(&output_path)?;
-		file.write_all(&picture.data)?;
-
-		Ok(())
-	} else {
-		Err("No cover art found in the MP3 file".into())
-	}
-}
-
-//assumes all mp3 have unique sets of metadata
-fn generate_uuid_from_metadata(curr_artist: &str, curr_title: &str, curr_album: &str) -> Uuid {
-	let mut hasher = DefaultHasher::new();
-	curr_artist.hash(&mut hasher);
-	curr_title.hash(&mut hasher);
-	curr_album.hash(&mut hasher);
-	let hash = hasher.finish();
-
-	// Convert the hash to a UUID
-	Uuid::from_u64_pair(hash, hash)
-}
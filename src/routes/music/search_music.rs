@@ -1,8 +1,10 @@
 use crate::core::app_state::AppState;
-use crate::lobic_db::models::Music;
+use crate::lobic_db::models::{Availability, Music, MusicAltName};
+use crate::services::music::{alt_names_by_track, name_forms};
+use crate::utils::{fields::FieldsQuery, list::ListResponse};
 use axum::{
 	extract::{Query, State},
-	http::{header, StatusCode},
+	http::StatusCode,
 	response::Response,
 };
 use diesel::prelude::*;
@@ -14,11 +16,19 @@ use strsim::jaro_winkler;
 pub struct SearchQuery {
 	search_string: String,
 	#[serde(default)]
-	start_index: usize,
-	page_length: Option<usize>,
+	start_index: i64,
+	page_length: Option<i64>,
 }
 
-pub async fn search_music(State(app_state): State<AppState>, Query(params): Query<SearchQuery>) -> Response<String> {
+pub async fn search_music(
+	State(app_state): State<AppState>,
+	Query(params): Query<SearchQuery>,
+	Query(fields): Query<FieldsQuery>,
+) -> Response<String> {
+	let fields = match fields.fields() {
+		Ok(fields) => fields,
+		Err(msg) => return Response::builder().status(StatusCode::BAD_REQUEST).body(msg).unwrap(),
+	};
 	let mut db_conn = match app_state.db_pool.get() {
 		Ok(conn) => conn,
 		Err(err) => {
@@ -33,7 +43,10 @@ pub async fn search_music(State(app_state): State<AppState>, Query(params): Quer
 	use crate::schema::music::dsl::*;
 
 	// Fetch all music entries from the database
-	let all_music = match music.load::<Music>(&mut db_conn) {
+	let all_music = match music
+		.filter(availability.eq(Availability::Available.as_str()))
+		.load::<Music>(&mut db_conn)
+	{
 		Ok(entries) => entries,
 		Err(err) => {
 			return Response::builder()
@@ -43,18 +56,33 @@ pub async fn search_music(State(app_state): State<AppState>, Query(params): Quer
 		}
 	};
 
+	// Titles and artists also match in their other forms, the best one counts
+	let alt_names = alt_names_by_track(&mut db_conn);
+	let search_lowercase = params.search_string.to_lowercase();
+	let best_score = |forms: &[&str]| {
+		forms
+			.iter()
+			.map(|form| jaro_winkler(form, &params.search_string))
+			.fold(0.0, f64::max)
+	};
+
 	// Perform fuzzy search on all fields with weighted scores
 	let search_results = all_music
 		.into_iter()
 		.map(|entry| {
+			let titles = name_forms(&entry.title, alt_names.get(&entry.music_id), MusicAltName::TITLE);
+			let artists = name_forms(&entry.artist, alt_names.get(&entry.music_id), MusicAltName::ARTIST);
+
 			// Check for exact matches in title, artist, or album
-			let exact_match = entry.title.eq_ignore_ascii_case(&params.search_string)
-				|| entry.artist.eq_ignore_ascii_case(&params.search_string)
+			let exact_match = titles
+				.iter()
+				.chain(&artists)
+				.any(|form| form.to_lowercase() == search_lowercase)
 				|| entry.album.eq_ignore_ascii_case(&params.search_string);
 
 			// Calculate similarity scores for each field
-			let title_score = jaro_winkler(&entry.title, &params.search_string);
-			let artist_score = jaro_winkler(&entry.artist, &params.search_string);
+			let title_score = best_score(&titles);
+			let artist_score = best_score(&artists);
 			let album_score = jaro_winkler(&entry.album, &params.search_string);
 			let genre_score = jaro_winkler(&entry.genre, &params.search_string);
 
@@ -77,8 +105,12 @@ pub async fn search_music(State(app_state): State<AppState>, Query(params): Quer
 						}
 					};
 
-					let artist_contains_bonus = contains_search_term(&entry.artist);
-					let title_contains_bonus = contains_search_term(&entry.title) * 0.75;
+					let artist_contains_bonus = artists
+						.iter()
+						.map(|form| contains_search_term(form))
+						.fold(0.0, f64::max);
+					let title_contains_bonus =
+						titles.iter().map(|form| contains_search_term(form)).fold(0.0, f64::max) * 0.75;
 
 					// Sum all components
 					weighted_artist
@@ -97,23 +129,14 @@ pub async fn search_music(State(app_state): State<AppState>, Query(params): Quer
 	sorted_results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
 
 	// Apply pagination
+	let total_count = sorted_results.len() as i64;
+	let page_length = params.page_length.filter(|length| *length > 0).unwrap_or(10);
 	let paginated_results = sorted_results
 		.into_iter()
-		.skip(params.start_index)
-		.take(params.page_length.unwrap_or(10))
-		.map(|(entry, _)| Music::create_music_response(entry))
+		.skip(params.start_index.max(0) as usize)
+		.take(page_length as usize)
+		.map(|(entry, _)| fields.project(&Music::create_music_response(entry)))
 		.collect::<Vec<_>>();
 
-	// Return the results as JSON
-	match serde_json::to_string(&paginated_results) {
-		Ok(json) => Response::builder()
-			.status(StatusCode::OK)
-			.header(header::CONTENT_TYPE, "application/json")
-			.body(json)
-			.unwrap(),
-		Err(err) => Response::builder()
-			.status(StatusCode::INTERNAL_SERVER_ERROR)
-			.body(format!("Failed to serialize response: {err}"))
-			.unwrap(),
-	}
+	ListResponse::page(paginated_results, total_count, params.start_index, Some(page_length)).into_response()
 }
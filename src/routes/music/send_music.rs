@@ -1,32 +1,83 @@
 use axum::{
 	body::Body,
-	extract::{Path, State},
-	http::StatusCode,
+	extract::{Path, Query, State},
+	http::{header, StatusCode},
 	response::{IntoResponse, Response},
 };
+use serde::Deserialize;
+use serde_json::json;
 use std::io;
 use std::path::PathBuf;
 use tokio::{fs::File, io::BufReader};
 use tokio_util::io::ReaderStream;
 
-use crate::config::MUSIC_STORAGE;
+use crate::config::{MUSIC_STORAGE, TRANSCODE_BITRATES};
 use crate::core::app_state::AppState;
+use crate::core::artwork::animated;
+use crate::core::transcode_cache::TranscodeCache;
+use crate::lobic_db::db::{music_availability, set_music_availability};
+use crate::lobic_db::models::Availability;
+use crate::services::music::valid_stream_signature;
 
-pub async fn send_music(Path(curr_music_id): Path<String>, State(_app_state): State<AppState>) -> impl IntoResponse {
+#[derive(Debug, Deserialize)]
+pub struct StreamParams {
+	pub sig: Option<String>,  // from a prefetch hint, turned down once it runs out
+	pub bitrate: Option<u32>, // one of TRANSCODE_BITRATES in kbps, the file as it is when left out
+}
+
+pub async fn send_music(
+	Path(curr_music_id): Path<String>,
+	Query(params): Query<StreamParams>,
+	State(app_state): State<AppState>,
+) -> impl IntoResponse {
 	// Validate music_id format first
 	if !is_valid_music_id(&curr_music_id) {
 		return (StatusCode::BAD_REQUEST, "Invalid music ID format").into_response();
 	}
+	if params
+		.sig
+		.is_some_and(|sig| !valid_stream_signature(&curr_music_id, &sig))
+	{
+		return (StatusCode::FORBIDDEN, "Invalid or expired signature").into_response();
+	}
+	if params
+		.bitrate
+		.is_some_and(|bitrate| !TRANSCODE_BITRATES.contains(&bitrate))
+	{
+		let msg = format!("The bitrate can be one of {TRANSCODE_BITRATES:?}");
+		return (StatusCode::BAD_REQUEST, msg).into_response();
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return (StatusCode::INTERNAL_SERVER_ERROR, msg).into_response();
+		}
+	};
+
+	// Refusing to stream the music which has been marked as unavailable
+	match music_availability(&curr_music_id, &mut db_conn) {
+		Some((Availability::Available, _)) => (),
+		Some((status, reason)) => return unavailable_response(&curr_music_id, status, reason),
+		None => return (StatusCode::NOT_FOUND, "Music not found").into_response(),
+	};
 
 	// Open the file
 	let mut path = PathBuf::from(MUSIC_STORAGE);
 	path.push(format!("{}.mp3", curr_music_id));
 
-	let file = match File::open(&path).await {
+	let mut file = match File::open(&path).await {
 		Ok(file) => file,
 		Err(err) => {
+			// The file has gone missing from the storage, flagging it so the listings stop showing it
+			if err.kind() == io::ErrorKind::NotFound {
+				let reason = Some("File not found in the music storage".to_string());
+				let _ = set_music_availability(&curr_music_id, Availability::MissingFile, reason.clone(), &mut db_conn);
+				return unavailable_response(&curr_music_id, Availability::MissingFile, reason);
+			}
+
 			let msg = match err.kind() {
-				io::ErrorKind::NotFound => "File not found",
 				io::ErrorKind::PermissionDenied => "Permission denied",
 				_ => "Failed to open file",
 			};
@@ -34,6 +85,23 @@ pub async fn send_music(Path(curr_music_id): Path<String>, State(_app_state): St
 		}
 	};
 
+	// The transcode from the cache, the file as it is when ffmpeg can't be run or fails on it
+	if let Some(bitrate) = params.bitrate.filter(|_| animated::available()) {
+		let music_id = curr_music_id.clone();
+		let transcoded =
+			tokio::task::spawn_blocking(move || TranscodeCache::new().get_or_transcode(&path, &music_id, bitrate))
+				.await;
+		match transcoded {
+			Ok(Ok(cached)) => {
+				if let Ok(cached) = File::open(cached).await {
+					file = cached;
+				}
+			}
+			Ok(Err(err)) => tracing::warn!("Streaming {curr_music_id} untranscoded: {err}"),
+			Err(err) => tracing::warn!("Streaming {curr_music_id} untranscoded: {err}"),
+		}
+	}
+
 	// Create a buffered reader and convert it to a stream
 	let reader = BufReader::new(file);
 	let stream = ReaderStream::new(reader);
@@ -60,3 +128,18 @@ pub async fn send_music(Path(curr_music_id): Path<String>, State(_app_state): St
 fn is_valid_music_id(id: &str) -> bool {
 	!id.is_empty() && id.len() < 100 && id.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
 }
+
+fn unavailable_response(music_id: &str, status: Availability, reason: Option<String>) -> Response {
+	let body = json!({
+		"music_id": music_id,
+		"availability": status,
+		"reason": reason,
+	})
+	.to_string();
+
+	Response::builder()
+		.status(StatusCode::GONE)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(Body::from(body))
+		.unwrap()
+}
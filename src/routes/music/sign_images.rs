@@ -0,0 +1,175 @@
+use crate::config::{IMAGE_SIZES, MAX_SIGNED_IMAGES, SIGNED_IMAGE_URL_SECS};
+use crate::core::app_state::AppState;
+use crate::core::artwork::urls;
+use crate::services::artwork::CoverSource;
+use crate::services::ArtworkService;
+use crate::utils::{auth::require_user, exp};
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct SignImagesPayload {
+	pub img_uuids: Vec<String>,
+	#[serde(default)]
+	pub sizes: Vec<usize>, // from IMAGE_SIZES, only the original when left out
+}
+
+// Only the generated art is rendered at a size, the other covers are served as they are stored
+fn sized(source: &CoverSource, sizes: &[usize]) -> Vec<usize> {
+	match source {
+		CoverSource::Placeholder => sizes.to_vec(),
+		_ => Vec::new(),
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct SignedImage {
+	pub original: String,
+	pub sizes: BTreeMap<usize, String>, // empty for the covers that only come in their original size
+	pub placeholder: bool,              // no art for it at all, the urls give the generated art
+	pub source: &'static str,           // cover, album, artist or placeholder, the art the urls give
+}
+
+// :sign_images
+// The urls of many covers at once for the grids, the same ones until the window of SIGNED_IMAGE_URL_SECS moves on
+pub async fn sign_images(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<SignImagesPayload>,
+) -> Response<String> {
+	if let Err(response) = require_user(&jar) {
+		return response;
+	}
+	if payload.img_uuids.len() > MAX_SIGNED_IMAGES {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("At most {MAX_SIGNED_IMAGES} images per request"))
+			.unwrap();
+	}
+	if let Some(size) = payload.sizes.iter().find(|size| !IMAGE_SIZES.contains(size)) {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Unknown size {size}, use one of {IMAGE_SIZES:?}"))
+			.unwrap();
+	}
+
+	let (img_uuids, invalid): (Vec<String>, Vec<String>) = payload
+		.img_uuids
+		.into_iter()
+		.partition(|img_uuid| Uuid::parse_str(img_uuid).is_ok());
+	let service = ArtworkService::new(&app_state.db_pool);
+	let resolved = tokio::task::spawn_blocking(move || {
+		img_uuids
+			.into_iter()
+			.map(|img_uuid| {
+				let source = service.resolve_cover(&img_uuid)?;
+				Ok((img_uuid, source))
+			})
+			.collect::<Result<Vec<(String, CoverSource)>, crate::services::ServiceError>>()
+	})
+	.await;
+	let resolved = match resolved {
+		Ok(Ok(resolved)) => resolved,
+		Ok(Err(err)) => return err.into_response(),
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to find the covers: {err}"))
+				.unwrap();
+		}
+	};
+
+	let mut images = BTreeMap::new();
+	for (img_uuid, source) in resolved {
+		let signed = (|| {
+			Some(SignedImage {
+				original: urls::signed_url(&img_uuid, None)?,
+				sizes: sized(&source, &payload.sizes)
+					.into_iter()
+					.map(|size| Some((size, urls::signed_url(&img_uuid, Some(size))?)))
+					.collect::<Option<_>>()?,
+				placeholder: source == CoverSource::Placeholder,
+				source: source.as_str(),
+			})
+		})();
+		let Some(signed) = signed else {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body("Failed to sign the image urls".to_string())
+				.unwrap();
+		};
+		images.insert(img_uuid, signed);
+	}
+
+	let (_, expires) = urls::window(exp::now());
+	let body = serde_json::json!({
+		"images": images,
+		"invalid": invalid,
+		"expires_date_time": DateTime::from_timestamp(expires as i64, 0).map(|time| time.to_rfc3339()),
+		"refresh_after_secs": SIGNED_IMAGE_URL_SECS,
+	});
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(body.to_string())
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support::TestApp;
+
+	use axum::http::Method;
+	use serde_json::json;
+	use std::path::PathBuf;
+
+	#[tokio::test]
+	async fn a_grid_of_covers_is_signed_at_once() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
+		let img_uuid = "3f1f0b5e-4a57-4c39-9a43-1a2b3c4d5e6f";
+		let payload = json!({ "img_uuids": [img_uuid, "../etc/passwd"], "sizes": [64, 300] });
+
+		let body = test_app
+			.request(Method::POST, "/images/sign", Some(payload.clone()), &cookies)
+			.await
+			.json();
+		assert_eq!(body["invalid"], json!(["../etc/passwd"]));
+		let image = &body["images"][img_uuid];
+		assert_eq!(image["placeholder"], true);
+		assert_eq!(image["source"], "placeholder");
+		let again = test_app
+			.request(Method::POST, "/images/sign", Some(payload), &cookies)
+			.await
+			.json();
+		assert_eq!(again["images"], body["images"]);
+
+		let small = image["sizes"]["64"].as_str().unwrap();
+		assert_eq!(test_app.get(small).await.status, StatusCode::OK);
+		// the signature is for the size
+		let tampered = small.replace("size=64", "size=300");
+		assert_eq!(test_app.get(&tampered).await.status, StatusCode::FORBIDDEN);
+
+		// a stored cover only comes in its own size
+		let cover = CoverSource::Cover(PathBuf::from("cover.png"));
+		assert!(sized(&cover, &[64, 300]).is_empty());
+		assert_eq!(sized(&CoverSource::Placeholder, &[64, 300]), [64, 300]);
+
+		let payload = json!({ "img_uuids": [img_uuid], "sizes": [65] });
+		let response = test_app
+			.request(Method::POST, "/images/sign", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+	}
+}
@@ -1,6 +1,6 @@
 use axum::{
 	extract::{Query, State},
-	http::{header, StatusCode},
+	http::StatusCode,
 	response::Response,
 };
 use diesel::prelude::*;
@@ -8,8 +8,9 @@ use serde::Deserialize;
 
 use crate::{
 	core::app_state::AppState,
-	lobic_db::models::{Music, MusicResponse},
+	lobic_db::models::{Availability, Music, MusicResponse},
 	schema::{music, play_log},
+	utils::{fields::FieldsQuery, list::ListResponse},
 };
 
 #[derive(Debug, Deserialize)]
@@ -23,7 +24,12 @@ pub struct TopTracksQueryParams {
 pub async fn get_top_tracks(
 	State(app_state): State<AppState>,
 	Query(params): Query<TopTracksQueryParams>,
+	Query(fields): Query<FieldsQuery>,
 ) -> Response<String> {
+	let fields = match fields.fields() {
+		Ok(fields) => fields,
+		Err(msg) => return Response::builder().status(StatusCode::BAD_REQUEST).body(msg).unwrap(),
+	};
 	let mut db_conn = match app_state.db_pool.get() {
 		Ok(conn) => conn,
 		Err(err) => {
@@ -39,6 +45,7 @@ pub async fn get_top_tracks(
 		.filter(play_log::user_times_played.ge(1))
 		.order(play_log::user_times_played.desc())
 		.inner_join(music::table)
+		.filter(music::availability.eq(Availability::Available.as_str()))
 		.select(music::all_columns)
 		.offset(params.start_index)
 		.into_boxed();
@@ -49,31 +56,28 @@ pub async fn get_top_tracks(
 		}
 	}
 
+	let count_query = play_log::table
+		.filter(play_log::user_id.eq(&params.user_id))
+		.filter(play_log::user_times_played.ge(1))
+		.inner_join(music::table)
+		.filter(music::availability.eq(Availability::Available.as_str()));
+
+	let total_count = match count_query.count().get_result::<i64>(&mut db_conn) {
+		Ok(count) => count,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
 	match query.load::<Music>(&mut db_conn) {
 		Ok(music_entries) => {
-			if music_entries.is_empty() {
-				return Response::builder()
-					.status(StatusCode::NOT_FOUND)
-					.body("No top tracks found".to_string())
-					.unwrap();
-			}
-
-			let responses: Vec<MusicResponse> = music_entries
-				.into_iter()
-				.map(|entry| Music::create_music_response(entry))
-				.collect();
-
-			match serde_json::to_string(&responses) {
-				Ok(json) => Response::builder()
-					.status(StatusCode::OK)
-					.header(header::CONTENT_TYPE, "application/json")
-					.body(json)
-					.unwrap(),
-				Err(err) => Response::builder()
-					.status(StatusCode::INTERNAL_SERVER_ERROR)
-					.body(format!("Failed to serialize response: {err}"))
-					.unwrap(),
-			}
+			let responses: Vec<MusicResponse> = music_entries.into_iter().map(Music::create_music_response).collect();
+			ListResponse::page(responses, total_count, params.start_index, params.page_length)
+				.map(|track| fields.project(&track))
+				.into_response()
 		}
 		Err(err) => Response::builder()
 			.status(StatusCode::INTERNAL_SERVER_ERROR)
@@ -81,3 +85,110 @@ pub async fn get_top_tracks(
 			.unwrap(),
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::play_log;
+	use crate::test_support::TestApp;
+
+	use axum::http::StatusCode;
+	use diesel::prelude::*;
+
+	#[tokio::test]
+	async fn orders_by_the_users_play_count() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+
+		let response = test_app.get(&format!("/music/get_top_tracks?user_id={user_id}")).await;
+		assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+		let counts: Vec<i32> = response.json()["items"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|track| {
+				play_log::table
+					.filter(play_log::user_id.eq(&user_id))
+					.filter(play_log::music_id.eq(track["id"].as_str().unwrap()))
+					.select(play_log::user_times_played)
+					.first::<i32>(&mut test_app.db_conn())
+					.unwrap()
+			})
+			.collect();
+		assert!(!counts.is_empty());
+		assert!(counts.windows(2).all(|pair| pair[0] >= pair[1]), "{counts:?}");
+	}
+
+	#[tokio::test]
+	async fn pages_through_the_tracks() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_1");
+
+		let all = test_app
+			.get(&format!("/music/get_top_tracks?user_id={user_id}"))
+			.await
+			.json();
+		let page = test_app
+			.get(&format!(
+				"/music/get_top_tracks?user_id={user_id}&start_index=2&page_length=3"
+			))
+			.await
+			.json();
+
+		assert_eq!(page["items"].as_array().unwrap().len(), 3);
+		assert_eq!(page["items"][0]["id"], all["items"][2]["id"]);
+		assert_eq!(page["total_count"], all["items"].as_array().unwrap().len());
+	}
+
+	#[tokio::test]
+	async fn lightweight_clients_leave_out_the_heavy_fields() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let uri = format!("/music/get_top_tracks?user_id={user_id}");
+
+		let full = test_app.get(&format!("{uri}&include=stats,image,audio")).await;
+		assert!(full.json()["items"][0].get("bpm").is_some());
+		let light = test_app.get(&uri).await;
+		let track = &light.json()["items"][0];
+		assert_eq!(track["id"], full.json()["items"][0]["id"]);
+		for field in [
+			"times_played",
+			"image_url",
+			"palette_url",
+			"animated_cover_url",
+			"bpm",
+			"camelot",
+		] {
+			assert!(track.get(field).is_none(), "{field}");
+		}
+		assert!(light.body.len() * 2 < full.body.len());
+
+		let image = test_app.get(&format!("{uri}&include=image")).await.json();
+		let image_url = full.json()["items"][0]["image_url"].as_str().unwrap().to_string();
+		assert_eq!(image["items"][0]["image_url"], image_url);
+		assert_eq!(image["items"][0]["palette_url"], format!("/image/{image_url}/palette"));
+		assert!(image["items"][0].get("times_played").is_none());
+
+		let excluded = test_app
+			.get(&format!("{uri}&include=stats,audio&exclude=stats"))
+			.await
+			.json();
+		assert!(excluded["items"][0].get("times_played").is_none());
+		assert!(excluded["items"][0].get("bpm").is_some());
+
+		let response = test_app.get(&format!("{uri}&include=lyrics")).await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+	}
+
+	#[tokio::test]
+	async fn user_without_plays_has_no_top_tracks() {
+		let test_app = TestApp::new();
+
+		let response = test_app.get("/music/get_top_tracks?user_id=nobody").await;
+		assert_eq!(response.status, StatusCode::OK);
+		assert_eq!(
+			response.json(),
+			serde_json::json!({ "items": [], "total_count": 0, "start_index": 0, "page_length": null, "next": null })
+		);
+	}
+}
@@ -1,6 +1,6 @@
 use axum::{
 	extract::{Query, State},
-	http::{header, StatusCode},
+	http::StatusCode,
 	response::Response,
 };
 use diesel::prelude::*;
@@ -8,8 +8,12 @@ use serde::Deserialize;
 
 use crate::core::app_state::AppState;
 use crate::lobic_db::models::MusicResponse;
+use crate::utils::list::ListResponse;
 
-use crate::{lobic_db::models::Music, schema::music};
+use crate::{
+	lobic_db::models::{Availability, ContentType, Music},
+	schema::music,
+};
 
 #[derive(Debug, Deserialize)]
 pub struct TrendingSongsQueryParams {
@@ -34,6 +38,8 @@ pub async fn get_trending_songs(
 
 	//Fetch the most played songs with pagination
 	let mut query = music::table
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.filter(music::content_type.eq(ContentType::Music.as_str()))
 		.select(music::all_columns)
 		.order(music::times_played.desc())
 		.offset(params.start_index)
@@ -46,31 +52,24 @@ pub async fn get_trending_songs(
 		}
 		//else infinity
 	}
-	match query.load::<Music>(&mut db_conn) {
-		Ok(music_entries) => {
-			if music_entries.is_empty() {
-				return Response::builder()
-					.status(StatusCode::NOT_FOUND)
-					.body("No top tracks found".to_string())
-					.unwrap();
-			}
+	let count_query = music::table
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.filter(music::content_type.eq(ContentType::Music.as_str()));
 
-			let responses: Vec<MusicResponse> = music_entries
-				.into_iter()
-				.map(|entry| Music::create_music_response(entry))
-				.collect();
+	let total_count = match count_query.count().get_result::<i64>(&mut db_conn) {
+		Ok(count) => count,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
 
-			match serde_json::to_string(&responses) {
-				Ok(json) => Response::builder()
-					.status(StatusCode::OK)
-					.header(header::CONTENT_TYPE, "application/json")
-					.body(json)
-					.unwrap(),
-				Err(err) => Response::builder()
-					.status(StatusCode::INTERNAL_SERVER_ERROR)
-					.body(format!("Failed to serialize response: {err}"))
-					.unwrap(),
-			}
+	match query.load::<Music>(&mut db_conn) {
+		Ok(music_entries) => {
+			let responses: Vec<MusicResponse> = music_entries.into_iter().map(Music::create_music_response).collect();
+			ListResponse::page(responses, total_count, params.start_index, params.page_length).into_response()
 		}
 		Err(err) => Response::builder()
 			.status(StatusCode::INTERNAL_SERVER_ERROR)
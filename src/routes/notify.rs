@@ -1,12 +1,12 @@
 use crate::config::{OpCode, SocketResponse};
 use crate::core::app_state::AppState;
-use crate::core::user_pool::UserPool;
-use crate::lobic_db::db::DatabasePool;
+use crate::core::user_pool::{Topic, UserPool};
+use crate::lobic_db::db::{is_quiet, DatabasePool};
 use crate::lobic_db::models::{NotifModel, Notification};
 use crate::schema::notifications::dsl::*;
 
 use axum::{
-	extract::{ws::Message, Path, State},
+	extract::{Path, State},
 	http::status::StatusCode,
 	response::Response,
 };
@@ -22,19 +22,17 @@ pub fn notify(client_id: &str, notif: Notification, db_pool: &DatabasePool, user
 		}
 	};
 
-	match user_pool.get(client_id) {
-		Some(conn) => {
-			// Sending to the user connection
-			let response = SocketResponse {
-				op_code: OpCode::NOTIFICATION,
-				r#for: OpCode::NOTIFICATION,
-				value: notif.clone().into(),
-			}
-			.to_string();
-			let _ = conn.send(Message::Text(response));
+	// Sending to the user connection, does nothing when the client is offline.
+	// During do not disturb it's only stored, the client picks it up with the rest later.
+	if !is_quiet(client_id, db_pool) {
+		let response = SocketResponse {
+			op_code: OpCode::NOTIFICATION,
+			r#for: OpCode::NOTIFICATION,
+			value: notif.clone().into(),
 		}
-		None => (), // Triggered when client is offline
-	};
+		.to_string();
+		user_pool.send(client_id, &Topic::Notifications, response);
+	}
 
 	// Storing the notification
 	diesel::insert_into(notifications)
@@ -0,0 +1,855 @@
+use crate::config::OAUTH_CODE_SECS;
+use crate::core::app_state::AppState;
+use crate::core::federation::generate_token;
+use crate::core::oauth::{hash_secret, issue_tokens, parse_scope, pkce_matches, refresh_cutoff, secret_matches};
+use crate::lobic_db::models::{OAuthClient, OAuthCode, OAuthGrant};
+use crate::schema::{oauth_clients, oauth_codes, oauth_grants, oauth_tokens};
+use crate::utils::auth::require_user;
+use crate::utils::list::ListResponse;
+use crate::utils::response::{db_error, json_response};
+
+use axum::{
+	extract::{Path, Query, State},
+	http::status::StatusCode,
+	response::Response,
+	Form, Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+fn bad_request(msg: impl Into<String>) -> Response<String> {
+	Response::builder()
+		.status(StatusCode::BAD_REQUEST)
+		.body(msg.into())
+		.unwrap()
+}
+
+// The token endpoint answers in the shape of RFC 6749, { error, error_description }
+fn token_error(status: StatusCode, error: &str, description: &str) -> Response<String> {
+	json_response(status, &json!({ "error": error, "error_description": description }))
+}
+
+fn find_client(client_id: &str, db_conn: &mut SqliteConnection) -> QueryResult<Option<OAuthClient>> {
+	oauth_clients::table
+		.find(client_id)
+		.first::<OAuthClient>(db_conn)
+		.optional()
+}
+
+fn allows_redirect(client: &OAuthClient, redirect_uri: &str) -> bool {
+	serde_json::from_str::<Vec<String>>(&client.redirect_uris)
+		.is_ok_and(|uris| uris.iter().any(|uri| uri == redirect_uri))
+}
+
+// Public apps have to send a code_challenge, the others may. Only S256, plain would hand the verifier
+// to whoever sees the redirect.
+#[allow(clippy::result_large_err)]
+fn check_pkce(
+	client: &OAuthClient,
+	code_challenge: Option<&str>,
+	code_challenge_method: Option<&str>,
+) -> Result<(), Response<String>> {
+	match (code_challenge, code_challenge_method) {
+		(None, _) if client.is_public => Err(bad_request("Public apps need a code_challenge")),
+		(None, _) => Ok(()),
+		// base64url of a sha256
+		(Some(challenge), Some("S256")) if challenge.len() == 43 => Ok(()),
+		(Some(_), Some("S256")) => Err(bad_request("Invalid code_challenge")),
+		(Some(_), _) => Err(bad_request("Only the S256 code_challenge_method is supported")),
+	}
+}
+
+// :register_oauth_client
+#[derive(Debug, Deserialize)]
+pub struct RegisterClientPayload {
+	pub name: String,
+	pub redirect_uris: Vec<String>,
+	#[serde(default)]
+	pub public: bool, // can't keep a secret, like the apps on phones. Gets none and uses PKCE.
+}
+
+// The secret is only shown here
+pub async fn register_oauth_client(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<RegisterClientPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	if payload.name.trim().is_empty() {
+		return bad_request("The app needs a name");
+	}
+	if payload.redirect_uris.is_empty() {
+		return bad_request("At least one redirect uri is needed");
+	}
+	if let Some(uri) = payload
+		.redirect_uris
+		.iter()
+		.find(|uri| Url::parse(uri).map_or(true, |url| url.cannot_be_a_base() || url.fragment().is_some()))
+	{
+		return bad_request(format!("Invalid redirect uri: {uri}"));
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	let client_secret = (!payload.public).then(generate_token);
+	let client = OAuthClient {
+		client_id: Uuid::new_v4().to_string(),
+		client_secret: client_secret.as_deref().map(hash_secret).unwrap_or_default(),
+		name: payload.name.trim().to_string(),
+		redirect_uris: serde_json::to_string(&payload.redirect_uris).unwrap(),
+		owner_id: user_id,
+		created_date_time: Utc::now().to_rfc3339(),
+		is_public: payload.public,
+	};
+	if let Err(err) = diesel::insert_into(oauth_clients::table)
+		.values(&client)
+		.execute(&mut db_conn)
+	{
+		return db_error(err);
+	}
+
+	let mut body = json!(client);
+	body["client_secret"] = client_secret.into();
+	json_response(StatusCode::CREATED, &body)
+}
+
+// :reset_oauth_client_secret
+// Only the hash is kept, a lost secret can't be shown again. The old one stops working.
+pub async fn reset_oauth_client_secret(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(client_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	let client = match find_client(&client_id, &mut db_conn) {
+		Ok(Some(client)) if client.owner_id == user_id => client,
+		Ok(_) => {
+			return Response::builder()
+				.status(StatusCode::NOT_FOUND)
+				.body(format!("No app {client_id} of yours"))
+				.unwrap();
+		}
+		Err(err) => return db_error(err),
+	};
+	if client.is_public {
+		return bad_request("Public apps have no secret, they use PKCE");
+	}
+
+	let client_secret = generate_token();
+	if let Err(err) = diesel::update(oauth_clients::table.find(&client_id))
+		.set(oauth_clients::client_secret.eq(hash_secret(&client_secret)))
+		.execute(&mut db_conn)
+	{
+		return db_error(err);
+	}
+	let mut body = json!(client);
+	body["client_secret"] = client_secret.into();
+	json_response(StatusCode::OK, &body)
+}
+
+// :get_oauth_clients
+// The apps the user registered
+pub async fn get_oauth_clients(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	match oauth_clients::table
+		.filter(oauth_clients::owner_id.eq(&user_id))
+		.order(oauth_clients::created_date_time.asc())
+		.load::<OAuthClient>(&mut db_conn)
+	{
+		Ok(clients) => ListResponse::all(clients).into_response(),
+		Err(err) => db_error(err),
+	}
+}
+
+// :delete_oauth_client
+// Every user of the app gets logged out of it
+pub async fn delete_oauth_client(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(client_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	let deleted = db_conn.transaction::<_, diesel::result::Error, _>(|conn| {
+		let owned = oauth_clients::table
+			.filter(oauth_clients::client_id.eq(&client_id))
+			.filter(oauth_clients::owner_id.eq(&user_id));
+		if owned.count().get_result::<i64>(conn)? == 0 {
+			return Ok(false);
+		}
+		diesel::delete(oauth_tokens::table.filter(oauth_tokens::client_id.eq(&client_id))).execute(conn)?;
+		diesel::delete(oauth_codes::table.filter(oauth_codes::client_id.eq(&client_id))).execute(conn)?;
+		diesel::delete(oauth_grants::table.filter(oauth_grants::client_id.eq(&client_id))).execute(conn)?;
+		diesel::delete(owned).execute(conn)?;
+		Ok(true)
+	});
+	match deleted {
+		Ok(false) => Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("No app {client_id} of yours"))
+			.unwrap(),
+		Ok(true) => Response::builder()
+			.status(StatusCode::OK)
+			.body("App deleted".to_string())
+			.unwrap(),
+		Err(err) => db_error(err),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthorizeQuery {
+	pub response_type: String, // only code
+	pub client_id: String,
+	pub redirect_uri: String,
+	pub scope: String,
+	pub state: Option<String>,
+	pub code_challenge: Option<String>,        // PKCE, needed from the public apps
+	pub code_challenge_method: Option<String>, // only S256
+}
+
+// :get_oauth_consent
+// What the consent screen of the frontend shows before the user approves
+pub async fn get_oauth_consent(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(params): Query<AuthorizeQuery>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	if params.response_type != "code" {
+		return bad_request(format!("Unsupported response_type: {}", params.response_type));
+	}
+	let Some(scopes) = parse_scope(&params.scope) else {
+		return bad_request(format!("Invalid scope: {}", params.scope));
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	let client = match find_client(&params.client_id, &mut db_conn) {
+		Ok(Some(client)) if allows_redirect(&client, &params.redirect_uri) => client,
+		Ok(_) => return bad_request("Unknown app or redirect uri"),
+		Err(err) => return db_error(err),
+	};
+	if let Err(response) = check_pkce(
+		&client,
+		params.code_challenge.as_deref(),
+		params.code_challenge_method.as_deref(),
+	) {
+		return response;
+	}
+	let granted = oauth_grants::table
+		.find((&user_id, &client.client_id))
+		.select(oauth_grants::scope)
+		.first::<String>(&mut db_conn)
+		.optional()
+		.unwrap_or_default();
+
+	json_response(
+		StatusCode::OK,
+		&json!({
+			"client": { "client_id": client.client_id, "name": client.name },
+			"scopes": scopes,
+			"redirect_uri": params.redirect_uri,
+			"state": params.state,
+			// the consent screen can be skipped when nothing new is asked for
+			"already_granted": granted.is_some_and(|granted| {
+				scopes.iter().all(|scope| granted.split_whitespace().any(|granted| granted == *scope))
+			}),
+		}),
+	)
+}
+
+// :authorize_oauth_client
+#[derive(Debug, Deserialize)]
+pub struct AuthorizePayload {
+	pub client_id: String,
+	pub redirect_uri: String,
+	pub scope: String,
+	pub state: Option<String>,
+	pub code_challenge: Option<String>,
+	pub code_challenge_method: Option<String>,
+	pub approve: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct AuthorizeResponse {
+	redirect_to: String, // where the frontend sends the browser, with the code or the error
+}
+
+pub async fn authorize_oauth_client(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<AuthorizePayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	let Some(scopes) = parse_scope(&payload.scope) else {
+		return bad_request(format!("Invalid scope: {}", payload.scope));
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	let client = match find_client(&payload.client_id, &mut db_conn) {
+		Ok(Some(client)) if allows_redirect(&client, &payload.redirect_uri) => client,
+		Ok(_) => return bad_request("Unknown app or redirect uri"),
+		Err(err) => return db_error(err),
+	};
+	if let Err(response) = check_pkce(
+		&client,
+		payload.code_challenge.as_deref(),
+		payload.code_challenge_method.as_deref(),
+	) {
+		return response;
+	}
+
+	// The redirect uris were checked to be urls when the app got registered
+	let redirect_with = |param: (&str, &str)| {
+		let mut url = Url::parse(&payload.redirect_uri).unwrap();
+		url.query_pairs_mut().append_pair(param.0, param.1);
+		if let Some(state) = &payload.state {
+			url.query_pairs_mut().append_pair("state", state);
+		}
+		json_response(
+			StatusCode::OK,
+			&AuthorizeResponse {
+				redirect_to: url.into(),
+			},
+		)
+	};
+	if !payload.approve {
+		return redirect_with(("error", "access_denied"));
+	}
+
+	let now = Utc::now();
+	let scope = scopes.join(" ");
+	let plain_code = generate_token();
+	let code = OAuthCode {
+		code: hash_secret(&plain_code),
+		client_id: payload.client_id.clone(),
+		user_id: user_id.clone(),
+		redirect_uri: payload.redirect_uri.clone(),
+		scope: scope.clone(),
+		expires_date_time: (now + Duration::seconds(OAUTH_CODE_SECS)).to_rfc3339(),
+		code_challenge: payload.code_challenge.clone(),
+	};
+	let grant = OAuthGrant {
+		user_id,
+		client_id: payload.client_id.clone(),
+		scope,
+		granted_date_time: now.to_rfc3339(),
+	};
+	let saved = db_conn.transaction::<_, diesel::result::Error, _>(|conn| {
+		diesel::replace_into(oauth_grants::table).values(&grant).execute(conn)?;
+		diesel::insert_into(oauth_codes::table).values(&code).execute(conn)
+	});
+	if let Err(err) = saved {
+		return db_error(err);
+	}
+
+	redirect_with(("code", &plain_code))
+}
+
+// :oauth_token
+// Form encoded like RFC 6749 asks for, the client authenticates with its id and secret in the body.
+// Public apps have no secret, their codes are traded with the code_verifier.
+#[derive(Debug, Deserialize)]
+pub struct TokenRequest {
+	pub grant_type: String, // authorization_code | refresh_token
+	pub client_id: String,
+	pub client_secret: Option<String>,
+	pub code: Option<String>,
+	pub redirect_uri: Option<String>,
+	pub code_verifier: Option<String>,
+	pub refresh_token: Option<String>,
+}
+
+pub async fn oauth_token(State(app_state): State<AppState>, Form(params): Form<TokenRequest>) -> Response<String> {
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	match find_client(&params.client_id, &mut db_conn) {
+		Ok(Some(client))
+			if client.is_public
+				|| secret_matches(
+					params.client_secret.as_deref().unwrap_or_default(),
+					&client.client_secret,
+				) => {}
+		Ok(_) => {
+			return token_error(
+				StatusCode::UNAUTHORIZED,
+				"invalid_client",
+				"Unknown app or wrong secret",
+			)
+		}
+		Err(err) => return db_error(err),
+	}
+
+	// Codes are single use and refresh tokens rotate, both are gone once traded
+	let traded = db_conn.transaction::<_, diesel::result::Error, _>(|conn| {
+		let (user_id, scope) = match (params.grant_type.as_str(), &params.code, &params.refresh_token) {
+			("authorization_code", Some(code), _) => {
+				let Some(code) = oauth_codes::table
+					.find(hash_secret(code))
+					.filter(oauth_codes::client_id.eq(&params.client_id))
+					.first::<OAuthCode>(conn)
+					.optional()?
+				else {
+					return Ok(Err(("invalid_grant", "The code is unknown or already used")));
+				};
+				diesel::delete(oauth_codes::table.find(&code.code)).execute(conn)?;
+				if code.expires_date_time < Utc::now().to_rfc3339() {
+					return Ok(Err(("invalid_grant", "The code expired")));
+				}
+				if params.redirect_uri.as_deref() != Some(code.redirect_uri.as_str()) {
+					return Ok(Err((
+						"invalid_grant",
+						"The redirect_uri doesn't match the one the code was given for",
+					)));
+				}
+				if let Some(code_challenge) = &code.code_challenge {
+					let verified = params
+						.code_verifier
+						.as_deref()
+						.is_some_and(|code_verifier| pkce_matches(code_verifier, code_challenge));
+					if !verified {
+						return Ok(Err((
+							"invalid_grant",
+							"The code_verifier doesn't match the code_challenge",
+						)));
+					}
+				}
+				(code.user_id, code.scope)
+			}
+			("refresh_token", _, Some(refresh_token)) => {
+				let refresh_token = hash_secret(refresh_token);
+				let Some((user_id, scope)) = oauth_tokens::table
+					.filter(oauth_tokens::refresh_token.eq(&refresh_token))
+					.filter(oauth_tokens::client_id.eq(&params.client_id))
+					.filter(oauth_tokens::created_date_time.ge(refresh_cutoff()))
+					.select((oauth_tokens::user_id, oauth_tokens::scope))
+					.first::<(String, String)>(conn)
+					.optional()?
+				else {
+					return Ok(Err((
+						"invalid_grant",
+						"The refresh token is unknown, expired or revoked",
+					)));
+				};
+				diesel::delete(oauth_tokens::table.filter(oauth_tokens::refresh_token.eq(&refresh_token)))
+					.execute(conn)?;
+				(user_id, scope)
+			}
+			_ => {
+				let description = "Use authorization_code with a code or refresh_token with a refresh_token";
+				return Ok(Err(("unsupported_grant_type", description)));
+			}
+		};
+		issue_tokens(&params.client_id, &user_id, &scope, conn).map(Ok)
+	});
+
+	match traded {
+		Ok(Ok(tokens)) => json_response(StatusCode::OK, &tokens),
+		Ok(Err((error, description))) => token_error(StatusCode::BAD_REQUEST, error, description),
+		Err(err) => db_error(err),
+	}
+}
+
+// :get_oauth_grants
+// The apps the user allowed in, with what they may do
+pub async fn get_oauth_grants(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	match oauth_grants::table
+		.inner_join(oauth_clients::table)
+		.filter(oauth_grants::user_id.eq(&user_id))
+		.select((
+			oauth_grants::client_id,
+			oauth_clients::name,
+			oauth_grants::scope,
+			oauth_grants::granted_date_time,
+		))
+		.load::<(String, String, String, String)>(&mut db_conn)
+	{
+		Ok(grants) => {
+			let grants: Vec<_> = grants
+				.into_iter()
+				.map(|(client_id, name, scope, granted_date_time)| {
+					json!({ "client_id": client_id, "name": name, "scope": scope, "granted_date_time": granted_date_time })
+				})
+				.collect();
+			ListResponse::all(grants).into_response()
+		}
+		Err(err) => db_error(err),
+	}
+}
+
+// :revoke_oauth_grant
+// The app's tokens for the user stop working right away
+pub async fn revoke_oauth_grant(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(client_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	let revoked = db_conn.transaction::<_, diesel::result::Error, _>(|conn| {
+		diesel::delete(
+			oauth_tokens::table
+				.filter(oauth_tokens::client_id.eq(&client_id))
+				.filter(oauth_tokens::user_id.eq(&user_id)),
+		)
+		.execute(conn)?;
+		diesel::delete(
+			oauth_codes::table
+				.filter(oauth_codes::client_id.eq(&client_id))
+				.filter(oauth_codes::user_id.eq(&user_id)),
+		)
+		.execute(conn)?;
+		diesel::delete(oauth_grants::table.find((&user_id, &client_id))).execute(conn)
+	});
+	match revoked {
+		Ok(0) => Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("App {client_id} wasn't allowed in"))
+			.unwrap(),
+		Ok(_) => Response::builder()
+			.status(StatusCode::OK)
+			.body("Access revoked".to_string())
+			.unwrap(),
+		Err(err) => db_error(err),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::core::oauth;
+	use crate::lobic_db::models::OAuthCode;
+	use crate::schema::{oauth_clients, oauth_codes, oauth_tokens, users};
+	use crate::test_support::TestApp;
+
+	use axum::http::{header, Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::{json, Value};
+
+	// The code from the redirect the frontend is sent to
+	fn code_of(body: &Value) -> String {
+		let redirect_to = reqwest::Url::parse(body["redirect_to"].as_str().unwrap()).unwrap();
+		let code = redirect_to.query_pairs().find(|(name, _)| name == "code");
+		code.unwrap().1.into_owned()
+	}
+
+	#[tokio::test]
+	async fn apps_act_for_the_user_within_the_scope_they_were_given() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
+		let redirect_uri = "https://companion.example/callback";
+		let client = test_app
+			.request(
+				Method::POST,
+				"/oauth/clients/new",
+				Some(json!({ "name": "Companion", "redirect_uris": [redirect_uri] })),
+				&cookies,
+			)
+			.await
+			.json();
+		let client_id = client["client_id"].as_str().unwrap().to_string();
+		let client_secret = client["client_secret"].as_str().unwrap().to_string();
+		let stored = oauth_clients::table
+			.find(&client_id)
+			.select(oauth_clients::client_secret)
+			.first::<String>(&mut test_app.db_conn())
+			.unwrap();
+		assert_eq!(stored, oauth::hash_secret(&client_secret));
+
+		let uri = format!(
+			"/oauth/authorize?response_type=code&client_id={client_id}&redirect_uri={redirect_uri}&scope=read&state=xyz"
+		);
+		let consent = test_app.request(Method::GET, &uri, None, &cookies).await.json();
+		assert_eq!(
+			(consent["client"]["name"].clone(), consent["already_granted"].clone()),
+			(json!("Companion"), json!(false))
+		);
+
+		let approval = json!({ "client_id": client_id, "redirect_uri": redirect_uri, "scope": "read", "state": "xyz", "approve": true });
+		let body = test_app
+			.request(Method::POST, "/oauth/authorize", Some(approval), &cookies)
+			.await
+			.json();
+		let redirect_to = reqwest::Url::parse(body["redirect_to"].as_str().unwrap()).unwrap();
+		let params: Vec<(String, String)> = redirect_to.query_pairs().into_owned().collect();
+		assert_eq!(params[1], ("state".to_string(), "xyz".to_string()));
+		let code = params[0].1.clone();
+
+		let exchange = [
+			("grant_type", "authorization_code"),
+			("client_id", client_id.as_str()),
+			("client_secret", client_secret.as_str()),
+			("code", code.as_str()),
+			("redirect_uri", redirect_uri),
+		];
+		let mut wrong_secret = exchange;
+		wrong_secret[2].1 = "guessed";
+		let response = test_app.post_form("/oauth/token", &wrong_secret).await;
+		assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+		let tokens = test_app.post_form("/oauth/token", &exchange).await.json();
+		let bearer = format!("Bearer {}", tokens["access_token"].as_str().unwrap());
+		let response = test_app.post_form("/oauth/token", &exchange).await;
+		assert_eq!(response.json()["error"], "invalid_grant", "codes are single use");
+
+		// reads as the user, but can't write or touch the account
+		let app_request = |method: Method, uri: &'static str| {
+			let bearer = bearer.clone();
+			let test_app = &test_app;
+			async move {
+				test_app
+					.request_with_headers(method, uri, None, &[], &[(header::AUTHORIZATION, &bearer)])
+					.await
+			}
+		};
+		let response = app_request(Method::GET, "/users/me/blocked_content").await;
+		assert_eq!(response.status, StatusCode::OK);
+		let response = app_request(Method::GET, "/user/settings").await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+		let response = app_request(Method::POST, "/playlist/saved_search/delete/none").await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+		// the socket would be a whole session
+		let response = app_request(Method::GET, "/ws").await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+		let refreshed = test_app
+			.post_form(
+				"/oauth/token",
+				&[
+					("grant_type", "refresh_token"),
+					("client_id", client_id.as_str()),
+					("client_secret", client_secret.as_str()),
+					("refresh_token", tokens["refresh_token"].as_str().unwrap()),
+				],
+			)
+			.await
+			.json();
+		assert_eq!(refreshed["scope"], "read");
+		let response = app_request(Method::GET, "/users/me/blocked_content").await;
+		assert_eq!(
+			response.status,
+			StatusCode::UNAUTHORIZED,
+			"the old token went with the refresh"
+		);
+
+		// a reset secret takes over from the old one
+		let uri = format!("/oauth/clients/reset_secret/{client_id}");
+		let others = test_app.login("seed_user_1").await;
+		let response = test_app.request(Method::POST, &uri, None, &others).await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+		let reset = test_app.request(Method::POST, &uri, None, &cookies).await.json();
+		let new_secret = reset["client_secret"].as_str().unwrap();
+		for (secret, status) in [
+			(client_secret.as_str(), StatusCode::UNAUTHORIZED),
+			(new_secret, StatusCode::OK),
+		] {
+			let response = test_app
+				.post_form(
+					"/oauth/token",
+					&[
+						("grant_type", "refresh_token"),
+						("client_id", client_id.as_str()),
+						("client_secret", secret),
+						("refresh_token", refreshed["refresh_token"].as_str().unwrap()),
+					],
+				)
+				.await;
+			assert_eq!(response.status, status);
+		}
+
+		let uri = format!("/oauth/grants/revoke/{client_id}");
+		let response = test_app.request(Method::POST, &uri, None, &cookies).await;
+		assert_eq!(response.status, StatusCode::OK);
+		let grants = test_app
+			.request(Method::GET, "/oauth/grants", None, &cookies)
+			.await
+			.json();
+		assert_eq!(grants["items"], json!([]));
+	}
+
+	#[tokio::test]
+	async fn public_apps_trade_their_codes_with_pkce_and_never_act_as_admin() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		diesel::update(users::table.find(&user_id))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let cookies = test_app.login("seed_user_0").await;
+		let redirect_uri = "https://phone.example/callback";
+		let client = test_app
+			.request(
+				Method::POST,
+				"/oauth/clients/new",
+				Some(json!({ "name": "Phone", "redirect_uris": [redirect_uri], "public": true })),
+				&cookies,
+			)
+			.await
+			.json();
+		assert_eq!(client["client_secret"], Value::Null);
+		let client_id = client["client_id"].as_str().unwrap().to_string();
+
+		// the challenge is the base64url sha256 of the verifier
+		let code_verifier = "dBjftJeZ4CVP-mJ92K9qqUBT3Ouo2hlt9l5Nx9MZK1U";
+		let code_challenge = "9dJM_5fZ0r2xszgD0OyXYnXvK2NYZ51UUkRmcGxVK7Y";
+		let approval =
+			json!({ "client_id": client_id, "redirect_uri": redirect_uri, "scope": "read write", "approve": true });
+		let response = test_app
+			.request(Method::POST, "/oauth/authorize", Some(approval.clone()), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST, "public apps need PKCE");
+		let mut approval = approval;
+		approval["code_challenge"] = code_challenge.into();
+		approval["code_challenge_method"] = "S256".into();
+		let authorize = || test_app.request(Method::POST, "/oauth/authorize", Some(approval.clone()), &cookies);
+
+		let code = code_of(&authorize().await.json());
+		let (test_app, client_id) = (&test_app, &client_id);
+		let exchange = |code_verifier: &'static str, code: String| async move {
+			let form = [
+				("grant_type", "authorization_code"),
+				("client_id", client_id.as_str()),
+				("code", code.as_str()),
+				("redirect_uri", redirect_uri),
+				("code_verifier", code_verifier),
+			];
+			test_app.post_form("/oauth/token", &form).await.json()
+		};
+		let tokens = exchange("another-verifier-of-the-right-length-0123456789", code).await;
+		assert_eq!(tokens["error"], "invalid_grant");
+		let code = code_of(&authorize().await.json());
+		let tokens = exchange(code_verifier, code).await;
+		let bearer = format!("Bearer {}", tokens["access_token"].as_str().unwrap());
+
+		let payload = json!({ "music_id": "none", "content_type": "audiobook" });
+		let response = test_app
+			.request(Method::POST, "/music/content_type/set", Some(payload.clone()), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+		let uri = "/music/content_type/set";
+		let response = test_app
+			.request_with_headers(
+				Method::POST,
+				uri,
+				Some(payload),
+				&[],
+				&[(header::AUTHORIZATION, &bearer)],
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN, "the admin's app isn't an admin");
+	}
+
+	#[tokio::test]
+	async fn expired_codes_and_old_tokens_are_pruned() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let cookies = test_app.login("seed_user_0").await;
+		let client = test_app
+			.request(
+				Method::POST,
+				"/oauth/clients/new",
+				Some(json!({ "name": "Companion", "redirect_uris": ["https://companion.example/callback"] })),
+				&cookies,
+			)
+			.await
+			.json();
+		let client_id = client["client_id"].as_str().unwrap();
+
+		let mut db_conn = test_app.db_conn();
+		diesel::insert_into(oauth_codes::table)
+			.values(OAuthCode {
+				code: oauth::hash_secret("expired"),
+				client_id: client_id.to_string(),
+				user_id: user_id.clone(),
+				redirect_uri: "https://companion.example/callback".to_string(),
+				scope: "read".to_string(),
+				expires_date_time: "2001-01-01T00:00:00+00:00".to_string(),
+				code_challenge: None,
+			})
+			.execute(&mut db_conn)
+			.unwrap();
+		oauth::issue_tokens(client_id, &user_id, "read", &mut db_conn).unwrap();
+		let old = oauth::issue_tokens(client_id, &user_id, "read", &mut db_conn).unwrap();
+		diesel::update(oauth_tokens::table.find(oauth::hash_secret(&old.access_token)))
+			.set(oauth_tokens::created_date_time.eq("2001-01-01T00:00:00+00:00"))
+			.execute(&mut db_conn)
+			.unwrap();
+
+		assert_eq!(oauth::prune(&mut db_conn).unwrap(), 2);
+		let tokens = oauth_tokens::table.count().get_result::<i64>(&mut db_conn).unwrap();
+		assert_eq!(tokens, 1);
+		drop(db_conn);
+		let response = test_app
+			.post_form(
+				"/oauth/token",
+				&[
+					("grant_type", "refresh_token"),
+					("client_id", client_id),
+					("client_secret", client["client_secret"].as_str().unwrap()),
+					("refresh_token", old.refresh_token.as_str()),
+				],
+			)
+			.await;
+		assert_eq!(response.json()["error"], "invalid_grant");
+	}
+}
@@ -0,0 +1,215 @@
+use crate::config::{MusicState, OVERLAY_REFRESH_SECS};
+use crate::core::app_state::AppState;
+use crate::lobic_db::models::{EmbedToken, Music};
+use crate::mail::templates::escape_html;
+use crate::schema::{embed_tokens, music};
+use crate::utils::response::db_error;
+
+use axum::{
+	extract::{Path, Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+pub struct OverlayQuery {
+	pub format: Option<String>, // json or html, the html one is a browser source as it is
+}
+
+// Only what the stream shows, never who is listening or where
+#[derive(Debug, Serialize)]
+pub struct OverlayTrack {
+	pub title: String,
+	pub artist: String,
+	pub cover_url: Option<String>,
+	pub playing: bool,
+	pub position: f64, // seconds into the track when it was read
+	pub duration: Option<i64>,
+}
+
+// :get_overlay_now_playing
+// Read by OBS with the token in the path, no session. Null when nothing is playing, during a private
+// session or once the lobby is gone. Revoking the token at /user/embed_tokens turns it off.
+pub async fn get_overlay_now_playing(
+	State(app_state): State<AppState>,
+	Path(token): Path<String>,
+	Query(params): Query<OverlayQuery>,
+) -> Response<String> {
+	let html = match params.format.as_deref() {
+		None | Some("json") => false,
+		Some("html") => true,
+		Some(format) => {
+			return Response::builder()
+				.status(StatusCode::BAD_REQUEST)
+				.body(format!("Invalid format: {format}, use json or html"))
+				.unwrap()
+		}
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	let embed_token = match embed_tokens::table
+		.filter(embed_tokens::token.eq(&token))
+		.first::<EmbedToken>(&mut db_conn)
+		.optional()
+	{
+		Ok(Some(embed_token)) if embed_token.is_overlay() => embed_token,
+		Ok(_) => {
+			return Response::builder()
+				.status(StatusCode::UNAUTHORIZED)
+				.body("The token isn't for an overlay".to_string())
+				.unwrap()
+		}
+		Err(err) => return db_error(err),
+	};
+	let _ = diesel::update(embed_tokens::table.find(&embed_token.token_id))
+		.set(embed_tokens::last_used_date_time.eq(Utc::now().to_rfc3339()))
+		.execute(&mut db_conn);
+
+	let track = if embed_token.target_type == EmbedToken::LOBBY {
+		app_state
+			.lobby_pool
+			.get(&embed_token.target_id)
+			.map(|lobby| lobby.music)
+			.filter(|music| !music.id.is_empty())
+			.map(|playing| OverlayTrack {
+				title: playing.title,
+				artist: playing.artist,
+				cover_url: (!playing.image_url.is_empty()).then(|| format!("/image/{}", playing.image_url)),
+				playing: !matches!(playing.state, MusicState::PAUSE),
+				position: playing.timestamp,
+				duration: None,
+			})
+	} else {
+		app_state
+			.now_playing_pool
+			.get_public(&embed_token.target_id)
+			.map(|now_playing| {
+				let entry = music::table
+					.find(&now_playing.music_id)
+					.first::<Music>(&mut db_conn)
+					.ok()
+					.map(Music::create_music_response);
+				let elapsed = match now_playing.state {
+					MusicState::PLAY => (Utc::now().timestamp() - now_playing.updated_at) as f64,
+					_ => 0.0,
+				};
+				OverlayTrack {
+					title: now_playing.title,
+					artist: now_playing.artist,
+					cover_url: entry.as_ref().map(|entry| format!("/image/{}", entry.image_url)),
+					playing: matches!(now_playing.state, MusicState::PLAY),
+					position: now_playing.position + elapsed,
+					duration: entry.map(|entry| entry.duration),
+				}
+			})
+	};
+
+	let (content_type, body) = match html {
+		true => ("text/html; charset=utf-8", overlay_html(track.as_ref())),
+		false => (
+			"application/json",
+			serde_json::json!({ "now_playing": track }).to_string(),
+		),
+	};
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, content_type)
+		.header(header::CACHE_CONTROL, "no-store")
+		.body(body)
+		.unwrap()
+}
+
+// Transparent, so it sits on top of the stream. Reloads itself instead of running any script.
+fn overlay_html(track: Option<&OverlayTrack>) -> String {
+	let content = match track {
+		Some(track) => format!(
+			r#"<div class="track">{cover}<div><div class="title">{title}</div><div class="artist">{artist}</div></div></div>"#,
+			cover = track
+				.cover_url
+				.as_ref()
+				.map(|url| format!(r#"<img src="{}" alt="">"#, escape_html(url)))
+				.unwrap_or_default(),
+			title = escape_html(&track.title),
+			artist = escape_html(&track.artist),
+		),
+		None => String::new(),
+	};
+	format!(
+		concat!(
+			r#"<!DOCTYPE html><html><head><meta charset="utf-8"><meta http-equiv="refresh" content="{refresh}">"#,
+			"<style>body{{margin:0;background:transparent;font-family:sans-serif;color:#fff}}",
+			".track{{display:flex;align-items:center;gap:12px}}img{{width:64px;height:64px;border-radius:4px}}",
+			".title{{font-weight:bold}}.artist{{opacity:.8}}</style></head><body>{content}</body></html>"
+		),
+		refresh = OVERLAY_REFRESH_SECS,
+		content = content,
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::config::MusicState;
+	use crate::test_support::TestApp;
+
+	use axum::http::{header, Method, StatusCode};
+	use serde_json::{json, Value};
+
+	#[tokio::test]
+	async fn overlays_show_the_track_and_nothing_else() {
+		let test_app = TestApp::seeded();
+		let host_id = test_app.user_id("seed_user_0");
+		let cookies = test_app.login("seed_user_0").await;
+		let lobby_pool = &test_app.app_state.lobby_pool;
+		let lobby_id = lobby_pool.create_lobby(&host_id, &test_app.app_state.db_pool).unwrap()["lobby_id"]
+			.as_str()
+			.unwrap()
+			.to_string();
+
+		let create = |payload: Value| test_app.request(Method::POST, "/user/embed_tokens/new", Some(payload), &cookies);
+		let created = create(json!({ "target_type": "lobby", "target_id": lobby_id }))
+			.await
+			.json();
+		let overlay_url = created["embed_url"].as_str().unwrap().to_string();
+		assert!(overlay_url.starts_with("/overlay/"));
+
+		let response = test_app.get(&overlay_url).await;
+		assert_eq!(response.json(), json!({ "now_playing": null }));
+
+		let mut lobby = lobby_pool.get(&lobby_id).unwrap();
+		lobby.music.id = "some-track".to_string();
+		lobby.music.title = "<Nectar>".to_string();
+		lobby.music.artist = "Joji".to_string();
+		lobby.music.state = MusicState::PLAY;
+		lobby_pool.insert(&lobby_id, lobby);
+		let now_playing = test_app.get(&overlay_url).await.json()["now_playing"].clone();
+		assert_eq!(
+			(now_playing["title"].clone(), now_playing["playing"].clone()),
+			(json!("<Nectar>"), json!(true))
+		);
+		assert!(now_playing.get("lobby_id").is_none());
+
+		let html = test_app.get(&format!("{overlay_url}?format=html")).await;
+		assert!(html.headers[header::CONTENT_TYPE]
+			.to_str()
+			.unwrap()
+			.starts_with("text/html"));
+		assert!(html.body.contains("&lt;Nectar&gt;"));
+
+		// someone else's lobby can't be shown, a revoked token shows nothing
+		let other_cookies = test_app.login("seed_user_1").await;
+		let payload = json!({ "target_type": "lobby", "target_id": lobby_id });
+		let response = test_app
+			.request(Method::POST, "/user/embed_tokens/new", Some(payload), &other_cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+		let uri = format!("/user/embed_tokens/revoke/{}", created["token_id"].as_str().unwrap());
+		test_app.request(Method::POST, &uri, None, &cookies).await;
+		assert_eq!(test_app.get(&overlay_url).await.status, StatusCode::UNAUTHORIZED);
+	}
+}
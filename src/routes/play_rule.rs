@@ -0,0 +1,158 @@
+use crate::core::app_state::AppState;
+use crate::core::play_rule::{PlayRule, PLAY_MIN_PERCENT, PLAY_MIN_SECS};
+use crate::lobic_db::db::set_instance_setting;
+use crate::utils::auth::require_admin;
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+
+// :get_play_rule
+pub async fn get_play_rule(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(serde_json::to_string(&PlayRule::load(&mut db_conn)).unwrap())
+		.unwrap()
+}
+
+// :set_play_rule
+// Only the parts given change, the plays already recorded stay as they are
+#[derive(Debug, Deserialize)]
+pub struct SetPlayRulePayload {
+	pub min_percent: Option<i64>,
+	pub min_secs: Option<i64>,
+}
+
+pub async fn set_play_rule(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<SetPlayRulePayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let parts = [
+		(PLAY_MIN_PERCENT, payload.min_percent, 0..=100),
+		(PLAY_MIN_SECS, payload.min_secs, 0..=600),
+	];
+	for (key, value, range) in &parts {
+		if let Some(value) = value.filter(|value| !range.contains(value)) {
+			return Response::builder()
+				.status(StatusCode::BAD_REQUEST)
+				.body(format!("Invalid {key}: {value}"))
+				.unwrap();
+		}
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	for (key, value, _) in parts {
+		let Some(value) = value else {
+			continue;
+		};
+		if let Err(err) = set_instance_setting(key, &value.to_string(), &mut db_conn) {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to save the setting: {err}"))
+				.unwrap();
+		}
+	}
+
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(serde_json::to_string(&PlayRule::load(&mut db_conn)).unwrap())
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::{music, play_events, users};
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn short_listens_dont_count_under_the_rule() {
+		let test_app = TestApp::seeded();
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let cookies = test_app.login("seed_user_1").await;
+
+		let payload = json!({ "min_percent": 50 });
+		let rule = test_app
+			.request(Method::POST, "/admin/play_rule", Some(payload), &cookies)
+			.await
+			.json();
+		assert_eq!(rule, json!({ "min_percent": 50, "min_secs": 0 }));
+		let capabilities = test_app.get("/api/capabilities").await.json();
+		assert_eq!(capabilities["plays"]["rule"], rule);
+
+		let (music_id, duration) = music::table
+			.filter(music::duration.gt(60))
+			.select((music::music_id, music::duration))
+			.first::<(String, i64)>(&mut test_app.db_conn())
+			.unwrap();
+		let user_id = test_app.user_id("seed_user_1");
+		let plays = || {
+			play_events::table
+				.filter(play_events::user_id.eq(&user_id))
+				.count()
+				.get_result::<i64>(&mut test_app.db_conn())
+				.unwrap()
+		};
+		let before = plays();
+		let short = json!({ "user_id": user_id, "music_id": music_id, "listened_secs": duration / 2 - 1 });
+		assert_eq!(
+			test_app.post("/music/log_song_play", short).await.status,
+			StatusCode::ACCEPTED
+		);
+		let long = json!({ "user_id": user_id, "music_id": music_id, "listened_secs": duration / 2 + 1 });
+		assert_eq!(
+			test_app.post("/music/log_song_play", long).await.status,
+			StatusCode::CREATED
+		);
+		assert_eq!(plays(), before + 1);
+
+		let payload = json!({ "min_percent": 101 });
+		let response = test_app
+			.request(Method::POST, "/admin/play_rule", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+	}
+}
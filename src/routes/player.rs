@@ -0,0 +1,228 @@
+use crate::core::app_state::AppState;
+use crate::core::now_playing::{
+	friend_ids, player_state, record_heartbeat, relay_intent, Heartbeat, NowPlaying, PlayerIntent,
+};
+use crate::lobic_db::db::user_exists;
+use crate::services::music::{snap_to_chapter, ChapterResponse, MusicService};
+use crate::utils::auth::require_user;
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::{Path, State},
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde_json::json;
+
+// :heartbeat
+// Clients report the current track, position and play state every few seconds
+pub async fn heartbeat(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<Heartbeat>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	match record_heartbeat(
+		&user_id,
+		payload,
+		&app_state.now_playing_pool,
+		&app_state.lobby_pool,
+		&app_state.user_pool,
+		&app_state.db_pool,
+	) {
+		Ok(now_playing) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&now_playing).unwrap())
+			.unwrap(),
+		Err(err) => Response::builder().status(StatusCode::BAD_REQUEST).body(err).unwrap(),
+	}
+}
+
+// :get_now_playing
+// What the user is listening to right now, null when they aren't
+pub async fn get_now_playing(State(app_state): State<AppState>, Path(user_id): Path<String>) -> Response<String> {
+	if !user_exists(&user_id, &app_state.db_pool) {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid user_id: {}", user_id))
+			.unwrap();
+	}
+
+	let now_playing = app_state.now_playing_pool.get_public(&user_id);
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(serde_json::to_string(&now_playing).unwrap())
+		.unwrap()
+}
+
+// :get_resume
+// The last reported track and position of the logged in user, to pick up where they left off
+// Tracks with chapters resume from the start of the chapter when the position is just past it
+pub async fn get_resume(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let Some(now_playing) = app_state.now_playing_pool.get(&user_id) else {
+		return Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body("Nothing to resume".to_string())
+			.unwrap();
+	};
+	let chapters = match MusicService::new(&app_state.db_pool).chapters(&now_playing.music_id) {
+		Ok(chapters) => chapters,
+		Err(err) => return err.into_response(),
+	};
+
+	let position_ms = (now_playing.position * 1000.0) as i64;
+	let chapter = chapters.iter().find(|chapter| chapter.contains(position_ms));
+	let mut response = serde_json::to_value(&now_playing).unwrap();
+	response["resume_position"] = json!(snap_to_chapter(&chapters, now_playing.position));
+	response["chapter"] = json!(chapter.map(ChapterResponse::from));
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(response.to_string())
+		.unwrap()
+}
+
+// :get_friends_activity
+// What the friends of the logged in user are listening to right now
+pub async fn get_friends_activity(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut activity: Vec<NowPlaying> = friend_ids(&user_id, &app_state.db_pool)
+		.iter()
+		.filter_map(|friend_id| app_state.now_playing_pool.get_public(friend_id))
+		.collect();
+	activity.sort_by_key(|now_playing| std::cmp::Reverse(now_playing.updated_at));
+
+	ListResponse::all(activity).into_response()
+}
+
+// :get_player_state
+// The user's own player, stopped when no client has reported lately
+pub async fn get_player_state(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	match player_state(&user_id, &app_state.now_playing_pool, &app_state.db_pool) {
+		Ok(state) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&state).unwrap())
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(err)
+			.unwrap(),
+	}
+}
+
+// :set_player_state
+// The intent goes to the connected player over the socket, the state changes once its next heartbeat says so
+pub async fn set_player_state(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(intent): Json<PlayerIntent>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	if let Err(err) = intent.validate() {
+		return Response::builder().status(StatusCode::BAD_REQUEST).body(err).unwrap();
+	}
+
+	if !relay_intent(&user_id, &intent, &app_state.user_pool) {
+		return Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body("No player is connected".to_string())
+			.unwrap();
+	}
+	Response::builder()
+		.status(StatusCode::ACCEPTED)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(serde_json::to_string(&intent).unwrap())
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::core::outbox::Outbox;
+	use crate::test_support::TestApp;
+
+	use axum::extract::ws::Message;
+	use axum::http::{Method, StatusCode};
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn player_state_intents_reach_the_connected_player() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let cookies = test_app.login("seed_user_0").await;
+		let body = test_app
+			.request(Method::GET, "/player/state", None, &cookies)
+			.await
+			.json();
+		assert_eq!(body["status"], "stopped");
+
+		let track = test_app.get("/music/get_music?page_length=1").await.json()["items"][0].clone();
+		let heartbeat = json!({ "music_id": track["id"], "position": 12.0, "state": "PAUSE", "volume": 0.5 });
+		test_app
+			.request(Method::POST, "/player/heartbeat", Some(heartbeat), &cookies)
+			.await;
+		let body = test_app
+			.request(Method::GET, "/player/state", None, &cookies)
+			.await
+			.json();
+		assert_eq!(body["status"], "paused");
+		assert_eq!(body["track"]["id"], track["id"]);
+		assert_eq!(
+			(body["position"].clone(), body["volume"].clone()),
+			(json!(12.0), json!(0.5))
+		);
+
+		let seek = json!({ "action": "seek", "position": 30.0 });
+		let response = test_app
+			.request(Method::PUT, "/player/state", Some(seek.clone()), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+		let outbox = Outbox::new();
+		test_app.app_state.user_pool.insert(&user_id, &outbox);
+		let response = test_app
+			.request(
+				Method::PUT,
+				"/player/state",
+				Some(json!({ "action": "volume", "volume": 2 })),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		let response = test_app
+			.request(Method::PUT, "/player/state", Some(seek.clone()), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::ACCEPTED);
+		let Some(Message::Text(sent)) = outbox.next().await else {
+			panic!("nothing was relayed");
+		};
+		let sent: serde_json::Value = serde_json::from_str(&sent).unwrap();
+		assert_eq!(sent["for"], "PLAYER_COMMAND");
+		assert_eq!(sent["value"], seek);
+	}
+}
@@ -1,54 +1,129 @@
 use crate::core::app_state::AppState;
-use crate::lobic_db::models::PlaylistSong;
-use axum::{extract::State, http::status::StatusCode, response::Response, Json};
-use chrono::Utc;
-use diesel::prelude::*;
+use crate::services::PlaylistService;
+use crate::utils::precondition::{etag, expected_version};
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode, HeaderMap},
+	response::Response,
+	Json,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AddSongToPlaylist {
 	pub playlist_id: String,
 	pub music_id: String,
 	pub song_adder_id: String,
+	pub version: Option<i32>, // when not sent as If-Match
+	#[serde(default)]
+	pub allow_duplicate: bool, // add it even when it's already in the playlist
 }
 
 pub async fn add_song_to_playlist(
 	State(app_state): State<AppState>,
+	headers: HeaderMap,
 	Json(payload): Json<AddSongToPlaylist>,
 ) -> Response<String> {
-	let mut db_conn = match app_state.db_pool.get() {
-		Ok(conn) => conn,
-		Err(err) => {
-			return Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(format!("Failed to get DB from pool: {err}"))
-				.unwrap();
-		}
+	let expected_version = match expected_version(&headers, payload.version) {
+		Ok(version) => version,
+		Err(response) => return response,
 	};
 
-	use crate::schema::playlist_songs::dsl::*;
-	let curr_song_added_date_time = Utc::now().to_rfc3339();
-
-	// Create a new PlaylistSong record
-	let new_playlist_song = PlaylistSong {
-		playlist_id: payload.playlist_id,
-		music_id: payload.music_id,
-		song_added_date_time: curr_song_added_date_time,
-		song_adder_id: payload.song_adder_id,
-	};
+	let service = PlaylistService::new(&app_state.db_pool);
+	if !payload.allow_duplicate {
+		match service.position_of(&payload.playlist_id, &payload.music_id) {
+			Ok(None) => {}
+			Ok(Some(position)) => {
+				let body = json!({
+					"message": "The song is already in the playlist, send allow_duplicate=true to add it again",
+					"music_id": payload.music_id,
+					"position": position,
+				});
+				return Response::builder()
+					.status(StatusCode::CONFLICT)
+					.header(header::CONTENT_TYPE, "application/json")
+					.body(body.to_string())
+					.unwrap();
+			}
+			Err(err) => return err.into_response(),
+		}
+	}
 
-	// Insert the new song into the playlist
-	match diesel::insert_into(playlist_songs)
-		.values(&new_playlist_song)
-		.execute(&mut db_conn)
-	{
-		Ok(_) => Response::builder()
+	match service.add_song(
+		payload.playlist_id.clone(),
+		payload.music_id,
+		payload.song_adder_id,
+		expected_version,
+	) {
+		Ok(version) => Response::builder()
 			.status(StatusCode::CREATED)
+			.header(header::ETAG, etag(version))
 			.body("Song added to playlist".to_string())
 			.unwrap(),
-		Err(err) => Response::builder()
-			.status(StatusCode::INTERNAL_SERVER_ERROR)
-			.body(format!("Failed to add song to playlist: {}", err))
-			.unwrap(),
+		Err(err) => service.error_response(&payload.playlist_id, err),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::music;
+	use crate::services::PlaylistService;
+	use crate::test_support::TestApp;
+
+	use axum::http::StatusCode;
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn duplicates_are_only_added_when_allowed() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let music_ids = music::table
+			.select(music::music_id)
+			.limit(2)
+			.load::<String>(&mut test_app.db_conn())
+			.unwrap();
+		let playlist_id = PlaylistService::new(&test_app.app_state.db_pool)
+			.create("Loops".to_string(), user_id.clone(), false, &[])
+			.unwrap()
+			.playlist_id;
+		let add_song = |music_id: &str, version: i32, allow_duplicate: bool| {
+			json!({
+				"playlist_id": playlist_id,
+				"music_id": music_id,
+				"song_adder_id": user_id,
+				"version": version,
+				"allow_duplicate": allow_duplicate,
+			})
+		};
+
+		for (version, music_id) in [(1, &music_ids[0]), (2, &music_ids[1])] {
+			let response = test_app
+				.post("/playlist/add_song", add_song(music_id, version, false))
+				.await;
+			assert_eq!(response.status, StatusCode::CREATED, "{}", response.body);
+		}
+		let response = test_app
+			.post("/playlist/add_song", add_song(&music_ids[1], 3, false))
+			.await;
+		assert_eq!(response.status, StatusCode::CONFLICT);
+		assert_eq!(response.json()["position"], 1);
+
+		let response = test_app
+			.post("/playlist/add_song", add_song(&music_ids[1], 3, true))
+			.await;
+		assert_eq!(response.status, StatusCode::CREATED, "{}", response.body);
+		let details = test_app
+			.get(&format!("/playlist/get_by_uuid?playlist_id={playlist_id}"))
+			.await
+			.json();
+		let songs: Vec<_> = details["songs"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|song| song["music_id"].as_str().unwrap())
+			.collect();
+		assert_eq!(songs, [&music_ids[0], &music_ids[1], &music_ids[1]]);
 	}
 }
@@ -0,0 +1,46 @@
+use crate::core::app_state::AppState;
+use crate::services::PlaylistService;
+use crate::utils::precondition::{etag, expected_version};
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode, HeaderMap},
+	response::Response,
+	Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct ClearPlaylist {
+	pub playlist_id: String,
+	pub version: Option<i32>, // when not sent as If-Match
+}
+
+// Takes every song out, the playlist itself stays
+pub async fn clear_playlist(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	Json(payload): Json<ClearPlaylist>,
+) -> Response<String> {
+	let expected_version = match expected_version(&headers, payload.version) {
+		Ok(version) => version,
+		Err(response) => return response,
+	};
+
+	let service = PlaylistService::new(&app_state.db_pool);
+	match service.clear(&payload.playlist_id, expected_version) {
+		Ok((songs_deleted, version, undo)) => {
+			let body = json!({
+				"message": format!("{songs_deleted} songs removed from playlist {}", payload.playlist_id),
+				"undo": undo,
+			});
+			Response::builder()
+				.status(StatusCode::OK)
+				.header(header::CONTENT_TYPE, "application/json")
+				.header(header::ETAG, etag(version))
+				.body(body.to_string())
+				.unwrap()
+		}
+		Err(err) => service.error_response(&payload.playlist_id, err),
+	}
+}
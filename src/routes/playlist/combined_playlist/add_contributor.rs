@@ -1,59 +1,64 @@
 use crate::core::app_state::AppState;
 use crate::lobic_db::models::PlaylistShare;
 use crate::schema::{playlist_shares, playlists};
+use crate::services::{PlaylistService, ServiceError};
+use crate::utils::precondition::{etag, expected_version};
 use axum::Json;
-use axum::{extract::State, http::status::StatusCode, response::Response};
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode, HeaderMap},
+	response::Response,
+};
 use diesel::prelude::*;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct AddContributorPayload {
+	playlist_id: String,
+	contributor_user_id: String,
+	version: Option<i32>, // when not sent as If-Match
+}
 
 pub async fn add_contributor(
 	State(app_state): State<AppState>,
-	Json(payload): Json<PlaylistShare>,
+	headers: HeaderMap,
+	Json(payload): Json<AddContributorPayload>,
 ) -> Response<String> {
-	let mut db_conn = match app_state.db_pool.get() {
-		Ok(conn) => conn,
-		Err(err) => {
-			let msg = format!("Failed to get DB from pool: {err}");
-			return Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(msg)
-				.unwrap();
-		}
+	let expected_version = match expected_version(&headers, payload.version) {
+		Ok(version) => version,
+		Err(response) => return response,
 	};
 
-	// Check if playlist is combined
-	let is_combined = match playlists::table
-		.select(playlists::is_playlist_combined)
-		.filter(playlists::playlist_id.eq(&payload.playlist_id))
-		.first::<bool>(&mut db_conn)
-	{
-		Ok(combined) => combined,
-		Err(err) => {
-			let msg = format!("Failed to check playlist status: {err}");
-			return Response::builder().status(StatusCode::NOT_FOUND).body(msg).unwrap();
+	let service = PlaylistService::new(&app_state.db_pool);
+	let added = service.versioned(&payload.playlist_id, expected_version, |conn| {
+		// Check if playlist is combined
+		let is_combined = playlists::table
+			.select(playlists::is_playlist_combined)
+			.filter(playlists::playlist_id.eq(&payload.playlist_id))
+			.first::<bool>(conn)
+			.map_err(|err| ServiceError::NotFound(format!("Failed to check playlist status: {err}")))?;
+
+		if !is_combined {
+			return Err(ServiceError::BadRequest(
+				"Cannot add contributors to a solo playlist".to_string(),
+			));
 		}
-	};
 
-	if !is_combined {
-		return Response::builder()
-			.status(StatusCode::BAD_REQUEST)
-			.body("Cannot add contributors to a solo playlist".to_string())
-			.unwrap();
-	}
+		diesel::insert_into(playlist_shares::table)
+			.values(PlaylistShare {
+				playlist_id: payload.playlist_id.clone(),
+				contributor_user_id: payload.contributor_user_id.clone(),
+			})
+			.execute(conn)
+			.map_err(|err| ServiceError::Internal(format!("Failed to add/update contributor: {err}")))
+	});
 
-	match diesel::insert_into(playlist_shares::table)
-		.values(&payload)
-		.execute(&mut db_conn)
-	{
-		Ok(_) => Response::builder()
+	match added {
+		Ok((_, version)) => Response::builder()
 			.status(StatusCode::OK)
+			.header(header::ETAG, etag(version))
 			.body("Successfully added or updated contributor".to_string())
 			.unwrap(),
-		Err(err) => {
-			let msg = format!("Failed to add/update contributor: {err}");
-			Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(msg)
-				.unwrap()
-		}
+		Err(err) => service.error_response(&payload.playlist_id, err),
 	}
 }
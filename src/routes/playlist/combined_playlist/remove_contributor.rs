@@ -1,7 +1,13 @@
 use crate::core::app_state::AppState;
 use crate::schema::playlist_shares;
+use crate::services::{PlaylistService, ServiceError};
+use crate::utils::precondition::{etag, expected_version};
 use axum::Json;
-use axum::{extract::State, http::status::StatusCode, response::Response};
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode, HeaderMap},
+	response::Response,
+};
 use diesel::prelude::*;
 use serde::Deserialize;
 
@@ -9,55 +15,45 @@ use serde::Deserialize;
 pub struct RemoveContributorPayload {
 	playlist_id: String,
 	contributor_user_id: String,
+	version: Option<i32>, // when not sent as If-Match
 }
 
 pub async fn remove_contributor(
 	State(app_state): State<AppState>,
+	headers: HeaderMap,
 	Json(payload): Json<RemoveContributorPayload>,
 ) -> Response<String> {
-	// Attempt to get a database connection from the pool
-	let mut db_conn = match app_state.db_pool.get() {
-		Ok(conn) => conn,
-		Err(err) => {
-			let msg = format!("Failed to get DB from pool: {err}");
-			return Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(msg)
-				.unwrap();
-		}
+	let expected_version = match expected_version(&headers, payload.version) {
+		Ok(version) => version,
+		Err(response) => return response,
 	};
 
-	// Attempt to delete the contributor from the playlist_shares table
-	match diesel::delete(
-		playlist_shares::table.filter(
-			playlist_shares::playlist_id
-				.eq(payload.playlist_id)
-				.and(playlist_shares::contributor_user_id.eq(payload.contributor_user_id)),
-		),
-	)
-	.execute(&mut db_conn)
-	{
-		Ok(0) => {
-			// No rows were affected, meaning the contributor was not found
-			Response::builder()
-				.status(StatusCode::NOT_FOUND)
-				.body("Contributor not found".to_string())
-				.unwrap()
-		}
-		Ok(_) => {
-			// Contributor was successfully removed
-			Response::builder()
-				.status(StatusCode::OK)
-				.body("Successfully removed contributor".to_string())
-				.unwrap()
-		}
-		Err(err) => {
-			// An error occurred while trying to remove the contributor
-			let msg = format!("Failed to remove contributor: {err}");
-			Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(msg)
-				.unwrap()
+	let service = PlaylistService::new(&app_state.db_pool);
+	let removed = service.versioned(&payload.playlist_id, expected_version, |conn| {
+		// Attempt to delete the contributor from the playlist_shares table
+		let removed = diesel::delete(
+			playlist_shares::table.filter(
+				playlist_shares::playlist_id
+					.eq(&payload.playlist_id)
+					.and(playlist_shares::contributor_user_id.eq(&payload.contributor_user_id)),
+			),
+		)
+		.execute(conn)
+		.map_err(|err| ServiceError::Internal(format!("Failed to remove contributor: {err}")))?;
+
+		// No rows were affected, meaning the contributor was not found
+		match removed {
+			0 => Err(ServiceError::NotFound("Contributor not found".to_string())),
+			_ => Ok(()),
 		}
+	});
+
+	match removed {
+		Ok(((), version)) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::ETAG, etag(version))
+			.body("Successfully removed contributor".to_string())
+			.unwrap(),
+		Err(err) => service.error_response(&payload.playlist_id, err),
 	}
 }
@@ -1,18 +1,12 @@
-use std::fs;
-use std::path::Path;
-
-use crate::lobic_db::models::Playlist;
-use crate::{config::PLAYLIST_COVER_IMG_STORAGE, core::app_state::AppState};
+use crate::core::app_state::AppState;
+use crate::services::PlaylistService;
 use axum::{
 	body::Bytes,
 	extract::{Query, State},
 	http::status::StatusCode,
 	response::Response,
 };
-use chrono::Utc;
-use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
-use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PlaylistParams {
@@ -31,52 +25,9 @@ pub async fn create_playlist(
 	Query(params): Query<PlaylistParams>,
 	body: Bytes,
 ) -> Response<String> {
-	let mut db_conn = match app_state.db_pool.get() {
-		Ok(conn) => conn,
-		Err(err) => {
-			let response = ApiResponse {
-				message: format!("Failed to get DB from pool: {err}"),
-			};
-			return Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(serde_json::to_string(&response).unwrap())
-				.unwrap();
-		}
-	};
-	use crate::schema::playlists::dsl::*;
-	let curr_playlist_id = Uuid::new_v4(); //now a user can create a playlist with the same name
-	let curr_creation_date_time = Utc::now().to_rfc3339();
-	let new_playlist = Playlist {
-		playlist_id: curr_playlist_id.to_string(),
-		playlist_name: params.playlist_name,
-		user_id: params.user_id,
-		creation_date_time: curr_creation_date_time.clone(),
-		last_updated_date_time: curr_creation_date_time,
-		is_playlist_combined: params.is_playlist_combined,
-	};
-
-	//save the image inside the storage
-	let storage_path = Path::new(PLAYLIST_COVER_IMG_STORAGE);
-	if let Err(err) = fs::create_dir_all(storage_path) {
-		return Response::builder()
-			.status(StatusCode::INTERNAL_SERVER_ERROR)
-			.body(format!("Failed to create directory: {}", err))
-			.unwrap();
-	}
-	if !body.is_empty() {
-		let image_path = storage_path.join(format!("{}.png", curr_playlist_id));
-		if let Err(err) = fs::write(&image_path, body) {
-			return Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(format!("Failed to save image: {}", err))
-				.unwrap();
-		}
-	}
-	match diesel::insert_into(playlists)
-		.values(&new_playlist)
-		.execute(&mut db_conn)
-	{
-		Ok(_) => {
+	let playlist_service = PlaylistService::new(&app_state.db_pool);
+	match playlist_service.create(params.playlist_name, params.user_id, params.is_playlist_combined, &body) {
+		Ok(new_playlist) => {
 			let response = ApiResponse {
 				message: format!("Playlist created with ID: {}", new_playlist.playlist_id),
 			};
@@ -86,13 +37,95 @@ pub async fn create_playlist(
 				.unwrap()
 		}
 		Err(err) => {
+			let status = err.status();
 			let response = ApiResponse {
-				message: format!("Failed to create playlist: {}", err),
+				message: err.to_string(),
 			};
 			Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.status(status)
 				.body(serde_json::to_string(&response).unwrap())
 				.unwrap()
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::music;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn playlist_lifecycle() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let music_id = music::table
+			.select(music::music_id)
+			.first::<String>(&mut test_app.db_conn())
+			.unwrap();
+
+		// Create
+		let response = test_app
+			.request(
+				Method::POST,
+				&format!("/playlist/new?playlist_name=Road%20Trip&user_id={user_id}&is_playlist_combined=false"),
+				None,
+				&[],
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::CREATED, "{}", response.body);
+		let message = response.json()["message"].as_str().unwrap().to_string();
+		let playlist_id = message.rsplit(' ').next().unwrap().to_string();
+
+		let playlists = test_app
+			.get(&format!("/playlist/get_users_playlists?user_uuid={user_id}"))
+			.await
+			.json();
+		assert_eq!(playlists["user_id"], user_id.as_str());
+		assert!(playlists["items"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.any(|playlist| playlist["playlist_id"] == playlist_id.as_str()));
+
+		// Add a song, edits have to say which version they were made against
+		let add_song = |version: Option<i32>| json!({ "playlist_id": playlist_id, "music_id": music_id, "song_adder_id": user_id, "version": version });
+		let response = test_app.post("/playlist/add_song", add_song(None)).await;
+		assert_eq!(response.status, StatusCode::PRECONDITION_REQUIRED);
+		let response = test_app.post("/playlist/add_song", add_song(Some(1))).await;
+		assert_eq!(response.status, StatusCode::CREATED, "{}", response.body);
+
+		let details = test_app
+			.get(&format!("/playlist/get_by_uuid?playlist_id={playlist_id}"))
+			.await
+			.json();
+		assert_eq!(details["playlist"]["playlist_name"], "Road Trip");
+		assert_eq!(details["songs"][0]["music_id"], music_id.as_str());
+		assert_eq!(details["playlist"]["version"], 2);
+
+		// Remove it again, twice, the failed removal leaves the version as it is
+		let payload = json!({ "playlist_id": playlist_id, "music_id": music_id, "version": 2 });
+		let response = test_app.post("/playlist/remove_song_from_playlist", payload).await;
+		assert_eq!(response.status, StatusCode::OK);
+		let payload = json!({ "playlist_id": playlist_id, "music_id": music_id, "version": 3 });
+		let response = test_app.post("/playlist/remove_song_from_playlist", payload).await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+		// A collaborator still on the first version gets the playlist as it is now
+		let response = test_app.post("/playlist/add_song", add_song(Some(1))).await;
+		assert_eq!(response.status, StatusCode::CONFLICT);
+		let body = response.json();
+		assert_eq!(body["current"]["playlist"]["version"], 3);
+		assert_eq!(body["current"]["songs"], json!([]));
+
+		// Delete
+		let uri = format!("/playlist/delete/{playlist_id}?version=3");
+		let response = test_app.request(Method::POST, &uri, None, &[]).await;
+		assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+		let response = test_app.request(Method::POST, &uri, None, &[]).await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+	}
+}
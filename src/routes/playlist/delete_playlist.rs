@@ -1,72 +1,46 @@
 use crate::core::app_state::AppState;
-use axum::{extract::State, http::status::StatusCode, response::Response};
-use diesel::prelude::*;
+use crate::services::PlaylistService;
+use crate::utils::precondition::expected_version;
+use axum::{
+	extract::{Query, State},
+	http::{header, status::StatusCode, HeaderMap},
+	response::Response,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct DeletePlaylistQuery {
+	pub version: Option<i32>, // when not sent as If-Match
+}
 
 pub async fn delete_playlist(
 	State(app_state): State<AppState>,
+	headers: HeaderMap,
 	axum::extract::Path(curr_playlist_id): axum::extract::Path<String>,
+	Query(query): Query<DeletePlaylistQuery>,
 ) -> Response<String> {
-	// Get a database connection from the pool
-	let mut db_conn = match app_state.db_pool.get() {
-		Ok(conn) => conn,
-		Err(err) => {
-			return Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(format!("Failed to get DB from pool: {err}"))
-				.unwrap();
-		}
+	let expected_version = match expected_version(&headers, query.version) {
+		Ok(version) => version,
+		Err(response) => return response,
 	};
 
-	// Use the playlists table for deletion
-	use crate::schema::playlists::dsl::*;
-
-	// Delete associated records in playlist_songs
-	let songs_deleted = match diesel::delete(crate::schema::playlist_songs::dsl::playlist_songs)
-		.filter(crate::schema::playlist_songs::dsl::playlist_id.eq(&curr_playlist_id))
-		.execute(&mut db_conn)
-	{
-		Ok(count) => count,
-		Err(err) => {
-			return Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(format!("Failed to delete playlist songs: {}", err))
-				.unwrap();
+	let service = PlaylistService::new(&app_state.db_pool);
+	match service.delete(&curr_playlist_id, expected_version) {
+		Ok(((songs_deleted, shares_deleted), undo)) => {
+			let body = json!({
+				"message": format!(
+					"Playlist deleted. Songs deleted: {}, Shares deleted: {}",
+					songs_deleted, shares_deleted
+				),
+				"undo": undo,
+			});
+			Response::builder()
+				.status(StatusCode::OK)
+				.header(header::CONTENT_TYPE, "application/json")
+				.body(body.to_string())
+				.unwrap()
 		}
-	};
-
-	// Delete associated records in playlist_shares
-	let shares_deleted = match diesel::delete(crate::schema::playlist_shares::dsl::playlist_shares)
-		.filter(crate::schema::playlist_shares::dsl::playlist_id.eq(&curr_playlist_id))
-		.execute(&mut db_conn)
-	{
-		Ok(count) => count,
-		Err(err) => {
-			return Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(format!("Failed to delete playlist shares: {}", err))
-				.unwrap();
-		}
-	};
-
-	// Delete the playlist itself
-	match diesel::delete(playlists)
-		.filter(playlist_id.eq(&curr_playlist_id))
-		.execute(&mut db_conn)
-	{
-		Ok(0) => Response::builder()
-			.status(StatusCode::NOT_FOUND)
-			.body("No playlist found to delete".to_string())
-			.unwrap(),
-		Ok(_) => Response::builder()
-			.status(StatusCode::OK)
-			.body(format!(
-				"Playlist deleted. Songs deleted: {}, Shares deleted: {}",
-				songs_deleted, shares_deleted
-			))
-			.unwrap(),
-		Err(err) => Response::builder()
-			.status(StatusCode::INTERNAL_SERVER_ERROR)
-			.body(format!("Failed to delete playlist: {}", err))
-			.unwrap(),
+		Err(err) => service.error_response(&curr_playlist_id, err),
 	}
 }
@@ -0,0 +1,260 @@
+use crate::core::app_state::AppState;
+use crate::services::playlist_folder::FolderTree;
+use crate::services::{PlaylistFolderService, ServiceError};
+use crate::utils::auth::require_user;
+
+use axum::{
+	extract::{Path, State},
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct NewFolderPayload {
+	pub name: String,
+	pub parent_id: Option<String>, // at the top when left out
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenameFolderPayload {
+	pub folder_id: String,
+	pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveFolderPayload {
+	pub folder_id: String,
+	pub parent_id: Option<String>, // to the top when left out
+	pub position: Option<usize>,   // 0 is first, last when left out
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MovePlaylistPayload {
+	pub playlist_id: String,
+	pub folder_id: Option<String>, // to the top when left out
+	pub position: Option<usize>,   // 0 is first, last when left out
+}
+
+fn tree_response(result: Result<FolderTree, ServiceError>) -> Response<String> {
+	match result {
+		Ok(tree) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&tree).unwrap())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :get_playlist_folders
+// The playlists of the user in their folders, the ones never put in one are at the top
+pub async fn get_playlist_folders(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	tree_response(PlaylistFolderService::new(&app_state.db_pool).tree(&user_id))
+}
+
+// :create_playlist_folder
+pub async fn create_playlist_folder(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<NewFolderPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let service = PlaylistFolderService::new(&app_state.db_pool);
+	match service.create(&user_id, &payload.name, payload.parent_id.as_deref()) {
+		Ok(folder) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&folder).unwrap())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :rename_playlist_folder
+pub async fn rename_playlist_folder(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<RenameFolderPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let service = PlaylistFolderService::new(&app_state.db_pool);
+	tree_response(service.rename(&user_id, &payload.folder_id, &payload.name))
+}
+
+// :move_playlist_folder
+// Reorders the folder, or moves it into another one along with everything in it
+pub async fn move_playlist_folder(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<MoveFolderPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let service = PlaylistFolderService::new(&app_state.db_pool);
+	tree_response(service.move_folder(
+		&user_id,
+		&payload.folder_id,
+		payload.parent_id.as_deref(),
+		payload.position,
+	))
+}
+
+// :delete_playlist_folder
+// Only the folder goes, the playlists and folders in it move up a level
+pub async fn delete_playlist_folder(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(folder_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	tree_response(PlaylistFolderService::new(&app_state.db_pool).delete(&user_id, &folder_id))
+}
+
+// :move_playlist
+// Reorders the playlist, or moves it into another folder. Only for where this user sees it.
+pub async fn move_playlist(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<MovePlaylistPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let service = PlaylistFolderService::new(&app_state.db_pool);
+	tree_response(service.move_playlist(
+		&user_id,
+		&payload.playlist_id,
+		payload.folder_id.as_deref(),
+		payload.position,
+	))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::lobic_db::models::{Availability, Playlist};
+	use crate::schema::playlists;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::{json, Value};
+	use uuid::Uuid;
+
+	fn ids(playlists: &Value) -> Vec<String> {
+		playlists
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|playlist| playlist["playlist_id"].as_str().unwrap().to_string())
+			.collect()
+	}
+
+	#[tokio::test]
+	async fn playlists_are_kept_in_nested_folders() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		for n in 1..3 {
+			let created = format!("2030-01-0{n}T00:00:00+00:00");
+			diesel::insert_into(playlists::table)
+				.values(Playlist {
+					playlist_id: Uuid::new_v4().to_string(),
+					playlist_name: format!("More {n}"),
+					user_id: user_id.clone(),
+					creation_date_time: created.clone(),
+					last_updated_date_time: created,
+					is_playlist_combined: false,
+					availability: Availability::Available.as_str().to_string(),
+					availability_reason: None,
+					version: 0,
+				})
+				.execute(&mut test_app.db_conn())
+				.unwrap();
+		}
+		let owned = playlists::table
+			.filter(playlists::user_id.eq(&user_id))
+			.order(playlists::creation_date_time.asc())
+			.select(playlists::playlist_id)
+			.load::<String>(&mut test_app.db_conn())
+			.unwrap();
+		let cookies = test_app.login("seed_user_0").await;
+
+		let tree = test_app
+			.request(Method::GET, "/playlist/folders", None, &cookies)
+			.await
+			.json();
+		assert_eq!(ids(&tree["playlists"]), owned);
+
+		let new_folder = |name: &str, parent_id: Option<&str>| {
+			let payload = json!({ "name": name, "parent_id": parent_id });
+			test_app.request(Method::POST, "/playlist/folders/new", Some(payload), &cookies)
+		};
+		let mixes = new_folder("Mixes", None).await.json();
+		let mixes_id = mixes["folder_id"].as_str().unwrap();
+		let gym = new_folder("Gym", Some(mixes_id)).await.json();
+		let gym_id = gym["folder_id"].as_str().unwrap();
+
+		let payload = json!({ "playlist_id": owned[2], "folder_id": gym_id });
+		test_app
+			.request(Method::POST, "/playlist/folders/move_playlist", Some(payload), &cookies)
+			.await;
+		let payload = json!({ "playlist_id": owned[1], "position": 0 });
+		let tree = test_app
+			.request(Method::POST, "/playlist/folders/move_playlist", Some(payload), &cookies)
+			.await
+			.json();
+		assert_eq!(ids(&tree["playlists"]), [owned[1].clone(), owned[0].clone()]);
+		assert_eq!(tree["folders"][0]["name"], "Mixes");
+		assert_eq!(ids(&tree["folders"][0]["folders"][0]["playlists"]), [owned[2].clone()]);
+
+		// not into itself
+		let payload = json!({ "folder_id": mixes_id, "parent_id": gym_id });
+		let response = test_app
+			.request(Method::POST, "/playlist/folders/move", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+		// what was in it moves up
+		let uri = format!("/playlist/folders/delete/{mixes_id}");
+		let tree = test_app.request(Method::POST, &uri, None, &cookies).await.json();
+		assert_eq!(tree["folders"][0]["name"], "Gym");
+		assert_eq!(ids(&tree["folders"][0]["playlists"]), [owned[2].clone()]);
+
+		// the folders are only the user's own
+		let cookies = test_app.login("seed_user_1").await;
+		let payload = json!({ "folder_id": gym_id, "name": "Mine now" });
+		let response = test_app
+			.request(Method::POST, "/playlist/folders/rename", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+		let payload = json!({ "playlist_id": owned[0] });
+		let response = test_app
+			.request(Method::POST, "/playlist/folders/move_playlist", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+	}
+}
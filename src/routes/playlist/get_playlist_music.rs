@@ -1,15 +1,15 @@
 use crate::core::app_state::AppState;
+use crate::core::artwork::cover_uuid;
+use crate::utils::{auth::session_user_id, precondition::etag};
 use axum::{
 	body::Body,
 	extract::{Query, State},
 	http::{header, status::StatusCode},
 	response::Response,
 };
+use axum_extra::extract::cookie::CookieJar;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::hash::Hash;
-use std::{collections::hash_map::DefaultHasher, hash::Hasher};
-use uuid::Uuid;
 
 #[derive(Queryable)]
 struct MusicQueryResult {
@@ -19,6 +19,7 @@ struct MusicQueryResult {
 	album: String,
 	genre: String,
 	duration: i64,
+	availability: String,
 	song_added_date_time: String,
 	song_adder_id: String,
 }
@@ -32,6 +33,7 @@ pub struct PlaylistMusicResponse {
 	pub genre: String,
 	pub duration: i64,
 	pub image_url: String,
+	pub availability: String, // unavailable songs are kept in the playlist but cannot be streamed
 	pub song_added_date_time: String,
 	pub song_adder_id: String,
 }
@@ -41,7 +43,7 @@ pub struct PlaylistQueryParams {
 	pub playlist_id: String,
 }
 
-use crate::lobic_db::models::Playlist;
+use crate::lobic_db::models::{Availability, Playlist};
 #[derive(Debug, Serialize)]
 pub struct PlaylistDetailsResponse {
 	pub playlist: Playlist,
@@ -50,11 +52,7 @@ pub struct PlaylistDetailsResponse {
 
 impl PlaylistMusicResponse {
 	fn from_query_result(result: MusicQueryResult) -> Self {
-		let mut hasher = DefaultHasher::new();
-		result.artist.hash(&mut hasher);
-		result.album.hash(&mut hasher);
-		let hash = hasher.finish();
-		let img_uuid = Uuid::from_u64_pair(hash, hash);
+		let img_uuid = cover_uuid(&result.artist, &result.album);
 
 		PlaylistMusicResponse {
 			music_id: result.music_id,
@@ -64,6 +62,7 @@ impl PlaylistMusicResponse {
 			genre: result.genre,
 			duration: result.duration,
 			image_url: img_uuid.to_string(),
+			availability: result.availability,
 			song_added_date_time: result.song_added_date_time,
 			song_adder_id: result.song_adder_id,
 		}
@@ -72,6 +71,7 @@ impl PlaylistMusicResponse {
 
 pub async fn get_playlist_music(
 	State(app_state): State<AppState>,
+	jar: CookieJar,
 	Query(params): Query<PlaylistQueryParams>,
 ) -> Response<Body> {
 	let mut db_conn = match app_state.db_pool.get() {
@@ -101,10 +101,27 @@ pub async fn get_playlist_music(
 		}
 	};
 
+	// Taken down playlists are not served at all, private ones only to their owner
+	let owner_sees = playlist.availability == Availability::Private.as_str()
+		&& session_user_id(&jar).is_some_and(|user_id| user_id == playlist.user_id);
+	if playlist.availability != Availability::Available.as_str() && !owner_sees {
+		let body = serde_json::json!({
+			"playlist_id": playlist.playlist_id,
+			"availability": playlist.availability,
+			"reason": playlist.availability_reason,
+		});
+		return Response::builder()
+			.status(StatusCode::GONE)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(Body::from(body.to_string()))
+			.unwrap();
+	}
+
 	// Fetch songs in the playlist with correct type mapping
 	let query_results = playlist_songs::table
 		.filter(playlist_songs::playlist_id.eq(&params.playlist_id))
 		.inner_join(music::table)
+		.order(playlist_songs::position.asc())
 		.select((
 			music::music_id,
 			music::artist,
@@ -112,6 +129,7 @@ pub async fn get_playlist_music(
 			music::album,
 			music::genre,
 			music::duration,
+			music::availability,
 			playlist_songs::song_added_date_time,
 			playlist_songs::song_adder_id,
 		))
@@ -130,12 +148,14 @@ pub async fn get_playlist_music(
 		}
 	};
 
-	// Construct the final response
+	// Construct the final response, the ETag is what edits send back as If-Match
+	let version = playlist.version;
 	let response = PlaylistDetailsResponse { playlist, songs };
 
 	Response::builder()
 		.status(StatusCode::OK)
 		.header(header::CONTENT_TYPE, "application/json")
+		.header(header::ETAG, etag(version))
 		.body(Body::from(serde_json::to_string(&response).unwrap()))
 		.unwrap()
 }
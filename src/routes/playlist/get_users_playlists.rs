@@ -1,10 +1,18 @@
 use crate::core::app_state::AppState;
+use crate::lobic_db::models::Availability;
 use crate::lobic_db::models::Playlist;
 use crate::lobic_db::models::PlaylistInfo;
 use crate::lobic_db::models::UserPlaylistsResponse;
 use crate::schema::playlist_shares;
 use crate::schema::playlists;
-use axum::{extract::Query, extract::State, http::status::StatusCode, response::Response};
+use crate::utils::{auth::session_user_id, list::ListResponse};
+use axum::{
+	extract::Query,
+	extract::State,
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
 use diesel::prelude::*;
 use serde::Deserialize;
 use serde::Serialize;
@@ -17,10 +25,14 @@ pub struct ApiResponse {
 #[derive(Debug, Deserialize)]
 pub struct UserPlaylistsQuery {
 	pub user_uuid: String,
+	#[serde(default)]
+	pub start_index: i64,
+	pub page_length: Option<i64>,
 }
 
 pub async fn get_users_playlists(
 	State(app_state): State<AppState>,
+	jar: CookieJar,
 	Query(query): Query<UserPlaylistsQuery>,
 ) -> Response<String> {
 	let user_uuid = query.user_uuid; // Extract user_uuid from query parameters
@@ -48,43 +60,51 @@ pub async fn get_users_playlists(
 				.eq(&user_uuid) // Owned playlists
 				.or(playlist_shares::contributor_user_id.eq(&user_uuid)), // Shared with user as contributor
 		)
+		// Hiding taken down playlists, and the private ones from everyone but their owner
+		.filter(
+			playlists::availability
+				.eq(Availability::Available.as_str())
+				.or(playlists::availability.eq(Availability::Private.as_str()).and(
+					playlists::user_id
+						.nullable()
+						.eq(session_user_id(&jar).filter(|id| *id == user_uuid)),
+				)),
+		)
 		.select(playlists::all_columns) // Explicitly select only playlists table columns
 		.distinct() // Add this to avoid duplicate results
 		.load::<Playlist>(&mut db_conn);
 
 	match result {
 		Ok(user_playlists) => {
-			if user_playlists.is_empty() {
-				let response = ApiResponse {
-					message: "No playlists found for this user".to_string(),
-				};
-				Response::builder()
-					.status(StatusCode::NOT_FOUND)
-					.body(serde_json::to_string(&response).unwrap())
-					.unwrap()
-			} else {
-				// Map the Playlist objects to PlaylistInfo
-				let playlists_info: Vec<PlaylistInfo> = user_playlists
-					.into_iter()
-					.map(|playlist| PlaylistInfo {
-						user_id: playlist.user_id,
-						playlist_id: playlist.playlist_id,
-						playlist_name: playlist.playlist_name,
-						creation_date_time: playlist.creation_date_time,
-						last_updated_date_time: playlist.last_updated_date_time,
-						is_playlist_combined: playlist.is_playlist_combined,
-					})
-					.collect();
+			// Map the Playlist objects to PlaylistInfo
+			let playlists_info: Vec<PlaylistInfo> = user_playlists
+				.into_iter()
+				.map(|playlist| PlaylistInfo {
+					user_id: playlist.user_id,
+					playlist_id: playlist.playlist_id,
+					playlist_name: playlist.playlist_name,
+					creation_date_time: playlist.creation_date_time,
+					last_updated_date_time: playlist.last_updated_date_time,
+					is_playlist_combined: playlist.is_playlist_combined,
+				})
+				.collect();
 
-				let response = UserPlaylistsResponse {
-					user_id: user_uuid,
-					playlists: playlists_info,
-				};
-				Response::builder()
-					.status(StatusCode::OK)
-					.body(serde_json::to_string(&response).unwrap())
-					.unwrap()
-			}
+			// The join needs the distinct, so the page is cut out here
+			let total_count = playlists_info.len() as i64;
+			let page: Vec<PlaylistInfo> = playlists_info
+				.into_iter()
+				.skip(query.start_index.max(0) as usize)
+				.take(query.page_length.filter(|length| *length > 0).unwrap_or(i64::MAX) as usize)
+				.collect();
+			let response = UserPlaylistsResponse {
+				user_id: user_uuid,
+				playlists: ListResponse::page(page, total_count, query.start_index, query.page_length),
+			};
+			Response::builder()
+				.status(StatusCode::OK)
+				.header(header::CONTENT_TYPE, "application/json")
+				.body(serde_json::to_string(&response).unwrap())
+				.unwrap()
 		}
 		Err(err) => {
 			let response = ApiResponse {
@@ -0,0 +1,176 @@
+use crate::core::app_state::AppState;
+use crate::services::PlaylistService;
+use crate::utils::auth::require_user;
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::{Path, State},
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct ScheduleReleasePayload {
+	pub playlist_id: String,
+	pub release_date_time: String, // rfc3339, in the future
+}
+
+// :schedule_playlist_release
+// The playlist turns private right away, only its owner sees it until the release
+pub async fn schedule_playlist_release(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<ScheduleReleasePayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	match PlaylistService::new(&app_state.db_pool).schedule_release(
+		&user_id,
+		&payload.playlist_id,
+		&payload.release_date_time,
+	) {
+		Ok(release) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&release).unwrap())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :get_playlist_releases
+pub async fn get_playlist_releases(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	match PlaylistService::new(&app_state.db_pool).releases(&user_id) {
+		Ok(releases) => ListResponse::all(releases).into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :cancel_playlist_release
+pub async fn cancel_playlist_release(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(playlist_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	match PlaylistService::new(&app_state.db_pool).cancel_release(&user_id, &playlist_id) {
+		Ok(()) => Response::builder()
+			.status(StatusCode::OK)
+			.body("Release called off".to_string())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::core::playlist_releases;
+	use crate::lobic_db::models::UserFriendship;
+	use crate::schema::{notifications, playlist_releases as releases, playlists, user_friendship};
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use chrono::{Duration, Utc};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[derive(Debug, QueryableByName)]
+	struct ForeignKeyCheck {
+		#[diesel(sql_type = diesel::sql_types::Text)]
+		#[allow(dead_code)]
+		parent: String,
+	}
+
+	#[tokio::test]
+	async fn scheduled_playlists_stay_private_until_released() {
+		let test_app = TestApp::seeded();
+		let (owner_id, follower_id) = (test_app.user_id("seed_user_0"), test_app.user_id("seed_user_1"));
+		let cookies = test_app.login("seed_user_0").await;
+		let playlist_id = playlists::table
+			.filter(playlists::user_id.eq(&owner_id))
+			.select(playlists::playlist_id)
+			.first::<String>(&mut test_app.db_conn())
+			.unwrap();
+		diesel::replace_into(user_friendship::table)
+			.values(&UserFriendship {
+				user_id: follower_id.clone(),
+				friend_id: owner_id.clone(),
+			})
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+
+		let release_date_time = (Utc::now() + Duration::days(7)).to_rfc3339();
+		let payload = json!({ "playlist_id": playlist_id, "release_date_time": release_date_time });
+		let response = test_app
+			.request(Method::POST, "/playlist/releases/schedule", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+		let uri = format!("/playlist/get_by_uuid?playlist_id={playlist_id}");
+		assert_eq!(test_app.get(&uri).await.status, StatusCode::GONE);
+		assert_eq!(
+			test_app.request(Method::GET, &uri, None, &cookies).await.status,
+			StatusCode::OK
+		);
+		let listed = format!("/playlist/get_users_playlists?user_uuid={owner_id}");
+		let own = test_app.request(Method::GET, &listed, None, &cookies).await.json();
+		assert_eq!(own["total_count"], 1);
+		assert_eq!(test_app.get(&listed).await.json()["total_count"], 0);
+
+		// not yet due
+		playlist_releases::release_due(&test_app.app_state).unwrap();
+		assert_eq!(test_app.get(&uri).await.status, StatusCode::GONE);
+
+		// kept through the trash without leaving a dangling reference
+		let version = playlists::table
+			.find(&playlist_id)
+			.select(playlists::version)
+			.first::<i32>(&mut test_app.db_conn())
+			.unwrap();
+		let delete = format!("/playlist/delete/{playlist_id}?version={version}");
+		let response = test_app.request(Method::POST, &delete, None, &cookies).await;
+		assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+		let dangling = diesel::sql_query("SELECT parent FROM pragma_foreign_key_check")
+			.load::<ForeignKeyCheck>(&mut test_app.db_conn())
+			.unwrap();
+		assert!(dangling.is_empty(), "{dangling:?}");
+		let restore = format!("/playlists/trash/restore/{playlist_id}");
+		let response = test_app.request(Method::POST, &restore, None, &cookies).await;
+		assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+		assert_eq!(test_app.get(&uri).await.status, StatusCode::GONE);
+
+		diesel::update(releases::table.find(&playlist_id))
+			.set(releases::release_date_time.eq(Utc::now().to_rfc3339()))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		playlist_releases::release_due(&test_app.app_state).unwrap();
+		assert_eq!(test_app.get(&uri).await.status, StatusCode::OK);
+		let notified = notifications::table
+			.filter(notifications::user_id.eq(&follower_id))
+			.filter(notifications::op_code.eq("\"PLAYLIST_RELEASED\""))
+			.count()
+			.get_result::<i64>(&mut test_app.db_conn())
+			.unwrap();
+		assert_eq!(notified, 1);
+		let releases = test_app
+			.request(Method::GET, "/playlist/releases", None, &cookies)
+			.await
+			.json();
+		assert_eq!(releases["items"], json!([]));
+	}
+}
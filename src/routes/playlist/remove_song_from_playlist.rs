@@ -1,58 +1,46 @@
 use crate::core::app_state::AppState;
-use crate::schema::playlist_songs::dsl::*;
-use axum::{extract::State, http::status::StatusCode, response::Response, Json};
-use diesel::prelude::*;
+use crate::services::PlaylistService;
+use crate::utils::precondition::{etag, expected_version};
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode, HeaderMap},
+	response::Response,
+	Json,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RemoveSongFromPlaylist {
 	pub playlist_id: String,
 	pub music_id: String,
+	pub version: Option<i32>, // when not sent as If-Match
 }
 
 pub async fn remove_song_from_playlist(
 	State(app_state): State<AppState>,
+	headers: HeaderMap,
 	Json(payload): Json<RemoveSongFromPlaylist>,
 ) -> Response<String> {
-	let mut db_conn = match app_state.db_pool.get() {
-		Ok(conn) => conn,
-		Err(err) => {
-			return Response::builder()
-				.status(StatusCode::INTERNAL_SERVER_ERROR)
-				.body(format!("Failed to get DB from pool: {err}"))
-				.unwrap();
-		}
+	let expected_version = match expected_version(&headers, payload.version) {
+		Ok(version) => version,
+		Err(response) => return response,
 	};
 
-	match diesel::delete(playlist_songs)
-		.filter(music_id.eq(&payload.music_id))
-		.filter(playlist_id.eq(&payload.playlist_id))
-		.execute(&mut db_conn)
-	{
-		Ok(rows_deleted) => {
-			if rows_deleted > 0 {
-				// If a record was deleted
-				Response::builder()
-					.status(StatusCode::OK)
-					.body(format!(
-						"song {} removed from playlist {}",
-						payload.music_id, payload.playlist_id
-					))
-					.unwrap()
-			} else {
-				// If no record was found to delete
-				Response::builder()
-					.status(StatusCode::NOT_FOUND)
-					.body(format!(
-						"song {} NOT FOUND playlist {}",
-						payload.music_id, payload.playlist_id
-					))
-					.unwrap()
-			}
+	let service = PlaylistService::new(&app_state.db_pool);
+	match service.remove_song(&payload.playlist_id, &payload.music_id, expected_version) {
+		Ok((version, undo)) => {
+			let body = json!({
+				"message": format!("song {} removed from playlist {}", payload.music_id, payload.playlist_id),
+				"undo": undo,
+			});
+			Response::builder()
+				.status(StatusCode::OK)
+				.header(header::CONTENT_TYPE, "application/json")
+				.header(header::ETAG, etag(version))
+				.body(body.to_string())
+				.unwrap()
 		}
-		Err(err) => Response::builder()
-			.status(StatusCode::INTERNAL_SERVER_ERROR)
-			.body(format!("Failed to remove song from playlist: {}", err))
-			.unwrap(),
+		Err(err) => service.error_response(&payload.playlist_id, err),
 	}
 }
@@ -0,0 +1,309 @@
+use crate::core::app_state::AppState;
+use crate::lobic_db::models::{Music, MusicResponse, SavedSearch};
+use crate::routes::search::{search_songs, SearchQuery};
+use crate::schema::saved_searches;
+use crate::utils::auth::require_user;
+use crate::utils::list::ListResponse;
+use crate::utils::response::db_error;
+
+use axum::{
+	extract::{Path, Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct SaveSearchPayload {
+	pub name: String,
+	pub search_string: String, // operators included, artist:"daft punk" -live
+	pub genre: Option<String>,
+	pub decade: Option<String>,
+	pub bpm_min: Option<f64>,
+	pub bpm_max: Option<f64>,
+	pub key: Option<String>,
+}
+
+// The search as it was saved and a page of the songs it finds right now
+#[derive(Debug, Serialize)]
+pub struct SavedSearchResponse {
+	pub saved_search: SavedSearch,
+	pub songs: ListResponse<MusicResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SongsPageQuery {
+	#[serde(default)]
+	pub start_index: i64,
+	pub page_length: Option<i64>, // SAVED_SEARCH_PAGE_LENGTH when left out, and at most that
+}
+
+// :save_search
+// The filters are checked by running the search once, a bad decade or key is a 400 here and not on
+// every open
+pub async fn save_search(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<SaveSearchPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	if payload.name.trim().is_empty() {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body("Name cannot be empty".to_string())
+			.unwrap();
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+
+	let saved_search = SavedSearch {
+		search_id: Uuid::new_v4().to_string(),
+		user_id,
+		name: payload.name.trim().to_string(),
+		search_string: payload.search_string,
+		genre: payload.genre,
+		decade: payload.decade,
+		bpm_min: payload.bpm_min,
+		bpm_max: payload.bpm_max,
+		musical_key: payload.key,
+		created_date_time: Utc::now().to_rfc3339(),
+	};
+	let songs = match search_songs(&mut db_conn, &SearchQuery::from(&saved_search), 0, None) {
+		Ok(songs) => songs,
+		Err(msg) => {
+			return Response::builder().status(StatusCode::BAD_REQUEST).body(msg).unwrap();
+		}
+	};
+	if let Err(err) = diesel::insert_into(saved_searches::table)
+		.values(&saved_search)
+		.execute(&mut db_conn)
+	{
+		return db_error(err);
+	}
+
+	saved_search_response(saved_search, songs)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserSavedSearchesQuery {
+	pub user_uuid: String,
+	#[serde(default)]
+	pub start_index: i64,
+	pub page_length: Option<i64>,
+}
+
+// :get_users_saved_searches
+// Paged the same way as get_users_playlists, the client lists both as the user's collections
+pub async fn get_users_saved_searches(
+	State(app_state): State<AppState>,
+	Query(query): Query<UserSavedSearchesQuery>,
+) -> Response<String> {
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+
+	let owned = saved_searches::table.filter(saved_searches::user_id.eq(&query.user_uuid));
+	let total_count = match owned.count().get_result::<i64>(&mut db_conn) {
+		Ok(count) => count,
+		Err(err) => return db_error(err),
+	};
+	let mut page = owned
+		.order(saved_searches::created_date_time.asc())
+		.offset(query.start_index.max(0))
+		.into_boxed();
+	if let Some(page_length) = query.page_length.filter(|length| *length > 0) {
+		page = page.limit(page_length);
+	}
+	match page.load::<SavedSearch>(&mut db_conn) {
+		Ok(items) => ListResponse::page(items, total_count, query.start_index, query.page_length).into_response(),
+		Err(err) => db_error(err),
+	}
+}
+
+// :get_saved_search
+// Runs the search again, songs added or retagged since it was saved show up. Only for its owner.
+pub async fn get_saved_search(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(search_id): Path<String>,
+	Query(page): Query<SongsPageQuery>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+
+	let saved_search = match saved_searches::table
+		.filter(saved_searches::search_id.eq(&search_id))
+		.filter(saved_searches::user_id.eq(&user_id))
+		.first::<SavedSearch>(&mut db_conn)
+		.optional()
+	{
+		Ok(Some(saved_search)) => saved_search,
+		Ok(None) => {
+			return Response::builder()
+				.status(StatusCode::NOT_FOUND)
+				.body(format!("No saved search {search_id} of yours"))
+				.unwrap();
+		}
+		Err(err) => return db_error(err),
+	};
+	let songs = search_songs(
+		&mut db_conn,
+		&SearchQuery::from(&saved_search),
+		page.start_index,
+		page.page_length,
+	);
+	match songs {
+		Ok(songs) => saved_search_response(saved_search, songs),
+		Err(msg) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(msg)
+			.unwrap(),
+	}
+}
+
+// :delete_saved_search
+pub async fn delete_saved_search(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(search_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+
+	let deleted = diesel::delete(
+		saved_searches::table
+			.filter(saved_searches::search_id.eq(&search_id))
+			.filter(saved_searches::user_id.eq(&user_id)),
+	)
+	.execute(&mut db_conn);
+	match deleted {
+		Ok(0) => Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("No saved search {search_id} of yours"))
+			.unwrap(),
+		Ok(_) => Response::builder()
+			.status(StatusCode::OK)
+			.body("Saved search deleted".to_string())
+			.unwrap(),
+		Err(err) => db_error(err),
+	}
+}
+
+fn saved_search_response(saved_search: SavedSearch, songs: ListResponse<Music>) -> Response<String> {
+	let response = SavedSearchResponse {
+		saved_search,
+		songs: songs.map(Music::create_music_response),
+	};
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(serde_json::to_string(&response).unwrap())
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::music;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn saved_searches_pick_up_new_matches() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
+		let music_ids = music::table
+			.select(music::music_id)
+			.limit(2)
+			.load::<String>(&mut test_app.db_conn())
+			.unwrap();
+		diesel::update(music::table.find(&music_ids[0]))
+			.set(music::artist.eq("Daft Punk"))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+
+		let payload = json!({ "name": "Daft Punk", "search_string": "artist:\"daft punk\"" });
+		let body = test_app
+			.request(Method::POST, "/playlist/saved_search/new", Some(payload), &cookies)
+			.await
+			.json();
+		let search_id = body["saved_search"]["search_id"].as_str().unwrap().to_string();
+		assert_eq!(body["songs"]["total_count"], 1);
+
+		diesel::update(music::table.find(&music_ids[1]))
+			.set(music::artist.eq("Daft Punk"))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let uri = format!("/playlist/saved_search/get/{search_id}");
+		let body = test_app.request(Method::GET, &uri, None, &cookies).await.json();
+		assert_eq!(body["songs"]["total_count"], 2);
+		let uri = format!("/playlist/saved_search/get/{search_id}?start_index=1&page_length=1");
+		let body = test_app.request(Method::GET, &uri, None, &cookies).await.json();
+		assert_eq!(body["songs"]["items"].as_array().unwrap().len(), 1);
+		assert_eq!(body["songs"]["next"], serde_json::Value::Null);
+
+		// only the owner opens it
+		let others = test_app.login("seed_user_1").await;
+		let response = test_app.request(Method::GET, &uri, None, &others).await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+		let response = test_app.get(&uri).await;
+		assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+		let user_id = test_app.user_id("seed_user_0");
+		let body = test_app
+			.get(&format!(
+				"/playlist/saved_search/get_users_saved_searches?user_uuid={user_id}"
+			))
+			.await
+			.json();
+		assert_eq!(body["total_count"], 1);
+		assert_eq!(body["items"][0]["name"], "Daft Punk");
+		// next to the playlists
+		let tree = test_app
+			.request(Method::GET, "/playlist/folders", None, &cookies)
+			.await
+			.json();
+		assert_eq!(tree["saved_searches"][0]["search_id"], search_id.as_str());
+
+		let payload = json!({ "name": "Bad", "search_string": "", "decade": "soon" });
+		let response = test_app
+			.request(Method::POST, "/playlist/saved_search/new", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+		// only the owner deletes it
+		let uri = format!("/playlist/saved_search/delete/{search_id}");
+		let response = test_app.request(Method::POST, &uri, None, &others).await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+		let response = test_app.request(Method::POST, &uri, None, &cookies).await;
+		assert_eq!(response.status, StatusCode::OK);
+	}
+}
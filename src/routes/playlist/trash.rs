@@ -0,0 +1,122 @@
+use crate::core::app_state::AppState;
+use crate::services::PlaylistService;
+use crate::utils::auth::require_user;
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::{Path, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+
+// :get_playlist_trash
+// The playlists the user deleted, apart from the ones still listed. Each stays until its purge_date_time.
+pub async fn get_playlist_trash(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	match PlaylistService::new(&app_state.db_pool).trash(&user_id) {
+		Ok(trashed) => ListResponse::all(trashed).into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :restore_trashed_playlist
+// Back with its songs and contributors, at the next version
+pub async fn restore_trashed_playlist(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(playlist_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	match PlaylistService::new(&app_state.db_pool).restore(&user_id, &playlist_id) {
+		Ok(state) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&state).unwrap())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :purge_trashed_playlist
+// Can't be undone, not even with an undo token from the delete
+pub async fn purge_trashed_playlist(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(playlist_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	match PlaylistService::new(&app_state.db_pool).purge(&user_id, &playlist_id) {
+		Ok(()) => Response::builder()
+			.status(StatusCode::OK)
+			.body("Playlist deleted for good".to_string())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::playlists;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+
+	#[tokio::test]
+	async fn deleted_playlists_wait_in_the_trash() {
+		let test_app = TestApp::seeded();
+		let owner_id = test_app.user_id("seed_user_0");
+		let (playlist_id, version) = playlists::table
+			.filter(playlists::user_id.eq(&owner_id))
+			.select((playlists::playlist_id, playlists::version))
+			.first::<(String, i32)>(&mut test_app.db_conn())
+			.unwrap();
+		let uri = format!("/playlist/delete/{playlist_id}?version={version}");
+		let response = test_app.request(Method::POST, &uri, None, &[]).await;
+		assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+		let cookies = test_app.login("seed_user_0").await;
+		let trash = test_app
+			.request(Method::GET, "/playlists/trash", None, &cookies)
+			.await
+			.json();
+		assert_eq!(trash["items"][0]["playlist_id"], playlist_id.as_str());
+		assert!(trash["items"][0].get("snapshot").is_none());
+
+		// only for the owner
+		let others = test_app.login("seed_user_1").await;
+		let restore = format!("/playlists/trash/restore/{playlist_id}");
+		let response = test_app.request(Method::POST, &restore, None, &others).await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+		let restored = test_app.request(Method::POST, &restore, None, &cookies).await.json();
+		assert_eq!(restored["playlist"]["playlist_id"], playlist_id.as_str());
+		let trash = test_app
+			.request(Method::GET, "/playlists/trash", None, &cookies)
+			.await
+			.json();
+		assert_eq!(trash["total_count"], 0);
+
+		let version = restored["playlist"]["version"].as_i64().unwrap();
+		let uri = format!("/playlist/delete/{playlist_id}?version={version}");
+		test_app.request(Method::POST, &uri, None, &[]).await;
+		let purge = format!("/playlists/trash/delete/{playlist_id}");
+		let response = test_app.request(Method::POST, &purge, None, &cookies).await;
+		assert_eq!(response.status, StatusCode::OK);
+		let response = test_app.request(Method::POST, &restore, None, &cookies).await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+	}
+}
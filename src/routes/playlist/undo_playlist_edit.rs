@@ -0,0 +1,97 @@
+use crate::core::app_state::AppState;
+use crate::services::PlaylistService;
+use crate::utils::precondition::etag;
+use axum::{
+	extract::{Path, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+
+// Restores what a removal, clear or delete took away, returns the playlist as it is after
+pub async fn undo_playlist_edit(State(app_state): State<AppState>, Path(token): Path<String>) -> Response<String> {
+	match PlaylistService::new(&app_state.db_pool).undo(&token) {
+		Ok(state) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.header(header::ETAG, etag(state.playlist.version))
+			.body(serde_json::to_string(&state).unwrap())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::music;
+	use crate::services::PlaylistService;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn cleared_and_deleted_playlists_come_back() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let music_ids = music::table
+			.select(music::music_id)
+			.limit(2)
+			.load::<String>(&mut test_app.db_conn())
+			.unwrap();
+		let service = PlaylistService::new(&test_app.app_state.db_pool);
+		let playlist_id = service
+			.create("Gym".to_string(), user_id.clone(), false, &[])
+			.unwrap()
+			.playlist_id;
+		for (version, music_id) in music_ids.iter().enumerate() {
+			service
+				.add_song(
+					playlist_id.clone(),
+					music_id.clone(),
+					user_id.clone(),
+					version as i32 + 1,
+				)
+				.unwrap();
+		}
+
+		let body = test_app
+			.post("/playlist/clear", json!({ "playlist_id": playlist_id, "version": 3 }))
+			.await
+			.json();
+		let token = body["undo"]["token"].as_str().unwrap().to_string();
+		assert_eq!(service.state(&playlist_id).unwrap().songs.len(), 0);
+
+		let response = test_app
+			.request(Method::POST, &format!("/undo/{token}"), None, &[])
+			.await;
+		assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+		let body = response.json();
+		assert_eq!(body["songs"].as_array().unwrap().len(), 2);
+		assert_eq!(body["playlist"]["version"], 5);
+		let response = test_app
+			.request(Method::POST, &format!("/undo/{token}"), None, &[])
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+		let body = test_app
+			.request(
+				Method::POST,
+				&format!("/playlist/delete/{playlist_id}?version=5"),
+				None,
+				&[],
+			)
+			.await
+			.json();
+		let token = body["undo"]["token"].as_str().unwrap().to_string();
+		assert!(service.state(&playlist_id).is_err());
+
+		let body = test_app
+			.request(Method::POST, &format!("/undo/{token}"), None, &[])
+			.await
+			.json();
+		assert_eq!(body["playlist"]["playlist_name"], "Gym");
+		assert_eq!(body["playlist"]["version"], 7);
+		assert_eq!(body["songs"].as_array().unwrap().len(), 2);
+	}
+}
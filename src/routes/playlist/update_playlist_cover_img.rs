@@ -1,6 +1,14 @@
 use crate::config::PLAYLIST_COVER_IMG_STORAGE;
+use crate::core::app_state::AppState;
+use crate::services::{PlaylistService, ServiceError};
+use crate::utils::precondition::{etag, expected_version};
 
-use axum::{body::Bytes, extract::Query, http::StatusCode, response::Response};
+use axum::{
+	body::Bytes,
+	extract::{Query, State},
+	http::{header, HeaderMap, StatusCode},
+	response::Response,
+};
 use serde::Deserialize;
 use std::fs;
 use std::path::Path;
@@ -9,9 +17,15 @@ use uuid::Uuid;
 #[derive(Deserialize)]
 pub struct PlaylistId {
 	playlist_id: String,
+	version: Option<i32>, // when not sent as If-Match
 }
 
-pub async fn update_playlist_cover_img(Query(playlist_id): Query<PlaylistId>, body: Bytes) -> Response<String> {
+pub async fn update_playlist_cover_img(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	Query(playlist_id): Query<PlaylistId>,
+	body: Bytes,
+) -> Response<String> {
 	let uuid = match Uuid::parse_str(&playlist_id.playlist_id) {
 		Ok(uuid) => uuid,
 		Err(_) => {
@@ -21,25 +35,27 @@ pub async fn update_playlist_cover_img(Query(playlist_id): Query<PlaylistId>, bo
 				.unwrap();
 		}
 	};
+	let expected_version = match expected_version(&headers, playlist_id.version) {
+		Ok(version) => version,
+		Err(response) => return response,
+	};
 
-	let storage_path = Path::new(PLAYLIST_COVER_IMG_STORAGE);
-	if let Err(err) = fs::create_dir_all(storage_path) {
-		return Response::builder()
-			.status(StatusCode::INTERNAL_SERVER_ERROR)
-			.body(format!("Failed to create directory: {}", err))
-			.unwrap();
-	}
+	let service = PlaylistService::new(&app_state.db_pool);
+	let saved = service.versioned(&playlist_id.playlist_id, expected_version, |_| {
+		let storage_path = Path::new(PLAYLIST_COVER_IMG_STORAGE);
+		fs::create_dir_all(storage_path)
+			.map_err(|err| ServiceError::Internal(format!("Failed to create directory: {}", err)))?;
 
-	let image_path = storage_path.join(format!("{}.png", uuid));
-	if let Err(err) = fs::write(&image_path, body) {
-		return Response::builder()
-			.status(StatusCode::INTERNAL_SERVER_ERROR)
-			.body(format!("Failed to save image: {}", err))
-			.unwrap();
-	}
+		let image_path = storage_path.join(format!("{}.png", uuid));
+		fs::write(&image_path, body).map_err(|err| ServiceError::Internal(format!("Failed to save image: {}", err)))
+	});
 
-	Response::builder()
-		.status(StatusCode::OK)
-		.body("Cover image updated successfully".to_string())
-		.unwrap()
+	match saved {
+		Ok(((), version)) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::ETAG, etag(version))
+			.body("Cover image updated successfully".to_string())
+			.unwrap(),
+		Err(err) => service.error_response(&playlist_id.playlist_id, err),
+	}
 }
@@ -0,0 +1,268 @@
+use crate::core::app_state::AppState;
+use crate::core::queue_snapshots::save;
+use crate::lobic_db::models::{MusicResponse, QueueSnapshot};
+use crate::schema::queue_snapshots;
+use crate::services::MusicService;
+use crate::utils::auth::require_user;
+use crate::utils::list::ListResponse;
+use crate::utils::response::{db_error, json_response};
+
+use axum::{
+	extract::{Path, State},
+	http::status::StatusCode,
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+pub struct QueueSnapshotPayload {
+	pub name: String,
+	pub music_ids: Option<Vec<String>>, // the queue last reported in a heartbeat when left out
+	pub position: Option<f64>,
+}
+
+// The snapshot and the tracks it still has, taken down or deleted ones are only listed as missing
+#[derive(Debug, Serialize)]
+pub struct RestoredQueue {
+	pub snapshot: QueueSnapshot,
+	pub items: Vec<MusicResponse>,
+	pub missing: Vec<String>,
+}
+
+// :get_queue_snapshots
+// Newest first, the automatic ones taken on logout included
+pub async fn get_queue_snapshots(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	match queue_snapshots::table
+		.filter(queue_snapshots::user_id.eq(&user_id))
+		.order(queue_snapshots::created_date_time.desc())
+		.load::<QueueSnapshot>(&mut db_conn)
+	{
+		Ok(snapshots) => ListResponse::all(snapshots).into_response(),
+		Err(err) => db_error(err),
+	}
+}
+
+// :save_queue_snapshot
+pub async fn save_queue_snapshot(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<QueueSnapshotPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	if payload.name.trim().is_empty() {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body("Name cannot be empty".to_string())
+			.unwrap();
+	}
+
+	let now_playing = app_state.now_playing_pool.get(&user_id);
+	let (music_ids, position) = match payload.music_ids {
+		Some(music_ids) => (music_ids, payload.position.unwrap_or(0.0)),
+		None => match now_playing.and_then(|now_playing| Some((now_playing.queue?, now_playing.position))) {
+			Some((queue, position)) => (queue, payload.position.unwrap_or(position)),
+			None => (vec![], 0.0),
+		},
+	};
+	if music_ids.is_empty() {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body("There is no queue to save".to_string())
+			.unwrap();
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	match save(&user_id, payload.name.trim(), &music_ids, position, false, &mut db_conn) {
+		Ok(snapshot) => json_response(StatusCode::OK, &snapshot),
+		Err(err) => db_error(err),
+	}
+}
+
+// :restore_queue_snapshot
+// The client replaces its queue with the items and seeks the first one to the position
+pub async fn restore_queue_snapshot(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(snapshot_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	let snapshot = match queue_snapshots::table
+		.filter(queue_snapshots::snapshot_id.eq(&snapshot_id))
+		.filter(queue_snapshots::user_id.eq(&user_id))
+		.first::<QueueSnapshot>(&mut db_conn)
+		.optional()
+	{
+		Ok(Some(snapshot)) => snapshot,
+		Ok(None) => {
+			return Response::builder()
+				.status(StatusCode::NOT_FOUND)
+				.body(format!("No queue snapshot {snapshot_id} of yours"))
+				.unwrap();
+		}
+		Err(err) => return db_error(err),
+	};
+	drop(db_conn);
+
+	let music_ids: Vec<String> = serde_json::from_str(&snapshot.music_ids).unwrap_or_default();
+	match MusicService::new(&app_state.db_pool).lookup(&music_ids) {
+		Ok(lookup) => json_response(StatusCode::OK, &RestoredQueue {
+			snapshot,
+			items: lookup.items,
+			missing: lookup.missing,
+		}),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :delete_queue_snapshot
+pub async fn delete_queue_snapshot(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(snapshot_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => return db_error(err),
+	};
+	let deleted = diesel::delete(
+		queue_snapshots::table
+			.filter(queue_snapshots::snapshot_id.eq(&snapshot_id))
+			.filter(queue_snapshots::user_id.eq(&user_id)),
+	)
+	.execute(&mut db_conn);
+	match deleted {
+		Ok(0) => Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("No queue snapshot {snapshot_id} of yours"))
+			.unwrap(),
+		Ok(_) => Response::builder()
+			.status(StatusCode::OK)
+			.body("Queue snapshot deleted".to_string())
+			.unwrap(),
+		Err(err) => db_error(err),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn queues_are_snapshotted_and_restored() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let cookies = test_app.login("seed_user_0").await;
+		let items = test_app.get("/music/get_music?page_length=3").await.json()["items"].clone();
+		let queue: Vec<_> = items
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|item| item["id"].clone())
+			.collect();
+
+		let payload = json!({ "name": "Friday set" });
+		let response = test_app
+			.request(
+				Method::POST,
+				"/player/queue_snapshots/new",
+				Some(payload.clone()),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+		let heartbeat = json!({ "music_id": queue[0], "position": 30.0, "state": "PLAY", "queue": queue });
+		test_app
+			.request(Method::POST, "/player/heartbeat", Some(heartbeat), &cookies)
+			.await;
+		let snapshot = test_app
+			.request(Method::POST, "/player/queue_snapshots/new", Some(payload), &cookies)
+			.await
+			.json();
+		assert_eq!(snapshot["position"], 30.0);
+
+		// an accidental replacement, then logging out keeps it as well
+		let replaced = json!({ "music_id": queue[2], "position": 5.0, "state": "PLAY", "queue": [queue[2]] });
+		test_app
+			.request(Method::POST, "/player/heartbeat", Some(replaced), &cookies)
+			.await;
+		// naming the user in the body isn't enough
+		let others = test_app.login("seed_user_1").await;
+		test_app
+			.request(
+				Method::POST,
+				"/logout",
+				Some(json!({ "user_id": user_id })),
+				&others,
+			)
+			.await;
+		let snapshots = test_app
+			.request(Method::GET, "/player/queue_snapshots", None, &cookies)
+			.await
+			.json();
+		assert_eq!(snapshots["total_count"], 1);
+		test_app
+			.request(
+				Method::POST,
+				"/logout",
+				Some(json!({ "user_id": user_id })),
+				&cookies,
+			)
+			.await;
+		let snapshots = test_app
+			.request(Method::GET, "/player/queue_snapshots", None, &cookies)
+			.await
+			.json();
+		assert_eq!(snapshots["total_count"], 2);
+		assert_eq!(snapshots["items"][0]["automatic"], true);
+
+		let uri = format!(
+			"/player/queue_snapshots/restore/{}",
+			snapshot["snapshot_id"].as_str().unwrap()
+		);
+		let response = test_app.request(Method::POST, &uri, None, &others).await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+		let body = test_app.request(Method::POST, &uri, None, &cookies).await.json();
+		let restored: Vec<_> = body["items"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|item| item["id"].clone())
+			.collect();
+		assert_eq!(restored, queue);
+	}
+}
@@ -0,0 +1,203 @@
+use crate::config::MAX_QUOTA;
+use crate::core::app_state::AppState;
+use crate::core::quotas::{self, Feature};
+use crate::lobic_db::db::set_instance_setting;
+use crate::utils::auth::{require_admin, require_user};
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+// :get_quotas
+// What the user has used of every quota today and when it resets
+pub async fn get_quotas(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+	let usages: Result<Vec<_>, _> = Feature::ALL
+		.iter()
+		.map(|feature| quotas::usage_of(&user_id, *feature, &mut db_conn))
+		.collect();
+	match usages {
+		Ok(usages) => ListResponse::all(usages).into_response(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap(),
+	}
+}
+
+fn limits(db_conn: &mut diesel::SqliteConnection) -> Value {
+	let limits: Map<String, Value> = Feature::ALL
+		.iter()
+		.map(|feature| (feature.as_str().to_string(), json!(feature.limit(db_conn))))
+		.collect();
+	Value::Object(limits)
+}
+
+// :get_quota_limits
+pub async fn get_quota_limits(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(limits(&mut db_conn).to_string())
+		.unwrap()
+}
+
+// :set_quota_limits
+// Per user and day, only the ones given change and 0 turns the feature off
+#[derive(Debug, Deserialize)]
+pub struct SetQuotaLimitsPayload {
+	pub transcode: Option<i64>,
+	pub export: Option<i64>,
+	pub metadata_lookup: Option<i64>,
+}
+
+pub async fn set_quota_limits(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<SetQuotaLimitsPayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let parts = [
+		(Feature::Transcode, payload.transcode),
+		(Feature::Export, payload.export),
+		(Feature::MetadataLookup, payload.metadata_lookup),
+	];
+	for (feature, limit) in &parts {
+		if let Some(limit) = limit.filter(|limit| !(0..=MAX_QUOTA).contains(limit)) {
+			return Response::builder()
+				.status(StatusCode::BAD_REQUEST)
+				.body(format!("Invalid {} quota: {limit}", feature.as_str()))
+				.unwrap();
+		}
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to get DB from pool: {err}"))
+				.unwrap();
+		}
+	};
+	for (feature, limit) in parts {
+		let Some(limit) = limit else {
+			continue;
+		};
+		if let Err(err) = set_instance_setting(&feature.setting_key(), &limit.to_string(), &mut db_conn) {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to save the setting: {err}"))
+				.unwrap();
+		}
+	}
+
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(limits(&mut db_conn).to_string())
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::users;
+	use crate::test_support::TestApp;
+
+	use axum::http::{header, Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn exports_stop_at_the_daily_quota() {
+		let test_app = TestApp::seeded();
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let admin = test_app.login("seed_user_1").await;
+		let host = test_app.login("seed_user_0").await;
+
+		let response = test_app
+			.request(Method::POST, "/admin/quotas", Some(json!({ "export": -1 })), &admin)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		let limits = test_app
+			.request(Method::POST, "/admin/quotas", Some(json!({ "export": 2 })), &admin)
+			.await
+			.json();
+		assert_eq!(limits["export"], 2);
+		assert_eq!(limits["transcode"], 10);
+
+		let app_state = &test_app.app_state;
+		let host_id = test_app.user_id("seed_user_0");
+		let lobby = app_state.lobby_pool.create_lobby(&host_id, &app_state.db_pool).unwrap();
+		let uri = format!("/lobby/{}/chat/export", lobby["lobby_id"].as_str().unwrap());
+		// a format that isn't exported doesn't count
+		let response = test_app
+			.request(Method::GET, &format!("{uri}?format=pdf"), None, &host)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		for _ in 0..2 {
+			let response = test_app.request(Method::GET, &uri, None, &host).await;
+			assert_eq!(response.status, StatusCode::OK);
+		}
+		let response = test_app.request(Method::GET, &uri, None, &host).await;
+		assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+		assert!(response.headers.contains_key(header::RETRY_AFTER));
+		let body = response.json();
+		assert_eq!(body["error"], "quota_exceeded");
+		assert_eq!(body["feature"], "export");
+
+		let usages = test_app.request(Method::GET, "/user/quotas", None, &host).await.json();
+		let export = usages["items"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.find(|usage| usage["feature"] == "export")
+			.unwrap();
+		assert_eq!(
+			(export["used"].as_i64(), export["remaining"].as_i64()),
+			(Some(2), Some(0))
+		);
+		// the quota is per user
+		let usages = test_app.request(Method::GET, "/user/quotas", None, &admin).await.json();
+		assert_eq!(usages["items"][1]["used"], 0);
+	}
+}
@@ -0,0 +1,166 @@
+use crate::config::{MAX_CHAT_RETENTION_HOURS, MAX_RETENTION_DAYS, MIN_PLAY_EVENT_RETENTION_DAYS};
+use crate::core::app_state::AppState;
+use crate::core::retention::{
+	RetentionPolicy, AUDIT_LOG_RETENTION_DAYS, CHAT_RETENTION_HOURS, PLAY_EVENT_RETENTION_DAYS,
+};
+use crate::lobic_db::db::set_instance_setting;
+use crate::utils::auth::require_admin;
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+
+// :get_retention_policy
+pub async fn get_retention_policy(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(serde_json::to_string(&RetentionPolicy::load(&mut db_conn)).unwrap())
+		.unwrap()
+}
+
+// :set_retention_policy
+// Only the windows given change, the pruning job picks them up on its next run
+#[derive(Debug, Deserialize)]
+pub struct SetRetentionPolicyPayload {
+	pub chat_hours: Option<i64>,
+	pub play_event_days: Option<i64>,
+	pub audit_log_days: Option<i64>,
+}
+
+pub async fn set_retention_policy(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<SetRetentionPolicyPayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let windows = [
+		(CHAT_RETENTION_HOURS, payload.chat_hours, 1..=MAX_CHAT_RETENTION_HOURS),
+		(
+			PLAY_EVENT_RETENTION_DAYS,
+			payload.play_event_days,
+			MIN_PLAY_EVENT_RETENTION_DAYS..=MAX_RETENTION_DAYS,
+		),
+		(AUDIT_LOG_RETENTION_DAYS, payload.audit_log_days, 1..=MAX_RETENTION_DAYS),
+	];
+	for (key, value, range) in &windows {
+		if let Some(value) = value.filter(|value| !range.contains(value)) {
+			return Response::builder()
+				.status(StatusCode::BAD_REQUEST)
+				.body(format!("Invalid {key}: {value}"))
+				.unwrap();
+		}
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	for (key, value, _) in windows {
+		let Some(value) = value else {
+			continue;
+		};
+		if let Err(err) = set_instance_setting(key, &value.to_string(), &mut db_conn) {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to save the setting: {err}"))
+				.unwrap();
+		}
+	}
+
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(serde_json::to_string(&RetentionPolicy::load(&mut db_conn)).unwrap())
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::users;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn admins_set_the_retention_windows() {
+		let test_app = TestApp::seeded();
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let cookies = test_app.login("seed_user_1").await;
+
+		let body = test_app
+			.request(Method::GET, "/admin/retention", None, &cookies)
+			.await
+			.json();
+		assert_eq!(body["play_event_days"], 400);
+
+		let response = test_app
+			.request(
+				Method::POST,
+				"/admin/retention",
+				Some(json!({ "play_event_days": 30 })),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		for payload in [
+			json!({ "play_event_days": i64::MAX }),
+			json!({ "audit_log_days": 0 }),
+			json!({ "audit_log_days": 36501 }),
+		] {
+			let response = test_app
+				.request(Method::POST, "/admin/retention", Some(payload), &cookies)
+				.await;
+			assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		}
+
+		let body = test_app
+			.request(
+				Method::POST,
+				"/admin/retention",
+				Some(json!({ "chat_hours": 2, "audit_log_days": 90 })),
+				&cookies,
+			)
+			.await
+			.json();
+		assert_eq!(
+			body,
+			json!({ "chat_hours": 2, "play_event_days": 400, "audit_log_days": 90 })
+		);
+	}
+}
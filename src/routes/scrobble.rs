@@ -0,0 +1,318 @@
+use crate::config::MAX_SCROBBLE_TOKENS;
+use crate::core::app_state::AppState;
+use crate::core::federation::{bearer_token, generate_token};
+use crate::lobic_db::models::ScrobbleToken;
+use crate::routes::music::log_song_play::{record_play, LogSongPlay};
+use crate::schema::scrobble_tokens;
+use crate::utils::auth::require_user;
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::{Path, State},
+	http::{header, status::StatusCode, HeaderMap},
+	response::{IntoResponse, Response},
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+// :create_scrobble_token
+// The token is only shown here. A scrobble token can report plays for the user and nothing else,
+// an mpd token logs MPD clients in to the bridge.
+#[derive(Debug, Deserialize)]
+pub struct CreateScrobbleTokenPayload {
+	pub name: String,
+	pub scope: Option<String>, // scrobble when left out
+}
+
+pub async fn create_scrobble_token(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<CreateScrobbleTokenPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	if payload.name.trim().is_empty() {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body("The device needs a name".to_string())
+			.unwrap();
+	}
+	let scope = payload.scope.as_deref().unwrap_or(ScrobbleToken::SCROBBLE);
+	if ![ScrobbleToken::SCROBBLE, ScrobbleToken::MPD].contains(&scope) {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid scope: {scope}"))
+			.unwrap();
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let token_count = scrobble_tokens::table
+		.filter(scrobble_tokens::user_id.eq(&user_id))
+		.count()
+		.get_result::<i64>(&mut db_conn)
+		.unwrap_or(0);
+	if token_count >= MAX_SCROBBLE_TOKENS {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!(
+				"At most {MAX_SCROBBLE_TOKENS} scrobble tokens, revoke one first"
+			))
+			.unwrap();
+	}
+
+	let scrobble_token = ScrobbleToken {
+		token_id: Uuid::new_v4().to_string(),
+		user_id,
+		name: payload.name.trim().to_string(),
+		token: generate_token(),
+		created_date_time: Utc::now().to_rfc3339(),
+		last_used_date_time: None,
+		scope: scope.to_string(),
+	};
+	if let Err(err) = diesel::insert_into(scrobble_tokens::table)
+		.values(&scrobble_token)
+		.execute(&mut db_conn)
+	{
+		return Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to create scrobble token: {err}"))
+			.unwrap();
+	}
+
+	let mut body = json!(scrobble_token);
+	body["token"] = scrobble_token.token.into();
+	Response::builder()
+		.status(StatusCode::CREATED)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(body.to_string())
+		.unwrap()
+}
+
+// :get_scrobble_tokens
+pub async fn get_scrobble_tokens(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	match scrobble_tokens::table
+		.filter(scrobble_tokens::user_id.eq(&user_id))
+		.order(scrobble_tokens::created_date_time.asc())
+		.load::<ScrobbleToken>(&mut db_conn)
+	{
+		Ok(tokens) => ListResponse::all(tokens).into_response(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap(),
+	}
+}
+
+// :revoke_scrobble_token
+pub async fn revoke_scrobble_token(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(token_id): Path<String>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let revoked = diesel::delete(
+		scrobble_tokens::table
+			.filter(scrobble_tokens::token_id.eq(&token_id))
+			.filter(scrobble_tokens::user_id.eq(&user_id)),
+	)
+	.execute(&mut db_conn);
+	match revoked {
+		Ok(0) => Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("No scrobble token: {token_id}"))
+			.unwrap(),
+		Ok(_) => Response::builder()
+			.status(StatusCode::OK)
+			.body(format!("Scrobble token {token_id} revoked"))
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to revoke scrobble token: {err}"))
+			.unwrap(),
+	}
+}
+
+// :report_play
+// Called by headless players with `Authorization: Bearer <scrobble token>`, no session needed
+#[derive(Debug, Deserialize)]
+pub struct ReportPlayPayload {
+	pub music_id: String,
+	pub listened_secs: Option<i64>,
+}
+
+pub async fn report_play(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	Json(payload): Json<ReportPlayPayload>,
+) -> Response {
+	let Some(token) = bearer_token(&headers) else {
+		return (StatusCode::UNAUTHORIZED, "Missing scrobble token").into_response();
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return (StatusCode::INTERNAL_SERVER_ERROR, msg).into_response();
+		}
+	};
+
+	let reporter = scrobble_tokens::table
+		.filter(scrobble_tokens::token.eq(token))
+		.filter(scrobble_tokens::scope.eq(ScrobbleToken::SCROBBLE))
+		.select((scrobble_tokens::user_id, scrobble_tokens::name))
+		.first::<(String, String)>(&mut db_conn)
+		.optional();
+	let (user_id, device) = match reporter {
+		Ok(Some(reporter)) => reporter,
+		Ok(None) => return (StatusCode::UNAUTHORIZED, "Invalid scrobble token").into_response(),
+		Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {err}")).into_response(),
+	};
+	let _ = diesel::update(scrobble_tokens::table.filter(scrobble_tokens::token.eq(token)))
+		.set(scrobble_tokens::last_used_date_time.eq(Utc::now().to_rfc3339()))
+		.execute(&mut db_conn);
+	drop(db_conn);
+
+	let play = LogSongPlay {
+		user_id,
+		music_id: payload.music_id,
+		listened_secs: payload.listened_secs,
+		device: Some(device),
+	};
+	record_play(&app_state, play).await
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::play_events;
+	use crate::test_support::TestApp;
+
+	use axum::http::{header, Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::{json, Value};
+
+	#[tokio::test]
+	async fn scrobble_tokens_only_report_plays() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let cookies = test_app.login("seed_user_0").await;
+		let body = test_app
+			.request(
+				Method::POST,
+				"/user/scrobble_tokens/new",
+				Some(json!({ "name": "Kitchen Pi" })),
+				&cookies,
+			)
+			.await
+			.json();
+		let token_id = body["token_id"].as_str().unwrap().to_string();
+		let bearer = format!("Bearer {}", body["token"].as_str().unwrap());
+		let plays = || {
+			play_events::table
+				.filter(play_events::user_id.eq(&user_id))
+				.count()
+				.get_result::<i64>(&mut test_app.db_conn())
+				.unwrap()
+		};
+		let before = plays();
+
+		let music_id = test_app.get("/music/get_music?page_length=1").await.json()["items"][0]["id"].clone();
+		let report = json!({ "music_id": music_id, "listened_secs": 42 });
+		let response = test_app
+			.request_with_headers(
+				Method::POST,
+				"/playlog/report",
+				Some(report.clone()),
+				&[],
+				&[(header::AUTHORIZATION, &bearer)],
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::CREATED, "{}", response.body);
+		assert_eq!(plays(), before + 1);
+
+		// Nothing else takes the token
+		let response = test_app
+			.request_with_headers(
+				Method::GET,
+				"/user/scrobble_tokens",
+				None,
+				&[],
+				&[(header::AUTHORIZATION, &bearer)],
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+		let body = test_app
+			.request(Method::GET, "/user/scrobble_tokens", None, &cookies)
+			.await
+			.json();
+		assert_eq!(body["items"][0]["name"], "Kitchen Pi");
+		assert_eq!(body["items"][0]["token"], Value::Null);
+
+		test_app
+			.request(
+				Method::POST,
+				&format!("/user/scrobble_tokens/revoke/{token_id}"),
+				None,
+				&cookies,
+			)
+			.await;
+		let response = test_app
+			.request_with_headers(
+				Method::POST,
+				"/playlog/report",
+				Some(report),
+				&[],
+				&[(header::AUTHORIZATION, &bearer)],
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+	}
+}
@@ -1,20 +1,51 @@
+use crate::config::SAVED_SEARCH_PAGE_LENGTH;
 use crate::core::app_state::AppState;
-use crate::lobic_db::models::{Music, MusicResponse, Playlist, PlaylistInfo, User, UserDataResponse};
-use crate::schema::{music, playlists, users};
+use crate::core::audio_analysis::MusicalKey;
+use crate::i18n::Locale;
+use crate::lobic_db::models::{
+	Availability, Music, MusicAltName, MusicResponse, Playlist, PlaylistInfo, SavedSearch, User, UserDataResponse,
+};
+use crate::schema::{music, music_alt_names, playlists, track_genres, users};
+use crate::services::music::{alt_names_by_track, name_forms, parse_decade};
+use crate::utils::{
+	fields::FieldsQuery,
+	list::ListResponse,
+	search_query::{contains_pattern, SearchTerms},
+};
 use axum::{
 	extract::{Query, State},
-	http::{header, StatusCode},
+	http::{header, HeaderMap, StatusCode},
 	response::Response,
 };
-use diesel::prelude::*;
+use axum_extra::extract::cookie::CookieJar;
+use diesel::{prelude::*, sqlite::Sqlite};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use strsim::jaro_winkler;
 
 #[derive(Deserialize)]
 pub struct SearchQuery {
-	search_category: String,
-	search_string: String,
+	pub search_category: String,
+	pub search_string: String, // may hold operators like artist:"daft punk" year:2001 -live, see SearchTerms
+	pub genre: Option<String>, // narrows the songs down to one of the facets
+	pub decade: Option<String>, // 1990, 1990s or 90s
+	pub bpm_min: Option<f64>,
+	pub bpm_max: Option<f64>,
+	pub key: Option<String>, // Am, A minor or the Camelot 8A
+}
+
+impl From<&SavedSearch> for SearchQuery {
+	fn from(saved: &SavedSearch) -> SearchQuery {
+		SearchQuery {
+			search_category: "all".to_string(),
+			search_string: saved.search_string.clone(),
+			genre: saved.genre.clone(),
+			decade: saved.decade.clone(),
+			bpm_min: saved.bpm_min,
+			bpm_max: saved.bpm_max,
+			key: saved.musical_key.clone(),
+		}
+	}
 }
 
 #[derive(Serialize)]
@@ -22,9 +53,29 @@ pub struct SearchResponse {
 	songs: Vec<MusicResponse>,
 	people: Vec<UserDataResponse>,
 	playlists: Vec<PlaylistInfo>,
+	genres: Vec<GenreFacet>, // genres of the matching songs, the most common first
+	terms: SearchTerms,      // how the search string was understood
 }
 
-pub async fn search(State(app_state): State<AppState>, Query(params): Query<SearchQuery>) -> Response<String> {
+#[derive(Serialize)]
+pub struct GenreFacet {
+	genre: String,
+	label: String, // in the user's language, filter on the genre
+	song_count: i64,
+}
+
+pub async fn search(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	jar: CookieJar,
+	Query(params): Query<SearchQuery>,
+	Query(fields): Query<FieldsQuery>,
+) -> Response<String> {
+	let fields = match fields.fields() {
+		Ok(fields) => fields,
+		Err(msg) => return Response::builder().status(StatusCode::BAD_REQUEST).body(msg).unwrap(),
+	};
+	let locale = Locale::negotiate(&headers, &jar, &app_state.db_pool);
 	let mut db_conn = match app_state.db_pool.get() {
 		Ok(conn) => conn,
 		Err(err) => {
@@ -36,21 +87,29 @@ pub async fn search(State(app_state): State<AppState>, Query(params): Query<Sear
 		}
 	};
 
+	let terms = SearchTerms::parse(&params.search_string);
+	let filters = match music_filters(&params, &terms) {
+		Ok(filters) => filters,
+		Err(msg) => {
+			return Response::builder().status(StatusCode::BAD_REQUEST).body(msg).unwrap();
+		}
+	};
+
 	let category = params.search_category.to_lowercase();
-	let search_string = params.search_string.to_lowercase();
+	let search_string = terms.text.to_lowercase();
 	let response = match category.as_str() {
 		"all" => {
 			// Define a constant limit for all searches
 			const SEARCH_LIMIT: i64 = 10;
 
+			let matching_music = || matching_music(&filters, &search_string);
+			let matching_ids = matching_music()
+				.select(music::music_id)
+				.load::<String>(&mut db_conn)
+				.unwrap_or_default();
+
 			// Search music with limit
-			let music_results = music::table
-				.filter(
-					music::title
-						.like(format!("%{}%", search_string))
-						.or(music::album.like(format!("%{}%", search_string)))
-						.or(music::artist.like(format!("%{}%", search_string))),
-				)
+			let music_results = matching_music()
 				.limit(SEARCH_LIMIT)
 				.load::<Music>(&mut db_conn)
 				.map(|entries| {
@@ -63,7 +122,7 @@ pub async fn search(State(app_state): State<AppState>, Query(params): Query<Sear
 
 			// Search users with limit
 			let people_results = users::table
-				.filter(users::username.like(format!("%{}%", search_string)))
+				.filter(users::username.like(contains_pattern(&search_string)).escape('\\'))
 				.limit(SEARCH_LIMIT)
 				.load::<User>(&mut db_conn)
 				.map(|entries| {
@@ -80,7 +139,12 @@ pub async fn search(State(app_state): State<AppState>, Query(params): Query<Sear
 
 			// Search playlists with limit
 			let playlist_results = playlists::table
-				.filter(playlists::playlist_name.like(format!("%{}%", search_string)))
+				.filter(
+					playlists::playlist_name
+						.like(contains_pattern(&search_string))
+						.escape('\\'),
+				)
+				.filter(playlists::availability.eq(Availability::Available.as_str()))
 				.limit(SEARCH_LIMIT)
 				.load::<Playlist>(&mut db_conn)
 				.unwrap_or_else(|_| vec![]);
@@ -100,10 +164,12 @@ pub async fn search(State(app_state): State<AppState>, Query(params): Query<Sear
 				songs: music_results,
 				people: people_results,
 				playlists: playlists_response,
+				genres: genre_facets(&matching_ids, locale, &mut db_conn),
+				terms,
 			}
 		}
 		"title" | "album" | "artist" => {
-			let all_music = match music::table.load::<Music>(&mut db_conn) {
+			let all_music = match available_music(&filters).load::<Music>(&mut db_conn) {
 				Ok(entries) => entries,
 				Err(err) => {
 					return Response::builder()
@@ -113,19 +179,24 @@ pub async fn search(State(app_state): State<AppState>, Query(params): Query<Sear
 				}
 			};
 
+			let alt_names = alt_names_by_track(&mut db_conn);
 			let search_results = all_music
 				.into_iter()
 				.map(|entry| {
-					let (score, exact_match) = calculate_music_score(&entry, &category, &search_string);
+					let alt_names = alt_names.get(&entry.music_id);
+					let (score, exact_match) = calculate_music_score(&entry, alt_names, &category, &search_string);
 					let weighted_score = if exact_match { 10000.0 } else { score };
 					(entry, weighted_score)
 				})
-				.filter(|(_, score)| *score > 9.0)
+				// only operators, the filters alone decide
+				.filter(|(_, score)| search_string.is_empty() || *score > 9.0)
 				.collect::<Vec<_>>();
 
 			let mut sorted_results = search_results;
 			sorted_results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
 
+			let matching_ids: Vec<String> = sorted_results.iter().map(|(entry, _)| entry.music_id.clone()).collect();
+
 			// Convert Music entries to MusicResponse with image URLs
 			let music_responses = sorted_results
 				.into_iter()
@@ -136,6 +207,8 @@ pub async fn search(State(app_state): State<AppState>, Query(params): Query<Sear
 				songs: music_responses,
 				people: vec![],
 				playlists: vec![],
+				genres: genre_facets(&matching_ids, locale, &mut db_conn),
+				terms,
 			}
 		}
 		"people" => {
@@ -174,10 +247,15 @@ pub async fn search(State(app_state): State<AppState>, Query(params): Query<Sear
 				songs: vec![],
 				people: people_response,
 				playlists: vec![],
+				genres: vec![],
+				terms,
 			}
 		}
 		"playlists" => {
-			let all_playlists = match playlists::table.load::<Playlist>(&mut db_conn) {
+			let all_playlists = match playlists::table
+				.filter(playlists::availability.eq(Availability::Available.as_str()))
+				.load::<Playlist>(&mut db_conn)
+			{
 				Ok(entries) => entries,
 				Err(err) => {
 					return Response::builder()
@@ -215,6 +293,8 @@ pub async fn search(State(app_state): State<AppState>, Query(params): Query<Sear
 				songs: vec![],
 				people: vec![],
 				playlists: playlist_response,
+				genres: vec![],
+				terms,
 			}
 		}
 		_ => {
@@ -226,7 +306,9 @@ pub async fn search(State(app_state): State<AppState>, Query(params): Query<Sear
 	};
 
 	// Serialize and return the response
-	match serde_json::to_string(&response) {
+	let mut body = serde_json::json!(response);
+	body["songs"] = serde_json::json!(fields.project_all(&response.songs));
+	match serde_json::to_string(&body) {
 		Ok(json) => Response::builder()
 			.status(StatusCode::OK)
 			.header(header::CONTENT_TYPE, "application/json")
@@ -239,30 +321,204 @@ pub async fn search(State(app_state): State<AppState>, Query(params): Query<Sear
 	}
 }
 
-fn calculate_music_score(entry: &Music, category: &str, search_string: &str) -> (f64, bool) {
+// Narrow the songs down on top of the search string
+struct MusicFilters {
+	genre: Option<String>,
+	decade: Option<i32>,
+	bpm_min: Option<f64>,
+	bpm_max: Option<f64>,
+	key: Option<MusicalKey>,
+	artist: Option<String>, // contained in the artist or one of its other forms
+	title: Option<String>,  // same for the title
+	album: Option<String>,
+	year: Option<(i32, i32)>,
+	excluded: Vec<String>, // in none of the title, artist or album
+}
+
+// The filters the query parameters and the operators in the search string ask for, the parameters win
+fn music_filters(params: &SearchQuery, terms: &SearchTerms) -> Result<MusicFilters, String> {
+	let decade = match params.decade.as_ref().or(terms.decade.as_ref()) {
+		Some(value) => Some(parse_decade(value).ok_or(format!("Invalid decade: {value}"))?),
+		None => None,
+	};
+	let key = match params.key.as_ref().or(terms.key.as_ref()) {
+		Some(value) => Some(MusicalKey::parse(value).ok_or(format!("Invalid key: {value}"))?),
+		None => None,
+	};
+	Ok(MusicFilters {
+		genre: params.genre.clone().or(terms.genre.clone()),
+		decade,
+		bpm_min: params.bpm_min.or(terms.bpm.map(|(min, _)| min)),
+		bpm_max: params.bpm_max.or(terms.bpm.map(|(_, max)| max)),
+		key,
+		artist: terms.artist.clone(),
+		title: terms.title.clone(),
+		album: terms.album.clone(),
+		year: terms.year,
+		excluded: terms.excluded.clone(),
+	})
+}
+
+// A page of the songs of the all category, the text anywhere in the title, album, artist or their
+// other forms. At most SAVED_SEARCH_PAGE_LENGTH songs.
+pub fn search_songs(
+	db_conn: &mut SqliteConnection,
+	params: &SearchQuery,
+	start_index: i64,
+	page_length: Option<i64>,
+) -> Result<ListResponse<Music>, String> {
+	let terms = SearchTerms::parse(&params.search_string);
+	let filters = music_filters(params, &terms)?;
+	let search_string = terms.text.to_lowercase();
+	let start_index = start_index.max(0);
+	let page_length = page_length
+		.filter(|length| *length > 0)
+		.map_or(SAVED_SEARCH_PAGE_LENGTH, |length| length.min(SAVED_SEARCH_PAGE_LENGTH));
+
+	let total_count = matching_music(&filters, &search_string)
+		.count()
+		.get_result::<i64>(db_conn)
+		.map_err(|err| format!("Database error: {err}"))?;
+	let songs = matching_music(&filters, &search_string)
+		.order((music::title.asc(), music::music_id.asc()))
+		.offset(start_index)
+		.limit(page_length)
+		.load::<Music>(db_conn)
+		.map_err(|err| format!("Database error: {err}"))?;
+	Ok(ListResponse::page(songs, total_count, start_index, Some(page_length)))
+}
+
+fn matching_music(filters: &MusicFilters, search_string: &str) -> music::BoxedQuery<'static, Sqlite> {
+	let pattern = contains_pattern(search_string);
+	let alt_matches = music_alt_names::table
+		.filter(music_alt_names::name.like(pattern.clone()).escape('\\'))
+		.select(music_alt_names::music_id);
+	available_music(filters).filter(
+		music::title
+			.like(pattern.clone())
+			.escape('\\')
+			.or(music::album.like(pattern.clone()).escape('\\'))
+			.or(music::artist.like(pattern).escape('\\'))
+			.or(music::music_id.eq_any(alt_matches)),
+	)
+}
+
+fn available_music(filters: &MusicFilters) -> music::BoxedQuery<'static, Sqlite> {
+	let mut query = music::table
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.into_boxed();
+	if let Some(genre) = filters.genre.clone() {
+		let tagged = track_genres::table
+			.filter(track_genres::genre.eq(genre))
+			.select(track_genres::music_id);
+		query = query.filter(music::music_id.eq_any(tagged));
+	}
+	if let Some(decade) = filters.decade {
+		query = query.filter(music::release_year.between(decade, decade + 9));
+	}
+	if let Some(bpm_min) = filters.bpm_min {
+		query = query.filter(music::bpm.ge(bpm_min));
+	}
+	if let Some(bpm_max) = filters.bpm_max {
+		query = query.filter(music::bpm.le(bpm_max));
+	}
+	if let Some(key) = filters.key {
+		query = query.filter(music::musical_key.eq(key.name()));
+	}
+	for (field, column_value) in [
+		(MusicAltName::ARTIST, &filters.artist),
+		(MusicAltName::TITLE, &filters.title),
+	] {
+		let Some(pattern) = column_value.as_deref().map(contains_pattern) else {
+			continue;
+		};
+		let alt_matches = music_alt_names::table
+			.filter(music_alt_names::field.eq(field))
+			.filter(music_alt_names::name.like(pattern.clone()).escape('\\'))
+			.select(music_alt_names::music_id);
+		query = match field {
+			MusicAltName::ARTIST => query.filter(
+				music::artist
+					.like(pattern)
+					.escape('\\')
+					.or(music::music_id.eq_any(alt_matches)),
+			),
+			_ => query.filter(
+				music::title
+					.like(pattern)
+					.escape('\\')
+					.or(music::music_id.eq_any(alt_matches)),
+			),
+		};
+	}
+	if let Some(album) = &filters.album {
+		query = query.filter(music::album.like(contains_pattern(album)).escape('\\'));
+	}
+	if let Some((from, to)) = filters.year {
+		query = query.filter(music::release_year.between(from, to));
+	}
+	for word in &filters.excluded {
+		let pattern = contains_pattern(word);
+		query = query.filter(
+			music::title
+				.not_like(pattern.clone())
+				.escape('\\')
+				.and(music::artist.not_like(pattern.clone()).escape('\\'))
+				.and(music::album.not_like(pattern).escape('\\')),
+		);
+	}
+	query
+}
+
+fn genre_facets(music_ids: &[String], locale: Locale, db_conn: &mut SqliteConnection) -> Vec<GenreFacet> {
+	track_genres::table
+		.filter(track_genres::music_id.eq_any(music_ids))
+		.group_by(track_genres::genre)
+		.select((track_genres::genre, diesel::dsl::count(track_genres::music_id)))
+		.order((diesel::dsl::count(track_genres::music_id).desc(), track_genres::genre))
+		.load::<(String, i64)>(db_conn)
+		.unwrap_or_default()
+		.into_iter()
+		.map(|(genre, song_count)| GenreFacet {
+			label: locale.genre_label(&genre),
+			genre,
+			song_count,
+		})
+		.collect()
+}
+
+// Titles and artists score by their best matching form, the tagged one or another
+fn calculate_music_score(
+	entry: &Music,
+	alt_names: Option<&Vec<MusicAltName>>,
+	category: &str,
+	search_string: &str,
+) -> (f64, bool) {
 	let search_term = search_string.to_lowercase();
 
 	let contains_search_term = |field: &str| -> f64 { field.to_lowercase().contains(&search_term) as i32 as f64 * 8.0 };
+	let best_form = |forms: Vec<&str>, weight: f64, contains_weight: f64| {
+		forms
+			.into_iter()
+			.map(|form| {
+				let exact = form.to_lowercase() == search_term;
+				let score = jaro_winkler(form, search_string) * weight + contains_search_term(form) * contains_weight;
+				(score, exact)
+			})
+			.fold((0.0_f64, false), |(best, any_exact), (score, exact)| {
+				(best.max(score), any_exact || exact)
+			})
+	};
 
 	match category {
-		"title" => {
-			let exact = entry.title.eq_ignore_ascii_case(search_string);
-			let similarity = jaro_winkler(&entry.title, search_string);
-			let contains_bonus = contains_search_term(&entry.title) * 0.75;
-			(similarity * 12.0 + contains_bonus, exact)
-		}
+		"title" => best_form(name_forms(&entry.title, alt_names, MusicAltName::TITLE), 12.0, 0.75),
 		"album" => {
 			let exact = entry.album.eq_ignore_ascii_case(search_string);
 			let similarity = jaro_winkler(&entry.album, search_string);
 			let contains_bonus = contains_search_term(&entry.album);
 			(similarity * 6.0 + contains_bonus, exact)
 		}
-		"artist" => {
-			let exact = entry.artist.eq_ignore_ascii_case(search_string);
-			let similarity = jaro_winkler(&entry.artist, search_string);
-			let contains_bonus = contains_search_term(&entry.artist);
-			(similarity * 15.0 + contains_bonus, exact)
-		}
+		"artist" => best_form(name_forms(&entry.artist, alt_names, MusicAltName::ARTIST), 15.0, 1.0),
 		_ => (0.0, false),
 	}
 }
@@ -284,3 +540,128 @@ fn calculate_playlist_score(entry: &Playlist, search_string: &str) -> (f64, bool
 	let contains_bonus = contains_search_term(&entry.playlist_name) * 0.75;
 	(similarity * 12.0 + contains_bonus, exact)
 }
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::music;
+	use crate::test_support::TestApp;
+
+	use diesel::prelude::*;
+
+	#[tokio::test]
+	async fn genre_facets_narrow_the_songs_down() {
+		let test_app = TestApp::seeded();
+
+		let body = test_app.get("/search?search_category=all&search_string=").await.json();
+		let facets = body["genres"].as_array().unwrap();
+		assert!(!facets.is_empty());
+		let (genre, song_count) = (facets[0]["genre"].as_str().unwrap(), &facets[0]["song_count"]);
+
+		let body = test_app
+			.get(&format!(
+				"/search?search_category=all&search_string=&genre={}",
+				genre.replace(' ', "%20")
+			))
+			.await
+			.json();
+		let facets = body["genres"].as_array().unwrap();
+		assert_eq!(facets[0]["genre"], genre);
+		assert_eq!(&facets[0]["song_count"], song_count);
+	}
+
+	#[tokio::test]
+	async fn songs_can_be_narrowed_to_a_decade() {
+		let test_app = TestApp::seeded();
+
+		let body = test_app
+			.get("/search?search_category=title&search_string=a&decade=1980s")
+			.await
+			.json();
+		for song in body["songs"].as_array().unwrap() {
+			assert!((1980..1990).contains(&song["release_year"].as_i64().unwrap()));
+		}
+
+		let response = test_app
+			.get("/search?search_category=all&search_string=&decade=80ish")
+			.await;
+		assert_eq!(response.status, axum::http::StatusCode::BAD_REQUEST);
+	}
+
+	#[tokio::test]
+	async fn songs_can_be_narrowed_to_a_tempo_and_key() {
+		let test_app = TestApp::seeded();
+
+		let body = test_app
+			.get("/search?search_category=all&search_string=&bpm_min=100&bpm_max=130&include=audio")
+			.await
+			.json();
+		let songs = body["songs"].as_array().unwrap();
+		assert!(!songs.is_empty());
+		for song in songs {
+			assert!((100.0..=130.0).contains(&song["bpm"].as_f64().unwrap()));
+		}
+
+		// Camelot and spelled out keys are the same filter
+		let camelot = test_app
+			.get("/search?search_category=all&search_string=&key=8A&include=audio")
+			.await
+			.json();
+		let named = test_app
+			.get("/search?search_category=all&search_string=&key=A%20minor&include=audio")
+			.await
+			.json();
+		assert_eq!(camelot["songs"], named["songs"]);
+		assert_eq!(camelot["songs"][0]["camelot"], "8A");
+
+		let response = test_app.get("/music/get_music?key=H").await;
+		assert_eq!(response.status, axum::http::StatusCode::BAD_REQUEST);
+	}
+
+	#[tokio::test]
+	async fn operators_in_the_search_string_become_filters() {
+		let test_app = TestApp::seeded();
+		let music_ids = music::table
+			.select(music::music_id)
+			.limit(2)
+			.load::<String>(&mut test_app.db_conn())
+			.unwrap();
+		for (music_id, title) in music_ids.iter().zip(["One More Time", "One More Time (Live)"]) {
+			diesel::update(music::table.find(music_id))
+				.set((
+					music::title.eq(title),
+					music::artist.eq("Daft Punk"),
+					music::release_year.eq(2001),
+				))
+				.execute(&mut test_app.db_conn())
+				.unwrap();
+		}
+
+		let body = test_app
+			.get("/search?search_category=title&search_string=artist:%22daft%20punk%22%20year:2000-2002%20-live")
+			.await
+			.json();
+		let songs = body["songs"].as_array().unwrap();
+		assert_eq!(songs.len(), 1);
+		assert_eq!(songs[0]["id"], music_ids[0].as_str());
+		assert_eq!(body["terms"]["artist"], "daft punk");
+		assert_eq!(body["terms"]["excluded"][0], "live");
+
+		// the rest of the string is still searched
+		let body = test_app
+			.get("/search?search_category=all&search_string=artist:%22daft%20punk%22%20live")
+			.await
+			.json();
+		let songs = body["songs"].as_array().unwrap();
+		assert_eq!(songs.len(), 1);
+		assert_eq!(songs[0]["id"], music_ids[1].as_str());
+
+		// % and _ are matched as they are, not as wildcards
+		for search_string in ["artist:daft_punk", "artist:%25", "%25%20-%25"] {
+			let body = test_app
+				.get(&format!("/search?search_category=all&search_string={search_string}"))
+				.await
+				.json();
+			assert_eq!(body["songs"], serde_json::json!([]), "{search_string}");
+		}
+	}
+}
@@ -0,0 +1,39 @@
+use crate::core::app_state::AppState;
+use crate::core::query_log::{self, SLOW_QUERIES_KEPT};
+use crate::utils::auth::require_admin;
+
+use axum::{
+	extract::{Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct SlowQueriesQuery {
+	pub limit: Option<usize>, // defaults to 20
+}
+
+// Slowest queries since the start, empty unless QUERY_LOG is on
+pub async fn get_slow_queries(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(params): Query<SlowQueriesQuery>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let limit = params.limit.unwrap_or(20).clamp(1, SLOW_QUERIES_KEPT);
+	let response = json!({
+		"enabled": query_log::enabled(),
+		"queries": query_log::slowest(limit),
+	});
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(response.to_string())
+		.unwrap()
+}
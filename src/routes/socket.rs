@@ -1,8 +1,10 @@
 use crate::config::{MusicState, OpCode, SocketPayload, SocketResponse};
 use crate::core::{
 	app_state::AppState,
-	lobby::{LobbyPool, Music},
-	user_pool::UserPool,
+	lobby::{LobbyPool, Music, NewPoll, Reaction},
+	now_playing::{listen_along, record_heartbeat, Heartbeat, NowPlayingPool},
+	outbox::{Outbox, SEND_TIMEOUT},
+	user_pool::{Topic, UserPool},
 };
 use crate::lobic_db::db::*;
 use crate::lobic_db::models::UserFriendship;
@@ -16,7 +18,6 @@ use axum::{
 use futures::{sink::SinkExt, stream::StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use tokio::sync::broadcast;
 use diesel::prelude::*;
 
 // :socket
@@ -26,11 +27,13 @@ pub async fn websocket_handler(ws: WebSocketUpgrade, State(app_state): State<App
 
 pub async fn handle_socket(socket: WebSocket, State(app_state): State<AppState>) {
 	let (mut sender, mut receiver) = socket.split();
-	let (tx, mut rx) = broadcast::channel(100);
+	let tx = Outbox::new();
+	let rx = tx.clone();
 
 	let db_pool = app_state.db_pool;
 	let lobby_pool = app_state.lobby_pool;
 	let user_pool = app_state.user_pool;
+	let now_playing_pool = app_state.now_playing_pool;
 
 	// Receiving msg through sockets
 	tokio::spawn(async move {
@@ -38,7 +41,17 @@ pub async fn handle_socket(socket: WebSocket, State(app_state): State<AppState>)
 		let mut user_id: Option<String> = None;
 		let mut curr_lobby_id: Option<String> = None;
 
-		while let Some(Ok(message)) = receiver.next().await {
+		loop {
+			// Stop reading once the outbox gives up on a slow client
+			let message = tokio::select! {
+				message = receiver.next() => message,
+				_ = tx.closed() => None,
+			};
+			let message = match message {
+				Some(Ok(message)) => message,
+				_ => break,
+			};
+
 			if let Message::Text(text) = message {
 				// Extracting payload
 				let payload: SocketPayload = match serde_json::from_str(&text) {
@@ -64,13 +77,32 @@ pub async fn handle_socket(socket: WebSocket, State(app_state): State<AppState>)
 					OpCode::GET_LOBBY_MEMBERS => handle_get_lobby_members(payload.value, &lobby_pool),
 					OpCode::MESSAGE => handle_message(payload.value, &db_pool, &lobby_pool, &user_pool),
 					OpCode::GET_MESSAGES => handle_get_messages(payload.value, &lobby_pool),
-					OpCode::SET_MUSIC_STATE => handle_set_music_state(payload.value, &lobby_pool, &user_pool),
+					OpCode::SET_MUSIC_STATE => {
+						handle_set_music_state(payload.value, &db_pool, &lobby_pool, &user_pool)
+					}
 					OpCode::SYNC_MUSIC => handle_sync_music(payload.value, &lobby_pool),
-					OpCode::SET_QUEUE => handle_set_queue(payload.value, &lobby_pool, &user_pool),
+					OpCode::SET_QUEUE => handle_set_queue(payload.value, &db_pool, &lobby_pool, &user_pool),
 					OpCode::SYNC_QUEUE => handle_sync_queue(payload.value, &lobby_pool),
 					OpCode::REQUEST_MUSIC_PLAY => {
 						handle_request_music_play(payload.value, &lobby_pool, &user_pool, &db_pool)
 					}
+					OpCode::SET_SLEEP_TIMER => handle_set_sleep_timer(payload.value, &lobby_pool, &user_pool),
+					OpCode::CANCEL_SLEEP_TIMER => handle_cancel_sleep_timer(payload.value, &lobby_pool, &user_pool),
+					OpCode::SET_DJ_ROTATION => handle_set_dj_rotation(payload.value, &lobby_pool, &user_pool),
+					OpCode::DJ_OPT_IN => handle_dj_opt_in(payload.value, &lobby_pool, &user_pool),
+					OpCode::CREATE_POLL => handle_create_poll(payload.value, &db_pool, &lobby_pool, &user_pool),
+					OpCode::VOTE_POLL => handle_vote_poll(payload.value, &lobby_pool, &user_pool),
+					OpCode::SEND_REACTION => handle_send_reaction(payload.value, &lobby_pool, &user_pool),
+					OpCode::SET_REACTIONS => handle_set_reactions(payload.value, &lobby_pool, &user_pool),
+					OpCode::SET_FAMILY_FILTER => handle_set_family_filter(payload.value, &lobby_pool, &user_pool),
+					OpCode::SUBSCRIBE => handle_subscribe(payload.value, user_id.as_deref(), &user_pool),
+					OpCode::UNSUBSCRIBE => handle_unsubscribe(payload.value, user_id.as_deref(), &user_pool),
+					OpCode::PLAYER_HEARTBEAT => {
+						handle_player_heartbeat(payload.value, &db_pool, &lobby_pool, &user_pool, &now_playing_pool)
+					}
+					OpCode::LISTEN_ALONG => {
+						handle_listen_along(payload.value, &db_pool, &lobby_pool, &user_pool, &now_playing_pool)
+					}
 					_ => Err(format!("Invalid opcode: {:?}", payload.op_code)),
 				};
 
@@ -82,15 +114,17 @@ pub async fn handle_socket(socket: WebSocket, State(app_state): State<AppState>)
 							OpCode::CONNECT => {
 								user_id = Some(soc_res.value.as_str().unwrap().to_string());
 							}
-							OpCode::CREATE_LOBBY => {
-								curr_lobby_id =
-									Some(soc_res.value.get("lobby_id").unwrap().as_str().unwrap().to_string());
-							}
-							OpCode::JOIN_LOBBY => {
-								curr_lobby_id =
-									Some(soc_res.value.get("lobby_id").unwrap().as_str().unwrap().to_string());
+							OpCode::CREATE_LOBBY | OpCode::JOIN_LOBBY | OpCode::LISTEN_ALONG => {
+								let lobby_id = soc_res.value.get("lobby_id").unwrap().as_str().unwrap().to_string();
+								if let Some(id) = &user_id {
+									user_pool.follow_lobby(id, &lobby_id);
+								}
+								curr_lobby_id = Some(lobby_id);
 							}
 							OpCode::LEAVE_LOBBY => {
+								if let (Some(id), Some(lobby_id)) = (&user_id, &curr_lobby_id) {
+									user_pool.unfollow_lobby(id, lobby_id);
+								}
 								curr_lobby_id = None;
 							}
 							_ => (),
@@ -115,19 +149,30 @@ pub async fn handle_socket(socket: WebSocket, State(app_state): State<AppState>)
 		if let Some(lobby_id) = curr_lobby_id {
 			let payload = json!({
 				"lobby_id": lobby_id,
-				"user_id": user_id.unwrap(),
+				"user_id": user_id.clone().unwrap(),
 			});
 			let _ = handle_leave_lobby(payload, &db_pool, &lobby_pool, &user_pool);
 		}
+
+		// Dropping the connection so nothing keeps queueing up for it
+		if let Some(user_id) = user_id {
+			user_pool.remove_conn(&user_id, &tx);
+		}
+		tx.close();
 	});
 
-	// Sending msg through sockets
+	// Sending msg through sockets, a client which cannot keep up gets disconnected
 	tokio::spawn(async move {
-		while let Ok(msg) = rx.recv().await {
-			if sender.send(msg).await.is_err() {
-				break;
+		while let Some(msg) = rx.next().await {
+			match tokio::time::timeout(SEND_TIMEOUT, sender.send(msg)).await {
+				Ok(Ok(())) => (),
+				_ => {
+					rx.close();
+					break;
+				}
 			}
 		}
+		let _ = sender.close().await;
 	});
 }
 
@@ -140,7 +185,7 @@ struct ConnectPayload {
 }
 
 fn handle_connect(
-	tx: &broadcast::Sender<Message>,
+	tx: &Outbox,
 	value: Value,
 	db_pool: &DatabasePool,
 	user_pool: &UserPool,
@@ -203,7 +248,6 @@ fn handle_create_lobby(
 	let user_ids = user_pool.get_ids();
 	for user_id in user_ids {
 		if friends.contains(&user_id) {
-			let conn = user_pool.get(&user_id).unwrap();
 			let ids = lobby_pool.get_ids_with_rel(user_id.clone(), db_pool);
 			let response = SocketResponse {
 				op_code: OpCode::OK,
@@ -211,7 +255,7 @@ fn handle_create_lobby(
 				value: ids.into(),
 			}
 			.to_string();
-			let _ = conn.send(Message::Text(response));
+			user_pool.send(&user_id, &Topic::FriendActivity, response);
 		}
 	}
 
@@ -287,7 +331,6 @@ fn handle_leave_lobby(
 		let user_ids = user_pool.get_ids();
 		for user_id in user_ids {
 			if friends.contains(&user_id) {
-				let conn = user_pool.get(&user_id).unwrap();
 				let ids = lobby_pool.get_ids_with_rel(user_id.clone(), db_pool);
 				let response = SocketResponse {
 					op_code: OpCode::OK,
@@ -295,7 +338,7 @@ fn handle_leave_lobby(
 					value: ids.into(),
 				}
 				.to_string();
-				let _ = conn.send(Message::Text(response));
+				user_pool.send(&user_id, &Topic::FriendActivity, response);
 			}
 		}
 	} else {
@@ -383,16 +426,13 @@ fn handle_message(
 		}
 		.to_string();
 
-		let client_conn = match user_pool.get(&client_id) {
-			Some(conn) => conn,
-			None => {
-				return Err(format!(
-					"Cannot find user {} in a lobby {} (in \"handle_message\" this shouldnt occure)",
-					client_id, payload.lobby_id
-				));
-			}
-		};
-		let _ = client_conn.send(Message::Text(response));
+		if !user_pool.is_reachable(&client_id) {
+			return Err(format!(
+				"Cannot find user {} in a lobby {} (in \"handle_message\" this shouldnt occure)",
+				client_id, payload.lobby_id
+			));
+		}
+		user_pool.send(&client_id, &Topic::lobby(&payload.lobby_id), response);
 	}
 
 	let response = SocketResponse {
@@ -442,11 +482,21 @@ struct SetMusicStatePayload {
 
 fn handle_set_music_state(
 	value: Value,
+	db_pool: &DatabasePool,
 	lobby_pool: &LobbyPool,
 	user_pool: &UserPool,
 ) -> Result<SocketResponse, String> {
 	let payload: SetMusicStatePayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
 
+	if payload.state == MusicState::CHANGE_MUSIC
+		&& !unavailable_music_ids(std::slice::from_ref(&payload.music_id), db_pool)?.is_empty()
+	{
+		return Err(format!("Music {} is not available", payload.music_id));
+	}
+	if payload.state == MusicState::CHANGE_MUSIC {
+		lobby_pool.check_family_filter(&payload.lobby_id, std::slice::from_ref(&payload.music_id), db_pool)?;
+	}
+
 	let music = Music {
 		id: payload.music_id,
 		title: payload.title,
@@ -456,7 +506,7 @@ fn handle_set_music_state(
 		state: payload.state,
 	};
 
-	lobby_pool.set_music_state(&payload.lobby_id, &payload.user_id, music)?;
+	lobby_pool.set_music_state(&payload.lobby_id, &payload.user_id, music, user_pool)?;
 
 	let lobby = lobby_pool.get(&payload.lobby_id).unwrap();
 	let music = lobby.music;
@@ -474,16 +524,13 @@ fn handle_set_music_state(
 		}
 		.to_string();
 
-		let client_conn = match user_pool.get(&client_id) {
-			Some(conn) => conn,
-			None => {
-				return Err(format!(
-					"Cannot find user {} in a lobby {} (in \"handle_set_music_state\" this shouldnt occure)",
-					client_id, payload.lobby_id
-				));
-			}
-		};
-		let _ = client_conn.send(Message::Text(response));
+		if !user_pool.is_reachable(&client_id) {
+			return Err(format!(
+				"Cannot find user {} in a lobby {} (in \"handle_set_music_state\" this shouldnt occure)",
+				client_id, payload.lobby_id
+			));
+		}
+		user_pool.send(&client_id, &Topic::lobby(&payload.lobby_id), response);
 	}
 
 	let response = SocketResponse {
@@ -532,10 +579,25 @@ struct SetQueuePayload {
 	pub queue: Vec<Music>,
 }
 
-fn handle_set_queue(value: Value, lobby_pool: &LobbyPool, user_pool: &UserPool) -> Result<SocketResponse, String> {
+fn handle_set_queue(
+	value: Value,
+	db_pool: &DatabasePool,
+	lobby_pool: &LobbyPool,
+	user_pool: &UserPool,
+) -> Result<SocketResponse, String> {
 	let payload: SetQueuePayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
 
-	lobby_pool.set_queue(&payload.lobby_id, payload.queue)?;
+	// Dropping the musics which cannot be streamed so the clients don't choke on them later
+	let ids: Vec<String> = payload.queue.iter().map(|m| m.id.clone()).collect();
+	let unavailable = unavailable_music_ids(&ids, db_pool)?;
+	let queue: Vec<Music> = payload
+		.queue
+		.into_iter()
+		.filter(|m| !unavailable.contains(&m.id))
+		.collect();
+	lobby_pool.check_family_filter(&payload.lobby_id, &ids, db_pool)?;
+
+	lobby_pool.set_queue(&payload.lobby_id, queue)?;
 
 	let lobby = lobby_pool.get(&payload.lobby_id).unwrap();
 	let queue = lobby.queue;
@@ -553,22 +615,22 @@ fn handle_set_queue(value: Value, lobby_pool: &LobbyPool, user_pool: &UserPool)
 		}
 		.to_string();
 
-		let client_conn = match user_pool.get(&client_id) {
-			Some(conn) => conn,
-			None => {
-				return Err(format!(
-					"Cannot find user {} in a lobby {} (in \"handle_set_music_state\" this shouldnt occure)",
-					client_id, payload.lobby_id
-				));
-			}
-		};
-		let _ = client_conn.send(Message::Text(response));
+		if !user_pool.is_reachable(&client_id) {
+			return Err(format!(
+				"Cannot find user {} in a lobby {} (in \"handle_set_music_state\" this shouldnt occure)",
+				client_id, payload.lobby_id
+			));
+		}
+		user_pool.send(&client_id, &Topic::lobby(&payload.lobby_id), response);
 	}
 
 	let response = SocketResponse {
 		op_code: OpCode::OK,
 		r#for: OpCode::SET_QUEUE,
-		value: "Sucessfully set queue".into(),
+		value: json!({
+			"message": "Sucessfully set queue",
+			"removed_unavailable": unavailable,
+		}),
 	};
 
 	Ok(response)
@@ -611,6 +673,12 @@ fn handle_request_music_play(
 	db_pool: &DatabasePool,
 ) -> Result<SocketResponse, String> {
 	let payload: RequestMusicPlayPayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
+
+	if !unavailable_music_ids(std::slice::from_ref(&payload.music.id), db_pool)?.is_empty() {
+		return Err(format!("Music {} is not available", payload.music.id));
+	}
+	lobby_pool.check_family_filter(&payload.lobby_id, std::slice::from_ref(&payload.music.id), db_pool)?;
+
 	lobby_pool.add_requested_music(&payload.lobby_id, payload.music, user_pool, db_pool)?;
 
 	let response = SocketResponse {
@@ -621,3 +689,375 @@ fn handle_request_music_play(
 
 	Ok(response)
 }
+
+// :set_sleep_timer
+const DEFAULT_FADE_SECS: u64 = 30;
+
+#[derive(Serialize, Deserialize)]
+struct SetSleepTimerPayload {
+	pub lobby_id: String,
+	pub user_id: String,
+	pub minutes: u64,
+	pub fade_secs: Option<u64>,
+}
+
+fn handle_set_sleep_timer(
+	value: Value,
+	lobby_pool: &LobbyPool,
+	user_pool: &UserPool,
+) -> Result<SocketResponse, String> {
+	let payload: SetSleepTimerPayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
+
+	let timer = lobby_pool.set_sleep_timer(
+		&payload.lobby_id,
+		&payload.user_id,
+		payload.minutes,
+		payload.fade_secs.unwrap_or(DEFAULT_FADE_SECS),
+		user_pool,
+	)?;
+
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::SET_SLEEP_TIMER,
+		value: timer.into(),
+	};
+
+	Ok(response)
+}
+
+// :cancel_sleep_timer
+#[derive(Serialize, Deserialize)]
+struct CancelSleepTimerPayload {
+	pub lobby_id: String,
+	pub user_id: String,
+}
+
+fn handle_cancel_sleep_timer(
+	value: Value,
+	lobby_pool: &LobbyPool,
+	user_pool: &UserPool,
+) -> Result<SocketResponse, String> {
+	let payload: CancelSleepTimerPayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
+
+	lobby_pool.cancel_sleep_timer(&payload.lobby_id, &payload.user_id, user_pool)?;
+
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::CANCEL_SLEEP_TIMER,
+		value: "Sucessfully cancelled the sleep timer".into(),
+	};
+
+	Ok(response)
+}
+
+// :set_dj_rotation
+#[derive(Serialize, Deserialize)]
+struct SetDjRotationPayload {
+	pub lobby_id: String,
+	pub user_id: String,
+	pub enabled: bool,
+	pub every_tracks: Option<u32>,
+	pub every_minutes: Option<u64>,
+}
+
+fn handle_set_dj_rotation(
+	value: Value,
+	lobby_pool: &LobbyPool,
+	user_pool: &UserPool,
+) -> Result<SocketResponse, String> {
+	let payload: SetDjRotationPayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
+
+	let value = if payload.enabled {
+		lobby_pool
+			.start_dj_rotation(
+				&payload.lobby_id,
+				&payload.user_id,
+				payload.every_tracks,
+				payload.every_minutes,
+				user_pool,
+			)?
+			.into()
+	} else {
+		lobby_pool.stop_dj_rotation(&payload.lobby_id, &payload.user_id, user_pool)?;
+		Value::Null
+	};
+
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::SET_DJ_ROTATION,
+		value,
+	};
+
+	Ok(response)
+}
+
+// :dj_opt_in
+#[derive(Serialize, Deserialize)]
+struct DjOptInPayload {
+	pub lobby_id: String,
+	pub user_id: String,
+	pub opt_in: bool,
+}
+
+fn handle_dj_opt_in(value: Value, lobby_pool: &LobbyPool, user_pool: &UserPool) -> Result<SocketResponse, String> {
+	let payload: DjOptInPayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
+
+	let rotation = lobby_pool.set_dj_opt_in(&payload.lobby_id, &payload.user_id, payload.opt_in, user_pool)?;
+
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::DJ_OPT_IN,
+		value: rotation.into(),
+	};
+
+	Ok(response)
+}
+
+// :create_poll
+#[derive(Deserialize)]
+struct CreatePollPayload {
+	pub lobby_id: String,
+	pub user_id: String,
+	#[serde(flatten)]
+	pub poll: NewPoll,
+}
+
+fn handle_create_poll(
+	value: Value,
+	db_pool: &DatabasePool,
+	lobby_pool: &LobbyPool,
+	user_pool: &UserPool,
+) -> Result<SocketResponse, String> {
+	let payload: CreatePollPayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
+
+	let poll = lobby_pool.create_poll(
+		&payload.lobby_id,
+		&payload.user_id,
+		payload.poll,
+		db_pool,
+		user_pool,
+	)?;
+
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::CREATE_POLL,
+		value: poll.into(),
+	};
+
+	Ok(response)
+}
+
+// :vote_poll
+#[derive(Serialize, Deserialize)]
+struct VotePollPayload {
+	pub lobby_id: String,
+	pub user_id: String,
+	pub poll_id: String,
+	pub option: usize, // index of the option
+}
+
+fn handle_vote_poll(value: Value, lobby_pool: &LobbyPool, user_pool: &UserPool) -> Result<SocketResponse, String> {
+	let payload: VotePollPayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
+
+	let poll = lobby_pool.vote_poll(
+		&payload.lobby_id,
+		&payload.user_id,
+		&payload.poll_id,
+		payload.option,
+		user_pool,
+	)?;
+
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::VOTE_POLL,
+		value: poll.into(),
+	};
+
+	Ok(response)
+}
+
+// :send_reaction
+#[derive(Serialize, Deserialize)]
+struct ReactPayload {
+	pub lobby_id: String,
+	pub user_id: String,
+	pub reaction: String, // airhorn | fire | clap | heart | laugh
+}
+
+fn handle_send_reaction(value: Value, lobby_pool: &LobbyPool, user_pool: &UserPool) -> Result<SocketResponse, String> {
+	let payload: ReactPayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
+
+	let reaction = Reaction::parse(&payload.reaction).ok_or(format!("Unknown reaction: {}", payload.reaction))?;
+	lobby_pool.react(&payload.lobby_id, &payload.user_id, reaction, user_pool)?;
+
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::SEND_REACTION,
+		value: json!({ "reaction": reaction }),
+	};
+
+	Ok(response)
+}
+
+// :set_reactions
+#[derive(Serialize, Deserialize)]
+struct SetReactionsPayload {
+	pub lobby_id: String,
+	pub user_id: String,
+	pub enabled: bool,
+}
+
+fn handle_set_reactions(value: Value, lobby_pool: &LobbyPool, user_pool: &UserPool) -> Result<SocketResponse, String> {
+	let payload: SetReactionsPayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
+
+	lobby_pool.set_reactions(&payload.lobby_id, &payload.user_id, payload.enabled, user_pool)?;
+
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::SET_REACTIONS,
+		value: json!({ "enabled": payload.enabled }),
+	};
+
+	Ok(response)
+}
+
+// :set_family_filter
+#[derive(Serialize, Deserialize)]
+struct SetFamilyFilterPayload {
+	pub lobby_id: String,
+	pub user_id: String,
+	pub enabled: bool,
+}
+
+fn handle_set_family_filter(
+	value: Value,
+	lobby_pool: &LobbyPool,
+	user_pool: &UserPool,
+) -> Result<SocketResponse, String> {
+	let payload: SetFamilyFilterPayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
+
+	lobby_pool.set_family_filter(&payload.lobby_id, &payload.user_id, payload.enabled, user_pool)?;
+
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::SET_FAMILY_FILTER,
+		value: json!({ "enabled": payload.enabled }),
+	};
+
+	Ok(response)
+}
+
+// :subscribe
+// Always for the user the socket connected as, whatever user_id older clients still send along
+#[derive(Debug, Serialize, Deserialize)]
+struct SubscribePayload {
+	pub topics: Vec<String>, // lobby:{id} | notifications | friend-activity | library-updates | player | imports
+}
+
+fn parse_topics(topics: &[String]) -> Result<Vec<Topic>, String> {
+	topics
+		.iter()
+		.map(|topic| Topic::parse(topic).ok_or(format!("Invalid topic: {}", topic)))
+		.collect()
+}
+
+fn handle_subscribe(value: Value, user_id: Option<&str>, user_pool: &UserPool) -> Result<SocketResponse, String> {
+	let payload: SubscribePayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
+	let user_id = user_id.ok_or("Connect before subscribing to topics")?;
+
+	let topics = parse_topics(&payload.topics)?;
+	let subscribed = user_pool.subscribe(user_id, &topics)?;
+
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::SUBSCRIBE,
+		value: subscribed.into(),
+	};
+
+	Ok(response)
+}
+
+// :unsubscribe
+fn handle_unsubscribe(value: Value, user_id: Option<&str>, user_pool: &UserPool) -> Result<SocketResponse, String> {
+	let payload: SubscribePayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
+	let user_id = user_id.ok_or("Connect before unsubscribing from topics")?;
+
+	let topics = parse_topics(&payload.topics)?;
+	let subscribed = user_pool.unsubscribe(user_id, &topics)?;
+
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::UNSUBSCRIBE,
+		value: subscribed.into(),
+	};
+
+	Ok(response)
+}
+
+// :player_heartbeat
+#[derive(Debug, Deserialize)]
+struct PlayerHeartbeatPayload {
+	pub user_id: String,
+	#[serde(flatten)]
+	pub heartbeat: Heartbeat,
+}
+
+fn handle_player_heartbeat(
+	value: Value,
+	db_pool: &DatabasePool,
+	lobby_pool: &LobbyPool,
+	user_pool: &UserPool,
+	now_playing_pool: &NowPlayingPool,
+) -> Result<SocketResponse, String> {
+	let payload: PlayerHeartbeatPayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
+
+	let now_playing = record_heartbeat(
+		&payload.user_id,
+		payload.heartbeat,
+		now_playing_pool,
+		lobby_pool,
+		user_pool,
+		db_pool,
+	)?;
+
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::PLAYER_HEARTBEAT,
+		value: serde_json::to_value(&now_playing).unwrap(),
+	};
+
+	Ok(response)
+}
+
+// :listen_along
+#[derive(Debug, Deserialize)]
+struct ListenAlongPayload {
+	pub user_id: String,
+	pub target_id: String, // the friend to listen along to
+}
+
+fn handle_listen_along(
+	value: Value,
+	db_pool: &DatabasePool,
+	lobby_pool: &LobbyPool,
+	user_pool: &UserPool,
+	now_playing_pool: &NowPlayingPool,
+) -> Result<SocketResponse, String> {
+	let payload: ListenAlongPayload = serde_json::from_value(value).map_err(|x| x.to_string())?;
+
+	let res = listen_along(
+		&payload.user_id,
+		&payload.target_id,
+		now_playing_pool,
+		lobby_pool,
+		user_pool,
+		db_pool,
+	)?;
+	let response = SocketResponse {
+		op_code: OpCode::OK,
+		r#for: OpCode::LISTEN_ALONG,
+		value: res,
+	};
+
+	Ok(response)
+}
@@ -0,0 +1,223 @@
+use crate::core::app_state::AppState;
+use crate::lobic_db::db::{are_friends, can_view_stats};
+use crate::lobic_db::models::{Availability, Music, MusicResponse};
+use crate::schema::{liked_songs, music, play_log};
+use crate::utils::auth::require_user;
+
+use axum::{
+	extract::{Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+
+// How many of each user's top artists and tracks are compared
+const COMPARE_TOP_N: usize = 25;
+
+#[derive(Debug, Deserialize)]
+pub struct CompareStatsQuery {
+	pub user: String,
+	pub friend: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SharedArtist {
+	artist: String,
+	user_plays: i64,
+	friend_plays: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct SharedTrack {
+	music: MusicResponse,
+	user_plays: i64,
+	friend_plays: i64,
+}
+
+pub async fn compare_stats(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(params): Query<CompareStatsQuery>,
+) -> Response<String> {
+	let session_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	if session_id != params.user {
+		return Response::builder()
+			.status(StatusCode::FORBIDDEN)
+			.body("Can only compare your own stats".to_string())
+			.unwrap();
+	}
+
+	if !are_friends(&params.user, &params.friend, &app_state.db_pool) {
+		return Response::builder()
+			.status(StatusCode::FORBIDDEN)
+			.body(format!("User {} is not a friend of {}", params.friend, params.user))
+			.unwrap();
+	}
+
+	// The comparison exposes the stats of both sides to each other
+	if !can_view_stats(&params.user, &params.friend, &app_state.db_pool)
+		|| !can_view_stats(&params.friend, &params.user, &app_state.db_pool)
+	{
+		return Response::builder()
+			.status(StatusCode::FORBIDDEN)
+			.body("Stats of one of the users are private".to_string())
+			.unwrap();
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let user_plays = match load_plays(&params.user, &mut db_conn) {
+		Ok(plays) => plays,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+	let friend_plays = match load_plays(&params.friend, &mut db_conn) {
+		Ok(plays) => plays,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	// Overlapping top artists
+	let user_artists = artist_plays(&user_plays);
+	let friend_artists = artist_plays(&friend_plays);
+	let friend_top_artists = top_keys(&friend_artists);
+	let mut top_artists: Vec<SharedArtist> = top_keys(&user_artists)
+		.into_iter()
+		.filter(|artist| friend_top_artists.contains(artist))
+		.map(|artist| SharedArtist {
+			user_plays: user_artists[&artist],
+			friend_plays: friend_artists[&artist],
+			artist,
+		})
+		.collect();
+	top_artists.sort_by_key(|shared| std::cmp::Reverse(shared.user_plays + shared.friend_plays));
+
+	// Overlapping top tracks
+	let friend_track_plays: HashMap<String, i64> = friend_plays
+		.iter()
+		.map(|(entry, plays)| (entry.music_id.clone(), *plays))
+		.collect();
+	let friend_top_tracks: Vec<&String> = friend_plays
+		.iter()
+		.take(COMPARE_TOP_N)
+		.map(|(entry, _)| &entry.music_id)
+		.collect();
+	let mut top_tracks: Vec<SharedTrack> = user_plays
+		.into_iter()
+		.take(COMPARE_TOP_N)
+		.filter(|(entry, _)| friend_top_tracks.contains(&&entry.music_id))
+		.map(|(entry, plays)| SharedTrack {
+			friend_plays: friend_track_plays[&entry.music_id],
+			user_plays: plays,
+			music: Music::create_music_response(entry),
+		})
+		.collect();
+	top_tracks.sort_by_key(|shared| std::cmp::Reverse(shared.user_plays + shared.friend_plays));
+
+	// Songs both of them liked
+	let friend_likes = liked_songs::table
+		.filter(liked_songs::user_id.eq(&params.friend))
+		.select(liked_songs::music_id)
+		.load::<String>(&mut db_conn)
+		.unwrap_or_default();
+	let shared_favorites = liked_songs::table
+		.filter(liked_songs::user_id.eq(&params.user))
+		.filter(liked_songs::music_id.eq_any(friend_likes))
+		.inner_join(music::table)
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.select(music::all_columns)
+		.load::<Music>(&mut db_conn);
+	let shared_favorites: Vec<MusicResponse> = match shared_favorites {
+		Ok(entries) => entries.into_iter().map(Music::create_music_response).collect(),
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	let response = json!({
+		"user_id": params.user,
+		"friend_id": params.friend,
+		"similarity": similarity(&user_artists, &friend_artists),
+		"top_artists": top_artists,
+		"top_tracks": top_tracks,
+		"shared_favorites": shared_favorites,
+	});
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(response.to_string())
+		.unwrap()
+}
+
+// Every available track the user played along with the play count, most played first
+fn load_plays(user_id: &str, db_conn: &mut SqliteConnection) -> QueryResult<Vec<(Music, i64)>> {
+	let plays = play_log::table
+		.filter(play_log::user_id.eq(user_id))
+		.filter(play_log::user_times_played.ge(1))
+		.order(play_log::user_times_played.desc())
+		.inner_join(music::table)
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.select((music::all_columns, play_log::user_times_played))
+		.load::<(Music, i32)>(db_conn)?;
+
+	Ok(plays.into_iter().map(|(entry, plays)| (entry, plays as i64)).collect())
+}
+
+fn artist_plays(plays: &[(Music, i64)]) -> HashMap<String, i64> {
+	let mut artists: HashMap<String, i64> = HashMap::new();
+	for (entry, count) in plays {
+		*artists.entry(entry.artist.clone()).or_insert(0) += count;
+	}
+	artists
+}
+
+fn top_keys(counts: &HashMap<String, i64>) -> Vec<String> {
+	let mut keys: Vec<(&String, &i64)> = counts.iter().collect();
+	keys.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+	keys.into_iter()
+		.take(COMPARE_TOP_N)
+		.map(|(key, _)| key.clone())
+		.collect()
+}
+
+// Cosine similarity of the artist play counts, as a percentage
+fn similarity(a: &HashMap<String, i64>, b: &HashMap<String, i64>) -> u32 {
+	let dot: f64 = a
+		.iter()
+		.filter_map(|(artist, count)| b.get(artist).map(|other| (*count * *other) as f64))
+		.sum();
+	let norm = |counts: &HashMap<String, i64>| counts.values().map(|c| (*c * *c) as f64).sum::<f64>().sqrt();
+
+	let denominator = norm(a) * norm(b);
+	if denominator == 0.0 {
+		return 0;
+	}
+	((dot / denominator) * 100.0).round() as u32
+}
@@ -0,0 +1,199 @@
+use crate::core::app_state::AppState;
+use crate::core::rollups;
+use crate::i18n::Locale;
+use crate::lobic_db::db::{can_view_stats, user_exists};
+use crate::schema::{music, play_events, play_rollups_monthly};
+use crate::utils::auth::require_user;
+
+use axum::{
+	extract::{Query, State},
+	http::{header, status::StatusCode, HeaderMap},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{Datelike, Duration, Local};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+
+#[derive(Debug, Deserialize)]
+pub struct GenresOverTimeQuery {
+	pub user_id: String,
+	pub year: Option<i32>, // the current year when not given
+}
+
+// The years the stored dates can be in, anything else is a typo
+const YEARS: std::ops::RangeInclusive<i32> = 1900..=9999;
+
+#[derive(Debug, Serialize)]
+struct GenreShare {
+	genre: String,
+	label: String, // in the viewer's language
+	percentage: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct MonthGenres {
+	month: String,
+	minutes: i64,
+	genres: Vec<GenreShare>,
+}
+
+// Share of the listening time each genre had in every month of the year
+pub async fn get_genres_over_time(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	jar: CookieJar,
+	Query(params): Query<GenresOverTimeQuery>,
+) -> Response<String> {
+	let viewer_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	if !user_exists(&params.user_id, &app_state.db_pool) {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid user_id: {}", params.user_id))
+			.unwrap();
+	}
+	if !can_view_stats(&viewer_id, &params.user_id, &app_state.db_pool) {
+		return Response::builder()
+			.status(StatusCode::FORBIDDEN)
+			.body(format!("User {} keeps their stats private", params.user_id))
+			.unwrap();
+	}
+
+	let year = params.year.unwrap_or(Local::now().year());
+	if !YEARS.contains(&year) {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid year: {year}"))
+			.unwrap();
+	}
+	let locale = Locale::negotiate(&headers, &jar, &app_state.db_pool);
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let listened = match listening(&params.user_id, year, &mut db_conn) {
+		Ok(listened) => listened,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	let months: Vec<MonthGenres> = listened
+		.into_iter()
+		.enumerate()
+		.map(|(month0, genres)| {
+			let total: i64 = genres.values().sum();
+			let mut shares: Vec<GenreShare> = genres
+				.into_iter()
+				.filter(|(_, secs)| *secs > 0)
+				.map(|(genre, secs)| GenreShare {
+					label: locale.genre_label(&genre),
+					genre,
+					percentage: (secs as f64 * 1000.0 / total as f64).round() / 10.0,
+				})
+				.collect();
+			shares.sort_by(|a, b| b.percentage.total_cmp(&a.percentage).then(a.genre.cmp(&b.genre)));
+
+			MonthGenres {
+				month: format!("{}-{:02}", year, month0 + 1),
+				minutes: total / 60,
+				genres: shares,
+			}
+		})
+		.collect();
+
+	let response = json!({
+		"user_id": params.user_id,
+		"year": year,
+		"months": months,
+	});
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(response.to_string())
+		.unwrap()
+}
+
+// Listened seconds per genre for each month of the year
+fn listening(user_id: &str, year: i32, db_conn: &mut SqliteConnection) -> QueryResult<Vec<HashMap<String, i64>>> {
+	let mut listened: Vec<HashMap<String, i64>> = vec![HashMap::new(); 12];
+	// The month of the recent days is counted from the plays, the ones before from the monthly rollups
+	let recent_since = rollups::recent_since();
+	let recent_month = recent_since.with_day(1).unwrap_or(recent_since);
+
+	let months = play_rollups_monthly::table
+		.filter(play_rollups_monthly::user_id.eq(user_id))
+		.filter(play_rollups_monthly::month.like(format!("{year}-%")))
+		.filter(play_rollups_monthly::month.lt(rollups::month_of(recent_month)))
+		.inner_join(music::table)
+		.select((
+			play_rollups_monthly::month,
+			music::genre,
+			play_rollups_monthly::listened_secs,
+		))
+		.load::<(String, String, i64)>(db_conn)?;
+	for (month, genre, listened_secs) in months {
+		let month0 = match month.get(5..).and_then(|month| month.parse::<usize>().ok()) {
+			Some(month @ 1..=12) => month - 1,
+			_ => continue,
+		};
+		*listened[month0].entry(genre).or_insert(0) += listened_secs;
+	}
+
+	// The stored times are UTC, a day of margin covers the local offset
+	if recent_month.year() <= year {
+		let plays = play_events::table
+			.filter(play_events::user_id.eq(user_id))
+			.filter(play_events::played_date_time.ge((recent_month - Duration::days(1)).to_string()))
+			.inner_join(music::table)
+			.select((play_events::played_date_time, music::genre, play_events::listened_secs))
+			.load::<(String, String, i64)>(db_conn)?;
+		for (played_date_time, genre, listened_secs) in plays {
+			let day = rollups::day_of(&played_date_time).filter(|day| *day >= recent_month && day.year() == year);
+			if let Some(day) = day {
+				*listened[day.month0() as usize].entry(genre).or_insert(0) += listened_secs;
+			}
+		}
+	}
+	Ok(listened)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+
+	#[tokio::test]
+	async fn years_out_of_range_are_rejected() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
+		let user_id = test_app.user_id("seed_user_0");
+
+		for year in [i32::MIN, -1, i32::MAX] {
+			let uri = format!("/stats/genres_over_time?user_id={user_id}&year={year}");
+			let response = test_app.request(Method::GET, &uri, None, &cookies).await;
+			assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		}
+		let uri = format!("/stats/genres_over_time?user_id={user_id}&year=2024");
+		let body = test_app.request(Method::GET, &uri, None, &cookies).await.json();
+		assert_eq!(body["year"], 2024);
+	}
+}
@@ -0,0 +1,243 @@
+use crate::core::app_state::AppState;
+use crate::core::rollups;
+use crate::lobic_db::db::{can_view_stats, user_exists};
+use crate::schema::{play_events, play_rollups_daily, play_rollups_monthly};
+use crate::utils::auth::require_user;
+
+use axum::{
+	extract::{Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{Datelike, Duration, Local, NaiveDate};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Deserialize)]
+pub struct HeatmapQuery {
+	pub user_id: String,
+	pub year: Option<i32>, // the current year when not given
+}
+
+#[derive(Debug, Serialize)]
+struct HeatmapDay {
+	date: String,
+	minutes: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct HeatmapMonth {
+	month: String,
+	minutes: i64,
+}
+
+// Listening minutes for every day of the year, days follow the instance's local time
+pub async fn get_heatmap(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(params): Query<HeatmapQuery>,
+) -> Response<String> {
+	let viewer_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	if !user_exists(&params.user_id, &app_state.db_pool) {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid user_id: {}", params.user_id))
+			.unwrap();
+	}
+	if !can_view_stats(&viewer_id, &params.user_id, &app_state.db_pool) {
+		return Response::builder()
+			.status(StatusCode::FORBIDDEN)
+			.body(format!("User {} keeps their stats private", params.user_id))
+			.unwrap();
+	}
+
+	let year = params.year.unwrap_or(Local::now().year());
+	let (first_day, last_day) = match (
+		NaiveDate::from_ymd_opt(year, 1, 1),
+		NaiveDate::from_ymd_opt(year, 12, 31),
+	) {
+		(Some(first_day), Some(last_day)) => (first_day, last_day),
+		_ => {
+			return Response::builder()
+				.status(StatusCode::BAD_REQUEST)
+				.body(format!("Invalid year: {}", year))
+				.unwrap();
+		}
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let (listened, rolled_up) = match listening(&params.user_id, year, &mut db_conn) {
+		Ok(listening) => listening,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+	let rolled_up_months: Vec<HeatmapMonth> = rolled_up
+		.into_iter()
+		.map(|(month, secs)| HeatmapMonth {
+			month,
+			minutes: secs / 60,
+		})
+		.collect();
+
+	// Every day of the year is in the grid, the quiet ones with zero minutes
+	let days: Vec<HeatmapDay> = first_day
+		.iter_days()
+		.take_while(|day| *day <= last_day)
+		.map(|day| HeatmapDay {
+			date: day.to_string(),
+			minutes: listened.get(&day).copied().unwrap_or(0) / 60,
+		})
+		.collect();
+
+	let response = json!({
+		"user_id": params.user_id,
+		"year": year,
+		"total_minutes": days.iter().map(|day| day.minutes).sum::<i64>()
+			+ rolled_up_months.iter().map(|month| month.minutes).sum::<i64>(),
+		"max_minutes": days.iter().map(|day| day.minutes).max().unwrap_or(0),
+		"days": days,
+		"rolled_up_months": rolled_up_months,
+	});
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(response.to_string())
+		.unwrap()
+}
+
+// Listened seconds for each day of the year, and for each month whatever of it is past the retention window
+fn listening(
+	user_id: &str,
+	year: i32,
+	db_conn: &mut SqliteConnection,
+) -> QueryResult<(HashMap<NaiveDate, i64>, BTreeMap<String, i64>)> {
+	let recent_since = rollups::recent_since();
+	let mut listened: HashMap<NaiveDate, i64> = HashMap::new();
+	let mut rolled_up: BTreeMap<String, i64> = BTreeMap::new();
+
+	// Older days come from the daily rollups, what the monthly ones have beyond them got pruned
+	let days = play_rollups_daily::table
+		.filter(play_rollups_daily::user_id.eq(user_id))
+		.filter(play_rollups_daily::day.like(format!("{year}-%")))
+		.select((play_rollups_daily::day, play_rollups_daily::listened_secs))
+		.load::<(String, i64)>(db_conn)?;
+	for (day, listened_secs) in days {
+		let Ok(day) = day.parse::<NaiveDate>() else {
+			continue;
+		};
+		*rolled_up.entry(rollups::month_of(day)).or_insert(0) -= listened_secs;
+		if day < recent_since {
+			*listened.entry(day).or_insert(0) += listened_secs;
+		}
+	}
+	let months = play_rollups_monthly::table
+		.filter(play_rollups_monthly::user_id.eq(user_id))
+		.filter(play_rollups_monthly::month.like(format!("{year}-%")))
+		.select((play_rollups_monthly::month, play_rollups_monthly::listened_secs))
+		.load::<(String, i64)>(db_conn)?;
+	for (month, listened_secs) in months {
+		*rolled_up.entry(month).or_insert(0) += listened_secs;
+	}
+	rolled_up.retain(|_, secs| *secs > 0);
+
+	// The last few days straight from the plays, the stored times are UTC so a day of margin covers the offset
+	if recent_since.year() <= year {
+		let plays = play_events::table
+			.filter(play_events::user_id.eq(user_id))
+			.filter(play_events::played_date_time.ge((recent_since - Duration::days(1)).to_string()))
+			.select((play_events::played_date_time, play_events::listened_secs))
+			.load::<(String, i64)>(db_conn)?;
+		for (played_date_time, listened_secs) in plays {
+			let day = rollups::day_of(&played_date_time).filter(|day| *day >= recent_since && day.year() == year);
+			if let Some(day) = day {
+				*listened.entry(day).or_insert(0) += listened_secs;
+			}
+		}
+	}
+	Ok((listened, rolled_up))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::core::rollups;
+	use crate::lobic_db::models::PlayEvent;
+	use crate::test_support::TestApp;
+
+	use axum::http::Method;
+	use chrono::{Datelike, Duration, Local, NaiveDate, TimeZone};
+	use serde_json::{json, Value};
+
+	fn minutes_on(heatmap: &Value, day: NaiveDate) -> i64 {
+		let days = heatmap["days"].as_array().unwrap();
+		days.iter().find(|entry| entry["date"] == day.to_string()).unwrap()["minutes"]
+			.as_i64()
+			.unwrap()
+	}
+
+	#[tokio::test]
+	async fn older_days_come_from_the_rollups_and_today_from_the_plays() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let music_id = test_app.get("/music/get_music?page_length=1").await.json()["items"][0]["id"].clone();
+		let cookies = test_app.login("seed_user_0").await;
+		let heatmap = |year: i32| format!("/stats/heatmap?user_id={user_id}&year={year}");
+
+		// Rolled up only, like a play whose event already got pruned
+		let day = rollups::recent_since() - Duration::days(10);
+		let before = test_app
+			.request(Method::GET, &heatmap(day.year()), None, &cookies)
+			.await
+			.json();
+		let noon = Local.from_local_datetime(&day.and_hms_opt(12, 0, 0).unwrap()).unwrap();
+		let event = PlayEvent {
+			event_id: uuid::Uuid::new_v4().to_string(),
+			user_id: user_id.clone(),
+			music_id: music_id.as_str().unwrap().to_string(),
+			played_date_time: noon.to_rfc3339(),
+			listened_secs: 600,
+			device: None,
+		};
+		rollups::roll_up(&[event], &mut test_app.db_conn()).unwrap();
+		let after = test_app
+			.request(Method::GET, &heatmap(day.year()), None, &cookies)
+			.await
+			.json();
+		assert_eq!(minutes_on(&after, day), minutes_on(&before, day) + 10);
+
+		let today = Local::now().date_naive();
+		let before = test_app
+			.request(Method::GET, &heatmap(today.year()), None, &cookies)
+			.await
+			.json();
+		let payload = json!({ "user_id": user_id, "music_id": music_id, "listened_secs": 120 });
+		test_app
+			.request(Method::POST, "/music/log_song_play", Some(payload), &cookies)
+			.await;
+		let after = test_app
+			.request(Method::GET, &heatmap(today.year()), None, &cookies)
+			.await
+			.json();
+		assert_eq!(minutes_on(&after, today), minutes_on(&before, today) + 2);
+	}
+}
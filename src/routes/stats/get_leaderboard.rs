@@ -0,0 +1,130 @@
+use crate::core::app_state::AppState;
+use crate::core::leaderboard::week_start;
+use crate::lobic_db::db::mutual_friend_ids;
+use crate::lobic_db::models::LeaderboardEntry;
+use crate::schema::{leaderboard_entries, user_settings, users};
+use crate::utils::auth::require_user;
+
+use axum::{
+	extract::{Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct LeaderboardQuery {
+	pub metric: Option<String>, // minutes | artists, defaults to minutes
+}
+
+#[derive(Debug, Serialize)]
+struct Standing {
+	rank: usize,
+	user_id: String,
+	username: String,
+	minutes_listened: i64,
+	distinct_artists: i32,
+}
+
+pub async fn get_leaderboard(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(params): Query<LeaderboardQuery>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let metric = params.metric.unwrap_or("minutes".to_string());
+	if metric != "minutes" && metric != "artists" {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid metric: {}", metric))
+			.unwrap();
+	}
+
+	let mut members = mutual_friend_ids(&user_id, &app_state.db_pool);
+	members.push(user_id);
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	// Users who opted out don't show up on anyone's board
+	let opted_out: Vec<String> = user_settings::table
+		.filter(user_settings::user_id.eq_any(&members))
+		.filter(user_settings::leaderboard_opt_out.eq(true))
+		.select(user_settings::user_id)
+		.load::<String>(&mut db_conn)
+		.unwrap_or_default();
+	members.retain(|member| !opted_out.contains(member));
+
+	let week = week_start(Utc::now());
+	let query = leaderboard_entries::table
+		.filter(leaderboard_entries::week_start.eq(&week))
+		.filter(leaderboard_entries::user_id.eq_any(&members))
+		.inner_join(users::table)
+		.select((leaderboard_entries::all_columns, users::username))
+		.load::<(LeaderboardEntry, String)>(&mut db_conn);
+
+	let mut entries = match query {
+		Ok(entries) => entries,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	if metric == "artists" {
+		entries.sort_by(|(a, _), (b, _)| {
+			b.distinct_artists
+				.cmp(&a.distinct_artists)
+				.then(b.minutes_listened.cmp(&a.minutes_listened))
+		});
+	} else {
+		entries.sort_by(|(a, _), (b, _)| {
+			b.minutes_listened
+				.cmp(&a.minutes_listened)
+				.then(b.distinct_artists.cmp(&a.distinct_artists))
+		});
+	}
+
+	let refreshed_date_time = entries.first().map(|(entry, _)| entry.refreshed_date_time.clone());
+	let standings: Vec<Standing> = entries
+		.into_iter()
+		.enumerate()
+		.map(|(index, (entry, username))| Standing {
+			rank: index + 1,
+			user_id: entry.user_id,
+			username,
+			minutes_listened: entry.minutes_listened,
+			distinct_artists: entry.distinct_artists,
+		})
+		.collect();
+
+	let response = json!({
+		"week_start": week,
+		"metric": metric,
+		"refreshed_date_time": refreshed_date_time,
+		"standings": standings,
+	});
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(response.to_string())
+		.unwrap()
+}
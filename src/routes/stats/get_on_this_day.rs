@@ -0,0 +1,75 @@
+use crate::core::app_state::AppState;
+use crate::core::on_this_day::anniversaries;
+use crate::lobic_db::db::{can_view_stats, user_exists};
+use crate::utils::auth::require_user;
+
+use axum::{
+	extract::{Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Local;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct OnThisDayQuery {
+	pub user_id: String,
+}
+
+// Tracks and artists the user first played on this day in earlier years
+pub async fn get_on_this_day(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(params): Query<OnThisDayQuery>,
+) -> Response<String> {
+	let viewer_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	if !user_exists(&params.user_id, &app_state.db_pool) {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid user_id: {}", params.user_id))
+			.unwrap();
+	}
+	if !can_view_stats(&viewer_id, &params.user_id, &app_state.db_pool) {
+		return Response::builder()
+			.status(StatusCode::FORBIDDEN)
+			.body(format!("User {} keeps their stats private", params.user_id))
+			.unwrap();
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let today = Local::now().date_naive();
+	match anniversaries(Some(&params.user_id), today, &mut db_conn) {
+		Ok(anniversaries) => {
+			let response = json!({
+				"user_id": params.user_id,
+				"date": today.to_string(),
+				"anniversaries": anniversaries,
+			});
+			Response::builder()
+				.status(StatusCode::OK)
+				.header(header::CONTENT_TYPE, "application/json")
+				.body(response.to_string())
+				.unwrap()
+		}
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap(),
+	}
+}
@@ -0,0 +1,300 @@
+use crate::core::app_state::AppState;
+use crate::services::tag::TagBrowseFilter;
+use crate::services::{TagService, TagTarget};
+use crate::utils::auth::{require_admin, require_user, session_user_id};
+use crate::utils::list::ListResponse;
+use crate::utils::response::json_response;
+
+use axum::{
+	extract::{Path, Query, State},
+	http::status::StatusCode,
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct TagPayload {
+	pub target_type: TagTarget,
+	pub target_id: String, // music_id, or the album name
+	pub tag: String,
+}
+
+// :get_tags
+// Tags in use across the library, the most used first
+pub async fn get_tags(State(app_state): State<AppState>, Query(filter): Query<TagBrowseFilter>) -> Response<String> {
+	match TagService::new(&app_state.db_pool).browse(&filter) {
+		Ok(tags) => tags.into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :get_target_tags
+pub async fn get_target_tags(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path((target_type, target_id)): Path<(String, String)>,
+) -> Response<String> {
+	let Some(target) = TagTarget::parse(&target_type) else {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid tag target: {target_type}"))
+			.unwrap();
+	};
+
+	let user_id = session_user_id(&jar);
+	match TagService::new(&app_state.db_pool).target_tags(target, &target_id, user_id.as_deref()) {
+		Ok(tags) => ListResponse::all(tags).into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :add_tag
+pub async fn add_tag(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<TagPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let service = TagService::new(&app_state.db_pool);
+	match service.add(&user_id, payload.target_type, &payload.target_id, &payload.tag) {
+		Ok(tags) => ListResponse::all(tags).into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :remove_tag
+pub async fn remove_tag(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<TagPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let service = TagService::new(&app_state.db_pool);
+	match service.remove(&user_id, payload.target_type, &payload.target_id, &payload.tag) {
+		Ok(tags) => ListResponse::all(tags).into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :moderate_remove_tag
+// Takes the tag off the target for everyone who put it there
+pub async fn moderate_remove_tag(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<TagPayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let service = TagService::new(&app_state.db_pool);
+	match service.remove_everywhere(payload.target_type, &payload.target_id, &payload.tag) {
+		Ok(removed) => json_response(StatusCode::OK, &json!({ "removed": removed })),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlockPayload {
+	pub tag: String,
+}
+
+// :block_tag
+pub async fn block_tag(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<BlockPayload>,
+) -> Response<String> {
+	let admin_id = match require_admin(&jar, &app_state.db_pool) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	match TagService::new(&app_state.db_pool).block(&payload.tag, &admin_id) {
+		Ok(removed) => json_response(StatusCode::OK, &json!({ "removed": removed })),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :unblock_tag
+pub async fn unblock_tag(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<BlockPayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	match TagService::new(&app_state.db_pool).unblock(&payload.tag) {
+		Ok(()) => Response::builder()
+			.status(StatusCode::OK)
+			.body(format!("The tag {} can be used again", payload.tag))
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :get_blocked_tags
+pub async fn get_blocked_tags(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	match TagService::new(&app_state.db_pool).blocked() {
+		Ok(blocked) => ListResponse::all(blocked).into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::users;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::{json, Value};
+
+	#[tokio::test]
+	async fn listeners_tag_tracks_and_albums() {
+		let test_app = TestApp::seeded();
+		let track = test_app.get("/music/get_music?page_length=1").await.json()["items"][0].clone();
+		let music_id = track["id"].as_str().unwrap();
+		let payload = |kind: &str, id: &Value, tag: &str| json!({ "target_type": kind, "target_id": id, "tag": tag });
+
+		for username in ["seed_user_0", "seed_user_1"] {
+			let cookies = test_app.login(username).await;
+			let response = test_app
+				.request(
+					Method::POST,
+					"/tags/add",
+					Some(payload("track", &track["id"], "Late  Night")),
+					&cookies,
+				)
+				.await;
+			assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+		}
+		let cookies = test_app.login("seed_user_2").await;
+		let body = test_app
+			.request(
+				Method::POST,
+				"/tags/add",
+				Some(payload("album", &track["album"], "road trip")),
+				&cookies,
+			)
+			.await
+			.json();
+		assert_eq!(
+			body["items"],
+			json!([{ "tag": "road trip", "count": 1, "tagged_by_me": true }])
+		);
+
+		let body = test_app.get(&format!("/tags/track/{music_id}")).await.json();
+		assert_eq!(
+			body["items"],
+			json!([{ "tag": "late night", "count": 2, "tagged_by_me": false }])
+		);
+		let body = test_app.get("/tags").await.json();
+		assert_eq!(body["items"][0]["tag"], "late night");
+		assert_eq!(body["items"][0]["use_count"], 2);
+		assert_eq!(body["items"][1]["album_count"], 1);
+
+		let body = test_app.get("/music/get_music?tag=late%20night").await.json();
+		assert_eq!(body["total_count"], 1);
+		let body = test_app.get("/music/get_music?tag=road%20trip").await.json();
+		for song in body["items"].as_array().unwrap() {
+			assert_eq!(song["album"], track["album"]);
+		}
+
+		let response = test_app
+			.request(
+				Method::POST,
+				"/tags/add",
+				Some(payload("track", &track["id"], "<b>")),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		let response = test_app
+			.request(
+				Method::POST,
+				"/tags/add",
+				Some(payload("track", &json!("missing"), "fine")),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+	}
+
+	#[tokio::test]
+	async fn admins_block_tags() {
+		let test_app = TestApp::seeded();
+		let music_id = test_app.get("/music/get_music?page_length=1").await.json()["items"][0]["id"].clone();
+		let payload = json!({ "target_type": "track", "target_id": music_id, "tag": "spam" });
+
+		let cookies = test_app.login("seed_user_0").await;
+		test_app
+			.request(Method::POST, "/tags/add", Some(payload.clone()), &cookies)
+			.await;
+		let response = test_app
+			.request(
+				Method::POST,
+				"/admin/tags/block",
+				Some(json!({ "tag": "spam" })),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let admin_cookies = test_app.login("seed_user_1").await;
+		let response = test_app
+			.request(
+				Method::POST,
+				"/admin/tags/block",
+				Some(json!({ "tag": "SPAM" })),
+				&admin_cookies,
+			)
+			.await;
+		assert_eq!(response.json()["removed"], 1);
+
+		let response = test_app
+			.request(Method::POST, "/tags/add", Some(payload.clone()), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::FORBIDDEN);
+		let body = test_app
+			.get(&format!("/tags/track/{}", music_id.as_str().unwrap()))
+			.await
+			.json();
+		assert_eq!(body["items"], json!([]));
+
+		let response = test_app
+			.request(
+				Method::POST,
+				"/admin/tags/unblock",
+				Some(json!({ "tag": "spam" })),
+				&admin_cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::OK);
+		let response = test_app
+			.request(Method::POST, "/tags/add", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::OK);
+	}
+}
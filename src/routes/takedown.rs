@@ -0,0 +1,492 @@
+use crate::config::OpCode;
+use crate::core::app_state::AppState;
+use crate::lobic_db::db::set_music_availability;
+use crate::lobic_db::models::{Availability, Notification, Takedown, TakedownEvent};
+use crate::routes::notify::notify;
+use crate::schema::{music, playlists, takedown_events, takedowns};
+use crate::utils::auth::{require_admin, require_user};
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::{Path, Query, State},
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetType {
+	Track,
+	Playlist,
+}
+
+impl TargetType {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			TargetType::Track => "track",
+			TargetType::Playlist => "playlist",
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct TakedownDetailsResponse {
+	pub takedown: Takedown,
+	pub trail: Vec<TakedownEvent>,
+}
+
+// :create_takedown
+#[derive(Debug, Deserialize)]
+pub struct CreateTakedownPayload {
+	pub target_type: TargetType,
+	pub target_id: String,
+	pub reason: String,
+}
+
+pub async fn create_takedown(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<CreateTakedownPayload>,
+) -> Response<String> {
+	let admin_id = match require_admin(&jar, &app_state.db_pool) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	// Finding who should be told about the takedown
+	let owner_id = match content_owner(payload.target_type, &payload.target_id, &mut db_conn) {
+		Ok(owner) => owner,
+		Err(_) => {
+			let msg = format!("Invalid {} id: {}", payload.target_type.as_str(), payload.target_id);
+			return Response::builder().status(StatusCode::NOT_FOUND).body(msg).unwrap();
+		}
+	};
+
+	let now = Utc::now().to_rfc3339();
+	let takedown = Takedown {
+		takedown_id: Uuid::new_v4().to_string(),
+		target_type: payload.target_type.as_str().to_string(),
+		target_id: payload.target_id.clone(),
+		reason: payload.reason.clone(),
+		admin_id: admin_id.clone(),
+		status: "active".to_string(),
+		created_date_time: now.clone(),
+	};
+	let event = TakedownEvent {
+		event_id: Uuid::new_v4().to_string(),
+		takedown_id: takedown.takedown_id.clone(),
+		actor_id: admin_id,
+		action: "takedown".to_string(),
+		message: payload.reason.clone(),
+		created_date_time: now,
+	};
+
+	let result = db_conn.transaction::<_, diesel::result::Error, _>(|conn| {
+		diesel::insert_into(takedowns::table).values(&takedown).execute(conn)?;
+		diesel::insert_into(takedown_events::table)
+			.values(&event)
+			.execute(conn)?;
+		apply_availability(
+			payload.target_type,
+			&payload.target_id,
+			Availability::TakenDown,
+			Some(payload.reason.clone()),
+			conn,
+		)?;
+		Ok(())
+	});
+
+	if let Err(err) = result {
+		return Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to take down the content: {err}"))
+			.unwrap();
+	}
+
+	// Letting the uploader know
+	if let Some(owner_id) = owner_id {
+		let value = json!({
+			"takedown_id": takedown.takedown_id,
+			"target_type": takedown.target_type,
+			"target_id": takedown.target_id,
+			"reason": takedown.reason,
+		});
+		let notif = Notification::new(OpCode::TAKEDOWN, value);
+		notify(&owner_id, notif, &app_state.db_pool, &app_state.user_pool);
+	}
+
+	Response::builder()
+		.status(StatusCode::CREATED)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(serde_json::to_string(&takedown).unwrap())
+		.unwrap()
+}
+
+// :appeal_takedown
+#[derive(Debug, Deserialize)]
+pub struct AppealTakedownPayload {
+	pub takedown_id: String,
+	pub message: String,
+}
+
+pub async fn appeal_takedown(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<AppealTakedownPayload>,
+) -> Response<String> {
+	let curr_user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let takedown = match takedowns::table
+		.filter(takedowns::takedown_id.eq(&payload.takedown_id))
+		.first::<Takedown>(&mut db_conn)
+	{
+		Ok(takedown) => takedown,
+		Err(_) => {
+			let msg = format!("Invalid takedown id: {}", payload.takedown_id);
+			return Response::builder().status(StatusCode::NOT_FOUND).body(msg).unwrap();
+		}
+	};
+
+	// Only the owner of the content can appeal
+	let owner_id = takedown_owner(&takedown, &mut db_conn);
+	if owner_id.as_deref() != Some(curr_user_id.as_str()) {
+		return Response::builder()
+			.status(StatusCode::FORBIDDEN)
+			.body("Only the owner of the content can appeal the takedown".to_string())
+			.unwrap();
+	}
+
+	if takedown.status != "active" {
+		let msg = format!("Takedown is already {}", takedown.status);
+		return Response::builder().status(StatusCode::BAD_REQUEST).body(msg).unwrap();
+	}
+
+	let event = TakedownEvent {
+		event_id: Uuid::new_v4().to_string(),
+		takedown_id: takedown.takedown_id.clone(),
+		actor_id: curr_user_id,
+		action: "appeal".to_string(),
+		message: payload.message,
+		created_date_time: Utc::now().to_rfc3339(),
+	};
+
+	let result = db_conn.transaction::<_, diesel::result::Error, _>(|conn| {
+		diesel::insert_into(takedown_events::table)
+			.values(&event)
+			.execute(conn)?;
+		diesel::update(takedowns::table.filter(takedowns::takedown_id.eq(&takedown.takedown_id)))
+			.set(takedowns::status.eq("appealed"))
+			.execute(conn)?;
+		Ok(())
+	});
+
+	if let Err(err) = result {
+		return Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to appeal the takedown: {err}"))
+			.unwrap();
+	}
+
+	// Letting the admin who took the content down know
+	let value = json!({
+		"takedown_id": takedown.takedown_id,
+		"message": event.message,
+	});
+	let notif = Notification::new(OpCode::TAKEDOWN_APPEAL, value);
+	notify(&takedown.admin_id, notif, &app_state.db_pool, &app_state.user_pool);
+
+	Response::builder()
+		.status(StatusCode::OK)
+		.body("Sucessfully appealed the takedown".to_string())
+		.unwrap()
+}
+
+// :resolve_takedown
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+	Reinstate,
+	Uphold,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveTakedownPayload {
+	pub takedown_id: String,
+	pub decision: Decision,
+	pub message: String,
+}
+
+pub async fn resolve_takedown(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<ResolveTakedownPayload>,
+) -> Response<String> {
+	let admin_id = match require_admin(&jar, &app_state.db_pool) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let takedown = match takedowns::table
+		.filter(takedowns::takedown_id.eq(&payload.takedown_id))
+		.first::<Takedown>(&mut db_conn)
+	{
+		Ok(takedown) => takedown,
+		Err(_) => {
+			let msg = format!("Invalid takedown id: {}", payload.takedown_id);
+			return Response::builder().status(StatusCode::NOT_FOUND).body(msg).unwrap();
+		}
+	};
+
+	if takedown.status == "reinstated" {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body("Content has already been reinstated".to_string())
+			.unwrap();
+	}
+
+	let target_type = match takedown.target_type.as_str() {
+		"playlist" => TargetType::Playlist,
+		_ => TargetType::Track,
+	};
+	let (action, new_status) = match payload.decision {
+		Decision::Reinstate => ("reinstate", "reinstated"),
+		Decision::Uphold => ("uphold", "upheld"),
+	};
+
+	let event = TakedownEvent {
+		event_id: Uuid::new_v4().to_string(),
+		takedown_id: takedown.takedown_id.clone(),
+		actor_id: admin_id,
+		action: action.to_string(),
+		message: payload.message,
+		created_date_time: Utc::now().to_rfc3339(),
+	};
+
+	let result = db_conn.transaction::<_, diesel::result::Error, _>(|conn| {
+		diesel::insert_into(takedown_events::table)
+			.values(&event)
+			.execute(conn)?;
+		diesel::update(takedowns::table.filter(takedowns::takedown_id.eq(&takedown.takedown_id)))
+			.set(takedowns::status.eq(new_status))
+			.execute(conn)?;
+		if payload.decision == Decision::Reinstate {
+			apply_availability(target_type, &takedown.target_id, Availability::Available, None, conn)?;
+		}
+		Ok(())
+	});
+
+	if let Err(err) = result {
+		return Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to resolve the takedown: {err}"))
+			.unwrap();
+	}
+
+	// Letting the uploader know about the decision
+	if let Some(owner_id) = takedown_owner(&takedown, &mut db_conn) {
+		let value = json!({
+			"takedown_id": takedown.takedown_id,
+			"status": new_status,
+			"message": event.message,
+		});
+		let notif = Notification::new(OpCode::TAKEDOWN_RESOLVED, value);
+		notify(&owner_id, notif, &app_state.db_pool, &app_state.user_pool);
+	}
+
+	Response::builder()
+		.status(StatusCode::OK)
+		.body(format!("Takedown {}", new_status))
+		.unwrap()
+}
+
+// :get_takedowns
+#[derive(Debug, Deserialize)]
+pub struct GetTakedownsQuery {
+	pub status: Option<String>,
+}
+
+pub async fn get_takedowns(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Query(params): Query<GetTakedownsQuery>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let mut query = takedowns::table.order(takedowns::created_date_time.desc()).into_boxed();
+	if let Some(status) = params.status {
+		query = query.filter(takedowns::status.eq(status));
+	}
+
+	match query.load::<Takedown>(&mut db_conn) {
+		Ok(results) => ListResponse::all(results).into_response(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap(),
+	}
+}
+
+// :get_takedown
+pub async fn get_takedown(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(curr_takedown_id): Path<String>,
+) -> Response<String> {
+	let curr_user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let takedown = match takedowns::table
+		.filter(takedowns::takedown_id.eq(&curr_takedown_id))
+		.first::<Takedown>(&mut db_conn)
+	{
+		Ok(takedown) => takedown,
+		Err(_) => {
+			let msg = format!("Invalid takedown id: {}", curr_takedown_id);
+			return Response::builder().status(StatusCode::NOT_FOUND).body(msg).unwrap();
+		}
+	};
+
+	// The trail is only visible to the owner and the admins
+	let is_owner = takedown_owner(&takedown, &mut db_conn).as_deref() == Some(curr_user_id.as_str());
+	if !is_owner && require_admin(&jar, &app_state.db_pool).is_err() {
+		return Response::builder()
+			.status(StatusCode::FORBIDDEN)
+			.body("Not allowed to view this takedown".to_string())
+			.unwrap();
+	}
+
+	let trail = match takedown_events::table
+		.filter(takedown_events::takedown_id.eq(&curr_takedown_id))
+		.order(takedown_events::created_date_time.asc())
+		.load::<TakedownEvent>(&mut db_conn)
+	{
+		Ok(trail) => trail,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	let response = TakedownDetailsResponse { takedown, trail };
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(serde_json::to_string(&response).unwrap())
+		.unwrap()
+}
+
+// Returns the uploader of the track or the owner of the playlist
+fn content_owner(
+	target_type: TargetType,
+	target_id: &str,
+	db_conn: &mut SqliteConnection,
+) -> QueryResult<Option<String>> {
+	match target_type {
+		TargetType::Track => music::table
+			.filter(music::music_id.eq(target_id))
+			.select(music::uploader_id)
+			.first::<Option<String>>(db_conn),
+		TargetType::Playlist => playlists::table
+			.filter(playlists::playlist_id.eq(target_id))
+			.select(playlists::user_id)
+			.first::<String>(db_conn)
+			.map(Some),
+	}
+}
+
+fn takedown_owner(takedown: &Takedown, db_conn: &mut SqliteConnection) -> Option<String> {
+	let target_type = match takedown.target_type.as_str() {
+		"playlist" => TargetType::Playlist,
+		_ => TargetType::Track,
+	};
+	content_owner(target_type, &takedown.target_id, db_conn).ok().flatten()
+}
+
+fn apply_availability(
+	target_type: TargetType,
+	target_id: &str,
+	status: Availability,
+	reason: Option<String>,
+	db_conn: &mut SqliteConnection,
+) -> QueryResult<usize> {
+	match target_type {
+		TargetType::Track => set_music_availability(target_id, status, reason, db_conn),
+		TargetType::Playlist => diesel::update(playlists::table.filter(playlists::playlist_id.eq(target_id)))
+			.set((
+				playlists::availability.eq(status.as_str()),
+				playlists::availability_reason.eq(reason),
+			))
+			.execute(db_conn),
+	}
+}
@@ -0,0 +1,96 @@
+use crate::core::app_state::AppState;
+use crate::core::telemetry::{build_report, is_enabled, telemetry_url, TELEMETRY_ENABLED, TELEMETRY_LAST_SENT};
+use crate::lobic_db::db::{get_instance_setting, set_instance_setting};
+use crate::utils::auth::require_admin;
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+use serde_json::json;
+
+// :get_telemetry
+// Whether telemetry is on, along with the exact payload that gets sent
+pub async fn get_telemetry(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let report = match build_report(&app_state, &mut db_conn) {
+		Ok(report) => report,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Database error: {err}"))
+				.unwrap();
+		}
+	};
+
+	let response = json!({
+		"enabled": is_enabled(&mut db_conn),
+		"url": telemetry_url(),
+		"last_sent_date_time": get_instance_setting(TELEMETRY_LAST_SENT, &mut db_conn),
+		"payload": report,
+	});
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(response.to_string())
+		.unwrap()
+}
+
+// :set_telemetry
+#[derive(Debug, Deserialize)]
+pub struct SetTelemetryPayload {
+	pub enabled: bool,
+}
+
+pub async fn set_telemetry(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<SetTelemetryPayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	match set_instance_setting(TELEMETRY_ENABLED, &payload.enabled.to_string(), &mut db_conn) {
+		Ok(_) => Response::builder()
+			.status(StatusCode::OK)
+			.body(format!(
+				"Telemetry {}",
+				if payload.enabled { "enabled" } else { "disabled" }
+			))
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to save the setting: {err}"))
+			.unwrap(),
+	}
+}
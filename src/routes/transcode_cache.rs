@@ -0,0 +1,217 @@
+use crate::config::MAX_TRANSCODE_CACHE_MB;
+use crate::core::app_state::AppState;
+use crate::core::transcode_cache::{CacheLimits, TranscodeCache, CACHE_LOW_WATER_PERCENT, CACHE_MAX_MB};
+use crate::lobic_db::db::set_instance_setting;
+use crate::utils::auth::require_admin;
+
+use axum::{
+	extract::{Path, State},
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::{Deserialize, Serialize};
+
+fn json_response(value: &impl Serialize) -> Response<String> {
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(serde_json::to_string(value).unwrap())
+		.unwrap()
+}
+
+#[allow(clippy::result_large_err)]
+fn load_limits(app_state: &AppState) -> Result<CacheLimits, Response<String>> {
+	match app_state.db_pool.get() {
+		Ok(mut db_conn) => Ok(CacheLimits::load(&mut db_conn)),
+		Err(err) => Err(Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to get DB from pool: {err}"))
+			.unwrap()),
+	}
+}
+
+// :get_transcode_cache
+// How big the cache is against its limits, and the tracks taking up the most of it
+pub async fn get_transcode_cache(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+	let limits = match load_limits(&app_state) {
+		Ok(limits) => limits,
+		Err(response) => return response,
+	};
+
+	match tokio::task::spawn_blocking(move || TranscodeCache::new().report(limits)).await {
+		Ok(Ok(report)) => json_response(&report),
+		Ok(Err(err)) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to read the transcode cache: {err}"))
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("The report didn't finish: {err}"))
+			.unwrap(),
+	}
+}
+
+// :set_transcode_cache_limits
+// Only the limits given change, the eviction job picks them up on its next run
+#[derive(Debug, Deserialize)]
+pub struct SetCacheLimitsPayload {
+	pub max_mb: Option<i64>,
+	pub low_water_percent: Option<i64>,
+}
+
+pub async fn set_transcode_cache_limits(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<SetCacheLimitsPayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let limits = [
+		(CACHE_MAX_MB, payload.max_mb, 0..=MAX_TRANSCODE_CACHE_MB),
+		(CACHE_LOW_WATER_PERCENT, payload.low_water_percent, 0..=100),
+	];
+	for (key, value, range) in &limits {
+		if let Some(value) = value.filter(|value| !range.contains(value)) {
+			return Response::builder()
+				.status(StatusCode::BAD_REQUEST)
+				.body(format!("Invalid {key}: {value}"))
+				.unwrap();
+		}
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	for (key, value, _) in limits {
+		let Some(value) = value else {
+			continue;
+		};
+		if let Err(err) = set_instance_setting(key, &value.to_string(), &mut db_conn) {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to save the setting: {err}"))
+				.unwrap();
+		}
+	}
+
+	json_response(&CacheLimits::load(&mut db_conn))
+}
+
+// :evict_transcode_cache
+// Runs the eviction now instead of waiting for the job, a cache under max_mb is left as it is
+pub async fn evict_transcode_cache(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+	let limits = match load_limits(&app_state) {
+		Ok(limits) => limits,
+		Err(response) => return response,
+	};
+
+	match tokio::task::spawn_blocking(move || TranscodeCache::new().evict(limits)).await {
+		Ok(Ok(freed)) => json_response(&freed),
+		Ok(Err(err)) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to evict from the transcode cache: {err}"))
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("The eviction didn't finish: {err}"))
+			.unwrap(),
+	}
+}
+
+// :purge_transcode_cache
+// Drops every bitrate of the track, the next stream transcodes it again
+pub async fn purge_transcode_cache(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(music_id): Path<String>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	match tokio::task::spawn_blocking(move || TranscodeCache::new().purge(&music_id)).await {
+		Ok(Ok(freed)) => json_response(&freed),
+		Ok(Err(err)) => Response::builder().status(StatusCode::BAD_REQUEST).body(err).unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("The purge didn't finish: {err}"))
+			.unwrap(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::users;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn admins_set_the_cache_limits() {
+		let test_app = TestApp::seeded();
+		let listener = test_app.login("seed_user_0").await;
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let admin = test_app.login("seed_user_1").await;
+
+		for (method, uri) in [
+			(Method::GET, "/admin/transcode_cache"),
+			(Method::POST, "/admin/transcode_cache/evict"),
+			(Method::POST, "/admin/transcode_cache/purge/some-track"),
+		] {
+			let response = test_app.request(method, uri, None, &listener).await;
+			assert_eq!(response.status, StatusCode::FORBIDDEN);
+		}
+
+		let body = test_app
+			.request(Method::GET, "/admin/transcode_cache", None, &admin)
+			.await
+			.json();
+		assert_eq!(body["limits"], json!({ "max_mb": 2048, "low_water_percent": 80 }));
+
+		for payload in [json!({ "max_mb": -1 }), json!({ "low_water_percent": 101 })] {
+			let response = test_app
+				.request(Method::POST, "/admin/transcode_cache", Some(payload), &admin)
+				.await;
+			assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		}
+		let body = test_app
+			.request(
+				Method::POST,
+				"/admin/transcode_cache",
+				Some(json!({ "low_water_percent": 50 })),
+				&admin,
+			)
+			.await
+			.json();
+		assert_eq!(body, json!({ "max_mb": 2048, "low_water_percent": 50 }));
+
+		// only ids of tracks, nothing that points out of the cache
+		let response = test_app
+			.request(Method::POST, "/admin/transcode_cache/purge/..%2Fmusic_db", None, &admin)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+	}
+}
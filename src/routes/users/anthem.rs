@@ -0,0 +1,163 @@
+use crate::config::MUSIC_STORAGE;
+use crate::core::app_state::AppState;
+use crate::services::ProfileService;
+use crate::utils::auth::require_user;
+
+use axum::{
+	body::Body,
+	extract::{Path, State},
+	http::{header, status::StatusCode},
+	response::{IntoResponse, Response},
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+#[derive(Debug, Deserialize)]
+pub struct AnthemPayload {
+	pub music_id: String,
+	pub start: Option<f64>, // seconds into the track, the beginning when left out
+}
+
+// :set_anthem
+pub async fn set_anthem(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<AnthemPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	match ProfileService::new(&app_state.db_pool).set_anthem(&user_id, &payload.music_id, payload.start) {
+		Ok(anthem) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&anthem).unwrap())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :clear_anthem
+pub async fn clear_anthem(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	match ProfileService::new(&app_state.db_pool).clear_anthem(&user_id) {
+		Ok(()) => Response::builder()
+			.status(StatusCode::OK)
+			.body("Anthem cleared".to_string())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :get_anthem_preview
+// The part of the mp3 between the start and end of the anthem. Constant bitrate is assumed for the
+// byte offsets, mp3 decoders resync on the next frame so cutting mid-frame is fine.
+pub async fn get_anthem_preview(State(app_state): State<AppState>, Path(user_id): Path<String>) -> Response {
+	let anthem = match ProfileService::new(&app_state.db_pool).anthem(&user_id) {
+		Ok(Some(anthem)) => anthem,
+		Ok(None) => return (StatusCode::NOT_FOUND, "No anthem").into_response(),
+		Err(err) => return err.into_response().into_response(),
+	};
+
+	let path = PathBuf::from(MUSIC_STORAGE).join(format!("{}.mp3", anthem.track.id));
+	let mut file = match File::open(&path).await {
+		Ok(file) => file,
+		Err(_) => return (StatusCode::NOT_FOUND, "Music not found").into_response(),
+	};
+	let file_len = match file.metadata().await {
+		Ok(metadata) => metadata.len(),
+		Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+	};
+
+	let bytes_per_sec = file_len as f64 / anthem.track.duration.max(1) as f64;
+	let offset = (anthem.start * bytes_per_sec) as u64;
+	let length = ((anthem.end - anthem.start) * bytes_per_sec) as u64;
+	if let Err(err) = file.seek(SeekFrom::Start(offset)).await {
+		return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+	}
+
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "audio/mpeg")
+		.header(header::CACHE_CONTROL, "public, max-age=300")
+		.body(Body::from_stream(ReaderStream::new(file.take(length))))
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::schema::user_friendship;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn anthems_show_on_profiles_and_friend_lists() {
+		let test_app = TestApp::seeded();
+		let track = test_app.get("/music/get_music?page_length=1").await.json()["items"][0].clone();
+		let user_id = test_app.user_id("seed_user_0");
+		let friend_id = test_app.user_id("seed_user_1");
+		diesel::insert_or_ignore_into(user_friendship::table)
+			.values((
+				user_friendship::user_id.eq(&friend_id),
+				user_friendship::friend_id.eq(&user_id),
+			))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+
+		let cookies = test_app.login("seed_user_0").await;
+		let payload = json!({ "music_id": track["id"], "start": track["duration"] });
+		let response = test_app
+			.request(Method::POST, "/user/anthem/set", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+		let payload = json!({ "music_id": track["id"], "start": 60.5 });
+		let body = test_app
+			.request(Method::POST, "/user/anthem/set", Some(payload), &cookies)
+			.await
+			.json();
+		assert_eq!(body["start"], 60.5);
+		assert_eq!(body["end"], 90.5);
+
+		let body = test_app
+			.get(&format!("/user/get_user_data?user_id={user_id}"))
+			.await
+			.json();
+		assert_eq!(body["anthem"]["track"]["id"], track["id"]);
+		assert_eq!(body["anthem"]["preview_url"], format!("/user/anthem/{user_id}/preview"));
+		let body = test_app.get(&format!("/friend/get/{friend_id}")).await.json();
+		let friend = body["items"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.find(|friend| friend["friend_id"] == user_id.as_str())
+			.unwrap();
+		assert_eq!(friend["anthem"]["track"]["id"], track["id"]);
+
+		test_app
+			.request(Method::POST, "/user/anthem/clear", None, &cookies)
+			.await;
+		let body = test_app
+			.get(&format!("/user/get_user_data?user_id={user_id}"))
+			.await
+			.json();
+		assert_eq!(body["anthem"], json!(null));
+		let response = test_app.get(&format!("/user/anthem/{user_id}/preview")).await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+	}
+}
@@ -0,0 +1,144 @@
+use crate::core::app_state::AppState;
+use crate::services::profile::{BlockTarget, BlockedContentResponse};
+use crate::services::{ProfileService, ServiceError};
+use crate::utils::auth::require_user;
+
+use axum::{
+	extract::State,
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct BlockPayload {
+	pub target_type: BlockTarget,
+	pub target_id: String, // music_id, or the artist name
+}
+
+fn blocked_response(result: Result<BlockedContentResponse, ServiceError>) -> Response<String> {
+	match result {
+		Ok(blocked) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&blocked).unwrap())
+			.unwrap(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :get_blocked_content
+pub async fn get_blocked_content(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+	blocked_response(ProfileService::new(&app_state.db_pool).blocked_content(&user_id))
+}
+
+// :block_content
+// Still found by search and playable on purpose, only what plays on its own skips it
+pub async fn block_content(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<BlockPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let service = ProfileService::new(&app_state.db_pool);
+	blocked_response(service.block(&user_id, payload.target_type, &payload.target_id))
+}
+
+// :unblock_content
+pub async fn unblock_content(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<BlockPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let service = ProfileService::new(&app_state.db_pool);
+	blocked_response(service.unblock(&user_id, payload.target_type, &payload.target_id))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn shuffles_skip_blocked_tracks_and_artists() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
+		let tracks = test_app.get("/music/get_music").await.json()["items"].clone();
+		let (track_id, artist) = (tracks[0]["id"].clone(), tracks[1]["artist"].clone());
+
+		for payload in [
+			json!({ "target_type": "track", "target_id": track_id }),
+			json!({ "target_type": "artist", "target_id": artist }),
+		] {
+			let response = test_app
+				.request(Method::POST, "/users/me/blocked_content/add", Some(payload), &cookies)
+				.await;
+			assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+		}
+		let response = test_app
+			.request(
+				Method::POST,
+				"/users/me/blocked_content/add",
+				Some(json!({ "target_type": "artist", "target_id": "Nobody At All" })),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+		let body = test_app
+			.request(Method::GET, "/users/me/blocked_content", None, &cookies)
+			.await
+			.json();
+		assert_eq!(body["tracks"][0]["id"], track_id);
+		assert_eq!(body["artists"], json!([artist]));
+
+		let shuffled = test_app
+			.request(Method::GET, "/music/get_music?randomizer=true", None, &cookies)
+			.await
+			.json();
+		let songs = shuffled["items"].as_array().unwrap();
+		assert!(!songs.is_empty());
+		assert!(songs
+			.iter()
+			.all(|song| song["id"] != track_id && song["artist"] != artist));
+		// only the shuffle, and only for that user
+		let listed = test_app
+			.request(Method::GET, "/music/get_music", None, &cookies)
+			.await
+			.json();
+		assert!(listed["items"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.any(|song| song["id"] == track_id));
+		let others = test_app.get("/music/get_music?randomizer=true").await.json();
+		assert!(others["total_count"].as_i64() > shuffled["total_count"].as_i64());
+
+		let body = test_app
+			.request(
+				Method::POST,
+				"/users/me/blocked_content/remove",
+				Some(json!({ "target_type": "track", "target_id": track_id })),
+				&cookies,
+			)
+			.await
+			.json();
+		assert_eq!(body["tracks"], json!([]));
+	}
+}
@@ -2,8 +2,11 @@ use crate::core::app_state::AppState;
 use crate::lobic_db::db::*;
 use crate::lobic_db::models::UserFriendship;
 use crate::schema::user_friendship;
+use crate::services::profile::AnthemResponse;
+use crate::services::ProfileService;
+use crate::utils::list::ListResponse;
 
-use serde_json::json;
+use serde::Serialize;
 use axum::{
 	extract::{Path, State},
 	http::status::StatusCode,
@@ -11,6 +14,11 @@ use axum::{
 };
 use diesel::prelude::*;
 
+#[derive(Debug, Serialize)]
+pub struct FriendResponse {
+	pub friend_id: String,
+	pub anthem: Option<AnthemResponse>, // shown next to them in the list
+}
 
 pub async fn get_friend(State(app_state): State<AppState>, Path(user_id): Path<String>) -> Response<String> {
 	if !user_exists(&user_id, &app_state.db_pool) {
@@ -53,12 +61,22 @@ pub async fn get_friend(State(app_state): State<AppState>, Path(user_id): Path<S
 		.map(|f| f.friend_id.clone())
 		.collect();
 
-	let response = json!({
-		"friends": friends
-	}).to_string();
-	
-	Response::builder()
-		.status(StatusCode::OK)
-		.body(response)
-		.unwrap()
+	// The anthems of the friends that have one
+	drop(db_conn);
+	let mut anthems = match ProfileService::new(&app_state.db_pool).anthems(&friends) {
+		Ok(anthems) => anthems,
+		Err(err) => return err.into_response(),
+	};
+
+	let friends: Vec<FriendResponse> = friends
+		.into_iter()
+		.map(|friend_id| {
+			let anthem = anthems
+				.iter()
+				.position(|anthem| anthem.user_id == friend_id)
+				.map(|index| anthems.swap_remove(index));
+			FriendResponse { friend_id, anthem }
+		})
+		.collect();
+	ListResponse::all(friends).into_response()
 }
@@ -1,6 +1,7 @@
 use crate::core::app_state::AppState;
 use crate::lobic_db::models::User;
 use crate::schema::users;
+use crate::services::ProfileService;
 
 use axum::{
 	extract::{Query, State},
@@ -48,10 +49,21 @@ pub async fn get_user_data(
 
 	match query {
 		Ok(user) => {
+			drop(db_conn);
+			let profile_service = ProfileService::new(&app_state.db_pool);
+			let (pinned, anthem) = match (
+				profile_service.pins(&user.user_id),
+				profile_service.anthem(&user.user_id),
+			) {
+				(Ok(pinned), Ok(anthem)) => (pinned, anthem),
+				(Err(err), _) | (_, Err(err)) => return err.into_response(),
+			};
 			let user_data = json!({
 				"id": user.user_id.clone(),
 				"username": user.username,
 				"email": user.email,
+				"pinned": pinned,
+				"anthem": anthem,
 			})
 			.to_string();
 			Response::builder().status(StatusCode::OK).body(user_data).unwrap()
@@ -0,0 +1,145 @@
+use crate::core::app_state::AppState;
+use crate::services::profile::{PinResponse, PinTarget};
+use crate::services::{ProfileService, ServiceError};
+use crate::utils::auth::require_user;
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::{Path, State},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct PinPayload {
+	pub target_type: PinTarget,
+	pub target_id: String,       // playlist_id, music_id, or the album name
+	pub position: Option<usize>, // 0 is first, last when left out
+}
+
+fn pins_response(result: Result<Vec<PinResponse>, ServiceError>) -> Response<String> {
+	match result {
+		Ok(pins) => ListResponse::all(pins).into_response(),
+		Err(err) => err.into_response(),
+	}
+}
+
+// :get_pins
+pub async fn get_pins(State(app_state): State<AppState>, Path(user_id): Path<String>) -> Response<String> {
+	pins_response(ProfileService::new(&app_state.db_pool).pins(&user_id))
+}
+
+// :pin_item
+// Pinning something already pinned moves it to the position
+pub async fn pin_item(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<PinPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let service = ProfileService::new(&app_state.db_pool);
+	pins_response(service.pin(&user_id, payload.target_type, &payload.target_id, payload.position))
+}
+
+// :unpin_item
+pub async fn unpin_item(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<PinPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let service = ProfileService::new(&app_state.db_pool);
+	pins_response(service.unpin(&user_id, payload.target_type, &payload.target_id))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::config::MAX_PROFILE_PINS;
+	use crate::schema::playlists;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn pins_are_ordered_and_capped() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let playlist_id = playlists::table
+			.filter(playlists::user_id.eq(&user_id))
+			.select(playlists::playlist_id)
+			.first::<String>(&mut test_app.db_conn())
+			.unwrap();
+		let tracks = test_app.get("/music/get_music").await.json()["items"].clone();
+		let cookies = test_app.login("seed_user_0").await;
+
+		for payload in [
+			json!({ "target_type": "track", "target_id": tracks[0]["id"] }),
+			json!({ "target_type": "album", "target_id": tracks[0]["album"] }),
+			json!({ "target_type": "playlist", "target_id": playlist_id, "position": 0 }),
+		] {
+			let response = test_app
+				.request(Method::POST, "/user/pins/pin", Some(payload), &cookies)
+				.await;
+			assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+		}
+		let body = test_app
+			.get(&format!("/user/get_user_data?user_id={user_id}"))
+			.await
+			.json();
+		let pinned: Vec<_> = body["pinned"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|pin| pin["target_type"].as_str().unwrap())
+			.collect();
+		assert_eq!(pinned, ["playlist", "track", "album"]);
+		assert_eq!(body["pinned"][0]["playlist_id"], playlist_id.as_str());
+
+		// Pinning again only moves it
+		let payload = json!({ "target_type": "album", "target_id": tracks[0]["album"], "position": 0 });
+		let body = test_app
+			.request(Method::POST, "/user/pins/pin", Some(payload), &cookies)
+			.await
+			.json();
+		assert_eq!(body["total_count"], 3);
+		assert_eq!(body["items"][0]["target_type"], "album");
+		assert_eq!(body["items"][2]["position"], 2);
+
+		for track in tracks.as_array().unwrap().iter().skip(1).take(MAX_PROFILE_PINS - 3) {
+			let payload = json!({ "target_type": "track", "target_id": track["id"] });
+			test_app
+				.request(Method::POST, "/user/pins/pin", Some(payload), &cookies)
+				.await;
+		}
+		let payload = json!({ "target_type": "track", "target_id": tracks[MAX_PROFILE_PINS]["id"] });
+		let response = test_app
+			.request(Method::POST, "/user/pins/pin", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+		let payload = json!({ "target_type": "playlist", "target_id": playlist_id });
+		let body = test_app
+			.request(Method::POST, "/user/pins/unpin", Some(payload.clone()), &cookies)
+			.await
+			.json();
+		assert_eq!(body["total_count"], MAX_PROFILE_PINS - 1);
+		let response = test_app
+			.request(Method::POST, "/user/pins/unpin", Some(payload), &cookies)
+			.await;
+		assert_eq!(response.status, StatusCode::NOT_FOUND);
+		let body = test_app.get(&format!("/user/pins/get/{user_id}")).await.json();
+		assert_eq!(body["items"][0]["target_type"], "album");
+	}
+}
@@ -0,0 +1,437 @@
+use crate::config::{MAX_PRIVATE_SESSION_HOURS, PRIVATE_SESSION_HOURS};
+use crate::core::app_state::AppState;
+use crate::core::weekly_email::unsubscribing_user;
+use crate::i18n::Locale;
+use crate::lobic_db::db::get_user_settings;
+use crate::lobic_db::models::StatsVisibility;
+use crate::mail::templates::escape_html;
+use crate::schema::user_settings;
+use crate::utils::auth::require_user;
+
+use axum::{
+	extract::{Query, State},
+	http::{header, status::StatusCode, HeaderMap},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{Duration, NaiveTime, Utc};
+use diesel::prelude::*;
+use serde::Deserialize;
+
+// :get_settings
+pub async fn get_settings(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let settings = get_user_settings(&user_id, &mut db_conn);
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(serde_json::to_string(&settings).unwrap())
+		.unwrap()
+}
+
+// :update_settings
+// Only the given fields are changed
+#[derive(Debug, Deserialize)]
+pub struct UpdateSettingsPayload {
+	pub stats_visibility: Option<String>, // public | friends | private
+	pub leaderboard_opt_out: Option<bool>,
+	pub language: Option<String>, // en | ne, empty follows Accept-Language
+	pub private_session: Option<bool>,
+	pub private_session_hours: Option<i64>, // PRIVATE_SESSION_HOURS when left out
+	pub do_not_disturb: Option<bool>,
+	pub quiet_hours_start: Option<String>, // HH:MM in server local time, empty turns quiet hours off
+	pub quiet_hours_end: Option<String>,
+	pub allow_listen_along: Option<bool>,
+	pub weekly_email: Option<bool>,
+}
+
+pub async fn update_settings(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	jar: CookieJar,
+	Json(payload): Json<UpdateSettingsPayload>,
+) -> Response<String> {
+	let user_id = match require_user(&jar) {
+		Ok(id) => id,
+		Err(response) => return response,
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let mut settings = get_user_settings(&user_id, &mut db_conn);
+	let locale = Locale::for_user(&user_id, &mut db_conn).unwrap_or_else(|| Locale::from_headers(&headers));
+	if let Some(visibility) = payload.stats_visibility {
+		match StatsVisibility::parse(&visibility) {
+			Some(visibility) => settings.stats_visibility = visibility.as_str().to_string(),
+			None => {
+				return Response::builder()
+					.status(StatusCode::BAD_REQUEST)
+					.body(locale.tf("settings.invalid_visibility", &[("visibility", &visibility)]))
+					.unwrap();
+			}
+		}
+	}
+
+	if let Some(opt_out) = payload.leaderboard_opt_out {
+		settings.leaderboard_opt_out = opt_out;
+	}
+
+	if let Some(language) = payload.language {
+		if language.is_empty() {
+			settings.language = None;
+		} else {
+			match Locale::parse(&language) {
+				Some(parsed) => settings.language = Some(parsed.as_str().to_string()),
+				None => {
+					return Response::builder()
+						.status(StatusCode::BAD_REQUEST)
+						.body(locale.tf("settings.invalid_language", &[("language", &language)]))
+						.unwrap();
+				}
+			}
+		}
+	}
+
+	// Plays during a private session only go to the user's own history
+	match payload.private_session {
+		Some(true) => {
+			let hours = payload.private_session_hours.unwrap_or(PRIVATE_SESSION_HOURS);
+			if !(1..=MAX_PRIVATE_SESSION_HOURS).contains(&hours) {
+				let max = MAX_PRIVATE_SESSION_HOURS.to_string();
+				return Response::builder()
+					.status(StatusCode::BAD_REQUEST)
+					.body(locale.tf("settings.invalid_private_session_hours", &[("max", &max)]))
+					.unwrap();
+			}
+			settings.private_session_until = Some((Utc::now() + Duration::hours(hours)).to_rfc3339());
+		}
+		Some(false) => settings.private_session_until = None,
+		None => (),
+	}
+
+	if let Some(do_not_disturb) = payload.do_not_disturb {
+		settings.do_not_disturb = do_not_disturb;
+	}
+
+	if let Some(allow) = payload.allow_listen_along {
+		settings.allow_listen_along = allow;
+	}
+
+	if let Some(weekly_email) = payload.weekly_email {
+		settings.weekly_email = weekly_email;
+	}
+
+	for (time, field) in [
+		(payload.quiet_hours_start, &mut settings.quiet_hours_start),
+		(payload.quiet_hours_end, &mut settings.quiet_hours_end),
+	] {
+		let Some(time) = time else {
+			continue;
+		};
+		if time.is_empty() {
+			*field = None;
+		} else if NaiveTime::parse_from_str(&time, "%H:%M").is_ok() {
+			*field = Some(time);
+		} else {
+			return Response::builder()
+				.status(StatusCode::BAD_REQUEST)
+				.body(locale.tf("settings.invalid_quiet_hours", &[("time", &time)]))
+				.unwrap();
+		}
+	}
+
+	let result = diesel::insert_into(user_settings::table)
+		.values(&settings)
+		.on_conflict(user_settings::user_id)
+		.do_update()
+		.set((
+			user_settings::stats_visibility.eq(&settings.stats_visibility),
+			user_settings::leaderboard_opt_out.eq(settings.leaderboard_opt_out),
+			user_settings::language.eq(&settings.language),
+			user_settings::private_session_until.eq(&settings.private_session_until),
+			user_settings::do_not_disturb.eq(settings.do_not_disturb),
+			user_settings::quiet_hours_start.eq(&settings.quiet_hours_start),
+			user_settings::quiet_hours_end.eq(&settings.quiet_hours_end),
+			user_settings::allow_listen_along.eq(settings.allow_listen_along),
+			user_settings::weekly_email.eq(settings.weekly_email),
+		))
+		.execute(&mut db_conn);
+
+	match result {
+		Ok(_) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string(&settings).unwrap())
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to save settings: {err}"))
+			.unwrap(),
+	}
+}
+
+// ?token= of the link in the mail, it stands in for being logged in
+#[derive(Debug, Deserialize)]
+pub struct UnsubscribeQuery {
+	pub token: String,
+}
+
+// :confirm_unsubscribe
+// Opened from the link in the mail, it only asks. Mail providers open the links to scan them, that
+// shouldn't unsubscribe anyone.
+pub async fn confirm_unsubscribe(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	Query(params): Query<UnsubscribeQuery>,
+) -> Response<String> {
+	let Some(user_id) = unsubscribing_user(&params.token) else {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body("Invalid or expired unsubscribe link".to_string())
+			.unwrap();
+	};
+
+	let locale = app_state
+		.db_pool
+		.get()
+		.ok()
+		.and_then(|mut db_conn| Locale::for_user(&user_id, &mut db_conn))
+		.unwrap_or_else(|| Locale::from_headers(&headers));
+	// without an action the form posts back to this url, token and all
+	let html = format!(
+		"<!DOCTYPE html>\n<html lang=\"{}\"><body><form method=\"post\"><p>{}</p><button type=\"submit\">{}</button></form></body></html>\n",
+		locale.as_str(),
+		escape_html(&locale.t("mail.unsubscribe_confirm")),
+		escape_html(&locale.t("mail.digest.unsubscribe")),
+	);
+	Response::builder()
+		.status(StatusCode::OK)
+		.header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+		.body(html)
+		.unwrap()
+}
+
+// :unsubscribe_weekly_email
+// The form of the confirm page, and the one-click unsubscribe of mail clients (RFC 8058) that post
+// List-Unsubscribe=One-Click to the link on their own
+pub async fn unsubscribe_weekly_email(
+	State(app_state): State<AppState>,
+	headers: HeaderMap,
+	Query(params): Query<UnsubscribeQuery>,
+) -> Response<String> {
+	let Some(user_id) = unsubscribing_user(&params.token) else {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body("Invalid or expired unsubscribe link".to_string())
+			.unwrap();
+	};
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let mut settings = get_user_settings(&user_id, &mut db_conn);
+	settings.weekly_email = false;
+	let result = diesel::insert_into(user_settings::table)
+		.values(&settings)
+		.on_conflict(user_settings::user_id)
+		.do_update()
+		.set(user_settings::weekly_email.eq(false))
+		.execute(&mut db_conn);
+
+	let locale = Locale::for_user(&user_id, &mut db_conn).unwrap_or_else(|| Locale::from_headers(&headers));
+	match result {
+		Ok(_) => Response::builder()
+			.status(StatusCode::OK)
+			.header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+			.body(locale.tf("mail.unsubscribed", &[]))
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to save settings: {err}"))
+			.unwrap(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::lobic_db::db::get_user_settings;
+	use crate::schema::user_friendship;
+	use crate::test_support::TestApp;
+
+	use axum::http::{Method, StatusCode};
+	use chrono::NaiveTime;
+	use diesel::prelude::*;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn language_setting_localizes_errors() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
+
+		let response = test_app
+			.request(
+				Method::POST,
+				"/user/settings/update",
+				Some(json!({ "language": "ne-NP" })),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+		assert_eq!(response.json()["language"], "ne");
+
+		let response = test_app
+			.request(
+				Method::POST,
+				"/user/settings/update",
+				Some(json!({ "stats_visibility": "everyone" })),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		assert_eq!(response.body, "अमान्य तथ्याङ्क दृश्यता: everyone");
+
+		let response = test_app
+			.request(
+				Method::POST,
+				"/user/settings/update",
+				Some(json!({ "language": "fr" })),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+	}
+
+	#[tokio::test]
+	async fn private_sessions_hide_listening_from_friends() {
+		let test_app = TestApp::seeded();
+		let music_id = test_app.get("/music/get_music?page_length=1").await.json()["items"][0]["id"].clone();
+		let user_id = test_app.user_id("seed_user_0");
+		let friend_id = test_app.user_id("seed_user_1");
+		diesel::insert_or_ignore_into(user_friendship::table)
+			.values((
+				user_friendship::user_id.eq(&friend_id),
+				user_friendship::friend_id.eq(&user_id),
+			))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+
+		let cookies = test_app.login("seed_user_0").await;
+		let response = test_app
+			.request(
+				Method::POST,
+				"/user/settings/update",
+				Some(json!({ "private_session": true, "private_session_hours": 100 })),
+				&cookies,
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		let body = test_app
+			.request(
+				Method::POST,
+				"/user/settings/update",
+				Some(json!({ "private_session": true })),
+				&cookies,
+			)
+			.await
+			.json();
+		assert!(body["private_session_until"].is_string());
+
+		let heartbeat = json!({ "music_id": music_id, "position": 12.0, "state": "PLAY" });
+		let body = test_app
+			.request(Method::POST, "/player/heartbeat", Some(heartbeat.clone()), &cookies)
+			.await
+			.json();
+		assert_eq!(body["private"], true);
+		let body = test_app.get(&format!("/player/now_playing/{user_id}")).await.json();
+		assert_eq!(body, json!(null));
+		let friend_cookies = test_app.login("seed_user_1").await;
+		let body = test_app
+			.request(Method::GET, "/player/friends_activity", None, &friend_cookies)
+			.await
+			.json();
+		assert_eq!(body["items"], json!([]));
+		let body = test_app
+			.request(Method::GET, "/player/resume", None, &cookies)
+			.await
+			.json();
+		assert_eq!(body["music_id"], music_id);
+
+		test_app
+			.request(
+				Method::POST,
+				"/user/settings/update",
+				Some(json!({ "private_session": false })),
+				&cookies,
+			)
+			.await;
+		test_app
+			.request(Method::POST, "/player/heartbeat", Some(heartbeat), &cookies)
+			.await;
+		let body = test_app
+			.request(Method::GET, "/player/friends_activity", None, &friend_cookies)
+			.await
+			.json();
+		assert_eq!(body["items"][0]["user_id"], user_id.as_str());
+	}
+
+	#[tokio::test]
+	async fn quiet_hours_wrap_around_midnight() {
+		let test_app = TestApp::seeded();
+		let cookies = test_app.login("seed_user_0").await;
+		let update = |payload| test_app.request(Method::POST, "/user/settings/update", Some(payload), &cookies);
+
+		let response = update(json!({ "quiet_hours_start": "25:00" })).await;
+		assert_eq!(response.status, StatusCode::BAD_REQUEST);
+		let body = update(json!({ "quiet_hours_start": "22:00", "quiet_hours_end": "07:30" }))
+			.await
+			.json();
+		assert_eq!(body["quiet_hours_start"], "22:00");
+		assert_eq!(body["do_not_disturb"], false);
+
+		let at = |time: &str| NaiveTime::parse_from_str(time, "%H:%M").unwrap();
+		let mut settings = get_user_settings(&test_app.user_id("seed_user_0"), &mut test_app.db_conn());
+		assert!(settings.is_quiet_at(at("23:15")));
+		assert!(settings.is_quiet_at(at("03:00")));
+		assert!(!settings.is_quiet_at(at("07:30")));
+		assert!(!settings.is_quiet_at(at("12:00")));
+
+		settings.do_not_disturb = true;
+		assert!(settings.is_quiet_at(at("12:00")));
+		update(json!({ "quiet_hours_end": "" })).await;
+		let settings = get_user_settings(&test_app.user_id("seed_user_0"), &mut test_app.db_conn());
+		assert!(!settings.is_quiet_at(at("23:15")));
+	}
+}
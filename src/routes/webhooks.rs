@@ -0,0 +1,289 @@
+use crate::core::app_state::AppState;
+use crate::core::federation::generate_token;
+use crate::core::webhooks::latest_change_id;
+use crate::lobic_db::models::Webhook;
+use crate::schema::webhooks;
+use crate::utils::auth::require_admin;
+use crate::utils::list::ListResponse;
+
+use axum::{
+	extract::{Path, State},
+	http::{header, status::StatusCode},
+	response::Response,
+	Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+// :get_webhooks
+pub async fn get_webhooks(State(app_state): State<AppState>, jar: CookieJar) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	match webhooks::table
+		.order(webhooks::created_date_time.asc())
+		.load::<Webhook>(&mut db_conn)
+	{
+		Ok(hooks) => ListResponse::all(hooks).into_response(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Database error: {err}"))
+			.unwrap(),
+	}
+}
+
+// :add_webhook
+// Tracks added, removed or retagged from now on get posted to the url, the secret comes along as a
+// bearer token and is only shown here
+#[derive(Debug, Deserialize)]
+pub struct AddWebhookPayload {
+	pub url: String,
+}
+
+pub async fn add_webhook(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Json(payload): Json<AddWebhookPayload>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+	if !payload.url.starts_with("http://") && !payload.url.starts_with("https://") {
+		return Response::builder()
+			.status(StatusCode::BAD_REQUEST)
+			.body(format!("Invalid webhook url: {}", payload.url))
+			.unwrap();
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	let created = db_conn.transaction(|conn| {
+		let hook = Webhook {
+			webhook_id: Uuid::new_v4().to_string(),
+			url: payload.url,
+			secret: generate_token(),
+			delivered_change_id: latest_change_id(conn)?,
+			last_error: None,
+			created_date_time: Utc::now().to_rfc3339(),
+		};
+		diesel::insert_into(webhooks::table).values(&hook).execute(conn)?;
+		Ok::<_, diesel::result::Error>(hook)
+	});
+	match created {
+		Ok(hook) => {
+			let mut body = json!(hook);
+			body["secret"] = hook.secret.into();
+			Response::builder()
+				.status(StatusCode::CREATED)
+				.header(header::CONTENT_TYPE, "application/json")
+				.body(body.to_string())
+				.unwrap()
+		}
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to add webhook: {err}"))
+			.unwrap(),
+	}
+}
+
+// :remove_webhook
+pub async fn remove_webhook(
+	State(app_state): State<AppState>,
+	jar: CookieJar,
+	Path(webhook_id): Path<String>,
+) -> Response<String> {
+	if let Err(response) = require_admin(&jar, &app_state.db_pool) {
+		return response;
+	}
+
+	let mut db_conn = match app_state.db_pool.get() {
+		Ok(conn) => conn,
+		Err(err) => {
+			let msg = format!("Failed to get DB from pool: {err}");
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(msg)
+				.unwrap();
+		}
+	};
+
+	match diesel::delete(webhooks::table.find(&webhook_id)).execute(&mut db_conn) {
+		Ok(0) => Response::builder()
+			.status(StatusCode::NOT_FOUND)
+			.body(format!("No webhook: {webhook_id}"))
+			.unwrap(),
+		Ok(_) => Response::builder()
+			.status(StatusCode::OK)
+			.body(format!("Webhook {webhook_id} removed"))
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to remove webhook: {err}"))
+			.unwrap(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::core::webhooks::deliver;
+	use crate::schema::{library_changes, users};
+	use crate::test_support::TestApp;
+
+	use axum::{
+		extract::State,
+		http::{HeaderMap, Method, StatusCode},
+		routing::post,
+		Json, Router,
+	};
+	use diesel::prelude::*;
+	use serde_json::{json, Value};
+	use std::sync::{Arc, Mutex};
+
+	type Received = Arc<Mutex<Vec<(Option<String>, Value)>>>;
+
+	async fn receive(State(received): State<Received>, headers: HeaderMap, Json(body): Json<Value>) {
+		let authorization = headers
+			.get("authorization")
+			.and_then(|value| value.to_str().ok())
+			.map(String::from);
+		received.lock().unwrap().push((authorization, body));
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn removed_tracks_get_posted_to_the_webhooks() {
+		let received: Received = Arc::default();
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let url = format!("http://{}/library", listener.local_addr().unwrap());
+		let receiver = Router::new()
+			.route("/library", post(receive))
+			.with_state(received.clone());
+		tokio::spawn(async move { axum::serve(listener, receiver).await });
+
+		let test_app = TestApp::seeded();
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let cookies = test_app.login("seed_user_1").await;
+		let hook = test_app
+			.request(
+				Method::POST,
+				"/admin/webhooks/add",
+				Some(json!({ "url": url })),
+				&cookies,
+			)
+			.await
+			.json();
+		let secret = hook["secret"].as_str().unwrap().to_string();
+
+		let music_id = test_app.get("/music/get_music?page_length=1").await.json()["items"][0]["id"].clone();
+		let response = test_app
+			.post(
+				"/music/availability/set",
+				json!({ "music_id": music_id, "availability": "taken_down" }),
+			)
+			.await;
+		assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+		test_app
+			.request(
+				Method::POST,
+				"/music/availability/set",
+				Some(json!({ "music_id": music_id, "availability": "taken_down" })),
+				&cookies,
+			)
+			.await;
+		let app_state = test_app.app_state.clone();
+		tokio::task::spawn_blocking(move || deliver(&app_state))
+			.await
+			.unwrap()
+			.unwrap();
+
+		let received = received.lock().unwrap().clone();
+		assert_eq!(received.len(), 1);
+		let (authorization, body) = &received[0];
+		assert_eq!(authorization.as_deref(), Some(format!("Bearer {secret}").as_str()));
+		assert_eq!(body["changes"][0]["music_id"], music_id);
+		assert_eq!(body["changes"][0]["change"], "removed");
+
+		let hooks = test_app
+			.request(Method::GET, "/admin/webhooks", None, &cookies)
+			.await
+			.json();
+		assert_eq!(
+			hooks["items"][0]["delivered_change_id"],
+			body["changes"][0]["change_id"]
+		);
+		assert_eq!(hooks["items"][0]["secret"], Value::Null);
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn unreachable_webhooks_lose_changes_older_than_a_week() {
+		let test_app = TestApp::seeded();
+		diesel::update(users::table.filter(users::username.eq("seed_user_1")))
+			.set(users::is_admin.eq(true))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let cookies = test_app.login("seed_user_1").await;
+		test_app
+			.request(
+				Method::POST,
+				"/admin/webhooks/add",
+				Some(json!({ "url": "http://127.0.0.1:9/library" })),
+				&cookies,
+			)
+			.await;
+
+		let now = chrono::Utc::now();
+		for (music_id, changed) in [("old", now - chrono::Duration::days(8)), ("new", now)] {
+			diesel::insert_into(library_changes::table)
+				.values((
+					library_changes::music_id.eq(music_id),
+					library_changes::change.eq("added"),
+					library_changes::changed_date_time.eq(changed.to_rfc3339()),
+				))
+				.execute(&mut test_app.db_conn())
+				.unwrap();
+		}
+		let app_state = test_app.app_state.clone();
+		tokio::task::spawn_blocking(move || deliver(&app_state))
+			.await
+			.unwrap()
+			.unwrap();
+
+		let pending = library_changes::table
+			.select(library_changes::music_id)
+			.load::<String>(&mut test_app.db_conn())
+			.unwrap();
+		assert_eq!(pending, ["new"]);
+		let hooks = test_app
+			.request(Method::GET, "/admin/webhooks", None, &cookies)
+			.await
+			.json();
+		assert!(hooks["items"][0]["last_error"].is_string());
+	}
+}
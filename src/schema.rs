@@ -1,5 +1,192 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    analytics_daily (day) {
+        day -> Text,
+        active_users -> Integer,
+        weekly_active_users -> Integer,
+        monthly_active_users -> Integer,
+        plays -> Integer,
+        minutes_listened -> BigInt,
+        refreshed_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    analytics_retention (cohort_week, week_offset) {
+        cohort_week -> Text,
+        week_offset -> Integer,
+        cohort_size -> Integer,
+        retained_users -> Integer,
+    }
+}
+
+diesel::table! {
+    analytics_top_content (kind, rank) {
+        kind -> Text,
+        rank -> Integer,
+        target_id -> Text,
+        plays -> Integer,
+    }
+}
+
+diesel::table! {
+    animated_covers (target_type, target_id) {
+        target_type -> Text,
+        target_id -> Text,
+        uploader_id -> Text,
+        duration_secs -> Double,
+        uploaded_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    artist_images (artist) {
+        artist -> Text,
+        img_uuid -> Text,
+        source -> Text,
+        source_url -> Nullable<Text>,
+        fetched_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    artist_follows (user_id, artist) {
+        user_id -> Text,
+        artist -> Text,
+        followed_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    audiobook_progress (user_id, music_id) {
+        user_id -> Text,
+        music_id -> Text,
+        position -> Double,
+        finished -> Bool,
+        updated_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    blocked_content (user_id, target_type, target_id) {
+        user_id -> Text,
+        target_type -> Text,
+        target_id -> Text,
+        blocked_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    blocked_tags (tag) {
+        tag -> Text,
+        blocked_by -> Text,
+        blocked_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    client_errors (error_id) {
+        error_id -> Text,
+        user_id -> Text,
+        music_id -> Text,
+        error_code -> Text,
+        device -> Nullable<Text>,
+        network_type -> Nullable<Text>,
+        message -> Nullable<Text>,
+        reported_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    cover_art (art_id) {
+        art_id -> Text,
+        img_uuid -> Text,
+        uploader_id -> Nullable<Text>,
+        is_original -> Bool,
+        is_canonical -> Bool,
+        uploaded_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    cover_palettes (img_uuid) {
+        img_uuid -> Text,
+        colors -> Text,
+        extracted_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    embed_tokens (token_id) {
+        token_id -> Text,
+        user_id -> Text,
+        target_type -> Text,
+        target_id -> Text,
+        token -> Text,
+        created_date_time -> Text,
+        last_used_date_time -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    federation_peers (peer_id) {
+        peer_id -> Text,
+        name -> Text,
+        base_url -> Nullable<Text>,
+        local_token -> Text,
+        remote_token -> Nullable<Text>,
+        status -> Text,
+        created_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    first_listens (user_id, target_type, target_id) {
+        user_id -> Text,
+        target_type -> Text,
+        target_id -> Text,
+        first_played_date_time -> Text,
+        notified_year -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    instance_settings (key) {
+        key -> Text,
+        value -> Text,
+    }
+}
+
+diesel::table! {
+    ip_rules (rule_id) {
+        rule_id -> Text,
+        cidr -> Text,
+        action -> Text,
+        note -> Nullable<Text>,
+        created_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    leaderboard_entries (week_start, user_id) {
+        week_start -> Text,
+        user_id -> Text,
+        minutes_listened -> BigInt,
+        distinct_artists -> Integer,
+        refreshed_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    library_changes (change_id) {
+        change_id -> Integer,
+        music_id -> Text,
+        change -> Text,
+        changed_date_time -> Text,
+    }
+}
+
 diesel::table! {
     liked_songs (user_id, music_id) {
         user_id -> Text,
@@ -8,6 +195,20 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    listening_goals (goal_id) {
+        goal_id -> Text,
+        user_id -> Text,
+        kind -> Text,
+        target -> Integer,
+        period_start -> Text,
+        period_end -> Text,
+        progress -> Integer,
+        completed_date_time -> Nullable<Text>,
+        created_date_time -> Text,
+    }
+}
+
 diesel::table! {
     music (music_id) {
         music_id -> Text,
@@ -17,6 +218,47 @@ diesel::table! {
         genre -> Text,
         times_played -> Integer,
         duration -> BigInt,
+        availability -> Text,
+        availability_reason -> Nullable<Text>,
+        uploader_id -> Nullable<Text>,
+        content_type -> Text,
+        series -> Nullable<Text>,
+        series_index -> Nullable<Integer>,
+        release_year -> Nullable<Integer>,
+        bpm -> Nullable<Double>,
+        musical_key -> Nullable<Text>,
+        explicit -> Bool,
+    }
+}
+
+diesel::table! {
+    music_alt_names (music_id, field, name) {
+        music_id -> Text,
+        field -> Text,
+        name -> Text,
+        kind -> Text,
+    }
+}
+
+diesel::table! {
+    music_chapters (music_id, chapter_index) {
+        music_id -> Text,
+        chapter_index -> Integer,
+        title -> Text,
+        start_ms -> BigInt,
+        end_ms -> BigInt,
+    }
+}
+
+diesel::table! {
+    new_releases (release_id) {
+        release_id -> Text,
+        user_id -> Text,
+        artist -> Text,
+        album -> Text,
+        music_ids -> Text,
+        released_date_time -> Text,
+        notified -> Bool,
     }
 }
 
@@ -29,6 +271,62 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    oauth_clients (client_id) {
+        client_id -> Text,
+        client_secret -> Text,
+        name -> Text,
+        redirect_uris -> Text,
+        owner_id -> Text,
+        created_date_time -> Text,
+        is_public -> Bool,
+    }
+}
+
+diesel::table! {
+    oauth_codes (code) {
+        code -> Text,
+        client_id -> Text,
+        user_id -> Text,
+        redirect_uri -> Text,
+        scope -> Text,
+        expires_date_time -> Text,
+        code_challenge -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    oauth_grants (user_id, client_id) {
+        user_id -> Text,
+        client_id -> Text,
+        scope -> Text,
+        granted_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    oauth_tokens (access_token) {
+        access_token -> Text,
+        refresh_token -> Text,
+        client_id -> Text,
+        user_id -> Text,
+        scope -> Text,
+        expires_date_time -> Text,
+        created_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    play_events (event_id) {
+        event_id -> Text,
+        user_id -> Text,
+        music_id -> Text,
+        played_date_time -> Text,
+        listened_secs -> BigInt,
+        device -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     play_log (user_id, music_id) {
         user_id -> Text,
@@ -38,6 +336,56 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    play_rollups_daily (user_id, day, music_id) {
+        user_id -> Text,
+        day -> Text,
+        music_id -> Text,
+        play_count -> Integer,
+        listened_secs -> BigInt,
+    }
+}
+
+diesel::table! {
+    play_rollups_monthly (user_id, month, music_id) {
+        user_id -> Text,
+        month -> Text,
+        music_id -> Text,
+        play_count -> Integer,
+        listened_secs -> BigInt,
+    }
+}
+
+diesel::table! {
+    playlist_folder_items (user_id, playlist_id) {
+        user_id -> Text,
+        playlist_id -> Text,
+        folder_id -> Nullable<Text>,
+        position -> Integer,
+    }
+}
+
+diesel::table! {
+    playlist_folders (folder_id) {
+        folder_id -> Text,
+        user_id -> Text,
+        name -> Text,
+        parent_id -> Nullable<Text>,
+        position -> Integer,
+        created_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    playlist_releases (playlist_id) {
+        playlist_id -> Text,
+        user_id -> Text,
+        release_date_time -> Text,
+        previous_availability -> Text,
+        scheduled_date_time -> Text,
+    }
+}
+
 diesel::table! {
     playlist_shares (playlist_id, contributor_user_id) {
         playlist_id -> Text,
@@ -46,11 +394,33 @@ diesel::table! {
 }
 
 diesel::table! {
-    playlist_songs (playlist_id, music_id) {
+    playlist_songs (playlist_id, position) {
         playlist_id -> Text,
         music_id -> Text,
         song_adder_id -> Text,
         song_added_date_time -> Text,
+        position -> Integer,
+    }
+}
+
+diesel::table! {
+    playlist_trash (playlist_id) {
+        playlist_id -> Text,
+        user_id -> Text,
+        playlist_name -> Text,
+        song_count -> Integer,
+        snapshot -> Text,
+        deleted_date_time -> Text,
+        purge_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    playlist_undo (token) {
+        token -> Text,
+        playlist_id -> Text,
+        snapshot -> Text,
+        expires_date_time -> Text,
     }
 }
 
@@ -62,6 +432,136 @@ diesel::table! {
         creation_date_time -> Text,
         last_updated_date_time -> Text,
         is_playlist_combined -> Bool,
+        availability -> Text,
+        availability_reason -> Nullable<Text>,
+        version -> Integer,
+    }
+}
+
+diesel::table! {
+    profile_anthems (user_id) {
+        user_id -> Text,
+        music_id -> Text,
+        start_secs -> Double,
+        updated_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    profile_pins (user_id, target_type, target_id) {
+        user_id -> Text,
+        target_type -> Text,
+        target_id -> Text,
+        position -> Integer,
+        pinned_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    queue_snapshots (snapshot_id) {
+        snapshot_id -> Text,
+        user_id -> Text,
+        name -> Text,
+        music_ids -> Text,
+        position -> Double,
+        automatic -> Bool,
+        created_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    quotas (user_id, feature, day) {
+        user_id -> Text,
+        feature -> Text,
+        day -> Text,
+        used -> Integer,
+    }
+}
+
+diesel::table! {
+    saved_searches (search_id) {
+        search_id -> Text,
+        user_id -> Text,
+        name -> Text,
+        search_string -> Text,
+        genre -> Nullable<Text>,
+        decade -> Nullable<Text>,
+        bpm_min -> Nullable<Double>,
+        bpm_max -> Nullable<Double>,
+        musical_key -> Nullable<Text>,
+        created_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    scrobble_tokens (token_id) {
+        token_id -> Text,
+        user_id -> Text,
+        name -> Text,
+        token -> Text,
+        created_date_time -> Text,
+        last_used_date_time -> Nullable<Text>,
+        scope -> Text,
+    }
+}
+
+diesel::table! {
+    takedown_events (event_id) {
+        event_id -> Text,
+        takedown_id -> Text,
+        actor_id -> Text,
+        action -> Text,
+        message -> Text,
+        created_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    takedowns (takedown_id) {
+        takedown_id -> Text,
+        target_type -> Text,
+        target_id -> Text,
+        reason -> Text,
+        admin_id -> Text,
+        status -> Text,
+        created_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    track_genres (music_id, genre) {
+        music_id -> Text,
+        genre -> Text,
+        position -> Integer,
+    }
+}
+
+diesel::table! {
+    track_moods (music_id) {
+        music_id -> Text,
+        mood -> Text,
+        energy -> Double,
+        source -> Text,
+        tagged_by -> Nullable<Text>,
+        updated_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    track_ratings (user_id, music_id) {
+        user_id -> Text,
+        music_id -> Text,
+        rating -> Integer,
+        rated_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    user_achievements (user_id, achievement_id) {
+        user_id -> Text,
+        achievement_id -> Text,
+        progress -> Integer,
+        unlocked_date_time -> Nullable<Text>,
     }
 }
 
@@ -72,6 +572,31 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_settings (user_id) {
+        user_id -> Text,
+        stats_visibility -> Text,
+        leaderboard_opt_out -> Bool,
+        language -> Nullable<Text>,
+        private_session_until -> Nullable<Text>,
+        do_not_disturb -> Bool,
+        quiet_hours_start -> Nullable<Text>,
+        quiet_hours_end -> Nullable<Text>,
+        allow_listen_along -> Bool,
+        weekly_email -> Bool,
+    }
+}
+
+diesel::table! {
+    user_tags (user_id, target_type, target_id, tag) {
+        user_id -> Text,
+        target_type -> Text,
+        target_id -> Text,
+        tag -> Text,
+        created_date_time -> Text,
+    }
+}
+
 diesel::table! {
     users (user_id) {
         user_id -> Text,
@@ -82,29 +607,154 @@ diesel::table! {
         otp -> Text,
         otp_expires_at -> Text,
         otp_verified -> Nullable<Text>,
+        is_admin -> Bool,
+    }
+}
+
+diesel::table! {
+    webhooks (webhook_id) {
+        webhook_id -> Text,
+        url -> Text,
+        secret -> Text,
+        delivered_change_id -> Integer,
+        last_error -> Nullable<Text>,
+        created_date_time -> Text,
+    }
+}
+
+diesel::table! {
+    weekly_emails (user_id, week_start) {
+        user_id -> Text,
+        week_start -> Text,
+        sent_date_time -> Text,
     }
 }
 
+diesel::joinable!(animated_covers -> users (uploader_id));
+diesel::joinable!(artist_follows -> users (user_id));
+diesel::joinable!(audiobook_progress -> music (music_id));
+diesel::joinable!(audiobook_progress -> users (user_id));
+diesel::joinable!(blocked_content -> users (user_id));
+diesel::joinable!(blocked_tags -> users (blocked_by));
+diesel::joinable!(client_errors -> music (music_id));
+diesel::joinable!(client_errors -> users (user_id));
+diesel::joinable!(cover_art -> users (uploader_id));
+diesel::joinable!(embed_tokens -> users (user_id));
+diesel::joinable!(first_listens -> users (user_id));
+diesel::joinable!(leaderboard_entries -> users (user_id));
 diesel::joinable!(liked_songs -> music (music_id));
 diesel::joinable!(liked_songs -> users (user_id));
+diesel::joinable!(listening_goals -> users (user_id));
+diesel::joinable!(music_alt_names -> music (music_id));
+diesel::joinable!(music_chapters -> music (music_id));
+diesel::joinable!(music -> users (uploader_id));
+diesel::joinable!(new_releases -> users (user_id));
 diesel::joinable!(notifications -> users (user_id));
+diesel::joinable!(oauth_clients -> users (owner_id));
+diesel::joinable!(oauth_codes -> oauth_clients (client_id));
+diesel::joinable!(oauth_codes -> users (user_id));
+diesel::joinable!(oauth_grants -> oauth_clients (client_id));
+diesel::joinable!(oauth_grants -> users (user_id));
+diesel::joinable!(oauth_tokens -> oauth_clients (client_id));
+diesel::joinable!(oauth_tokens -> users (user_id));
+diesel::joinable!(play_events -> music (music_id));
+diesel::joinable!(play_events -> users (user_id));
 diesel::joinable!(play_log -> music (music_id));
 diesel::joinable!(play_log -> users (user_id));
+diesel::joinable!(play_rollups_daily -> music (music_id));
+diesel::joinable!(play_rollups_daily -> users (user_id));
+diesel::joinable!(play_rollups_monthly -> music (music_id));
+diesel::joinable!(play_rollups_monthly -> users (user_id));
+diesel::joinable!(playlist_folder_items -> playlist_folders (folder_id));
+diesel::joinable!(playlist_folder_items -> users (user_id));
+diesel::joinable!(playlist_folders -> users (user_id));
+diesel::joinable!(playlist_releases -> users (user_id));
 diesel::joinable!(playlist_shares -> playlists (playlist_id));
 diesel::joinable!(playlist_shares -> users (contributor_user_id));
 diesel::joinable!(playlist_songs -> music (music_id));
 diesel::joinable!(playlist_songs -> playlists (playlist_id));
 diesel::joinable!(playlist_songs -> users (song_adder_id));
+diesel::joinable!(playlist_trash -> users (user_id));
 diesel::joinable!(playlists -> users (user_id));
+diesel::joinable!(profile_anthems -> music (music_id));
+diesel::joinable!(profile_anthems -> users (user_id));
+diesel::joinable!(profile_pins -> users (user_id));
+diesel::joinable!(queue_snapshots -> users (user_id));
+diesel::joinable!(quotas -> users (user_id));
+diesel::joinable!(saved_searches -> users (user_id));
+diesel::joinable!(scrobble_tokens -> users (user_id));
+diesel::joinable!(takedown_events -> takedowns (takedown_id));
+diesel::joinable!(takedown_events -> users (actor_id));
+diesel::joinable!(takedowns -> users (admin_id));
+diesel::joinable!(track_genres -> music (music_id));
+diesel::joinable!(track_moods -> music (music_id));
+diesel::joinable!(track_moods -> users (tagged_by));
+diesel::joinable!(track_ratings -> music (music_id));
+diesel::joinable!(track_ratings -> users (user_id));
+diesel::joinable!(user_achievements -> users (user_id));
+diesel::joinable!(user_settings -> users (user_id));
+diesel::joinable!(user_tags -> users (user_id));
+diesel::joinable!(weekly_emails -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    analytics_daily,
+    analytics_retention,
+    analytics_top_content,
+    animated_covers,
+    artist_follows,
+    artist_images,
+    audiobook_progress,
+    blocked_content,
+    blocked_tags,
+    client_errors,
+    cover_art,
+    cover_palettes,
+    embed_tokens,
+    federation_peers,
+    first_listens,
+    instance_settings,
+    ip_rules,
+    leaderboard_entries,
+    library_changes,
     liked_songs,
+    listening_goals,
     music,
+    music_alt_names,
+    music_chapters,
+    new_releases,
     notifications,
+    oauth_clients,
+    oauth_codes,
+    oauth_grants,
+    oauth_tokens,
+    play_events,
     play_log,
+    play_rollups_daily,
+    play_rollups_monthly,
+    playlist_folder_items,
+    playlist_folders,
+    playlist_releases,
     playlist_shares,
     playlist_songs,
+    playlist_trash,
+    playlist_undo,
     playlists,
+    profile_anthems,
+    profile_pins,
+    queue_snapshots,
+    quotas,
+    saved_searches,
+    scrobble_tokens,
+    takedown_events,
+    takedowns,
+    track_genres,
+    track_moods,
+    track_ratings,
+    user_achievements,
     user_friendship,
+    user_settings,
+    user_tags,
     users,
+    webhooks,
+    weekly_emails,
 );
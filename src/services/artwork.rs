@@ -0,0 +1,696 @@
+use crate::config::{
+	ANIMATED_COVER_STORAGE, ARTIST_IMG_STORAGE, COVER_ART_STORAGE, COVER_IMG_STORAGE, MAX_ANIMATED_COVER_BYTES,
+	MAX_ANIMATED_COVER_SECS, MAX_COVER_ART_BYTES, PLAYLIST_COVER_IMG_STORAGE,
+};
+use crate::core::artist_images::main_artist;
+use crate::core::artwork::placeholder::{self, Placeholder};
+use crate::core::artwork::{self, animated, PaletteColor};
+use crate::core::quotas::{self, Feature};
+use crate::lobic_db::db::{user_is_admin, DatabasePool};
+use crate::lobic_db::models::{AnimatedCover, CoverArt, CoverPalette};
+use crate::schema::{animated_covers, artist_images, cover_art, cover_palettes, music, playlists};
+use crate::services::ServiceError;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use uuid::Uuid;
+
+// What an animated cover belongs to, albums are known by the uuid of their cover image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArtworkTarget {
+	Album,
+	Playlist,
+}
+
+impl ArtworkTarget {
+	pub fn parse(target: &str) -> Option<ArtworkTarget> {
+		match target {
+			"album" => Some(ArtworkTarget::Album),
+			"playlist" => Some(ArtworkTarget::Playlist),
+			_ => None,
+		}
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			ArtworkTarget::Album => "album",
+			ArtworkTarget::Playlist => "playlist",
+		}
+	}
+
+	// The static cover, also the fallback frame while there is no animated one
+	pub fn static_cover(&self, target_id: &str) -> PathBuf {
+		let storage = match self {
+			ArtworkTarget::Album => COVER_IMG_STORAGE,
+			ArtworkTarget::Playlist => PLAYLIST_COVER_IMG_STORAGE,
+		};
+		PathBuf::from(storage).join(format!("{target_id}.png"))
+	}
+}
+
+// Where the art served under a cover uuid comes from, the first there is of these
+#[derive(Debug, Clone, PartialEq)]
+pub enum CoverSource {
+	Cover(PathBuf),  // the album's own
+	Album(PathBuf),  // of the same album credited to its main artist with others
+	Artist(PathBuf), // a picture of the main artist
+	Placeholder,
+}
+
+impl CoverSource {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			CoverSource::Cover(_) => "cover",
+			CoverSource::Album(_) => "album",
+			CoverSource::Artist(_) => "artist",
+			CoverSource::Placeholder => "placeholder",
+		}
+	}
+
+	pub fn path(&self) -> Option<&Path> {
+		match self {
+			CoverSource::Cover(path) | CoverSource::Album(path) | CoverSource::Artist(path) => Some(path),
+			CoverSource::Placeholder => None,
+		}
+	}
+}
+
+// The albums of the library, by the uuid of their cover and by their name
+#[derive(Debug, Default)]
+struct AlbumIndex {
+	by_uuid: HashMap<Uuid, (String, String)>,
+	artists_of: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArtworkService {
+	db_pool: DatabasePool,
+	storage: PathBuf,
+	art_storage: PathBuf,
+	artist_storage: PathBuf,
+	albums: Arc<OnceLock<AlbumIndex>>, // loaded on the first lookup, shared with the clones
+}
+
+impl ArtworkService {
+	pub fn new(db_pool: &DatabasePool) -> ArtworkService {
+		ArtworkService {
+			db_pool: db_pool.clone(),
+			storage: PathBuf::from(COVER_IMG_STORAGE),
+			art_storage: PathBuf::from(COVER_ART_STORAGE),
+			artist_storage: PathBuf::from(ARTIST_IMG_STORAGE),
+			albums: Arc::default(),
+		}
+	}
+
+	// Colors of the cover, extracted on the first request and kept in the db after that
+	pub fn palette(&self, img_uuid: &str) -> Result<Vec<PaletteColor>, ServiceError> {
+		if Uuid::parse_str(img_uuid).is_err() {
+			return Err(ServiceError::NotFound(format!("No cover image: {img_uuid}")));
+		}
+
+		let mut db_conn = self.db_pool.get()?;
+		let cached = cover_palettes::table
+			.find(img_uuid)
+			.select(cover_palettes::colors)
+			.first::<String>(&mut db_conn)
+			.optional()?;
+		if let Some(colors) = cached.and_then(|colors| serde_json::from_str(&colors).ok()) {
+			return Ok(colors);
+		}
+		drop(db_conn);
+
+		let bytes = fs::read(self.storage.join(format!("{img_uuid}.png")))
+			.map_err(|_| ServiceError::NotFound(format!("No cover image: {img_uuid}")))?;
+		let pixels = artwork::decode(&bytes).map_err(ServiceError::Unsupported)?;
+		let colors = artwork::extract_palette(&pixels);
+
+		let mut db_conn = self.db_pool.get()?;
+		diesel::insert_into(cover_palettes::table)
+			.values(CoverPalette {
+				img_uuid: img_uuid.to_string(),
+				colors: serde_json::to_string(&colors).unwrap(),
+				extracted_date_time: Utc::now().to_rfc3339(),
+			})
+			.on_conflict_do_nothing()
+			.execute(&mut db_conn)?;
+		Ok(colors)
+	}
+
+	// Art for a cover uuid without a file, the initials come from the album the uuid was made from
+	pub fn placeholder(&self, img_uuid: &str) -> Result<Placeholder, ServiceError> {
+		let Ok(parsed) = Uuid::parse_str(img_uuid) else {
+			let mut hasher = DefaultHasher::new();
+			img_uuid.hash(&mut hasher);
+			return Ok(Placeholder::new(hasher.finish(), String::new()));
+		};
+
+		let initials = self
+			.album_of(parsed)?
+			.map(|(artist, album)| placeholder::initials(&artist, &album))
+			.unwrap_or_default();
+		Ok(Placeholder::new(parsed.as_u64_pair().0, initials))
+	}
+
+	// The artist and album a cover uuid was made from
+	fn album_of(&self, img_uuid: Uuid) -> Result<Option<(String, String)>, ServiceError> {
+		Ok(self.albums()?.by_uuid.get(&img_uuid).cloned())
+	}
+
+	// The uuids can't be looked up in the db, they are hashes of the artist and album. Worked out once
+	// for every album, a service signing a few hundred covers doesn't load them for each.
+	fn albums(&self) -> Result<&AlbumIndex, ServiceError> {
+		if let Some(albums) = self.albums.get() {
+			return Ok(albums);
+		}
+		let mut db_conn = self.db_pool.get()?;
+		let loaded = music::table
+			.select((music::artist, music::album))
+			.distinct()
+			.load::<(String, String)>(&mut db_conn)?;
+
+		let mut albums = AlbumIndex::default();
+		for (artist, album) in loaded {
+			albums.artists_of.entry(album.clone()).or_default().push(artist.clone());
+			albums
+				.by_uuid
+				.insert(artwork::cover_uuid(&artist, &album), (artist, album));
+		}
+		Ok(self.albums.get_or_init(|| albums))
+	}
+
+	// Every response only has the cover uuid, what's served for it is worked out here so they all agree
+	pub fn resolve_cover(&self, img_uuid: &str) -> Result<CoverSource, ServiceError> {
+		let cover = self.storage.join(format!("{img_uuid}.png"));
+		if cover.exists() {
+			return Ok(CoverSource::Cover(cover));
+		}
+		let Ok(parsed) = Uuid::parse_str(img_uuid) else {
+			return Ok(CoverSource::Placeholder);
+		};
+		let albums = self.albums()?;
+		let Some((artist, album)) = albums.by_uuid.get(&parsed) else {
+			return Ok(CoverSource::Placeholder);
+		};
+
+		let main = main_artist(artist);
+		if !album.trim().is_empty() && album != "Unknown Album" {
+			let same_album = albums.artists_of[album]
+				.iter()
+				.filter(|other| *other != artist && main_artist(other) == main)
+				.map(|other| self.storage.join(format!("{}.png", artwork::cover_uuid(other, album))))
+				.find(|cover| cover.exists());
+			if let Some(cover) = same_album {
+				return Ok(CoverSource::Album(cover));
+			}
+		}
+
+		let mut db_conn = self.db_pool.get()?;
+		let picture = artist_images::table
+			.find(&main)
+			.select(artist_images::img_uuid)
+			.first::<String>(&mut db_conn)
+			.optional()?
+			.map(|img_uuid| self.artist_storage.join(format!("{img_uuid}.png")))
+			.filter(|picture| picture.exists());
+		Ok(picture.map_or(CoverSource::Placeholder, CoverSource::Artist))
+	}
+
+	// Colors of what's served for a cover uuid without its own cover, not kept as the album may get one
+	pub fn fallback_palette(&self, img_uuid: &str) -> Result<Vec<PaletteColor>, ServiceError> {
+		match self.resolve_cover(img_uuid)?.path() {
+			Some(path) => {
+				let bytes =
+					fs::read(path).map_err(|err| ServiceError::Internal(format!("Failed to read the cover: {err}")))?;
+				let pixels = artwork::decode(&bytes).map_err(ServiceError::Unsupported)?;
+				Ok(artwork::extract_palette(&pixels))
+			}
+			None => Ok(self.placeholder(img_uuid)?.palette()),
+		}
+	}
+
+	// The cover uuid of the album, given by it or by one of its tracks. The tracks of an album share its art.
+	pub fn cover_art_album(&self, target: &str, target_id: &str) -> Result<String, ServiceError> {
+		match target {
+			"album" => {
+				let album = Uuid::parse_str(target_id)
+					.ok()
+					.map(|parsed| self.album_of(parsed))
+					.transpose()?
+					.flatten();
+				album
+					.map(|_| target_id.to_string())
+					.ok_or_else(|| ServiceError::NotFound(format!("No album with the cover: {target_id}")))
+			}
+			"track" => {
+				let mut db_conn = self.db_pool.get()?;
+				let (artist, album) = music::table
+					.find(target_id)
+					.select((music::artist, music::album))
+					.first::<(String, String)>(&mut db_conn)
+					.optional()?
+					.ok_or_else(|| ServiceError::NotFound(format!("No track: {target_id}")))?;
+				Ok(artwork::cover_uuid(&artist, &album).to_string())
+			}
+			_ => Err(ServiceError::BadRequest(format!("Invalid cover art target: {target}"))),
+		}
+	}
+
+	// Every version of the album's art, oldest first
+	pub fn cover_art(&self, img_uuid: &str) -> Result<Vec<CoverArt>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		Ok(cover_art::table
+			.filter(cover_art::img_uuid.eq(img_uuid))
+			.order(cover_art::uploaded_date_time.asc())
+			.load::<CoverArt>(&mut db_conn)?)
+	}
+
+	pub fn cover_art_file(&self, art_id: &str) -> PathBuf {
+		self.art_storage.join(format!("{art_id}.png"))
+	}
+
+	// Saves the upload as a new version of the album's art and makes it the canonical one. The cover from the
+	// tags is kept as the original the first time.
+	pub fn upload_cover_art(&self, img_uuid: &str, user_id: &str, bytes: &[u8]) -> Result<CoverArt, ServiceError> {
+		self.check_can_edit_art(img_uuid, user_id)?;
+
+		if bytes.len() > MAX_COVER_ART_BYTES {
+			return Err(ServiceError::BadRequest(format!(
+				"Cover art can be at most {MAX_COVER_ART_BYTES} bytes"
+			)));
+		}
+		match artwork::decode(bytes) {
+			Ok(pixels) if !pixels.is_empty() => {}
+			_ => {
+				return Err(ServiceError::Unsupported(
+					"Cover art has to be a png or jpeg".to_string(),
+				))
+			}
+		}
+		fs::create_dir_all(&self.art_storage)
+			.map_err(|err| ServiceError::Internal(format!("Failed to create directory: {err}")))?;
+
+		let versions = self.cover_art(img_uuid)?;
+		let current = self.storage.join(format!("{img_uuid}.png"));
+		if versions.is_empty() && current.exists() {
+			let original = self.new_cover_art(img_uuid, None, true);
+			fs::copy(&current, self.cover_art_file(&original.art_id))
+				.map_err(|err| ServiceError::Internal(format!("Failed to keep the original cover: {err}")))?;
+			let mut db_conn = self.db_pool.get()?;
+			diesel::insert_into(cover_art::table)
+				.values(&original)
+				.execute(&mut db_conn)?;
+		}
+
+		let art = self.new_cover_art(img_uuid, Some(user_id), false);
+		fs::write(self.cover_art_file(&art.art_id), bytes)
+			.map_err(|err| ServiceError::Internal(format!("Failed to save cover art: {err}")))?;
+		let mut db_conn = self.db_pool.get()?;
+		diesel::insert_into(cover_art::table)
+			.values(&art)
+			.execute(&mut db_conn)?;
+		drop(db_conn);
+
+		self.make_canonical(img_uuid, &art.art_id)
+	}
+
+	pub fn select_cover_art(&self, img_uuid: &str, art_id: &str, user_id: &str) -> Result<CoverArt, ServiceError> {
+		self.check_can_edit_art(img_uuid, user_id)?;
+		self.make_canonical(img_uuid, art_id)
+	}
+
+	fn new_cover_art(&self, img_uuid: &str, uploader_id: Option<&str>, is_original: bool) -> CoverArt {
+		CoverArt {
+			art_id: Uuid::new_v4().to_string(),
+			img_uuid: img_uuid.to_string(),
+			uploader_id: uploader_id.map(String::from),
+			is_original,
+			is_canonical: false,
+			uploaded_date_time: Utc::now().to_rfc3339(),
+		}
+	}
+
+	// Copies the version over the album's cover, everything showing the cover shows it from then on
+	fn make_canonical(&self, img_uuid: &str, art_id: &str) -> Result<CoverArt, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let mut art = cover_art::table
+			.find(art_id)
+			.filter(cover_art::img_uuid.eq(img_uuid))
+			.first::<CoverArt>(&mut db_conn)
+			.optional()?
+			.ok_or_else(|| ServiceError::NotFound(format!("No cover art {art_id} for {img_uuid}")))?;
+
+		let cover = self.storage.join(format!("{img_uuid}.png"));
+		let cover_tmp = cover.with_extension("tmp.png");
+		fs::create_dir_all(&self.storage)
+			.and_then(|_| fs::copy(self.cover_art_file(art_id), &cover_tmp))
+			.and_then(|_| fs::rename(&cover_tmp, &cover))
+			.map_err(|err| ServiceError::Internal(format!("Failed to replace the cover: {err}")))?;
+
+		db_conn.transaction::<_, diesel::result::Error, _>(|db_conn| {
+			diesel::update(cover_art::table.filter(cover_art::img_uuid.eq(img_uuid)))
+				.set(cover_art::is_canonical.eq(cover_art::art_id.eq(art_id)))
+				.execute(db_conn)?;
+			// extracted again from the new cover
+			diesel::delete(cover_palettes::table.find(img_uuid)).execute(db_conn)?;
+			Ok(())
+		})?;
+		art.is_canonical = true;
+		Ok(art)
+	}
+
+	// The admins, and the users who uploaded tracks of the album
+	fn check_can_edit_art(&self, img_uuid: &str, user_id: &str) -> Result<(), ServiceError> {
+		let (artist, album) = Uuid::parse_str(img_uuid)
+			.ok()
+			.map(|parsed| self.album_of(parsed))
+			.transpose()?
+			.flatten()
+			.ok_or_else(|| ServiceError::NotFound(format!("No album with the cover: {img_uuid}")))?;
+		if user_is_admin(user_id, &self.db_pool) {
+			return Ok(());
+		}
+
+		let mut db_conn = self.db_pool.get()?;
+		let uploaded = music::table
+			.filter(music::artist.eq(&artist))
+			.filter(music::album.eq(&album))
+			.filter(music::uploader_id.eq(user_id))
+			.count()
+			.get_result::<i64>(&mut db_conn)?;
+		if uploaded == 0 {
+			return Err(ServiceError::Forbidden(
+				"Only the admins and the uploaders of the album can change its art".to_string(),
+			));
+		}
+		Ok(())
+	}
+
+	pub fn animated_cover(&self, target: ArtworkTarget, target_id: &str) -> Result<AnimatedCover, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		animated_covers::table
+			.find((target.as_str(), target_id))
+			.first::<AnimatedCover>(&mut db_conn)
+			.optional()?
+			.ok_or_else(|| ServiceError::NotFound(format!("No animated cover for {} {target_id}", target.as_str())))
+	}
+
+	// The transcoded mp4 and its first frame as a jpeg
+	pub fn animated_cover_files(&self, target: ArtworkTarget, target_id: &str) -> (PathBuf, PathBuf) {
+		let storage = PathBuf::from(ANIMATED_COVER_STORAGE);
+		let name = format!("{}_{target_id}", target.as_str());
+		(storage.join(format!("{name}.mp4")), storage.join(format!("{name}.jpg")))
+	}
+
+	// Validates and transcodes the upload, replacing the earlier animated cover
+	pub fn set_animated_cover(
+		&self,
+		target: ArtworkTarget,
+		target_id: &str,
+		user_id: &str,
+		bytes: &[u8],
+	) -> Result<AnimatedCover, ServiceError> {
+		self.check_can_edit(target, target_id, user_id)?;
+
+		if bytes.len() > MAX_ANIMATED_COVER_BYTES {
+			return Err(ServiceError::BadRequest(format!(
+				"Animated covers can be at most {} bytes",
+				MAX_ANIMATED_COVER_BYTES
+			)));
+		}
+		let format = animated::detect_format(bytes)
+			.ok_or_else(|| ServiceError::Unsupported("Animated covers have to be mp4, mov, webm or gif".to_string()))?;
+		if !animated::available() {
+			return Err(ServiceError::Unavailable(
+				"Animated covers need ffmpeg on the server".to_string(),
+			));
+		}
+		let mut db_conn = self.db_pool.get()?;
+		quotas::take(user_id, Feature::Transcode, 1, &mut db_conn)?;
+		drop(db_conn);
+
+		let (video, poster) = self.animated_cover_files(target, target_id);
+		let upload = video.with_extension(format!("upload.{format}"));
+		let (video_tmp, poster_tmp) = (video.with_extension("tmp.mp4"), poster.with_extension("tmp.jpg"));
+		let result = (|| {
+			fs::create_dir_all(ANIMATED_COVER_STORAGE)
+				.map_err(|err| ServiceError::Internal(format!("Failed to create directory: {err}")))?;
+			fs::write(&upload, bytes).map_err(|err| ServiceError::Internal(format!("Failed to save upload: {err}")))?;
+			let duration = animated::probe_duration(&upload).map_err(ServiceError::Unsupported)?;
+			if duration.is_some_and(|secs| secs > MAX_ANIMATED_COVER_SECS) {
+				return Err(ServiceError::BadRequest(format!(
+					"Animated covers can be at most {MAX_ANIMATED_COVER_SECS} seconds long"
+				)));
+			}
+			animated::transcode(&upload, &video_tmp, &poster_tmp).map_err(ServiceError::Unsupported)?;
+			fs::rename(&video_tmp, &video)
+				.and_then(|_| fs::rename(&poster_tmp, &poster))
+				.map_err(|err| ServiceError::Internal(format!("Failed to save animated cover: {err}")))?;
+			Ok(duration.unwrap_or(MAX_ANIMATED_COVER_SECS).min(MAX_ANIMATED_COVER_SECS))
+		})();
+		for leftover in [&upload, &video_tmp, &poster_tmp] {
+			let _ = fs::remove_file(leftover);
+		}
+		// only the covers that made it count against the quota
+		let duration_secs = match result {
+			Ok(duration_secs) => duration_secs,
+			Err(err) => {
+				let mut db_conn = self.db_pool.get()?;
+				quotas::give_back(user_id, Feature::Transcode, 1, &mut db_conn)?;
+				return Err(err);
+			}
+		};
+
+		let cover = AnimatedCover {
+			target_type: target.as_str().to_string(),
+			target_id: target_id.to_string(),
+			uploader_id: user_id.to_string(),
+			duration_secs,
+			uploaded_date_time: Utc::now().to_rfc3339(),
+		};
+		let mut db_conn = self.db_pool.get()?;
+		diesel::replace_into(animated_covers::table)
+			.values(&cover)
+			.execute(&mut db_conn)?;
+		Ok(cover)
+	}
+
+	pub fn remove_animated_cover(
+		&self,
+		target: ArtworkTarget,
+		target_id: &str,
+		user_id: &str,
+	) -> Result<(), ServiceError> {
+		self.check_can_edit(target, target_id, user_id)?;
+
+		let mut db_conn = self.db_pool.get()?;
+		let removed =
+			diesel::delete(animated_covers::table.find((target.as_str(), target_id))).execute(&mut db_conn)?;
+		if removed == 0 {
+			return Err(ServiceError::NotFound(format!(
+				"No animated cover for {} {target_id}",
+				target.as_str()
+			)));
+		}
+
+		let (video, poster) = self.animated_cover_files(target, target_id);
+		let _ = fs::remove_file(video);
+		let _ = fs::remove_file(poster);
+		Ok(())
+	}
+
+	// Playlists are edited by their owner, albums by the admins
+	fn check_can_edit(&self, target: ArtworkTarget, target_id: &str, user_id: &str) -> Result<(), ServiceError> {
+		match target {
+			ArtworkTarget::Album => {
+				if Uuid::parse_str(target_id).is_err() || !target.static_cover(target_id).exists() {
+					return Err(ServiceError::NotFound(format!("No album with the cover: {target_id}")));
+				}
+				if !user_is_admin(user_id, &self.db_pool) {
+					return Err(ServiceError::Forbidden("Admin privileges required".to_string()));
+				}
+			}
+			ArtworkTarget::Playlist => {
+				let mut db_conn = self.db_pool.get()?;
+				let owner = playlists::table
+					.find(target_id)
+					.select(playlists::user_id)
+					.first::<String>(&mut db_conn)
+					.optional()?
+					.ok_or_else(|| ServiceError::NotFound(format!("No playlist: {target_id}")))?;
+				if owner != user_id {
+					return Err(ServiceError::Forbidden(
+						"Only the owner can change the playlist cover".to_string(),
+					));
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::lobic_db::models::{ArtistImage, Availability, ContentType, Music};
+	use crate::test_support::TestApp;
+
+	#[test]
+	fn uploaded_art_replaces_the_cover_and_keeps_the_original() {
+		let test_app = TestApp::seeded();
+		let storage = std::env::temp_dir().join(format!("lobic_cover_art_{}", Uuid::new_v4()));
+		let service = ArtworkService {
+			db_pool: test_app.app_state.db_pool.clone(),
+			storage: storage.join("covers"),
+			art_storage: storage.join("art"),
+			artist_storage: storage.join("artists"),
+			albums: Default::default(),
+		};
+		let (music_id, artist, album) = music::table
+			.select((music::music_id, music::artist, music::album))
+			.first::<(String, String, String)>(&mut test_app.db_conn())
+			.unwrap();
+		let img_uuid = service.cover_art_album("track", &music_id).unwrap();
+		assert_eq!(img_uuid, artwork::cover_uuid(&artist, &album).to_string());
+		assert_eq!(service.cover_art_album("album", &img_uuid).unwrap(), img_uuid);
+
+		let original = Placeholder::new(1, String::new()).png(32);
+		fs::create_dir_all(storage.join("covers")).unwrap();
+		fs::write(storage.join("covers").join(format!("{img_uuid}.png")), &original).unwrap();
+		let upload = Placeholder::new(2, "NU".to_string()).png(32);
+
+		let (uploader_id, other_id) = (test_app.user_id("seed_user_0"), test_app.user_id("seed_user_1"));
+		diesel::update(music::table.find(&music_id))
+			.set(music::uploader_id.eq(&uploader_id))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		let err = service.upload_cover_art(&img_uuid, &other_id, &upload).unwrap_err();
+		assert!(matches!(err, ServiceError::Forbidden(_)));
+		let err = service
+			.upload_cover_art(&img_uuid, &uploader_id, b"not an image")
+			.unwrap_err();
+		assert!(matches!(err, ServiceError::Unsupported(_)));
+
+		let art = service.upload_cover_art(&img_uuid, &uploader_id, &upload).unwrap();
+		let cover = storage.join("covers").join(format!("{img_uuid}.png"));
+		assert_eq!(fs::read(&cover).unwrap(), upload);
+		let versions = service.cover_art(&img_uuid).unwrap();
+		assert_eq!(versions.len(), 2);
+		assert!(versions[0].is_original && !versions[0].is_canonical);
+		assert!(versions[1].is_canonical && versions[1].art_id == art.art_id);
+
+		// back to the one from the tags
+		service
+			.select_cover_art(&img_uuid, &versions[0].art_id, &uploader_id)
+			.unwrap();
+		assert_eq!(fs::read(&cover).unwrap(), original);
+		let canonical: Vec<bool> = service
+			.cover_art(&img_uuid)
+			.unwrap()
+			.iter()
+			.map(|art| art.is_canonical)
+			.collect();
+		assert_eq!(canonical, vec![true, false]);
+
+		fs::remove_dir_all(&storage).unwrap();
+	}
+
+	#[test]
+	fn albums_without_a_cover_fall_back_to_the_album_then_the_artist() {
+		let test_app = TestApp::new();
+		let storage = std::env::temp_dir().join(format!("lobic_fallbacks_{}", Uuid::new_v4()));
+		let service = ArtworkService {
+			db_pool: test_app.app_state.db_pool.clone(),
+			storage: storage.join("covers"),
+			art_storage: storage.join("art"),
+			artist_storage: storage.join("artists"),
+			albums: Default::default(),
+		};
+		let artist = "Fallback".to_string();
+		let featuring = format!("{artist} feat. Guest");
+		for (n, credit) in [&artist, &featuring].into_iter().enumerate() {
+			diesel::insert_into(music::table)
+				.values(Music {
+					music_id: Uuid::new_v4().to_string(),
+					artist: credit.clone(),
+					title: format!("Track {n}"),
+					album: "Shared".to_string(),
+					genre: "Pop".to_string(),
+					times_played: 0,
+					duration: 180,
+					availability: Availability::Available.as_str().to_string(),
+					availability_reason: None,
+					uploader_id: None,
+					content_type: ContentType::Music.as_str().to_string(),
+					series: None,
+					series_index: None,
+					release_year: None,
+					bpm: None,
+					musical_key: None,
+					explicit: false,
+				})
+				.execute(&mut test_app.db_conn())
+				.unwrap();
+		}
+		let img_uuid = artwork::cover_uuid(&featuring, "Shared").to_string();
+		assert_eq!(service.resolve_cover(&img_uuid).unwrap(), CoverSource::Placeholder);
+
+		let picture = Placeholder::new(2, "P".to_string()).png(32);
+		let image = ArtistImage {
+			artist: main_artist(&featuring),
+			img_uuid: Uuid::new_v4().to_string(),
+			source: "deezer".to_string(),
+			source_url: None,
+			fetched_date_time: Utc::now().to_rfc3339(),
+		};
+		fs::create_dir_all(storage.join("artists")).unwrap();
+		let picture_file = storage.join("artists").join(format!("{}.png", image.img_uuid));
+		fs::write(&picture_file, &picture).unwrap();
+		diesel::insert_into(artist_images::table)
+			.values(&image)
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		assert_eq!(
+			service.resolve_cover(&img_uuid).unwrap(),
+			CoverSource::Artist(picture_file)
+		);
+		assert!(!service.fallback_palette(&img_uuid).unwrap().is_empty());
+
+		// the cover of the album as credited to the artist alone comes first
+		let cover = storage
+			.join("covers")
+			.join(format!("{}.png", artwork::cover_uuid(&artist, "Shared")));
+		fs::create_dir_all(storage.join("covers")).unwrap();
+		fs::write(&cover, Placeholder::new(1, "A".to_string()).png(32)).unwrap();
+		assert_eq!(service.resolve_cover(&img_uuid).unwrap(), CoverSource::Album(cover));
+
+		fs::remove_dir_all(&storage).unwrap();
+	}
+
+	#[test]
+	fn albums_are_loaded_once_per_service() {
+		let test_app = TestApp::seeded();
+		let service = ArtworkService::new(&test_app.app_state.db_pool);
+		let (artist, album) = music::table
+			.select((music::artist, music::album))
+			.first::<(String, String)>(&mut test_app.db_conn())
+			.unwrap();
+		let img_uuid = artwork::cover_uuid(&artist, &album).to_string();
+		let placeholder = service.placeholder(&img_uuid).unwrap();
+		assert_eq!(placeholder.initials, placeholder::initials(&artist, &album));
+
+		diesel::update(music::table.filter(music::album.eq(&album)))
+			.set(music::album.eq("Renamed"))
+			.execute(&mut test_app.db_conn())
+			.unwrap();
+		// the clones share what was loaded, a new service sees the rename
+		assert_eq!(service.clone().placeholder(&img_uuid).unwrap(), placeholder);
+		let reloaded = ArtworkService::new(&test_app.app_state.db_pool);
+		assert_eq!(reloaded.placeholder(&img_uuid).unwrap().initials, "");
+	}
+}
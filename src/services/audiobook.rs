@@ -0,0 +1,251 @@
+use crate::lobic_db::db::DatabasePool;
+use crate::lobic_db::models::{AudiobookProgress, Availability, ContentType, Music, MusicResponse};
+use crate::schema::{audiobook_progress, music};
+use crate::services::ServiceError;
+use crate::utils::list::ListResponse;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+// Positions this close to the end count as finishing the book
+pub const FINISHED_MARGIN_SECS: f64 = 30.0;
+
+// Narrows down the books, authors are the artists of the files
+#[derive(Debug, Default, Deserialize)]
+pub struct AudiobookFilter {
+	pub author: Option<String>,
+	pub series: Option<String>,
+	#[serde(default)]
+	pub start_index: i64,
+	pub page_length: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProgressResponse {
+	pub position: f64,
+	pub finished: bool,
+	pub updated_date_time: String,
+}
+
+impl From<AudiobookProgress> for ProgressResponse {
+	fn from(progress: AudiobookProgress) -> ProgressResponse {
+		ProgressResponse {
+			position: progress.position,
+			finished: progress.finished,
+			updated_date_time: progress.updated_date_time,
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct AudiobookResponse {
+	#[serde(flatten)]
+	pub music: MusicResponse,
+	pub series: Option<String>,
+	pub series_index: Option<i32>,
+	pub progress: Option<ProgressResponse>, // null for guests and books never started
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthorResponse {
+	pub author: String,
+	pub book_count: i64,
+	pub series: Vec<String>,
+	pub image_url: String, // cover of their first book
+}
+
+#[derive(Debug, Serialize)]
+pub struct SeriesResponse {
+	pub series: String,
+	pub author: String,
+	pub books: Vec<AudiobookResponse>, // in series order
+}
+
+#[derive(Debug, Clone)]
+pub struct AudiobookService {
+	db_pool: DatabasePool,
+}
+
+impl AudiobookService {
+	pub fn new(db_pool: &DatabasePool) -> AudiobookService {
+		AudiobookService {
+			db_pool: db_pool.clone(),
+		}
+	}
+
+	// The available books by author, series and position in the series
+	pub fn books(
+		&self,
+		filter: &AudiobookFilter,
+		user_id: Option<&str>,
+	) -> Result<ListResponse<AudiobookResponse>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+
+		let total_count = filtered(filter).count().get_result::<i64>(&mut db_conn)?;
+		let mut query = filtered(filter)
+			.order((music::artist, music::series, music::series_index, music::title))
+			.offset(filter.start_index);
+		if let Some(length) = filter.page_length.filter(|length| *length > 0) {
+			query = query.limit(length);
+		}
+		let books = query.load::<Music>(&mut db_conn)?;
+
+		let items = self.with_progress(books, user_id, &mut db_conn)?;
+		Ok(ListResponse::page(
+			items,
+			total_count,
+			filter.start_index,
+			filter.page_length,
+		))
+	}
+
+	pub fn authors(&self) -> Result<Vec<AuthorResponse>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let books = filtered(&AudiobookFilter::default())
+			.order((music::artist, music::series, music::series_index, music::title))
+			.load::<Music>(&mut db_conn)?;
+
+		let mut authors: BTreeMap<String, (i64, BTreeSet<String>, String)> = BTreeMap::new();
+		for mut book in books {
+			let book_series = book.series.take();
+			let book = Music::create_music_response(book);
+			let (book_count, series, _) = authors
+				.entry(book.artist)
+				.or_insert((0, BTreeSet::new(), book.image_url));
+			*book_count += 1;
+			series.extend(book_series);
+		}
+		Ok(authors
+			.into_iter()
+			.map(|(author, (book_count, series, image_url))| AuthorResponse {
+				author,
+				book_count,
+				series: series.into_iter().collect(),
+				image_url,
+			})
+			.collect())
+	}
+
+	// Books grouped by their series, books outside of any series are left out
+	pub fn series(&self, author: Option<String>, user_id: Option<&str>) -> Result<Vec<SeriesResponse>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let filter = AudiobookFilter {
+			author,
+			..Default::default()
+		};
+		let books = filtered(&filter)
+			.filter(music::series.is_not_null())
+			.order((music::series, music::artist, music::series_index, music::title))
+			.load::<Music>(&mut db_conn)?;
+
+		let mut series: Vec<SeriesResponse> = Vec::new();
+		for book in self.with_progress(books, user_id, &mut db_conn)? {
+			let name = book.series.clone().unwrap_or_default();
+			match series.last_mut() {
+				Some(last) if last.series == name && last.author == book.music.artist => last.books.push(book),
+				_ => series.push(SeriesResponse {
+					series: name,
+					author: book.music.artist.clone(),
+					books: vec![book],
+				}),
+			}
+		}
+		Ok(series)
+	}
+
+	// Started but not finished books, the most recently listened first
+	pub fn in_progress(&self, user_id: &str) -> Result<Vec<AudiobookResponse>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let books = audiobook_progress::table
+			.inner_join(music::table)
+			.filter(audiobook_progress::user_id.eq(user_id))
+			.filter(audiobook_progress::finished.eq(false))
+			.filter(music::availability.eq(Availability::Available.as_str()))
+			.order(audiobook_progress::updated_date_time.desc())
+			.select(music::all_columns)
+			.load::<Music>(&mut db_conn)?;
+		self.with_progress(books, Some(user_id), &mut db_conn)
+	}
+
+	// Saves where the user is in the book, reaching the end marks it finished unless told otherwise
+	pub fn set_progress(
+		&self,
+		user_id: &str,
+		music_id: &str,
+		position: f64,
+		finished: Option<bool>,
+	) -> Result<ProgressResponse, ServiceError> {
+		if !position.is_finite() || position < 0.0 {
+			return Err(ServiceError::BadRequest(format!("Invalid position: {position}")));
+		}
+
+		let mut db_conn = self.db_pool.get()?;
+		let (curr_content_type, duration) = music::table
+			.find(music_id)
+			.select((music::content_type, music::duration))
+			.first::<(String, i64)>(&mut db_conn)
+			.optional()?
+			.ok_or_else(|| ServiceError::NotFound(format!("No music: {music_id}")))?;
+		if curr_content_type != ContentType::Audiobook.as_str() {
+			return Err(ServiceError::BadRequest(format!("Not an audiobook: {music_id}")));
+		}
+
+		let position = position.min(duration as f64);
+		let progress = AudiobookProgress {
+			user_id: user_id.to_string(),
+			music_id: music_id.to_string(),
+			position,
+			finished: finished.unwrap_or(position >= duration as f64 - FINISHED_MARGIN_SECS),
+			updated_date_time: Utc::now().to_rfc3339(),
+		};
+		diesel::replace_into(audiobook_progress::table)
+			.values(&progress)
+			.execute(&mut db_conn)?;
+		Ok(progress.into())
+	}
+
+	fn with_progress(
+		&self,
+		books: Vec<Music>,
+		user_id: Option<&str>,
+		db_conn: &mut SqliteConnection,
+	) -> Result<Vec<AudiobookResponse>, ServiceError> {
+		let mut progress: HashMap<String, AudiobookProgress> = match user_id {
+			Some(user_id) => audiobook_progress::table
+				.filter(audiobook_progress::user_id.eq(user_id))
+				.filter(audiobook_progress::music_id.eq_any(books.iter().map(|book| &book.music_id)))
+				.load::<AudiobookProgress>(db_conn)?
+				.into_iter()
+				.map(|progress| (progress.music_id.clone(), progress))
+				.collect(),
+			None => HashMap::new(),
+		};
+
+		Ok(books
+			.into_iter()
+			.map(|book| AudiobookResponse {
+				progress: progress.remove(&book.music_id).map(ProgressResponse::from),
+				series: book.series.clone(),
+				series_index: book.series_index,
+				music: Music::create_music_response(book),
+			})
+			.collect())
+	}
+}
+
+fn filtered(filter: &AudiobookFilter) -> music::BoxedQuery<'static, diesel::sqlite::Sqlite> {
+	let mut query = music::table
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.filter(music::content_type.eq(ContentType::Audiobook.as_str()))
+		.into_boxed();
+
+	if let Some(author) = filter.author.clone() {
+		query = query.filter(music::artist.eq(author));
+	}
+	if let Some(series) = filter.series.clone() {
+		query = query.filter(music::series.eq(series));
+	}
+	query
+}
@@ -0,0 +1,220 @@
+use crate::config::MAX_CHAT_RETENTION_HOURS;
+use crate::core::app_state::AppState;
+use crate::core::lobby::{LobbyPool, Music};
+use crate::core::retention::{RetentionPolicy, CHAT_RETENTION_HOURS};
+use crate::lobic_db::db::{set_instance_setting, user_is_admin, DatabasePool};
+use crate::lobic_db::models::User;
+use crate::schema::users;
+use crate::services::music::PrefetchHint;
+use crate::services::{MusicService, ServiceError};
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// What the lobby cards show
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LobbySummary {
+	pub id: String,
+	pub lobby_name: String,
+	pub lobby_icon: String,
+	pub listeners: i32,
+	pub song_name: String,
+	pub artist_name: String,
+}
+
+// What is playing and what comes after, with the hints to prefetch each next track
+#[derive(Debug, Serialize)]
+pub struct LobbyQueue {
+	pub current: Option<Music>,
+	pub queue: Vec<Music>,
+	pub prefetch: Vec<PrefetchHint>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TranscriptEntry {
+	Message {
+		at: String,
+		user_id: String,
+		username: String,
+		message: String,
+	},
+	Track {
+		at: String,
+		music_id: String,
+		title: String,
+		artist: String,
+	},
+}
+
+#[derive(Debug, Serialize)]
+pub struct Transcript {
+	pub lobby_id: String,
+	pub host_id: String,
+	pub exported_date_time: String,
+	pub retention_hours: i64, // anything older is no longer part of it
+	pub entries: Vec<TranscriptEntry>,
+}
+
+impl Transcript {
+	// One line per message or track, for people rather than programs
+	pub fn to_text(&self) -> String {
+		let mut text = format!("Lobby {} exported at {}\n", self.lobby_id, self.exported_date_time);
+		for entry in &self.entries {
+			let line = match entry {
+				TranscriptEntry::Message {
+					at, username, message, ..
+				} => format!("[{at}] {username}: {message}"),
+				TranscriptEntry::Track { at, title, artist, .. } => format!("[{at}] Now playing: {title} by {artist}"),
+			};
+			text.push_str(&line);
+			text.push('\n');
+		}
+		text
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct LobbyService {
+	db_pool: DatabasePool,
+	lobby_pool: LobbyPool,
+}
+
+impl LobbyService {
+	pub fn new(app_state: &AppState) -> LobbyService {
+		LobbyService {
+			db_pool: app_state.db_pool.clone(),
+			lobby_pool: app_state.lobby_pool.clone(),
+		}
+	}
+
+	pub fn summary(&self, lobby_id: &str) -> Result<LobbySummary, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+
+		// Getting the required lobby
+		let lobby = self
+			.lobby_pool
+			.get(lobby_id)
+			.ok_or(ServiceError::NotFound(format!("Invalid lobby id: {lobby_id}")))?;
+
+		// Getting the user data of the host
+		let host = users::table
+			.filter(users::user_id.eq(&lobby.host_id))
+			.first::<User>(&mut db_conn)
+			.map_err(|err| ServiceError::Internal(format!("Failed to fetch user: {err}")))?;
+
+		Ok(LobbySummary {
+			id: lobby_id.to_string(),
+			lobby_name: format!("{}'s Lobby", host.username),
+			lobby_icon: lobby.music.image_url,
+			listeners: lobby.clients.len() as i32,
+			song_name: lobby.music.title,
+			artist_name: lobby.music.artist,
+		})
+	}
+
+	pub fn queue(&self, lobby_id: &str) -> Result<LobbyQueue, ServiceError> {
+		let lobby = self
+			.lobby_pool
+			.get(lobby_id)
+			.ok_or(ServiceError::NotFound(format!("Invalid lobby id: {lobby_id}")))?;
+
+		let current = Some(lobby.music).filter(|music| !music.id.is_empty());
+		let ids: Vec<String> = current
+			.iter()
+			.chain(&lobby.queue)
+			.map(|music| music.id.clone())
+			.collect();
+		Ok(LobbyQueue {
+			prefetch: MusicService::new(&self.db_pool).prefetch_hints(&ids),
+			current,
+			queue: lobby.queue,
+		})
+	}
+
+	// The chat and played tracks within the retention period, for the host or an admin
+	pub fn transcript(&self, lobby_id: &str, user_id: &str) -> Result<Transcript, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let lobby = self
+			.lobby_pool
+			.get(lobby_id)
+			.ok_or(ServiceError::NotFound(format!("Invalid lobby id: {lobby_id}")))?;
+		if lobby.host_id != user_id && !user_is_admin(user_id, &self.db_pool) {
+			return Err(ServiceError::Forbidden(
+				"Only the host can export the chat of a lobby".to_string(),
+			));
+		}
+
+		let retention_hours = RetentionPolicy::load(&mut db_conn).chat_hours;
+		let cutoff = Utc::now().timestamp_millis() - retention_hours * 60 * 60 * 1000;
+		self.lobby_pool.prune_history(lobby_id, cutoff);
+		let lobby = self
+			.lobby_pool
+			.get(lobby_id)
+			.ok_or(ServiceError::NotFound(format!("Invalid lobby id: {lobby_id}")))?;
+
+		let usernames: HashMap<String, String> = users::table
+			.filter(users::user_id.eq_any(lobby.chat.iter().map(|chat| &chat.user_id)))
+			.select((users::user_id, users::username))
+			.load::<(String, String)>(&mut db_conn)?
+			.into_iter()
+			.collect();
+		let date_time = |millis: i64| {
+			DateTime::from_timestamp_millis(millis)
+				.map(|date_time| date_time.to_rfc3339())
+				.unwrap_or_default()
+		};
+
+		// Tracks go first so a message sent the moment a track started comes after it
+		let mut entries: Vec<(i64, TranscriptEntry)> = lobby
+			.played
+			.into_iter()
+			.map(|track| {
+				let entry = TranscriptEntry::Track {
+					at: date_time(track.started_at),
+					music_id: track.music_id,
+					title: track.title,
+					artist: track.artist,
+				};
+				(track.started_at, entry)
+			})
+			.collect();
+		entries.extend(lobby.chat.into_iter().map(|chat| {
+			let entry = TranscriptEntry::Message {
+				at: date_time(chat.sent_at),
+				// Remote members of shared lobbies aren't in our users table
+				username: usernames.get(&chat.user_id).cloned().unwrap_or(chat.user_id.clone()),
+				user_id: chat.user_id,
+				message: chat.message,
+			};
+			(chat.sent_at, entry)
+		}));
+		entries.sort_by_key(|(at, _)| *at);
+
+		Ok(Transcript {
+			lobby_id: lobby.id,
+			host_id: lobby.host_id,
+			exported_date_time: Utc::now().to_rfc3339(),
+			retention_hours,
+			entries: entries.into_iter().map(|(_, entry)| entry).collect(),
+		})
+	}
+
+	pub fn set_chat_retention_hours(&self, hours: i64) -> Result<i64, ServiceError> {
+		if !(1..=MAX_CHAT_RETENTION_HOURS).contains(&hours) {
+			return Err(ServiceError::BadRequest(format!(
+				"Chat is kept between 1 and {MAX_CHAT_RETENTION_HOURS} hours"
+			)));
+		}
+		let mut db_conn = self.db_pool.get()?;
+		set_instance_setting(CHAT_RETENTION_HOURS, &hours.to_string(), &mut db_conn)?;
+		Ok(hours)
+	}
+
+	pub fn chat_retention_hours(&self) -> Result<i64, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		Ok(RetentionPolicy::load(&mut db_conn).chat_hours)
+	}
+}
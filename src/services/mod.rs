@@ -0,0 +1,97 @@
+use crate::core::quotas::{QuotaError, Usage};
+
+use axum::{http::StatusCode, response::Response};
+use diesel::r2d2::PoolError;
+use std::fmt;
+
+pub mod artwork;
+pub mod audiobook;
+pub mod lobby;
+pub mod music;
+pub mod playlist;
+pub mod playlist_folder;
+pub mod profile;
+pub mod tag;
+
+pub use artwork::{ArtworkService, ArtworkTarget};
+pub use audiobook::AudiobookService;
+pub use lobby::LobbyService;
+pub use music::MusicService;
+pub use playlist::PlaylistService;
+pub use playlist_folder::PlaylistFolderService;
+pub use profile::ProfileService;
+pub use tag::{TagService, TagTarget};
+
+// What went wrong inside a service, the handlers turn it into a response and the cli prints it
+#[derive(Debug)]
+pub enum ServiceError {
+	NotFound(String),
+	BadRequest(String),
+	Forbidden(String),
+	Conflict(String), // the target changed since the client last saw it
+	Unsupported(String),
+	Unavailable(String),
+	QuotaExceeded(Usage), // the user's daily quota of an expensive feature
+	Internal(String),
+}
+
+impl ServiceError {
+	pub fn status(&self) -> StatusCode {
+		match self {
+			ServiceError::NotFound(_) => StatusCode::NOT_FOUND,
+			ServiceError::BadRequest(_) => StatusCode::BAD_REQUEST,
+			ServiceError::Forbidden(_) => StatusCode::FORBIDDEN,
+			ServiceError::Conflict(_) => StatusCode::CONFLICT,
+			ServiceError::Unsupported(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+			ServiceError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+			ServiceError::QuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+			ServiceError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+		}
+	}
+
+	pub fn into_response(self) -> Response<String> {
+		if let ServiceError::QuotaExceeded(usage) = self {
+			return QuotaError::Exceeded(usage).into_response();
+		}
+		Response::builder()
+			.status(self.status())
+			.body(self.to_string())
+			.unwrap()
+	}
+}
+
+impl fmt::Display for ServiceError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ServiceError::NotFound(msg)
+			| ServiceError::BadRequest(msg)
+			| ServiceError::Forbidden(msg)
+			| ServiceError::Conflict(msg)
+			| ServiceError::Unsupported(msg)
+			| ServiceError::Unavailable(msg)
+			| ServiceError::Internal(msg) => write!(f, "{msg}"),
+			ServiceError::QuotaExceeded(usage) => write!(f, "Daily {} quota used up", usage.feature.as_str()),
+		}
+	}
+}
+
+impl From<diesel::result::Error> for ServiceError {
+	fn from(err: diesel::result::Error) -> Self {
+		ServiceError::Internal(format!("Database error: {err}"))
+	}
+}
+
+impl From<QuotaError> for ServiceError {
+	fn from(err: QuotaError) -> Self {
+		match err {
+			QuotaError::Exceeded(usage) => ServiceError::QuotaExceeded(usage),
+			QuotaError::Database(err) => err.into(),
+		}
+	}
+}
+
+impl From<PoolError> for ServiceError {
+	fn from(err: PoolError) -> Self {
+		ServiceError::Internal(format!("Failed to get DB from pool: {err}"))
+	}
+}
@@ -0,0 +1,1387 @@
+use crate::config::{
+	COVER_IMG_STORAGE, MAX_LOOKUP_IDS, MAX_SCAN_WORKERS, MUSIC_STORAGE, PREFETCH_URL_SECS, SCAN_BATCH_SIZE,
+	STREAM_FORMATS,
+};
+use crate::core::artwork::cover_uuid;
+use crate::core::audio_analysis::{self, MusicalKey};
+use crate::core::new_releases;
+use crate::lobic_db::db::{record_library_change, DatabasePool};
+use crate::lobic_db::models::{
+	Availability, ContentType, LibraryChange, Mood, Music, MusicAltName, MusicChapter, MusicResponse, TrackGenre,
+	TrackMood,
+};
+use crate::schema::music::dsl::*;
+use crate::schema::{
+	blocked_content, cover_art, music_alt_names, music_chapters, play_log, track_genres, track_moods, user_tags,
+};
+use crate::services::profile::BlockTarget;
+use crate::services::tag::{normalize_tag, TagTarget};
+use crate::services::ServiceError;
+use crate::utils::list::ListResponse;
+use crate::utils::{exp, jwt};
+
+use chrono::Utc;
+use diesel::{dsl::sql, prelude::*, sql_types::Integer, sqlite::Sqlite};
+use id3::{frame::PictureType, Tag, TagLike};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+// Narrows down the library, every field left out matches everything
+#[derive(Debug, Default, Deserialize)]
+pub struct MusicFilter {
+	pub title: Option<String>,
+	pub uuid: Option<String>,
+	pub artist: Option<String>,
+	pub album: Option<String>,
+	pub genre: Option<String>,        // any of the genres of the track
+	pub content_type: Option<String>, // music unless asked for, ignored when looking up by uuid
+	pub year: Option<i32>,
+	pub decade: Option<String>, // 1990, 1990s, 90s or '90s
+	pub bpm_min: Option<f64>,
+	pub bpm_max: Option<f64>,
+	pub key: Option<String>, // Am, A minor or the Camelot 8A
+	pub mood: Option<String>,
+	pub energy_min: Option<f64>, // 0 to 1
+	pub energy_max: Option<f64>,
+	pub tag: Option<String>, // tagged by the listeners, on the track or its album
+	pub randomizer: Option<bool>,
+	#[serde(skip)]
+	pub listener_id: Option<String>, // whose blocked tracks and artists a shuffle leaves out
+	#[serde(default)]
+	pub start_index: i64,
+	pub page_length: Option<i64>,
+}
+
+// What a scan reports while it goes through the files
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanStep {
+	Discovered { files: usize, workers: usize }, // before any of the files is processed
+	Saved,
+	Failed(String), // the file and what went wrong with it
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScanReport {
+	pub saved: usize,
+	pub errors: Vec<String>,
+	pub workers: usize,
+	pub elapsed_secs: f64,
+	pub files_per_sec: f64, // saved and failed ones alike
+}
+
+// A music file read and copied into the storage, saved along with the rest of its batch
+struct ScannedFile {
+	path: PathBuf,
+	music: Music,
+	genres: Vec<TrackGenre>,
+	alt_names: Vec<MusicAltName>,
+	chapters: Vec<MusicChapter>,
+	mood: Option<TrackMood>,
+}
+
+// How many files a scan reads at the same time, SCAN_WORKERS or one per core
+pub fn scan_workers() -> usize {
+	std::env::var("SCAN_WORKERS")
+		.ok()
+		.and_then(|workers| workers.parse().ok())
+		.unwrap_or_else(|| thread::available_parallelism().map_or(1, |cores| cores.get()))
+		.clamp(1, MAX_SCAN_WORKERS)
+}
+
+// Resuming this close after a chapter start goes back to the start of the chapter
+pub const CHAPTER_SNAP_SECS: f64 = 30.0;
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ChapterResponse {
+	pub index: i32,
+	pub title: String,
+	pub start: f64, // seconds into the track
+	pub end: f64,
+}
+
+impl From<&MusicChapter> for ChapterResponse {
+	fn from(chapter: &MusicChapter) -> ChapterResponse {
+		ChapterResponse {
+			index: chapter.chapter_index,
+			title: chapter.title.clone(),
+			start: chapter.start_ms as f64 / 1000.0,
+			end: chapter.end_ms as f64 / 1000.0,
+		}
+	}
+}
+
+// Everything a client needs before it starts playing a track
+#[derive(Debug, Serialize)]
+pub struct PlaybackInfo {
+	#[serde(flatten)]
+	pub music: MusicResponse,
+	pub stream_url: String,
+	pub cover_url: String,
+	pub genres: Vec<String>, // the primary genre first
+	pub chapters: Vec<ChapterResponse>,
+	pub mood: Option<String>,
+	pub energy: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LookupResponse {
+	pub items: Vec<MusicResponse>, // in the order asked for
+	pub missing: Vec<String>,      // unknown or no longer available
+}
+
+// Lets the client warm up the next track's buffer while the one before it plays
+#[derive(Debug, Serialize)]
+pub struct PrefetchHint {
+	pub after: String, // the track during which to prefetch
+	pub music_id: String,
+	pub url: String, // signed, good for PREFETCH_URL_SECS
+	pub bytes: u64,
+	pub format: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MoodSummary {
+	pub mood: String,
+	pub song_count: i64,
+	pub average_energy: f64,
+}
+
+// How many related artists are given when the client doesn't say
+pub const RELATED_ARTISTS_LIMIT: usize = 20;
+
+// An edge of the artist graph, how much the audiences of two artists overlap
+#[derive(Debug, Serialize)]
+pub struct RelatedArtist {
+	pub artist: String,
+	pub weight: f64, // cosine of the listeners' play counts, 1 is the very same audience playing both as much
+	pub shared_listeners: i64,
+}
+
+// The artist asked about and its listeners next to the usual list fields
+#[derive(Debug, Serialize)]
+pub struct RelatedArtists {
+	pub artist: String,
+	pub listeners: i64,
+	#[serde(flatten)]
+	pub related: ListResponse<RelatedArtist>, // heaviest first
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AltNameInput {
+	pub field: String, // title or artist
+	pub name: String,
+	pub kind: String, // original, romanized or translated
+}
+
+#[derive(Debug, Clone)]
+pub struct MusicService {
+	db_pool: DatabasePool,
+	storage: PathBuf,
+}
+
+impl MusicService {
+	pub fn new(db_pool: &DatabasePool) -> MusicService {
+		MusicService {
+			db_pool: db_pool.clone(),
+			storage: PathBuf::from(MUSIC_STORAGE),
+		}
+	}
+
+	// The available musics matching the filter
+	pub fn find(&self, filter: MusicFilter) -> Result<ListResponse<MusicResponse>, ServiceError> {
+		if let Some(decade) = &filter.decade {
+			parse_decade(decade).ok_or_else(|| ServiceError::BadRequest(format!("Invalid decade: {decade}")))?;
+		}
+		if let Some(key) = &filter.key {
+			MusicalKey::parse(key).ok_or_else(|| ServiceError::BadRequest(format!("Invalid key: {key}")))?;
+		}
+		if let Some(mood) = &filter.mood {
+			Mood::parse(mood).ok_or_else(|| ServiceError::BadRequest(format!("Invalid mood: {mood}")))?;
+		}
+		let mut db_conn = self.db_pool.get()?;
+
+		let total_count = filtered(&filter).count().get_result::<i64>(&mut db_conn)?;
+
+		let mut query = filtered(&filter);
+		if filter.randomizer.unwrap_or(false) {
+			query = query.order(sql::<Integer>("RANDOM()"));
+		}
+
+		query = query.offset(filter.start_index);
+
+		if let Some(length) = filter.page_length {
+			if length > 0 {
+				query = query.limit(length);
+			}
+		}
+
+		let music_entries = query.load::<Music>(&mut db_conn)?;
+		let responses = music_entries.into_iter().map(Music::create_music_response).collect();
+		Ok(ListResponse::page(
+			responses,
+			total_count,
+			filter.start_index,
+			filter.page_length,
+		))
+	}
+
+	pub fn playback_info(&self, curr_music_id: &str) -> Result<PlaybackInfo, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let entry = music
+			.find(curr_music_id)
+			.filter(availability.eq(Availability::Available.as_str()))
+			.first::<Music>(&mut db_conn)
+			.optional()?
+			.ok_or_else(|| ServiceError::NotFound(format!("No music: {curr_music_id}")))?;
+		let genres = track_genres::table
+			.filter(track_genres::music_id.eq(curr_music_id))
+			.order(track_genres::position.asc())
+			.select(track_genres::genre)
+			.load::<String>(&mut db_conn)?;
+		let chapters = self.chapters(curr_music_id)?;
+		let curr_mood = track_moods::table
+			.find(curr_music_id)
+			.first::<TrackMood>(&mut db_conn)
+			.optional()?;
+
+		let entry = Music::create_music_response(entry);
+		Ok(PlaybackInfo {
+			stream_url: format!("/music/{}", entry.id),
+			cover_url: format!("/image/{}", entry.image_url),
+			genres,
+			chapters: chapters.iter().map(ChapterResponse::from).collect(),
+			mood: curr_mood.as_ref().map(|curr_mood| curr_mood.mood.clone()),
+			energy: curr_mood.map(|curr_mood| curr_mood.energy),
+			music: entry,
+		})
+	}
+
+	// The tracks behind the stored ids of a queue or playlist, in one go
+	pub fn lookup(&self, music_ids: &[String]) -> Result<LookupResponse, ServiceError> {
+		if music_ids.len() > MAX_LOOKUP_IDS {
+			return Err(ServiceError::BadRequest(format!(
+				"At most {MAX_LOOKUP_IDS} music ids can be looked up at once"
+			)));
+		}
+		let mut db_conn = self.db_pool.get()?;
+		let found: HashMap<String, MusicResponse> = music
+			.filter(music_id.eq_any(music_ids))
+			.filter(availability.eq(Availability::Available.as_str()))
+			.load::<Music>(&mut db_conn)?
+			.into_iter()
+			.map(|entry| (entry.music_id.clone(), Music::create_music_response(entry)))
+			.collect();
+
+		// A queue can hold the same track more than once, so can the response
+		let mut response = LookupResponse {
+			items: Vec::new(),
+			missing: Vec::new(),
+		};
+		for curr_music_id in music_ids {
+			match found.get(curr_music_id) {
+				Some(entry) => response.items.push(entry.clone()),
+				None => response.missing.push(curr_music_id.clone()),
+			}
+		}
+		Ok(response)
+	}
+
+	// A hint for every track that follows another in the list, tracks missing their file are skipped
+	pub fn prefetch_hints(&self, music_ids: &[String]) -> Vec<PrefetchHint> {
+		music_ids
+			.windows(2)
+			.filter_map(|pair| {
+				let path = self.storage.join(format!("{}.mp3", pair[1]));
+				let bytes = fs::metadata(path).ok()?.len();
+				Some(PrefetchHint {
+					after: pair[0].clone(),
+					music_id: pair[1].clone(),
+					url: signed_stream_url(&pair[1])?,
+					bytes,
+					format: STREAM_FORMATS[0],
+				})
+			})
+			.collect()
+	}
+
+	// Chapters of the track in playback order, empty for most music
+	pub fn chapters(&self, curr_music_id: &str) -> Result<Vec<MusicChapter>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		Ok(music_chapters::table
+			.filter(music_chapters::music_id.eq(curr_music_id))
+			.order(music_chapters::chapter_index.asc())
+			.load::<MusicChapter>(&mut db_conn)?)
+	}
+
+	// The artists whose listeners also play this one, from the play counts of everyone. Start a radio off
+	// one with /music/get_music?artist=&randomizer=true. The ones the listener blocked are left out.
+	pub fn related_artists(
+		&self,
+		curr_artist: &str,
+		limit: usize,
+		listener_id: Option<&str>,
+	) -> Result<RelatedArtists, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let known = music
+			.filter(artist.eq(curr_artist))
+			.filter(availability.eq(Availability::Available.as_str()))
+			.count()
+			.get_result::<i64>(&mut db_conn)?;
+		if known == 0 {
+			return Err(ServiceError::NotFound(format!("No artist named {curr_artist}")));
+		}
+
+		let plays = play_log::table
+			.inner_join(music)
+			.filter(availability.eq(Availability::Available.as_str()))
+			.filter(content_type.eq(ContentType::Music.as_str()))
+			.filter(play_log::user_times_played.ge(1))
+			.select((artist, play_log::user_id, play_log::user_times_played))
+			.load::<(String, String, i32)>(&mut db_conn)?;
+		// artist -> listener -> plays
+		let mut audiences: HashMap<String, HashMap<String, f64>> = HashMap::new();
+		for (played_artist, listener, listener_plays) in plays {
+			*audiences.entry(played_artist).or_default().entry(listener).or_default() += listener_plays as f64;
+		}
+
+		let blocked = match listener_id {
+			Some(listener_id) => blocked_content::table
+				.filter(blocked_content::user_id.eq(listener_id))
+				.filter(blocked_content::target_type.eq(BlockTarget::Artist.as_str()))
+				.select(blocked_content::target_id)
+				.load::<String>(&mut db_conn)?,
+			None => Vec::new(),
+		};
+
+		let norm = |audience: &HashMap<String, f64>| audience.values().map(|plays| plays * plays).sum::<f64>().sqrt();
+		let empty = HashMap::new();
+		let audience = audiences.get(curr_artist).unwrap_or(&empty);
+		let mut related: Vec<RelatedArtist> = audiences
+			.iter()
+			.filter(|(other, _)| other.as_str() != curr_artist && !blocked.contains(other))
+			.filter_map(|(other, other_audience)| {
+				let shared: Vec<f64> = audience
+					.iter()
+					.filter_map(|(listener, plays)| other_audience.get(listener).map(|other_plays| plays * other_plays))
+					.collect();
+				if shared.is_empty() {
+					return None;
+				}
+				let weight = shared.iter().sum::<f64>() / (norm(audience) * norm(other_audience));
+				Some(RelatedArtist {
+					artist: other.clone(),
+					weight: (weight * 1000.0).round() / 1000.0,
+					shared_listeners: shared.len() as i64,
+				})
+			})
+			.collect();
+		related.sort_by(|a, b| {
+			b.weight
+				.total_cmp(&a.weight)
+				.then(b.shared_listeners.cmp(&a.shared_listeners))
+				.then_with(|| a.artist.cmp(&b.artist))
+		});
+		related.truncate(limit);
+
+		Ok(RelatedArtists {
+			artist: curr_artist.to_string(),
+			listeners: audience.len() as i64,
+			related: ListResponse::all(related),
+		})
+	}
+
+	// Other forms of the title and artist, the tagged ones first by field
+	pub fn alt_names(&self, curr_music_id: &str) -> Result<Vec<MusicAltName>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		Ok(music_alt_names::table
+			.filter(music_alt_names::music_id.eq(curr_music_id))
+			.order((music_alt_names::field.desc(), music_alt_names::name.asc()))
+			.load::<MusicAltName>(&mut db_conn)?)
+	}
+
+	// Replaces every other form of the title and artist of the track
+	pub fn set_alt_names(
+		&self,
+		curr_music_id: &str,
+		names: Vec<AltNameInput>,
+	) -> Result<Vec<MusicAltName>, ServiceError> {
+		let mut alt_names = Vec::new();
+		for name in names {
+			let trimmed = name.name.trim();
+			if name.field != MusicAltName::TITLE && name.field != MusicAltName::ARTIST {
+				return Err(ServiceError::BadRequest(format!(
+					"Invalid field, expected title or artist: {}",
+					name.field
+				)));
+			}
+			if !MusicAltName::KINDS.contains(&name.kind.as_str()) {
+				return Err(ServiceError::BadRequest(format!(
+					"Invalid kind, expected original, romanized or translated: {}",
+					name.kind
+				)));
+			}
+			if trimmed.is_empty()
+				|| alt_names
+					.iter()
+					.any(|known: &MusicAltName| known.field == name.field && known.name == trimmed)
+			{
+				continue;
+			}
+			alt_names.push(MusicAltName {
+				music_id: curr_music_id.to_string(),
+				field: name.field,
+				name: trimmed.to_string(),
+				kind: name.kind,
+			});
+		}
+
+		let mut db_conn = self.db_pool.get()?;
+		let exists = music
+			.find(curr_music_id)
+			.select(music_id)
+			.first::<String>(&mut db_conn)
+			.optional()?
+			.is_some();
+		if !exists {
+			return Err(ServiceError::NotFound(format!("No music: {curr_music_id}")));
+		}
+
+		db_conn.transaction(|conn| {
+			diesel::delete(music_alt_names::table.filter(music_alt_names::music_id.eq(curr_music_id))).execute(conn)?;
+			diesel::insert_into(music_alt_names::table)
+				.values(&alt_names)
+				.execute(conn)?;
+			record_library_change(curr_music_id, LibraryChange::Retagged, conn)
+		})?;
+		drop(db_conn);
+		self.alt_names(curr_music_id)
+	}
+
+	// Reads the release year of the music saved before years were kept, returns how many were found
+	pub fn backfill_release_years(&self) -> Result<usize, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let missing = music
+			.filter(release_year.is_null())
+			.select(music_id)
+			.load::<String>(&mut db_conn)?;
+
+		let mut found = 0;
+		for curr_music_id in missing {
+			let path = self.storage.join(format!("{curr_music_id}.mp3"));
+			let Some(year) = Tag::read_from_path(path).ok().and_then(|tag| release_year_of(&tag)) else {
+				continue;
+			};
+			diesel::update(music.find(&curr_music_id))
+				.set(release_year.eq(year))
+				.execute(&mut db_conn)?;
+			found += 1;
+		}
+		Ok(found)
+	}
+
+	// Tempo and key of the music saved without them, returns how many were analyzed
+	pub fn analyze_missing(&self) -> Result<usize, ServiceError> {
+		if !audio_analysis::enabled() {
+			return Err(ServiceError::Unavailable(
+				"Audio analysis needs AUDIO_ANALYSIS=true and ffmpeg".to_string(),
+			));
+		}
+
+		let mut db_conn = self.db_pool.get()?;
+		let missing = music
+			.filter(bpm.is_null().or(musical_key.is_null()))
+			.filter(content_type.eq(ContentType::Music.as_str()))
+			.select((music_id, bpm, musical_key))
+			.load::<(String, Option<f64>, Option<String>)>(&mut db_conn)?;
+
+		let mut analyzed = 0;
+		for (curr_music_id, curr_bpm, curr_key) in missing {
+			let path = self.storage.join(format!("{curr_music_id}.mp3"));
+			let Ok(analysis) = audio_analysis::analyze(&path) else {
+				continue;
+			};
+			diesel::update(music.find(&curr_music_id))
+				.set((
+					bpm.eq(curr_bpm.or(analysis.bpm)),
+					musical_key.eq(curr_key.or(analysis.key.map(|key| key.name()))),
+				))
+				.execute(&mut db_conn)?;
+			analyzed += 1;
+		}
+		Ok(analyzed)
+	}
+
+	// Moods in use with how many available songs have them
+	pub fn moods(&self) -> Result<Vec<MoodSummary>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let tagged = track_moods::table
+			.inner_join(music)
+			.filter(availability.eq(Availability::Available.as_str()))
+			.filter(content_type.eq(ContentType::Music.as_str()))
+			.select((track_moods::mood, track_moods::energy))
+			.load::<(String, f64)>(&mut db_conn)?;
+
+		Ok(Mood::ALL
+			.iter()
+			.filter_map(|curr_mood| {
+				let energies: Vec<f64> = tagged
+					.iter()
+					.filter(|(tagged_mood, _)| tagged_mood == curr_mood.as_str())
+					.map(|(_, energy)| *energy)
+					.collect();
+				(!energies.is_empty()).then(|| MoodSummary {
+					mood: curr_mood.as_str().to_string(),
+					song_count: energies.len() as i64,
+					average_energy: energies.iter().sum::<f64>() / energies.len() as f64,
+				})
+			})
+			.collect())
+	}
+
+	// Tags the mood by hand, it stays until tagged again and analysis never replaces it
+	pub fn set_mood(
+		&self,
+		curr_music_id: &str,
+		curr_mood: Mood,
+		energy: Option<f64>,
+		user_id: &str,
+	) -> Result<TrackMood, ServiceError> {
+		let energy = energy.unwrap_or(curr_mood.typical_energy());
+		if !(0.0..=1.0).contains(&energy) {
+			return Err(ServiceError::BadRequest(format!(
+				"Energy has to be between 0 and 1: {energy}"
+			)));
+		}
+
+		let mut db_conn = self.db_pool.get()?;
+		let exists = music
+			.find(curr_music_id)
+			.select(music_id)
+			.first::<String>(&mut db_conn)
+			.optional()?
+			.is_some();
+		if !exists {
+			return Err(ServiceError::NotFound(format!("No music: {curr_music_id}")));
+		}
+
+		let tagged = TrackMood {
+			music_id: curr_music_id.to_string(),
+			mood: curr_mood.as_str().to_string(),
+			energy,
+			source: "manual".to_string(),
+			tagged_by: Some(user_id.to_string()),
+			updated_date_time: Utc::now().to_rfc3339(),
+		};
+		diesel::replace_into(track_moods::table)
+			.values(&tagged)
+			.execute(&mut db_conn)?;
+		record_library_change(curr_music_id, LibraryChange::Retagged, &mut db_conn)?;
+		Ok(tagged)
+	}
+
+	// Works out the mood of the music with a tempo but no mood yet, returns how many were tagged
+	pub fn tag_missing_moods(&self) -> Result<usize, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let untagged = music
+			.filter(bpm.is_not_null())
+			.filter(content_type.eq(ContentType::Music.as_str()))
+			.filter(music_id.ne_all(track_moods::table.select(track_moods::music_id)))
+			.select((music_id, bpm, musical_key))
+			.load::<(String, Option<f64>, Option<String>)>(&mut db_conn)?;
+
+		let derived: Vec<TrackMood> = untagged
+			.into_iter()
+			.filter_map(|(curr_music_id, curr_bpm, curr_key)| {
+				Some(analysis_mood(&curr_music_id, curr_bpm?, curr_key.as_deref()))
+			})
+			.collect();
+		diesel::insert_or_ignore_into(track_moods::table)
+			.values(&derived)
+			.execute(&mut db_conn)?;
+		Ok(derived.len())
+	}
+
+	// Saves every music file under the path, returns how many were saved and the errors of the others.
+	// The followers of the artists get the new tracks in their feed.
+	pub fn scan(&self, path: &str, curr_uploader_id: Option<&str>) -> Result<ScanReport, ServiceError> {
+		self.scan_with_progress(path, curr_uploader_id, |_| {})
+	}
+
+	// Same as `scan`, telling on_step about every file as it goes. The files are read by a pool of
+	// workers while this thread saves them in batches, sqlite takes a single writer anyway.
+	pub fn scan_with_progress(
+		&self,
+		path: &str,
+		curr_uploader_id: Option<&str>,
+		mut on_step: impl FnMut(ScanStep),
+	) -> Result<ScanReport, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let started = Instant::now();
+
+		// Convert Windows path to WSL path if needed
+		let path = normalize_path(path);
+		let path = Path::new(&path);
+
+		// Found first, so the progress can be told against the total
+		let files: Vec<PathBuf> = if path.is_dir() {
+			WalkDir::new(path)
+				.into_iter()
+				.filter_map(|e| e.ok())
+				.filter(|entry| is_music_file(entry.path()))
+				.map(|entry| entry.into_path())
+				.collect()
+		} else if is_music_file(path) {
+			vec![path.to_path_buf()]
+		} else {
+			Vec::new()
+		};
+		let workers = scan_workers().min(files.len().max(1));
+		on_step(ScanStep::Discovered {
+			files: files.len(),
+			workers,
+		});
+
+		// Covers picked on /cover_art stay, the tags of a rescan don't overwrite them
+		let curated: HashSet<String> = cover_art::table
+			.select(cover_art::img_uuid)
+			.distinct()
+			.load::<String>(&mut db_conn)?
+			.into_iter()
+			.collect();
+		let mut saved = Vec::new();
+		let mut errors = Vec::new();
+		let next_file = AtomicUsize::new(0);
+		// Bounded, the workers wait when the saving falls behind instead of piling the files up
+		let (sender, receiver) = mpsc::sync_channel(SCAN_BATCH_SIZE);
+		thread::scope(|scope| {
+			for _ in 0..workers {
+				let (files, next_file, sender, curated) = (&files, &next_file, sender.clone(), &curated);
+				scope.spawn(move || {
+					while let Some(file) = files.get(next_file.fetch_add(1, Ordering::Relaxed)) {
+						let read = read_music_file(file, curr_uploader_id, curated)
+							.map_err(|e| format!("{}: {}", file.display(), e));
+						if sender.send(read).is_err() {
+							return;
+						}
+					}
+				});
+			}
+			drop(sender);
+
+			let mut batch = Vec::with_capacity(SCAN_BATCH_SIZE);
+			for read in receiver {
+				match read {
+					Ok(scanned) => batch.push(scanned),
+					Err(error) => {
+						on_step(ScanStep::Failed(error.clone()));
+						errors.push(error);
+					}
+				}
+				if batch.len() >= SCAN_BATCH_SIZE {
+					let full = std::mem::replace(&mut batch, Vec::with_capacity(SCAN_BATCH_SIZE));
+					save_batch(full, &mut db_conn, &mut saved, &mut errors, &mut on_step);
+				}
+			}
+			save_batch(batch, &mut db_conn, &mut saved, &mut errors, &mut on_step);
+		});
+
+		new_releases::record(&saved, &mut db_conn)?;
+		let elapsed_secs = started.elapsed().as_secs_f64();
+		Ok(ScanReport {
+			saved: saved.len(),
+			files_per_sec: files.len() as f64 / elapsed_secs.max(0.001),
+			errors,
+			workers,
+			elapsed_secs,
+		})
+	}
+}
+
+// Every other form of the titles and artists, by music id, for searching them along with the tagged ones
+pub fn alt_names_by_track(db_conn: &mut SqliteConnection) -> HashMap<String, Vec<MusicAltName>> {
+	let mut by_track: HashMap<String, Vec<MusicAltName>> = HashMap::new();
+	for alt_name in music_alt_names::table.load::<MusicAltName>(db_conn).unwrap_or_default() {
+		by_track.entry(alt_name.music_id.clone()).or_default().push(alt_name);
+	}
+	by_track
+}
+
+// The tagged name followed by the other forms of the field
+pub fn name_forms<'a>(tagged: &'a str, alt_names: Option<&'a Vec<MusicAltName>>, field: &str) -> Vec<&'a str> {
+	let mut forms = vec![tagged];
+	forms.extend(
+		alt_names
+			.into_iter()
+			.flatten()
+			.filter(|alt_name| alt_name.field == field)
+			.map(|alt_name| alt_name.name.as_str()),
+	);
+	forms
+}
+
+fn filtered(filter: &MusicFilter) -> crate::schema::music::BoxedQuery<'static, Sqlite> {
+	let mut query = music
+		.filter(availability.eq(Availability::Available.as_str()))
+		.into_boxed();
+
+	if let Some(title_val) = filter.title.clone() {
+		query = query.filter(title.eq(title_val));
+	}
+	if let Some(uuid_val) = filter.uuid.clone() {
+		query = query.filter(music_id.eq(uuid_val));
+	}
+	if let Some(artist_val) = filter.artist.clone() {
+		query = query.filter(artist.eq(artist_val));
+	}
+	if let Some(album_val) = filter.album.clone() {
+		query = query.filter(album.eq(album_val));
+	}
+	if let Some(genre_val) = filter.genre.clone() {
+		let tagged = track_genres::table
+			.filter(track_genres::genre.eq(genre_val))
+			.select(track_genres::music_id);
+		query = query.filter(music_id.eq_any(tagged));
+	}
+	if let Some(year) = filter.year {
+		query = query.filter(release_year.eq(year));
+	}
+	if let Some(decade) = filter.decade.as_deref().and_then(parse_decade) {
+		query = query.filter(release_year.between(decade, decade + 9));
+	}
+	if let Some(bpm_min) = filter.bpm_min {
+		query = query.filter(bpm.ge(bpm_min));
+	}
+	if let Some(bpm_max) = filter.bpm_max {
+		query = query.filter(bpm.le(bpm_max));
+	}
+	if let Some(key) = filter.key.as_deref().and_then(MusicalKey::parse) {
+		query = query.filter(musical_key.eq(key.name()));
+	}
+	if filter.mood.is_some() || filter.energy_min.is_some() || filter.energy_max.is_some() {
+		let mut tagged = track_moods::table.select(track_moods::music_id).into_boxed();
+		if let Some(curr_mood) = filter.mood.as_deref().and_then(Mood::parse) {
+			tagged = tagged.filter(track_moods::mood.eq(curr_mood.as_str()));
+		}
+		if let Some(energy_min) = filter.energy_min {
+			tagged = tagged.filter(track_moods::energy.ge(energy_min));
+		}
+		if let Some(energy_max) = filter.energy_max {
+			tagged = tagged.filter(track_moods::energy.le(energy_max));
+		}
+		query = query.filter(music_id.eq_any(tagged));
+	}
+	if let Some(tag_val) = filter.tag.as_deref().and_then(normalize_tag) {
+		let tagged_tracks = user_tags::table
+			.filter(user_tags::target_type.eq(TagTarget::Track.as_str()))
+			.filter(user_tags::tag.eq(tag_val.clone()))
+			.select(user_tags::target_id);
+		let tagged_albums = user_tags::table
+			.filter(user_tags::target_type.eq(TagTarget::Album.as_str()))
+			.filter(user_tags::tag.eq(tag_val))
+			.select(user_tags::target_id);
+		query = query.filter(music_id.eq_any(tagged_tracks).or(album.eq_any(tagged_albums)));
+	}
+	// Audiobooks and podcasts never end up in a shuffle, neither does what the listener blocked
+	if filter.randomizer.unwrap_or(false) {
+		query = query.filter(content_type.eq(ContentType::Music.as_str()));
+		if let Some(listener_id) = filter.listener_id.clone() {
+			let blocked = |target: BlockTarget| {
+				blocked_content::table
+					.filter(blocked_content::user_id.eq(listener_id.clone()))
+					.filter(blocked_content::target_type.eq(target.as_str()))
+					.select(blocked_content::target_id)
+			};
+			query = query
+				.filter(music_id.ne_all(blocked(BlockTarget::Track)))
+				.filter(artist.ne_all(blocked(BlockTarget::Artist)));
+		}
+	} else if filter.uuid.is_none() {
+		let content_type_val = filter.content_type.clone();
+		query = query.filter(content_type.eq(content_type_val.unwrap_or(ContentType::Music.as_str().to_string())));
+	}
+
+	query
+}
+
+fn normalize_path(path: &str) -> String {
+	if cfg!(windows) {
+		// On Windows, convert forward slashes to backslashes
+		path.replace('/', "\\")
+	} else {
+		// If running under WSL, convert Windows paths to WSL paths
+		if path.contains('\\') || path.contains(':') {
+			convert_windows_to_wsl_path(path)
+		} else {
+			path.to_string()
+		}
+	}
+}
+
+fn convert_windows_to_wsl_path(windows_path: &str) -> String {
+	let path = windows_path.replace('\\', "/");
+
+	// Check if it's a Windows-style path (e.g., C:\...)
+	if path.chars().nth(1) == Some(':') {
+		let drive_letter = path.chars().next().unwrap().to_lowercase().to_string();
+		format!("/mnt/{}/{}", drive_letter, &path[3..])
+	} else {
+		path
+	}
+}
+
+fn is_music_file(path: &Path) -> bool {
+	match path.extension() {
+		Some(ext) => matches!(
+			ext.to_str().unwrap_or("").to_lowercase().as_str(),
+			"mp3" | "m4a" | "flac" | "wav" | "ogg"
+		),
+		None => false,
+	}
+}
+
+// Reads the tags and copies the file into the storage, nothing is saved yet. The covers of the
+// albums in curated are left as they are.
+fn read_music_file(
+	path: &Path,
+	curr_uploader_id: Option<&str>,
+	curated: &HashSet<String>,
+) -> Result<ScannedFile, Box<dyn std::error::Error>> {
+	let path_str = path.to_str().ok_or("Invalid path")?;
+
+	// Read ID3 tags
+	let tag = Tag::read_from_path(path_str).unwrap_or_else(|_| Tag::new());
+
+	let curr_artist = tag.artist().unwrap_or("Unknown Artist");
+	let curr_title = tag.title().unwrap_or("Unknown Title");
+	let curr_album = tag.album().unwrap_or("Unknown Album");
+
+	let curr_music_id = generate_uuid_from_metadata(curr_artist, curr_title, curr_album);
+	let curr_content_type = content_type_of(&tag);
+	let genres = split_genres(&tag.genres().unwrap_or_default());
+	let tag_bpm = tag
+		.get("TBPM")
+		.and_then(|frame| frame.content().text())
+		.and_then(|text| text.trim().parse::<f64>().ok())
+		.filter(|tempo| *tempo > 0.0);
+	let tag_key = tag
+		.get("TKEY")
+		.and_then(|frame| frame.content().text())
+		.and_then(MusicalKey::parse);
+
+	// Create the music_db directory if it doesn't exist
+	let music_db_dir = PathBuf::from(MUSIC_STORAGE);
+	fs::create_dir_all(&music_db_dir)?;
+
+	let new_file_path = music_db_dir.join(format!("{}.mp3", curr_music_id));
+
+	// Copy the music file to the new location
+	fs::copy(path, &new_file_path)?;
+
+	let file = fs::File::open(path_str)?;
+	let duration_u64 = mp3_duration::from_file(&file)?.as_secs();
+	let curr_duration = i64::try_from(duration_u64)?; // Convert u64 to i64, will error if too large
+
+	// Only music gets analyzed, and only for what the tags left out
+	let analysis = match (tag_bpm, tag_key) {
+		(Some(_), Some(_)) => None,
+		_ if curr_content_type == ContentType::Music && audio_analysis::enabled() => {
+			audio_analysis::analyze(&new_file_path).ok()
+		}
+		_ => None,
+	};
+
+	let curr_music = Music {
+		music_id: curr_music_id.to_string(),
+		artist: curr_artist.to_string(),
+		title: curr_title.to_string(),
+		album: curr_album.to_string(),
+		genre: genres.first().cloned().unwrap_or("Unknown Genre".to_string()),
+		times_played: 0,
+		duration: curr_duration,
+		availability: Availability::Available.as_str().to_string(),
+		availability_reason: None,
+		uploader_id: curr_uploader_id.map(String::from),
+		content_type: curr_content_type.as_str().to_string(),
+		series: (curr_content_type != ContentType::Music)
+			.then(|| extended_text(&tag, "SERIES").or(tag.get("TIT1").and_then(|frame| frame.content().text())))
+			.flatten()
+			.map(String::from),
+		series_index: extended_text(&tag, "SERIES-PART").and_then(|part| part.trim().parse().ok()),
+		release_year: release_year_of(&tag),
+		bpm: tag_bpm.or(analysis.and_then(|analysis| analysis.bpm)),
+		musical_key: tag_key
+			.or(analysis.and_then(|analysis| analysis.key))
+			.map(|key| key.name()),
+		explicit: is_explicit(&tag),
+	};
+
+	extract_cover_art(path_str, curr_artist, curr_album, curated)?;
+
+	let curr_track_genres = track_genres_of(&curr_music.music_id, genres, &curr_music.genre);
+	let mood = match (curr_music.bpm, curr_content_type) {
+		(Some(curr_bpm), ContentType::Music) => Some(analysis_mood(
+			&curr_music.music_id,
+			curr_bpm,
+			curr_music.musical_key.as_deref(),
+		)),
+		_ => None,
+	};
+
+	Ok(ScannedFile {
+		path: path.to_path_buf(),
+		alt_names: sort_order_names(&curr_music, &tag),
+		chapters: tag_chapters(&curr_music.music_id, &tag, duration_u64 as i64 * 1000),
+		genres: curr_track_genres,
+		mood,
+		music: curr_music,
+	})
+}
+
+// Saves the batch in one transaction. When that fails the files are saved one by one, only the
+// ones that can't be, like a second copy of a track, are left out.
+fn save_batch(
+	batch: Vec<ScannedFile>,
+	db_conn: &mut SqliteConnection,
+	saved: &mut Vec<Music>,
+	errors: &mut Vec<String>,
+	on_step: &mut impl FnMut(ScanStep),
+) {
+	if batch.is_empty() {
+		return;
+	}
+	let results: Vec<QueryResult<()>> = match db_conn.transaction(|db_conn| insert_scanned(&batch, db_conn)) {
+		Ok(()) => batch.iter().map(|_| Ok(())).collect(),
+		Err(_) => batch
+			.iter()
+			.map(|scanned| db_conn.transaction(|db_conn| insert_scanned(std::slice::from_ref(scanned), db_conn)))
+			.collect(),
+	};
+
+	for (scanned, result) in batch.into_iter().zip(results) {
+		match result {
+			Ok(()) => {
+				on_step(ScanStep::Saved);
+				saved.push(scanned.music);
+			}
+			Err(e) => {
+				let error = format!("{}: {}", scanned.path.display(), e);
+				on_step(ScanStep::Failed(error.clone()));
+				errors.push(error);
+			}
+		}
+	}
+}
+
+fn insert_scanned(batch: &[ScannedFile], db_conn: &mut SqliteConnection) -> QueryResult<()> {
+	let tracks: Vec<&Music> = batch.iter().map(|scanned| &scanned.music).collect();
+	diesel::insert_into(music).values(tracks).execute(db_conn)?;
+	for scanned in batch {
+		record_library_change(&scanned.music.music_id, LibraryChange::Added, db_conn)?;
+	}
+
+	let curr_track_genres: Vec<&TrackGenre> = batch.iter().flat_map(|scanned| &scanned.genres).collect();
+	diesel::insert_into(track_genres::table)
+		.values(curr_track_genres)
+		.execute(db_conn)?;
+	let alt_names: Vec<&MusicAltName> = batch.iter().flat_map(|scanned| &scanned.alt_names).collect();
+	diesel::insert_or_ignore_into(music_alt_names::table)
+		.values(alt_names)
+		.execute(db_conn)?;
+	let chapters: Vec<&MusicChapter> = batch.iter().flat_map(|scanned| &scanned.chapters).collect();
+	diesel::insert_into(music_chapters::table)
+		.values(chapters)
+		.execute(db_conn)?;
+	let moods: Vec<&TrackMood> = batch.iter().filter_map(|scanned| scanned.mood.as_ref()).collect();
+	diesel::insert_or_ignore_into(track_moods::table)
+		.values(moods)
+		.execute(db_conn)?;
+	Ok(())
+}
+
+// TSOT and TSOP, the sort order frames, mostly hold the romanized title and artist of music tagged in
+// another script
+fn sort_order_names(curr_music: &Music, tag: &Tag) -> Vec<MusicAltName> {
+	[
+		("TSOT", MusicAltName::TITLE, &curr_music.title),
+		("TSOP", MusicAltName::ARTIST, &curr_music.artist),
+	]
+	.into_iter()
+	.filter_map(|(frame_id, field, tagged)| {
+		let name = tag.get(frame_id)?.content().text()?.trim();
+		(!name.is_empty() && name.to_lowercase() != tagged.to_lowercase()).then(|| MusicAltName {
+			music_id: curr_music.music_id.clone(),
+			field: field.to_string(),
+			name: name.to_string(),
+			kind: "romanized".to_string(),
+		})
+	})
+	.collect()
+}
+
+pub fn analysis_mood(curr_music_id: &str, curr_bpm: f64, curr_key: Option<&str>) -> TrackMood {
+	let (curr_mood, energy) = audio_analysis::derive_mood(curr_bpm, curr_key.and_then(MusicalKey::parse));
+	TrackMood {
+		music_id: curr_music_id.to_string(),
+		mood: curr_mood.as_str().to_string(),
+		energy,
+		source: "analysis".to_string(),
+		tagged_by: None,
+		updated_date_time: Utc::now().to_rfc3339(),
+	}
+}
+
+// Tags keep several genres as separate TCON values or in one string split by semicolons or commas,
+// the first one is the primary genre and repeats are dropped ignoring case
+pub fn split_genres(values: &[&str]) -> Vec<String> {
+	let mut genres: Vec<String> = Vec::new();
+	for value in values.iter().flat_map(|value| value.split([';', ','])) {
+		let value = value.trim();
+		if !value.is_empty() && !genres.iter().any(|known| known.eq_ignore_ascii_case(value)) {
+			genres.push(value.to_string());
+		}
+	}
+	genres
+}
+
+// Untagged tracks get a row for their primary genre, so genre filters and facets still find them
+fn track_genres_of(curr_music_id: &str, genres: Vec<String>, primary: &str) -> Vec<TrackGenre> {
+	let genres = if genres.is_empty() {
+		vec![primary.to_string()]
+	} else {
+		genres
+	};
+	genres
+		.into_iter()
+		.enumerate()
+		.map(|(position, curr_genre)| TrackGenre {
+			music_id: curr_music_id.to_string(),
+			genre: curr_genre,
+			position: position as i32,
+		})
+		.collect()
+}
+
+// The original release wins over a reissue, implausible years are dropped
+fn release_year_of(tag: &Tag) -> Option<i32> {
+	[
+		tag.original_date_released().map(|date| date.year),
+		tag.date_released().map(|date| date.year),
+		tag.date_recorded().map(|date| date.year),
+		tag.year(),
+	]
+	.into_iter()
+	.flatten()
+	.find(|year| (1000..=9999).contains(year))
+}
+
+// iTunes keeps the advisory as 1 for explicit, 2 for clean
+fn is_explicit(tag: &Tag) -> bool {
+	extended_text(tag, "ITUNESADVISORY").is_some_and(|advisory| advisory.trim() == "1")
+}
+
+// First year of the decade, two digit decades before the 30s are taken as this century
+pub fn parse_decade(decade: &str) -> Option<i32> {
+	let digits = decade.trim().trim_start_matches('\'').trim_end_matches(['s', 'S']);
+	let year: i32 = digits.parse().ok()?;
+	let year = match digits.len() {
+		2 if year < 30 => 2000 + year,
+		2 => 1900 + year,
+		4 => year,
+		_ => return None,
+	};
+	(year % 10 == 0).then_some(year)
+}
+
+// Podcasts carry a PCST frame, audiobooks are told by their genre
+fn content_type_of(tag: &Tag) -> ContentType {
+	let tag_genres = split_genres(&tag.genres().unwrap_or_default()).join(";").to_lowercase();
+	let has_genre = |names: &[&str]| tag_genres.split(';').any(|tag_genre| names.contains(&tag_genre));
+	if tag.get("PCST").is_some() || has_genre(&["podcast"]) {
+		ContentType::Podcast
+	} else if has_genre(&["audiobook", "audiobooks", "audio book"]) {
+		ContentType::Audiobook
+	} else {
+		ContentType::Music
+	}
+}
+
+// Value of a TXXX frame, the description is matched ignoring case
+fn extended_text<'a>(tag: &'a Tag, description: &str) -> Option<&'a str> {
+	tag.extended_texts()
+		.find(|text| text.description.eq_ignore_ascii_case(description))
+		.map(|text| text.value.as_str())
+}
+
+// Chapters from the CHAP frames, the end of a chapter is taken from the next one when it is missing
+fn tag_chapters(curr_music_id: &str, tag: &Tag, duration_ms: i64) -> Vec<MusicChapter> {
+	let mut chapters: Vec<_> = tag.chapters().collect();
+	chapters.sort_by_key(|chapter| chapter.start_time);
+
+	let starts: Vec<i64> = chapters.iter().map(|chapter| chapter.start_time as i64).collect();
+	chapters
+		.iter()
+		.enumerate()
+		.map(|(index, chapter)| {
+			let next_start = starts.get(index + 1).copied().unwrap_or(duration_ms);
+			let end_ms = match chapter.end_time as i64 {
+				end if end > chapter.start_time as i64 => end,
+				_ => next_start,
+			};
+			MusicChapter {
+				music_id: curr_music_id.to_string(),
+				chapter_index: index as i32,
+				title: chapter
+					.title()
+					.map(str::to_string)
+					.unwrap_or_else(|| format!("Chapter {}", index + 1)),
+				start_ms: chapter.start_time as i64,
+				end_ms,
+			}
+		})
+		.collect()
+}
+
+// The stream url with a signature that runs out, so it can be handed to a cache or a download manager
+pub fn signed_stream_url(curr_music_id: &str) -> Option<String> {
+	let secret_key = jwt::purpose_key(&std::env::var("JWT_SECRET_KEY").ok()?, "stream");
+	let claims = jwt::Claims {
+		id: curr_music_id.to_string(),
+		exp: exp::expiration_from_sec(PREFETCH_URL_SECS),
+		iat: exp::now(),
+	};
+	let signature = jwt::generate(claims, &secret_key).ok()?;
+	Some(format!("/music/{curr_music_id}?sig={signature}"))
+}
+
+pub fn valid_stream_signature(curr_music_id: &str, signature: &str) -> bool {
+	let Ok(secret_key) = std::env::var("JWT_SECRET_KEY") else {
+		return false;
+	};
+	jwt::verify(signature, &jwt::purpose_key(&secret_key, "stream")).is_ok_and(|data| data.claims.id == curr_music_id)
+}
+
+// Where a resume at the position should start, the chapter start when the position is just past it
+pub fn snap_to_chapter(chapters: &[MusicChapter], position: f64) -> f64 {
+	let position_ms = (position * 1000.0) as i64;
+	match chapters.iter().find(|chapter| chapter.contains(position_ms)) {
+		Some(chapter) if position - (chapter.start_ms as f64 / 1000.0) <= CHAPTER_SNAP_SECS => {
+			chapter.start_ms as f64 / 1000.0
+		}
+		_ => position,
+	}
+}
+
+fn extract_cover_art(
+	mp3_path: &str,
+	curr_artist: &str,
+	curr_album: &str,
+	curated: &HashSet<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+	if curated.contains(&cover_uuid(curr_artist, curr_album).to_string()) {
+		return Ok(());
+	}
+	let tag = Tag::read_from_path(mp3_path)?;
+	let pictures: Vec<_> = tag.pictures().collect();
+
+	if let Some(picture) = pictures.iter().find(|pic| pic.picture_type == PictureType::CoverFront) {
+		// Create platform-independent path for cover_images directory
+
+		let img_uuid = cover_uuid(curr_artist, curr_album);
+
+		let cover_dir = PathBuf::from(COVER_IMG_STORAGE);
+		fs::create_dir_all(&cover_dir)?;
+
+		let output_path = cover_dir.join(format!("{}.png", img_uuid));
+		let mut file = fs::File::create(&output_path)?;
+		file.write_all(&picture.data)?;
+
+		Ok(())
+	} else {
+		Err("No cover art found in the MP3 file".into())
+	}
+}
+
+//assumes all mp3 have unique sets of metadata
+fn generate_uuid_from_metadata(curr_artist: &str, curr_title: &str, curr_album: &str) -> Uuid {
+	let mut hasher = DefaultHasher::new();
+	curr_artist.hash(&mut hasher);
+	curr_title.hash(&mut hasher);
+	curr_album.hash(&mut hasher);
+	let hash = hasher.finish();
+
+	// Convert the hash to a UUID
+	Uuid::from_u64_pair(hash, hash)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support::TestApp;
+	use crate::utils::auth::session_user_id;
+
+	use axum_extra::extract::cookie::{Cookie, CookieJar};
+	use id3::frame::Chapter;
+
+	fn chapter(element_id: &str, start_time: u32, end_time: u32, chapter_title: Option<&str>) -> Chapter {
+		let mut chapter = Chapter {
+			element_id: element_id.to_string(),
+			start_time,
+			end_time,
+			start_offset: u32::MAX,
+			end_offset: u32::MAX,
+			frames: Vec::new(),
+		};
+		if let Some(chapter_title) = chapter_title {
+			chapter.set_title(chapter_title);
+		}
+		chapter
+	}
+
+	#[test]
+	fn chapters_are_read_in_order() {
+		let mut tag = Tag::new();
+		tag.add_frame(chapter("ch1", 90_000, 0, None));
+		tag.add_frame(chapter("ch0", 0, 90_000, Some("Intro")));
+
+		let chapters = tag_chapters("id", &tag, 200_000);
+		assert_eq!(chapters.len(), 2);
+		assert_eq!((chapters[0].title.as_str(), chapters[0].end_ms), ("Intro", 90_000));
+		// A missing end runs until the end of the track
+		assert_eq!((chapters[1].title.as_str(), chapters[1].end_ms), ("Chapter 2", 200_000));
+
+		assert_eq!(snap_to_chapter(&chapters, 100.0), 90.0);
+		assert_eq!(snap_to_chapter(&chapters, 150.0), 150.0);
+		assert_eq!(snap_to_chapter(&[], 10.0), 10.0);
+	}
+
+	#[test]
+	fn prefetch_hints_are_signed_for_the_next_track_only() {
+		let test_app = TestApp::seeded();
+		let storage = std::env::temp_dir().join(format!("lobic_music_{}", Uuid::new_v4()));
+		let service = MusicService {
+			db_pool: test_app.app_state.db_pool.clone(),
+			storage: storage.clone(),
+		};
+		fs::create_dir_all(&storage).unwrap();
+		fs::write(storage.join("second.mp3"), [0u8; 64]).unwrap();
+
+		// the third track isn't on disk
+		let ids = ["first", "second", "third"].map(String::from);
+		let hints = service.prefetch_hints(&ids);
+		fs::remove_dir_all(&storage).unwrap();
+		assert_eq!(hints.len(), 1);
+		assert_eq!(
+			(hints[0].after.as_str(), hints[0].music_id.as_str(), hints[0].bytes),
+			("first", "second", 64)
+		);
+
+		let signature = hints[0].url.split_once("sig=").unwrap().1;
+		assert!(valid_stream_signature("second", signature));
+		assert!(!valid_stream_signature("first", signature));
+		let jar = CookieJar::new().add(Cookie::new("access_token", signature.to_string()));
+		assert_eq!(session_user_id(&jar), None);
+	}
+
+	#[test]
+	fn genres_are_split_and_deduplicated() {
+		assert_eq!(
+			split_genres(&["Rock; Pop", "rock", "Jazz,  Blues,"]),
+			["Rock", "Pop", "Jazz", "Blues"]
+		);
+		assert!(split_genres(&[" ; "]).is_empty());
+
+		let untagged = track_genres_of("id", split_genres(&[]), "Unknown Genre");
+		assert_eq!(
+			(untagged.len(), untagged[0].genre.as_str(), untagged[0].position),
+			(1, "Unknown Genre", 0)
+		);
+		assert_eq!(track_genres_of("id", split_genres(&["Rock; Pop"]), "Rock").len(), 2);
+	}
+
+	#[test]
+	fn decades_are_parsed() {
+		assert_eq!(parse_decade("90s"), Some(1990));
+		assert_eq!(parse_decade("1990s"), Some(1990));
+		assert_eq!(parse_decade("2010"), Some(2010));
+		assert_eq!(parse_decade("'00s"), Some(2000));
+		assert_eq!(parse_decade("1995"), None);
+		assert_eq!(parse_decade("nineties"), None);
+	}
+
+	#[test]
+	fn content_type_is_told_by_the_tags() {
+		let mut tag = Tag::new();
+		assert_eq!(content_type_of(&tag), ContentType::Music);
+		tag.set_genre("Fantasy; Audiobook");
+		assert_eq!(content_type_of(&tag), ContentType::Audiobook);
+		tag.set_genre("Podcast");
+		assert_eq!(content_type_of(&tag), ContentType::Podcast);
+	}
+
+	#[test]
+	fn a_bad_file_only_takes_itself_out_of_the_batch() {
+		let test_app = crate::test_support::TestApp::seeded();
+		let mut db_conn = test_app.db_conn();
+		let scanned = |curr_music_id: Option<&str>, db_conn: &mut SqliteConnection| {
+			let mut curr_music = music.first::<Music>(db_conn).unwrap();
+			if let Some(curr_music_id) = curr_music_id {
+				curr_music.music_id = curr_music_id.to_string();
+			}
+			ScannedFile {
+				path: PathBuf::from(format!("{}.mp3", curr_music.music_id)),
+				genres: vec![TrackGenre {
+					music_id: curr_music.music_id.clone(),
+					genre: "Rock".to_string(),
+					position: 0,
+				}],
+				alt_names: Vec::new(),
+				chapters: Vec::new(),
+				mood: None,
+				music: curr_music,
+			}
+		};
+		// the one in the middle is already in the library
+		let batch = vec![
+			scanned(Some("scanned-1"), &mut db_conn),
+			scanned(None, &mut db_conn),
+			scanned(Some("scanned-2"), &mut db_conn),
+		];
+
+		let (mut saved, mut errors, mut steps) = (Vec::new(), Vec::new(), Vec::new());
+		save_batch(batch, &mut db_conn, &mut saved, &mut errors, &mut |step| {
+			steps.push(step)
+		});
+		let saved_ids: Vec<&str> = saved.iter().map(|curr_music| curr_music.music_id.as_str()).collect();
+		assert_eq!(saved_ids, ["scanned-1", "scanned-2"]);
+		assert_eq!(errors.len(), 1);
+		assert_eq!(steps[0], ScanStep::Saved);
+		assert!(matches!(steps[1], ScanStep::Failed(_)));
+		let genres = track_genres::table
+			.filter(track_genres::music_id.eq_any(["scanned-1", "scanned-2"]))
+			.count()
+			.get_result::<i64>(&mut db_conn)
+			.unwrap();
+		assert_eq!(genres, 2);
+	}
+
+	#[test]
+	fn curated_covers_are_left_alone_by_rescans() {
+		let curated = HashSet::from([cover_uuid("Artist", "Album").to_string()]);
+		// never read, the file isn't there
+		let missing = std::env::temp_dir().join(format!("lobic_missing_{}.mp3", Uuid::new_v4()));
+		let missing = missing.to_str().unwrap();
+		assert!(extract_cover_art(missing, "Artist", "Album", &curated).is_ok());
+		assert!(extract_cover_art(missing, "Artist", "Another Album", &curated).is_err());
+	}
+}
@@ -0,0 +1,656 @@
+use crate::config::{MAX_RELEASE_SCHEDULE_DAYS, PLAYLIST_COVER_IMG_STORAGE, PLAYLIST_TRASH_DAYS, PLAYLIST_UNDO_SECS};
+use crate::lobic_db::db::DatabasePool;
+use crate::lobic_db::models::{
+	Availability, Playlist, PlaylistRelease, PlaylistShare, PlaylistSong, PlaylistUndo, TrashedPlaylist,
+};
+use crate::schema::{
+	music, playlist_releases, playlist_shares, playlist_songs, playlist_trash, playlist_undo, playlists,
+};
+use crate::services::ServiceError;
+use crate::utils::precondition::etag;
+
+use axum::{
+	http::{header, StatusCode},
+	response::Response,
+};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+// Sent back along with a conflict, so the client can redo its edit on top of it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlaylistState {
+	pub playlist: Playlist,
+	pub songs: Vec<PlaylistSong>,
+	pub contributors: Vec<String>,
+}
+
+// A playlist of the user that has the track, the same track can be in it more than once
+#[derive(Debug, Serialize)]
+pub struct PlaylistMembership {
+	pub playlist_id: String,
+	pub playlist_name: String,
+	pub times: usize,
+}
+
+// What an undo puts back
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum UndoSnapshot {
+	Songs { songs: Vec<PlaylistSong> }, // taken out of a playlist that's still there
+	Playlist(PlaylistState),            // deleted along with everything in it
+}
+
+// Handed out with every removal, clear and delete
+#[derive(Debug, Serialize)]
+pub struct UndoToken {
+	pub token: String,
+	pub expires_date_time: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlaylistService {
+	db_pool: DatabasePool,
+	cover_storage: PathBuf,
+}
+
+impl PlaylistService {
+	pub fn new(db_pool: &DatabasePool) -> PlaylistService {
+		PlaylistService {
+			db_pool: db_pool.clone(),
+			cover_storage: PathBuf::from(PLAYLIST_COVER_IMG_STORAGE),
+		}
+	}
+
+	// Creates the playlist, the cover is only saved when given
+	pub fn create(
+		&self,
+		playlist_name: String,
+		user_id: String,
+		is_playlist_combined: bool,
+		cover_img: &[u8],
+	) -> Result<Playlist, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+
+		let playlist_id = Uuid::new_v4(); //now a user can create a playlist with the same name
+		let creation_date_time = Utc::now().to_rfc3339();
+		let new_playlist = Playlist {
+			playlist_id: playlist_id.to_string(),
+			playlist_name,
+			user_id,
+			creation_date_time: creation_date_time.clone(),
+			last_updated_date_time: creation_date_time,
+			is_playlist_combined,
+			availability: Availability::Available.as_str().to_string(),
+			availability_reason: None,
+			version: 1,
+		};
+
+		//save the image inside the storage
+		if !cover_img.is_empty() {
+			fs::create_dir_all(&self.cover_storage)
+				.map_err(|err| ServiceError::Internal(format!("Failed to create directory: {err}")))?;
+			let image_path = self.cover_storage.join(format!("{playlist_id}.png"));
+			fs::write(&image_path, cover_img)
+				.map_err(|err| ServiceError::Internal(format!("Failed to save image: {err}")))?;
+		}
+
+		diesel::insert_into(playlists::table)
+			.values(&new_playlist)
+			.execute(&mut db_conn)
+			.map_err(|err| ServiceError::Internal(format!("Failed to create playlist: {err}")))?;
+
+		Ok(new_playlist)
+	}
+
+	// Adds the song at the end of the playlist
+	pub fn add_song(
+		&self,
+		playlist_id: String,
+		music_id: String,
+		song_adder_id: String,
+		expected_version: i32,
+	) -> Result<i32, ServiceError> {
+		let ((), version) = self.versioned(&playlist_id, expected_version, |conn| {
+			let new_playlist_song = PlaylistSong {
+				playlist_id: playlist_id.clone(),
+				music_id,
+				song_added_date_time: Utc::now().to_rfc3339(),
+				song_adder_id,
+				position: next_position(&playlist_id, conn)?,
+			};
+			diesel::insert_into(playlist_songs::table)
+				.values(&new_playlist_song)
+				.execute(conn)
+				.map_err(|err| ServiceError::Internal(format!("Failed to add song to playlist: {err}")))?;
+			Ok(())
+		})?;
+		Ok(version)
+	}
+
+	// Where the song first shows up in the playlist, counting from 0
+	pub fn position_of(&self, playlist_id: &str, music_id: &str) -> Result<Option<usize>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let music_ids = playlist_songs::table
+			.filter(playlist_songs::playlist_id.eq(playlist_id))
+			.order(playlist_songs::position.asc())
+			.select(playlist_songs::music_id)
+			.load::<String>(&mut db_conn)?;
+		Ok(music_ids.iter().position(|id| id == music_id))
+	}
+
+	// Takes out every copy of the song
+	pub fn remove_song(
+		&self,
+		playlist_id: &str,
+		music_id: &str,
+		expected_version: i32,
+	) -> Result<(i32, UndoToken), ServiceError> {
+		let (undo, version) = self.versioned(playlist_id, expected_version, |conn| {
+			let songs = playlist_songs::table
+				.filter(playlist_songs::playlist_id.eq(playlist_id))
+				.filter(playlist_songs::music_id.eq(music_id))
+				.load::<PlaylistSong>(conn)?;
+			if songs.is_empty() {
+				return Err(ServiceError::NotFound(format!(
+					"song {music_id} NOT FOUND playlist {playlist_id}"
+				)));
+			}
+
+			diesel::delete(playlist_songs::table)
+				.filter(playlist_songs::music_id.eq(music_id))
+				.filter(playlist_songs::playlist_id.eq(playlist_id))
+				.execute(conn)
+				.map_err(|err| ServiceError::Internal(format!("Failed to remove song from playlist: {err}")))?;
+			save_undo(playlist_id, UndoSnapshot::Songs { songs }, conn)
+		})?;
+		Ok((version, undo))
+	}
+
+	// Takes every song out of the playlist, returns how many there were
+	pub fn clear(&self, playlist_id: &str, expected_version: i32) -> Result<(usize, i32, UndoToken), ServiceError> {
+		let ((songs_deleted, undo), version) = self.versioned(playlist_id, expected_version, |conn| {
+			let songs = playlist_songs::table
+				.filter(playlist_songs::playlist_id.eq(playlist_id))
+				.load::<PlaylistSong>(conn)?;
+			diesel::delete(playlist_songs::table)
+				.filter(playlist_songs::playlist_id.eq(playlist_id))
+				.execute(conn)
+				.map_err(|err| ServiceError::Internal(format!("Failed to clear playlist: {err}")))?;
+			Ok((
+				songs.len(),
+				save_undo(playlist_id, UndoSnapshot::Songs { songs }, conn)?,
+			))
+		})?;
+		Ok((songs_deleted, version, undo))
+	}
+
+	// Deletes the playlist along with its songs and shares, returns how many songs and shares went with it. The
+	// playlist goes to the trash of its owner.
+	pub fn delete(
+		&self,
+		playlist_id: &str,
+		expected_version: i32,
+	) -> Result<((usize, usize), UndoToken), ServiceError> {
+		let (deleted, _) = self.versioned(playlist_id, expected_version, |conn| {
+			let state = load_state(playlist_id, conn)?;
+			save_trash(&state, conn)?;
+			let undo = save_undo(playlist_id, UndoSnapshot::Playlist(state), conn)?;
+
+			let songs_deleted = diesel::delete(playlist_songs::table)
+				.filter(playlist_songs::playlist_id.eq(playlist_id))
+				.execute(conn)
+				.map_err(|err| ServiceError::Internal(format!("Failed to delete playlist songs: {err}")))?;
+
+			let shares_deleted = diesel::delete(playlist_shares::table)
+				.filter(playlist_shares::playlist_id.eq(playlist_id))
+				.execute(conn)
+				.map_err(|err| ServiceError::Internal(format!("Failed to delete playlist shares: {err}")))?;
+
+			diesel::delete(playlists::table)
+				.filter(playlists::playlist_id.eq(playlist_id))
+				.execute(conn)
+				.map_err(|err| ServiceError::Internal(format!("Failed to delete playlist: {err}")))?;
+			Ok(((songs_deleted, shares_deleted), undo))
+		})?;
+		Ok(deleted)
+	}
+
+	// Puts back what the removal, clear or delete behind the token took away. Tokens work once and only until
+	// they expire, the playlist moves on to a new version like with any other edit.
+	pub fn undo(&self, token: &str) -> Result<PlaylistState, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let playlist_id = db_conn.transaction(|conn| {
+			let now = Utc::now().to_rfc3339();
+			let undo = playlist_undo::table
+				.find(token)
+				.first::<PlaylistUndo>(conn)
+				.optional()?
+				.filter(|undo| undo.expires_date_time > now)
+				.ok_or_else(|| ServiceError::NotFound("The undo token expired or was already used".to_string()))?;
+			diesel::delete(playlist_undo::table.find(token)).execute(conn)?;
+			let snapshot = serde_json::from_str::<UndoSnapshot>(&undo.snapshot)
+				.map_err(|err| ServiceError::Internal(format!("Failed to read the undo snapshot: {err}")))?;
+
+			match snapshot {
+				UndoSnapshot::Songs { songs } => {
+					let bumped = diesel::update(playlists::table.find(&undo.playlist_id))
+						.set((
+							playlists::version.eq(playlists::version + 1),
+							playlists::last_updated_date_time.eq(&now),
+						))
+						.execute(conn)?;
+					if bumped == 0 {
+						return Err(ServiceError::NotFound(format!(
+							"Playlist {} was deleted since",
+							undo.playlist_id
+						)));
+					}
+					// Back where they were, or at the end when something was added there since
+					for mut song in songs {
+						let taken = playlist_songs::table
+							.find((&song.playlist_id, song.position))
+							.count()
+							.get_result::<i64>(conn)?
+							> 0;
+						if taken {
+							song.position = next_position(&song.playlist_id, conn)?;
+						}
+						diesel::insert_into(playlist_songs::table).values(&song).execute(conn)?;
+					}
+				}
+				UndoSnapshot::Playlist(state) => restore_state(state, conn)?,
+			}
+			Ok::<_, ServiceError>(undo.playlist_id)
+		})?;
+		drop(db_conn);
+		self.state(&playlist_id)
+	}
+
+	// The playlists the user sees that already have the track, in the order they are listed
+	pub fn membership(&self, user_id: &str, music_id: &str) -> Result<Vec<PlaylistMembership>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let exists = music::table.find(music_id).count().get_result::<i64>(&mut db_conn)? > 0;
+		if !exists {
+			return Err(ServiceError::NotFound(format!("No music: {music_id}")));
+		}
+
+		let visible = visible_playlists(user_id, &mut db_conn)?;
+		let containing = playlist_songs::table
+			.filter(playlist_songs::music_id.eq(music_id))
+			.filter(playlist_songs::playlist_id.eq_any(visible.iter().map(|playlist| &playlist.playlist_id)))
+			.select(playlist_songs::playlist_id)
+			.load::<String>(&mut db_conn)?;
+		Ok(visible
+			.into_iter()
+			.map(|playlist| PlaylistMembership {
+				times: containing.iter().filter(|id| **id == playlist.playlist_id).count(),
+				playlist_id: playlist.playlist_id,
+				playlist_name: playlist.playlist_name,
+			})
+			.filter(|membership| membership.times > 0)
+			.collect())
+	}
+
+	// The deleted playlists of the owner, most recently deleted first
+	pub fn trash(&self, user_id: &str) -> Result<Vec<TrashedPlaylist>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		Ok(playlist_trash::table
+			.filter(playlist_trash::user_id.eq(user_id))
+			.filter(playlist_trash::purge_date_time.gt(Utc::now().to_rfc3339()))
+			.order(playlist_trash::deleted_date_time.desc())
+			.load::<TrashedPlaylist>(&mut db_conn)?)
+	}
+
+	// Brings the playlist back out of the trash the way an undo of the delete would
+	pub fn restore(&self, user_id: &str, playlist_id: &str) -> Result<PlaylistState, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		db_conn.transaction(|conn| {
+			let trashed = find_trashed(user_id, playlist_id, conn)?;
+			let state = serde_json::from_str::<PlaylistState>(&trashed.snapshot)
+				.map_err(|err| ServiceError::Internal(format!("Failed to read the trashed playlist: {err}")))?;
+			restore_state(state, conn)
+		})?;
+		drop(db_conn);
+		self.state(playlist_id)
+	}
+
+	// Deletes the playlist for good, its cover and undo snapshots go with it
+	pub fn purge(&self, user_id: &str, playlist_id: &str) -> Result<(), ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		db_conn.transaction(|conn| {
+			find_trashed(user_id, playlist_id, conn)?;
+			diesel::delete(playlist_trash::table.find(playlist_id)).execute(conn)?;
+			diesel::delete(playlist_undo::table.filter(playlist_undo::playlist_id.eq(playlist_id))).execute(conn)?;
+			diesel::delete(playlist_releases::table.find(playlist_id)).execute(conn)?;
+			Ok::<_, ServiceError>(())
+		})?;
+		let _ = fs::remove_file(self.cover_storage.join(format!("{playlist_id}.png")));
+		Ok(())
+	}
+
+	// Keeps the owner's playlist private until the time, a new time replaces the one scheduled before. A deleted
+	// playlist keeps its release, it goes out once restored.
+	pub fn schedule_release(
+		&self,
+		user_id: &str,
+		playlist_id: &str,
+		release_date_time: &str,
+	) -> Result<PlaylistRelease, ServiceError> {
+		let release_at = DateTime::parse_from_rfc3339(release_date_time)
+			.map_err(|_| ServiceError::BadRequest(format!("Invalid release_date_time: {release_date_time}")))?
+			.with_timezone(&Utc);
+		let now = Utc::now();
+		if release_at <= now {
+			return Err(ServiceError::BadRequest(
+				"The release has to be in the future".to_string(),
+			));
+		}
+		if release_at > now + Duration::days(MAX_RELEASE_SCHEDULE_DAYS) {
+			return Err(ServiceError::BadRequest(format!(
+				"A release can be scheduled at most {MAX_RELEASE_SCHEDULE_DAYS} days ahead"
+			)));
+		}
+
+		let mut db_conn = self.db_pool.get()?;
+		db_conn.transaction(|conn| {
+			let playlist = owned_playlist(user_id, playlist_id, conn)?;
+			if playlist.availability == Availability::TakenDown.as_str() {
+				return Err(ServiceError::Forbidden(format!(
+					"Playlist {playlist_id} was taken down"
+				)));
+			}
+			let scheduled = playlist_releases::table
+				.find(playlist_id)
+				.first::<PlaylistRelease>(conn)
+				.optional()?;
+			let release = PlaylistRelease {
+				playlist_id: playlist_id.to_string(),
+				user_id: user_id.to_string(),
+				release_date_time: release_at.to_rfc3339(),
+				previous_availability: match scheduled {
+					Some(scheduled) => scheduled.previous_availability,
+					None => playlist.availability,
+				},
+				scheduled_date_time: now.to_rfc3339(),
+			};
+			diesel::replace_into(playlist_releases::table)
+				.values(&release)
+				.execute(conn)?;
+			diesel::update(playlists::table.find(playlist_id))
+				.set((
+					playlists::availability.eq(Availability::Private.as_str()),
+					playlists::availability_reason.eq(Some("Scheduled for release")),
+				))
+				.execute(conn)?;
+			Ok(release)
+		})
+	}
+
+	// The owner's upcoming releases, the soonest first
+	pub fn releases(&self, user_id: &str) -> Result<Vec<PlaylistRelease>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		Ok(playlist_releases::table
+			.filter(playlist_releases::user_id.eq(user_id))
+			.order(playlist_releases::release_date_time.asc())
+			.load::<PlaylistRelease>(&mut db_conn)?)
+	}
+
+	// Calls the release off, the playlist is as visible as it was before it got scheduled
+	pub fn cancel_release(&self, user_id: &str, playlist_id: &str) -> Result<(), ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		db_conn.transaction(|conn| {
+			let release = playlist_releases::table
+				.find(playlist_id)
+				.filter(playlist_releases::user_id.eq(user_id))
+				.first::<PlaylistRelease>(conn)
+				.optional()?
+				.ok_or_else(|| ServiceError::NotFound(format!("No release scheduled for playlist {playlist_id}")))?;
+			diesel::delete(playlist_releases::table.find(playlist_id)).execute(conn)?;
+			diesel::update(playlists::table.find(playlist_id))
+				.filter(playlists::availability.eq(Availability::Private.as_str()))
+				.set((
+					playlists::availability.eq(release.previous_availability),
+					playlists::availability_reason.eq(None::<String>),
+				))
+				.execute(conn)?;
+			Ok(())
+		})
+	}
+
+	// Runs the edit only if the playlist is still at the version the client saw, and moves it to the next one.
+	// Both happen in one transaction, so of two edits made against the same version only the first goes through.
+	pub fn versioned<T>(
+		&self,
+		playlist_id: &str,
+		expected_version: i32,
+		edit: impl FnOnce(&mut SqliteConnection) -> Result<T, ServiceError>,
+	) -> Result<(T, i32), ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		db_conn.transaction(|conn| {
+			let bumped = diesel::update(playlists::table)
+				.filter(playlists::playlist_id.eq(playlist_id))
+				.filter(playlists::version.eq(expected_version))
+				.set((
+					playlists::version.eq(playlists::version + 1),
+					playlists::last_updated_date_time.eq(Utc::now().to_rfc3339()),
+				))
+				.execute(conn)?;
+			if bumped == 0 {
+				let current = playlists::table
+					.find(playlist_id)
+					.select(playlists::version)
+					.first::<i32>(conn)
+					.optional()?;
+				return Err(match current {
+					Some(current) => ServiceError::Conflict(format!(
+						"Playlist {playlist_id} is at version {current}, the edit was made against {expected_version}"
+					)),
+					None => ServiceError::NotFound(format!("No playlist: {playlist_id}")),
+				});
+			}
+			Ok((edit(conn)?, expected_version + 1))
+		})
+	}
+
+	pub fn state(&self, playlist_id: &str) -> Result<PlaylistState, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		load_state(playlist_id, &mut db_conn)
+	}
+
+	// The response for a failed edit, a conflict comes with the playlist as it is now
+	pub fn error_response(&self, playlist_id: &str, err: ServiceError) -> Response<String> {
+		let ServiceError::Conflict(message) = &err else {
+			return err.into_response();
+		};
+		let state = match self.state(playlist_id) {
+			Ok(state) => state,
+			Err(err) => return err.into_response(),
+		};
+		let version = state.playlist.version;
+		let body = serde_json::json!({ "message": message, "current": state });
+		Response::builder()
+			.status(StatusCode::CONFLICT)
+			.header(header::CONTENT_TYPE, "application/json")
+			.header(header::ETAG, etag(version))
+			.body(body.to_string())
+			.unwrap()
+	}
+}
+
+// Owned and shared with the user, like get_users_playlists lists them to the user themselves
+pub fn visible_playlists(user_id: &str, db_conn: &mut SqliteConnection) -> QueryResult<Vec<Playlist>> {
+	playlists::table
+		.left_join(playlist_shares::table.on(playlists::playlist_id.eq(playlist_shares::playlist_id)))
+		.filter(
+			playlists::user_id
+				.eq(user_id)
+				.or(playlist_shares::contributor_user_id.eq(user_id)),
+		)
+		.filter(
+			playlists::availability
+				.eq(Availability::Available.as_str())
+				.or(playlists::availability
+					.eq(Availability::Private.as_str())
+					.and(playlists::user_id.eq(user_id))),
+		)
+		.select(playlists::all_columns)
+		.distinct()
+		.order(playlists::creation_date_time.asc())
+		.load::<Playlist>(db_conn)
+}
+
+fn load_state(playlist_id: &str, conn: &mut SqliteConnection) -> Result<PlaylistState, ServiceError> {
+	let playlist = playlists::table
+		.find(playlist_id)
+		.first::<Playlist>(conn)
+		.optional()?
+		.ok_or_else(|| ServiceError::NotFound(format!("No playlist: {playlist_id}")))?;
+	let songs = playlist_songs::table
+		.filter(playlist_songs::playlist_id.eq(playlist_id))
+		.order(playlist_songs::position.asc())
+		.load::<PlaylistSong>(conn)?;
+	let contributors = playlist_shares::table
+		.filter(playlist_shares::playlist_id.eq(playlist_id))
+		.select(playlist_shares::contributor_user_id)
+		.load::<String>(conn)?;
+	Ok(PlaylistState {
+		playlist,
+		songs,
+		contributors,
+	})
+}
+
+fn next_position(playlist_id: &str, conn: &mut SqliteConnection) -> QueryResult<i32> {
+	let last = playlist_songs::table
+		.filter(playlist_songs::playlist_id.eq(playlist_id))
+		.select(diesel::dsl::max(playlist_songs::position))
+		.first::<Option<i32>>(conn)?;
+	Ok(last.map_or(0, |last| last + 1))
+}
+
+// Puts a deleted playlist back at the next version, it leaves the trash
+fn restore_state(state: PlaylistState, conn: &mut SqliteConnection) -> Result<(), ServiceError> {
+	let playlist_id = state.playlist.playlist_id.clone();
+	let exists = playlists::table.find(&playlist_id).count().get_result::<i64>(conn)? > 0;
+	if exists {
+		return Err(ServiceError::Conflict(format!("Playlist {playlist_id} already exists")));
+	}
+	let playlist = Playlist {
+		version: state.playlist.version + 1,
+		last_updated_date_time: Utc::now().to_rfc3339(),
+		..state.playlist
+	};
+	let shares: Vec<PlaylistShare> = state
+		.contributors
+		.into_iter()
+		.map(|contributor_user_id| PlaylistShare {
+			playlist_id: playlist_id.clone(),
+			contributor_user_id,
+		})
+		.collect();
+	diesel::insert_into(playlists::table).values(&playlist).execute(conn)?;
+	diesel::insert_into(playlist_songs::table)
+		.values(&state.songs)
+		.execute(conn)?;
+	diesel::insert_into(playlist_shares::table)
+		.values(&shares)
+		.execute(conn)?;
+	diesel::delete(playlist_trash::table.find(&playlist_id)).execute(conn)?;
+	Ok(())
+}
+
+fn save_trash(state: &PlaylistState, conn: &mut SqliteConnection) -> Result<(), ServiceError> {
+	let now = Utc::now();
+	let trashed = TrashedPlaylist {
+		playlist_id: state.playlist.playlist_id.clone(),
+		user_id: state.playlist.user_id.clone(),
+		playlist_name: state.playlist.playlist_name.clone(),
+		song_count: state.songs.len() as i32,
+		snapshot: serde_json::to_string(state)
+			.map_err(|err| ServiceError::Internal(format!("Failed to save the trashed playlist: {err}")))?,
+		deleted_date_time: now.to_rfc3339(),
+		purge_date_time: (now + Duration::days(PLAYLIST_TRASH_DAYS)).to_rfc3339(),
+	};
+	diesel::replace_into(playlist_trash::table)
+		.values(&trashed)
+		.execute(conn)?;
+	Ok(())
+}
+
+fn owned_playlist(user_id: &str, playlist_id: &str, conn: &mut SqliteConnection) -> Result<Playlist, ServiceError> {
+	playlists::table
+		.find(playlist_id)
+		.filter(playlists::user_id.eq(user_id))
+		.first::<Playlist>(conn)
+		.optional()?
+		.ok_or_else(|| ServiceError::NotFound(format!("No playlist {playlist_id} of yours")))
+}
+
+// Only the owner sees their trash, the purged ones are gone even before the pruning gets to them
+fn find_trashed(
+	user_id: &str,
+	playlist_id: &str,
+	conn: &mut SqliteConnection,
+) -> Result<TrashedPlaylist, ServiceError> {
+	playlist_trash::table
+		.find(playlist_id)
+		.filter(playlist_trash::user_id.eq(user_id))
+		.filter(playlist_trash::purge_date_time.gt(Utc::now().to_rfc3339()))
+		.first::<TrashedPlaylist>(conn)
+		.optional()?
+		.ok_or_else(|| ServiceError::NotFound(format!("No playlist {playlist_id} in the trash")))
+}
+
+// Keeps what the edit took away, in the edit's own transaction
+fn save_undo(
+	playlist_id: &str,
+	snapshot: UndoSnapshot,
+	conn: &mut SqliteConnection,
+) -> Result<UndoToken, ServiceError> {
+	let undo = PlaylistUndo {
+		token: Uuid::new_v4().to_string(),
+		playlist_id: playlist_id.to_string(),
+		snapshot: serde_json::to_string(&snapshot)
+			.map_err(|err| ServiceError::Internal(format!("Failed to save the undo snapshot: {err}")))?,
+		expires_date_time: (Utc::now() + Duration::seconds(PLAYLIST_UNDO_SECS)).to_rfc3339(),
+	};
+	diesel::insert_into(playlist_undo::table).values(&undo).execute(conn)?;
+	Ok(UndoToken {
+		token: undo.token,
+		expires_date_time: undo.expires_date_time,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support::TestApp;
+
+	#[test]
+	fn covers_are_saved_with_the_playlist_and_purged_with_it() {
+		let test_app = TestApp::seeded();
+		let user_id = test_app.user_id("seed_user_0");
+		let cover_storage = std::env::temp_dir().join(format!("lobic_playlist_covers_{}", Uuid::new_v4()));
+		let service = PlaylistService {
+			db_pool: test_app.app_state.db_pool.clone(),
+			cover_storage: cover_storage.clone(),
+		};
+
+		let cover = vec![7u8; 1024];
+		let playlist = service
+			.create("Covered".to_string(), user_id.clone(), false, &cover)
+			.unwrap();
+		let saved = cover_storage.join(format!("{}.png", playlist.playlist_id));
+		let saved_cover = fs::read(&saved);
+		service.delete(&playlist.playlist_id, 1).unwrap();
+		service.purge(&user_id, &playlist.playlist_id).unwrap();
+		let purged = !saved.exists();
+		fs::remove_dir_all(&cover_storage).unwrap();
+		assert_eq!(saved_cover.unwrap(), cover);
+		assert!(purged);
+	}
+}
@@ -0,0 +1,347 @@
+use crate::config::{MAX_FOLDER_DEPTH, MAX_PLAYLIST_FOLDERS};
+use crate::lobic_db::db::DatabasePool;
+use crate::lobic_db::models::{Playlist, PlaylistFolder, PlaylistFolderItem, PlaylistInfo, SavedSearch};
+use crate::schema::{playlist_folder_items, playlist_folders, saved_searches};
+use crate::services::playlist::visible_playlists;
+use crate::services::ServiceError;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+// A folder with what's in it, folders first. The top of the tree has no id.
+#[derive(Debug, Serialize)]
+pub struct FolderTree {
+	pub folder_id: Option<String>,
+	pub name: String,
+	pub folders: Vec<FolderTree>,
+	pub playlists: Vec<PlaylistInfo>,
+	pub saved_searches: Vec<SavedSearch>, // only at the top, they don't go in folders
+}
+
+#[derive(Debug, Clone)]
+pub struct PlaylistFolderService {
+	db_pool: DatabasePool,
+}
+
+impl PlaylistFolderService {
+	pub fn new(db_pool: &DatabasePool) -> PlaylistFolderService {
+		PlaylistFolderService {
+			db_pool: db_pool.clone(),
+		}
+	}
+
+	// Every playlist the user sees in its folder, the ones taken down are left out. The saved searches
+	// of the user are listed at the top next to them.
+	pub fn tree(&self, user_id: &str) -> Result<FolderTree, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let folders = user_folders(user_id, &mut db_conn)?;
+		let items = user_items(user_id, &mut db_conn)?;
+		let mut visible = visible_playlists(user_id, &mut db_conn)?;
+
+		let mut tree = build_tree(None, String::new(), &folders, &items, &mut visible);
+		// the ones not in a folder of the user
+		tree.playlists.extend(visible.into_iter().map(playlist_info));
+		tree.saved_searches = saved_searches::table
+			.filter(saved_searches::user_id.eq(user_id))
+			.order(saved_searches::created_date_time.asc())
+			.load::<SavedSearch>(&mut db_conn)?;
+		Ok(tree)
+	}
+
+	pub fn create(&self, user_id: &str, name: &str, parent_id: Option<&str>) -> Result<PlaylistFolder, ServiceError> {
+		let name = check_name(name)?;
+		let mut db_conn = self.db_pool.get()?;
+		let folders = user_folders(user_id, &mut db_conn)?;
+		if folders.len() >= MAX_PLAYLIST_FOLDERS {
+			return Err(ServiceError::BadRequest(format!(
+				"At most {MAX_PLAYLIST_FOLDERS} folders, delete one first"
+			)));
+		}
+		if let Some(parent_id) = parent_id {
+			find(&folders, parent_id)?;
+			if depth(&folders, parent_id) + 1 > MAX_FOLDER_DEPTH {
+				return Err(ServiceError::BadRequest(format!(
+					"Folders can only go {MAX_FOLDER_DEPTH} deep"
+				)));
+			}
+		}
+
+		let folder = PlaylistFolder {
+			folder_id: Uuid::new_v4().to_string(),
+			user_id: user_id.to_string(),
+			name,
+			parent_id: parent_id.map(String::from),
+			position: children(&folders, parent_id).len() as i32,
+			created_date_time: Utc::now().to_rfc3339(),
+		};
+		diesel::insert_into(playlist_folders::table)
+			.values(&folder)
+			.execute(&mut db_conn)?;
+		Ok(folder)
+	}
+
+	pub fn rename(&self, user_id: &str, folder_id: &str, name: &str) -> Result<FolderTree, ServiceError> {
+		let name = check_name(name)?;
+		let mut db_conn = self.db_pool.get()?;
+		find(&user_folders(user_id, &mut db_conn)?, folder_id)?;
+		diesel::update(playlist_folders::table.find(folder_id))
+			.set(playlist_folders::name.eq(name))
+			.execute(&mut db_conn)?;
+		drop(db_conn);
+		self.tree(user_id)
+	}
+
+	// Into another folder or the top, at the position among the folders there. Last when left out.
+	pub fn move_folder(
+		&self,
+		user_id: &str,
+		folder_id: &str,
+		parent_id: Option<&str>,
+		position: Option<usize>,
+	) -> Result<FolderTree, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let folders = user_folders(user_id, &mut db_conn)?;
+		let folder = find(&folders, folder_id)?.clone();
+		if let Some(parent_id) = parent_id {
+			find(&folders, parent_id)?;
+			if is_within(&folders, parent_id, folder_id) {
+				return Err(ServiceError::BadRequest(
+					"A folder can't go into itself or one of its folders".to_string(),
+				));
+			}
+			if depth(&folders, parent_id) + height(&folders, folder_id) > MAX_FOLDER_DEPTH {
+				return Err(ServiceError::BadRequest(format!(
+					"Folders can only go {MAX_FOLDER_DEPTH} deep"
+				)));
+			}
+		}
+
+		let mut siblings: Vec<PlaylistFolder> = children(&folders, parent_id)
+			.into_iter()
+			.filter(|sibling| sibling.folder_id != folder_id)
+			.cloned()
+			.collect();
+		let index = position.unwrap_or(siblings.len()).min(siblings.len());
+		siblings.insert(
+			index,
+			PlaylistFolder {
+				parent_id: parent_id.map(String::from),
+				..folder
+			},
+		);
+		db_conn.transaction::<_, diesel::result::Error, _>(|conn| {
+			for (position, sibling) in siblings.iter().enumerate() {
+				diesel::update(playlist_folders::table.find(&sibling.folder_id))
+					.set((
+						playlist_folders::parent_id.eq(&sibling.parent_id),
+						playlist_folders::position.eq(position as i32),
+					))
+					.execute(conn)?;
+			}
+			Ok(())
+		})?;
+		drop(db_conn);
+		self.tree(user_id)
+	}
+
+	// What was in the folder goes to its parent, after what's already there
+	pub fn delete(&self, user_id: &str, folder_id: &str) -> Result<FolderTree, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let folders = user_folders(user_id, &mut db_conn)?;
+		let items = user_items(user_id, &mut db_conn)?;
+		let folder = find(&folders, folder_id)?;
+		let parent_id = folder.parent_id.as_deref();
+
+		let folders_after = children(&folders, parent_id).len() - 1;
+		let items_after = items
+			.iter()
+			.filter(|item| item.folder_id.as_deref() == parent_id)
+			.count();
+		db_conn.transaction::<_, diesel::result::Error, _>(|conn| {
+			for (offset, child) in children(&folders, Some(folder_id)).into_iter().enumerate() {
+				diesel::update(playlist_folders::table.find(&child.folder_id))
+					.set((
+						playlist_folders::parent_id.eq(parent_id),
+						playlist_folders::position.eq((folders_after + offset) as i32),
+					))
+					.execute(conn)?;
+			}
+			let moved = items.iter().filter(|item| item.folder_id.as_deref() == Some(folder_id));
+			for (offset, item) in moved.enumerate() {
+				diesel::update(playlist_folder_items::table.find((user_id, &item.playlist_id)))
+					.set((
+						playlist_folder_items::folder_id.eq(parent_id),
+						playlist_folder_items::position.eq((items_after + offset) as i32),
+					))
+					.execute(conn)?;
+			}
+			diesel::delete(playlist_folders::table.find(folder_id)).execute(conn)?;
+			Ok(())
+		})?;
+		drop(db_conn);
+		self.tree(user_id)
+	}
+
+	// Into a folder or the top, at the position among the playlists there. Last when left out.
+	pub fn move_playlist(
+		&self,
+		user_id: &str,
+		playlist_id: &str,
+		folder_id: Option<&str>,
+		position: Option<usize>,
+	) -> Result<FolderTree, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let folders = user_folders(user_id, &mut db_conn)?;
+		if let Some(folder_id) = folder_id {
+			find(&folders, folder_id)?;
+		}
+		let visible = visible_playlists(user_id, &mut db_conn)?;
+		if !visible.iter().any(|playlist| playlist.playlist_id == playlist_id) {
+			return Err(ServiceError::NotFound(format!("No playlist: {playlist_id}")));
+		}
+		let items = user_items(user_id, &mut db_conn)?;
+
+		// the order of the folder as it's shown, the top also has the playlists never moved
+		let mut order: Vec<&str> = items
+			.iter()
+			.filter(|item| item.folder_id.as_deref() == folder_id)
+			.map(|item| item.playlist_id.as_str())
+			.collect();
+		if folder_id.is_none() {
+			let placed = |id: &str| items.iter().any(|item| item.playlist_id == id);
+			order.extend(
+				visible
+					.iter()
+					.map(|playlist| playlist.playlist_id.as_str())
+					.filter(|id| !placed(id)),
+			);
+		}
+		order.retain(|id| *id != playlist_id);
+		let index = position.unwrap_or(order.len()).min(order.len());
+		order.insert(index, playlist_id);
+
+		let items: Vec<PlaylistFolderItem> = order
+			.into_iter()
+			.enumerate()
+			.map(|(position, id)| PlaylistFolderItem {
+				user_id: user_id.to_string(),
+				playlist_id: id.to_string(),
+				folder_id: folder_id.map(String::from),
+				position: position as i32,
+			})
+			.collect();
+		diesel::replace_into(playlist_folder_items::table)
+			.values(&items)
+			.execute(&mut db_conn)?;
+		drop(db_conn);
+		self.tree(user_id)
+	}
+}
+
+fn user_folders(user_id: &str, db_conn: &mut SqliteConnection) -> QueryResult<Vec<PlaylistFolder>> {
+	playlist_folders::table
+		.filter(playlist_folders::user_id.eq(user_id))
+		.order(playlist_folders::position.asc())
+		.load::<PlaylistFolder>(db_conn)
+}
+
+fn user_items(user_id: &str, db_conn: &mut SqliteConnection) -> QueryResult<Vec<PlaylistFolderItem>> {
+	playlist_folder_items::table
+		.filter(playlist_folder_items::user_id.eq(user_id))
+		.order(playlist_folder_items::position.asc())
+		.load::<PlaylistFolderItem>(db_conn)
+}
+
+// Takes the playlists it shows out of visible
+fn build_tree(
+	folder_id: Option<&str>,
+	name: String,
+	folders: &[PlaylistFolder],
+	items: &[PlaylistFolderItem],
+	visible: &mut Vec<Playlist>,
+) -> FolderTree {
+	let mut playlists = Vec::new();
+	for item in items.iter().filter(|item| item.folder_id.as_deref() == folder_id) {
+		// playlists deleted or no longer shared keep their row until moved again
+		if let Some(index) = visible
+			.iter()
+			.position(|playlist| playlist.playlist_id == item.playlist_id)
+		{
+			playlists.push(playlist_info(visible.remove(index)));
+		}
+	}
+	let folders = children(folders, folder_id)
+		.into_iter()
+		.map(|child| build_tree(Some(&child.folder_id), child.name.clone(), folders, items, visible))
+		.collect();
+	FolderTree {
+		folder_id: folder_id.map(String::from),
+		name,
+		folders,
+		playlists,
+		saved_searches: Vec::new(),
+	}
+}
+
+fn playlist_info(playlist: Playlist) -> PlaylistInfo {
+	PlaylistInfo {
+		playlist_id: playlist.playlist_id,
+		user_id: playlist.user_id,
+		playlist_name: playlist.playlist_name,
+		creation_date_time: playlist.creation_date_time,
+		last_updated_date_time: playlist.last_updated_date_time,
+		is_playlist_combined: playlist.is_playlist_combined,
+	}
+}
+
+fn check_name(name: &str) -> Result<String, ServiceError> {
+	match name.trim() {
+		"" => Err(ServiceError::BadRequest("Name cannot be empty".to_string())),
+		name => Ok(name.to_string()),
+	}
+}
+
+fn find<'a>(folders: &'a [PlaylistFolder], folder_id: &str) -> Result<&'a PlaylistFolder, ServiceError> {
+	folders
+		.iter()
+		.find(|folder| folder.folder_id == folder_id)
+		.ok_or_else(|| ServiceError::NotFound(format!("No folder: {folder_id}")))
+}
+
+// In position order, the folders are loaded sorted
+fn children<'a>(folders: &'a [PlaylistFolder], parent_id: Option<&str>) -> Vec<&'a PlaylistFolder> {
+	folders
+		.iter()
+		.filter(|folder| folder.parent_id.as_deref() == parent_id)
+		.collect()
+}
+
+// 1 for the folders at the top
+fn depth(folders: &[PlaylistFolder], folder_id: &str) -> usize {
+	let parent = folders
+		.iter()
+		.find(|folder| folder.folder_id == folder_id)
+		.and_then(|folder| folder.parent_id.as_deref());
+	1 + parent.map_or(0, |parent_id| depth(folders, parent_id))
+}
+
+// 1 for a folder without folders in it
+fn height(folders: &[PlaylistFolder], folder_id: &str) -> usize {
+	1 + children(folders, Some(folder_id))
+		.iter()
+		.map(|child| height(folders, &child.folder_id))
+		.max()
+		.unwrap_or(0)
+}
+
+// Whether the folder is the ancestor or one of its folders
+fn is_within(folders: &[PlaylistFolder], folder_id: &str, ancestor_id: &str) -> bool {
+	folder_id == ancestor_id
+		|| folders
+			.iter()
+			.find(|folder| folder.folder_id == folder_id)
+			.and_then(|folder| folder.parent_id.as_deref())
+			.is_some_and(|parent_id| is_within(folders, parent_id, ancestor_id))
+}
@@ -0,0 +1,402 @@
+use crate::config::{ANTHEM_PREVIEW_SECS, MAX_PROFILE_PINS};
+use crate::lobic_db::db::DatabasePool;
+use crate::lobic_db::models::{
+	Availability, BlockedContent, ContentType, Music, MusicResponse, ProfileAnthem, ProfilePin,
+};
+use crate::schema::{blocked_content, music, playlist_songs, playlists, profile_anthems, profile_pins};
+use crate::services::ServiceError;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PinTarget {
+	Playlist,
+	Album,
+	Track,
+}
+
+impl PinTarget {
+	pub fn parse(target: &str) -> Option<PinTarget> {
+		match target {
+			"playlist" => Some(PinTarget::Playlist),
+			"album" => Some(PinTarget::Album),
+			"track" => Some(PinTarget::Track),
+			_ => None,
+		}
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			PinTarget::Playlist => "playlist",
+			PinTarget::Album => "album",
+			PinTarget::Track => "track",
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockTarget {
+	Track,
+	Artist, // every track of it
+}
+
+impl BlockTarget {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			BlockTarget::Track => "track",
+			BlockTarget::Artist => "artist",
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockedContentResponse {
+	pub tracks: Vec<MusicResponse>, // the ones taken down or removed are left out
+	pub artists: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "target_type", rename_all = "snake_case")]
+pub enum PinnedItem {
+	Playlist {
+		playlist_id: String,
+		playlist_name: String,
+		owner_id: String,
+		song_count: i64,
+	},
+	Album {
+		album: String,
+		artist: String,
+		image_url: String, // cover of its first track
+		song_count: i64,
+	},
+	Track(MusicResponse),
+}
+
+#[derive(Debug, Serialize)]
+pub struct PinResponse {
+	pub position: i32,
+	#[serde(flatten)]
+	pub item: PinnedItem,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnthemResponse {
+	pub user_id: String,
+	pub track: MusicResponse,
+	pub start: f64, // seconds into the track
+	pub end: f64,   // where the preview clip stops
+	pub preview_url: String,
+}
+
+impl AnthemResponse {
+	fn new(anthem: ProfileAnthem, track: Music) -> AnthemResponse {
+		let start = anthem.start_secs.min(track.duration as f64);
+		AnthemResponse {
+			preview_url: format!("/user/anthem/{}/preview", anthem.user_id),
+			user_id: anthem.user_id,
+			start,
+			end: (start + ANTHEM_PREVIEW_SECS).min(track.duration as f64),
+			track: Music::create_music_response(track),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfileService {
+	db_pool: DatabasePool,
+}
+
+impl ProfileService {
+	pub fn new(db_pool: &DatabasePool) -> ProfileService {
+		ProfileService {
+			db_pool: db_pool.clone(),
+		}
+	}
+
+	// The pins in order, those whose target was taken down or removed are left out
+	pub fn pins(&self, user_id: &str) -> Result<Vec<PinResponse>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let pins = ordered_pins(user_id, &mut db_conn)?;
+
+		let mut responses = Vec::new();
+		for pin in pins {
+			let Some(target) = PinTarget::parse(&pin.target_type) else {
+				continue;
+			};
+			if let Some(item) = resolve(target, &pin.target_id, &mut db_conn)? {
+				responses.push(PinResponse {
+					position: pin.position,
+					item,
+				});
+			}
+		}
+		Ok(responses)
+	}
+
+	// Pins the target at the position, or moves it there when it's already pinned. Left out, it goes last.
+	pub fn pin(
+		&self,
+		user_id: &str,
+		target: PinTarget,
+		target_id: &str,
+		position: Option<usize>,
+	) -> Result<Vec<PinResponse>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		if resolve(target, target_id, &mut db_conn)?.is_none() {
+			return Err(ServiceError::NotFound(format!("No {}: {target_id}", target.as_str())));
+		}
+
+		let mut pins = ordered_pins(user_id, &mut db_conn)?;
+		let existing = pins
+			.iter()
+			.position(|pin| pin.target_type == target.as_str() && pin.target_id == target_id);
+		let pin = match existing {
+			Some(index) => pins.remove(index),
+			None if pins.len() >= MAX_PROFILE_PINS => {
+				return Err(ServiceError::BadRequest(format!(
+					"At most {MAX_PROFILE_PINS} items can be pinned, unpin one first"
+				)));
+			}
+			None => ProfilePin {
+				user_id: user_id.to_string(),
+				target_type: target.as_str().to_string(),
+				target_id: target_id.to_string(),
+				position: 0,
+				pinned_date_time: Utc::now().to_rfc3339(),
+			},
+		};
+		let index = position.unwrap_or(pins.len()).min(pins.len());
+		pins.insert(index, pin);
+
+		save_pins(user_id, pins, &mut db_conn)?;
+		drop(db_conn);
+		self.pins(user_id)
+	}
+
+	pub fn unpin(&self, user_id: &str, target: PinTarget, target_id: &str) -> Result<Vec<PinResponse>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let mut pins = ordered_pins(user_id, &mut db_conn)?;
+		let before = pins.len();
+		pins.retain(|pin| !(pin.target_type == target.as_str() && pin.target_id == target_id));
+		if pins.len() == before {
+			return Err(ServiceError::NotFound(format!(
+				"The {} {target_id} is not pinned",
+				target.as_str()
+			)));
+		}
+
+		save_pins(user_id, pins, &mut db_conn)?;
+		drop(db_conn);
+		self.pins(user_id)
+	}
+
+	pub fn anthem(&self, user_id: &str) -> Result<Option<AnthemResponse>, ServiceError> {
+		Ok(self.anthems(&[user_id.to_string()])?.pop())
+	}
+
+	// Anthems of the users that have one, an anthem whose track became unavailable isn't shown
+	pub fn anthems(&self, user_ids: &[String]) -> Result<Vec<AnthemResponse>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let anthems = profile_anthems::table
+			.inner_join(music::table)
+			.filter(profile_anthems::user_id.eq_any(user_ids))
+			.filter(music::availability.eq(Availability::Available.as_str()))
+			.select((profile_anthems::all_columns, music::all_columns))
+			.load::<(ProfileAnthem, Music)>(&mut db_conn)?;
+		Ok(anthems
+			.into_iter()
+			.map(|(anthem, track)| AnthemResponse::new(anthem, track))
+			.collect())
+	}
+
+	pub fn set_anthem(
+		&self,
+		user_id: &str,
+		music_id: &str,
+		start_secs: Option<f64>,
+	) -> Result<AnthemResponse, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let track = music::table
+			.find(music_id)
+			.filter(music::availability.eq(Availability::Available.as_str()))
+			.filter(music::content_type.eq(ContentType::Music.as_str()))
+			.first::<Music>(&mut db_conn)
+			.optional()?
+			.ok_or_else(|| ServiceError::NotFound(format!("No music: {music_id}")))?;
+
+		let start_secs = start_secs.unwrap_or(0.0);
+		if !start_secs.is_finite() || start_secs < 0.0 || start_secs >= track.duration as f64 {
+			return Err(ServiceError::BadRequest(format!(
+				"The start has to be within the {} seconds of the track",
+				track.duration
+			)));
+		}
+
+		let anthem = ProfileAnthem {
+			user_id: user_id.to_string(),
+			music_id: music_id.to_string(),
+			start_secs,
+			updated_date_time: Utc::now().to_rfc3339(),
+		};
+		diesel::replace_into(profile_anthems::table)
+			.values(&anthem)
+			.execute(&mut db_conn)?;
+		Ok(AnthemResponse::new(anthem, track))
+	}
+
+	pub fn clear_anthem(&self, user_id: &str) -> Result<(), ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		diesel::delete(profile_anthems::table.find(user_id)).execute(&mut db_conn)?;
+		Ok(())
+	}
+
+	// The tracks and artists the user never wants played, the latest blocked first
+	pub fn blocked_content(&self, user_id: &str) -> Result<BlockedContentResponse, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let blocked = blocked_content::table
+			.filter(blocked_content::user_id.eq(user_id))
+			.order(blocked_content::blocked_date_time.desc())
+			.load::<BlockedContent>(&mut db_conn)?;
+
+		let blocked_ids: Vec<&str> = blocked
+			.iter()
+			.filter(|entry| entry.target_type == BlockTarget::Track.as_str())
+			.map(|entry| entry.target_id.as_str())
+			.collect();
+		let mut tracks: Vec<Music> = music::table
+			.filter(music::music_id.eq_any(&blocked_ids))
+			.filter(music::availability.eq(Availability::Available.as_str()))
+			.load::<Music>(&mut db_conn)?;
+		tracks.sort_by_key(|track| blocked_ids.iter().position(|id| *id == track.music_id));
+
+		Ok(BlockedContentResponse {
+			tracks: tracks.into_iter().map(Music::create_music_response).collect(),
+			artists: blocked
+				.into_iter()
+				.filter(|entry| entry.target_type == BlockTarget::Artist.as_str())
+				.map(|entry| entry.target_id)
+				.collect(),
+		})
+	}
+
+	// Shuffles, radios and lobby queues skip the target from now on, blocking it again does nothing
+	pub fn block(
+		&self,
+		user_id: &str,
+		target: BlockTarget,
+		target_id: &str,
+	) -> Result<BlockedContentResponse, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let known = match target {
+			BlockTarget::Track => music::table.filter(music::music_id.eq(target_id)).into_boxed(),
+			BlockTarget::Artist => music::table.filter(music::artist.eq(target_id)).into_boxed(),
+		}
+		.count()
+		.get_result::<i64>(&mut db_conn)?;
+		if known == 0 {
+			return Err(ServiceError::NotFound(format!("No {}: {target_id}", target.as_str())));
+		}
+
+		diesel::insert_or_ignore_into(blocked_content::table)
+			.values(&BlockedContent {
+				user_id: user_id.to_string(),
+				target_type: target.as_str().to_string(),
+				target_id: target_id.to_string(),
+				blocked_date_time: Utc::now().to_rfc3339(),
+			})
+			.execute(&mut db_conn)?;
+		self.blocked_content(user_id)
+	}
+
+	pub fn unblock(
+		&self,
+		user_id: &str,
+		target: BlockTarget,
+		target_id: &str,
+	) -> Result<BlockedContentResponse, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		diesel::delete(blocked_content::table.find((user_id, target.as_str(), target_id))).execute(&mut db_conn)?;
+		self.blocked_content(user_id)
+	}
+}
+
+fn ordered_pins(user_id: &str, db_conn: &mut SqliteConnection) -> QueryResult<Vec<ProfilePin>> {
+	profile_pins::table
+		.filter(profile_pins::user_id.eq(user_id))
+		.order(profile_pins::position.asc())
+		.load::<ProfilePin>(db_conn)
+}
+
+// Replaces the pins of the user, numbering them in the given order
+fn save_pins(user_id: &str, pins: Vec<ProfilePin>, db_conn: &mut SqliteConnection) -> QueryResult<()> {
+	let pins: Vec<ProfilePin> = pins
+		.into_iter()
+		.enumerate()
+		.map(|(position, pin)| ProfilePin {
+			position: position as i32,
+			..pin
+		})
+		.collect();
+	db_conn.transaction(|conn| {
+		diesel::delete(profile_pins::table.filter(profile_pins::user_id.eq(user_id))).execute(conn)?;
+		diesel::insert_into(profile_pins::table).values(&pins).execute(conn)?;
+		Ok(())
+	})
+}
+
+// What the pin shows, None when the target is gone or not available
+fn resolve(target: PinTarget, target_id: &str, db_conn: &mut SqliteConnection) -> QueryResult<Option<PinnedItem>> {
+	let available = music::table
+		.filter(music::availability.eq(Availability::Available.as_str()))
+		.into_boxed();
+	match target {
+		PinTarget::Track => Ok(available
+			.filter(music::music_id.eq(target_id))
+			.first::<Music>(db_conn)
+			.optional()?
+			.map(|track| PinnedItem::Track(Music::create_music_response(track)))),
+		PinTarget::Album => {
+			let tracks = available
+				.filter(music::album.eq(target_id))
+				.filter(music::content_type.eq(ContentType::Music.as_str()))
+				.order(music::title)
+				.load::<Music>(db_conn)?;
+			let song_count = tracks.len() as i64;
+			Ok(tracks.into_iter().next().map(|first| {
+				let first = Music::create_music_response(first);
+				PinnedItem::Album {
+					album: first.album,
+					artist: first.artist,
+					image_url: first.image_url,
+					song_count,
+				}
+			}))
+		}
+		PinTarget::Playlist => {
+			let playlist = playlists::table
+				.filter(playlists::playlist_id.eq(target_id))
+				.filter(playlists::availability.eq(Availability::Available.as_str()))
+				.select((playlists::playlist_name, playlists::user_id))
+				.first::<(String, String)>(db_conn)
+				.optional()?;
+			let Some((playlist_name, owner_id)) = playlist else {
+				return Ok(None);
+			};
+			let song_count = playlist_songs::table
+				.filter(playlist_songs::playlist_id.eq(target_id))
+				.count()
+				.get_result::<i64>(db_conn)?;
+			Ok(Some(PinnedItem::Playlist {
+				playlist_id: target_id.to_string(),
+				playlist_name,
+				owner_id,
+				song_count,
+			}))
+		}
+	}
+}
@@ -0,0 +1,290 @@
+use crate::lobic_db::db::DatabasePool;
+use crate::lobic_db::models::{Availability, BlockedTag, UserTag};
+use crate::schema::{blocked_tags, music, user_tags};
+use crate::services::ServiceError;
+use crate::utils::list::ListResponse;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+
+pub const MAX_TAG_LEN: usize = 32;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TagTarget {
+	Track,
+	Album,
+}
+
+impl TagTarget {
+	pub fn parse(target: &str) -> Option<TagTarget> {
+		match target {
+			"track" => Some(TagTarget::Track),
+			"album" => Some(TagTarget::Album),
+			_ => None,
+		}
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			TagTarget::Track => "track",
+			TagTarget::Album => "album",
+		}
+	}
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct TagCount {
+	pub tag: String,
+	pub count: i64, // how many listeners put it on the target
+	pub tagged_by_me: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagSummary {
+	pub tag: String,
+	pub use_count: i64,
+	pub track_count: i64,
+	pub album_count: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TagBrowseFilter {
+	pub prefix: Option<String>,
+	#[serde(default)]
+	pub start_index: i64,
+	pub page_length: Option<i64>,
+}
+
+// Lowercased with the whitespace collapsed, so "Late  Night" and "late night" are the same tag
+pub fn normalize_tag(raw: &str) -> Option<String> {
+	let tag = raw.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+	let allowed = |c: char| c.is_alphanumeric() || matches!(c, ' ' | '-' | '&' | '\'');
+	(!tag.is_empty() && tag.chars().count() <= MAX_TAG_LEN && tag.chars().all(allowed)).then_some(tag)
+}
+
+#[derive(Debug, Clone)]
+pub struct TagService {
+	db_pool: DatabasePool,
+}
+
+impl TagService {
+	pub fn new(db_pool: &DatabasePool) -> TagService {
+		TagService {
+			db_pool: db_pool.clone(),
+		}
+	}
+
+	// Tags of the track or album, the most used first
+	pub fn target_tags(
+		&self,
+		target: TagTarget,
+		target_id: &str,
+		user_id: Option<&str>,
+	) -> Result<Vec<TagCount>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let uses = user_tags::table
+			.filter(user_tags::target_type.eq(target.as_str()))
+			.filter(user_tags::target_id.eq(target_id))
+			.select((user_tags::tag, user_tags::user_id))
+			.load::<(String, String)>(&mut db_conn)?;
+
+		let mut counts: BTreeMap<String, TagCount> = BTreeMap::new();
+		for (tag, tagger_id) in uses {
+			let count = counts.entry(tag.clone()).or_insert(TagCount {
+				tag,
+				count: 0,
+				tagged_by_me: false,
+			});
+			count.count += 1;
+			count.tagged_by_me |= user_id == Some(tagger_id.as_str());
+		}
+		let mut counts: Vec<TagCount> = counts.into_values().collect();
+		counts.sort_by_key(|count| Reverse(count.count));
+		Ok(counts)
+	}
+
+	pub fn add(
+		&self,
+		user_id: &str,
+		target: TagTarget,
+		target_id: &str,
+		raw_tag: &str,
+	) -> Result<Vec<TagCount>, ServiceError> {
+		let tag = normalize_tag(raw_tag).ok_or_else(|| {
+			ServiceError::BadRequest(format!(
+				"Tags are up to {MAX_TAG_LEN} letters, digits, spaces, - & or '"
+			))
+		})?;
+
+		let mut db_conn = self.db_pool.get()?;
+		let blocked = blocked_tags::table
+			.find(&tag)
+			.select(blocked_tags::tag)
+			.first::<String>(&mut db_conn)
+			.optional()?;
+		if blocked.is_some() {
+			return Err(ServiceError::Forbidden(format!("The tag {tag} is not allowed")));
+		}
+
+		let available = music::table.filter(music::availability.eq(Availability::Available.as_str()));
+		let exists = match target {
+			TagTarget::Track => available
+				.filter(music::music_id.eq(target_id))
+				.count()
+				.get_result::<i64>(&mut db_conn)?,
+			TagTarget::Album => available
+				.filter(music::album.eq(target_id))
+				.count()
+				.get_result::<i64>(&mut db_conn)?,
+		};
+		if exists == 0 {
+			return Err(ServiceError::NotFound(format!("No {}: {target_id}", target.as_str())));
+		}
+
+		diesel::insert_or_ignore_into(user_tags::table)
+			.values(UserTag {
+				user_id: user_id.to_string(),
+				target_type: target.as_str().to_string(),
+				target_id: target_id.to_string(),
+				tag,
+				created_date_time: Utc::now().to_rfc3339(),
+			})
+			.execute(&mut db_conn)?;
+		drop(db_conn);
+		self.target_tags(target, target_id, Some(user_id))
+	}
+
+	// Takes back the user's own tag, the tags of others stay
+	pub fn remove(
+		&self,
+		user_id: &str,
+		target: TagTarget,
+		target_id: &str,
+		raw_tag: &str,
+	) -> Result<Vec<TagCount>, ServiceError> {
+		let tag = normalize_tag(raw_tag).unwrap_or_default();
+		let mut db_conn = self.db_pool.get()?;
+		diesel::delete(
+			user_tags::table
+				.filter(user_tags::user_id.eq(user_id))
+				.filter(user_tags::target_type.eq(target.as_str()))
+				.filter(user_tags::target_id.eq(target_id))
+				.filter(user_tags::tag.eq(tag)),
+		)
+		.execute(&mut db_conn)?;
+		drop(db_conn);
+		self.target_tags(target, target_id, Some(user_id))
+	}
+
+	// Every tag in use, the most used first, optionally only those starting with the prefix
+	pub fn browse(&self, filter: &TagBrowseFilter) -> Result<ListResponse<TagSummary>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		let mut query = user_tags::table
+			.select((user_tags::tag, user_tags::target_type, user_tags::target_id))
+			.into_boxed();
+		if let Some(prefix) = filter.prefix.as_deref().and_then(normalize_tag) {
+			query = query.filter(user_tags::tag.like(format!("{prefix}%")));
+		}
+		let uses = query.load::<(String, String, String)>(&mut db_conn)?;
+
+		let mut summaries: BTreeMap<String, (i64, HashSet<String>, HashSet<String>)> = BTreeMap::new();
+		for (tag, target_type, target_id) in uses {
+			let (use_count, tracks, albums) = summaries.entry(tag).or_default();
+			*use_count += 1;
+			match TagTarget::parse(&target_type) {
+				Some(TagTarget::Track) => tracks.insert(target_id),
+				Some(TagTarget::Album) => albums.insert(target_id),
+				None => false,
+			};
+		}
+		let mut summaries: Vec<TagSummary> = summaries
+			.into_iter()
+			.map(|(tag, (use_count, tracks, albums))| TagSummary {
+				tag,
+				use_count,
+				track_count: tracks.len() as i64,
+				album_count: albums.len() as i64,
+			})
+			.collect();
+		summaries.sort_by_key(|summary| Reverse(summary.use_count));
+
+		let total_count = summaries.len() as i64;
+		let items = summaries
+			.into_iter()
+			.skip(filter.start_index.max(0) as usize)
+			.take(filter.page_length.filter(|length| *length > 0).unwrap_or(i64::MAX) as usize)
+			.collect();
+		Ok(ListResponse::page(
+			items,
+			total_count,
+			filter.start_index,
+			filter.page_length,
+		))
+	}
+
+	// Moderation, takes the tag off the target for every user
+	pub fn remove_everywhere(&self, target: TagTarget, target_id: &str, raw_tag: &str) -> Result<usize, ServiceError> {
+		let tag = normalize_tag(raw_tag).unwrap_or_default();
+		let mut db_conn = self.db_pool.get()?;
+		Ok(diesel::delete(
+			user_tags::table
+				.filter(user_tags::target_type.eq(target.as_str()))
+				.filter(user_tags::target_id.eq(target_id))
+				.filter(user_tags::tag.eq(tag)),
+		)
+		.execute(&mut db_conn)?)
+	}
+
+	// Removes every use of the tag and keeps it from being used again, returns how many uses were removed
+	pub fn block(&self, raw_tag: &str, admin_id: &str) -> Result<usize, ServiceError> {
+		let tag = normalize_tag(raw_tag).ok_or_else(|| ServiceError::BadRequest(format!("Invalid tag: {raw_tag}")))?;
+		let mut db_conn = self.db_pool.get()?;
+		let removed = db_conn.transaction::<_, diesel::result::Error, _>(|conn| {
+			diesel::replace_into(blocked_tags::table)
+				.values(BlockedTag {
+					tag: tag.clone(),
+					blocked_by: admin_id.to_string(),
+					blocked_date_time: Utc::now().to_rfc3339(),
+				})
+				.execute(conn)?;
+			diesel::delete(user_tags::table.filter(user_tags::tag.eq(&tag))).execute(conn)
+		})?;
+		Ok(removed)
+	}
+
+	pub fn unblock(&self, raw_tag: &str) -> Result<(), ServiceError> {
+		let tag = normalize_tag(raw_tag).unwrap_or_default();
+		let mut db_conn = self.db_pool.get()?;
+		let removed = diesel::delete(blocked_tags::table.find(&tag)).execute(&mut db_conn)?;
+		if removed == 0 {
+			return Err(ServiceError::NotFound(format!("The tag {raw_tag} is not blocked")));
+		}
+		Ok(())
+	}
+
+	pub fn blocked(&self) -> Result<Vec<BlockedTag>, ServiceError> {
+		let mut db_conn = self.db_pool.get()?;
+		Ok(blocked_tags::table
+			.order(blocked_tags::blocked_date_time.desc())
+			.load::<BlockedTag>(&mut db_conn)?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn tags_are_normalized() {
+		assert_eq!(normalize_tag("  Late   Night "), Some("late night".to_string()));
+		assert_eq!(normalize_tag("drum & bass"), Some("drum & bass".to_string()));
+		assert_eq!(normalize_tag("lo-fi"), Some("lo-fi".to_string()));
+		assert_eq!(normalize_tag("   "), None);
+		assert_eq!(normalize_tag("<script>"), None);
+		assert_eq!(normalize_tag(&"a".repeat(MAX_TAG_LEN + 1)), None);
+	}
+}
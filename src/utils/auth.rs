@@ -0,0 +1,57 @@
+use crate::i18n::Locale;
+use crate::lobic_db::db::{user_is_admin, DatabasePool};
+use crate::utils::jwt;
+
+use axum::{http::status::StatusCode, response::Response};
+use axum_extra::extract::cookie::CookieJar;
+
+// Set next to the session the bearer token of an app stands in for, see core::oauth::bearer_session
+pub const OAUTH_SESSION_COOKIE: &str = "oauth_client";
+
+// Returns the id of the logged in user by verifying the session cookies
+pub fn session_user_id(jar: &CookieJar) -> Option<String> {
+	let secret_key = std::env::var("JWT_SECRET_KEY").expect("JWT_SECRET_KEY must be set in .env file");
+
+	for token_name in ["access_token", "refresh_token"] {
+		if let Some(token) = jar.get(token_name) {
+			if let Ok(data) = jwt::verify(token.value(), &secret_key) {
+				return Some(data.claims.id);
+			}
+		}
+	}
+
+	None
+}
+
+// Returns the id of the logged in user, or the response to send back if the user is not allowed in
+#[allow(clippy::result_large_err)]
+pub fn require_user(jar: &CookieJar) -> Result<String, Response<String>> {
+	match session_user_id(jar) {
+		Some(id) => Ok(id),
+		None => Err(Response::builder()
+			.status(StatusCode::UNAUTHORIZED)
+			.body("Required Authentication".to_string())
+			.unwrap()),
+	}
+}
+
+// Same as `require_user` but the user also needs to be an admin. Apps never act as one, whatever
+// the admin allowed them.
+#[allow(clippy::result_large_err)]
+pub fn require_admin(jar: &CookieJar, db_pool: &DatabasePool) -> Result<String, Response<String>> {
+	let id = require_user(jar)?;
+
+	if jar.get(OAUTH_SESSION_COOKIE).is_some() || !user_is_admin(&id, db_pool) {
+		let locale = db_pool
+			.get()
+			.ok()
+			.and_then(|mut db_conn| Locale::for_user(&id, &mut db_conn))
+			.unwrap_or(Locale::DEFAULT);
+		return Err(Response::builder()
+			.status(StatusCode::FORBIDDEN)
+			.body(locale.t("auth.admin_required"))
+			.unwrap());
+	}
+
+	Ok(id)
+}
@@ -14,3 +14,7 @@ pub fn expiration_from_min(min: u64) -> usize {
 pub fn expiration_from_days(days: u64) -> usize {
 	expiration_from_sec(days * 24 * 60 * 60)
 }
+
+pub fn now() -> usize {
+	expiration_from_sec(0)
+}
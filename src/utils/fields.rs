@@ -0,0 +1,104 @@
+use crate::lobic_db::models::MusicResponse;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+// The heavy parts of a track in the lists, only sent when asked for with ?include=
+pub const GROUPS: [(&str, &[&str]); 3] = [
+	("stats", &["times_played"]),
+	("image", &["image_url", "palette_url", "animated_cover_url"]),
+	("audio", &["bpm", "musical_key", "camelot"]),
+];
+
+// Read along with the query of the list endpoints
+#[derive(Debug, Deserialize)]
+pub struct FieldsQuery {
+	pub include: Option<String>,
+	pub exclude: Option<String>,
+}
+
+impl FieldsQuery {
+	pub fn fields(&self) -> Result<MusicFields, String> {
+		MusicFields::parse(self.include.as_deref(), self.exclude.as_deref())
+	}
+}
+
+// Which of the groups go out. None of them unless included, exclude then drops some of the included ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MusicFields {
+	left_out: Vec<&'static str>, // the fields, not the groups
+}
+
+impl MusicFields {
+	// Comma separated groups, include=stats,image sends those and exclude=image takes that one back out
+	pub fn parse(include: Option<&str>, exclude: Option<&str>) -> Result<MusicFields, String> {
+		let (include, exclude) = (groups(include)?, groups(exclude)?);
+		let left_out = GROUPS
+			.iter()
+			.filter(|(name, _)| !include.contains(name) || exclude.contains(name))
+			.flat_map(|(_, fields)| fields.iter().copied())
+			.collect();
+		Ok(MusicFields { left_out })
+	}
+
+	pub fn project(&self, track: &MusicResponse) -> Value {
+		let mut value = serde_json::to_value(track).unwrap_or(Value::Null);
+		if let Value::Object(fields) = &mut value {
+			// where the colors and the looping video of the cover are, the animated one may not be there
+			fields.insert(
+				"palette_url".to_string(),
+				Value::from(format!("/image/{}/palette", track.image_url)),
+			);
+			fields.insert(
+				"animated_cover_url".to_string(),
+				Value::from(format!("/animated_cover/album/{}", track.image_url)),
+			);
+			for field in &self.left_out {
+				fields.remove(*field);
+			}
+		}
+		value
+	}
+
+	pub fn project_all(&self, tracks: &[MusicResponse]) -> Vec<Value> {
+		tracks.iter().map(|track| self.project(track)).collect()
+	}
+}
+
+fn groups(list: Option<&str>) -> Result<Vec<&str>, String> {
+	list.unwrap_or_default()
+		.split(',')
+		.map(str::trim)
+		.filter(|group| !group.is_empty())
+		.map(|group| match GROUPS.iter().any(|(name, _)| *name == group) {
+			true => Ok(group),
+			false => Err(format!("Unknown field group: {group}, use stats, image or audio")),
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn only_the_asked_for_groups_go_out() {
+		assert_eq!(MusicFields::parse(None, None).unwrap().left_out.len(), 7);
+		assert_eq!(MusicFields::parse(Some(""), None).unwrap().left_out.len(), 7);
+		assert_eq!(
+			MusicFields::parse(Some("image"), None).unwrap().left_out,
+			vec!["times_played", "bpm", "musical_key", "camelot"]
+		);
+		assert!(MusicFields::parse(Some("stats,image,audio"), None)
+			.unwrap()
+			.left_out
+			.is_empty());
+		assert_eq!(
+			MusicFields::parse(Some("stats,image,audio"), Some("stats, audio"))
+				.unwrap()
+				.left_out,
+			vec!["times_played", "bpm", "musical_key", "camelot"]
+		);
+		assert!(MusicFields::parse(Some("lyrics"), None).is_err());
+	}
+}
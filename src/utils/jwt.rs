@@ -1,12 +1,20 @@
 use jsonwebtoken::{
-	decode, encode, errors::Result, Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
+	decode, encode,
+	errors::{ErrorKind, Result},
+	Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
 };
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Tokens issued before this unix time are rejected, kept in sync by core::tokens
+static REVOKED_BEFORE: AtomicUsize = AtomicUsize::new(0);
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
 	pub id: String,
 	pub exp: usize,
+	#[serde(default)] // tokens from before iat was added count as issued at 0
+	pub iat: usize,
 }
 
 pub fn generate(claims: Claims, secret_key: &str) -> Result<String> {
@@ -18,9 +26,25 @@ pub fn generate(claims: Claims, secret_key: &str) -> Result<String> {
 }
 
 pub fn verify(token: &str, secret_key: &str) -> Result<TokenData<Claims>> {
-	decode::<Claims>(
-		&token,
+	let data = decode::<Claims>(
+		token,
 		&DecodingKey::from_secret(secret_key.as_bytes()),
 		&Validation::new(Algorithm::HS256),
-	)
+	)?;
+
+	if data.claims.iat < REVOKED_BEFORE.load(Ordering::Relaxed) {
+		return Err(ErrorKind::InvalidToken.into());
+	}
+
+	Ok(data)
+}
+
+// Key for the tokens that aren't sessions, like the ones in signed urls. Signed with the session key they would
+// pass as a session of whatever id they carry.
+pub fn purpose_key(secret_key: &str, purpose: &str) -> String {
+	format!("{purpose}:{secret_key}")
+}
+
+pub fn set_revoked_before(time: usize) {
+	REVOKED_BEFORE.store(time, Ordering::Relaxed);
 }
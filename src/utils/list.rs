@@ -0,0 +1,84 @@
+use axum::{
+	http::{header, StatusCode},
+	response::Response,
+};
+use serde::Serialize;
+
+// What every list endpoint answers with, an empty list is still a 200
+#[derive(Debug, Serialize)]
+pub struct ListResponse<T: Serialize> {
+	pub items: Vec<T>,
+	pub total_count: i64, // across every page, not just this one
+	pub start_index: i64,
+	pub page_length: Option<i64>, // null when everything from start_index on was returned
+	pub next: Option<i64>,        // start_index of the following page, null on the last one
+}
+
+impl<T: Serialize> ListResponse<T> {
+	pub fn page(items: Vec<T>, total_count: i64, start_index: i64, page_length: Option<i64>) -> ListResponse<T> {
+		let page_length = page_length.filter(|length| *length > 0);
+		let end = start_index + items.len() as i64;
+		let next = (!items.is_empty() && end < total_count).then_some(end);
+
+		ListResponse {
+			items,
+			total_count,
+			start_index,
+			page_length,
+			next,
+		}
+	}
+
+	// The whole list in one page, for the ones that are never paged
+	pub fn all(items: Vec<T>) -> ListResponse<T> {
+		let total_count = items.len() as i64;
+		ListResponse::page(items, total_count, 0, None)
+	}
+
+	pub fn map<U: Serialize>(self, f: impl FnMut(T) -> U) -> ListResponse<U> {
+		ListResponse {
+			items: self.items.into_iter().map(f).collect(),
+			total_count: self.total_count,
+			start_index: self.start_index,
+			page_length: self.page_length,
+			next: self.next,
+		}
+	}
+
+	pub fn into_response(self) -> Response<String> {
+		match serde_json::to_string(&self) {
+			Ok(json) => Response::builder()
+				.status(StatusCode::OK)
+				.header(header::CONTENT_TYPE, "application/json")
+				.body(json)
+				.unwrap(),
+			Err(err) => Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to serialize response: {err}"))
+				.unwrap(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::ListResponse;
+
+	#[test]
+	fn points_at_the_next_page() {
+		let list = ListResponse::page(vec![1, 2, 3], 10, 3, Some(3));
+		assert_eq!(list.next, Some(6));
+	}
+
+	#[test]
+	fn last_and_empty_pages_have_no_next() {
+		assert_eq!(ListResponse::page(vec![1, 2], 5, 3, Some(3)).next, None);
+		assert_eq!(ListResponse::page(Vec::<i32>::new(), 0, 0, None).next, None);
+		assert_eq!(ListResponse::page(Vec::<i32>::new(), 5, 10, Some(3)).next, None);
+	}
+
+	#[test]
+	fn ignores_non_positive_page_lengths() {
+		assert_eq!(ListResponse::page(vec![1], 1, 0, Some(0)).page_length, None);
+	}
+}
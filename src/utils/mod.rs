@@ -1,4 +1,11 @@
+pub mod auth;
 pub mod cookie;
 pub mod exp;
+pub mod fields;
 pub mod jwt;
+pub mod list;
+pub mod negotiate;
+pub mod precondition;
+pub mod response;
+pub mod search_query;
 pub mod timestamp;
@@ -0,0 +1,196 @@
+use axum::{
+	http::{header, HeaderMap, StatusCode},
+	response::Response,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+// What the client asked for in Accept, json unless xml is strictly preferred
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+	Json,
+	Xml,
+}
+
+impl Format {
+	pub fn from_headers(headers: &HeaderMap) -> Format {
+		let Some(accept) = headers.get(header::ACCEPT).and_then(|value| value.to_str().ok()) else {
+			return Format::Json;
+		};
+		let (mut json, mut xml) = (0.0_f32, 0.0_f32);
+		for range in accept.split(',') {
+			let mut params = range.split(';').map(str::trim);
+			let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+			let quality = params
+				.find_map(|param| param.strip_prefix("q="))
+				.and_then(|q| q.parse::<f32>().ok())
+				.unwrap_or(1.0);
+			match media_type.as_str() {
+				"application/json" => json = json.max(quality),
+				"application/xml" | "text/xml" => xml = xml.max(quality),
+				"*/*" | "application/*" => {
+					json = json.max(quality);
+					xml = xml.max(quality);
+				}
+				_ => {}
+			}
+		}
+		if xml > json {
+			Format::Xml
+		} else {
+			Format::Json
+		}
+	}
+}
+
+// Serves the data as json or as xml under `root`, whichever the client prefers
+pub fn respond<T: Serialize>(headers: &HeaderMap, status: StatusCode, root: &str, data: &T) -> Response<String> {
+	let value = match serde_json::to_value(data) {
+		Ok(value) => value,
+		Err(err) => {
+			return Response::builder()
+				.status(StatusCode::INTERNAL_SERVER_ERROR)
+				.body(format!("Failed to serialize response: {err}"))
+				.unwrap()
+		}
+	};
+	let (content_type, body) = match Format::from_headers(headers) {
+		Format::Json => ("application/json", value.to_string()),
+		Format::Xml => ("application/xml", to_xml(root, &value)),
+	};
+	Response::builder()
+		.status(status)
+		.header(header::CONTENT_TYPE, content_type)
+		.header(header::VARY, "Accept")
+		.body(body)
+		.unwrap()
+}
+
+// Subsonic style, scalar fields become attributes and objects and arrays child elements named after
+// their field. Nulls are left out.
+pub fn to_xml(root: &str, value: &Value) -> String {
+	let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+	match value {
+		Value::Array(items) => {
+			let root = element_name(root);
+			xml.push_str(&format!("<{root}>"));
+			for item in items {
+				write_element(&mut xml, "item", item);
+			}
+			xml.push_str(&format!("</{root}>"));
+		}
+		value => write_element(&mut xml, root, value),
+	}
+	xml
+}
+
+fn write_element(xml: &mut String, name: &str, value: &Value) {
+	let name = element_name(name);
+	let Value::Object(fields) = value else {
+		match value {
+			Value::Null => {}
+			Value::Array(items) => items.iter().for_each(|item| write_element(xml, &name, item)),
+			scalar => xml.push_str(&format!("<{name}>{}</{name}>", escape(&scalar_text(scalar)))),
+		}
+		return;
+	};
+
+	xml.push_str(&format!("<{name}"));
+	for (key, field) in fields {
+		if matches!(field, Value::Bool(_) | Value::Number(_) | Value::String(_)) {
+			xml.push_str(&format!(r#" {}="{}""#, element_name(key), escape(&scalar_text(field))));
+		}
+	}
+	let children: Vec<_> = fields
+		.iter()
+		.filter(|(_, field)| matches!(field, Value::Object(_) | Value::Array(_)))
+		.collect();
+	if children.is_empty() {
+		xml.push_str("/>");
+		return;
+	}
+	xml.push('>');
+	for (key, field) in children {
+		write_element(xml, key, field);
+	}
+	xml.push_str(&format!("</{name}>"));
+}
+
+fn scalar_text(value: &Value) -> String {
+	match value {
+		Value::String(text) => text.clone(),
+		other => other.to_string(),
+	}
+}
+
+// Field names as xml names, anything that can't be in one becomes an underscore
+fn element_name(name: &str) -> String {
+	let name: String = name
+		.chars()
+		.map(|c| {
+			if c.is_alphanumeric() || matches!(c, '_' | '-' | '.') {
+				c
+			} else {
+				'_'
+			}
+		})
+		.collect();
+	match name.chars().next() {
+		Some(first) if first.is_alphabetic() || first == '_' => name,
+		_ => format!("_{name}"),
+	}
+}
+
+fn escape(text: &str) -> String {
+	text.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+		.replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	#[test]
+	fn xml_is_picked_only_when_preferred() {
+		let accept = |value: &str| {
+			let mut headers = HeaderMap::new();
+			headers.insert(header::ACCEPT, value.parse().unwrap());
+			Format::from_headers(&headers)
+		};
+		assert_eq!(Format::from_headers(&HeaderMap::new()), Format::Json);
+		assert_eq!(accept("*/*"), Format::Json);
+		assert_eq!(accept("application/xml"), Format::Xml);
+		assert_eq!(accept("text/xml, */*;q=0.1"), Format::Xml);
+		assert_eq!(accept("application/xml;q=0.5, application/json"), Format::Json);
+	}
+
+	#[test]
+	fn scalars_become_attributes_and_lists_repeated_elements() {
+		let value = json!({
+			"total_count": 2,
+			"next": null,
+			"items": [
+				{ "id": "a", "title": "Rock & Roll", "tags": ["live"] },
+				{ "id": "b", "title": "<Intro>" },
+			],
+		});
+		assert_eq!(
+			to_xml("music", &value),
+			concat!(
+				r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+				r#"<music total_count="2">"#,
+				r#"<items id="a" title="Rock &amp; Roll"><tags>live</tags></items>"#,
+				r#"<items id="b" title="&lt;Intro&gt;"/>"#,
+				r#"</music>"#,
+			)
+		);
+		assert_eq!(
+			to_xml("genres", &json!(["jazz"])),
+			r#"<?xml version="1.0" encoding="UTF-8"?><genres><item>jazz</item></genres>"#
+		);
+	}
+}
@@ -0,0 +1,41 @@
+use axum::{
+	http::{header, HeaderMap, StatusCode},
+	response::Response,
+};
+
+// The version a change was made against, from If-Match or else from the request itself
+#[allow(clippy::result_large_err)]
+pub fn expected_version(headers: &HeaderMap, version: Option<i32>) -> Result<i32, Response<String>> {
+	let if_match = headers
+		.get(header::IF_MATCH)
+		.and_then(|value| value.to_str().ok())
+		.and_then(parse_etag);
+	if_match.or(version).ok_or_else(|| {
+		Response::builder()
+			.status(StatusCode::PRECONDITION_REQUIRED)
+			.body("The version being edited is required, either as If-Match or as version".to_string())
+			.unwrap()
+	})
+}
+
+pub fn etag(version: i32) -> String {
+	format!("\"{version}\"")
+}
+
+fn parse_etag(value: &str) -> Option<i32> {
+	value.trim().trim_start_matches("W/").trim_matches('"').parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn etags_are_read_with_or_without_quotes() {
+		assert_eq!(parse_etag("\"3\""), Some(3));
+		assert_eq!(parse_etag("W/\"12\""), Some(12));
+		assert_eq!(parse_etag("7"), Some(7));
+		assert_eq!(parse_etag("*"), None);
+		assert_eq!(parse_etag(&etag(5)), Some(5));
+	}
+}
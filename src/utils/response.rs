@@ -0,0 +1,26 @@
+use axum::{
+	http::{header, StatusCode},
+	response::Response,
+};
+use serde::Serialize;
+
+pub fn json_response<T: Serialize>(status: StatusCode, value: &T) -> Response<String> {
+	match serde_json::to_string(value) {
+		Ok(json) => Response::builder()
+			.status(status)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(json)
+			.unwrap(),
+		Err(err) => Response::builder()
+			.status(StatusCode::INTERNAL_SERVER_ERROR)
+			.body(format!("Failed to serialize response: {err}"))
+			.unwrap(),
+	}
+}
+
+pub fn db_error(err: impl std::fmt::Display) -> Response<String> {
+	Response::builder()
+		.status(StatusCode::INTERNAL_SERVER_ERROR)
+		.body(format!("Database error: {err}"))
+		.unwrap()
+}
@@ -0,0 +1,134 @@
+use serde::Serialize;
+
+// A search string taken apart, `artist:"daft punk" year:2001 genre:electronic -live one more`
+// gives the artist, year and genre filters, live as an excluded word and `one more` as the text.
+// Words with an unknown operator stay part of the text, titles like re:zero are searched as they are.
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct SearchTerms {
+	pub text: String,
+	pub artist: Option<String>,
+	pub title: Option<String>,
+	pub album: Option<String>,
+	pub genre: Option<String>,
+	pub year: Option<(i32, i32)>, // year:2001 or year:1999-2003, both ends included
+	pub decade: Option<String>,
+	pub bpm: Option<(f64, f64)>, // bpm:120 or bpm:120-130
+	pub key: Option<String>,
+	pub excluded: Vec<String>, // -live, left out when in the title, artist or album
+}
+
+impl SearchTerms {
+	pub fn parse(query: &str) -> SearchTerms {
+		let mut terms = SearchTerms::default();
+		let mut text = Vec::new();
+		for token in tokenize(query) {
+			if let Some(word) = token.strip_prefix('-').filter(|word| !word.is_empty()) {
+				terms.excluded.push(unquote(word).to_lowercase());
+				continue;
+			}
+			let Some((operator, value)) = token.split_once(':') else {
+				text.push(unquote(&token).to_string());
+				continue;
+			};
+			let operator = operator.to_lowercase();
+			let value = unquote(value).to_string();
+			let filter = match operator.as_str() {
+				"artist" => Some(&mut terms.artist),
+				"title" => Some(&mut terms.title),
+				"album" => Some(&mut terms.album),
+				"genre" => Some(&mut terms.genre),
+				"decade" => Some(&mut terms.decade),
+				"key" => Some(&mut terms.key),
+				_ => None,
+			};
+			if let Some(filter) = filter {
+				*filter = Some(value);
+				continue;
+			}
+			let ranged = match operator.as_str() {
+				"year" => range(&value).map(|year| terms.year = Some(year)),
+				"bpm" => range(&value).map(|bpm| terms.bpm = Some(bpm)),
+				_ => None,
+			};
+			if ranged.is_none() {
+				text.push(unquote(&token).to_string());
+			}
+		}
+		terms.text = text.join(" ");
+		terms
+	}
+}
+
+// Splits on whitespace outside of double quotes, the quotes stay on the tokens
+fn tokenize(query: &str) -> Vec<String> {
+	let mut tokens = Vec::new();
+	let mut token = String::new();
+	let mut quoted = false;
+	for c in query.chars() {
+		match c {
+			'"' => {
+				quoted = !quoted;
+				token.push(c);
+			}
+			c if c.is_whitespace() && !quoted => {
+				if !token.is_empty() {
+					tokens.push(std::mem::take(&mut token));
+				}
+			}
+			c => token.push(c),
+		}
+	}
+	if !token.is_empty() {
+		tokens.push(token);
+	}
+	tokens
+}
+
+// LIKE pattern for the text anywhere in the column, used with ESCAPE '\' so % and _ are taken literally
+pub fn contains_pattern(text: &str) -> String {
+	let escaped = text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+	format!("%{escaped}%")
+}
+
+fn unquote(value: &str) -> &str {
+	value.trim_matches('"')
+}
+
+// `2001` or `1999-2003`, in either order
+fn range<T: std::str::FromStr + PartialOrd + Copy>(value: &str) -> Option<(T, T)> {
+	let (from, to) = match value.split_once('-') {
+		Some((from, to)) => (from.trim().parse().ok()?, to.trim().parse().ok()?),
+		None => {
+			let single = value.trim().parse().ok()?;
+			(single, single)
+		}
+	};
+	Some(if from <= to { (from, to) } else { (to, from) })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn operators_become_filters_and_the_rest_stays_text() {
+		let terms = SearchTerms::parse(r#"artist:"daft punk" year:2001 genre:electronic -live one more"#);
+		assert_eq!(terms.artist.as_deref(), Some("daft punk"));
+		assert_eq!(terms.year, Some((2001, 2001)));
+		assert_eq!(terms.genre.as_deref(), Some("electronic"));
+		assert_eq!(terms.excluded, vec!["live"]);
+		assert_eq!(terms.text, "one more");
+
+		let terms = SearchTerms::parse(r#"re:zero bpm:130-120 year:soon "harder better""#);
+		assert_eq!(terms.bpm, Some((120.0, 130.0)));
+		assert_eq!(terms.year, None);
+		assert_eq!(terms.text, "re:zero year:soon harder better");
+		assert_eq!(SearchTerms::parse("  "), SearchTerms::default());
+	}
+
+	#[test]
+	fn wildcards_are_matched_literally() {
+		assert_eq!(contains_pattern("100%_pure\\"), "%100\\%\\_pure\\\\%");
+		assert_eq!(contains_pattern("plain"), "%plain%");
+	}
+}